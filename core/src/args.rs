@@ -0,0 +1,1474 @@
+//! The arguments each built-in tool takes, as types. A tool's schema is
+//! generated from its struct and every call is deserialized into it before
+//! the handler runs, so `"persist": "yes"` or a misspelled `presist` is an
+//! invalid-params error naming the field instead of being read as a default.
+
+use crate::TrendBucket;
+use crate::fragment::FragmentMode;
+use crate::lsp::Action;
+use crate::output::Verbosity;
+use rmcp::ErrorData as McpError;
+use rmcp::model::JsonObject;
+use schemars::{JsonSchema, Schema, SchemaGenerator, json_schema};
+use serde::de::value::MapDeserializer;
+use serde::de::{DeserializeOwned, Error as _, IntoDeserializer, Visitor};
+use serde::{Deserialize, Deserializer, Serialize};
+use serde_json::Value;
+use std::collections::BTreeMap;
+use std::num::{NonZeroU32, NonZeroU64};
+use std::sync::Arc;
+
+/// Deserialize a call's arguments into `T`. Errors name the argument at
+/// fault, e.g. "persist: invalid type: string \"yes\", expected a boolean".
+pub fn parse<T: DeserializeOwned>(arguments: Option<&JsonObject>) -> Result<T, McpError> {
+    let fields = arguments
+        .into_iter()
+        .flatten()
+        .map(|(key, value)| (key.as_str(), Field { key, value }));
+    T::deserialize(MapDeserializer::new(fields))
+        .map_err(|e: serde_json::Error| McpError::invalid_params(e.to_string(), None))
+}
+
+/// The JSON schema `list_tools` reports for arguments of type `T`
+pub fn schema<T: JsonSchema + 'static>() -> Arc<JsonObject> {
+    rmcp::handler::server::common::cached_schema_for_type::<T>()
+}
+
+/// One argument's value, deserialized with its name attached to any error
+struct Field<'a> {
+    key: &'a str,
+    value: &'a Value,
+}
+
+impl Field<'_> {
+    fn error(&self, error: serde_json::Error) -> serde_json::Error {
+        serde_json::Error::custom(format!("{}: {}", self.key, error))
+    }
+}
+
+impl<'a> IntoDeserializer<'a, serde_json::Error> for Field<'a> {
+    type Deserializer = Self;
+
+    fn into_deserializer(self) -> Self {
+        self
+    }
+}
+
+macro_rules! forward {
+    ($($method:ident($($arg:ident: $ty:ty),*))*) => {$(
+        fn $method<V: Visitor<'a>>(self, $($arg: $ty,)* visitor: V) -> Result<V::Value, Self::Error> {
+            self.value.$method($($arg,)* visitor).map_err(|e| self.error(e))
+        }
+    )*};
+}
+
+impl<'a> Deserializer<'a> for Field<'a> {
+    type Error = serde_json::Error;
+
+    forward! {
+        deserialize_any() deserialize_bool() deserialize_i8() deserialize_i16()
+        deserialize_i32() deserialize_i64() deserialize_u8() deserialize_u16()
+        deserialize_u32() deserialize_u64() deserialize_f32() deserialize_f64()
+        deserialize_char() deserialize_str() deserialize_string() deserialize_bytes()
+        deserialize_byte_buf() deserialize_option() deserialize_unit() deserialize_seq()
+        deserialize_map() deserialize_identifier() deserialize_ignored_any()
+        deserialize_unit_struct(name: &'static str)
+        deserialize_newtype_struct(name: &'static str)
+        deserialize_tuple(len: usize)
+        deserialize_tuple_struct(name: &'static str, len: usize)
+        deserialize_struct(name: &'static str, fields: &'static [&'static str])
+        deserialize_enum(name: &'static str, variants: &'static [&'static str])
+    }
+}
+
+/// A crates.io crate to analyze instead of a snippet
+#[derive(Debug, Clone, Deserialize, JsonSchema)]
+#[serde(deny_unknown_fields)]
+#[schemars(inline)]
+pub struct CrateSourceArg {
+    pub name: String,
+    /// An exact version such as 1.0.200 or a requirement; the latest when omitted
+    pub version: Option<String>,
+}
+
+/// `cargo_build`'s build profile
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "lowercase")]
+#[schemars(inline)]
+pub enum Profile {
+    /// The unoptimized dev profile
+    #[default]
+    Dev,
+    /// The optimized release profile (--release)
+    Release,
+}
+
+/// What runs `cargo_test`'s tests
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "lowercase")]
+#[schemars(inline)]
+pub enum Runner {
+    /// cargo test
+    #[default]
+    Libtest,
+    /// cargo nextest run, with per-test durations
+    Nextest,
+}
+
+/// How `cargo_tree` returns the tree
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "lowercase")]
+#[schemars(inline)]
+pub enum TreeFormat {
+    /// cargo's tree drawing
+    #[default]
+    Text,
+    /// The drawing plus the graph as nested objects under tree
+    Json,
+}
+
+/// The lint level `cargo_doc` gives missing_docs
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "lowercase")]
+#[schemars(inline)]
+pub enum MissingDocs {
+    /// Report undocumented items as warnings
+    Warn,
+    /// Fail the build on undocumented items
+    Deny,
+}
+
+impl MissingDocs {
+    /// The rustdoc flag setting the level
+    pub fn flag(self) -> &'static str {
+        match self {
+            MissingDocs::Warn => "-W",
+            MissingDocs::Deny => "-D",
+        }
+    }
+}
+
+/// The release `semver_check` checks the new code against
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "lowercase")]
+#[schemars(inline)]
+pub enum ReleaseType {
+    /// Breaking changes allowed
+    Major,
+    /// Additions allowed
+    Minor,
+    /// Fixes only
+    Patch,
+}
+
+impl ReleaseType {
+    pub fn name(self) -> &'static str {
+        match self {
+            ReleaseType::Major => "major",
+            ReleaseType::Minor => "minor",
+            ReleaseType::Patch => "patch",
+        }
+    }
+}
+
+fn suggestion_schema(_: &mut SchemaGenerator) -> Schema {
+    json_schema!({
+        "type": ["object", "array"],
+        "properties": {
+            "file": {"type": "string"},
+            "byte_start": {"type": "integer"},
+            "byte_end": {"type": "integer"},
+            "line_start": {"type": "integer"},
+            "column_start": {"type": "integer"},
+            "line_end": {"type": "integer"},
+            "column_end": {"type": "integer"},
+            "replacement": {"type": "string"}
+        }
+    })
+}
+
+/// No constraint; the handler checks the shape
+fn any_schema(_: &mut SchemaGenerator) -> Schema {
+    json_schema!({})
+}
+
+fn powerset() -> Value {
+    Value::from("powerset")
+}
+
+fn yes() -> bool {
+    true
+}
+
+/// `N`, as the default of a count
+fn count<const N: u64>() -> u64 {
+    N
+}
+
+/// `N`, as the default of a count that must be positive
+fn positive<const N: u64>() -> NonZeroU64 {
+    NonZeroU64::new(N).expect("positive defaults are non-zero")
+}
+
+/// Arguments of `cargo_fmt`
+#[derive(Debug, Clone, Deserialize, JsonSchema)]
+#[serde(deny_unknown_fields)]
+pub struct CargoFmtArgs {
+    /// Rust code to format
+    pub code: String,
+    /// What code is: file (a whole source file), item (items such as an impl block) or expression (an expression or statements); fragments come back alone at their own indentation
+    #[serde(default)]
+    pub mode: FragmentMode,
+    /// Run even if a cached result for the same code, arguments and toolchain exists
+    #[serde(default)]
+    pub no_cache: bool,
+    /// Keep the generated project on disk and return its path for manual debugging
+    #[serde(default)]
+    pub keep_project: bool,
+    /// Store results in SQLite database
+    #[serde(default)]
+    pub persist: bool,
+    /// Label stored with the analysis to group related runs (optional)
+    pub session: Option<String>,
+    /// full: raw output; trimmed: stderr without cargo progress lines; summary: summary only
+    #[schemars(extend("default" = "trimmed"))]
+    pub verbosity: Option<Verbosity>,
+    /// Cap stdout and stderr at this many bytes each, in the response and when stored, marking the cut with "...[truncated N bytes]" (optional)
+    pub max_output_bytes: Option<NonZeroU64>,
+    /// Return only errors: error-level diagnostics, or stderr's error messages for tools without diagnostics; persist still stores the full output
+    #[serde(default)]
+    pub errors_only: bool,
+}
+
+/// Arguments of `cargo_clippy`
+#[derive(Debug, Clone, Deserialize, JsonSchema)]
+#[serde(deny_unknown_fields)]
+pub struct CargoClippyArgs {
+    /// Rust code to analyze (or crate_source or files); a project pasted as one string, with Markdown fences or // src/lib.rs markers, is split into files
+    pub code: Option<String>,
+    /// Analyze a crates.io crate instead of code: {name, version?}; a bare version is exact, e.g. 1.0.200, and no version means the latest (optional)
+    pub crate_source: Option<CrateSourceArg>,
+    /// Project files by relative path, e.g. {"src/util.rs": "..."}, written next to code; with a root Cargo.toml they are the whole project, and a [workspace] manifest gets missing member manifests generated (optional)
+    pub files: Option<BTreeMap<String, String>>,
+    /// Package to run cargo on, passed as -p, e.g. a workspace member (optional)
+    pub package: Option<String>,
+    /// Run on every workspace member (--workspace)
+    #[serde(default)]
+    pub workspace: bool,
+    /// Build even when dependencies declared in files, or a crate_source crate and its dependencies, run build scripts or proc-macros; without it such builds are refused and the crates listed
+    #[serde(default)]
+    pub allow_build_scripts: bool,
+    /// Run even if a cached result for the same code, arguments and toolchain exists
+    #[serde(default)]
+    pub no_cache: bool,
+    /// Keep the generated project on disk and return its path for manual debugging
+    #[serde(default)]
+    pub keep_project: bool,
+    /// Store results in SQLite database
+    #[serde(default)]
+    pub persist: bool,
+    /// Label stored with the analysis to group related runs (optional)
+    pub session: Option<String>,
+    /// Target triple to compile for, e.g. wasm32-unknown-unknown; must be installed with rustup (optional)
+    pub target: Option<String>,
+    /// Build the snippet as a #![no_std] library with a stub panic handler
+    #[serde(default)]
+    pub no_std: bool,
+    /// Extra rustc flags passed via RUSTFLAGS, e.g. ["-Copt-level=3"]; linker, path and output flags are rejected (optional)
+    pub rustflags: Option<Vec<String>>,
+    /// cfg specs passed as --cfg, e.g. ["feature=\"foo\"", "test"] (optional)
+    pub cfgs: Option<Vec<String>>,
+    /// full: raw output; trimmed: stderr without cargo progress lines; summary: summary only
+    #[schemars(extend("default" = "trimmed"))]
+    pub verbosity: Option<Verbosity>,
+    /// Cap stdout and stderr at this many bytes each, in the response and when stored, marking the cut with "...[truncated N bytes]" (optional)
+    pub max_output_bytes: Option<NonZeroU64>,
+    /// Return only errors: error-level diagnostics, or stderr's error messages for tools without diagnostics; persist still stores the full output
+    #[serde(default)]
+    pub errors_only: bool,
+}
+
+/// Arguments of `cargo_check`
+#[derive(Debug, Clone, Deserialize, JsonSchema)]
+#[serde(deny_unknown_fields)]
+pub struct CargoCheckArgs {
+    /// Rust code to check (or files); a project pasted as one string, with Markdown fences or // src/lib.rs markers, is split into files
+    pub code: Option<String>,
+    /// Project files by relative path, e.g. {"src/util.rs": "..."}, written next to code; with a root Cargo.toml they are the whole project, and a [workspace] manifest gets missing member manifests generated (optional)
+    pub files: Option<BTreeMap<String, String>>,
+    /// Package to run cargo on, passed as -p, e.g. a workspace member (optional)
+    pub package: Option<String>,
+    /// Run on every workspace member (--workspace)
+    #[serde(default)]
+    pub workspace: bool,
+    /// Build even when dependencies declared in files run build scripts or proc-macros; without it such builds are refused and the crates listed
+    #[serde(default)]
+    pub allow_build_scripts: bool,
+    /// With a progress token, send each diagnostic as a {"diagnostic": ...} progress message as cargo reports it; the result still holds them all
+    #[serde(default)]
+    pub stream: bool,
+    /// Run even if a cached result for the same code, arguments and toolchain exists
+    #[serde(default)]
+    pub no_cache: bool,
+    /// Keep the generated project on disk and return its path for manual debugging
+    #[serde(default)]
+    pub keep_project: bool,
+    /// Store results in SQLite database
+    #[serde(default)]
+    pub persist: bool,
+    /// Label stored with the analysis to group related runs (optional)
+    pub session: Option<String>,
+    /// Target triple to compile for, e.g. wasm32-unknown-unknown; must be installed with rustup (optional)
+    pub target: Option<String>,
+    /// Build the snippet as a #![no_std] library with a stub panic handler
+    #[serde(default)]
+    pub no_std: bool,
+    /// Extra rustc flags passed via RUSTFLAGS, e.g. ["-Copt-level=3"]; linker, path and output flags are rejected (optional)
+    pub rustflags: Option<Vec<String>>,
+    /// cfg specs passed as --cfg, e.g. ["feature=\"foo\"", "test"] (optional)
+    pub cfgs: Option<Vec<String>>,
+    /// full: raw output; trimmed: stderr without cargo progress lines; summary: summary only
+    #[schemars(extend("default" = "trimmed"))]
+    pub verbosity: Option<Verbosity>,
+    /// Cap stdout and stderr at this many bytes each, in the response and when stored, marking the cut with "...[truncated N bytes]" (optional)
+    pub max_output_bytes: Option<NonZeroU64>,
+    /// Return only errors: error-level diagnostics, or stderr's error messages for tools without diagnostics; persist still stores the full output
+    #[serde(default)]
+    pub errors_only: bool,
+}
+
+/// Arguments of `rustc_explain`
+#[derive(Debug, Clone, Deserialize, JsonSchema)]
+#[serde(deny_unknown_fields)]
+pub struct RustcExplainArgs {
+    /// Error code like E0308
+    pub error_code: String,
+}
+
+/// Arguments of `cargo_fix`
+#[derive(Debug, Clone, Deserialize, JsonSchema)]
+#[serde(deny_unknown_fields)]
+pub struct CargoFixArgs {
+    /// Rust code to fix
+    pub code: String,
+    /// Run even if a cached result for the same code, arguments and toolchain exists
+    #[serde(default)]
+    pub no_cache: bool,
+    /// Keep the generated project on disk and return its path for manual debugging
+    #[serde(default)]
+    pub keep_project: bool,
+    /// Store results in SQLite database
+    #[serde(default)]
+    pub persist: bool,
+    /// Label stored with the analysis to group related runs (optional)
+    pub session: Option<String>,
+    /// full: raw output; trimmed: stderr without cargo progress lines; summary: summary only
+    #[schemars(extend("default" = "trimmed"))]
+    pub verbosity: Option<Verbosity>,
+    /// Cap stdout and stderr at this many bytes each, in the response and when stored, marking the cut with "...[truncated N bytes]" (optional)
+    pub max_output_bytes: Option<NonZeroU64>,
+    /// Return only errors: error-level diagnostics, or stderr's error messages for tools without diagnostics; persist still stores the full output
+    #[serde(default)]
+    pub errors_only: bool,
+}
+
+/// Arguments of `apply_suggestion`
+#[derive(Debug, Clone, Deserialize, JsonSchema)]
+#[serde(deny_unknown_fields)]
+pub struct ApplySuggestionArgs {
+    /// Rust code the suggestion was reported for
+    pub code: String,
+    /// A suggestion from a diagnostics array: replacement plus byte_start/byte_end or line_start/column_start/line_end/column_end (1-based, end exclusive). An array applies the parts of a multi-span suggestion together
+    #[schemars(schema_with = "suggestion_schema")]
+    pub suggestion: Value,
+    /// Stored error the suggestion fixes, linked to the recorded fix (optional)
+    pub error_id: Option<i64>,
+    /// Record the fix and whether it compiled in the fixes table
+    #[serde(default)]
+    pub persist: bool,
+}
+
+/// Arguments of `cargo_audit`
+#[derive(Debug, Clone, Deserialize, JsonSchema)]
+#[serde(deny_unknown_fields)]
+pub struct CargoAuditArgs {
+    /// Rust code with Cargo.toml to audit (or crate_source)
+    pub code: Option<String>,
+    /// Advisory ids to leave out of the report, e.g. ["RUSTSEC-2021-0078"] (optional)
+    pub ignore: Option<Vec<String>>,
+    /// Analyze a crates.io crate instead of code: {name, version?}; a bare version is exact, e.g. 1.0.200, and no version means the latest (optional)
+    pub crate_source: Option<CrateSourceArg>,
+    /// Run even if a cached result for the same code, arguments and toolchain exists
+    #[serde(default)]
+    pub no_cache: bool,
+    /// Keep the generated project on disk and return its path for manual debugging
+    #[serde(default)]
+    pub keep_project: bool,
+    /// Store results in SQLite database
+    #[serde(default)]
+    pub persist: bool,
+    /// Label stored with the analysis to group related runs (optional)
+    pub session: Option<String>,
+    /// full: raw output; trimmed: stderr without cargo progress lines; summary: summary only
+    #[schemars(extend("default" = "trimmed"))]
+    pub verbosity: Option<Verbosity>,
+    /// Cap stdout and stderr at this many bytes each, in the response and when stored, marking the cut with "...[truncated N bytes]" (optional)
+    pub max_output_bytes: Option<NonZeroU64>,
+    /// Return only errors: error-level diagnostics, or stderr's error messages for tools without diagnostics; persist still stores the full output
+    #[serde(default)]
+    pub errors_only: bool,
+}
+
+/// Arguments of `cargo_deny`
+#[derive(Debug, Clone, Deserialize, JsonSchema)]
+#[serde(deny_unknown_fields)]
+pub struct CargoDenyArgs {
+    /// Rust code with Cargo.toml to check
+    pub code: String,
+    /// Contents of a deny.toml policy (optional; cargo deny defaults otherwise)
+    pub config: Option<String>,
+    /// Run even if a cached result for the same code, arguments and toolchain exists
+    #[serde(default)]
+    pub no_cache: bool,
+    /// Keep the generated project on disk and return its path for manual debugging
+    #[serde(default)]
+    pub keep_project: bool,
+    /// Store results in SQLite database
+    #[serde(default)]
+    pub persist: bool,
+    /// Label stored with the analysis to group related runs (optional)
+    pub session: Option<String>,
+}
+
+/// Arguments of `cargo_coverage`
+#[derive(Debug, Clone, Deserialize, JsonSchema)]
+#[serde(deny_unknown_fields)]
+pub struct CargoCoverageArgs {
+    /// Rust code with tests to measure
+    pub code: String,
+    /// Include region coverage for each function
+    #[serde(default)]
+    pub functions: bool,
+    /// Fail (success: false) when line coverage is below this percentage (optional)
+    pub fail_under: Option<f64>,
+    /// Run even if a cached result for the same code, arguments and toolchain exists
+    #[serde(default)]
+    pub no_cache: bool,
+    /// Keep the generated project on disk and return its path for manual debugging
+    #[serde(default)]
+    pub keep_project: bool,
+    /// Store results in SQLite database
+    #[serde(default)]
+    pub persist: bool,
+    /// Label stored with the analysis to group related runs (optional)
+    pub session: Option<String>,
+    /// full: raw output; trimmed: stderr without cargo progress lines; summary: summary only
+    #[schemars(extend("default" = "trimmed"))]
+    pub verbosity: Option<Verbosity>,
+    /// Cap stdout and stderr at this many bytes each, in the response and when stored, marking the cut with "...[truncated N bytes]" (optional)
+    pub max_output_bytes: Option<NonZeroU64>,
+    /// Return only errors: error-level diagnostics, or stderr's error messages for tools without diagnostics; persist still stores the full output
+    #[serde(default)]
+    pub errors_only: bool,
+}
+
+/// Arguments of `cargo_test`
+#[derive(Debug, Clone, Deserialize, JsonSchema)]
+#[serde(deny_unknown_fields)]
+pub struct CargoTestArgs {
+    /// Rust code with tests to run (or files); a project pasted as one string, with Markdown fences or // src/lib.rs markers, is split into files
+    pub code: Option<String>,
+    /// Project files by relative path, e.g. {"src/util.rs": "..."}, written next to code; with a root Cargo.toml they are the whole project, and a [workspace] manifest gets missing member manifests generated (optional)
+    pub files: Option<BTreeMap<String, String>>,
+    /// Package to run cargo on, passed as -p, e.g. a workspace member (optional)
+    pub package: Option<String>,
+    /// Run on every workspace member (--workspace)
+    #[serde(default)]
+    pub workspace: bool,
+    /// Build even when dependencies declared in files run build scripts or proc-macros; without it such builds are refused and the crates listed
+    #[serde(default)]
+    pub allow_build_scripts: bool,
+    /// Build the code as a library and run only its doc tests (cargo test --doc)
+    #[serde(default)]
+    pub doc: bool,
+    /// Build the code as a library so its doc tests run alongside unit tests
+    #[serde(default)]
+    pub doc_tests: bool,
+    /// Environment variables set for the test run, e.g. {"APP_MODE": "test"}; PATH, HOME, RUSTC, RUSTFLAGS and CARGO_*, RUSTC_*, RUSTUP_*, LD_* and DYLD_* variables are rejected. Only the keys are echoed back as env_keys (optional)
+    pub env: Option<BTreeMap<String, String>>,
+    /// nextest runs cargo nextest and reports per-test durations; falls back to libtest with a warning when cargo-nextest is not installed
+    #[serde(default)]
+    pub runner: Runner,
+    /// Run even if a cached result for the same code, arguments and toolchain exists
+    #[serde(default)]
+    pub no_cache: bool,
+    /// Keep the generated project on disk and return its path for manual debugging
+    #[serde(default)]
+    pub keep_project: bool,
+    /// Store results in SQLite database
+    #[serde(default)]
+    pub persist: bool,
+    /// Label stored with the analysis to group related runs (optional)
+    pub session: Option<String>,
+    /// Extra rustc flags passed via RUSTFLAGS, e.g. ["-Copt-level=3"]; linker, path and output flags are rejected (optional)
+    pub rustflags: Option<Vec<String>>,
+    /// cfg specs passed as --cfg, e.g. ["feature=\"foo\"", "test"] (optional)
+    pub cfgs: Option<Vec<String>>,
+    /// full: raw output; trimmed: stderr without cargo progress lines; summary: summary only
+    #[schemars(extend("default" = "trimmed"))]
+    pub verbosity: Option<Verbosity>,
+    /// Cap stdout and stderr at this many bytes each, in the response and when stored, marking the cut with "...[truncated N bytes]" (optional)
+    pub max_output_bytes: Option<NonZeroU64>,
+    /// Return only errors: error-level diagnostics, or stderr's error messages for tools without diagnostics; persist still stores the full output
+    #[serde(default)]
+    pub errors_only: bool,
+}
+
+/// Arguments of `cargo_build`
+#[derive(Debug, Clone, Deserialize, JsonSchema)]
+#[serde(deny_unknown_fields)]
+pub struct CargoBuildArgs {
+    /// Rust code to build-check (or files); a project pasted as one string, with Markdown fences or // src/lib.rs markers, is split into files
+    pub code: Option<String>,
+    /// Project files by relative path, e.g. {"src/util.rs": "..."}, written next to code; with a root Cargo.toml they are the whole project, and a [workspace] manifest gets missing member manifests generated (optional)
+    pub files: Option<BTreeMap<String, String>>,
+    /// Package to run cargo on, passed as -p, e.g. a workspace member (optional)
+    pub package: Option<String>,
+    /// Run on every workspace member (--workspace)
+    #[serde(default)]
+    pub workspace: bool,
+    /// Build even when dependencies declared in files run build scripts or proc-macros; without it such builds are refused and the crates listed
+    #[serde(default)]
+    pub allow_build_scripts: bool,
+    /// Run even if a cached result for the same code, arguments and toolchain exists
+    #[serde(default)]
+    pub no_cache: bool,
+    /// Keep the generated project on disk and return its path for manual debugging
+    #[serde(default)]
+    pub keep_project: bool,
+    /// Store results in SQLite database
+    #[serde(default)]
+    pub persist: bool,
+    /// Label stored with the analysis to group related runs (optional)
+    pub session: Option<String>,
+    /// Target triple to compile for, e.g. wasm32-unknown-unknown; must be installed with rustup (optional)
+    pub target: Option<String>,
+    /// Build the snippet as a #![no_std] library with a stub panic handler
+    #[serde(default)]
+    pub no_std: bool,
+    /// Build profile; release maps to --release
+    #[serde(default)]
+    pub profile: Profile,
+    /// Return the built executable's size and target, plus the binary base64-encoded when under the size cap
+    #[serde(default)]
+    pub return_artifact: bool,
+    /// Extra rustc flags passed via RUSTFLAGS, e.g. ["-Copt-level=3"]; linker, path and output flags are rejected (optional)
+    pub rustflags: Option<Vec<String>>,
+    /// cfg specs passed as --cfg, e.g. ["feature=\"foo\"", "test"] (optional)
+    pub cfgs: Option<Vec<String>>,
+    /// full: raw output; trimmed: stderr without cargo progress lines; summary: summary only
+    #[schemars(extend("default" = "trimmed"))]
+    pub verbosity: Option<Verbosity>,
+    /// Cap stdout and stderr at this many bytes each, in the response and when stored, marking the cut with "...[truncated N bytes]" (optional)
+    pub max_output_bytes: Option<NonZeroU64>,
+    /// Return only errors: error-level diagnostics, or stderr's error messages for tools without diagnostics; persist still stores the full output
+    #[serde(default)]
+    pub errors_only: bool,
+}
+
+/// Arguments of `cargo_search`
+#[derive(Debug, Clone, Deserialize, JsonSchema)]
+#[serde(deny_unknown_fields)]
+pub struct CargoSearchArgs {
+    /// Search query for crates.io
+    pub query: String,
+}
+
+/// Arguments of `cargo_tree`
+#[derive(Debug, Clone, Deserialize, JsonSchema)]
+#[serde(deny_unknown_fields)]
+pub struct CargoTreeArgs {
+    /// Rust code with dependencies to analyze (or crate_source)
+    pub code: Option<String>,
+    /// Analyze a crates.io crate instead of code: {name, version?}; a bare version is exact, e.g. 1.0.200, and no version means the latest (optional)
+    pub crate_source: Option<CrateSourceArg>,
+    /// Maximum display depth of the tree (--depth) (optional)
+    pub depth: Option<u64>,
+    /// Repeat shared dependencies instead of marking them (*) (--no-dedupe)
+    #[serde(default)]
+    pub no_dedupe: bool,
+    /// Show what depends on this package (--invert), e.g. serde or serde@1.0.200 (optional)
+    pub invert: Option<String>,
+    /// Only show packages present in multiple versions (--duplicates)
+    #[serde(default)]
+    pub duplicates: bool,
+    /// Dependency kinds to show (--edges), comma-separated: all, normal, build, dev, features, no-normal, no-build, no-dev, no-proc-macro (optional)
+    pub edges: Option<String>,
+    /// text: cargo's tree drawing; json: also return the graph as nested {name, version, source, dependencies} under tree
+    #[serde(default)]
+    pub format: TreeFormat,
+    /// Crates to add to [dependencies] as name: version requirement, e.g. {"serde": "1.0"} (optional)
+    pub dependencies: Option<BTreeMap<String, String>>,
+    /// Run even if a cached result for the same code, arguments and toolchain exists
+    #[serde(default)]
+    pub no_cache: bool,
+    /// Keep the generated project on disk and return its path for manual debugging
+    #[serde(default)]
+    pub keep_project: bool,
+    /// Store results in SQLite database
+    #[serde(default)]
+    pub persist: bool,
+    /// Label stored with the analysis to group related runs (optional)
+    pub session: Option<String>,
+    /// full: raw output; trimmed: stderr without cargo progress lines; summary: summary only
+    #[schemars(extend("default" = "trimmed"))]
+    pub verbosity: Option<Verbosity>,
+    /// Cap stdout and stderr at this many bytes each, in the response and when stored, marking the cut with "...[truncated N bytes]" (optional)
+    pub max_output_bytes: Option<NonZeroU64>,
+    /// Return only errors: error-level diagnostics, or stderr's error messages for tools without diagnostics; persist still stores the full output
+    #[serde(default)]
+    pub errors_only: bool,
+}
+
+/// Arguments of `find_duplicate_deps`
+#[derive(Debug, Clone, Deserialize, JsonSchema)]
+#[serde(deny_unknown_fields)]
+pub struct FindDuplicateDepsArgs {
+    /// Rust code with dependencies to analyze
+    pub code: String,
+    /// Run even if a cached result for the same code, arguments and toolchain exists
+    #[serde(default)]
+    pub no_cache: bool,
+    /// Keep the generated project on disk and return its path for manual debugging
+    #[serde(default)]
+    pub keep_project: bool,
+    /// Store results in SQLite database
+    #[serde(default)]
+    pub persist: bool,
+    /// Label stored with the analysis to group related runs (optional)
+    pub session: Option<String>,
+}
+
+/// Arguments of `cargo_deps_report`
+#[derive(Debug, Clone, Deserialize, JsonSchema)]
+#[serde(deny_unknown_fields)]
+pub struct CargoDepsReportArgs {
+    /// Rust code whose dependency graph to report on (or crate_source)
+    pub code: Option<String>,
+    /// Analyze a crates.io crate instead of code: {name, version?}; a bare version is exact, e.g. 1.0.200, and no version means the latest (optional)
+    pub crate_source: Option<CrateSourceArg>,
+    /// Crates to add to [dependencies] as name: version requirement, e.g. {"serde": "1.0"} (optional)
+    pub dependencies: Option<BTreeMap<String, String>>,
+    /// Run even if a cached result for the same code, arguments and toolchain exists
+    #[serde(default)]
+    pub no_cache: bool,
+    /// Store results in SQLite database
+    #[serde(default)]
+    pub persist: bool,
+    /// Label stored with the analysis to group related runs (optional)
+    pub session: Option<String>,
+}
+
+/// Arguments of `cargo_update`
+#[derive(Debug, Clone, Deserialize, JsonSchema)]
+#[serde(deny_unknown_fields)]
+pub struct CargoUpdateArgs {
+    /// Rust code for src/main.rs (optional; defaults to an empty main)
+    pub code: Option<String>,
+    /// Crates to add to [dependencies] as name: version requirement, e.g. {"serde": "1.0"} (optional)
+    pub dependencies: Option<BTreeMap<String, String>>,
+    /// Complete Cargo.toml to use instead of the generated one (optional)
+    pub cargo_toml: Option<String>,
+    /// Cargo.lock to update; without one every package is reported as added (optional)
+    pub cargo_lock: Option<String>,
+    /// Only report what would change (--dry-run); false returns the updated Cargo.lock
+    #[serde(default = "yes")]
+    pub dry_run: bool,
+    /// Only update this package and its dependencies (-p), e.g. serde or serde@1.0.100 (optional)
+    pub package: Option<String>,
+    /// Keep the generated project on disk and return its path for manual debugging
+    #[serde(default)]
+    pub keep_project: bool,
+    /// Store results in SQLite database; each update is stored as a note to find with cargo_history
+    #[serde(default)]
+    pub persist: bool,
+    /// Label stored with the analysis to group related runs (optional)
+    pub session: Option<String>,
+    /// full: raw output; trimmed: stderr without cargo progress lines; summary: summary only
+    #[schemars(extend("default" = "trimmed"))]
+    pub verbosity: Option<Verbosity>,
+    /// Cap stdout and stderr at this many bytes each, in the response and when stored, marking the cut with "...[truncated N bytes]" (optional)
+    pub max_output_bytes: Option<NonZeroU64>,
+    /// Return only errors: error-level diagnostics, or stderr's error messages for tools without diagnostics; persist still stores the full output
+    #[serde(default)]
+    pub errors_only: bool,
+}
+
+/// Arguments of `cargo_analyze`
+#[derive(Debug, Clone, Deserialize, JsonSchema)]
+#[serde(deny_unknown_fields)]
+pub struct CargoAnalyzeArgs {
+    /// Rust code to analyze
+    pub code: String,
+    /// Checks to run, in order
+    #[schemars(extend("items" = {"type": "string", "enum": ["fmt", "clippy", "check", "test", "audit"]}))]
+    pub checks: Vec<String>,
+    /// Stop at the first failing check
+    #[serde(default)]
+    pub fail_fast: bool,
+    /// Run even if a cached result for the same code, arguments and toolchain exists
+    #[serde(default)]
+    pub no_cache: bool,
+    /// Store one analysis per check, linked by a shared batch_id
+    #[serde(default)]
+    pub persist: bool,
+    /// Label stored with the analyses to group related runs (optional)
+    pub session: Option<String>,
+    /// full: raw output; trimmed: stderr without cargo progress lines; summary: summary only
+    #[schemars(extend("default" = "trimmed"))]
+    pub verbosity: Option<Verbosity>,
+    /// Cap stdout and stderr at this many bytes each, in the response and when stored, marking the cut with "...[truncated N bytes]" (optional)
+    pub max_output_bytes: Option<NonZeroU64>,
+    /// Return only errors: error-level diagnostics, or stderr's error messages for tools without diagnostics; persist still stores the full output
+    #[serde(default)]
+    pub errors_only: bool,
+}
+
+/// Arguments of `pipeline`
+#[derive(Debug, Clone, Deserialize, JsonSchema)]
+#[serde(deny_unknown_fields)]
+pub struct PipelineArgs {
+    /// Rust code shared by every step
+    pub code: String,
+    /// Tools to run, in order
+    #[schemars(extend("items" = {"type": "string", "enum": ["cargo_fmt", "cargo_clippy", "cargo_check", "cargo_fix", "cargo_audit", "cargo_deny", "cargo_coverage", "cargo_test", "cargo_build", "cargo_tree", "find_duplicate_deps", "cargo_doc", "rust_analyzer"]}))]
+    pub tools: Vec<String>,
+    /// Keep running after a step fails instead of skipping the rest
+    #[serde(default)]
+    pub continue_on_error: bool,
+    /// Run even if a cached result for the same code, arguments and toolchain exists
+    #[serde(default)]
+    pub no_cache: bool,
+    /// Store one analysis per step, linked by a shared batch_id
+    #[serde(default)]
+    pub persist: bool,
+    /// Label stored with the analyses to group related runs (optional)
+    pub session: Option<String>,
+    /// full: raw output; trimmed: stderr without cargo progress lines; summary: summary only
+    #[schemars(extend("default" = "trimmed"))]
+    pub verbosity: Option<Verbosity>,
+    /// Cap stdout and stderr at this many bytes each, in the response and when stored, marking the cut with "...[truncated N bytes]" (optional)
+    pub max_output_bytes: Option<NonZeroU64>,
+    /// Return only errors: error-level diagnostics, or stderr's error messages for tools without diagnostics; persist still stores the full output
+    #[serde(default)]
+    pub errors_only: bool,
+}
+
+/// Arguments of `cargo_machete`
+#[derive(Debug, Clone, Deserialize, JsonSchema)]
+#[serde(deny_unknown_fields)]
+pub struct CargoMacheteArgs {
+    /// Rust code whose dependencies to check
+    pub code: String,
+    /// Store results in SQLite database
+    #[serde(default)]
+    pub persist: bool,
+    /// Label stored with the analysis to group related runs (optional)
+    pub session: Option<String>,
+}
+
+/// Arguments of `cargo_doc`
+#[derive(Debug, Clone, Deserialize, JsonSchema)]
+#[serde(deny_unknown_fields)]
+pub struct CargoDocArgs {
+    /// Rust code to generate documentation for (or crate_source)
+    pub code: Option<String>,
+    /// Analyze a crates.io crate instead of code: {name, version?}; a bare version is exact, e.g. 1.0.200, and no version means the latest (optional)
+    pub crate_source: Option<CrateSourceArg>,
+    /// Copy the generated HTML to the server's doc directory (RUSTY_TOOLS_DOC_DIR) and return its path
+    #[serde(default)]
+    pub save_docs: bool,
+    /// Build even when a crate_source crate or its dependencies run build scripts or proc-macros; without it such builds are refused and the crates listed
+    #[serde(default)]
+    pub allow_build_scripts: bool,
+    /// Build the code as a library with RUSTDOCFLAGS=-W/-D missing_docs and list undocumented items; stored as todos when persisted (optional)
+    pub missing_docs: Option<MissingDocs>,
+    /// Run even if a cached result for the same code, arguments and toolchain exists
+    #[serde(default)]
+    pub no_cache: bool,
+    /// Keep the generated project on disk and return its path for manual debugging
+    #[serde(default)]
+    pub keep_project: bool,
+    /// Store results in SQLite database
+    #[serde(default)]
+    pub persist: bool,
+    /// Label stored with the analysis to group related runs (optional)
+    pub session: Option<String>,
+    /// full: raw output; trimmed: stderr without cargo progress lines; summary: summary only
+    #[schemars(extend("default" = "trimmed"))]
+    pub verbosity: Option<Verbosity>,
+    /// Cap stdout and stderr at this many bytes each, in the response and when stored, marking the cut with "...[truncated N bytes]" (optional)
+    pub max_output_bytes: Option<NonZeroU64>,
+    /// Return only errors: error-level diagnostics, or stderr's error messages for tools without diagnostics; persist still stores the full output
+    #[serde(default)]
+    pub errors_only: bool,
+}
+
+/// Arguments of `cargo_bloat`
+#[derive(Debug, Clone, Deserialize, JsonSchema)]
+#[serde(deny_unknown_fields)]
+pub struct CargoBloatArgs {
+    /// Rust code with a main function to measure
+    pub code: String,
+    /// Number of functions and crates to list
+    #[serde(default = "positive::<20>")]
+    pub top: NonZeroU64,
+    /// Installed target triple to build for, e.g. wasm32-unknown-unknown (optional)
+    pub target: Option<String>,
+    /// Store results in SQLite database
+    #[serde(default)]
+    pub persist: bool,
+    /// Label stored with the analysis to group related runs (optional)
+    pub session: Option<String>,
+}
+
+/// Arguments of `cargo_feature_check`
+#[derive(Debug, Clone, Deserialize, JsonSchema)]
+#[serde(deny_unknown_fields)]
+pub struct CargoFeatureCheckArgs {
+    /// Rust code using #[cfg(feature = "...")]
+    pub code: String,
+    /// The Cargo.toml [features] table, e.g. {"std": [], "serde": ["std"]}
+    pub features: BTreeMap<String, Vec<String>>,
+    /// "powerset" (at most 16 combinations) or an explicit list of feature sets, e.g. [[], ["std"]]
+    #[serde(default = "powerset")]
+    #[schemars(schema_with = "any_schema")]
+    pub combinations: Value,
+    /// Store one analysis per combination in SQLite database
+    #[serde(default)]
+    pub persist: bool,
+    /// Label stored with the analyses to group related runs (optional)
+    pub session: Option<String>,
+}
+
+/// Arguments of `rust_analyzer`
+#[derive(Debug, Clone, Deserialize, JsonSchema)]
+#[serde(deny_unknown_fields)]
+pub struct RustAnalyzerArgs {
+    /// Rust code to analyze
+    pub code: String,
+    /// diagnostics: published diagnostics including check-on-save; hover: type and docs at line/column; completions: completions at line/column; symbols: outline of the code; definition: where the item at line/column is defined
+    #[serde(default)]
+    pub action: Action,
+    /// 1-based line for hover, completions and definition
+    pub line: Option<NonZeroU32>,
+    /// 1-based character column for hover, completions and definition
+    pub column: Option<NonZeroU32>,
+    /// Most completions to return
+    #[serde(default = "positive::<50>")]
+    pub limit: NonZeroU64,
+    /// Run even if a cached result for the same code, arguments and toolchain exists (cargo check fallback only)
+    #[serde(default)]
+    pub no_cache: bool,
+    /// Keep the generated project on disk and return its path for manual debugging (cargo check fallback only)
+    #[serde(default)]
+    pub keep_project: bool,
+    /// Store results in SQLite database
+    #[serde(default)]
+    pub persist: bool,
+    /// Label stored with the analysis to group related runs; calls with the same session reuse one rust-analyzer (optional)
+    pub session: Option<String>,
+    /// full: raw output; trimmed: stderr without cargo progress lines; summary: summary only
+    #[schemars(extend("default" = "trimmed"))]
+    pub verbosity: Option<Verbosity>,
+    /// Cap stdout and stderr at this many bytes each, in the response and when stored, marking the cut with "...[truncated N bytes]" (optional)
+    pub max_output_bytes: Option<NonZeroU64>,
+    /// Return only errors: error-level diagnostics, or stderr's error messages for tools without diagnostics; persist still stores the full output
+    #[serde(default)]
+    pub errors_only: bool,
+}
+
+/// Arguments of `rust_parse`
+#[derive(Debug, Clone, Deserialize, JsonSchema)]
+#[serde(deny_unknown_fields)]
+pub struct RustParseArgs {
+    /// Rust code to parse
+    pub code: String,
+    /// Store results in SQLite database
+    #[serde(default)]
+    pub persist: bool,
+    /// Label stored with the analysis to group related runs (optional)
+    pub session: Option<String>,
+}
+
+/// Arguments of `rust_unused`
+#[derive(Debug, Clone, Deserialize, JsonSchema)]
+#[serde(deny_unknown_fields)]
+pub struct RustUnusedArgs {
+    /// Rust code to check for unused items
+    pub code: String,
+    /// Store each finding as a todo with source "unused"
+    #[serde(default)]
+    pub store_as_todos: bool,
+    /// Run even if a cached result for the same code, arguments and toolchain exists
+    #[serde(default)]
+    pub no_cache: bool,
+    /// Keep the generated project on disk and return its path for manual debugging
+    #[serde(default)]
+    pub keep_project: bool,
+    /// Store results in SQLite database
+    #[serde(default)]
+    pub persist: bool,
+    /// Label stored with the analysis to group related runs (optional)
+    pub session: Option<String>,
+}
+
+/// Arguments of `check_msrv`
+#[derive(Debug, Clone, Deserialize, JsonSchema)]
+#[serde(deny_unknown_fields)]
+pub struct CheckMsrvArgs {
+    /// Rust code to check
+    pub code: String,
+    /// Minimum supported Rust version, e.g. 1.70 or 1.70.0
+    pub rust_version: String,
+    /// Install the toolchain with rustup if it is missing (never when the server is offline)
+    #[serde(default = "yes")]
+    pub install: bool,
+    /// How much raw output to include alongside the summary
+    #[schemars(extend("default" = "trimmed"))]
+    pub verbosity: Option<Verbosity>,
+    /// Cap stdout and stderr at this many bytes each, in the response and when stored, marking the cut with "...[truncated N bytes]" (optional)
+    pub max_output_bytes: Option<NonZeroU64>,
+    /// Return only errors: error-level diagnostics, or stderr's error messages for tools without diagnostics; persist still stores the full output
+    #[serde(default)]
+    pub errors_only: bool,
+    /// Run even if a cached result for the same code, arguments and toolchain exists
+    #[serde(default)]
+    pub no_cache: bool,
+    /// Keep the generated project on disk and return its path for manual debugging
+    #[serde(default)]
+    pub keep_project: bool,
+    /// Store results in SQLite database
+    #[serde(default)]
+    pub persist: bool,
+    /// Label stored with the analysis to group related runs (optional)
+    pub session: Option<String>,
+}
+
+/// Arguments of `semver_check`
+#[derive(Debug, Clone, Deserialize, JsonSchema)]
+#[serde(deny_unknown_fields)]
+pub struct SemverCheckArgs {
+    /// The new version of the library's code
+    pub code: String,
+    /// The prior version of the library's code to compare against (this or baseline_version)
+    pub baseline_code: Option<String>,
+    /// Published version of crate_name to compare against, e.g. 1.2.0 (this or baseline_code)
+    pub baseline_version: Option<String>,
+    /// crates.io name of the crate, required with baseline_version
+    pub crate_name: Option<String>,
+    /// The release being made; breaking changes it allows are not reported (default: patch)
+    pub release_type: Option<ReleaseType>,
+    /// Keep the generated project on disk and return its path for manual debugging
+    #[serde(default)]
+    pub keep_project: bool,
+    /// Store results in SQLite database
+    #[serde(default)]
+    pub persist: bool,
+    /// Label stored with the analysis to group related runs (optional)
+    pub session: Option<String>,
+}
+
+/// Arguments of `rust_outline`
+#[derive(Debug, Clone, Deserialize, JsonSchema)]
+#[serde(deny_unknown_fields)]
+pub struct RustOutlineArgs {
+    /// Rust code to outline
+    pub code: String,
+    /// Return only this item with its full source text: a name (Point), kind and name (impl Point, impl Display for Point) or path (Point::new, shapes::Circle) (optional)
+    pub item: Option<String>,
+}
+
+/// Arguments of `code_metrics`
+#[derive(Debug, Clone, Deserialize, JsonSchema)]
+#[serde(deny_unknown_fields)]
+pub struct CodeMetricsArgs {
+    /// Rust code to measure
+    pub code: String,
+}
+
+/// Arguments of `rust_type_of`
+#[derive(Debug, Clone, Deserialize, JsonSchema)]
+#[serde(deny_unknown_fields)]
+pub struct RustTypeOfArgs {
+    /// Rust code the expression belongs to
+    pub code: String,
+    /// Expression to type; evaluated after line when given, otherwise in a function of its own
+    pub expression: Option<String>,
+    /// 1-based line: the position to inspect with column, or the line after which expression is evaluated
+    pub line: Option<NonZeroU32>,
+    /// 1-based character column of the identifier to inspect when no expression is given
+    pub column: Option<NonZeroU32>,
+    /// rust-analyzer session to reuse for hover (optional)
+    pub session: Option<String>,
+}
+
+/// Arguments of `code_intel`
+#[derive(Debug, Clone, Deserialize, JsonSchema)]
+#[serde(deny_unknown_fields)]
+pub struct CodeIntelArgs {
+    /// Rust code to inspect
+    pub code: String,
+    /// 1-based line of the symbol
+    pub line: NonZeroU32,
+    /// 1-based character column of the symbol
+    pub column: NonZeroU32,
+    /// rust-analyzer session to reuse (optional)
+    pub session: Option<String>,
+}
+
+/// Arguments of `cargo_history`
+#[derive(Debug, Clone, Deserialize, JsonSchema)]
+#[serde(deny_unknown_fields)]
+pub struct CargoHistoryArgs {
+    /// Specific error code to search for (optional)
+    pub error_code: Option<String>,
+    /// Only include diagnostics of this severity (optional)
+    #[schemars(extend("enum" = ["error", "warning", "note", "help"]))]
+    pub severity: Option<String>,
+    /// Only include errors from this tool, e.g. cargo_clippy (optional)
+    pub tool: Option<String>,
+    /// Only include analyses that succeeded (true) or failed (false) (optional)
+    pub success: Option<bool>,
+    /// Substring match against the error's file path (optional)
+    pub file: Option<String>,
+    /// Inclusive ISO-8601 lower bound, e.g. 2025-01-31T00:00:00Z (optional)
+    pub since: Option<String>,
+    /// Inclusive ISO-8601 upper bound (optional)
+    pub until: Option<String>,
+    /// Only include errors from analyses tagged with this session (optional)
+    pub session: Option<String>,
+    /// Only include cargo_feature_check errors from this comma-separated feature set; empty string for no features (optional)
+    pub features: Option<String>,
+    /// Only include errors from the steps of one cargo_analyze or pipeline call (optional)
+    pub batch_id: Option<String>,
+    /// Maximum number of results to return
+    #[serde(default = "count::<10>")]
+    pub limit: u64,
+    /// Number of results to skip for pagination
+    #[serde(default)]
+    pub offset: u64,
+    /// Return the analyzed code of each result under code, keyed by code_hash
+    #[serde(default)]
+    pub include_code: bool,
+}
+
+/// Arguments of `cargo_todos`
+#[derive(Debug, Clone, Deserialize, JsonSchema)]
+#[serde(deny_unknown_fields)]
+pub struct CargoTodosArgs {
+    /// Include completed todos
+    #[serde(default)]
+    pub show_completed: bool,
+    /// Only todos from this source: clippy, clippy_help, cargo_audit, missing_docs or unused (optional)
+    pub source: Option<String>,
+    /// Return todos_by_source, the todos bucketed by source, instead of one todos list
+    #[serde(default)]
+    pub group_by_source: bool,
+    /// Also list the clippy lints currently ignored with todo_ignore_lint
+    #[serde(default)]
+    pub list_ignores: bool,
+}
+
+/// Arguments of `todo_ignore_lint`
+#[derive(Debug, Clone, Deserialize, JsonSchema)]
+#[serde(deny_unknown_fields)]
+pub struct TodoIgnoreLintArgs {
+    /// Clippy lint name, e.g. module_name_repetitions or clippy::module_name_repetitions
+    pub lint: String,
+    /// Snooze until this ISO-8601 date or timestamp instead of ignoring for good (optional)
+    pub until: Option<String>,
+}
+
+/// Arguments of `todo_unignore_lint`
+#[derive(Debug, Clone, Deserialize, JsonSchema)]
+#[serde(deny_unknown_fields)]
+pub struct TodoUnignoreLintArgs {
+    /// Clippy lint name, e.g. module_name_repetitions
+    pub lint: String,
+}
+
+/// Arguments of `reconcile_todos`
+#[derive(Debug, Clone, Deserialize, JsonSchema)]
+#[serde(deny_unknown_fields)]
+pub struct ReconcileTodosArgs {
+    /// Rust code to run clippy on for the fresh findings
+    pub code: Option<String>,
+    /// Output of cargo clippy --message-format=json to compare against instead of running clippy
+    pub clippy_output: Option<String>,
+}
+
+/// Arguments of `db_stats`
+#[derive(Debug, Clone, Deserialize, JsonSchema)]
+#[serde(deny_unknown_fields)]
+pub struct DbStatsArgs {}
+
+/// Arguments of `metrics`
+#[derive(Debug, Clone, Deserialize, JsonSchema)]
+#[serde(deny_unknown_fields)]
+pub struct MetricsArgs {
+    /// Only report this tool (optional)
+    pub tool: Option<String>,
+    /// Only count analyses at or after this ISO-8601 timestamp, e.g. 2025-01-31T00:00:00Z (optional)
+    pub since: Option<String>,
+}
+
+/// Arguments of `error_trends`
+#[derive(Debug, Clone, Deserialize, JsonSchema)]
+#[serde(deny_unknown_fields)]
+pub struct ErrorTrendsArgs {
+    /// Bucket size; weeks run Monday to Sunday
+    #[serde(default)]
+    pub bucket: TrendBucket,
+    /// How many days back from today to report, today included
+    #[serde(default = "count::<30>")]
+    #[schemars(range(min = 1, max = 3650))]
+    pub days: u64,
+    /// Only count errors with this code, e.g. E0308 (optional)
+    pub error_code: Option<String>,
+    /// Only count analyses by this tool, e.g. cargo_clippy (optional)
+    pub tool: Option<String>,
+    /// Only count analyses tagged with this session (optional)
+    pub tag: Option<String>,
+    /// Also break the counts down by rustc minor version, e.g. 1.85, as by_rustc (optional)
+    #[schemars(extend("enum" = ["rustc_minor"]))]
+    pub group_by: Option<String>,
+}
+
+/// Arguments of `error_code_stats`
+#[derive(Debug, Clone, Deserialize, JsonSchema)]
+#[serde(deny_unknown_fields)]
+pub struct ErrorCodeStatsArgs {
+    /// Error code to report on, e.g. E0308 (optional; the most frequent stored rustc error code otherwise)
+    pub error_code: Option<String>,
+    /// How many of the most frequent messages and of the fixes that worked to list
+    #[serde(default = "positive::<5>")]
+    pub limit: NonZeroU64,
+}
+
+/// Arguments of `db_vacuum`
+#[derive(Debug, Clone, Deserialize, JsonSchema)]
+#[serde(deny_unknown_fields)]
+pub struct DbVacuumArgs {}
+
+/// Arguments of `db_cleanup`
+#[derive(Debug, Clone, Deserialize, JsonSchema)]
+#[serde(deny_unknown_fields)]
+pub struct DbCleanupArgs {
+    /// Number of most recent analyses to keep (optional; analyses are left alone when omitted)
+    pub keep_analyses: Option<u64>,
+    /// Delete every cached tool result
+    #[serde(default = "yes")]
+    pub purge_cache: bool,
+    /// Delete downloaded registry and git sources from the dedicated cargo home
+    #[serde(default)]
+    pub prune_cargo_home: bool,
+}
+
+/// Arguments of `db_reset`
+#[derive(Debug, Clone, Deserialize, JsonSchema)]
+#[serde(deny_unknown_fields)]
+pub struct DbResetArgs {
+    /// Must be true; guards against wiping the database by accident
+    pub confirm: bool,
+    /// VACUUM afterwards to return the freed space to the OS
+    #[serde(default)]
+    pub vacuum: bool,
+}
+
+/// Arguments of `db_backup`
+#[derive(Debug, Clone, Deserialize, JsonSchema)]
+#[serde(deny_unknown_fields)]
+pub struct DbBackupArgs {
+    /// Absolute path of the backup file to create
+    pub destination: String,
+    /// Replace the destination if it already exists
+    #[serde(default)]
+    pub overwrite: bool,
+}
+
+/// Arguments of `ping`
+#[derive(Debug, Clone, Deserialize, JsonSchema)]
+#[serde(deny_unknown_fields)]
+pub struct PingArgs {}
+
+/// Arguments of `server_status`
+#[derive(Debug, Clone, Deserialize, JsonSchema)]
+#[serde(deny_unknown_fields)]
+pub struct ServerStatusArgs {}
+
+/// Arguments of `doctor`
+#[derive(Debug, Clone, Deserialize, JsonSchema)]
+#[serde(deny_unknown_fields)]
+pub struct DoctorArgs {}
+
+/// Arguments of `rerun_analysis`
+#[derive(Debug, Clone, Deserialize, JsonSchema)]
+#[serde(deny_unknown_fields)]
+pub struct RerunAnalysisArgs {
+    /// Id of the stored analysis to re-run
+    pub analysis_id: i64,
+    /// Store the new result in SQLite database
+    #[serde(default)]
+    pub persist: bool,
+    /// Label stored with the new analysis (optional)
+    pub session: Option<String>,
+}
+
+/// Arguments of `compare_analyses`
+#[derive(Debug, Clone, Deserialize, JsonSchema)]
+#[serde(deny_unknown_fields)]
+pub struct CompareAnalysesArgs {
+    /// Analysis id to compare against
+    pub baseline_id: Option<i64>,
+    /// Analysis id to compare
+    pub current_id: Option<i64>,
+    /// Compare the two most recent analyses instead of explicit ids
+    #[serde(default)]
+    pub latest: bool,
+    /// With latest, only consider analyses from this tool (optional)
+    pub tool: Option<String>,
+}
+
+/// Arguments of `diff_analyses`
+#[derive(Debug, Clone, Deserialize, JsonSchema)]
+#[serde(deny_unknown_fields)]
+pub struct DiffAnalysesArgs {
+    /// Analysis id of the earlier run
+    pub baseline_id: i64,
+    /// Analysis id of the later run
+    pub current_id: i64,
+}
+
+/// Arguments of `get_analysis`
+#[derive(Debug, Clone, Deserialize, JsonSchema)]
+#[serde(deny_unknown_fields)]
+pub struct GetAnalysisArgs {
+    /// Id of the stored analysis
+    pub analysis_id: i64,
+    /// Include the analyzed code when it was stored
+    #[serde(default)]
+    pub include_code: bool,
+}
+
+/// Arguments of `search_output`
+#[derive(Debug, Clone, Deserialize, JsonSchema)]
+#[serde(deny_unknown_fields)]
+pub struct SearchOutputArgs {
+    /// Text to search for, matched as a phrase, e.g. borrow of moved value
+    pub query: String,
+    /// Maximum number of results to return
+    #[serde(default = "count::<10>")]
+    pub limit: u64,
+}
+
+/// What a call asks of the run rather than of its tool: the options
+/// `build_invocation` turns into cargo arguments, environment and project
+/// files, and how the result is reported and stored. Each tool's arguments
+/// carry the ones it takes (`ToolArgs::options`), the rest left at their
+/// defaults; a stored output is read back into one to re-run its analysis.
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default)]
+pub struct Options {
+    pub code: Option<String>,
+    pub baseline_code: Option<String>,
+    pub files: Option<BTreeMap<String, String>>,
+    pub package: Option<String>,
+    pub workspace: bool,
+    pub crate_source: Option<CrateSourceArg>,
+    pub allow_build_scripts: bool,
+    pub no_cache: bool,
+    pub keep_project: bool,
+    pub persist: bool,
+    pub session: Option<String>,
+    pub verbosity: Option<Verbosity>,
+    pub errors_only: bool,
+    pub max_output_bytes: Option<NonZeroU64>,
+    pub target: Option<String>,
+    pub no_std: bool,
+    pub rustflags: Option<Vec<String>>,
+    pub cfgs: Option<Vec<String>>,
+    pub ignore: Option<Vec<String>>,
+    pub functions: bool,
+    pub fail_under: Option<f64>,
+    pub doc: bool,
+    pub doc_tests: bool,
+    pub env: Option<BTreeMap<String, String>>,
+    pub runner: Runner,
+    pub profile: Profile,
+    pub return_artifact: bool,
+    pub depth: Option<u64>,
+    pub no_dedupe: bool,
+    pub invert: Option<String>,
+    pub duplicates: bool,
+    pub edges: Option<String>,
+    pub format: TreeFormat,
+    pub dependencies: Option<BTreeMap<String, String>>,
+    pub cargo_toml: Option<String>,
+    pub cargo_lock: Option<String>,
+    #[serde(default = "yes")]
+    pub dry_run: bool,
+    pub save_docs: bool,
+    pub missing_docs: Option<MissingDocs>,
+    pub rust_version: Option<String>,
+    pub release_type: Option<ReleaseType>,
+}
+
+impl Options {
+    /// `verbosity`, or `Errors` with `errors_only`
+    pub fn verbosity(&self) -> Result<Verbosity, McpError> {
+        match (self.errors_only, self.verbosity) {
+            (false, verbosity) => Ok(verbosity.unwrap_or(Verbosity::Trimmed)),
+            (true, None) => Ok(Verbosity::Errors),
+            (true, Some(_)) => Err(McpError::invalid_params(
+                "errors_only can't be combined with verbosity",
+                None,
+            )),
+        }
+    }
+}
+
+/// A tool's arguments
+pub trait ToolArgs {
+    /// The `Options` among them
+    fn options(&self) -> Options;
+}
+
+/// An argument as `Options` holds it
+trait Share<T> {
+    fn share(&self) -> T;
+}
+
+impl<T: Clone> Share<T> for T {
+    fn share(&self) -> T {
+        self.clone()
+    }
+}
+
+/// A required argument, which `Options` holds as given
+impl Share<Option<String>> for String {
+    fn share(&self) -> Option<String> {
+        Some(self.clone())
+    }
+}
+
+macro_rules! options {
+    ($($args:ident { $($field:ident),* })*) => {$(
+        impl ToolArgs for $args {
+            fn options(&self) -> Options {
+                Options {
+                    $($field: self.$field.share(),)*
+                    ..Options::default()
+                }
+            }
+        }
+    )*};
+}
+
+options! {
+    CargoFmtArgs {
+        code, no_cache, keep_project, persist, session, verbosity, max_output_bytes, errors_only
+    }
+    CargoClippyArgs {
+        code, crate_source, files, package, workspace, allow_build_scripts, no_cache,
+        keep_project, persist, session, target, no_std, rustflags, cfgs, verbosity,
+        max_output_bytes, errors_only
+    }
+    CargoCheckArgs {
+        code, files, package, workspace, allow_build_scripts, no_cache, keep_project, persist,
+        session, target, no_std, rustflags, cfgs, verbosity, max_output_bytes, errors_only
+    }
+    RustcExplainArgs {}
+    CargoFixArgs {
+        code, no_cache, keep_project, persist, session, verbosity, max_output_bytes, errors_only
+    }
+    ApplySuggestionArgs { code, persist }
+    CargoAuditArgs {
+        code, ignore, crate_source, no_cache, keep_project, persist, session, verbosity,
+        max_output_bytes, errors_only
+    }
+    CargoDenyArgs { code, no_cache, keep_project, persist, session }
+    CargoCoverageArgs {
+        code, functions, fail_under, no_cache, keep_project, persist, session, verbosity,
+        max_output_bytes, errors_only
+    }
+    CargoTestArgs {
+        code, files, package, workspace, allow_build_scripts, doc, doc_tests, env, runner,
+        no_cache, keep_project, persist, session, rustflags, cfgs, verbosity, max_output_bytes,
+        errors_only
+    }
+    CargoBuildArgs {
+        code, files, package, workspace, allow_build_scripts, no_cache, keep_project, persist,
+        session, target, no_std, profile, return_artifact, rustflags, cfgs, verbosity,
+        max_output_bytes, errors_only
+    }
+    CargoSearchArgs {}
+    CargoTreeArgs {
+        code, crate_source, depth, no_dedupe, invert, duplicates, edges, format, dependencies,
+        no_cache, keep_project, persist, session, verbosity, max_output_bytes, errors_only
+    }
+    FindDuplicateDepsArgs { code, no_cache, keep_project, persist, session }
+    CargoDepsReportArgs { code, crate_source, dependencies, no_cache, persist, session }
+    CargoUpdateArgs {
+        code, dependencies, cargo_toml, cargo_lock, dry_run, package, keep_project, persist,
+        session, verbosity, max_output_bytes, errors_only
+    }
+    CargoAnalyzeArgs {
+        code, no_cache, persist, session, verbosity, max_output_bytes, errors_only
+    }
+    PipelineArgs {
+        code, no_cache, persist, session, verbosity, max_output_bytes, errors_only
+    }
+    CargoMacheteArgs { code, persist, session }
+    CargoDocArgs {
+        code, crate_source, save_docs, allow_build_scripts, missing_docs, no_cache,
+        keep_project, persist, session, verbosity, max_output_bytes, errors_only
+    }
+    CargoBloatArgs { code, target, persist, session }
+    CargoFeatureCheckArgs { code, persist, session }
+    RustAnalyzerArgs {
+        code, no_cache, keep_project, persist, session, verbosity, max_output_bytes, errors_only
+    }
+    RustParseArgs { code, persist, session }
+    RustUnusedArgs { code, no_cache, keep_project, persist, session }
+    CheckMsrvArgs {
+        code, rust_version, verbosity, max_output_bytes, errors_only, no_cache, keep_project,
+        persist, session
+    }
+    SemverCheckArgs { code, baseline_code, release_type, keep_project, persist, session }
+    RustOutlineArgs { code }
+    CodeMetricsArgs { code }
+    RustTypeOfArgs { code }
+    CodeIntelArgs { code }
+    CargoHistoryArgs {}
+    CargoTodosArgs {}
+    TodoIgnoreLintArgs {}
+    TodoUnignoreLintArgs {}
+    ReconcileTodosArgs { code }
+    DbStatsArgs {}
+    MetricsArgs {}
+    ErrorTrendsArgs {}
+    ErrorCodeStatsArgs {}
+    DbVacuumArgs {}
+    DbCleanupArgs {}
+    DbResetArgs {}
+    DbBackupArgs {}
+    PingArgs {}
+    ServerStatusArgs {}
+    DoctorArgs {}
+    RerunAnalysisArgs { persist, session }
+    CompareAnalysesArgs {}
+    DiffAnalysesArgs {}
+    GetAnalysisArgs {}
+    SearchOutputArgs {}
+}
@@ -0,0 +1,288 @@
+//! Vulnerabilities reported by `cargo audit --json`, with a severity rating
+//! derived from each advisory's CVSS v3 vector.
+
+use serde_json::Value;
+
+#[derive(Debug, Clone, PartialEq, serde::Serialize)]
+pub struct Vulnerability {
+    /// RustSec id, e.g. `RUSTSEC-2021-0078`
+    pub advisory_id: String,
+    pub package: String,
+    pub version: String,
+    pub title: String,
+    /// "none", "low", "medium", "high" or "critical"; absent when the
+    /// advisory has no CVSS vector
+    pub severity: Option<&'static str>,
+    /// The advisory's CVSS v3 vector and the base score computed from it
+    pub cvss: Option<String>,
+    pub cvss_score: Option<f64>,
+    pub patched_versions: Vec<String>,
+    pub url: String,
+}
+
+/// An informational finding: an unmaintained, unsound or yanked crate
+#[derive(Debug, Clone, PartialEq, serde::Serialize)]
+pub struct AuditWarning {
+    /// "unmaintained", "unsound", "yanked" or "notice"
+    pub kind: String,
+    pub package: String,
+    pub version: String,
+    /// Yanked crates have no advisory
+    pub advisory_id: Option<String>,
+    pub title: Option<String>,
+    pub url: Option<String>,
+}
+
+/// The JSON report among cargo-audit's stdout lines
+fn report(stdout: &str) -> Option<Value> {
+    stdout
+        .lines()
+        .filter(|line| line.starts_with('{'))
+        .find_map(|line| serde_json::from_str::<Value>(line).ok())
+}
+
+fn str_field(value: &Value, name: &str) -> Option<String> {
+    value.get(name)?.as_str().map(str::to_string)
+}
+
+/// The advisory's own link, or its page on rustsec.org
+fn advisory_url(advisory: &Value) -> Option<String> {
+    str_field(advisory, "url").or_else(|| {
+        str_field(advisory, "id").map(|id| format!("https://rustsec.org/advisories/{}", id))
+    })
+}
+
+/// The vulnerability list from a `cargo audit --json` report, or `None` when
+/// stdout holds no report (e.g. the advisory database couldn't be fetched)
+pub fn vulnerabilities(stdout: &str) -> Option<Vec<Vulnerability>> {
+    let report = report(stdout)?;
+    let list = report.get("vulnerabilities")?.get("list")?.as_array()?;
+    Some(
+        list.iter()
+            .filter_map(|entry| {
+                let advisory = entry.get("advisory")?;
+                let package = entry.get("package")?;
+                let cvss = str_field(advisory, "cvss");
+                let cvss_score = cvss.as_deref().and_then(cvss_base_score);
+                Some(Vulnerability {
+                    advisory_id: str_field(advisory, "id")?,
+                    package: str_field(package, "name")?,
+                    version: str_field(package, "version")?,
+                    title: str_field(advisory, "title").unwrap_or_default(),
+                    severity: cvss_score.map(severity),
+                    cvss,
+                    cvss_score,
+                    patched_versions: entry["versions"]["patched"]
+                        .as_array()
+                        .map(|patched| {
+                            patched
+                                .iter()
+                                .filter_map(|v| v.as_str().map(str::to_string))
+                                .collect()
+                        })
+                        .unwrap_or_default(),
+                    url: advisory_url(advisory)?,
+                })
+            })
+            .collect(),
+    )
+}
+
+/// The warnings from a `cargo audit --json` report, grouped under their kind
+/// in the report and flattened here, or `None` without a report
+pub fn warnings(stdout: &str) -> Option<Vec<AuditWarning>> {
+    let report = report(stdout)?;
+    let Some(groups) = report.get("warnings").and_then(|w| w.as_object()) else {
+        return Some(Vec::new());
+    };
+    Some(
+        groups
+            .iter()
+            .flat_map(|(kind, entries)| {
+                entries
+                    .as_array()
+                    .into_iter()
+                    .flatten()
+                    .map(move |entry| (kind, entry))
+            })
+            .filter_map(|(kind, entry)| {
+                let package = entry.get("package")?;
+                let advisory = entry.get("advisory").filter(|a| !a.is_null());
+                Some(AuditWarning {
+                    kind: str_field(entry, "kind").unwrap_or_else(|| kind.clone()),
+                    package: str_field(package, "name")?,
+                    version: str_field(package, "version")?,
+                    advisory_id: advisory.and_then(|a| str_field(a, "id")),
+                    title: advisory.and_then(|a| str_field(a, "title")),
+                    url: advisory.and_then(advisory_url),
+                })
+            })
+            .collect(),
+    )
+}
+
+/// Whether `id` looks like an advisory id cargo audit can ignore, e.g.
+/// `RUSTSEC-2021-0078`
+pub fn is_advisory_id(id: &str) -> bool {
+    !id.is_empty()
+        && !id.starts_with('-')
+        && id.bytes().all(|b| b.is_ascii_alphanumeric() || b == b'-')
+}
+
+/// CVSS v3 qualitative rating for a base score
+fn severity(score: f64) -> &'static str {
+    match score {
+        s if s >= 9.0 => "critical",
+        s if s >= 7.0 => "high",
+        s if s >= 4.0 => "medium",
+        s if s > 0.0 => "low",
+        _ => "none",
+    }
+}
+
+/// Base score of a `CVSS:3.x/AV:../AC:../..` vector, per the CVSS v3.1
+/// specification
+fn cvss_base_score(vector: &str) -> Option<f64> {
+    if !vector.starts_with("CVSS:3") {
+        return None;
+    }
+    let metric = |name: &str| {
+        vector
+            .split('/')
+            .find_map(|m| m.strip_prefix(name)?.strip_prefix(':'))
+    };
+    let changed = match metric("S")? {
+        "U" => false,
+        "C" => true,
+        _ => return None,
+    };
+    let attack_vector = match metric("AV")? {
+        "N" => 0.85,
+        "A" => 0.62,
+        "L" => 0.55,
+        "P" => 0.2,
+        _ => return None,
+    };
+    let attack_complexity = match metric("AC")? {
+        "L" => 0.77,
+        "H" => 0.44,
+        _ => return None,
+    };
+    let privileges = match (metric("PR")?, changed) {
+        ("N", _) => 0.85,
+        ("L", false) => 0.62,
+        ("L", true) => 0.68,
+        ("H", false) => 0.27,
+        ("H", true) => 0.5,
+        _ => return None,
+    };
+    let interaction = match metric("UI")? {
+        "N" => 0.85,
+        "R" => 0.62,
+        _ => return None,
+    };
+    let impact_of = |name: &str| match metric(name)? {
+        "H" => Some(0.56),
+        "L" => Some(0.22),
+        "N" => Some(0.0),
+        _ => None,
+    };
+    let iss = 1.0 - (1.0 - impact_of("C")?) * (1.0 - impact_of("I")?) * (1.0 - impact_of("A")?);
+    let impact = if changed {
+        7.52 * (iss - 0.029) - 3.25 * (iss - 0.02f64).powi(15)
+    } else {
+        6.42 * iss
+    };
+    if impact <= 0.0 {
+        return Some(0.0);
+    }
+    let exploitability = 8.22 * attack_vector * attack_complexity * privileges * interaction;
+    let score = if changed {
+        1.08 * (impact + exploitability)
+    } else {
+        impact + exploitability
+    };
+    Some(round_up(score.min(10.0)))
+}
+
+/// The specification's Roundup: the smallest one-decimal number not below
+/// `value`, computed on integers to avoid floating point artifacts
+fn round_up(value: f64) -> f64 {
+    let scaled = (value * 100_000.0).round() as i64;
+    if scaled % 10_000 == 0 {
+        scaled as f64 / 100_000.0
+    } else {
+        (scaled / 10_000 + 1) as f64 / 10.0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn report_entries_become_vulnerabilities() {
+        let stdout = r#"{"database":{"advisory-count":800},"lockfile":{"dependency-count":3},"vulnerabilities":{"found":true,"count":2,"list":[{"advisory":{"id":"RUSTSEC-2021-0078","package":"hyper","title":"Lenient `hyper` header parsing of `Content-Length` could allow request smuggling","cvss":"CVSS:3.1/AV:N/AC:L/PR:N/UI:N/S:U/C:N/I:H/A:N"},"versions":{"patched":[">=0.14.10"],"unaffected":[]},"package":{"name":"hyper","version":"0.14.9"}},{"advisory":{"id":"RUSTSEC-2020-0071","package":"time","title":"Potential segfault in the time crate","cvss":null},"versions":{"patched":[">=0.2.23"]},"package":{"name":"time","version":"0.1.45"}}]},"warnings":{}}"#;
+        let found = vulnerabilities(stdout).unwrap();
+        assert_eq!(
+            found[0],
+            Vulnerability {
+                advisory_id: "RUSTSEC-2021-0078".into(),
+                package: "hyper".into(),
+                version: "0.14.9".into(),
+                title: "Lenient `hyper` header parsing of `Content-Length` could allow request smuggling".into(),
+                severity: Some("high"),
+                cvss: Some("CVSS:3.1/AV:N/AC:L/PR:N/UI:N/S:U/C:N/I:H/A:N".into()),
+                cvss_score: Some(7.5),
+                patched_versions: vec![">=0.14.10".into()],
+                url: "https://rustsec.org/advisories/RUSTSEC-2021-0078".into(),
+            }
+        );
+        assert_eq!(found[1].severity, None);
+        assert_eq!(warnings(stdout), Some(Vec::new()));
+
+        let clean = r#"{"vulnerabilities":{"found":false,"count":0,"list":[]}}"#;
+        assert_eq!(vulnerabilities(clean), Some(Vec::new()));
+        assert!(vulnerabilities("error: couldn't fetch advisory database").is_none());
+    }
+
+    #[test]
+    fn unmaintained_and_yanked_crates_are_warnings() {
+        let stdout = r#"{"vulnerabilities":{"found":false,"count":0,"list":[]},"warnings":{"unmaintained":[{"kind":"unmaintained","package":{"name":"ansi_term","version":"0.12.1"},"advisory":{"id":"RUSTSEC-2021-0139","title":"ansi_term is Unmaintained","url":"https://github.com/ogham/rust-ansi-term/issues/72"},"versions":{"patched":[]}}],"yanked":[{"kind":"yanked","package":{"name":"bumpalo","version":"3.2.0"},"advisory":null,"versions":null}]}}"#;
+        let found = warnings(stdout).unwrap();
+        assert_eq!(
+            found,
+            [
+                AuditWarning {
+                    kind: "unmaintained".into(),
+                    package: "ansi_term".into(),
+                    version: "0.12.1".into(),
+                    advisory_id: Some("RUSTSEC-2021-0139".into()),
+                    title: Some("ansi_term is Unmaintained".into()),
+                    url: Some("https://github.com/ogham/rust-ansi-term/issues/72".into()),
+                },
+                AuditWarning {
+                    kind: "yanked".into(),
+                    package: "bumpalo".into(),
+                    version: "3.2.0".into(),
+                    advisory_id: None,
+                    title: None,
+                    url: None,
+                },
+            ]
+        );
+        assert!(is_advisory_id("RUSTSEC-2021-0139") && !is_advisory_id("--json"));
+    }
+
+    #[test]
+    fn cvss_vectors_are_scored() {
+        let score = |v| cvss_base_score(v).unwrap();
+        assert_eq!(score("CVSS:3.1/AV:N/AC:L/PR:N/UI:N/S:U/C:H/I:H/A:H"), 9.8);
+        assert_eq!(score("CVSS:3.1/AV:N/AC:L/PR:N/UI:N/S:U/C:N/I:H/A:N"), 7.5);
+        assert_eq!(score("CVSS:3.1/AV:N/AC:L/PR:L/UI:N/S:C/C:L/I:L/A:N"), 6.4);
+        assert_eq!(score("CVSS:3.0/AV:L/AC:H/PR:H/UI:R/S:U/C:N/I:N/A:L"), 1.8);
+        assert_eq!(score("CVSS:3.1/AV:N/AC:L/PR:N/UI:N/S:U/C:N/I:N/A:N"), 0.0);
+        assert_eq!(severity(6.4), "medium");
+        assert!(cvss_base_score("AV:N/AC:L/Au:N/C:P/I:P/A:P").is_none());
+    }
+}
@@ -0,0 +1,206 @@
+//! Binary size reports for `cargo_bloat`, from `cargo bloat` JSON when the
+//! plugin is installed or from an `nm` symbol table otherwise.
+
+use serde_json::Value;
+use std::collections::HashMap;
+
+#[derive(Debug, Clone, PartialEq, serde::Serialize)]
+pub struct SymbolSize {
+    pub name: String,
+    #[serde(rename = "crate")]
+    pub krate: String,
+    pub size: u64,
+}
+
+#[derive(Debug, Clone, PartialEq, serde::Serialize)]
+pub struct CrateSize {
+    pub name: String,
+    pub size: u64,
+}
+
+#[derive(Debug, Clone, serde::Serialize)]
+pub struct BloatReport {
+    /// "cargo-bloat", "nm", or "size-only" when no symbol tool is available
+    pub method: &'static str,
+    pub total_size_bytes: u64,
+    pub text_size_bytes: Option<u64>,
+    pub functions: Vec<SymbolSize>,
+    pub crates: Vec<CrateSize>,
+}
+
+impl BloatReport {
+    /// A report with only the binary size
+    pub fn size_only(total_size_bytes: u64) -> Self {
+        BloatReport {
+            method: "size-only",
+            total_size_bytes,
+            text_size_bytes: None,
+            functions: Vec::new(),
+            crates: Vec::new(),
+        }
+    }
+
+    /// Combine `cargo bloat --message-format json` output from a functions
+    /// run and a `--crates` run
+    pub fn from_cargo_bloat(functions: &Value, crates: &Value, top: usize) -> Option<Self> {
+        let functions_list = functions.get("functions")?.as_array()?;
+        let crates_list = crates.get("crates")?.as_array()?;
+        Some(BloatReport {
+            method: "cargo-bloat",
+            total_size_bytes: functions.get("file-size")?.as_u64()?,
+            text_size_bytes: functions.get("text-section-size").and_then(|v| v.as_u64()),
+            functions: functions_list
+                .iter()
+                .take(top)
+                .filter_map(|f| {
+                    Some(SymbolSize {
+                        name: f.get("name")?.as_str()?.to_string(),
+                        krate: f
+                            .get("crate")
+                            .and_then(|c| c.as_str())
+                            .unwrap_or("[Unknown]")
+                            .to_string(),
+                        size: f.get("size")?.as_u64()?,
+                    })
+                })
+                .collect(),
+            crates: crates_list
+                .iter()
+                .take(top)
+                .filter_map(|c| {
+                    Some(CrateSize {
+                        name: c.get("name")?.as_str()?.to_string(),
+                        size: c.get("size")?.as_u64()?,
+                    })
+                })
+                .collect(),
+        })
+    }
+
+    /// Build a report from `nm -C --print-size --size-sort --radix=d` output.
+    /// Only code symbols count toward the text size.
+    pub fn from_nm(nm_output: &str, total_size_bytes: u64, top: usize) -> Self {
+        let mut symbols: Vec<SymbolSize> = nm_output
+            .lines()
+            .filter_map(|line| {
+                let mut fields = line.splitn(4, ' ');
+                let _address = fields.next()?;
+                let size = fields.next()?.parse::<u64>().ok()?;
+                let kind = fields.next()?;
+                let name = fields.next()?;
+                if !matches!(kind, "t" | "T" | "w" | "W") {
+                    return None;
+                }
+                Some(SymbolSize {
+                    name: name.to_string(),
+                    krate: crate_of(name).to_string(),
+                    size,
+                })
+            })
+            .collect();
+        symbols.sort_by(|a, b| b.size.cmp(&a.size).then_with(|| a.name.cmp(&b.name)));
+
+        let mut by_crate: HashMap<&str, u64> = HashMap::new();
+        for symbol in &symbols {
+            *by_crate.entry(symbol.krate.as_str()).or_default() += symbol.size;
+        }
+        let mut crates: Vec<CrateSize> = by_crate
+            .into_iter()
+            .map(|(name, size)| CrateSize {
+                name: name.to_string(),
+                size,
+            })
+            .collect();
+        crates.sort_by(|a, b| b.size.cmp(&a.size).then_with(|| a.name.cmp(&b.name)));
+        crates.truncate(top);
+
+        let text_size_bytes = symbols.iter().map(|s| s.size).sum();
+        symbols.truncate(top);
+        BloatReport {
+            method: "nm",
+            total_size_bytes,
+            text_size_bytes: Some(text_size_bytes),
+            functions: symbols,
+            crates,
+        }
+    }
+}
+
+/// First path segment of a demangled symbol, looking inside `<...>` for
+/// trait impls; C symbols have no crate
+fn crate_of(symbol: &str) -> &str {
+    let path = symbol.trim_start_matches('<');
+    match path.find("::") {
+        Some(end) if !path[..end].contains([' ', '<', '(']) => &path[..end],
+        _ => "[Unknown]",
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn nm_symbols_are_ranked_and_grouped() {
+        let nm = "\
+0000000000001000 0000000000000010 T main
+0000000000002000 0000000000000300 t <std::backtrace_rs::Cache>::with_global
+0000000000003000 0000000000000500 T miniz_oxide::inflate::core::decompress
+0000000000004000 0000000000000200 t std::rt::lang_start_internal
+0000000000005000 0000000000009999 D some_data
+";
+        let report = BloatReport::from_nm(nm, 4096, 2);
+        assert_eq!(report.text_size_bytes, Some(1010));
+        assert_eq!(
+            report.functions,
+            [
+                SymbolSize {
+                    name: "miniz_oxide::inflate::core::decompress".into(),
+                    krate: "miniz_oxide".into(),
+                    size: 500
+                },
+                SymbolSize {
+                    name: "<std::backtrace_rs::Cache>::with_global".into(),
+                    krate: "std".into(),
+                    size: 300
+                },
+            ]
+        );
+        assert_eq!(
+            report.crates,
+            [
+                CrateSize {
+                    name: "miniz_oxide".into(),
+                    size: 500
+                },
+                CrateSize {
+                    name: "std".into(),
+                    size: 500
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn cargo_bloat_json_is_read() {
+        let functions = json!({
+            "file-size": 4096,
+            "text-section-size": 2048,
+            "functions": [
+                {"crate": "std", "name": "std::rt::lang_start", "size": 100},
+                {"name": "main", "size": 10}
+            ]
+        });
+        let crates = json!({
+            "file-size": 4096,
+            "text-section-size": 2048,
+            "crates": [{"name": "std", "size": 1900}, {"name": "temp_project", "size": 20}]
+        });
+        let report = BloatReport::from_cargo_bloat(&functions, &crates, 1).unwrap();
+        assert_eq!(report.total_size_bytes, 4096);
+        assert_eq!(report.functions.len(), 1);
+        assert_eq!(report.crates[0].name, "std");
+        assert!(BloatReport::from_cargo_bloat(&json!({}), &crates, 1).is_none());
+    }
+}
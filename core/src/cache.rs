@@ -0,0 +1,277 @@
+//! Result cache for snippet tools, so re-running an unchanged snippet on the
+//! same toolchain skips cargo. Entries live in the `result_cache` table when
+//! persistence is enabled and in a [`MemoryCache`] otherwise. Calls still in
+//! progress are tracked in [`InFlight`], so an identical call arriving
+//! meanwhile waits for their result instead of running cargo a second time.
+
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex, PoisonError};
+use std::time::{Duration, Instant};
+use tokio::sync::watch;
+
+/// Entries kept before the least recently used are evicted
+pub const MAX_ENTRIES: usize = 500;
+
+/// The parts of an `ExecResult` replayed on a cache hit
+#[derive(Debug, Clone, PartialEq)]
+pub struct CachedRun {
+    pub stdout: String,
+    pub stderr: String,
+    pub status: i32,
+    pub duration_ms: u128,
+}
+
+/// Hash of everything that decides a run's output: the cargo command and
+/// environment, the snippet, the project files that carry its dependency
+/// set and policies, and the toolchain version
+pub fn cache_key(
+    args: &[&str],
+    env: &[(String, String)],
+    code: &str,
+    files: &[(&str, String)],
+    toolchain: &str,
+) -> String {
+    let mut input = format!("{}\0{}\0", toolchain, args.join("\x1f"));
+    for (key, value) in env {
+        input.push_str(&format!("{}={}\x1f", key, value));
+    }
+    for (name, contents) in files {
+        input.push_str(&format!("\0{}\0{}", name, contents));
+    }
+    input.push('\0');
+    input.push_str(code);
+    crate::code_hash(&input)
+}
+
+struct MemoryEntry {
+    run: CachedRun,
+    created: Instant,
+    /// Value of `MemoryEntries::clock` at the last hit or insert
+    last_used: u64,
+}
+
+#[derive(Default)]
+struct MemoryEntries {
+    entries: HashMap<String, MemoryEntry>,
+    clock: u64,
+}
+
+/// Least recently used cache for servers running without a database
+#[derive(Default)]
+pub struct MemoryCache {
+    inner: Mutex<MemoryEntries>,
+}
+
+impl MemoryCache {
+    /// The run stored under `key` if it is younger than `ttl`
+    pub fn get(&self, key: &str, ttl: Duration) -> Option<CachedRun> {
+        let mut inner = self.inner.lock().ok()?;
+        inner.clock += 1;
+        let clock = inner.clock;
+        let entry = inner.entries.get_mut(key)?;
+        if entry.created.elapsed() > ttl {
+            inner.entries.remove(key);
+            return None;
+        }
+        entry.last_used = clock;
+        Some(entry.run.clone())
+    }
+
+    pub fn put(&self, key: String, run: CachedRun) {
+        let Ok(mut inner) = self.inner.lock() else {
+            return;
+        };
+        if !inner.entries.contains_key(&key)
+            && inner.entries.len() >= MAX_ENTRIES
+            && let Some(oldest) = inner
+                .entries
+                .iter()
+                .min_by_key(|(_, entry)| entry.last_used)
+                .map(|(key, _)| key.clone())
+        {
+            inner.entries.remove(&oldest);
+        }
+        inner.clock += 1;
+        let last_used = inner.clock;
+        inner.entries.insert(
+            key,
+            MemoryEntry {
+                run,
+                created: Instant::now(),
+                last_used,
+            },
+        );
+    }
+
+    /// Drop every entry, returning how many there were
+    pub fn clear(&self) -> usize {
+        self.inner
+            .lock()
+            .map(|mut inner| inner.entries.drain().count())
+            .unwrap_or(0)
+    }
+}
+
+/// Runs in progress by cache key, each with a channel its result is
+/// published on
+pub struct InFlight<T> {
+    runs: Mutex<HashMap<String, watch::Sender<Option<T>>>>,
+}
+
+impl<T> Default for InFlight<T> {
+    fn default() -> Self {
+        InFlight {
+            runs: Mutex::new(HashMap::new()),
+        }
+    }
+}
+
+/// A caller's part in the run for a key
+pub enum Flight<T> {
+    /// Nothing was running: the caller runs it and publishes the result
+    Lead(Leader<T>),
+    /// An identical run is in progress: wait for its result
+    Follow(Follower<T>),
+}
+
+impl<T: Clone> InFlight<T> {
+    /// Lead the run for `key`, or follow the one already in progress
+    pub fn join(self: &Arc<Self>, key: &str) -> Flight<T> {
+        let mut runs = self.runs.lock().unwrap_or_else(PoisonError::into_inner);
+        if let Some(sender) = runs.get(key) {
+            return Flight::Follow(Follower(sender.subscribe()));
+        }
+        runs.insert(key.to_string(), watch::channel(None).0);
+        Flight::Lead(Leader {
+            runs: Arc::clone(self),
+            key: key.to_string(),
+        })
+    }
+}
+
+/// Holds a key's entry until dropped; a leader dropped without publishing,
+/// because its run failed or was cancelled, leaves its followers to run
+/// themselves
+pub struct Leader<T> {
+    runs: Arc<InFlight<T>>,
+    key: String,
+}
+
+impl<T> Leader<T> {
+    /// Hand `result` to every follower
+    pub fn publish(self, result: T) {
+        let runs = self
+            .runs
+            .runs
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner);
+        if let Some(sender) = runs.get(&self.key) {
+            sender.send_replace(Some(result));
+        }
+    }
+}
+
+impl<T> Drop for Leader<T> {
+    fn drop(&mut self) {
+        self.runs
+            .runs
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .remove(&self.key);
+    }
+}
+
+/// Waits for the result of a run already in progress
+pub struct Follower<T>(watch::Receiver<Option<T>>);
+
+impl<T: Clone> Follower<T> {
+    /// The leader's result, or `None` if it ended without one
+    pub async fn result(mut self) -> Option<T> {
+        let published = self.0.wait_for(Option::is_some).await.ok()?;
+        published.clone()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn run(status: i32) -> CachedRun {
+        CachedRun {
+            stdout: String::new(),
+            stderr: String::new(),
+            status,
+            duration_ms: 10,
+        }
+    }
+
+    #[test]
+    fn memory_cache_evicts_least_recently_used() {
+        let cache = MemoryCache::default();
+        let ttl = Duration::from_secs(60);
+        for i in 0..MAX_ENTRIES {
+            cache.put(i.to_string(), run(i as i32));
+        }
+        // Touch the oldest entry so the second oldest is evicted instead
+        assert_eq!(cache.get("0", ttl), Some(run(0)));
+        cache.put("new".to_string(), run(-1));
+        assert!(cache.get("0", ttl).is_some());
+        assert!(cache.get("1", ttl).is_none());
+        assert!(cache.get("new", ttl).is_some());
+
+        assert!(cache.get("new", Duration::ZERO).is_none());
+        assert_eq!(cache.clear(), MAX_ENTRIES - 1);
+    }
+
+    #[tokio::test]
+    async fn followers_get_the_leaders_result_or_run_themselves() {
+        let flights = Arc::new(InFlight::<i32>::default());
+        let Flight::Lead(leader) = flights.join("a") else {
+            panic!("nothing was running");
+        };
+        let Flight::Follow(follower) = flights.join("a") else {
+            panic!("the run is in progress");
+        };
+        assert!(matches!(flights.join("b"), Flight::Lead(_)));
+        leader.publish(7);
+        assert_eq!(follower.result().await, Some(7));
+
+        // A cancelled leader frees the key and releases its followers empty-handed
+        let Flight::Lead(leader) = flights.join("a") else {
+            panic!("the finished run's entry was removed");
+        };
+        let Flight::Follow(follower) = flights.join("a") else {
+            panic!("the run is in progress");
+        };
+        drop(leader);
+        assert_eq!(follower.result().await, None);
+        assert!(matches!(flights.join("a"), Flight::Lead(_)));
+    }
+
+    #[test]
+    fn key_covers_command_code_files_and_toolchain() {
+        let files = [("Cargo.toml", "[dependencies]\n".to_string())];
+        let key = cache_key(&["check"], &[], "fn main() {}", &files, "rustc 1.90.0");
+        assert_eq!(
+            key,
+            cache_key(&["check"], &[], "fn main() {}", &files, "rustc 1.90.0")
+        );
+        assert_ne!(
+            key,
+            cache_key(&["clippy"], &[], "fn main() {}", &files, "rustc 1.90.0")
+        );
+        assert_ne!(
+            key,
+            cache_key(&["check"], &[], "fn main() {}", &[], "rustc 1.90.0")
+        );
+        assert_ne!(
+            key,
+            cache_key(&["check"], &[], "fn main() {}", &files, "rustc 1.91.0")
+        );
+        let env = [("RUSTFLAGS".to_string(), "-Dwarnings".to_string())];
+        assert_ne!(
+            key,
+            cache_key(&["check"], &env, "fn main() {}", &files, "rustc 1.90.0")
+        );
+    }
+}
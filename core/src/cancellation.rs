@@ -0,0 +1,41 @@
+//! Client cancellation of tool calls. rmcp cancels a request's token when a
+//! `notifications/cancelled` arrives but leaves the handler running, so cargo
+//! runs watch the token of the call they belong to and kill cargo when it fires.
+
+use std::future::Future;
+use tokio_util::sync::CancellationToken;
+
+tokio::task_local! {
+    /// Cancellation token of the tool call running on this task
+    static CANCELLATION: CancellationToken;
+}
+
+/// Run `future` with `token` as the token `cancelled` waits on
+pub fn scope<F: Future>(token: CancellationToken, future: F) -> impl Future<Output = F::Output> {
+    CANCELLATION.scope(token, future)
+}
+
+/// Resolves once the current tool call is cancelled; never outside one
+pub async fn cancelled() {
+    match CANCELLATION.try_with(Clone::clone) {
+        Ok(token) => token.cancelled().await,
+        Err(_) => std::future::pending().await,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    #[tokio::test]
+    async fn cancelled_follows_the_scoped_token() {
+        let uncancelled = tokio::time::timeout(Duration::from_millis(20), cancelled()).await;
+        assert!(uncancelled.is_err());
+
+        let token = CancellationToken::new();
+        token.cancel();
+        let scoped = tokio::time::timeout(Duration::from_millis(20), scope(token, cancelled()));
+        assert!(scoped.await.is_ok());
+    }
+}
@@ -0,0 +1,274 @@
+//! Environment for spawned cargo commands. Children start from an empty
+//! environment plus an allowlist, so registry tokens and other credentials
+//! in the server's environment never reach build scripts or snippet code,
+//! and builds use a dedicated cargo home unless the user opts to share theirs.
+
+use crate::limits::ResourceLimits;
+use std::ffi::OsString;
+use std::path::{Path, PathBuf};
+
+/// Variables passed through from the server's environment: what cargo,
+/// rustup and linkers need to find the toolchain and reach the network
+const PASSTHROUGH: &[&str] = &[
+    "PATH",
+    "HOME",
+    "USER",
+    "LOGNAME",
+    "LANG",
+    "LC_ALL",
+    "LC_CTYPE",
+    "TERM",
+    "TMPDIR",
+    "TMP",
+    "TEMP",
+    "RUSTUP_HOME",
+    "RUSTUP_TOOLCHAIN",
+    "HTTP_PROXY",
+    "HTTPS_PROXY",
+    "NO_PROXY",
+    "http_proxy",
+    "https_proxy",
+    "no_proxy",
+    "SSL_CERT_FILE",
+    "SSL_CERT_DIR",
+    "CARGO_HTTP_CAINFO",
+    "CC",
+    "CXX",
+    "AR",
+    "PKG_CONFIG_PATH",
+    "LD_LIBRARY_PATH",
+    // Windows
+    "SYSTEMROOT",
+    "WINDIR",
+    "USERPROFILE",
+    "APPDATA",
+    "LOCALAPPDATA",
+    "PATHEXT",
+    "COMSPEC",
+];
+
+/// Variables a caller may not set with a tool's `env` argument: they pick
+/// the programs cargo runs or where it looks for them and its config
+const DENIED: &[&str] = &[
+    "PATH",
+    "HOME",
+    "RUSTC",
+    "RUSTDOC",
+    "RUSTFLAGS",
+    "RUSTDOCFLAGS",
+    "CC",
+    "CXX",
+    "AR",
+];
+
+/// Prefixes denied the same way: cargo's own settings (`CARGO_HOME`,
+/// `CARGO_TARGET_<triple>_RUNNER`, ...), wrappers, toolchain selection and
+/// the dynamic loader
+const DENIED_PREFIXES: &[&str] = &["CARGO_", "RUSTC_", "RUSTUP_", "LD_", "DYLD_"];
+
+/// Why a caller-supplied variable can't be set, if it can't
+pub fn check_user_var(key: &str, value: &str) -> Result<(), String> {
+    if key.is_empty() || key.contains(['=', '\0']) {
+        return Err(format!("invalid environment variable name: {:?}", key));
+    }
+    if value.contains('\0') {
+        return Err(format!("value of {} contains a NUL byte", key));
+    }
+    let upper = key.to_ascii_uppercase();
+    if DENIED.contains(&upper.as_str())
+        || DENIED_PREFIXES
+            .iter()
+            .any(|prefix| upper.starts_with(prefix))
+    {
+        return Err(format!(
+            "{} can't be set: it could change which programs cargo runs",
+            key
+        ));
+    }
+    Ok(())
+}
+
+/// Cargo home subdirectories that only hold downloaded sources and indexes
+const CACHE_DIRS: &[&str] = &["registry", "git"];
+
+/// `cargo-home` in the data directory, next to the database
+pub fn default_cargo_home() -> PathBuf {
+    crate::data_dir()
+        .map(|dir| dir.join("cargo-home"))
+        .unwrap_or_else(|| std::env::temp_dir().join("rusty-tools-cargo-home"))
+}
+
+#[derive(Debug, Clone)]
+pub struct ChildEnv {
+    /// Dedicated `CARGO_HOME`; `None` shares the user's
+    cargo_home: Option<PathBuf>,
+    /// Keep cargo off the network (`CARGO_NET_OFFLINE`)
+    offline: bool,
+    /// rlimits cargo and everything it starts runs under
+    limits: ResourceLimits,
+}
+
+impl Default for ChildEnv {
+    fn default() -> Self {
+        ChildEnv::new(Some(default_cargo_home()), false)
+    }
+}
+
+impl ChildEnv {
+    pub fn new(cargo_home: Option<PathBuf>, offline: bool) -> Self {
+        ChildEnv {
+            cargo_home,
+            offline,
+            limits: ResourceLimits::default(),
+        }
+    }
+
+    pub fn with_limits(mut self, limits: ResourceLimits) -> Self {
+        self.limits = limits;
+        self
+    }
+
+    pub fn limits(&self) -> ResourceLimits {
+        self.limits
+    }
+
+    pub fn cargo_home(&self) -> Option<&Path> {
+        self.cargo_home.as_deref()
+    }
+
+    /// Variables to spawn a command with after `env_clear`. The dedicated
+    /// cargo home is created on first use.
+    pub fn vars(&self) -> Vec<(OsString, OsString)> {
+        let mut vars = allowed(std::env::vars_os());
+        match &self.cargo_home {
+            Some(home) => {
+                if let Err(e) = std::fs::create_dir_all(home) {
+                    tracing::warn!("Failed to create {}: {}", home.display(), e);
+                }
+                vars.push(("CARGO_HOME".into(), home.into()));
+            }
+            None => {
+                if let Some(home) = std::env::var_os("CARGO_HOME") {
+                    vars.push(("CARGO_HOME".into(), home));
+                }
+            }
+        }
+        if self.offline {
+            vars.push(("CARGO_NET_OFFLINE".into(), "true".into()));
+        }
+        vars
+    }
+
+    /// Bytes used by the dedicated cargo home, `None` when sharing the user's
+    pub fn size_bytes(&self) -> Option<u64> {
+        self.cargo_home.as_deref().map(dir_size)
+    }
+
+    /// Delete the downloaded registry and git sources from the dedicated
+    /// cargo home, returning the bytes freed. Sharing the user's cargo home
+    /// leaves it alone and frees nothing.
+    pub fn prune(&self) -> std::io::Result<u64> {
+        let Some(home) = &self.cargo_home else {
+            return Ok(0);
+        };
+        let mut freed = 0;
+        for name in CACHE_DIRS {
+            let dir = home.join(name);
+            if dir.exists() {
+                freed += dir_size(&dir);
+                std::fs::remove_dir_all(&dir)?;
+            }
+        }
+        Ok(freed)
+    }
+}
+
+/// The allowlisted subset of `vars`
+fn allowed(vars: impl Iterator<Item = (OsString, OsString)>) -> Vec<(OsString, OsString)> {
+    vars.filter(|(key, _)| key.to_str().is_some_and(|key| PASSTHROUGH.contains(&key)))
+        .collect()
+}
+
+pub(crate) fn dir_size(path: &Path) -> u64 {
+    let Ok(entries) = std::fs::read_dir(path) else {
+        return 0;
+    };
+    entries
+        .flatten()
+        .map(|entry| match entry.file_type() {
+            Ok(kind) if kind.is_dir() => dir_size(&entry.path()),
+            Ok(_) => entry.metadata().map(|m| m.len()).unwrap_or(0),
+            Err(_) => 0,
+        })
+        .sum()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn credentials_are_not_passed_through() {
+        let vars = [
+            ("PATH", "/usr/bin"),
+            ("CARGO_REGISTRY_TOKEN", "secret"),
+            ("CARGO_REGISTRIES_CORP_TOKEN", "secret"),
+            ("GITHUB_TOKEN", "secret"),
+            ("AWS_SECRET_ACCESS_KEY", "secret"),
+            ("RUSTUP_TOOLCHAIN", "stable"),
+        ]
+        .map(|(key, value)| (OsString::from(key), OsString::from(value)));
+        let names: Vec<OsString> = allowed(vars.into_iter())
+            .into_iter()
+            .map(|(key, _)| key)
+            .collect();
+        assert_eq!(names, ["PATH", "RUSTUP_TOOLCHAIN"]);
+    }
+
+    #[test]
+    fn user_vars_cannot_redirect_execution() {
+        assert!(check_user_var("APP_CONFIG", "debug").is_ok());
+        assert!(check_user_var("RUST_LOG", "trace").is_ok());
+        for key in [
+            "PATH",
+            "CARGO_HOME",
+            "CARGO_TARGET_X86_64_UNKNOWN_LINUX_GNU_RUNNER",
+            "RUSTC",
+            "RUSTC_WRAPPER",
+            "LD_PRELOAD",
+            "DYLD_INSERT_LIBRARIES",
+            "ld_preload",
+            "",
+            "A=B",
+        ] {
+            assert!(check_user_var(key, "x").is_err(), "{}", key);
+        }
+        assert!(check_user_var("APP_CONFIG", "a\0b").is_err());
+    }
+
+    #[test]
+    fn dedicated_home_is_created_measured_and_pruned() {
+        let dir = tempfile::tempdir().unwrap();
+        let home = dir.path().join("cargo-home");
+        let env = ChildEnv::new(Some(home.clone()), false);
+        let vars = env.vars();
+        assert!(home.is_dir());
+        assert!(vars.contains(&("CARGO_HOME".into(), home.clone().into())));
+        assert!(!vars.iter().any(|(key, _)| key == "CARGO_NET_OFFLINE"));
+        let offline = ChildEnv::new(Some(home.clone()), true).vars();
+        assert!(offline.contains(&("CARGO_NET_OFFLINE".into(), "true".into())));
+
+        std::fs::create_dir_all(home.join("registry").join("cache")).unwrap();
+        std::fs::write(
+            home.join("registry").join("cache").join("a.crate"),
+            [0; 100],
+        )
+        .unwrap();
+        std::fs::write(home.join("config.toml"), [0; 10]).unwrap();
+        assert_eq!(env.size_bytes(), Some(110));
+        assert_eq!(env.prune().unwrap(), 100);
+        assert_eq!(env.size_bytes(), Some(10));
+
+        assert_eq!(ChildEnv::new(None, false).size_bytes(), None);
+    }
+}
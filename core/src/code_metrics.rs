@@ -0,0 +1,339 @@
+//! Size and complexity figures for a snippet, read from syn's syntax tree
+//! in-process: line counts, items per kind, and a rough cyclomatic
+//! complexity for each function.
+
+use crate::syntax::{self, SyntaxError};
+use syn::spanned::Spanned;
+use syn::visit::{self, Visit};
+
+/// Lines of the snippet by what they hold
+#[derive(Debug, Clone, Default, PartialEq, serde::Serialize)]
+pub struct LineCounts {
+    pub total: usize,
+    pub code: usize,
+    /// Lines holding only a comment, doc comments included
+    pub comment: usize,
+    pub blank: usize,
+}
+
+/// One function, method, type, trait, impl or module
+#[derive(Debug, Clone, PartialEq, serde::Serialize)]
+pub struct ItemMetrics {
+    /// "fn", "struct", "enum", "union", "trait", "impl" or "mod"
+    pub kind: &'static str,
+    /// Methods and nested items are qualified, e.g. `Point::new` or
+    /// `geometry::area`
+    pub name: String,
+    pub start_line: usize,
+    pub end_line: usize,
+    /// Cyclomatic complexity, for functions with a body
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub complexity: Option<usize>,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, serde::Serialize)]
+pub struct MetricTotals {
+    /// Free functions and methods with a body
+    pub functions: usize,
+    pub structs: usize,
+    pub enums: usize,
+    pub traits: usize,
+    pub impls: usize,
+    pub modules: usize,
+    /// Sum over all functions
+    pub complexity: usize,
+    pub max_complexity: usize,
+    pub average_complexity: f64,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, serde::Serialize)]
+pub struct CodeMetrics {
+    pub lines: LineCounts,
+    pub items: Vec<ItemMetrics>,
+    pub totals: MetricTotals,
+}
+
+/// Metrics for `code`, or the errors syn reports when it doesn't parse
+pub fn measure(code: &str) -> Result<CodeMetrics, Vec<SyntaxError>> {
+    let parsed = syn::parse_file(code);
+    let measured = match parsed {
+        Ok(file) => {
+            let mut items = Vec::new();
+            collect(&file.items, "", &mut items);
+            let totals = totals(&items);
+            Ok(CodeMetrics {
+                lines: count_lines(code),
+                items,
+                totals,
+            })
+        }
+        Err(error) => Err(error.into_iter().map(SyntaxError::from).collect()),
+    };
+    syntax::release_spans();
+    measured
+}
+
+fn collect(items: &[syn::Item], prefix: &str, out: &mut Vec<ItemMetrics>) {
+    for item in items {
+        let qualified = |name: &dyn std::fmt::Display| format!("{}{}", prefix, name);
+        match item {
+            syn::Item::Fn(f) => out.push(function(qualified(&f.sig.ident), item.span(), &f.block)),
+            syn::Item::Struct(s) => out.push(named("struct", qualified(&s.ident), item.span())),
+            syn::Item::Enum(e) => out.push(named("enum", qualified(&e.ident), item.span())),
+            syn::Item::Union(u) => out.push(named("union", qualified(&u.ident), item.span())),
+            syn::Item::Trait(t) => {
+                let name = qualified(&t.ident);
+                out.push(named("trait", name.clone(), item.span()));
+                for trait_item in &t.items {
+                    // Only default methods have a body to measure
+                    if let syn::TraitItem::Fn(f) = trait_item
+                        && let Some(block) = &f.default
+                    {
+                        out.push(function(
+                            format!("{}::{}", name, f.sig.ident),
+                            trait_item.span(),
+                            block,
+                        ));
+                    }
+                }
+            }
+            syn::Item::Impl(i) => {
+                let self_ty = type_name(&i.self_ty);
+                let name = match &i.trait_ {
+                    Some((_, path, _)) => {
+                        let trait_name = path
+                            .segments
+                            .last()
+                            .map(|s| s.ident.to_string())
+                            .unwrap_or_default();
+                        format!("{} for {}", trait_name, self_ty)
+                    }
+                    None => self_ty.clone(),
+                };
+                out.push(named("impl", qualified(&name), item.span()));
+                for impl_item in &i.items {
+                    if let syn::ImplItem::Fn(f) = impl_item {
+                        out.push(function(
+                            format!("{}{}::{}", prefix, self_ty, f.sig.ident),
+                            impl_item.span(),
+                            &f.block,
+                        ));
+                    }
+                }
+            }
+            syn::Item::Mod(m) => {
+                let name = qualified(&m.ident);
+                out.push(named("mod", name.clone(), item.span()));
+                if let Some((_, items)) = &m.content {
+                    collect(items, &format!("{}::", name), out);
+                }
+            }
+            _ => {}
+        }
+    }
+}
+
+fn named(kind: &'static str, name: String, span: proc_macro2::Span) -> ItemMetrics {
+    ItemMetrics {
+        kind,
+        name,
+        start_line: span.start().line,
+        end_line: span.end().line,
+        complexity: None,
+    }
+}
+
+fn function(name: String, span: proc_macro2::Span, body: &syn::Block) -> ItemMetrics {
+    ItemMetrics {
+        complexity: Some(complexity(body)),
+        ..named("fn", name, span)
+    }
+}
+
+/// The last path segment of a type, e.g. `Point` for `crate::Point<T>`
+fn type_name(ty: &syn::Type) -> String {
+    match ty {
+        syn::Type::Path(path) => path
+            .path
+            .segments
+            .last()
+            .map(|s| s.ident.to_string())
+            .unwrap_or_default(),
+        syn::Type::Reference(reference) => type_name(&reference.elem),
+        _ => "_".to_string(),
+    }
+}
+
+/// McCabe's count: one path through the body plus one for each branch
+/// point. `if`, `while`, `for`, each `match` arm after the first, match
+/// guards and `&&`/`||` each add one; closures count toward the function
+/// they are in, nested items don't.
+fn complexity(body: &syn::Block) -> usize {
+    let mut counter = Complexity { branches: 0 };
+    counter.visit_block(body);
+    1 + counter.branches
+}
+
+struct Complexity {
+    branches: usize,
+}
+
+impl<'ast> Visit<'ast> for Complexity {
+    fn visit_expr_if(&mut self, node: &'ast syn::ExprIf) {
+        self.branches += 1;
+        visit::visit_expr_if(self, node);
+    }
+
+    fn visit_expr_while(&mut self, node: &'ast syn::ExprWhile) {
+        self.branches += 1;
+        visit::visit_expr_while(self, node);
+    }
+
+    fn visit_expr_for_loop(&mut self, node: &'ast syn::ExprForLoop) {
+        self.branches += 1;
+        visit::visit_expr_for_loop(self, node);
+    }
+
+    fn visit_expr_match(&mut self, node: &'ast syn::ExprMatch) {
+        self.branches += node.arms.len().saturating_sub(1);
+        self.branches += node.arms.iter().filter(|arm| arm.guard.is_some()).count();
+        visit::visit_expr_match(self, node);
+    }
+
+    fn visit_expr_binary(&mut self, node: &'ast syn::ExprBinary) {
+        if matches!(node.op, syn::BinOp::And(_) | syn::BinOp::Or(_)) {
+            self.branches += 1;
+        }
+        visit::visit_expr_binary(self, node);
+    }
+
+    fn visit_item(&mut self, _: &'ast syn::Item) {}
+}
+
+fn totals(items: &[ItemMetrics]) -> MetricTotals {
+    let count = |kind: &str| items.iter().filter(|item| item.kind == kind).count();
+    let complexities: Vec<usize> = items.iter().filter_map(|item| item.complexity).collect();
+    let complexity: usize = complexities.iter().sum();
+    MetricTotals {
+        functions: complexities.len(),
+        structs: count("struct"),
+        enums: count("enum"),
+        traits: count("trait"),
+        impls: count("impl"),
+        modules: count("mod"),
+        complexity,
+        max_complexity: complexities.iter().copied().max().unwrap_or(0),
+        average_complexity: if complexities.is_empty() {
+            0.0
+        } else {
+            complexity as f64 / complexities.len() as f64
+        },
+    }
+}
+
+/// Classify each line; a line inside a block comment counts as comment
+fn count_lines(code: &str) -> LineCounts {
+    let mut counts = LineCounts::default();
+    let mut in_block = false;
+    for line in code.lines() {
+        let line = line.trim();
+        counts.total += 1;
+        if in_block {
+            counts.comment += 1;
+            in_block = !line.contains("*/");
+        } else if line.is_empty() {
+            counts.blank += 1;
+        } else if line.starts_with("//") {
+            counts.comment += 1;
+        } else if line.starts_with("/*") {
+            counts.comment += 1;
+            in_block = !line.contains("*/");
+        } else {
+            counts.code += 1;
+        }
+    }
+    counts
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn functions_get_a_complexity_and_items_are_counted() {
+        let code = r#"//! Geometry helpers
+
+/// A point
+struct Point { x: i32, y: i32 }
+
+enum Shape { Dot, Line }
+
+impl Point {
+    fn quadrant(&self) -> u8 {
+        if self.x > 0 && self.y > 0 {
+            1
+        } else if self.x < 0 {
+            2
+        } else {
+            3
+        }
+    }
+}
+
+mod shapes {
+    pub fn sides(shape: &super::Shape) -> u32 {
+        match shape {
+            super::Shape::Dot => 0,
+            super::Shape::Line => 1,
+        }
+    }
+}
+
+/* the entry
+   point */
+fn main() {
+    for i in 0..3 {
+        let _ = |x: i32| if x > i { x } else { i };
+    }
+}
+"#;
+        let metrics = measure(code).unwrap();
+        assert_eq!(
+            metrics.lines,
+            LineCounts {
+                total: 35,
+                code: 26,
+                comment: 4,
+                blank: 5,
+            }
+        );
+        let functions: Vec<_> = metrics
+            .items
+            .iter()
+            .filter_map(|item| Some((item.name.as_str(), item.complexity?)))
+            .collect();
+        // quadrant: if + else if + &&; sides: two arms; main: for + the closure's if
+        assert_eq!(
+            functions,
+            [("Point::quadrant", 4), ("shapes::sides", 2), ("main", 3)]
+        );
+        let quadrant = &metrics.items[3];
+        assert_eq!((quadrant.start_line, quadrant.end_line), (9, 17));
+
+        let totals = &metrics.totals;
+        assert_eq!(
+            (
+                totals.functions,
+                totals.structs,
+                totals.enums,
+                totals.impls,
+                totals.modules
+            ),
+            (3, 1, 1, 1, 1)
+        );
+        assert_eq!((totals.complexity, totals.max_complexity), (9, 4));
+        assert_eq!(totals.average_complexity, 3.0);
+
+        assert!(measure("fn main() {").is_err());
+    }
+}
@@ -0,0 +1,189 @@
+//! Coverage summaries for `cargo_coverage`, read from the
+//! `llvm.coverage.json.export` document `cargo llvm-cov --json` prints.
+
+use serde_json::Value;
+
+/// Covered and total counts for one kind of coverage
+#[derive(Debug, Clone, Copy, PartialEq, serde::Serialize)]
+pub struct CoverageCount {
+    pub covered: u64,
+    pub count: u64,
+    pub percent: f64,
+}
+
+impl CoverageCount {
+    fn from_summary(summary: &Value) -> Option<Self> {
+        Some(CoverageCount {
+            covered: summary.get("covered")?.as_u64()?,
+            count: summary.get("count")?.as_u64()?,
+            percent: summary.get("percent")?.as_f64()?,
+        })
+    }
+}
+
+/// Region coverage of a single function
+#[derive(Debug, Clone, PartialEq, serde::Serialize)]
+pub struct FunctionCoverage {
+    pub name: String,
+    /// Times the function was entered
+    pub execution_count: u64,
+    pub regions: CoverageCount,
+}
+
+/// Coverage of one of the snippet's own source files
+#[derive(Debug, Clone, PartialEq, serde::Serialize)]
+pub struct FileCoverage {
+    /// Path relative to the project, e.g. `src/main.rs`
+    pub file: String,
+    pub lines: CoverageCount,
+    pub regions: CoverageCount,
+    pub functions: CoverageCount,
+}
+
+/// Source files a snippet can occupy in the scratch project
+const SNIPPET_FILES: &[&str] = &["src/main.rs", "src/lib.rs"];
+
+/// The snippet file an exported absolute path refers to, if any
+fn snippet_file(path: &str) -> Option<&'static str> {
+    SNIPPET_FILES
+        .iter()
+        .copied()
+        .find(|file| path.ends_with(&format!("/{}", file)) || path == *file)
+}
+
+#[derive(Debug, Clone, PartialEq, serde::Serialize)]
+pub struct CoverageReport {
+    pub lines: CoverageCount,
+    pub regions: CoverageCount,
+    pub functions: CoverageCount,
+    /// Only exported when branch coverage was instrumented
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub branches: Option<CoverageCount>,
+    pub files: Vec<FileCoverage>,
+    /// Per-function detail, only present when requested and exported
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub per_function: Option<Vec<FunctionCoverage>>,
+}
+
+impl CoverageReport {
+    /// Read the export from `cargo llvm-cov --json` stdout, which may also
+    /// carry test output before the JSON line
+    pub fn from_llvm_cov_output(stdout: &str, per_function: bool) -> Option<Self> {
+        let export = stdout
+            .lines()
+            .rev()
+            .filter(|line| line.starts_with('{'))
+            .find_map(|line| serde_json::from_str::<Value>(line).ok())?;
+        let data = export.get("data")?.get(0)?;
+        let totals = data.get("totals")?;
+        let per_function = if per_function {
+            data.get("functions")
+                .and_then(|f| f.as_array())
+                .map(|functions| {
+                    functions
+                        .iter()
+                        .filter(|f| {
+                            f["filenames"][0]
+                                .as_str()
+                                .is_some_and(|path| snippet_file(path).is_some())
+                        })
+                        .filter_map(function_coverage)
+                        .collect()
+                })
+        } else {
+            None
+        };
+        let files = data
+            .get("files")
+            .and_then(|f| f.as_array())
+            .map(|files| {
+                files
+                    .iter()
+                    .filter_map(|file| {
+                        let summary = file.get("summary")?;
+                        Some(FileCoverage {
+                            file: snippet_file(file.get("filename")?.as_str()?)?.to_string(),
+                            lines: CoverageCount::from_summary(summary.get("lines")?)?,
+                            regions: CoverageCount::from_summary(summary.get("regions")?)?,
+                            functions: CoverageCount::from_summary(summary.get("functions")?)?,
+                        })
+                    })
+                    .collect()
+            })
+            .unwrap_or_default();
+        Some(CoverageReport {
+            lines: CoverageCount::from_summary(totals.get("lines")?)?,
+            regions: CoverageCount::from_summary(totals.get("regions")?)?,
+            functions: CoverageCount::from_summary(totals.get("functions")?)?,
+            branches: totals
+                .get("branches")
+                .and_then(CoverageCount::from_summary)
+                .filter(|branches| branches.count > 0),
+            files,
+            per_function,
+        })
+    }
+}
+
+/// Regions are `[line_start, col_start, line_end, col_end, execution_count,
+/// file_id, expanded_file_id, kind]`; only code regions (kind 0) count
+fn function_coverage(function: &Value) -> Option<FunctionCoverage> {
+    let mangled = function.get("name")?.as_str()?;
+    let (mut covered, mut count) = (0, 0);
+    for region in function.get("regions")?.as_array()? {
+        let region = region.as_array()?;
+        if region.get(7).and_then(|k| k.as_u64()).unwrap_or(0) != 0 {
+            continue;
+        }
+        count += 1;
+        if region.get(4).and_then(|c| c.as_u64()).unwrap_or(0) > 0 {
+            covered += 1;
+        }
+    }
+    let percent = if count == 0 {
+        0.0
+    } else {
+        covered as f64 * 100.0 / count as f64
+    };
+    Some(FunctionCoverage {
+        name: format!("{:#}", rustc_demangle::demangle(mangled)),
+        execution_count: function.get("count")?.as_u64()?,
+        regions: CoverageCount {
+            covered,
+            count,
+            percent,
+        },
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const EXPORT: &str = r#"{"data":[{"files":[{"filename":"/tmp/.tmpabc/src/main.rs","summary":{"functions":{"count":2,"covered":1,"percent":50.0},"lines":{"count":4,"covered":3,"percent":75.0},"regions":{"count":3,"covered":1,"notcovered":2,"percent":33.33333333333333}}},{"filename":"/root/.cargo/registry/src/foo/src/lib.rs.in","summary":{}}],"functions":[{"count":1,"filenames":["/tmp/.tmpabc/src/main.rs"],"name":"_RNvCs1234_12temp_project3add","regions":[[1,1,3,2,1,0,0,0],[2,8,2,12,0,0,0,0],[2,9,2,10,1,0,0,1]]},{"count":0,"filenames":["/tmp/.tmpabc/src/main.rs"],"name":"_RNvCs1234_12temp_project6unused","regions":[[5,1,5,20,0,0,0,0]]},{"count":3,"filenames":["/rustc/abc/library/core/src/fmt.rs"],"name":"_RNvCs1234_4core3fmt","regions":[]}],"totals":{"branches":{"count":0,"covered":0,"notcovered":0,"percent":0},"functions":{"count":2,"covered":1,"percent":50.0},"instantiations":{"count":2,"covered":1,"percent":50.0},"lines":{"count":4,"covered":3,"percent":75.0},"regions":{"count":3,"covered":1,"notcovered":2,"percent":33.33333333333333}}}],"type":"llvm.coverage.json.export","version":"2.0.1"}"#;
+
+    #[test]
+    fn totals_and_functions_are_read() {
+        let stdout = format!("running 1 test\ntest t ... ok\n{}\n", EXPORT);
+        let report = CoverageReport::from_llvm_cov_output(&stdout, true).unwrap();
+        assert_eq!(report.lines.percent, 75.0);
+        assert_eq!(report.regions.covered, 1);
+        assert_eq!(report.functions.count, 2);
+        assert_eq!(report.branches, None);
+        assert_eq!(report.files.len(), 1);
+        assert_eq!(report.files[0].file, "src/main.rs");
+
+        let functions = report.per_function.unwrap();
+        assert_eq!(functions.len(), 2);
+        assert_eq!(functions[0].name, "temp_project::add");
+        assert_eq!(
+            (functions[0].regions.covered, functions[0].regions.count),
+            (1, 2)
+        );
+        assert_eq!(functions[1].execution_count, 0);
+
+        let summary = CoverageReport::from_llvm_cov_output(EXPORT, false).unwrap();
+        assert!(summary.per_function.is_none());
+        assert!(CoverageReport::from_llvm_cov_output("error: no tests", false).is_none());
+    }
+}
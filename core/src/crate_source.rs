@@ -0,0 +1,220 @@
+//! Published crates analyzed in place of a snippet. `crate_source` resolves a
+//! crates.io crate with `cargo metadata`, so the download goes through cargo's
+//! own registry client and cargo home, then copies its source into a scratch
+//! directory the tool can build in.
+
+use crate::ChildEnv;
+use crate::args::CrateSourceArg;
+use rmcp::ErrorData as McpError;
+use serde_json::{Value, json};
+use std::path::Path;
+use std::time::Duration;
+use tempfile::TempDir;
+use tokio::process::Command;
+
+/// How long resolving and downloading a crate may take
+const FETCH_TIMEOUT: Duration = Duration::from_secs(120);
+
+/// A crates.io crate requested with `crate_source: {name, version?}`
+#[derive(Debug, Clone, PartialEq)]
+pub struct CrateSource {
+    pub name: String,
+    /// Version requirement; a bare version such as `1.0.200` means exactly
+    /// that version, and none means the latest
+    pub version: Option<String>,
+}
+
+/// The crate and exact version a `CrateSource` resolved to
+#[derive(Debug, Clone, PartialEq, serde::Serialize)]
+pub struct ResolvedCrate {
+    pub name: String,
+    pub version: String,
+}
+
+/// A resolved crate's source, copied into a directory of its own
+pub struct FetchedCrate {
+    pub dir: TempDir,
+    pub resolved: ResolvedCrate,
+}
+
+impl CrateSource {
+    pub fn parse(source: &CrateSourceArg) -> Result<Self, McpError> {
+        let invalid = |message: &str| McpError::invalid_params(message.to_string(), None);
+        if !crate::is_package_spec(&source.name) || source.name.contains('@') {
+            return Err(invalid(&format!("invalid crate name: {:?}", source.name)));
+        }
+        if let Some(version) = &source.version
+            && !crate::is_version_requirement(version)
+        {
+            return Err(invalid(
+                "crate_source version must be a version such as 1.0.200 or a requirement such as ^1.0",
+            ));
+        }
+        Ok(CrateSource {
+            name: source.name.clone(),
+            version: source.version.clone(),
+        })
+    }
+
+    /// The dependency line for the resolver project
+    fn requirement(&self) -> String {
+        match &self.version {
+            None => "*".to_string(),
+            Some(version) if version.starts_with(|c: char| c.is_ascii_digit()) => {
+                format!("={}", version)
+            }
+            Some(requirement) => requirement.clone(),
+        }
+    }
+
+    /// Resolve and download the crate, refusing archives over `max_bytes`,
+    /// and copy its source into a fresh directory
+    pub async fn fetch(
+        &self,
+        child_env: &ChildEnv,
+        max_bytes: u64,
+    ) -> Result<FetchedCrate, McpError> {
+        let io_err = |e: std::io::Error| {
+            McpError::internal_error(format!("Failed to prepare crate source: {}", e), None)
+        };
+        let resolver = tempfile::tempdir().map_err(io_err)?;
+        std::fs::create_dir_all(resolver.path().join("src")).map_err(io_err)?;
+        std::fs::write(resolver.path().join("src").join("lib.rs"), "").map_err(io_err)?;
+        std::fs::write(
+            resolver.path().join("Cargo.toml"),
+            format!(
+                "[package]\nname = \"rusty-tools-resolver\"\nversion = \"0.0.0\"\nedition = \"2021\"\n\n[dependencies]\n{} = {}\n",
+                self.name,
+                json!(self.requirement())
+            ),
+        )
+        .map_err(io_err)?;
+
+        let child = Command::new("cargo")
+            .args(["metadata", "--format-version", "1"])
+            .current_dir(resolver.path())
+            .env_clear()
+            .envs(child_env.vars())
+            .kill_on_drop(true)
+            .output();
+        // Dropping the child on cancellation kills it
+        let output = tokio::select! {
+            output = tokio::time::timeout(FETCH_TIMEOUT, child) => output,
+            _ = crate::cancellation::cancelled() => {
+                return Err(McpError::internal_error("Tool call cancelled", None));
+            }
+        };
+        let output = output
+            .map_err(|_| {
+                McpError::internal_error(
+                    format!(
+                        "Resolving {} timed out after {}s",
+                        self.name,
+                        FETCH_TIMEOUT.as_secs()
+                    ),
+                    None,
+                )
+            })?
+            .map_err(|e| McpError::internal_error(format!("Failed to run cargo: {}", e), None))?;
+        if !output.status.success() {
+            return Err(McpError::invalid_params(
+                format!("Could not resolve crate {}", self.name),
+                Some(json!({"stderr": String::from_utf8_lossy(&output.stderr).trim()})),
+            ));
+        }
+
+        let metadata: Value = serde_json::from_slice(&output.stdout).map_err(|e| {
+            McpError::internal_error(format!("Unreadable cargo metadata: {}", e), None)
+        })?;
+        let package = metadata["packages"]
+            .as_array()
+            .into_iter()
+            .flatten()
+            .find(|package| {
+                package["name"] == self.name.as_str()
+                    && package["source"].as_str().is_some_and(|source| {
+                        source.starts_with("registry+") || source.starts_with("sparse+")
+                    })
+            })
+            .ok_or_else(|| {
+                McpError::internal_error(format!("cargo metadata did not list {}", self.name), None)
+            })?;
+        let version = package["version"].as_str().unwrap_or_default().to_string();
+        let source_dir = package["manifest_path"]
+            .as_str()
+            .and_then(|manifest| Path::new(manifest).parent())
+            .ok_or_else(|| {
+                McpError::internal_error(format!("No source found for {}", self.name), None)
+            })?;
+
+        let size = archive_size(source_dir, &self.name, &version)
+            .unwrap_or_else(|| crate::child_env::dir_size(source_dir));
+        if size > max_bytes {
+            return Err(McpError::invalid_params(
+                format!(
+                    "{} {} is {} bytes, over the {} byte limit",
+                    self.name, version, size, max_bytes
+                ),
+                None,
+            ));
+        }
+
+        let dir = tempfile::tempdir().map_err(io_err)?;
+        crate::copy_dir(source_dir, dir.path()).map_err(io_err)?;
+        Ok(FetchedCrate {
+            dir,
+            resolved: ResolvedCrate {
+                name: self.name.clone(),
+                version,
+            },
+        })
+    }
+}
+
+/// Size of the downloaded `.crate` archive, which cargo keeps at
+/// `registry/cache/<index>/<name>-<version>.crate` next to the unpacked
+/// `registry/src/<index>/<name>-<version>`
+fn archive_size(source_dir: &Path, name: &str, version: &str) -> Option<u64> {
+    let index_dir = source_dir.parent()?;
+    let registry = index_dir.parent()?.parent()?;
+    let archive = registry
+        .join("cache")
+        .join(index_dir.file_name()?)
+        .join(format!("{}-{}.crate", name, version));
+    std::fs::metadata(archive).ok().map(|m| m.len())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn crate_sources_are_validated() {
+        let parse = |source: Value| CrateSource::parse(&serde_json::from_value(source).unwrap());
+        let source = parse(json!({"name": "serde", "version": "1.0.200"})).unwrap();
+        assert_eq!(source.requirement(), "=1.0.200");
+        let latest = parse(json!({"name": "serde_json"})).unwrap();
+        assert_eq!(latest.requirement(), "*");
+        let range = parse(json!({"name": "log", "version": "^0.4"})).unwrap();
+        assert_eq!(range.requirement(), "^0.4");
+
+        assert!(parse(json!({"name": "serde\"\nevil = \"1"})).is_err());
+        assert!(parse(json!({"name": "serde@1"})).is_err());
+        assert!(parse(json!({"name": "serde", "version": "1\"; x"})).is_err());
+        assert!(serde_json::from_value::<CrateSourceArg>(json!({"version": "1"})).is_err());
+    }
+
+    #[test]
+    fn archive_is_found_beside_unpacked_source() {
+        let home = tempfile::tempdir().unwrap();
+        let registry = home.path().join("registry");
+        let source = registry.join("src").join("index-abc").join("itoa-1.0.11");
+        std::fs::create_dir_all(&source).unwrap();
+        let cache = registry.join("cache").join("index-abc");
+        std::fs::create_dir_all(&cache).unwrap();
+        std::fs::write(cache.join("itoa-1.0.11.crate"), [0; 42]).unwrap();
+
+        assert_eq!(archive_size(&source, "itoa", "1.0.11"), Some(42));
+        assert_eq!(archive_size(&source, "itoa", "1.0.12"), None);
+    }
+}
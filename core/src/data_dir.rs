@@ -0,0 +1,63 @@
+//! Where the database, saved docs and the dedicated cargo home go by
+//! default: `~/.rusty-tools` on Unix and `%LOCALAPPDATA%\rusty-tools` on
+//! Windows. The directories come from the platform (the known-folder API on
+//! Windows) rather than `HOME`, which Windows doesn't set.
+
+use std::path::PathBuf;
+
+/// The default data directory; `None` when the platform reports neither a
+/// home nor a data directory
+pub fn data_dir() -> Option<PathBuf> {
+    if cfg!(windows) {
+        choose(true, dirs::home_dir(), dirs::data_local_dir())
+    } else {
+        let xdg_data_home = std::env::var_os("XDG_DATA_HOME")
+            .filter(|dir| !dir.is_empty())
+            .map(PathBuf::from);
+        choose(false, dirs::home_dir(), xdg_data_home)
+    }
+}
+
+/// `<app_data>/rusty-tools` or `<home>/.rusty-tools`: the first on Windows,
+/// where `app_data` is the local AppData folder, and the second elsewhere,
+/// where `app_data` is `XDG_DATA_HOME`
+fn choose(windows: bool, home: Option<PathBuf>, app_data: Option<PathBuf>) -> Option<PathBuf> {
+    let dotted = home.map(|home| home.join(".rusty-tools"));
+    let app = app_data.map(|dir| dir.join("rusty-tools"));
+    if windows {
+        app.or(dotted)
+    } else {
+        dotted.or(app)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn data_dir_follows_each_platforms_convention() {
+        let home = || Some(PathBuf::from("/home/ana"));
+        let xdg = || Some(PathBuf::from("/data"));
+        assert_eq!(
+            choose(false, home(), xdg()),
+            Some(PathBuf::from("/home/ana/.rusty-tools"))
+        );
+        assert_eq!(
+            choose(false, None, xdg()),
+            Some(PathBuf::from("/data/rusty-tools"))
+        );
+
+        let profile = || Some(PathBuf::from(r"C:\Users\ana"));
+        let local = || Some(PathBuf::from(r"C:\Users\ana\AppData\Local"));
+        assert_eq!(
+            choose(true, profile(), local()),
+            Some(PathBuf::from(r"C:\Users\ana\AppData\Local").join("rusty-tools"))
+        );
+        assert_eq!(
+            choose(true, profile(), None),
+            Some(PathBuf::from(r"C:\Users\ana").join(".rusty-tools"))
+        );
+        assert_eq!(choose(true, None, None), None);
+    }
+}
@@ -0,0 +1,193 @@
+//! Policy results for `cargo_deny`, read from the JSON lines
+//! `cargo deny --format json check` writes to stderr.
+
+use serde_json::Value;
+use std::collections::BTreeMap;
+
+/// The checks `cargo deny check` runs by default
+pub const CATEGORIES: &[&str] = &["advisories", "bans", "licenses", "sources"];
+
+#[derive(Debug, Clone, PartialEq, serde::Serialize)]
+pub struct DenyDiagnostic {
+    /// "error" or "warning"
+    pub severity: String,
+    /// cargo-deny's diagnostic code, e.g. `vulnerability` or `rejected`
+    pub code: Option<String>,
+    pub message: String,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub notes: Vec<String>,
+}
+
+#[derive(Debug, Clone, PartialEq, serde::Serialize)]
+pub struct CategoryResult {
+    pub passed: bool,
+    pub errors: u64,
+    pub warnings: u64,
+    pub diagnostics: Vec<DenyDiagnostic>,
+}
+
+#[derive(Debug, Clone, PartialEq, serde::Serialize)]
+#[serde(transparent)]
+pub struct DenyReport {
+    pub categories: BTreeMap<&'static str, CategoryResult>,
+}
+
+impl DenyReport {
+    /// Read diagnostics and the closing summary from cargo-deny's JSON
+    /// output. `None` when there is no summary, e.g. the config failed to
+    /// parse before any check ran.
+    pub fn from_json_output(stderr: &str) -> Option<Self> {
+        let events: Vec<Value> = stderr
+            .lines()
+            .filter_map(|line| serde_json::from_str::<Value>(line.trim()).ok())
+            .collect();
+        let summary = events
+            .iter()
+            .rev()
+            .find(|event| event["type"] == "summary")?
+            .get("fields")?;
+
+        let mut categories: BTreeMap<&'static str, CategoryResult> = CATEGORIES
+            .iter()
+            .filter_map(|category| {
+                let counts = summary.get(*category)?;
+                let errors = counts.get("errors")?.as_u64()?;
+                Some((
+                    *category,
+                    CategoryResult {
+                        passed: errors == 0,
+                        errors,
+                        warnings: counts.get("warnings").and_then(|w| w.as_u64()).unwrap_or(0),
+                        diagnostics: Vec::new(),
+                    },
+                ))
+            })
+            .collect();
+
+        for fields in events
+            .iter()
+            .filter(|event| event["type"] == "diagnostic")
+            .filter_map(|event| event.get("fields"))
+        {
+            let severity = fields["severity"].as_str().unwrap_or_default();
+            if !matches!(severity, "error" | "warning") {
+                continue;
+            }
+            let code = fields["code"].as_str();
+            let Some(result) = code
+                .and_then(category_of)
+                .and_then(|category| categories.get_mut(category))
+            else {
+                continue;
+            };
+            result.diagnostics.push(DenyDiagnostic {
+                severity: severity.to_string(),
+                code: code.map(str::to_string),
+                message: fields["message"].as_str().unwrap_or_default().to_string(),
+                notes: fields["notes"]
+                    .as_array()
+                    .map(|notes| {
+                        notes
+                            .iter()
+                            .filter_map(|n| n.as_str().map(str::to_string))
+                            .collect()
+                    })
+                    .unwrap_or_default(),
+            });
+        }
+        Some(DenyReport { categories })
+    }
+
+    pub fn passed(&self) -> bool {
+        self.categories.values().all(|result| result.passed)
+    }
+}
+
+/// The check a diagnostic code belongs to. Advisory diagnostics also carry
+/// an `advisory` object, but the code alone covers every check.
+fn category_of(code: &str) -> Option<&'static str> {
+    let category = match code {
+        "vulnerability"
+        | "notice"
+        | "unmaintained"
+        | "unsound"
+        | "yanked"
+        | "index-failure"
+        | "index-cache-load-failure"
+        | "advisory-ignored"
+        | "advisory-not-detected"
+        | "unknown-advisory" => "advisories",
+        "rejected"
+        | "accepted"
+        | "unlicensed"
+        | "skipped-private-workspace-crate"
+        | "license-not-encountered"
+        | "license-exception-not-encountered"
+        | "missing-clarification-file"
+        | "parse-error"
+        | "empty-license-field"
+        | "no-license-field"
+        | "gather-failure" => "licenses",
+        "git-source-underspecified"
+        | "allowed-source"
+        | "allowed-by-organization"
+        | "source-not-allowed"
+        | "unmatched-source"
+        | "unmatched-organization" => "sources",
+        "banned"
+        | "allowed"
+        | "not-allowed"
+        | "duplicate"
+        | "skipped"
+        | "wildcard"
+        | "unmatched-skip"
+        | "allowed-by-wrapper"
+        | "unmatched-wrapper"
+        | "skipped-by-root"
+        | "unmatched-skip-root"
+        | "build-script-not-allowed"
+        | "exact-features-mismatch"
+        | "feature-banned"
+        | "unknown-feature"
+        | "default-feature-enabled"
+        | "path-bypassed"
+        | "workspace-duplicate" => "bans",
+        _ => return None,
+    };
+    Some(category)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn categories_pass_or_fail_independently() {
+        let stderr = r#"{"type":"log","fields":{"timestamp":"2026-01-01T00:00:00Z","level":"WARN","message":"unable to find a config path"}}
+{"type":"diagnostic","fields":{"severity":"error","message":"failed to satisfy license requirements","code":"rejected","labels":[{"message":"","span":"GPL-3.0","line":1,"column":1}],"notes":["GPL-3.0 - GNU General Public License v3.0 only"],"graphs":[]}}
+{"type":"diagnostic","fields":{"severity":"warning","message":"found 2 duplicate entries for crate 'syn'","code":"duplicate","labels":[],"notes":[],"graphs":[]}}
+{"type":"diagnostic","fields":{"severity":"note","message":"license was accepted","code":"accepted","labels":[],"notes":[],"graphs":[]}}
+{"type":"summary","fields":{"advisories":{"errors":0,"warnings":0,"notes":0,"helps":0},"bans":{"errors":0,"warnings":1,"notes":0,"helps":0},"licenses":{"errors":1,"warnings":0,"notes":1,"helps":0},"sources":{"errors":0,"warnings":0,"notes":0,"helps":0}}}
+"#;
+        let report = DenyReport::from_json_output(stderr).unwrap();
+        assert!(!report.passed());
+        assert_eq!(report.categories.len(), 4);
+        assert!(report.categories["advisories"].passed);
+        assert!(report.categories["sources"].passed);
+
+        let bans = &report.categories["bans"];
+        assert!(bans.passed);
+        assert_eq!(bans.warnings, 1);
+        assert_eq!(bans.diagnostics[0].code.as_deref(), Some("duplicate"));
+
+        let licenses = &report.categories["licenses"];
+        assert!(!licenses.passed);
+        assert_eq!(licenses.diagnostics.len(), 1);
+        assert_eq!(
+            licenses.diagnostics[0].notes,
+            ["GPL-3.0 - GNU General Public License v3.0 only"]
+        );
+
+        assert!(DenyReport::from_json_output("error: failed to parse deny.toml").is_none());
+    }
+}
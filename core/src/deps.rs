@@ -0,0 +1,145 @@
+//! Unused dependency detection for `cargo_machete`: a fallback scan of the
+//! manifest and source when cargo-machete isn't installed, and a parser for
+//! its report when it is.
+
+/// Manifest tables whose keys are dependency names
+const DEPENDENCY_TABLES: &[&str] = &["dependencies", "dev-dependencies", "build-dependencies"];
+
+/// Dependency names declared in a Cargo.toml, as written in the manifest.
+/// Handles `name = ...` keys inside dependency tables and `[dependencies.name]`
+/// headers, including target-specific tables.
+pub fn declared_dependencies(manifest: &str) -> Vec<String> {
+    let mut declared: Vec<String> = Vec::new();
+    let mut in_table = false;
+    for line in manifest.lines() {
+        let line = line.trim();
+        if let Some(header) = line.strip_prefix('[').and_then(|h| h.strip_suffix(']')) {
+            let header = header.trim();
+            // `target.'cfg(unix)'.dependencies` keeps only the last segments
+            let table = header.rsplit_once("'.").map_or(header, |(_, rest)| rest);
+            in_table = DEPENDENCY_TABLES.contains(&table);
+            if let Some((table, name)) = table.split_once('.')
+                && DEPENDENCY_TABLES.contains(&table)
+                && !declared.iter().any(|d| d == name)
+            {
+                declared.push(name.to_string());
+            }
+            continue;
+        }
+        if !in_table || line.starts_with('#') {
+            continue;
+        }
+        if let Some((name, _)) = line.split_once('=') {
+            let name = name.trim().trim_matches('"');
+            if !name.is_empty() && !declared.iter().any(|d| d == name) {
+                declared.push(name.to_string());
+            }
+        }
+    }
+    declared
+}
+
+/// Declared dependencies whose crate name never appears as a path root,
+/// `use`, or `extern crate` in the code
+pub fn unused_dependencies(manifest: &str, code: &str) -> Vec<String> {
+    declared_dependencies(manifest)
+        .into_iter()
+        .filter(|name| !is_referenced(&name.replace('-', "_"), code))
+        .collect()
+}
+
+fn is_referenced(crate_name: &str, code: &str) -> bool {
+    code.match_indices(crate_name).any(|(start, _)| {
+        let before = code[..start].chars().next_back();
+        let after = &code[start + crate_name.len()..];
+        let word_start = !before.is_some_and(|c| c.is_alphanumeric() || c == '_' || c == ':');
+        let word_end = !after.starts_with(|c: char| c.is_alphanumeric() || c == '_');
+        if !word_start || !word_end {
+            return false;
+        }
+        let preceding = code[..start].trim_end();
+        after.trim_start().starts_with("::")
+            || preceding.ends_with("use")
+            || preceding.ends_with("extern crate")
+    })
+}
+
+/// Crate names listed in a `cargo machete` report, which indents each
+/// unused dependency beneath its package's manifest path
+pub fn machete_unused(stdout: &str) -> Vec<String> {
+    stdout
+        .lines()
+        .filter(|line| line.starts_with('\t') || line.starts_with("    "))
+        .map(|line| line.trim().to_string())
+        .filter(|name| !name.is_empty())
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const MANIFEST: &str = r#"[package]
+name = "temp_project"
+version = "0.1.0"
+
+[dependencies]
+serde = { version = "1", features = ["derive"] }
+serde_json = "1"
+regex = "1"
+anyhow = "1"
+# log = "0.4"
+
+[dependencies.rand]
+version = "0.8"
+
+[target.'cfg(unix)'.dependencies]
+libc = "0.2"
+
+[dev-dependencies]
+tempfile = "3"
+"#;
+
+    #[test]
+    fn unreferenced_dependencies_are_reported() {
+        assert_eq!(
+            declared_dependencies(MANIFEST),
+            [
+                "serde",
+                "serde_json",
+                "regex",
+                "anyhow",
+                "rand",
+                "libc",
+                "tempfile"
+            ]
+        );
+        let code = r#"
+use serde::Serialize;
+extern crate libc;
+
+fn main() -> anyhow::Result<()> {
+    let _: serde_json::Value = serde_json::from_str("1")?;
+    let _ = rand::random::<u8>();
+    let myregex = 1;
+    Ok(())
+}
+"#;
+        assert_eq!(unused_dependencies(MANIFEST, code), ["regex", "tempfile"]);
+        assert!(unused_dependencies("[package]\nname = \"a\"\n", code).is_empty());
+    }
+
+    #[test]
+    fn machete_report_lists_crates() {
+        let stdout = "Analyzing dependencies of crates in this directory...
+cargo-machete found the following unused dependencies in /tmp/.tmpabc:
+temp_project -- /tmp/.tmpabc/Cargo.toml:
+\tregex
+\ttempfile
+
+If you believe cargo-machete has detected an unused dependency incorrectly,
+";
+        assert_eq!(machete_unused(stdout), ["regex", "tempfile"]);
+        assert!(machete_unused("cargo-machete didn't find any unused dependencies").is_empty());
+    }
+}
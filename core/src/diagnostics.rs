@@ -0,0 +1,451 @@
+//! Compiler diagnostics from cargo's `--message-format=json` output: the one
+//! place compiler messages are parsed for responses, stored errors, clippy
+//! todos and summaries.
+
+use serde_json::Value;
+use std::ops::Range;
+
+/// One rustc or clippy diagnostic
+#[derive(Debug, Clone, PartialEq, serde::Serialize)]
+pub struct Diagnostic {
+    /// "error", "warning", "note", "help" or "failure-note"
+    pub level: String,
+    /// e.g. `E0308` or `clippy::len_zero`
+    pub code: Option<String>,
+    pub message: String,
+    /// Location of the primary span
+    pub file: Option<String>,
+    pub line: Option<u32>,
+    pub column: Option<u32>,
+    /// The diagnostic as rustc prints it, with its code frame
+    pub rendered: String,
+    /// Every span rustc points at, the primary one included
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub spans: Vec<Span>,
+    /// Messages of `help` children, e.g. "remove this semicolon"
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub help: Vec<String>,
+    /// Machine-applicable fixes
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub suggestions: Vec<Suggestion>,
+    /// The package being compiled, e.g. a workspace member's name
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub package: Option<String>,
+}
+
+/// A source range a diagnostic points at
+#[derive(Debug, Clone, PartialEq, serde::Serialize)]
+pub struct Span {
+    pub file: String,
+    /// 1-based lines and columns, end exclusive
+    pub line_start: u32,
+    pub column_start: u32,
+    pub line_end: u32,
+    pub column_end: u32,
+    /// Whether this is where the diagnostic is reported, rather than a
+    /// related location
+    pub is_primary: bool,
+    /// rustc's note at the span, e.g. "expected `i32`, found `&str`"
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub label: Option<String>,
+}
+
+/// An edit rustc is confident fixes a diagnostic
+#[derive(Debug, Clone, PartialEq, serde::Serialize, serde::Deserialize)]
+pub struct Suggestion {
+    /// What the edit does, e.g. "using `is_empty` is clearer and more explicit"
+    #[serde(default)]
+    pub message: String,
+    pub file: String,
+    /// Byte range replaced, end exclusive
+    pub byte_start: usize,
+    pub byte_end: usize,
+    /// The same range as 1-based lines and columns, end exclusive
+    pub line_start: usize,
+    pub column_start: usize,
+    pub line_end: usize,
+    pub column_end: usize,
+    pub replacement: String,
+}
+
+impl Diagnostic {
+    /// Severity stored in the errors table
+    pub fn severity(&self) -> Option<&'static str> {
+        match self.level.as_str() {
+            "error" | "error: internal compiler error" => Some("error"),
+            "warning" => Some("warning"),
+            "note" | "failure-note" => Some("note"),
+            "help" => Some("help"),
+            _ => None,
+        }
+    }
+
+    /// An error that stopped compilation, as opposed to a lint denied by
+    /// `-D warnings`: lints always carry their name as the code, errors an
+    /// `E` number or none at all
+    pub fn is_compile_error(&self) -> bool {
+        self.severity() == Some("error")
+            && self.code.as_deref().is_none_or(|code| {
+                code.len() > 1
+                    && code.starts_with('E')
+                    && code[1..].bytes().all(|b| b.is_ascii_digit())
+            })
+    }
+
+    fn from_message(message: &Value) -> Option<Self> {
+        let text = message["message"].as_str()?;
+        let spans = message["spans"]
+            .as_array()
+            .map(Vec::as_slice)
+            .unwrap_or_default();
+        // Closing tallies such as "aborting due to 2 previous errors" and
+        // "1 warning emitted" restate diagnostics already reported
+        if spans.is_empty() && (text.starts_with("aborting due to") || text.ends_with(" emitted")) {
+            return None;
+        }
+        let primary = spans.iter().find(|span| span["is_primary"] == true);
+        let number = |span: Option<&Value>, key: &str| {
+            span.and_then(|span| span[key].as_u64()).map(|n| n as u32)
+        };
+        let children = message["children"]
+            .as_array()
+            .map(Vec::as_slice)
+            .unwrap_or_default();
+        let help = children
+            .iter()
+            .filter(|child| child["level"] == "help")
+            .filter_map(|child| child["message"].as_str().map(str::to_string))
+            .collect();
+        let suggestions = children
+            .iter()
+            .flat_map(|child| {
+                let message = child["message"].as_str().unwrap_or_default();
+                child["spans"]
+                    .as_array()
+                    .into_iter()
+                    .flatten()
+                    .filter_map(move |span| Suggestion::from_span(message, span))
+            })
+            .collect();
+        Some(Diagnostic {
+            level: message["level"].as_str().unwrap_or_default().to_string(),
+            code: message["code"]["code"].as_str().map(str::to_string),
+            message: text.to_string(),
+            file: primary.and_then(|span| span["file_name"].as_str().map(str::to_string)),
+            line: number(primary, "line_start"),
+            column: number(primary, "column_start"),
+            rendered: message["rendered"].as_str().unwrap_or_default().to_string(),
+            spans: spans.iter().filter_map(Span::from_json).collect(),
+            help,
+            suggestions,
+            package: None,
+        })
+    }
+}
+
+impl Span {
+    fn from_json(span: &Value) -> Option<Self> {
+        let number = |key: &str| span[key].as_u64().map(|n| n as u32);
+        Some(Span {
+            file: span["file_name"].as_str()?.to_string(),
+            line_start: number("line_start")?,
+            column_start: number("column_start")?,
+            line_end: number("line_end")?,
+            column_end: number("column_end")?,
+            is_primary: span["is_primary"] == true,
+            label: span["label"].as_str().map(str::to_string),
+        })
+    }
+}
+
+impl Suggestion {
+    fn from_span(message: &str, span: &Value) -> Option<Self> {
+        if span["suggestion_applicability"] != "MachineApplicable" {
+            return None;
+        }
+        let number = |key: &str| span[key].as_u64().map(|n| n as usize);
+        Some(Suggestion {
+            message: message.to_string(),
+            file: span["file_name"].as_str()?.to_string(),
+            byte_start: number("byte_start")?,
+            byte_end: number("byte_end")?,
+            line_start: number("line_start")?,
+            column_start: number("column_start")?,
+            line_end: number("line_end")?,
+            column_end: number("column_end")?,
+            replacement: span["suggested_replacement"].as_str()?.to_string(),
+        })
+    }
+}
+
+/// A replacement submitted to `apply_suggestion`, located by its byte span or,
+/// without one, its line/column range; the shape of `Suggestion`
+#[derive(Debug, Clone, serde::Deserialize)]
+pub struct Edit {
+    pub file: Option<String>,
+    pub byte_start: Option<usize>,
+    pub byte_end: Option<usize>,
+    pub line_start: Option<usize>,
+    pub column_start: Option<usize>,
+    pub line_end: Option<usize>,
+    pub column_end: Option<usize>,
+    pub replacement: String,
+}
+
+impl Edit {
+    /// Byte range of `code` the edit replaces
+    fn span(&self, code: &str) -> Result<Range<usize>, String> {
+        if let Some(file) = &self.file
+            && file != "src/main.rs"
+            && file != "src/lib.rs"
+        {
+            return Err(format!("suggestion is for {}, not the snippet", file));
+        }
+        let range = match (self.byte_start, self.byte_end) {
+            (Some(start), Some(end)) => start..end,
+            (None, None) => {
+                let position = |line: Option<usize>, column: Option<usize>| {
+                    match (line, column) {
+                    (Some(line), Some(column)) => byte_offset(code, line, column),
+                    _ => Err("suggestion needs byte_start/byte_end or line_start, column_start, line_end and column_end".to_string()),
+                }
+                };
+                position(self.line_start, self.column_start)?
+                    ..position(self.line_end, self.column_end)?
+            }
+            _ => return Err("byte_start and byte_end must be given together".to_string()),
+        };
+        if range.start > range.end || range.end > code.len() {
+            return Err(format!(
+                "span {}..{} is out of bounds for {} bytes of code",
+                range.start,
+                range.end,
+                code.len()
+            ));
+        }
+        if !code.is_char_boundary(range.start) || !code.is_char_boundary(range.end) {
+            return Err(format!(
+                "span {}..{} does not fall on character boundaries",
+                range.start, range.end
+            ));
+        }
+        Ok(range)
+    }
+}
+
+/// Byte offset of a 1-based line and character column, as rustc reports
+/// them; the column may point one past the end of the line
+fn byte_offset(code: &str, line: usize, column: usize) -> Result<usize, String> {
+    let out_of_bounds = || format!("line {} column {} is out of bounds", line, column);
+    if line == 0 || column == 0 {
+        return Err(out_of_bounds());
+    }
+    let mut line_start = 0;
+    for _ in 1..line {
+        line_start += code[line_start..].find('\n').ok_or_else(out_of_bounds)? + 1;
+    }
+    let text = code[line_start..].split('\n').next().unwrap_or_default();
+    match text.char_indices().nth(column - 1) {
+        Some((offset, _)) => Ok(line_start + offset),
+        None if column - 1 == text.chars().count() => Ok(line_start + text.len()),
+        None => Err(out_of_bounds()),
+    }
+}
+
+/// Apply edits to `code` together; edits may not overlap
+pub fn apply_edits(code: &str, edits: &[Edit]) -> Result<String, String> {
+    let mut spans = edits
+        .iter()
+        .map(|edit| Ok((edit.span(code)?, edit.replacement.as_str())))
+        .collect::<Result<Vec<_>, String>>()?;
+    spans.sort_by_key(|(range, _)| (range.start, range.end));
+    for pair in spans.windows(2) {
+        let (first, second) = (&pair[0].0, &pair[1].0);
+        if second.start < first.end || second.start == first.start {
+            return Err(format!(
+                "spans {}..{} and {}..{} overlap",
+                first.start, first.end, second.start, second.end
+            ));
+        }
+    }
+    let mut patched = code.to_string();
+    for (range, replacement) in spans.into_iter().rev() {
+        patched.replace_range(range, replacement);
+    }
+    Ok(patched)
+}
+
+/// Diagnostics from the `compiler-message` lines of cargo's JSON output
+pub fn from_json(stdout: &str) -> Vec<Diagnostic> {
+    stdout.lines().filter_map(from_line).collect()
+}
+
+/// The diagnostic on one line of cargo's JSON output, if it is a
+/// `compiler-message` with one
+pub fn from_line(line: &str) -> Option<Diagnostic> {
+    if !line.starts_with('{') {
+        return None;
+    }
+    let msg = serde_json::from_str::<Value>(line).ok()?;
+    if msg["reason"] != "compiler-message" {
+        return None;
+    }
+    let mut diagnostic = Diagnostic::from_message(&msg["message"])?;
+    diagnostic.package = msg["package_id"].as_str().and_then(package_name);
+    Some(diagnostic)
+}
+
+/// The package name in a cargo package id: `path+file:///ws/app#0.1.0`,
+/// `path+file:///ws/util#util_crate@0.2.0`, or the older
+/// `app 0.1.0 (path+file:///ws/app)`
+fn package_name(id: &str) -> Option<String> {
+    let Some((source, fragment)) = id.split_once('#') else {
+        return id.split_whitespace().next().map(str::to_string);
+    };
+    match fragment.split_once('@') {
+        Some((name, _)) => Some(name.to_string()),
+        None => source
+            .trim_end_matches('/')
+            .rsplit('/')
+            .next()
+            .map(str::to_string),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const CLIPPY_JSON: &str = r#"{"reason":"compiler-artifact","package_id":"temp_project 0.1.0"}
+{"reason":"compiler-message","message":{"rendered":"warning: length comparison to zero\n --> src/main.rs:3:13\n","children":[{"children":[],"code":null,"level":"note","message":"`#[warn(clippy::len_zero)]` on by default","rendered":null,"spans":[]},{"children":[],"code":null,"level":"help","message":"using `is_empty` is clearer and more explicit","rendered":null,"spans":[{"byte_end":62,"byte_start":50,"column_end":25,"column_start":13,"file_name":"src/main.rs","is_primary":true,"label":null,"line_end":3,"line_start":3,"suggested_replacement":"v.is_empty()","suggestion_applicability":"MachineApplicable"}]}],"level":"warning","message":"length comparison to zero","spans":[{"byte_end":62,"byte_start":50,"column_end":25,"column_start":13,"file_name":"src/main.rs","is_primary":true,"label":null,"line_end":3,"line_start":3,"suggested_replacement":null,"suggestion_applicability":null}],"code":{"code":"clippy::len_zero","explanation":null}}}
+{"reason":"compiler-message","message":{"rendered":"warning: 1 warning emitted\n\n","children":[],"level":"warning","message":"1 warning emitted","spans":[],"code":null}}
+{"reason":"build-finished","success":true}"#;
+
+    #[test]
+    fn compiler_messages_become_diagnostics() {
+        let diagnostics = from_json(CLIPPY_JSON);
+        assert_eq!(diagnostics.len(), 1);
+        let lint = &diagnostics[0];
+        assert_eq!(lint.code.as_deref(), Some("clippy::len_zero"));
+        assert_eq!(lint.severity(), Some("warning"));
+        assert_eq!(
+            (lint.file.as_deref(), lint.line, lint.column),
+            (Some("src/main.rs"), Some(3), Some(13))
+        );
+        assert!(
+            lint.rendered
+                .starts_with("warning: length comparison to zero")
+        );
+        assert_eq!(
+            lint.spans,
+            [Span {
+                file: "src/main.rs".into(),
+                line_start: 3,
+                column_start: 13,
+                line_end: 3,
+                column_end: 25,
+                is_primary: true,
+                label: None,
+            }]
+        );
+        assert_eq!(lint.help, ["using `is_empty` is clearer and more explicit"]);
+        assert_eq!(
+            lint.suggestions,
+            [Suggestion {
+                message: "using `is_empty` is clearer and more explicit".into(),
+                file: "src/main.rs".into(),
+                byte_start: 50,
+                byte_end: 62,
+                line_start: 3,
+                column_start: 13,
+                line_end: 3,
+                column_end: 25,
+                replacement: "v.is_empty()".into(),
+            }]
+        );
+        assert!(from_json("error: could not compile `temp_project`").is_empty());
+        for (id, name) in [
+            ("path+file:///tmp/ws/app#0.1.0", "app"),
+            ("path+file:///tmp/ws/util#util_crate@0.2.0", "util_crate"),
+            (
+                "registry+https://github.com/rust-lang/crates.io-index#serde@1.0.200",
+                "serde",
+            ),
+            ("temp_project 0.1.0 (path+file:///tmp/x)", "temp_project"),
+        ] {
+            assert_eq!(package_name(id).as_deref(), Some(name));
+        }
+        assert!(!lint.is_compile_error());
+        let denied = Diagnostic {
+            level: "error".into(),
+            ..lint.clone()
+        };
+        assert!(!denied.is_compile_error());
+        let mismatch = Diagnostic {
+            code: Some("E0308".into()),
+            ..denied.clone()
+        };
+        let syntax = Diagnostic {
+            code: None,
+            ..denied
+        };
+        assert!(mismatch.is_compile_error() && syntax.is_compile_error());
+    }
+
+    fn edit(value: Value) -> Edit {
+        serde_json::from_value(value).unwrap()
+    }
+
+    #[test]
+    fn edits_apply_by_byte_span_or_line_and_column() {
+        let code = "fn main() {\n    let é = v.len() == 0;\n}\n";
+        let start = code.find("v.len").unwrap();
+        let by_bytes = edit(serde_json::json!({
+            "file": "src/main.rs",
+            "byte_start": start,
+            "byte_end": start + "v.len() == 0".len(),
+            "replacement": "v.is_empty()"
+        }));
+        let by_position = edit(serde_json::json!({
+            "line_start": 2, "column_start": 13, "line_end": 2, "column_end": 25,
+            "replacement": "v.is_empty()"
+        }));
+        let expected = "fn main() {\n    let é = v.is_empty();\n}\n";
+        assert_eq!(
+            apply_edits(code, std::slice::from_ref(&by_bytes)).unwrap(),
+            expected
+        );
+        assert_eq!(apply_edits(code, &[by_position]).unwrap(), expected);
+
+        let rename = edit(serde_json::json!({
+            "line_start": 2, "column_start": 9, "line_end": 2, "column_end": 10,
+            "replacement": "empty"
+        }));
+        assert_eq!(
+            apply_edits(code, &[by_bytes.clone(), rename]).unwrap(),
+            "fn main() {\n    let empty = v.is_empty();\n}\n"
+        );
+
+        let overlapping = edit(serde_json::json!({
+            "byte_start": start + 2, "byte_end": start + 5, "replacement": "x"
+        }));
+        assert!(apply_edits(code, &[by_bytes, overlapping]).is_err());
+        let past_end = edit(serde_json::json!({
+            "byte_start": 0, "byte_end": code.len() + 1, "replacement": ""
+        }));
+        assert!(apply_edits(code, &[past_end]).is_err());
+        let mid_char = edit(serde_json::json!({
+            "byte_start": code.find('é').unwrap() + 1, "byte_end": start, "replacement": ""
+        }));
+        assert!(apply_edits(code, &[mid_char]).is_err());
+        let missing_line = edit(serde_json::json!({
+            "line_start": 9, "column_start": 1, "line_end": 9, "column_end": 1,
+            "replacement": ""
+        }));
+        assert!(apply_edits(code, &[missing_line]).is_err());
+        let other_file = edit(serde_json::json!({
+            "file": "build.rs", "byte_start": 0, "byte_end": 0, "replacement": ""
+        }));
+        assert!(apply_edits(code, &[other_file]).is_err());
+    }
+}
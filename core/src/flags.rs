@@ -0,0 +1,261 @@
+//! Validation of caller-supplied rustc flags. Only flags that change how code
+//! is checked or generated are accepted; anything that can read or write
+//! arbitrary paths or pick a linker is rejected so the flags can't be used to
+//! escape the scratch project.
+
+use rmcp::ErrorData as McpError;
+
+/// `-C` options that only affect code generation
+const ALLOWED_CODEGEN: &[&str] = &[
+    "code-model",
+    "codegen-units",
+    "debug-assertions",
+    "debuginfo",
+    "embed-bitcode",
+    "force-frame-pointers",
+    "force-unwind-tables",
+    "instrument-coverage",
+    "lto",
+    "no-redzone",
+    "opt-level",
+    "overflow-checks",
+    "panic",
+    "relocation-model",
+    "soft-float",
+    "strip",
+    "symbol-mangling-version",
+    "target-cpu",
+    "target-feature",
+];
+
+/// `-Z` options useful for reproducing CI failures on nightly
+const ALLOWED_UNSTABLE: &[&str] = &[
+    "macro-backtrace",
+    "polonius",
+    "randomize-layout",
+    "sanitizer",
+    "sanitizer-memory-track-origins",
+    "threads",
+    "track-diagnostics",
+];
+
+/// Flags whose value is a lint name, level, cfg or edition
+const VALUE_FLAGS: &[&str] = &[
+    "--cfg",
+    "--check-cfg",
+    "-A",
+    "-W",
+    "-D",
+    "-F",
+    "--allow",
+    "--warn",
+    "--deny",
+    "--forbid",
+    "--force-warn",
+    "--cap-lints",
+    "--edition",
+];
+
+/// Flags that take no value
+const SWITCHES: &[&str] = &["-g", "-O"];
+
+/// Effective RUSTFLAGS for a call, built from `rustflags` and `cfgs` arguments
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct CompileFlags {
+    flags: Vec<String>,
+}
+
+impl CompileFlags {
+    /// Validate raw rustc flags and `--cfg` specs such as `feature="foo"`
+    pub fn new(rustflags: Vec<String>, cfgs: Vec<String>) -> Result<Self, McpError> {
+        let mut flags = Vec::with_capacity(rustflags.len() + cfgs.len() * 2);
+        let mut tokens = rustflags.into_iter();
+        while let Some(token) = tokens.next() {
+            // RUSTFLAGS is split on whitespace, so a token can't carry any
+            if token.is_empty() || token.chars().any(char::is_whitespace) {
+                return Err(rejected(&token, "flags must be single non-empty tokens"));
+            }
+            if SWITCHES.contains(&token.as_str()) {
+                flags.push(token);
+                continue;
+            }
+
+            let (flag, value) = split_flag(&token);
+            let value = match value {
+                Some(value) => value.to_string(),
+                None if takes_value(flag) => tokens
+                    .next()
+                    .ok_or_else(|| rejected(&token, "missing value"))?,
+                None => return Err(rejected(&token, "flag is not allowed")),
+            };
+            let flag = flag.to_string();
+            check_flag(&flag, &value)?;
+            flags.push(flag);
+            flags.push(value);
+        }
+
+        for cfg in cfgs {
+            check_cfg(&cfg)?;
+            flags.push("--cfg".to_string());
+            flags.push(cfg);
+        }
+        Ok(CompileFlags { flags })
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.flags.is_empty()
+    }
+
+    /// The flags in the order they are passed to rustc
+    pub fn effective(&self) -> &[String] {
+        &self.flags
+    }
+
+    /// Value for the RUSTFLAGS environment variable
+    pub fn rustflags_env(&self) -> String {
+        self.flags.join(" ")
+    }
+}
+
+fn rejected(token: &str, reason: &str) -> McpError {
+    McpError::invalid_params(format!("rustflag {:?} rejected: {}", token, reason), None)
+}
+
+fn takes_value(flag: &str) -> bool {
+    flag == "-C" || flag == "-Z" || VALUE_FLAGS.contains(&flag)
+}
+
+/// Split `-Copt-level=3`, `--cfg=test` or `-Dwarnings` into flag and value
+fn split_flag(token: &str) -> (&str, Option<&str>) {
+    if let Some(long) = token.strip_prefix("--") {
+        return match long.split_once('=') {
+            Some((name, value)) => (&token[..name.len() + 2], Some(value)),
+            None => (token, None),
+        };
+    }
+    if token.len() > 2 && token.starts_with('-') && token.is_char_boundary(2) {
+        return (&token[..2], Some(&token[2..]));
+    }
+    (token, None)
+}
+
+fn check_flag(flag: &str, value: &str) -> Result<(), McpError> {
+    let token = format!("{} {}", flag, value);
+    let allowed = |options: &[&str]| {
+        let name = value.split_once('=').map_or(value, |(name, _)| name);
+        options.contains(&name)
+    };
+    match flag {
+        "-C" if allowed(ALLOWED_CODEGEN) => Ok(()),
+        "-Z" if allowed(ALLOWED_UNSTABLE) => Ok(()),
+        "-C" | "-Z" => Err(rejected(&token, "option is not allowed")),
+        "--cfg" => check_cfg(value),
+        _ if VALUE_FLAGS.contains(&flag) => {
+            if value.starts_with('-') || value.contains(['/', '\\']) {
+                Err(rejected(&token, "invalid value"))
+            } else {
+                Ok(())
+            }
+        }
+        _ => Err(rejected(&token, "flag is not allowed")),
+    }
+}
+
+fn is_identifier(name: &str) -> bool {
+    let mut chars = name.chars();
+    chars
+        .next()
+        .is_some_and(|c| c.is_ascii_alphabetic() || c == '_')
+        && chars.all(|c| c.is_ascii_alphanumeric() || c == '_')
+}
+
+/// Accept `name` or `name="value"` with a plain quoted value
+fn check_cfg(cfg: &str) -> Result<(), McpError> {
+    let valid = match cfg.split_once('=') {
+        None => is_identifier(cfg),
+        Some((name, value)) => {
+            is_identifier(name)
+                && value.len() >= 2
+                && value.starts_with('"')
+                && value.ends_with('"')
+                && !value[1..value.len() - 1].contains(['"', '\\'])
+                && !value.chars().any(char::is_whitespace)
+        }
+    };
+    if valid {
+        Ok(())
+    } else {
+        Err(McpError::invalid_params(
+            format!(
+                "cfg {:?} rejected: expected `name` or `name=\"value\"`",
+                cfg
+            ),
+            None,
+        ))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn flags(rustflags: &[&str], cfgs: &[&str]) -> Result<CompileFlags, McpError> {
+        CompileFlags::new(
+            rustflags.iter().map(|s| s.to_string()).collect(),
+            cfgs.iter().map(|s| s.to_string()).collect(),
+        )
+    }
+
+    #[test]
+    fn cfgs_become_cfg_flags() {
+        let compiled = flags(
+            &["-Copt-level=3", "-D", "warnings"],
+            &["test", "feature=\"foo\""],
+        )
+        .unwrap();
+        assert_eq!(
+            compiled.effective(),
+            [
+                "-C",
+                "opt-level=3",
+                "-D",
+                "warnings",
+                "--cfg",
+                "test",
+                "--cfg",
+                "feature=\"foo\""
+            ]
+        );
+        assert_eq!(
+            compiled.rustflags_env(),
+            "-C opt-level=3 -D warnings --cfg test --cfg feature=\"foo\""
+        );
+        assert!(flags(&["-Zsanitizer=address"], &[]).is_ok());
+    }
+
+    #[test]
+    fn sandbox_escaping_flags_are_rejected() {
+        for bad in [
+            &["-L", "/usr/lib"][..],
+            &["-lfoo"],
+            &["--emit=link=/tmp/out"],
+            &["-o", "/tmp/out"],
+            &["-C", "linker=/bin/sh"],
+            &["-Clink-arg=-Wl,-rpath"],
+            &["-C", "incremental=/tmp"],
+            &["--extern", "foo=/tmp/libfoo.rlib"],
+            &["-Zself-profile=/tmp"],
+            &["-D"],
+            &["-C opt-level=3"],
+        ] {
+            assert!(flags(bad, &[]).is_err(), "{:?} should be rejected", bad);
+        }
+    }
+
+    #[test]
+    fn malformed_cfgs_are_rejected() {
+        for bad in ["", "1abc", "feature=foo", "feature=\"a\\\"b\"", "a b"] {
+            assert!(flags(&[], &[bad]).is_err(), "{:?} should be rejected", bad);
+        }
+    }
+}
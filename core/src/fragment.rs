@@ -0,0 +1,141 @@
+//! Formatting of code fragments that don't parse as a file, for
+//! `cargo_fmt`'s `mode`. The fragment is wrapped in a minimal item,
+//! formatted as usual, and unwrapped again at the fragment's own indentation.
+
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+
+/// What `cargo_fmt`'s code is
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "lowercase")]
+#[schemars(inline)]
+pub enum FragmentMode {
+    /// A whole source file
+    #[default]
+    File,
+    /// Items, e.g. an impl block or functions, wrapped in a module
+    Item,
+    /// An expression or statements, wrapped in a function body
+    Expression,
+}
+
+impl FragmentMode {
+    pub fn name(self) -> &'static str {
+        match self {
+            FragmentMode::File => "file",
+            FragmentMode::Item => "item",
+            FragmentMode::Expression => "expression",
+        }
+    }
+
+    /// First line of the wrapper as rustfmt prints it; None for files
+    fn opening(self) -> Option<&'static str> {
+        match self {
+            FragmentMode::File => None,
+            FragmentMode::Item => Some("mod __wrap {"),
+            FragmentMode::Expression => Some("fn __wrap() {"),
+        }
+    }
+
+    /// The fragment inside its wrapper, or the code itself for files
+    pub fn wrap(self, fragment: &str) -> String {
+        match self.opening() {
+            Some(opening) => format!("{}\n{}\n}}\n", opening, fragment),
+            None => fragment.to_string(),
+        }
+    }
+
+    /// The fragment back out of rustfmt's output for `wrap(fragment)`,
+    /// indented like the original. None when the output isn't the wrapper
+    /// with the fragment inside it, e.g. because the fragment closed the
+    /// wrapper early.
+    pub fn unwrap(self, fragment: &str, formatted: &str) -> Option<String> {
+        let Some(opening) = self.opening() else {
+            return Some(formatted.to_string());
+        };
+        let formatted = formatted.trim_end();
+        let body = if formatted == format!("{}}}", opening) {
+            // An empty fragment collapses the braces
+            ""
+        } else {
+            formatted
+                .strip_prefix(opening)?
+                .strip_prefix('\n')?
+                .strip_suffix("\n}")?
+        };
+        let indent: String = fragment
+            .lines()
+            .find(|line| !line.trim().is_empty())
+            .map(|line| line.chars().take_while(|c| c.is_whitespace()).collect())
+            .unwrap_or_default();
+        let mut lines = Vec::new();
+        for line in body.lines() {
+            if line == "}" {
+                // Only a brace from the fragment itself lands at column 0
+                return None;
+            }
+            lines.push(match line.strip_prefix("    ") {
+                Some(line) => format!("{}{}", indent, line),
+                // Blank lines, and continuation lines of multi-line strings
+                None => line.to_string(),
+            });
+        }
+        let mut unwrapped = lines.join("\n");
+        if fragment.ends_with('\n') && !unwrapped.is_empty() {
+            unwrapped.push('\n');
+        }
+        Some(unwrapped)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn fragments_round_trip_through_their_wrapper() {
+        let expression = FragmentMode::Expression;
+        assert_eq!(
+            expression.wrap("a+b"),
+            "fn __wrap() {\na+b\n}\n",
+            "the fragment goes inside a function body"
+        );
+        let formatted =
+            "fn __wrap() {\n    // leading\n    let x = 1;\n\n    x + 1 // trailing\n}\n";
+        assert_eq!(
+            expression
+                .unwrap(
+                    "        // leading\nlet x=1;\n\nx+1 // trailing\n",
+                    formatted
+                )
+                .unwrap(),
+            "        // leading\n        let x = 1;\n\n        x + 1 // trailing\n"
+        );
+        assert_eq!(expression.unwrap("", "fn __wrap() {}\n").unwrap(), "");
+
+        let item = FragmentMode::Item;
+        let formatted = "mod __wrap {\n    impl Foo {\n        fn a(&self) -> u8 {\n            1\n        }\n    }\n    // end\n}\n";
+        assert_eq!(
+            item.unwrap("impl Foo{fn a(&self)->u8{1}}\n// end", formatted)
+                .unwrap(),
+            "impl Foo {\n    fn a(&self) -> u8 {\n        1\n    }\n}\n// end"
+        );
+        assert_eq!(FragmentMode::File.wrap("fn main(){}"), "fn main(){}");
+    }
+
+    #[test]
+    fn fragments_that_escape_their_wrapper_are_refused() {
+        // "x\n}\nfn other() {\ny" closes the function early
+        let formatted = "fn __wrap() {\n    x\n}\nfn other() {\n    y\n}\n";
+        assert!(
+            FragmentMode::Expression
+                .unwrap("x\n}\nfn other() {\ny", formatted)
+                .is_none()
+        );
+        assert!(
+            FragmentMode::Item
+                .unwrap("}\nmod other {", "mod __wrap {}\nmod other {}\n")
+                .is_none()
+        );
+    }
+}
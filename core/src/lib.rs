@@ -1,24 +1,100 @@
 use anyhow::Result;
+use flate2::Compression;
+use flate2::read::GzDecoder;
+use flate2::write::GzEncoder;
 use rmcp::{
     ErrorData as McpError, ServerHandler,
     model::{
-        CallToolRequestParam, CallToolResult, InitializeRequestParam, InitializeResult,
-        ListResourcesResult, ListToolsResult, PaginatedRequestParam, Resource, ServerCapabilities,
-        ServerInfo, Tool,
+        AnnotateAble, CallToolRequestParam, CallToolResult, GetPromptRequestParam, GetPromptResult,
+        InitializeRequestParam, InitializeResult, ListPromptsResult, ListResourceTemplatesResult,
+        ListResourcesResult, ListToolsResult, PaginatedRequestParam, RawContent,
+        RawResourceTemplate, ReadResourceRequestParam, ReadResourceResult, Resource,
+        ResourceContents, ServerCapabilities, ServerInfo, Tool,
     },
     service::{RequestContext, RoleServer},
 };
 use rusqlite::Connection;
+use rusqlite::types::Value as SqlValue;
 use serde_json::{Value, json};
-use std::borrow::Cow;
 use std::future::Future;
-use std::path::PathBuf;
+use std::io::{Read, Write};
+use std::num::NonZeroU32;
+use std::path::{Path, PathBuf};
 use std::process::Command as StdCommand;
-use std::sync::{Arc, Mutex};
+use std::sync::Arc;
+use std::sync::atomic::{AtomicU64, Ordering};
 use std::time::{Duration, Instant};
 
-use tokio::io::AsyncReadExt;
 use tokio::process::Command;
+use tracing::{Instrument, debug, info, warn};
+
+pub mod args;
+mod audit;
+mod bloat;
+mod cache;
+mod cancellation;
+mod child_env;
+mod code_metrics;
+mod coverage;
+mod crate_source;
+mod data_dir;
+mod deny;
+mod deps;
+mod diagnostics;
+mod flags;
+mod fragment;
+mod limits;
+mod lsp;
+mod msrv;
+mod outline;
+mod output;
+mod paste;
+mod pool;
+mod process_tree;
+mod progress;
+mod prompts;
+mod provenance;
+mod requirements;
+mod semver;
+mod shared_db;
+mod syntax;
+mod tool_invocation;
+mod tools;
+mod type_of;
+mod unused;
+mod workspace;
+pub use audit::{AuditWarning, Vulnerability};
+pub use bloat::{BloatReport, CrateSize, SymbolSize};
+pub use cache::{CachedRun, MemoryCache};
+use cache::{Flight, InFlight};
+pub use child_env::ChildEnv;
+pub use code_metrics::{CodeMetrics, ItemMetrics, LineCounts, MetricTotals};
+pub use coverage::{CoverageCount, CoverageReport, FunctionCoverage};
+use crate_source::FetchedCrate;
+pub use crate_source::{CrateSource, ResolvedCrate};
+pub use data_dir::data_dir;
+pub use deny::{CategoryResult, DenyDiagnostic, DenyReport};
+pub use diagnostics::{Diagnostic, Span, Suggestion};
+pub use flags::CompileFlags;
+pub use limits::ResourceLimits;
+pub use lsp::LspSessions;
+pub use outline::{Field, Node, Outline};
+pub use output::Verbosity;
+pub use paste::Paste;
+pub use pool::{PoolStats, PooledProject, ProjectPool, ScratchProject};
+pub use progress::Progress;
+pub use provenance::{BuildCode, DepsReport, PackageSource, SourceTotals};
+pub use requirements::{Requirement, ToolRequirements, Toolchain};
+pub use semver::{CheckCounts, SemverChange, SemverReport};
+pub use shared_db::SharedDatabase;
+use shared_db::tolerate;
+pub use syntax::{OutlineItem, SyntaxError};
+pub use tool_invocation::{CrateType, ToolInvocation};
+pub use tools::{ToolFuture, ToolHandler, ToolOutput, ToolRegistry};
+pub use unused::Finding;
+pub use workspace::Workspace;
+
+use process_tree::ProcessTree;
 
 #[derive(Debug, Clone)]
 pub enum PersistenceMode {
@@ -29,1478 +105,8205 @@ pub enum PersistenceMode {
 #[derive(Debug, Clone)]
 pub struct ErrorInfo {
     code: Option<String>,
+    /// "error", "warning", "note" or "help"; unknown for location-only lines
+    severity: Option<&'static str>,
     message: String,
     file: Option<String>,
     line: Option<i32>,
     suggestion: Option<String>,
 }
 
+impl From<&Diagnostic> for ErrorInfo {
+    fn from(diagnostic: &Diagnostic) -> Self {
+        let suggestion = match diagnostic.suggestions.first() {
+            Some(fix) => Some(format!("{}: `{}`", fix.message, fix.replacement)),
+            None => diagnostic.help.first().cloned(),
+        };
+        ErrorInfo {
+            code: diagnostic.code.clone(),
+            severity: diagnostic.severity(),
+            message: diagnostic.message.clone(),
+            file: diagnostic.file.clone(),
+            line: diagnostic.line.map(|line| line as i32),
+            suggestion,
+        }
+    }
+}
+
+/// Server settings beyond the persistence mode
+#[derive(Debug, Clone)]
+pub struct ServerConfig {
+    /// Number of pre-initialized scratch projects kept ready (0 disables pooling)
+    pub pool_size: usize,
+    /// Largest binary `cargo_build` returns inline with `return_artifact`
+    pub artifact_max_bytes: u64,
+    /// Where `cargo_doc` copies generated HTML with `save_docs`
+    pub doc_dir: PathBuf,
+    /// How long a cached tool result is reused (zero disables the cache)
+    pub cache_ttl: Duration,
+    /// `CARGO_HOME` for spawned cargo commands; `None` shares the user's
+    pub cargo_home: Option<PathBuf>,
+    /// Run cargo with `--offline` semantics: nothing is downloaded
+    pub offline: bool,
+    /// Largest `.crate` archive `crate_source` will analyze
+    pub crate_max_bytes: u64,
+    /// Tool calls that may run cargo at once; further calls wait in order
+    pub max_concurrency: usize,
+    /// Keep submitted code with persisted analyses
+    pub store_code: bool,
+    /// Largest snippet stored with an analysis; bigger ones keep only the result
+    pub code_max_bytes: usize,
+    /// How long an unused rust-analyzer session keeps running
+    pub lsp_idle: Duration,
+    /// Memory and CPU time each process cargo starts may use
+    pub limits: ResourceLimits,
+    /// Newest analyses kept when the server starts; older ones are pruned
+    /// (`None` keeps everything)
+    pub max_analyses: Option<usize>,
+    /// Indent JSON tool results for reading rather than sending them compact
+    pub pretty_json: bool,
+    /// Largest `code`, `baseline_code` or single `files` entry a call may send
+    pub input_max_bytes: usize,
+    /// Largest total size of a `files` map
+    pub files_max_bytes: usize,
+}
+
+impl Default for ServerConfig {
+    fn default() -> Self {
+        ServerConfig {
+            pool_size: 2,
+            artifact_max_bytes: 8 * 1024 * 1024,
+            doc_dir: data_dir()
+                .map(|dir| dir.join("docs"))
+                .unwrap_or_else(|| std::env::temp_dir().join("rusty-tools-docs")),
+            cache_ttl: Duration::from_secs(60 * 60),
+            cargo_home: Some(child_env::default_cargo_home()),
+            offline: false,
+            crate_max_bytes: 10 * 1024 * 1024,
+            max_concurrency: std::thread::available_parallelism().map_or(1, |n| n.get()),
+            store_code: true,
+            code_max_bytes: 256 * 1024,
+            lsp_idle: Duration::from_secs(5 * 60),
+            limits: ResourceLimits {
+                memory_bytes: Some(4 * 1024 * 1024 * 1024),
+                cpu_secs: Some(10 * 60),
+            },
+            max_analyses: None,
+            pretty_json: false,
+            input_max_bytes: 512 * 1024,
+            files_max_bytes: 2 * 1024 * 1024,
+        }
+    }
+}
+
+impl ServerConfig {
+    /// Read overrides from `RUSTY_TOOLS_*` environment variables, keeping the
+    /// default for anything unset or unparseable
+    pub fn from_env() -> Self {
+        let mut config = ServerConfig::default();
+        if let Some(size) = std::env::var("RUSTY_TOOLS_POOL_SIZE")
+            .ok()
+            .and_then(|v| v.parse().ok())
+        {
+            config.pool_size = size;
+        }
+        if let Some(bytes) = std::env::var("RUSTY_TOOLS_ARTIFACT_MAX_BYTES")
+            .ok()
+            .and_then(|v| v.parse().ok())
+        {
+            config.artifact_max_bytes = bytes;
+        }
+        if let Ok(dir) = std::env::var("RUSTY_TOOLS_DOC_DIR") {
+            config.doc_dir = PathBuf::from(dir);
+        }
+        if let Some(secs) = std::env::var("RUSTY_TOOLS_CACHE_TTL")
+            .ok()
+            .and_then(|v| v.parse().ok())
+        {
+            config.cache_ttl = Duration::from_secs(secs);
+        }
+        if let Ok(dir) = std::env::var("RUSTY_TOOLS_CARGO_HOME") {
+            config.cargo_home = Some(PathBuf::from(dir));
+        }
+        if std::env::var("RUSTY_TOOLS_SHARE_CARGO_HOME").is_ok_and(|v| v == "1" || v == "true") {
+            config.cargo_home = None;
+        }
+        if std::env::var("RUSTY_TOOLS_OFFLINE").is_ok_and(|v| v == "1" || v == "true") {
+            config.offline = true;
+        }
+        if let Some(bytes) = std::env::var("RUSTY_TOOLS_CRATE_MAX_BYTES")
+            .ok()
+            .and_then(|v| v.parse().ok())
+        {
+            config.crate_max_bytes = bytes;
+        }
+        if let Some(max) = std::env::var("RUSTY_TOOLS_MAX_CONCURRENCY")
+            .ok()
+            .and_then(|v| v.parse().ok())
+            .filter(|max| *max > 0)
+        {
+            config.max_concurrency = max;
+        }
+        if std::env::var("RUSTY_TOOLS_STORE_CODE").is_ok_and(|v| v == "0" || v == "false") {
+            config.store_code = false;
+        }
+        if let Some(bytes) = std::env::var("RUSTY_TOOLS_CODE_MAX_BYTES")
+            .ok()
+            .and_then(|v| v.parse().ok())
+        {
+            config.code_max_bytes = bytes;
+        }
+        if let Some(secs) = std::env::var("RUSTY_TOOLS_LSP_IDLE_SECS")
+            .ok()
+            .and_then(|v| v.parse().ok())
+        {
+            config.lsp_idle = Duration::from_secs(secs);
+        }
+        // Zero lifts a limit
+        if let Some(mb) = std::env::var("RUSTY_TOOLS_MAX_MEMORY_MB")
+            .ok()
+            .and_then(|v| v.parse::<u64>().ok())
+        {
+            config.limits.memory_bytes = (mb > 0).then(|| mb * 1024 * 1024);
+        }
+        if let Some(secs) = std::env::var("RUSTY_TOOLS_MAX_CPU_SECS")
+            .ok()
+            .and_then(|v| v.parse::<u64>().ok())
+        {
+            config.limits.cpu_secs = (secs > 0).then_some(secs);
+        }
+        if let Some(max) = std::env::var("RUSTY_TOOLS_MAX_ANALYSES")
+            .ok()
+            .and_then(|v| v.parse::<usize>().ok())
+        {
+            config.max_analyses = (max > 0).then_some(max);
+        }
+        if let Some(bytes) = std::env::var("RUSTY_TOOLS_INPUT_MAX_BYTES")
+            .ok()
+            .and_then(|v| v.parse().ok())
+            .filter(|bytes| *bytes > 0)
+        {
+            config.input_max_bytes = bytes;
+        }
+        if let Some(bytes) = std::env::var("RUSTY_TOOLS_FILES_MAX_BYTES")
+            .ok()
+            .and_then(|v| v.parse().ok())
+            .filter(|bytes| *bytes > 0)
+        {
+            config.files_max_bytes = bytes;
+        }
+        if std::env::var("RUSTY_TOOLS_PRETTY_JSON").is_ok_and(|v| v == "1" || v == "true") {
+            config.pretty_json = true;
+        }
+        config
+    }
+}
+
+/// Tool call counters reported by `server_status`
+#[derive(Debug, Default)]
+struct ToolActivity {
+    in_flight: AtomicU64,
+    completed: AtomicU64,
+}
+
+impl ToolActivity {
+    /// Count a call as in flight until the returned guard is dropped
+    fn start(self: &Arc<Self>) -> ActivityGuard {
+        self.in_flight.fetch_add(1, Ordering::Relaxed);
+        ActivityGuard(Arc::clone(self))
+    }
+}
+
+struct ActivityGuard(Arc<ToolActivity>);
+
+impl Drop for ActivityGuard {
+    fn drop(&mut self) {
+        self.0.in_flight.fetch_sub(1, Ordering::Relaxed);
+        self.0.completed.fetch_add(1, Ordering::Relaxed);
+    }
+}
+
+/// Caps how many tool calls run cargo at once. Tokio's semaphore is fair, so
+/// waiting calls start in arrival order.
+#[derive(Debug)]
+struct RunLimiter {
+    slots: tokio::sync::Semaphore,
+    max: usize,
+    queued: AtomicU64,
+}
+
+impl RunLimiter {
+    fn new(max: usize) -> Self {
+        RunLimiter {
+            slots: tokio::sync::Semaphore::new(max),
+            max,
+            queued: AtomicU64::new(0),
+        }
+    }
+
+    /// Wait for a slot, telling the client its place in the queue. Gives up
+    /// if the call is cancelled while waiting.
+    async fn acquire(&self) -> Result<tokio::sync::SemaphorePermit<'_>, McpError> {
+        if let Ok(permit) = self.slots.try_acquire() {
+            return Ok(permit);
+        }
+        let position = self.queued.fetch_add(1, Ordering::Relaxed) + 1;
+        let message = format!(
+            "queued: {} cargo runs in progress, position {}",
+            self.max, position
+        );
+        info!("{}", message);
+        if let Some(progress) = progress::current() {
+            progress.report(&message).await;
+        }
+        let permit = tokio::select! {
+            permit = self.slots.acquire() => Some(permit),
+            _ = cancellation::cancelled() => None,
+        };
+        self.queued.fetch_sub(1, Ordering::Relaxed);
+        match permit {
+            Some(permit) => permit
+                .map_err(|e| McpError::internal_error(format!("Run queue closed: {}", e), None)),
+            None => Err(McpError::internal_error("Tool call cancelled", None)),
+        }
+    }
+
+    fn stats(&self) -> Value {
+        json!({
+            "max": self.max,
+            "running": self.max - self.slots.available_permits(),
+            "queued": self.queued.load(Ordering::Relaxed)
+        })
+    }
+}
+
 #[derive(Clone)]
 pub struct RustyToolsServer {
-    db: Option<Arc<Mutex<Database>>>,
+    db: Option<Arc<SharedDatabase>>,
+    mode: PersistenceMode,
+    pool: ProjectPool,
+    requirements: Arc<ToolRequirements>,
+    config: ServerConfig,
+    started: Instant,
+    activity: Arc<ToolActivity>,
+    runs: Arc<RunLimiter>,
+    /// Result cache used when there is no database to hold it
+    cache: Arc<MemoryCache>,
+    /// Snippet tool calls in progress, which identical calls wait for
+    in_flight: Arc<InFlight<CallToolResult>>,
+    child_env: ChildEnv,
+    /// rust-analyzer servers kept running per session
+    lsp: LspSessions,
+    /// What `list_tools` offers and `call_tool` runs
+    tools: Arc<ToolRegistry>,
 }
 
 impl RustyToolsServer {
     pub fn new(mode: PersistenceMode) -> Self {
+        Self::with_config(mode, ServerConfig::default())
+    }
+
+    pub fn with_config(mode: PersistenceMode, config: ServerConfig) -> Self {
         let db = match Database::new(mode.clone()) {
             Ok(Some(db)) => {
-                match mode {
+                match &mode {
                     PersistenceMode::Path(path) => {
-                        eprintln!("✅ Database initialized at: {}", path.display());
+                        info!("Database initialized at: {}", path.display());
                     }
                     PersistenceMode::Disabled => {}
                 }
-                Some(Arc::new(Mutex::new(db)))
+                Some(Arc::new(SharedDatabase::new(db, mode.clone())))
             }
-            _ => {
-                eprintln!("⚠️  Warning: Could not initialize database: Persistence disabled.");
+            Ok(None) => {
+                info!("Persistence turned off: nothing is written to a database");
+                None
+            }
+            Err(_) => {
+                warn!("Could not initialize database: Persistence disabled.");
                 None
             }
         };
+        if let (Some(db), Some(max)) = (&db, config.max_analyses) {
+            match db.run(|db| db.cleanup_old_data(max)) {
+                Ok(pruned) if pruned.analyses > 0 => info!(
+                    "Pruned {} analyses ({} errors, {} snippets) beyond the newest {}",
+                    pruned.analyses, pruned.errors, pruned.snippets, max
+                ),
+                Ok(_) => debug!("No analyses beyond the newest {} to prune", max),
+                Err(e) => warn!("Failed to prune old analyses: {}", e),
+            }
+        }
+
+        let requirements = Arc::new(ToolRequirements::default());
+        // Probe the toolchain in the background so startup isn't delayed and
+        // later calls and get_info see cached results
+        if let Ok(handle) = tokio::runtime::Handle::try_current() {
+            let requirements = Arc::clone(&requirements);
+            handle.spawn_blocking(move || {
+                let mut unavailable = Vec::new();
+                for requirement in requirements::ALL {
+                    if requirements.version(requirement).is_some() {
+                        continue;
+                    }
+                    if requirement.optional {
+                        unavailable.push(requirement.name);
+                    } else {
+                        warn!(
+                            "{} not found ({})",
+                            requirement.name, requirement.install_hint
+                        );
+                    }
+                }
+                if !unavailable.is_empty() {
+                    info!("Optional tools unavailable: {}", unavailable.join(", "));
+                }
+            });
+        }
 
-        RustyToolsServer { db }
+        let child_env =
+            ChildEnv::new(config.cargo_home.clone(), config.offline).with_limits(config.limits);
+        let runs = Arc::new(RunLimiter::new(config.max_concurrency));
+        let lsp = LspSessions::new(child_env.clone(), config.lsp_idle);
+        RustyToolsServer {
+            db,
+            mode,
+            pool: ProjectPool::new(config.pool_size, child_env.clone()),
+            requirements,
+            config,
+            started: Instant::now(),
+            activity: Arc::default(),
+            runs,
+            cache: Arc::default(),
+            in_flight: Arc::default(),
+            lsp,
+            child_env,
+            tools: Arc::new(tools::builtin()),
+        }
     }
 
-    fn get_persist_flag(request: &CallToolRequestParam) -> bool {
-        request
-            .arguments
-            .as_ref()
-            .and_then(|args| args.get("persist"))
-            .and_then(|v| v.as_bool())
-            .unwrap_or(false)
+    /// Offer another tool, or replace a built-in one of the same name. Its
+    /// calls run as `execute` runs them: input size limits, run slots and
+    /// shared runs of identical calls come with the built-in tools' arguments.
+    pub fn register_tool(&mut self, handler: impl ToolHandler + 'static) {
+        Arc::make_mut(&mut self.tools).register(handler);
     }
 
-    /// Parse and store errors from stderr output
-    fn parse_and_store_errors(db: &Database, analysis_id: i64, stderr: &str) {
-        let mut error_count = 0;
+    /// Release server resources such as pooled scratch projects
+    pub fn shutdown(&self) {
+        self.pool.shutdown();
+        self.lsp.shutdown();
+    }
 
-        // Parse Rust compiler errors and warnings
-        for line in stderr.lines() {
-            if let Some(error_info) = Self::parse_error_line(line) {
-                if let Err(e) = db.store_error(
-                    analysis_id,
-                    error_info.code.as_deref(),
-                    &error_info.message,
-                    error_info.file.as_deref(),
-                    error_info.line,
-                    error_info.suggestion.as_deref(),
-                ) {
-                    eprintln!("Failed to store error: {}", e);
-                } else {
-                    error_count += 1;
-                }
-            }
+    /// Run a snippet tool by name in a pooled project using its entry in
+    /// `cargo_tool_command`
+    async fn run_cargo_tool(
+        &self,
+        code: &str,
+        tool: &str,
+        invocation: &Invocation,
+    ) -> Result<ExecResult, McpError> {
+        let (args, timeout) = cargo_tool_command(tool).ok_or_else(|| {
+            McpError::internal_error(format!("{} is not a cargo snippet tool", tool), None)
+        })?;
+        self.requirements.check(tool)?;
+        if let Some(source) = &invocation.crate_source {
+            return self
+                .run_crate_source(source, args, timeout, invocation)
+                .await;
         }
-
-        if error_count > 0 {
-            eprintln!(
-                "Stored {} errors from analysis {}",
-                error_count, analysis_id
-            );
+        if !invocation.files.is_empty() {
+            return self
+                .run_project_files(code, args, timeout, invocation)
+                .await;
+        }
+        let project = self.pool.checkout()?;
+        let mut result = self
+            .run_cargo_tool_in(project.path(), code, args, timeout, invocation)
+            .await?;
+        if invocation.keep_project {
+            result.project_path = Some(project.keep());
         }
+        Ok(result)
     }
 
-    /// Enhanced error parsing that handles multiple error patterns
-    fn parse_error_line(line: &str) -> Option<ErrorInfo> {
-        let line = line.trim();
+    /// Run a snippet tool's cargo command against a published crate's source
+    /// instead of a snippet. Results aren't cached: the crate's dependencies
+    /// resolve afresh on every run.
+    async fn run_crate_source(
+        &self,
+        source: &CrateSource,
+        args: &[&str],
+        timeout: Option<Duration>,
+        invocation: &Invocation,
+    ) -> Result<ExecResult, McpError> {
+        let fetched = source
+            .fetch(&self.child_env, self.config.crate_max_bytes)
+            .await?;
+        self.run_fetched_crate(fetched, args, timeout, invocation)
+            .await
+    }
 
-        // Pattern 1: error[E0308]: message
-        if let Some(captures) = Self::extract_error_pattern(line, r"error\[([^\]]+)\]:\s*(.+)") {
-            return Some(ErrorInfo {
-                code: Some(captures.0),
-                message: captures.1,
-                file: None,
-                line: None,
-                suggestion: None,
-            });
+    /// Run a snippet tool's cargo command in a fetched crate's source, after
+    /// checking its build code like a `files` manifest's
+    async fn run_fetched_crate(
+        &self,
+        fetched: FetchedCrate,
+        args: &[&str],
+        timeout: Option<Duration>,
+        invocation: &Invocation,
+    ) -> Result<ExecResult, McpError> {
+        let args = invocation.cargo_args(invocation.command.unwrap_or(args));
+        let args: Vec<&str> = args.iter().map(String::as_str).collect();
+        // Real crates build their dependency graph, which snippet timeouts
+        // aren't sized for
+        let timeout = timeout.map(|t| t.max(CRATE_SOURCE_MIN_TIMEOUT));
+        let mut metadata_run = ToolInvocation::from_files()
+            .working_dir(fetched.dir.path())
+            .child_env(self.child_env.clone());
+        for (key, value) in &invocation.env {
+            metadata_run = metadata_run.env(key, value);
         }
-
-        // Pattern 2: warning: message
-        if let Some(captures) = Self::extract_error_pattern(line, r"warning:\s*(.+)") {
-            return Some(ErrorInfo {
-                code: Some("WARNING".to_string()),
-                message: captures.1,
-                file: None,
-                line: None,
-                suggestion: None,
-            });
+        let build_code = match self
+            .check_build_code(&metadata_run, true, invocation)
+            .await?
+        {
+            Ok(build_code) => build_code,
+            Err(mut failed) => {
+                failed.crate_source = Some(fetched.resolved);
+                return Ok(failed);
+            }
+        };
+        let mut result = run_cargo(
+            fetched.dir.path(),
+            &args,
+            timeout,
+            &invocation.env,
+            &self.child_env,
+        )
+        .await?;
+        result.build_code = build_code;
+        let resolved = fetched.resolved;
+        if invocation.save_docs && result.status == 0 {
+            let destination = self
+                .config
+                .doc_dir
+                .join(format!("{}-{}", resolved.name, resolved.version));
+            save_docs(&fetched.dir.path().join("target").join("doc"), &destination)?;
+            result.docs_path = Some(destination);
+        }
+        if invocation.keep_project {
+            result.project_path = Some(fetched.dir.keep());
         }
+        result.crate_source = Some(resolved);
+        Ok(result)
+    }
 
-        // Pattern 3: --> file:line:col (file path indicators)
-        if line.contains(" --> ") && line.contains(':') {
-            let parts: Vec<&str> = line.split(" --> ").collect();
-            if parts.len() == 2 {
-                let location = parts[1];
-                if let Some(file_info) = Self::parse_file_location(location) {
-                    return Some(ErrorInfo {
-                        code: None,
-                        message: format!("Error at {}", location),
-                        file: Some(file_info.0),
-                        line: file_info.1,
-                        suggestion: None,
-                    });
-                }
+    /// Run a snippet tool's cargo command in a project made from `files`:
+    /// laid over a fresh package, or on their own when they bring a root
+    /// `Cargo.toml`, e.g. a workspace's. Like crate sources, these runs
+    /// aren't cached.
+    async fn run_project_files(
+        &self,
+        code: &str,
+        args: &[&str],
+        timeout: Option<Duration>,
+        invocation: &Invocation,
+    ) -> Result<ExecResult, McpError> {
+        let has_manifest = invocation
+            .files
+            .iter()
+            .any(|(path, _)| path == Path::new("Cargo.toml"));
+        let project = if has_manifest {
+            ScratchProject::empty()?
+        } else {
+            ScratchProject::create(&self.child_env)?
+        };
+        if !invocation.dependencies.is_empty() {
+            write_dependencies(project.path(), &invocation.dependencies)?;
+        }
+        let mut run = match code {
+            "" => ToolInvocation::from_files(),
+            code if invocation.library => {
+                write_library_scaffold(project.path(), code, false)?;
+                ToolInvocation::from_files()
             }
+            code => ToolInvocation::new(code),
+        };
+        for (path, contents) in &invocation.files {
+            run = run.file(path, contents);
+        }
+        for (key, value) in &invocation.env {
+            run = run.env(key, value);
         }
+        if let Some(timeout) = timeout {
+            run = run.timeout(timeout);
+        }
+        let run = run
+            .working_dir(project.path())
+            .child_env(self.child_env.clone());
+        let build_code = match self.check_build_code(&run, false, invocation).await? {
+            Ok(build_code) => build_code,
+            // Cargo's error says why the manifest doesn't resolve
+            Err(failed) => return Ok(failed),
+        };
+        let mut result = run
+            .args(invocation.cargo_args(invocation.command.unwrap_or(args)))
+            .run()
+            .await?;
+        result.build_code = build_code;
+        self.attach_artifact(&mut result, invocation)?;
+        result.project_path = invocation.keep_project.then(|| project.keep());
+        Ok(result)
+    }
 
-        // Pattern 4: help: suggestion
-        if line.starts_with("help:") {
-            return Some(ErrorInfo {
-                code: None,
-                message: "Help".to_string(),
-                file: None,
-                line: None,
-                suggestion: Some(
-                    line.strip_prefix("help:")
-                        .unwrap_or(line)
-                        .trim()
-                        .to_string(),
+    /// With `inspect_build_code`, the packages of the project `run` works on
+    /// that run code at build time, refusing to build them without
+    /// `allow_build_scripts`. A manifest that doesn't resolve gives back the
+    /// failed run.
+    async fn check_build_code(
+        &self,
+        run: &ToolInvocation,
+        members: bool,
+        invocation: &Invocation,
+    ) -> Result<Result<Vec<BuildCode>, ExecResult>, McpError> {
+        if !invocation.inspect_build_code {
+            return Ok(Ok(Vec::new()));
+        }
+        let build_code = match self.resolve_build_code(run, members).await? {
+            Ok(build_code) => build_code,
+            Err(failed) => return Ok(Err(failed)),
+        };
+        if !build_code.is_empty() && !invocation.allow_build_scripts {
+            let names: Vec<String> = build_code
+                .iter()
+                .map(|krate| format!("{} {}", krate.name, krate.version))
+                .collect();
+            return Err(McpError::invalid_params(
+                format!(
+                    "Refusing to build: {} {} run code at build time ({}); pass allow_build_scripts: true to build them anyway",
+                    build_code.len(),
+                    if members { "packages" } else { "dependencies" },
+                    names.join(", ")
                 ),
-            });
+                Some(json!({
+                    "build_code": build_code,
+                    "allow_with": "allow_build_scripts"
+                })),
+            ));
         }
-
-        None
+        Ok(Ok(build_code))
     }
 
-    /// Extract error code and message using regex-like pattern matching
-    fn extract_error_pattern(line: &str, pattern: &str) -> Option<(String, String)> {
-        // Simple pattern matching for error[CODE]: message
-        if pattern.contains(r"error\[([^\]]+)\]")
-            && let Some(error_start) = line.find("error[")
-        {
-            let after_error = &line[error_start..];
-            if let Some(bracket_end) = after_error.find(']') {
-                let code = after_error[6..bracket_end].to_string(); // Skip "error["
-                if let Some(colon_pos) = after_error.find(": ") {
-                    let message = after_error[colon_pos + 2..].trim().to_string();
-                    return Some((code, message));
+    /// Dependencies of the project `run` writes that have build scripts or
+    /// are proc-macros, from `cargo metadata`, and with `members` the
+    /// workspace's own packages too. Resolves offline when the cargo home
+    /// already has what the manifest needs. A manifest that doesn't resolve
+    /// gives back the failed run.
+    async fn resolve_build_code(
+        &self,
+        run: &ToolInvocation,
+        members: bool,
+    ) -> Result<Result<Vec<BuildCode>, ExecResult>, McpError> {
+        let metadata = ["metadata", "--format-version", "1"];
+        let mut result = run.clone().args(metadata).arg("--offline").run().await?;
+        if result.status != 0 && !self.config.offline {
+            result = run.clone().args(metadata).run().await?;
+        }
+        match DepsReport::from_metadata(&result.stdout) {
+            Some(report) if result.status == 0 => {
+                let mut build_code = Vec::new();
+                if members {
+                    build_code = provenance::member_build_code(&result.stdout);
                 }
+                build_code.extend(report.build_code());
+                Ok(Ok(build_code))
             }
+            _ => Ok(Err(result)),
         }
+    }
 
-        // Simple pattern matching for warning: message
-        if pattern.contains(r"warning:\s*")
-            && let Some(warning_pos) = line.find("warning:")
+    /// Run a snippet tool's cargo command in an already checked-out project
+    async fn run_cargo_tool_in(
+        &self,
+        project_path: &Path,
+        code: &str,
+        args: &[&str],
+        timeout: Option<Duration>,
+        invocation: &Invocation,
+    ) -> Result<ExecResult, McpError> {
+        let args = invocation.command.unwrap_or(args);
+        if invocation.library {
+            write_library_scaffold(project_path, code, invocation.no_std)?;
+        }
+        if !invocation.dependencies.is_empty() {
+            write_dependencies(project_path, &invocation.dependencies)?;
+        }
+        if let Some(edition) = invocation.edition {
+            write_edition(project_path, edition)?;
+        }
+        let args = invocation.cargo_args(args);
+        let args: Vec<&str> = args.iter().map(String::as_str).collect();
+        let cache_key = self.result_cache_key(project_path, code, &args, invocation);
+        if let Some(run) = cache_key.as_deref().and_then(|key| self.cached_run(key)) {
+            return Ok(ExecResult {
+                stdout: run.stdout,
+                stderr: run.stderr,
+                status: run.status,
+                duration_ms: run.duration_ms,
+                artifact: None,
+                docs_path: None,
+                project_path: None,
+                crate_source: None,
+                cached: true,
+                timed_out: false,
+                resource_exceeded: None,
+                build_code: Vec::new(),
+            });
+        }
+        let mut result = run_in_project(
+            project_path,
+            code,
+            &args,
+            timeout,
+            &invocation.env,
+            &self.child_env,
+        )
+        .await?;
+        if let Some(key) = cache_key
+            && !result.timed_out
+            && result.resource_exceeded.is_none()
         {
-            let message = line[warning_pos + 8..].trim().to_string();
-            if !message.is_empty() {
-                return Some(("WARNING".to_string(), message));
-            }
+            self.store_cached_run(key, &result);
         }
-
-        None
+        // Read the binary before the project goes back to the pool and is reset
+        self.attach_artifact(&mut result, invocation)?;
+        if invocation.save_docs && result.status == 0 {
+            let destination = self.config.doc_dir.join(&code_hash(code)[..16]);
+            save_docs(&project_path.join("target").join("doc"), &destination)?;
+            result.docs_path = Some(destination);
+        }
+        Ok(result)
     }
 
-    /// Parse file location like "src/main.rs:10:5"
-    fn parse_file_location(location: &str) -> Option<(String, Option<i32>)> {
-        let parts: Vec<&str> = location.split(':').collect();
-        if parts.len() >= 2 {
-            let file = parts[0].to_string();
-            let line = parts[1].parse::<i32>().ok();
-            return Some((file, line));
+    /// With `collect_artifact`, read the built executable cargo reported,
+    /// while the project is still on disk
+    fn attach_artifact(
+        &self,
+        result: &mut ExecResult,
+        invocation: &Invocation,
+    ) -> Result<(), McpError> {
+        if invocation.collect_artifact {
+            let target = match invocation.details.get("target").and_then(|v| v.as_str()) {
+                Some(target) => target.to_string(),
+                None => self
+                    .requirements
+                    .host_target()
+                    .unwrap_or_else(|| "unknown".to_string()),
+            };
+            let profile = invocation
+                .details
+                .get("profile")
+                .and_then(|v| v.as_str())
+                .unwrap_or("dev");
+            result.artifact = collect_artifact(
+                &mut result.stdout,
+                target,
+                profile,
+                self.config.artifact_max_bytes,
+            )?;
         }
-        None
+        Ok(())
     }
 
-    /// Parse clippy warnings and store as todos
-    fn parse_and_store_clippy_todos(db: &Database, stderr: &str) {
-        let mut todo_count = 0;
-
-        for line in stderr.lines() {
-            let line = line.trim();
+    /// Result cache key for a run, or `None` when it must run fresh: the
+    /// caller passed `no_cache`, the run has effects beyond its output, the
+    /// cache is disabled, or the toolchain version is unknown
+    fn result_cache_key(
+        &self,
+        project_path: &Path,
+        code: &str,
+        args: &[&str],
+        invocation: &Invocation,
+    ) -> Option<String> {
+        if invocation.no_cache
+            || invocation.keep_project
+            || invocation.crate_source.is_some()
+            || invocation.collect_artifact
+            || invocation.save_docs
+            || self.config.cache_ttl.is_zero()
+        {
+            return None;
+        }
+        let toolchain = self.rustc_version()?;
+        let files: Vec<(&str, String)> = CACHE_KEY_FILES
+            .iter()
+            .filter_map(|name| {
+                Some((
+                    *name,
+                    std::fs::read_to_string(project_path.join(name)).ok()?,
+                ))
+            })
+            .collect();
+        Some(cache::cache_key(
+            args,
+            &invocation.env,
+            code,
+            &files,
+            &toolchain,
+        ))
+    }
 
-            // Clippy warnings often contain "warning:" and helpful suggestions
-            if line.contains("warning:") && (line.contains("clippy::") || line.contains("#[warn("))
-            {
-                let warning_msg = if let Some(pos) = line.find("warning:") {
-                    line[pos + 8..].trim()
-                } else {
-                    line
-                };
+    /// Run snippet tools one after another in a single pooled project so they
+    /// share target/. Steps after a failure are skipped (`None`) when
+    /// `stop_on_failure` is set; a missing binary fails its step only. With
+    /// `persist`, each step is stored as its own analysis under one batch id.
+    async fn run_batch(
+        &self,
+        options: &args::Options,
+        code: &str,
+        tools: &[&'static str],
+        stop_on_failure: bool,
+        invocation: &Invocation,
+    ) -> Result<BatchRun, McpError> {
+        let verbosity = options.verbosity()?;
+        let persist = options.persist;
+        let batch_id = persist.then(|| new_batch_id(code));
 
-                if !warning_msg.is_empty() {
-                    if let Err(e) = db.store_todo("clippy", warning_msg, None, None) {
-                        eprintln!("Failed to store clippy todo: {}", e);
-                    } else {
-                        todo_count += 1;
-                    }
-                }
+        let project = self.pool.checkout()?;
+        let mut steps = Vec::with_capacity(tools.len());
+        let mut success = true;
+        for &tool in tools {
+            if stop_on_failure && !success {
+                steps.push(None);
+                continue;
             }
-
-            // Store "help:" suggestions as todos too
-            if line.starts_with("help:") {
-                let help_msg = line.strip_prefix("help:").unwrap_or(line).trim();
-                if !help_msg.is_empty() {
-                    if let Err(e) = db.store_todo("clippy_help", help_msg, None, None) {
-                        eprintln!("Failed to store clippy help: {}", e);
-                    } else {
-                        todo_count += 1;
-                    }
+            let (cargo_args, timeout) =
+                cargo_tool_command(tool).expect("batch steps are snippet tools");
+            let outcome = match self.requirements.check(tool) {
+                Ok(()) => {
+                    self.run_cargo_tool_in(project.path(), code, cargo_args, timeout, invocation)
+                        .await
                 }
+                Err(e) => Err(e),
+            };
+            let result = match outcome {
+                Ok(result) => result,
+                Err(e) => {
+                    success = false;
+                    steps.push(Some(
+                        json!({"success": false, "error": e.message, "data": e.data}),
+                    ));
+                    continue;
+                }
+            };
+            success &= result.status == 0;
+
+            let mut json_result = tool_response(tool, code, &result, verbosity, invocation);
+            let stored = self.store_analysis_with_errors(
+                tool,
+                code,
+                &result,
+                invocation,
+                persist,
+                options.session.as_deref(),
+            );
+            json_result["persistence"] = persistence_report(persist, &stored);
+            let analysis_id = stored.ok().flatten();
+            if let (Some(id), Some(batch_id), Some(db_arc)) = (analysis_id, &batch_id, &self.db)
+                && let Err(e) = db_arc.run(|db| db.set_analysis_batch(id, batch_id))
+            {
+                warn!("Failed to record batch id: {}", e);
             }
+            json_result["analysis_id"] = json!(analysis_id);
+            steps.push(Some(json_result));
         }
+        Ok(BatchRun {
+            batch_id,
+            steps,
+            success,
+        })
+    }
 
-        if todo_count > 0 {
-            eprintln!("Stored {} clippy todos", todo_count);
+    fn cached_run(&self, key: &str) -> Option<CachedRun> {
+        let Some(ref db_arc) = self.db else {
+            return self.cache.get(key, self.config.cache_ttl);
+        };
+        db_arc
+            .run(|db| db.get_cached_run(key, self.config.cache_ttl))
+            .unwrap_or_else(|e| {
+                warn!("Failed to read result cache: {}", e);
+                None
+            })
+    }
+
+    fn store_cached_run(&self, key: String, result: &ExecResult) {
+        let run = CachedRun {
+            stdout: result.stdout.clone(),
+            stderr: result.stderr.clone(),
+            status: result.status,
+            duration_ms: result.duration_ms,
+        };
+        let Some(ref db_arc) = self.db else {
+            self.cache.put(key, run);
+            return;
+        };
+        if let Err(e) = db_arc.run(|db| db.put_cached_run(&key, &run)) {
+            warn!("Failed to cache result: {}", e);
         }
     }
 
-    /// Store analysis with improved error handling
-    fn store_analysis_with_errors(
+    /// Run `cargo deny check` on the snippet's dependencies, with `config` as
+    /// the project's deny.toml when given. The report is `None` when
+    /// cargo-deny stopped before checking, e.g. on an invalid config.
+    async fn run_deny(
         &self,
-        tool: &str,
-        result: &ExecResult,
-        persist: bool,
-    ) -> Result<(), String> {
-        if !persist {
-            return Ok(());
+        code: &str,
+        config: Option<&str>,
+        invocation: &Invocation,
+    ) -> Result<(ExecResult, Option<DenyReport>), McpError> {
+        let (args, timeout) = cargo_tool_command("cargo_deny").expect("cargo_deny has a command");
+        self.requirements.check("cargo_deny")?;
+        let project = self.pool.checkout()?;
+        if let Some(config) = config {
+            std::fs::write(project.path().join("deny.toml"), config).map_err(|e| {
+                McpError::internal_error(format!("Failed to write deny.toml: {}", e), None)
+            })?;
+        }
+        let mut result = self
+            .run_cargo_tool_in(project.path(), code, args, timeout, invocation)
+            .await?;
+        if invocation.keep_project {
+            result.project_path = Some(project.keep());
         }
+        let report = DenyReport::from_json_output(&result.stderr);
+        Ok((result, report))
+    }
 
-        let Some(ref db_arc) = self.db else {
-            return Err("Database not initialized".to_string());
-        };
+    /// Run `cargo semver-checks` with `code` as the new version of a library
+    /// and `baseline` as the old one. The report is `None` when no checks
+    /// ran, e.g. one of the versions failed to build.
+    async fn run_semver_checks(
+        &self,
+        code: &str,
+        baseline: &SemverBaseline<'_>,
+        invocation: &Invocation,
+    ) -> Result<(ExecResult, Option<SemverReport>), McpError> {
+        self.requirements.check("semver_check")?;
+        let project = self.pool.checkout()?;
+        write_library_scaffold(project.path(), code, false)?;
+        let mut baseline_project = None;
+        let mut base = vec!["semver-checks", "check-release"];
+        let baseline_root;
+        match baseline {
+            SemverBaseline::Code(baseline_code) => {
+                let checkout = self.pool.checkout()?;
+                write_library_scaffold(checkout.path(), baseline_code, false)?;
+                baseline_root = checkout.path().display().to_string();
+                base.extend(["--baseline-root", baseline_root.as_str()]);
+                baseline_project = Some(checkout);
+            }
+            SemverBaseline::Published {
+                crate_name,
+                version,
+            } => {
+                // The registry baseline is looked up by the package's name
+                let manifest_path = project.path().join("Cargo.toml");
+                let rename_err = |e: std::io::Error| {
+                    McpError::internal_error(format!("Failed to rename package: {}", e), None)
+                };
+                let manifest = std::fs::read_to_string(&manifest_path).map_err(rename_err)?;
+                let manifest = manifest.replacen(
+                    "name = \"temp_project\"",
+                    &format!("name = \"{}\"", crate_name),
+                    1,
+                );
+                std::fs::write(&manifest_path, manifest).map_err(rename_err)?;
+                base.extend(["--baseline-version", version]);
+            }
+        }
+        let args = invocation.cargo_args(&base);
+        let args: Vec<&str> = args.iter().map(String::as_str).collect();
+        let mut result = run_in_project(
+            project.path(),
+            code,
+            &args,
+            SEMVER_TIMEOUT,
+            &invocation.env,
+            &self.child_env,
+        )
+        .await?;
+        // Locations name files inside the scratch projects; keep them relative
+        for path in
+            std::iter::once(project.path()).chain(baseline_project.as_ref().map(|p| p.path()))
+        {
+            result.stderr = result.stderr.replace(&format!("{}/", path.display()), "");
+        }
+        if invocation.keep_project {
+            result.project_path = Some(project.keep());
+        }
+        let report = SemverReport::from_output(&result.stderr);
+        Ok((result, report))
+    }
 
-        let db = db_arc
-            .lock()
-            .map_err(|e| format!("Database lock failed: {}", e))?;
+    /// Build `code` in release mode and measure the binary, preferring
+    /// `cargo bloat` and falling back to the `nm` symbol table. The report is
+    /// `None` when the build produced no executable.
+    async fn run_bloat(
+        &self,
+        code: &str,
+        invocation: &Invocation,
+        top: usize,
+    ) -> Result<(ExecResult, Option<BloatReport>), McpError> {
+        self.requirements.check("cargo_bloat")?;
+        let project = self.pool.checkout()?;
+        let top_arg = top.to_string();
 
-        let json_result = json!({
-            "status": result.status,
-            "success": result.status == 0,
-            "stdout": result.stdout,
-            "stderr": result.stderr,
-            "duration_ms": result.duration_ms
-        });
+        if self
+            .requirements
+            .version(&requirements::CARGO_BLOAT)
+            .is_some()
+        {
+            let base = ["bloat", "--release", "--message-format", "json", "-n"];
+            let args = invocation.cargo_args(&[&base[..], &[top_arg.as_str()]].concat());
+            let args: Vec<&str> = args.iter().map(String::as_str).collect();
+            let functions = run_in_project(
+                project.path(),
+                code,
+                &args,
+                BLOAT_TIMEOUT,
+                &invocation.env,
+                &self.child_env,
+            )
+            .await?;
+            if functions.status != 0 {
+                return Ok((functions, None));
+            }
+            let args =
+                invocation.cargo_args(&[&base[..], &[top_arg.as_str(), "--crates"]].concat());
+            let args: Vec<&str> = args.iter().map(String::as_str).collect();
+            let crates = run_in_project(
+                project.path(),
+                code,
+                &args,
+                BLOAT_TIMEOUT,
+                &invocation.env,
+                &self.child_env,
+            )
+            .await?;
+            if let (Ok(f), Ok(c)) = (
+                serde_json::from_str::<Value>(functions.stdout.trim()),
+                serde_json::from_str::<Value>(crates.stdout.trim()),
+            ) && let Some(report) = BloatReport::from_cargo_bloat(&f, &c, top)
+            {
+                return Ok((functions, Some(report)));
+            }
+            warn!("Unexpected cargo bloat output, falling back to nm");
+        }
 
-        match db.store_analysis(tool, &json_result, result.status == 0, None) {
-            Ok(analysis_id) => {
-                // Store errors from stderr
-                Self::parse_and_store_errors(&db, analysis_id, &result.stderr);
+        let args = invocation.cargo_args(&[
+            "build",
+            "--release",
+            "--message-format=json-render-diagnostics",
+        ]);
+        let args: Vec<&str> = args.iter().map(String::as_str).collect();
+        let mut result = run_in_project(
+            project.path(),
+            code,
+            &args,
+            BLOAT_TIMEOUT,
+            &invocation.env,
+            &self.child_env,
+        )
+        .await?;
+        let Some(path) = find_executable(&mut result.stdout) else {
+            return Ok((result, None));
+        };
+        let total_size_bytes = std::fs::metadata(&path)
+            .map_err(|e| {
+                McpError::internal_error(format!("Failed to read build artifact: {}", e), None)
+            })?
+            .len();
+        if self.requirements.version(&requirements::NM).is_none() {
+            return Ok((result, Some(BloatReport::size_only(total_size_bytes))));
+        }
+        let report = match Command::new("nm")
+            .args(["-C", "--print-size", "--size-sort", "--radix=d"])
+            .arg(&path)
+            .output()
+            .await
+        {
+            Ok(output) if output.status.success() => BloatReport::from_nm(
+                &String::from_utf8_lossy(&output.stdout),
+                total_size_bytes,
+                top,
+            ),
+            _ => {
+                warn!("nm failed, reporting binary size only");
+                BloatReport::size_only(total_size_bytes)
+            }
+        };
+        Ok((result, Some(report)))
+    }
 
-                // Store clippy-specific todos if this was a clippy run
-                if tool == "cargo_clippy" {
-                    Self::parse_and_store_clippy_todos(&db, &result.stderr);
-                }
+    /// `rustc --version` of the toolchain cargo runs, cached until rustup
+    /// switches or updates it
+    fn rustc_version(&self) -> Option<String> {
+        self.requirements
+            .toolchain()
+            .map(|toolchain| toolchain.version)
+    }
 
-                Ok(())
+    /// `rustc --explain error_code`, served from the explanations table when
+    /// the current rustc already explained it
+    fn explain(&self, error_code: &str) -> Result<Explanation, McpError> {
+        let rustc_version = self.rustc_version();
+        if let (Some(db), Some(version)) = (&self.db, &rustc_version) {
+            match db.run(|db| db.get_explanation(error_code, version)) {
+                Ok(Some(text)) => {
+                    return Ok(Explanation {
+                        text,
+                        stderr: String::new(),
+                        success: true,
+                        cached: true,
+                    });
+                }
+                Ok(None) => {}
+                Err(e) => warn!("Failed to read explanation cache: {}", e),
             }
-            Err(e) => Err(format!("Failed to store analysis: {}", e)),
         }
-    }
-}
 
-impl ServerHandler for RustyToolsServer {
-    fn get_info(&self) -> ServerInfo {
-        ServerInfo {
-            instructions: Some(
-                "Rust development tools for formatting, linting, and analysis with persistence"
-                    .into(),
-            ),
-            capabilities: ServerCapabilities::builder().enable_tools().build(),
-            ..Default::default()
+        let output = StdCommand::new("rustc")
+            .args(["--explain", error_code])
+            .output()
+            .map_err(|e| {
+                McpError::internal_error(format!("Failed to run rustc --explain: {}", e), None)
+            })?;
+        let explanation = Explanation {
+            text: String::from_utf8_lossy(&output.stdout).to_string(),
+            stderr: String::from_utf8_lossy(&output.stderr).to_string(),
+            success: output.status.success(),
+            cached: false,
+        };
+        if explanation.success
+            && let (Some(db), Some(version)) = (&self.db, &rustc_version)
+            && let Err(e) = db.run(|db| db.put_explanation(error_code, version, &explanation.text))
+        {
+            warn!("Failed to cache explanation: {}", e);
         }
+        Ok(explanation)
     }
 
-    #[allow(clippy::manual_async_fn)]
-    fn initialize(
-        &self,
-        _request: InitializeRequestParam,
-        _context: RequestContext<RoleServer>,
-    ) -> impl Future<Output = Result<InitializeResult, McpError>> + Send + '_ {
-        async move {
-            eprintln!("🚀 MCP Server initialized");
-            Ok(self.get_info())
+    /// The `explain://E0308` resource: the explanation as markdown
+    fn read_explanation(&self, uri: &str) -> Result<ReadResourceResult, McpError> {
+        let error_code = uri
+            .strip_prefix(EXPLAIN_SCHEME)
+            .filter(|code| !code.is_empty() && code.bytes().all(|b| b.is_ascii_alphanumeric()))
+            .ok_or_else(|| {
+                McpError::resource_not_found(
+                    format!("Unknown resource {}; expected explain://<error code>", uri),
+                    None,
+                )
+            })?;
+        let explanation = self.explain(error_code)?;
+        if !explanation.success {
+            return Err(McpError::resource_not_found(
+                format!(
+                    "No explanation for {}: {}",
+                    error_code,
+                    explanation.stderr.trim()
+                ),
+                None,
+            ));
         }
+        Ok(ReadResourceResult {
+            contents: vec![ResourceContents::TextResourceContents {
+                uri: uri.to_string(),
+                mime_type: Some("text/markdown".to_string()),
+                text: explanation.text,
+                meta: None,
+            }],
+        })
     }
 
-    #[allow(clippy::manual_async_fn)]
-    fn list_tools(
-        &self,
-        _request: Option<PaginatedRequestParam>,
-        _context: RequestContext<RoleServer>,
-    ) -> impl Future<Output = Result<ListToolsResult, McpError>> + Send + '_ {
-        async move {
-            eprintln!("📋 Listing tools");
-
-            let tools = vec![
-                Tool::new(
-                    Cow::Borrowed("cargo_fmt"),
-                    Cow::Borrowed("Format Rust code using rustfmt"),
-                    Arc::new(rmcp::object!({
-                        "type": "object",
-                        "properties": {
-                            "code": {"type": "string", "description": "Rust code to format"},
-                            "persist": {"type": "boolean", "description": "Store results in SQLite database", "default": false}
-                        },
-                        "required": ["code"]
-                    })),
-                ),
-                Tool::new(
-                    Cow::Borrowed("cargo_clippy"),
-                    Cow::Borrowed("Analyze code with clippy for improvements"),
-                    Arc::new(rmcp::object!({
-                        "type": "object",
-                        "properties": {
-                            "code": {"type": "string", "description": "Rust code to analyze"},
-                            "persist": {"type": "boolean", "description": "Store results in SQLite database", "default": false}
-                        },
-                        "required": ["code"]
-                    })),
-                ),
-                Tool::new(
-                    Cow::Borrowed("cargo_check"),
-                    Cow::Borrowed("Type-check Rust code without building"),
-                    Arc::new(rmcp::object!({
-                        "type": "object",
-                        "properties": {
-                            "code": {"type": "string", "description": "Rust code to check"},
-                            "persist": {"type": "boolean", "description": "Store results in SQLite database", "default": false}
-                        },
-                        "required": ["code"]
-                    })),
-                ),
-                Tool::new(
-                    Cow::Borrowed("rustc_explain"),
-                    Cow::Borrowed("Explain a Rust compiler error code"),
-                    Arc::new(rmcp::object!({
-                        "type": "object",
-                        "properties": {
-                            "error_code": {"type": "string", "description": "Error code like E0308"}
-                        },
-                        "required": ["error_code"]
-                    })),
-                ),
-                Tool::new(
-                    Cow::Borrowed("cargo_fix"),
-                    Cow::Borrowed("Automatically fix compiler warnings"),
-                    Arc::new(rmcp::object!({
-                        "type": "object",
-                        "properties": {
-                            "code": {"type": "string", "description": "Rust code to fix"},
-                            "persist": {"type": "boolean", "description": "Store results in SQLite database", "default": false}
-                        },
-                        "required": ["code"]
-                    })),
-                ),
-                Tool::new(
-                    Cow::Borrowed("cargo_audit"),
-                    Cow::Borrowed("Scan for security vulnerabilities in dependencies"),
-                    Arc::new(rmcp::object!({
-                        "type": "object",
-                        "properties": {
-                            "code": {"type": "string", "description": "Rust code with Cargo.toml to audit"},
-                            "persist": {"type": "boolean", "description": "Store results in SQLite database", "default": false}
-                        },
-                        "required": ["code"]
-                    })),
-                ),
-                Tool::new(
-                    Cow::Borrowed("cargo_test"),
-                    Cow::Borrowed("Run tests on Rust code"),
-                    Arc::new(rmcp::object!({
-                        "type": "object",
-                        "properties": {
-                            "code": {"type": "string", "description": "Rust code with tests to run"},
-                            "persist": {"type": "boolean", "description": "Store results in SQLite database", "default": false}
-                        },
-                        "required": ["code"]
-                    })),
-                ),
-                Tool::new(
-                    Cow::Borrowed("cargo_build"),
-                    Cow::Borrowed("Build Rust code (produces artifacts)"),
-                    Arc::new(rmcp::object!({
-                        "type": "object",
-                        "properties": {
-                            "code": {"type": "string", "description": "Rust code to build-check"},
-                            "persist": {"type": "boolean", "description": "Store results in SQLite database", "default": false}
-                        },
-                        "required": ["code"]
-                    })),
-                ),
-                Tool::new(
-                    Cow::Borrowed("cargo_search"),
-                    Cow::Borrowed("Search crates.io for packages"),
-                    Arc::new(rmcp::object!({
-                        "type": "object",
-                        "properties": {
-                            "query": {"type": "string", "description": "Search query for crates.io"}
-                        },
-                        "required": ["query"]
-                    })),
-                ),
-                Tool::new(
-                    Cow::Borrowed("cargo_tree"),
-                    Cow::Borrowed("Show dependency tree for Rust code"),
-                    Arc::new(rmcp::object!({
-                        "type": "object",
-                        "properties": {
-                            "code": {"type": "string", "description": "Rust code with dependencies to analyze"},
-                            "persist": {"type": "boolean", "description": "Store results in SQLite database", "default": false}
-                        },
-                        "required": ["code"]
-                    })),
-                ),
-                Tool::new(
-                    Cow::Borrowed("cargo_doc"),
-                    Cow::Borrowed("Generate documentation for Rust code"),
-                    Arc::new(rmcp::object!({
-                        "type": "object",
-                        "properties": {
-                            "code": {"type": "string", "description": "Rust code to generate documentation for"},
-                            "persist": {"type": "boolean", "description": "Store results in SQLite database", "default": false}
-                        },
-                        "required": ["code"]
-                    })),
-                ),
-                Tool::new(
-                    Cow::Borrowed("rust_analyzer"),
-                    Cow::Borrowed(
-                        "Analyze Rust code with rust-analyzer for diagnostics and suggestions",
-                    ),
-                    Arc::new(rmcp::object!({
-                        "type": "object",
-                        "properties": {
-                            "code": {"type": "string", "description": "Rust code to analyze"},
-                            "persist": {"type": "boolean", "description": "Store results in SQLite database", "default": false}
-                        },
-                        "required": ["code"]
-                    })),
-                ),
-                Tool::new(
-                    Cow::Borrowed("cargo_history"),
-                    Cow::Borrowed("Query past errors by error code from stored analyses"),
-                    Arc::new(rmcp::object!({
-                        "type": "object",
-                        "properties": {
-                            "error_code": {"type": "string", "description": "Specific error code to search for (optional)"},
-                            "limit": {"type": "number", "description": "Maximum number of results to return", "default": 10}
-                        },
-                        "required": []
-                    })),
-                ),
-                Tool::new(
-                    Cow::Borrowed("cargo_todos"),
-                    Cow::Borrowed("Show current todo list from warnings and clippy suggestions"),
-                    Arc::new(rmcp::object!({
-                        "type": "object",
-                        "properties": {
-                            "show_completed": {"type": "boolean", "description": "Include completed todos", "default": false}
-                        },
-                        "required": []
-                    })),
-                ),
-                Tool::new(
-                    Cow::Borrowed("db_stats"),
-                    Cow::Borrowed("Show database statistics and stored data counts"),
-                    Arc::new(rmcp::object!({
-                        "type": "object",
-                        "properties": {},
-                        "required": []
-                    })),
-                ),
-            ];
-
-            Ok(ListToolsResult {
-                tools,
-                ..Default::default()
-            })
+    /// Make sure a rustup toolchain is installed, installing it when allowed.
+    /// Returns whether it had to be installed.
+    async fn ensure_toolchain(&self, toolchain: &str, install: bool) -> Result<bool, McpError> {
+        if self.requirements.installed_toolchain(toolchain).is_some() {
+            return Ok(false);
+        }
+        let install_hint = format!("rustup toolchain install {} --profile minimal", toolchain);
+        if !install || self.config.offline {
+            return Err(McpError::invalid_params(
+                format!("Rust {} is not installed", toolchain),
+                Some(json!({
+                    "missing_toolchain": toolchain,
+                    "install_hint": install_hint
+                })),
+            ));
         }
+        info!("Installing Rust {}", toolchain);
+        let name = toolchain.to_string();
+        tokio::task::spawn_blocking(move || requirements::install_toolchain(&name))
+            .await
+            .map_err(|e| McpError::internal_error(format!("Install task failed: {}", e), None))?
+            .map_err(|e| {
+                McpError::invalid_params(
+                    format!("Failed to install Rust {}: {}", toolchain, e),
+                    Some(json!({"install_hint": install_hint})),
+                )
+            })?;
+        Ok(true)
     }
 
-    #[allow(clippy::manual_async_fn)]
-    fn list_resources(
-        &self,
-        _request: Option<PaginatedRequestParam>,
-        _context: RequestContext<RoleServer>,
-    ) -> impl Future<Output = Result<ListResourcesResult, McpError>> + Send + '_ {
-        async move {
-            Ok(ListResourcesResult {
-                resources: Vec::<Resource>::new(),
-                ..Default::default()
-            })
+    /// Run a tool call
+    async fn dispatch(&self, request: CallToolRequestParam) -> Result<CallToolResult, McpError> {
+        info!("Calling tool");
+        debug!(arguments = ?request.arguments, "Tool arguments");
+        let _activity = self.activity.start();
+        let Some(handler) = self.tools.get(&request.name) else {
+            return Err(McpError::internal_error(
+                format!("Unknown tool: {}", request.name),
+                None,
+            ));
+        };
+        let mut result = handler.execute(self, request).await?;
+        if self.config.pretty_json {
+            pretty_print(&mut result);
         }
+        Ok(result)
     }
 
-    #[allow(clippy::manual_async_fn)]
-    fn call_tool(
-        &self,
+    /// Run a built-in tool's call once its arguments are parsed, with their
+    /// `options`: wait for a slot if it runs cargo, unless an identical call
+    /// is already running, whose result it then shares
+    async fn run_builtin<'a>(
+        &'a self,
         request: CallToolRequestParam,
-        _context: RequestContext<RoleServer>,
-    ) -> impl Future<Output = Result<CallToolResult, McpError>> + Send + '_ {
-        async move {
-            eprintln!("🔧 Calling tool: {}", request.name);
-            eprintln!("🔧 Tool arguments: {:?}", request.arguments);
-
-            match request.name.as_ref() {
-                "cargo_fmt" => {
-                    eprintln!("🔧 Executing cargo_fmt");
-                    let code = get_code_arg(&request, "cargo_fmt")?;
-                    validate_rust_code(code)?;
-                    let result = run_rust_tool(code, &["fmt", "--", "--emit=stdout"], None).await?;
-                    let json_result = json!({
-                        "status": result.status,
-                        "success": result.status == 0,
-                        "stdout": result.stdout,
-                        "stderr": result.stderr,
-                        "duration_ms": result.duration_ms
-                    });
-                    let persist = Self::get_persist_flag(&request);
-                    if let Err(e) = self.store_analysis_with_errors("cargo_fmt", &result, persist) {
-                        eprintln!("⚠️  Failed to store analysis: {}", e);
-                    }
-                    Ok(CallToolResult {
-                        content: vec![rmcp::model::Content::text(json_result.to_string())],
-                        structured_content: None,
-                        meta: None,
-                        is_error: Some(result.status != 0),
-                    })
-                }
-                "cargo_clippy" => {
-                    eprintln!("🔧 Executing cargo_clippy");
-                    let code = get_code_arg(&request, "cargo_clippy")?;
-                    validate_rust_code(code)?;
-                    let result = run_rust_tool(
-                        code,
-                        &["clippy", "--", "-D", "warnings"],
-                        Some(Duration::from_secs(30)),
-                    )
-                    .await?;
-                    let json_result = json!({
-                        "status": result.status,
-                        "success": result.status == 0,
-                        "stdout": result.stdout,
-                        "stderr": result.stderr,
-                        "duration_ms": result.duration_ms
-                    });
-                    let persist = Self::get_persist_flag(&request);
-                    if let Err(e) =
-                        self.store_analysis_with_errors("cargo_clippy", &result, persist)
-                    {
-                        eprintln!("⚠️  Failed to store analysis: {}", e);
-                    }
-                    Ok(CallToolResult {
-                        content: vec![rmcp::model::Content::text(json_result.to_string())],
-                        structured_content: None,
-                        meta: None,
-                        is_error: Some(result.status != 0),
-                    })
-                }
-                "cargo_check" => {
-                    eprintln!("🔧 Executing cargo_check");
-                    let code = get_code_arg(&request, "cargo_check")?;
-                    validate_rust_code(code)?;
-                    let result =
-                        run_rust_tool(code, &["check"], Some(Duration::from_secs(30))).await?;
-                    let json_result = json!({
-                        "status": result.status,
-                        "success": result.status == 0,
-                        "stdout": result.stdout,
-                        "stderr": result.stderr,
-                        "duration_ms": result.duration_ms
-                    });
-                    let persist = Self::get_persist_flag(&request);
-                    if let Err(e) = self.store_analysis_with_errors("cargo_check", &result, persist)
-                    {
-                        eprintln!("⚠️  Failed to store analysis: {}", e);
+        options: args::Options,
+        run: impl FnOnce(args::Options) -> ToolFuture<'a>,
+    ) -> ToolOutput {
+        self.check_input_size(&options)?;
+        // Waiting here holds neither a slot nor a project; when the running
+        // call fails or is cancelled, a waiting one runs in its place
+        let mut leader = None;
+        if let Some(key) = self.flight_key(&request, &options) {
+            while leader.is_none() {
+                match self.in_flight.join(&key) {
+                    Flight::Lead(lead) => leader = Some(lead),
+                    Flight::Follow(follower) => {
+                        let result = tokio::select! {
+                            result = follower.result() => result,
+                            _ = cancellation::cancelled() => {
+                                return Err(McpError::internal_error("Tool call cancelled", None));
+                            }
+                        };
+                        if let Some(mut result) = result {
+                            mark_coalesced(&mut result);
+                            return Ok(result);
+                        }
                     }
-                    Ok(CallToolResult {
-                        content: vec![rmcp::model::Content::text(json_result.to_string())],
-                        structured_content: None,
-                        meta: None,
-                        is_error: Some(result.status != 0),
-                    })
                 }
-                "rustc_explain" => {
-                    eprintln!("🔧 Executing rustc_explain");
-                    let error_code = request
-                        .arguments
-                        .as_ref()
-                        .and_then(|args| args.get("error_code"))
-                        .and_then(|v| v.as_str())
-                        .ok_or_else(|| McpError::invalid_params("error_code is required", None))?;
-
-                    let output = StdCommand::new("rustc")
-                        .args(["--explain", error_code])
-                        .output()
-                        .map_err(|e| {
-                            McpError::internal_error(
-                                format!("Failed to run rustc --explain: {}", e),
-                                None,
-                            )
-                        })?;
+            }
+        }
+        let _slot = if runs_cargo(&request.name) {
+            Some(self.runs.acquire().await?)
+        } else {
+            None
+        };
 
-                    let explanation = String::from_utf8_lossy(&output.stdout).to_string();
-                    let stderr = String::from_utf8_lossy(&output.stderr).to_string();
+        let result = run(options).await?;
+        if let Some(leader) = leader {
+            leader.publish(result.clone());
+        }
+        Ok(result)
+    }
 
-                    let json_result = json!({
-                        "error_code": error_code,
-                        "explanation": explanation,
-                        "stderr": stderr,
-                        "success": output.status.success()
-                    });
+    /// Key under which identical calls of a snippet tool share one run: the
+    /// tool, its arguments and the toolchain. `None` runs the call on its
+    /// own, as with `no_cache` or `keep_project`, whose callers each expect
+    /// a run and a project of their own.
+    fn flight_key(
+        &self,
+        request: &CallToolRequestParam,
+        options: &args::Options,
+    ) -> Option<String> {
+        if cargo_tool_command(&request.name).is_none() || options.no_cache || options.keep_project {
+            return None;
+        }
+        let toolchain = self.rustc_version()?;
+        // Argument maps serialize with sorted keys
+        Some(code_hash(&format!(
+            "{}\0{}\0{}",
+            toolchain,
+            request.name,
+            json!(request.arguments)
+        )))
+    }
 
-                    Ok(CallToolResult {
-                        content: vec![rmcp::model::Content::text(json_result.to_string())],
-                        structured_content: None,
-                        meta: None,
-                        is_error: Some(!output.status.success()),
-                    })
+    /// Refuse `code`, `baseline_code` and `files` entries over
+    /// `input_max_bytes`, and `files` over `files_max_bytes` in all, before
+    /// anything is written or run
+    fn check_input_size(&self, options: &args::Options) -> Result<(), McpError> {
+        let max = self.config.input_max_bytes;
+        let too_large = |what: String, size: usize, limit: usize, hint: &str| {
+            McpError::invalid_params(
+                format!(
+                    "{} is {} bytes, over the {}-byte limit; {}",
+                    what, size, limit, hint
+                ),
+                Some(json!({"input": what, "size_bytes": size, "limit_bytes": limit})),
+            )
+        };
+        for (key, code) in [
+            ("code", &options.code),
+            ("baseline_code", &options.baseline_code),
+        ] {
+            if let Some(code) = code
+                && code.len() > max
+            {
+                return Err(too_large(
+                    key.to_string(),
+                    code.len(),
+                    max,
+                    "send only the items under analysis, or split them into modules in files",
+                ));
+            }
+        }
+        let Some(files) = &options.files else {
+            return Ok(());
+        };
+        let mut total = 0;
+        for (path, contents) in files {
+            let size = contents.len();
+            if size > max {
+                return Err(too_large(
+                    format!("files[{}]", path),
+                    size,
+                    max,
+                    "split it into smaller modules",
+                ));
+            }
+            total += size;
+        }
+        if total > self.config.files_max_bytes {
+            return Err(too_large(
+                "files".to_string(),
+                total,
+                self.config.files_max_bytes,
+                "send only the crates and modules under analysis",
+            ));
+        }
+        Ok(())
+    }
+
+    /// Rebuild the options a stored analysis ran with from its output, whose
+    /// `details` recorded the tool's own arguments
+    fn stored_invocation(&self, tool: &str, output: &Value) -> Result<Invocation, McpError> {
+        let output = output.as_object();
+        let recorded = |key: &str| output.and_then(|output| output.get(key));
+        // Stored outputs list the paths of files only, and record only that
+        // a lockfile was given
+        let unstored = if recorded("files").is_some_and(Value::is_array) {
+            Some("Analyses of project files can't be re-run: their contents aren't stored")
+        } else if recorded("reconstructed_files").is_some() {
+            Some("Analyses of pasted projects can't be re-run: their files aren't stored")
+        } else if recorded("cargo_lock").is_some_and(Value::is_boolean) {
+            Some("Updates of a cargo_lock can't be re-run: its contents aren't stored")
+        } else {
+            None
+        };
+        if let Some(message) = unstored {
+            return Err(McpError::invalid_params(message, None));
+        }
+        let schema = self.tools.get(tool).map(|handler| handler.schema());
+        let properties = schema
+            .as_ref()
+            .and_then(|schema| schema.get("properties"))
+            .and_then(Value::as_object);
+        let arguments: serde_json::Map<String, Value> = output
+            .into_iter()
+            .flatten()
+            .filter(|(key, _)| properties.is_some_and(|properties| properties.contains_key(*key)))
+            .map(|(key, value)| (key.clone(), value.clone()))
+            .collect();
+        let options = args::parse::<args::Options>(Some(&arguments))?;
+        self.build_invocation(tool, &options)
+    }
+
+    /// `rustflags`/`cfgs` for `COMPILE_FLAG_TOOLS` and `target`/`no_std` for
+    /// `TARGET_TOOLS`; `cargo_bloat` takes `target` only. Stored outputs carry
+    /// the same options, with cfgs already folded into `rustflags`.
+    fn build_invocation(
+        &self,
+        tool: &str,
+        options: &args::Options,
+    ) -> Result<Invocation, McpError> {
+        let mut invocation = Invocation {
+            max_output_bytes: options
+                .max_output_bytes
+                .map(|max| usize::try_from(max.get()).unwrap_or(usize::MAX)),
+            ..Default::default()
+        };
+        if let Some(max) = options.max_output_bytes {
+            invocation
+                .details
+                .insert("max_output_bytes".to_string(), json!(max));
+        }
+        if tool == "rust_unused" {
+            invocation
+                .env
+                .push(("RUSTFLAGS".to_string(), UNUSED_RUSTFLAGS.to_string()));
+        }
+        if COMPILE_FLAG_TOOLS.contains(&tool) {
+            let flags = CompileFlags::new(
+                options.rustflags.clone().unwrap_or_default(),
+                options.cfgs.clone().unwrap_or_default(),
+            )?;
+            invocation.set_compile_flags(&flags);
+        }
+        if (TARGET_TOOLS.contains(&tool) || tool == "cargo_bloat")
+            && let Some(target) = &options.target
+        {
+            self.requirements.check_target(target)?;
+            invocation.set_target(target);
+        }
+        if SNIPPET_TOOLS.contains(&tool) {
+            invocation.set_toolchain(self.requirements.toolchain());
+            invocation.no_cache = options.no_cache;
+            invocation.keep_project = options.keep_project;
+        }
+        if tool == "cargo_audit" {
+            let ignore = options.ignore.as_deref().unwrap_or_default();
+            if let Some(bad) = ignore.iter().find(|id| !audit::is_advisory_id(id)) {
+                return Err(McpError::invalid_params(
+                    format!(
+                        "ignore must list advisory ids such as RUSTSEC-2021-0078, got: {}",
+                        bad
+                    ),
+                    None,
+                ));
+            }
+            for id in ignore {
+                invocation.args.extend(["--ignore".to_string(), id.clone()]);
+            }
+            if !ignore.is_empty() {
+                invocation
+                    .details
+                    .insert("ignore".to_string(), json!(ignore));
+            }
+        }
+        if tool == "semver_check" {
+            invocation.set_toolchain(self.requirements.toolchain());
+            invocation.keep_project = options.keep_project;
+            if let Some(release_type) = options.release_type {
+                invocation.args.extend([
+                    "--release-type".to_string(),
+                    release_type.name().to_string(),
+                ]);
+                invocation
+                    .details
+                    .insert("release_type".to_string(), json!(release_type));
+            }
+        }
+        if tool == "check_msrv" {
+            let version = options
+                .rust_version
+                .as_deref()
+                .ok_or_else(|| McpError::invalid_params("rust_version is required", None))?;
+            let version =
+                msrv::RustVersion::parse(version).map_err(|e| McpError::invalid_params(e, None))?;
+            let toolchain = version.toolchain();
+            invocation
+                .env
+                .push(("RUSTUP_TOOLCHAIN".to_string(), toolchain.clone()));
+            invocation.edition = Some(version.edition());
+            // The toolchain's own version, not the default one
+            invocation.details.remove("rustc_version");
+            invocation.details.remove("toolchain");
+            invocation.set_toolchain(self.requirements.installed_toolchain(&toolchain));
+            invocation
+                .details
+                .insert("rust_version".to_string(), json!(toolchain));
+        }
+        if CRATE_SOURCE_TOOLS.contains(&tool)
+            && let Some(source) = &options.crate_source
+        {
+            if options.code.is_some() {
+                return Err(McpError::invalid_params(
+                    "pass either code or crate_source, not both",
+                    None,
+                ));
+            }
+            for (option, given) in [
+                ("no_std", options.no_std),
+                ("dependencies", options.dependencies.is_some()),
+            ] {
+                if given {
+                    return Err(McpError::invalid_params(
+                        format!("{} only applies to code snippets, not crate_source", option),
+                        None,
+                    ));
                 }
-                "cargo_fix" => {
-                    eprintln!("🔧 Executing cargo_fix");
-                    let code = get_code_arg(&request, "cargo_fix")?;
-                    validate_rust_code(code)?;
-                    let result = run_rust_tool(
-                        code,
-                        &["fix", "--allow-dirty"],
-                        Some(Duration::from_secs(60)),
-                    )
-                    .await?;
-                    let json_result = json!({
-                        "status": result.status,
-                        "success": result.status == 0,
-                        "stdout": result.stdout,
-                        "stderr": result.stderr,
-                        "duration_ms": result.duration_ms
-                    });
-                    let persist = Self::get_persist_flag(&request);
-                    if let Err(e) = self.store_analysis_with_errors("cargo_fix", &result, persist) {
-                        eprintln!("⚠️  Failed to store analysis: {}", e);
-                    }
-                    Ok(CallToolResult {
-                        content: vec![rmcp::model::Content::text(json_result.to_string())],
-                        structured_content: None,
-                        meta: None,
-                        is_error: Some(result.status != 0),
-                    })
+            }
+            invocation.crate_source = Some(CrateSource::parse(source)?);
+            invocation.inspect_build_code = CRATE_SOURCE_BUILD_TOOLS.contains(&tool);
+        }
+        if TARGET_TOOLS.contains(&tool) && options.no_std {
+            invocation.library = true;
+            invocation.no_std = true;
+            invocation.details.insert("no_std".to_string(), json!(true));
+        }
+        if tool == "cargo_build" {
+            if options.profile == args::Profile::Release {
+                invocation.args.push("--release".to_string());
+                invocation
+                    .details
+                    .insert("profile".to_string(), json!(options.profile));
+            }
+            if options.return_artifact {
+                // Artifact messages go to stdout; diagnostics stay rendered on stderr
+                invocation
+                    .args
+                    .push("--message-format=json-render-diagnostics".to_string());
+                invocation.collect_artifact = true;
+                invocation
+                    .details
+                    .insert("return_artifact".to_string(), json!(true));
+            }
+        }
+        if tool == "cargo_test"
+            && let Some(vars) = options.env.as_ref().filter(|vars| !vars.is_empty())
+        {
+            for (key, value) in vars {
+                child_env::check_user_var(key, value)
+                    .map_err(|e| McpError::invalid_params(e, None))?;
+                invocation.env.push((key.clone(), value.clone()));
+            }
+            let keys: Vec<&String> = vars.keys().collect();
+            invocation
+                .details
+                .insert("env_keys".to_string(), json!(keys));
+        }
+        if tool == "cargo_test" && options.doc {
+            // Doc tests only run for library targets
+            invocation.library = true;
+            invocation.args.push("--doc".to_string());
+            invocation.details.insert("doc".to_string(), json!(true));
+        }
+        if tool == "cargo_test" && options.doc_tests {
+            // Run unit and doc tests together against the code as a library
+            invocation.library = true;
+            invocation
+                .details
+                .insert("doc_tests".to_string(), json!(true));
+        }
+        if tool == "cargo_test" {
+            let mut runner = options.runner;
+            if runner == args::Runner::Nextest && invocation.library {
+                return Err(McpError::invalid_params(
+                    "nextest does not run doc tests; use runner \"libtest\" with doc or doc_tests",
+                    None,
+                ));
+            }
+            if runner == args::Runner::Nextest
+                && self
+                    .requirements
+                    .version(&requirements::CARGO_NEXTEST)
+                    .is_none()
+            {
+                invocation.details.insert(
+                    "warning".to_string(),
+                    json!(format!(
+                        "cargo-nextest is not installed ({}); ran libtest instead",
+                        requirements::CARGO_NEXTEST.install_hint
+                    )),
+                );
+                runner = args::Runner::Libtest;
+            }
+            if runner == args::Runner::Nextest {
+                invocation.command = Some(&["nextest", "run", "--message-format", "libtest-json"]);
+                // The libtest-json format is still experimental in nextest
+                invocation.env.push((
+                    "NEXTEST_EXPERIMENTAL_LIBTEST_JSON".to_string(),
+                    "1".to_string(),
+                ));
+            }
+            invocation
+                .details
+                .insert("runner".to_string(), json!(runner));
+        }
+        if tool == "cargo_tree" {
+            if let Some(depth) = options.depth {
+                invocation.args.push("--depth".to_string());
+                invocation.args.push(depth.to_string());
+                invocation.details.insert("depth".to_string(), json!(depth));
+            }
+            if let Some(package) = &options.invert {
+                if !is_package_spec(package) {
+                    return Err(McpError::invalid_params(
+                        "invert must be a package spec such as serde or serde@1.0.200",
+                        None,
+                    ));
                 }
-                "cargo_audit" => {
-                    eprintln!("🔧 Executing cargo_audit");
-                    let code = get_code_arg(&request, "cargo_audit")?;
-                    validate_rust_code(code)?;
-                    // cargo audit requires cargo-audit to be installed
-                    let result =
-                        run_rust_tool(code, &["audit"], Some(Duration::from_secs(60))).await?;
-                    let json_result = json!({
-                        "status": result.status,
-                        "success": result.status == 0,
-                        "stdout": result.stdout,
-                        "stderr": result.stderr,
-                        "duration_ms": result.duration_ms
-                    });
-                    let persist = Self::get_persist_flag(&request);
-                    if let Err(e) = self.store_analysis_with_errors("cargo_audit", &result, persist)
-                    {
-                        eprintln!("⚠️  Failed to store analysis: {}", e);
-                    }
-                    Ok(CallToolResult {
-                        content: vec![rmcp::model::Content::text(json_result.to_string())],
-                        structured_content: None,
-                        meta: None,
-                        is_error: Some(result.status != 0),
-                    })
+                invocation.args.push("--invert".to_string());
+                invocation.args.push(package.clone());
+                invocation
+                    .details
+                    .insert("invert".to_string(), json!(package));
+            }
+            for (name, flag, given) in [
+                ("no_dedupe", "--no-dedupe", options.no_dedupe),
+                ("duplicates", "--duplicates", options.duplicates),
+            ] {
+                if given {
+                    invocation.args.push(flag.to_string());
+                    invocation.details.insert(name.to_string(), json!(true));
                 }
-                "cargo_test" => {
-                    eprintln!("🔧 Executing cargo_test");
-                    let code = get_code_arg(&request, "cargo_test")?;
-                    validate_rust_code(code)?;
-                    let result =
-                        run_rust_tool(code, &["test"], Some(Duration::from_secs(60))).await?;
-                    let json_result = json!({
-                        "status": result.status,
-                        "success": result.status == 0,
-                        "stdout": result.stdout,
-                        "stderr": result.stderr,
-                        "duration_ms": result.duration_ms
-                    });
-                    let persist = Self::get_persist_flag(&request);
-                    if let Err(e) = self.store_analysis_with_errors("cargo_test", &result, persist)
-                    {
-                        eprintln!("⚠️  Failed to store analysis: {}", e);
-                    }
-                    Ok(CallToolResult {
-                        content: vec![rmcp::model::Content::text(json_result.to_string())],
-                        structured_content: None,
-                        meta: None,
-                        is_error: Some(result.status != 0),
-                    })
+            }
+            if let Some(edges) = &options.edges {
+                if !edges.split(',').all(|kind| TREE_EDGE_KINDS.contains(&kind)) {
+                    return Err(McpError::invalid_params(
+                        format!(
+                            "edges must be a comma-separated list of {}",
+                            TREE_EDGE_KINDS.join(", ")
+                        ),
+                        None,
+                    ));
                 }
-                "cargo_build" => {
-                    eprintln!("🔧 Executing cargo_build");
-                    let code = get_code_arg(&request, "cargo_build")?;
-                    validate_rust_code(code)?;
-                    let result =
-                        run_rust_tool(code, &["build"], Some(Duration::from_secs(60))).await?;
-                    let json_result = json!({
-                        "status": result.status,
-                        "success": result.status == 0,
-                        "stdout": result.stdout,
-                        "stderr": result.stderr,
-                        "duration_ms": result.duration_ms
-                    });
-                    let persist = Self::get_persist_flag(&request);
-                    if let Err(e) = self.store_analysis_with_errors("cargo_build", &result, persist)
-                    {
-                        eprintln!("⚠️  Failed to store analysis: {}", e);
-                    }
-                    Ok(CallToolResult {
-                        content: vec![rmcp::model::Content::text(json_result.to_string())],
-                        structured_content: None,
-                        meta: None,
-                        is_error: Some(result.status != 0),
-                    })
+                invocation.args.push("--edges".to_string());
+                invocation.args.push(edges.clone());
+                invocation.details.insert("edges".to_string(), json!(edges));
+            }
+            if options.format == args::TreeFormat::Json {
+                invocation.args.push("--prefix".to_string());
+                invocation.args.push("depth".to_string());
+                invocation
+                    .details
+                    .insert("format".to_string(), json!(options.format));
+            }
+            set_dependencies(&mut invocation, options.dependencies.as_ref())?;
+        }
+        if tool == "cargo_deps_report" {
+            set_dependencies(&mut invocation, options.dependencies.as_ref())?;
+        }
+        if tool == "cargo_update" {
+            // The answer changes as the index does
+            invocation.no_cache = true;
+            if options.cargo_toml.is_some() && options.dependencies.is_some() {
+                return Err(McpError::invalid_params(
+                    "pass either cargo_toml or dependencies, not both",
+                    None,
+                ));
+            }
+            set_dependencies(&mut invocation, options.dependencies.as_ref())?;
+            if let Some(manifest) = &options.cargo_toml {
+                invocation
+                    .files
+                    .push((PathBuf::from("Cargo.toml"), manifest.clone()));
+                invocation
+                    .details
+                    .insert("cargo_toml".to_string(), json!(manifest));
+            }
+            if let Some(lockfile) = &options.cargo_lock {
+                invocation
+                    .files
+                    .push((PathBuf::from("Cargo.lock"), lockfile.clone()));
+                invocation
+                    .details
+                    .insert("cargo_lock".to_string(), json!(true));
+            }
+            if options.dry_run {
+                invocation.args.push("--dry-run".to_string());
+            }
+            invocation
+                .details
+                .insert("dry_run".to_string(), json!(options.dry_run));
+            if let Some(package) = &options.package {
+                if !is_package_spec(package) {
+                    return Err(McpError::invalid_params(
+                        "package must be a package spec such as serde or serde@1.0.100",
+                        None,
+                    ));
                 }
-                "cargo_search" => {
-                    eprintln!("🔧 Executing cargo_search");
-                    let query = request
-                        .arguments
-                        .as_ref()
-                        .and_then(|args| args.get("query"))
-                        .and_then(|v| v.as_str())
-                        .ok_or_else(|| McpError::invalid_params("query is required", None))?;
-
-                    let output = StdCommand::new("cargo")
-                        .args(["search", query])
-                        .output()
-                        .map_err(|e| {
-                            McpError::internal_error(
-                                format!("Failed to run cargo search: {}", e),
-                                None,
-                            )
-                        })?;
-
-                    let results = String::from_utf8_lossy(&output.stdout).to_string();
-                    let stderr = String::from_utf8_lossy(&output.stderr).to_string();
+                invocation.args.push("-p".to_string());
+                invocation.args.push(package.clone());
+                invocation
+                    .details
+                    .insert("package".to_string(), json!(package));
+            }
+            // Only the local copy of the index is consulted, so newer
+            // releases may be missing
+            if self.config.offline {
+                invocation
+                    .details
+                    .insert("offline".to_string(), json!(true));
+            }
+        }
+        if tool == "cargo_coverage" {
+            if options.functions {
+                invocation
+                    .details
+                    .insert("functions".to_string(), json!(true));
+            } else {
+                invocation.args.push("--summary-only".to_string());
+            }
+            if let Some(threshold) = options.fail_under {
+                if !(0.0..=100.0).contains(&threshold) {
+                    return Err(McpError::invalid_params(
+                        "fail_under must be a line coverage percentage between 0 and 100",
+                        None,
+                    ));
+                }
+                // cargo llvm-cov exits non-zero below the threshold
+                invocation.args.push("--fail-under-lines".to_string());
+                invocation.args.push(threshold.to_string());
+                invocation
+                    .details
+                    .insert("fail_under".to_string(), json!(threshold));
+            }
+        }
+        if tool == "cargo_doc" && options.save_docs {
+            invocation.save_docs = true;
+            invocation
+                .details
+                .insert("save_docs".to_string(), json!(true));
+        }
+        if tool == "cargo_doc"
+            && let Some(missing_docs) = options.missing_docs
+        {
+            // missing_docs only sees items a library exports
+            invocation.library = true;
+            invocation.env.push((
+                "RUSTDOCFLAGS".to_string(),
+                format!("{} missing_docs", missing_docs.flag()),
+            ));
+            invocation
+                .details
+                .insert("missing_docs".to_string(), json!(missing_docs));
+        }
+        if PROJECT_FILE_TOOLS.contains(&tool) {
+            set_project_files(&mut invocation, options)?;
+        }
+        if options.allow_build_scripts {
+            invocation.allow_build_scripts = true;
+            invocation
+                .details
+                .insert("allow_build_scripts".to_string(), json!(true));
+        }
+        Ok(invocation)
+    }
 
-                    let json_result = json!({
-                        "query": query,
-                        "results": results,
-                        "stderr": stderr,
-                        "success": output.status.success()
-                    });
+    /// Answer a `rust_analyzer` action with the session's rust-analyzer
+    async fn analyze_with_lsp(
+        &self,
+        options: &args::Options,
+        args: &args::RustAnalyzerArgs,
+    ) -> Result<CallToolResult, McpError> {
+        let code = args.code.as_str();
+        let action = args.action;
+        let started = Instant::now();
+        let query = lsp::Query {
+            action,
+            code,
+            position: position(args.line, args.column)?,
+            limit: args.limit.get() as usize,
+        };
+        let mut json_result = self
+            .lsp
+            .run(
+                args.session.as_deref().unwrap_or(DEFAULT_LSP_SESSION),
+                query,
+            )
+            .await?;
+        let duration_ms = started.elapsed().as_millis();
+        let diagnostics = json_result["diagnostics"]
+            .as_array()
+            .cloned()
+            .unwrap_or_default();
+        let success = !diagnostics.iter().any(|d| d["severity"] == "error");
+        json_result["action"] = json!(action.name());
+        json_result["success"] = json!(success);
+        json_result["duration_ms"] = json!(duration_ms);
 
-                    Ok(CallToolResult {
-                        content: vec![rmcp::model::Content::text(json_result.to_string())],
-                        structured_content: None,
-                        meta: None,
-                        is_error: Some(!output.status.success()),
-                    })
-                }
-                "cargo_tree" => {
-                    eprintln!("🔧 Executing cargo_tree");
-                    let code = get_code_arg(&request, "cargo_tree")?;
-                    validate_rust_code(code)?;
-                    let result =
-                        run_rust_tool(code, &["tree"], Some(Duration::from_secs(30))).await?;
-                    let json_result = json!({
-                        "status": result.status,
-                        "success": result.status == 0,
-                        "stdout": result.stdout,
-                        "stderr": result.stderr,
-                        "duration_ms": result.duration_ms
-                    });
-                    let persist = Self::get_persist_flag(&request);
-                    if let Err(e) = self.store_analysis_with_errors("cargo_tree", &result, persist)
-                    {
-                        eprintln!("⚠️  Failed to store analysis: {}", e);
+        let result = ExecResult {
+            stdout: json_result.to_string(),
+            stderr: String::new(),
+            status: if success { 0 } else { 1 },
+            duration_ms,
+            artifact: None,
+            docs_path: None,
+            project_path: None,
+            crate_source: None,
+            cached: false,
+            timed_out: false,
+            resource_exceeded: None,
+            build_code: Vec::new(),
+        };
+        if let Some(analysis_id) = self.persist_analysis(
+            &mut json_result,
+            options,
+            "rust_analyzer",
+            code,
+            &result,
+            &Invocation::default(),
+        ) && let Some(db) = &self.db
+            && let Err(e) = db.run(|db| {
+                db.transaction(|db| {
+                    for diagnostic in &diagnostics {
+                        let severity = match diagnostic["severity"].as_str() {
+                            Some("information") => "note",
+                            Some("hint") => "help",
+                            Some("warning") => "warning",
+                            _ => "error",
+                        };
+                        let line = diagnostic["range"]["start"]["line"]
+                            .as_i64()
+                            .map(|line| line as i32);
+                        let stored = db.store_error(
+                            analysis_id,
+                            diagnostic["code"].as_str(),
+                            Some(severity),
+                            diagnostic["message"].as_str().unwrap_or_default(),
+                            Some("src/main.rs"),
+                            line,
+                            None,
+                        );
+                        tolerate(stored, "store error")?;
                     }
-                    Ok(CallToolResult {
-                        content: vec![rmcp::model::Content::text(json_result.to_string())],
-                        structured_content: None,
-                        meta: None,
-                        is_error: Some(result.status != 0),
-                    })
-                }
-                "cargo_doc" => {
-                    eprintln!("🔧 Executing cargo_doc");
-                    let code = get_code_arg(&request, "cargo_doc")?;
-                    validate_rust_code(code)?;
-                    let result =
-                        run_rust_tool(code, &["doc"], Some(Duration::from_secs(60))).await?;
-                    let json_result = json!({
-                        "status": result.status,
-                        "success": result.status == 0,
-                        "stdout": result.stdout,
-                        "stderr": result.stderr,
-                        "duration_ms": result.duration_ms
-                    });
-                    let persist = Self::get_persist_flag(&request);
-                    if let Err(e) = self.store_analysis_with_errors("cargo_doc", &result, persist) {
-                        eprintln!("⚠️  Failed to store analysis: {}", e);
-                    }
-                    Ok(CallToolResult {
-                        content: vec![rmcp::model::Content::text(json_result.to_string())],
-                        structured_content: None,
-                        meta: None,
-                        is_error: Some(result.status != 0),
-                    })
-                }
-                "rust_analyzer" => {
-                    eprintln!("🔧 Executing rust_analyzer");
-                    let code = get_code_arg(&request, "rust_analyzer")?;
-                    validate_rust_code(code)?;
-                    // rust-analyzer check
-                    let result = run_rust_tool(
-                        code,
-                        &["check", "--message-format=json"],
-                        Some(Duration::from_secs(30)),
+                    Ok(())
+                })
+            })
+        {
+            warn!("Failed to store errors of analysis {}: {}", analysis_id, e);
+        }
+
+        Ok(CallToolResult {
+            content: vec![rmcp::model::Content::text(json_result.to_string())],
+            structured_content: None,
+            meta: None,
+            is_error: Some(!success),
+        })
+    }
+
+    /// Infer the type of an expression, or of what sits at a position: from
+    /// rust-analyzer's hover when it names one, otherwise by type-checking the
+    /// code with the expression assigned to `()` and reading the mismatch
+    async fn type_of(
+        &self,
+        options: &args::Options,
+        args: &args::RustTypeOfArgs,
+    ) -> Result<CallToolResult, McpError> {
+        let code = args.code.as_str();
+        let expression = args
+            .expression
+            .as_deref()
+            .filter(|expression| !expression.trim().is_empty());
+        let line = args.line.map(NonZeroU32::get);
+        let position = match expression {
+            Some(_) => None,
+            None => Some(position(args.line, args.column)?.ok_or_else(|| {
+                McpError::invalid_params("expression or line and column is required", None)
+            })?),
+        };
+        let started = Instant::now();
+
+        if let Some(position) = position
+            && self
+                .requirements
+                .version(&requirements::RUST_ANALYZER)
+                .is_some()
+        {
+            let query = lsp::Query {
+                action: lsp::Action::Hover,
+                code,
+                position: Some(position),
+                limit: 0,
+            };
+            let hover = self
+                .lsp
+                .run(
+                    args.session.as_deref().unwrap_or(DEFAULT_LSP_SESSION),
+                    query,
+                )
+                .await?;
+            let contents = hover["hover"]["contents"].as_str().unwrap_or_default();
+            if let Some(inferred) = type_of::from_hover(contents) {
+                let json_result = json!({
+                    "success": true,
+                    "type": inferred.ty,
+                    "approximate": inferred.approximate,
+                    "method": "rust_analyzer",
+                    "hover": hover["hover"],
+                    "duration_ms": started.elapsed().as_millis()
+                });
+                return Ok(CallToolResult {
+                    content: vec![rmcp::model::Content::text(json_result.to_string())],
+                    structured_content: None,
+                    meta: None,
+                    is_error: Some(false),
+                });
+            }
+        }
+
+        let expression = match (expression, position) {
+            (Some(expression), _) => expression.to_string(),
+            (None, Some(position)) => type_of::identifier_at(code, position.line, position.column)
+                .ok_or_else(|| {
+                    McpError::invalid_params(
+                        format!(
+                            "no identifier at line {}, column {}; pass an expression",
+                            position.line, position.column
+                        ),
+                        None,
                     )
-                    .await?;
-                    let json_result = json!({
-                        "status": result.status,
-                        "success": result.status == 0,
-                        "stdout": result.stdout,
-                        "stderr": result.stderr,
-                        "duration_ms": result.duration_ms
+                })?,
+            (None, None) => unreachable!("position is required without an expression"),
+        };
+        let (probed, probe_line) = type_of::probe(code, &expression, line)
+            .map_err(|e| McpError::invalid_params(e, None))?;
+        validate_rust_code(&probed)?;
+        let invocation = self.build_invocation("cargo_check", options)?;
+        let result = self
+            .run_cargo_tool(&probed, "cargo_check", &invocation)
+            .await?;
+        let diagnostics = diagnostics::from_json(&result.stdout);
+        let at_probe = diagnostics.iter().find(|d| {
+            d.level == "error"
+                && d.file.as_deref() == Some("src/main.rs")
+                && d.line == Some(probe_line)
+        });
+        let inferred = match at_probe {
+            Some(diagnostic) => type_of::from_mismatch(diagnostic),
+            // Only a unit expression assigns to `()` without complaint
+            None if result.status == 0 => Some(type_of::Inferred {
+                ty: "()".to_string(),
+                approximate: false,
+            }),
+            None => None,
+        };
+        let json_result = match inferred {
+            Some(inferred) => json!({
+                "success": true,
+                "type": inferred.ty,
+                "approximate": inferred.approximate,
+                "method": "type_error",
+                "expression": expression,
+                "diagnostic": at_probe,
+                "duration_ms": started.elapsed().as_millis()
+            }),
+            None => json!({
+                "success": false,
+                "error": if at_probe.is_some() {
+                    "the expression does not type-check where it was inserted"
+                } else {
+                    "the code has errors that stop type checking"
+                },
+                "method": "type_error",
+                "expression": expression,
+                "diagnostics": diagnostics,
+                "duration_ms": started.elapsed().as_millis()
+            }),
+        };
+        let success = json_result["success"] == true;
+        Ok(CallToolResult {
+            content: vec![rmcp::model::Content::text(json_result.to_string())],
+            structured_content: None,
+            meta: None,
+            is_error: Some(!success),
+        })
+    }
+
+    /// Errors in a run's output: JSON diagnostics on stdout for tools run
+    /// with `--message-format=json`, plus rendered ones on stderr
+    fn error_infos(stdout: &str, stderr: &str) -> Vec<ErrorInfo> {
+        diagnostics::from_json(stdout)
+            .iter()
+            .map(ErrorInfo::from)
+            .chain(stderr.lines().filter_map(Self::parse_error_line))
+            .collect()
+    }
+
+    /// Parse output into unsaved error records, in the shape stored by
+    /// `parse_and_store_errors`. Records carry ids of 0 and an empty timestamp.
+    fn parse_error_records(tool: &str, stdout: &str, stderr: &str) -> Vec<ErrorRecord> {
+        Self::error_infos(stdout, stderr)
+            .into_iter()
+            .map(|info| ErrorRecord {
+                id: 0,
+                error_code: info.code,
+                severity: info.severity.map(str::to_string),
+                message: info.message,
+                file: info.file,
+                line: info.line,
+                suggestion: info.suggestion,
+                timestamp: String::new(),
+                tool: tool.to_string(),
+                analysis_id: 0,
+                code_hash: None,
+                session: None,
+                features: None,
+                batch_id: None,
+                rustc_version: None,
+            })
+            .collect()
+    }
+
+    /// Parse and store errors from a run's output
+    fn parse_and_store_errors(
+        db: &Database,
+        analysis_id: i64,
+        stdout: &str,
+        stderr: &str,
+    ) -> Result<()> {
+        let mut error_count = 0;
+
+        // Parse Rust compiler errors and warnings
+        for error_info in Self::error_infos(stdout, stderr) {
+            let stored = db.store_error(
+                analysis_id,
+                error_info.code.as_deref(),
+                error_info.severity,
+                &error_info.message,
+                error_info.file.as_deref(),
+                error_info.line,
+                error_info.suggestion.as_deref(),
+            );
+            if tolerate(stored, "store error")?.is_some() {
+                error_count += 1;
+            }
+        }
+
+        if error_count > 0 {
+            debug!(
+                "Stored {} errors from analysis {}",
+                error_count, analysis_id
+            );
+        }
+        Ok(())
+    }
+
+    /// Enhanced error parsing that handles multiple error patterns
+    fn parse_error_line(line: &str) -> Option<ErrorInfo> {
+        let line = line.trim();
+
+        // Pattern 1: error[E0308]: message
+        if let Some(captures) = Self::extract_error_pattern(line, r"error\[([^\]]+)\]:\s*(.+)") {
+            return Some(ErrorInfo {
+                code: Some(captures.0),
+                severity: Some("error"),
+                message: captures.1,
+                file: None,
+                line: None,
+                suggestion: None,
+            });
+        }
+
+        // Pattern 2: warning: message
+        if let Some(captures) = Self::extract_error_pattern(line, r"warning:\s*(.+)") {
+            return Some(ErrorInfo {
+                code: None,
+                severity: Some("warning"),
+                message: captures.1,
+                file: None,
+                line: None,
+                suggestion: None,
+            });
+        }
+
+        // Pattern 3: --> file:line:col (file path indicators)
+        if line.contains(" --> ") && line.contains(':') {
+            let parts: Vec<&str> = line.split(" --> ").collect();
+            if parts.len() == 2 {
+                let location = parts[1];
+                if let Some(file_info) = output::file_location(location) {
+                    return Some(ErrorInfo {
+                        code: None,
+                        severity: None,
+                        message: format!("Error at {}", location),
+                        file: Some(file_info.0),
+                        line: file_info.1,
+                        suggestion: None,
                     });
-                    let persist = Self::get_persist_flag(&request);
-                    if let Err(e) =
-                        self.store_analysis_with_errors("rust_analyzer", &result, persist)
-                    {
-                        eprintln!("⚠️  Failed to store analysis: {}", e);
-                    }
-                    Ok(CallToolResult {
-                        content: vec![rmcp::model::Content::text(json_result.to_string())],
-                        structured_content: None,
-                        meta: None,
-                        is_error: Some(result.status != 0),
-                    })
                 }
-                "cargo_history" => {
-                    eprintln!("🔧 Executing cargo_history");
-                    let error_code = request
-                        .arguments
-                        .as_ref()
-                        .and_then(|args| args.get("error_code"))
-                        .and_then(|v| v.as_str());
-
-                    let limit = request
-                        .arguments
-                        .as_ref()
-                        .and_then(|args| args.get("limit"))
-                        .and_then(|v| v.as_u64())
-                        .unwrap_or(10) as usize;
-
-                    let Some(ref db_arc) = self.db else {
-                        return Err(McpError::internal_error("Database not available", None));
-                    };
+            }
+        }
+
+        // Pattern 4: help: suggestion
+        if line.starts_with("help:") {
+            return Some(ErrorInfo {
+                code: None,
+                severity: Some("help"),
+                message: "Help".to_string(),
+                file: None,
+                line: None,
+                suggestion: Some(
+                    line.strip_prefix("help:")
+                        .unwrap_or(line)
+                        .trim()
+                        .to_string(),
+                ),
+            });
+        }
+
+        None
+    }
+
+    /// Extract error code and message using regex-like pattern matching
+    fn extract_error_pattern(line: &str, pattern: &str) -> Option<(String, String)> {
+        // Simple pattern matching for error[CODE]: message
+        if pattern.contains(r"error\[([^\]]+)\]")
+            && let Some(error_start) = line.find("error[")
+        {
+            let after_error = &line[error_start..];
+            if let Some(bracket_end) = after_error.find(']') {
+                let code = after_error[6..bracket_end].to_string(); // Skip "error["
+                if let Some(colon_pos) = after_error.find(": ") {
+                    let message = after_error[colon_pos + 2..].trim().to_string();
+                    return Some((code, message));
+                }
+            }
+        }
+
+        // Simple pattern matching for warning: message
+        if pattern.contains(r"warning:\s*")
+            && let Some(warning_pos) = line.find("warning:")
+        {
+            let message = line[warning_pos + 8..].trim().to_string();
+            if !message.is_empty() {
+                // Warnings carry no code
+                return Some((String::new(), message));
+            }
+        }
+
+        None
+    }
+
+    /// Store undocumented items from a `missing_docs` run as todos
+    fn store_missing_doc_todos(db: &Database, stderr: &str) -> Result<()> {
+        let mut todo_count = 0;
+        for item in output::missing_docs(stderr) {
+            let description = format!("missing documentation for {}: {}", item.kind, item.item);
+            let stored = db.store_todo(
+                "missing_docs",
+                &description,
+                Some(&item.file),
+                Some(item.line),
+            );
+            if tolerate(stored, "store missing_docs todo")? == Some(true) {
+                todo_count += 1;
+            }
+        }
+        if todo_count > 0 {
+            debug!("Stored {} missing_docs todos", todo_count);
+        }
+        Ok(())
+    }
+
+    /// Store failed doc test examples as errors at their source line
+    fn store_doc_test_failures(db: &Database, analysis_id: i64, stdout: &str) -> Result<()> {
+        for failure in output::doc_test_failures(stdout) {
+            let stored = db.store_error(
+                analysis_id,
+                Some("DOCTEST"),
+                Some("error"),
+                &format!("doc test for {} failed: {}", failure.item, failure.reason),
+                Some(&failure.file),
+                Some(failure.line),
+                None,
+            );
+            tolerate(stored, "store doc test failure")?;
+        }
+        Ok(())
+    }
+
+    /// Store each reported advisory as an error keyed by its RustSec id,
+    /// plus a todo to upgrade the affected package; unmaintained, unsound
+    /// and yanked crates are stored as warnings
+    /// Store each lockfile change `cargo update` reported as a note, e.g.
+    /// "serde 1.0.100 -> 1.0.210"
+    fn store_lockfile_updates(db: &Database, analysis_id: i64, stderr: &str) -> Result<()> {
+        for update in output::lockfile_updates(stderr) {
+            let message = match (&update.from, &update.to) {
+                (Some(from), Some(to)) => format!("{} {} -> {}", update.name, from, to),
+                (None, Some(to)) => format!("{} added at {}", update.name, to),
+                (Some(from), None) => format!("{} {} removed", update.name, from),
+                (None, None) => continue,
+            };
+            let suggestion = match update.compatible {
+                Some(false) => {
+                    Some("semver-incompatible: check the changelog for breaking changes")
+                }
+                _ => None,
+            };
+            let stored = db.store_error(
+                analysis_id,
+                None,
+                Some("note"),
+                &message,
+                Some("Cargo.lock"),
+                None,
+                suggestion,
+            );
+            tolerate(stored, "store lockfile update")?;
+        }
+        Ok(())
+    }
+
+    fn store_vulnerabilities(db: &Database, analysis_id: i64, stdout: &str) -> Result<()> {
+        let mut todo_count = 0;
+        for vulnerability in audit::vulnerabilities(stdout).unwrap_or_default() {
+            let upgrade = if vulnerability.patched_versions.is_empty() {
+                "no patched version available".to_string()
+            } else {
+                format!("upgrade to {}", vulnerability.patched_versions.join(" or "))
+            };
+            let stored = db.store_error(
+                analysis_id,
+                Some(&vulnerability.advisory_id),
+                Some("error"),
+                &format!(
+                    "{} {}: {}",
+                    vulnerability.package, vulnerability.version, vulnerability.title
+                ),
+                None,
+                None,
+                Some(&upgrade),
+            );
+            tolerate(stored, "store vulnerability")?;
+            let description = format!(
+                "{} {} ({}): {}",
+                vulnerability.package, vulnerability.version, vulnerability.advisory_id, upgrade
+            );
+            let stored = db.store_todo("cargo_audit", &description, None, None);
+            if tolerate(stored, "store cargo_audit todo")? == Some(true) {
+                todo_count += 1;
+            }
+        }
+        for warning in audit::warnings(stdout).unwrap_or_default() {
+            let title = warning.title.as_deref().unwrap_or("no advisory");
+            let stored = db.store_error(
+                analysis_id,
+                warning.advisory_id.as_deref(),
+                Some("warning"),
+                &format!(
+                    "{} {} is {}: {}",
+                    warning.package, warning.version, warning.kind, title
+                ),
+                None,
+                None,
+                None,
+            );
+            tolerate(stored, "store audit warning")?;
+        }
+        if todo_count > 0 {
+            debug!("Stored {} cargo_audit todos", todo_count);
+        }
+        Ok(())
+    }
+
+    /// Parse clippy warnings and store as todos, skipping lints on the
+    /// ignore list
+    fn parse_and_store_clippy_todos(db: &Database, stdout: &str) -> Result<()> {
+        let mut todo_count = 0;
+        let ignored: Vec<String> = tolerate(db.get_lint_ignores(), "read lint ignores")?
+            .unwrap_or_default()
+            .into_iter()
+            .map(|ignore| ignore.lint)
+            .collect();
+        let mut suppressed: std::collections::BTreeMap<&str, usize> =
+            std::collections::BTreeMap::new();
+
+        for todo in clippy_todos(stdout) {
+            if let Some(ignore) = ignored.iter().find(|ignore| **ignore == todo.lint) {
+                if todo.source == "clippy" {
+                    *suppressed.entry(ignore).or_default() += 1;
+                }
+                continue;
+            }
+            let stored = db.store_todo(
+                todo.source,
+                &todo.description,
+                todo.file.as_deref(),
+                todo.line,
+            );
+            let action = format!("store {} todo", todo.source);
+            if tolerate(stored, &action)? == Some(true) {
+                todo_count += 1;
+            }
+        }
+
+        if todo_count > 0 {
+            debug!("Stored {} clippy todos", todo_count);
+        }
+        for (lint, count) in suppressed {
+            let action = format!("count suppressed {} findings", lint);
+            tolerate(db.record_suppressed(lint, count), &action)?;
+        }
+        Ok(())
+    }
+
+    /// Store `rust_unused` findings as todos, returning how many were new
+    fn store_unused_todos(db: &Database, findings: &[unused::Finding]) -> Result<usize> {
+        let mut todo_count = 0;
+        for finding in findings {
+            // Name the binding when the message doesn't, or open todos for
+            // different `mut`s would be taken for duplicates
+            let description = match finding.names.as_slice() {
+                [name] if !finding.message.contains('`') => {
+                    format!("{}: `{}` ({})", finding.message, name, finding.kind)
+                }
+                _ => format!("{} ({})", finding.message, finding.kind),
+            };
+            let stored = db.store_todo(
+                "unused",
+                &description,
+                finding.file.as_deref(),
+                finding.line.map(|line| line as i32),
+            );
+            if tolerate(stored, "store unused todo")? == Some(true) {
+                todo_count += 1;
+            }
+        }
+        if todo_count > 0 {
+            debug!("Stored {} unused todos", todo_count);
+        }
+        Ok(todo_count)
+    }
+
+    /// Store the analysis when the call asked to `persist` and report how that
+    /// went as the response's `persistence` object. Returns the analysis id.
+    fn persist_analysis(
+        &self,
+        response: &mut Value,
+        options: &args::Options,
+        tool: &str,
+        code: &str,
+        result: &ExecResult,
+        invocation: &Invocation,
+    ) -> Option<i64> {
+        let stored = self.store_analysis_with_errors(
+            tool,
+            code,
+            result,
+            invocation,
+            options.persist,
+            options.session.as_deref(),
+        );
+        response["persistence"] = persistence_report(options.persist, &stored);
+        stored.ok().flatten()
+    }
+
+    /// Store analysis with improved error handling. Returns the new analysis
+    /// id, or `None` when persistence wasn't requested.
+    fn store_analysis_with_errors(
+        &self,
+        tool: &str,
+        code: &str,
+        result: &ExecResult,
+        invocation: &Invocation,
+        persist: bool,
+        session: Option<&str>,
+    ) -> Result<Option<i64>, String> {
+        // The server's mode comes first: started with --no-persist (or
+        // RUSTY_TOOLS_NO_PERSIST) it never writes, whatever the call asks.
+        // Otherwise the call's `persist` decides, and a database that failed
+        // to open is reported as an error.
+        if let PersistenceMode::Disabled = self.mode {
+            return if persist {
+                Err(PERSISTENCE_OFF.to_string())
+            } else {
+                Ok(None)
+            };
+        }
+        if !persist {
+            return Ok(None);
+        }
+
+        let Some(ref db_arc) = self.db else {
+            return Err(PERSISTENCE_DISABLED.to_string());
+        };
+
+        let mut json_result = json!({
+            "status": result.status,
+            "success": result.status == 0,
+            "stdout": result.stdout,
+            "stderr": result.stderr,
+            "duration_ms": result.duration_ms
+        });
+        if result.timed_out {
+            json_result["timed_out"] = json!(true);
+        }
+        if let Some(resource) = result.resource_exceeded {
+            json_result["resource_exceeded"] = json!(resource);
+        }
+        if !result.build_code.is_empty() {
+            json_result["build_code"] = json!(result.build_code);
+        }
+        if let Some(artifact) = &result.artifact {
+            json_result["artifact"] = artifact.stored();
+        }
+        if let Some(docs_path) = &result.docs_path {
+            json_result["docs_path"] = json!(docs_path.display().to_string());
+        }
+        if let Some(project_path) = &result.project_path {
+            json_result["project_path"] = json!(project_path.display().to_string());
+        }
+        // The exact version, so re-runs analyze the same release
+        if let Some(source) = &result.crate_source {
+            json_result["crate_source"] = json!(source);
+        }
+        // Crate sources have no snippet to keep
+        let code = if code.is_empty() {
+            None
+        } else if !self.config.store_code {
+            json_result["code_omitted"] = json!("disabled");
+            None
+        } else if code.len() > self.config.code_max_bytes {
+            json_result["code_omitted"] = json!("too_large");
+            None
+        } else {
+            Some(code)
+        };
+        cap_output(&mut json_result, invocation.max_output_bytes);
+        invocation.annotate(&mut json_result);
+        let file_path = result
+            .crate_source
+            .as_ref()
+            .map(|source| format!("{}@{}", source.name, source.version));
+
+        match db_arc.run(|db| {
+            db.store_analysis(
+                tool,
+                &json_result,
+                result.status == 0,
+                Some(result.duration_ms as i64),
+                file_path.as_deref(),
+                code,
+                session,
+            )
+        }) {
+            Ok(analysis_id) => {
+                // One transaction, so a retry after a broken connection
+                // starts over rather than storing rows twice
+                let stored = db_arc.run(|db| {
+                    db.transaction(|db| {
+                        if let Some(version) = invocation
+                            .details
+                            .get("rustc_version")
+                            .and_then(|v| v.as_str())
+                        {
+                            let recorded = db.set_analysis_toolchain(
+                                analysis_id,
+                                version,
+                                invocation.details.get("toolchain"),
+                            );
+                            tolerate(recorded, "record rustc version")?;
+                        }
+
+                        // Store errors from stderr
+                        Self::parse_and_store_errors(
+                            db,
+                            analysis_id,
+                            &result.stdout,
+                            &result.stderr,
+                        )?;
+
+                        // Doc test failures are reported on stdout
+                        if tool == "cargo_test" {
+                            Self::store_doc_test_failures(db, analysis_id, &result.stdout)?;
+                        }
+
+                        // Advisories become errors to track and todos to upgrade
+                        if tool == "cargo_audit" {
+                            Self::store_vulnerabilities(db, analysis_id, &result.stdout)?;
+                        }
+
+                        // Each bump is kept, to follow a dependency across sessions
+                        if tool == "cargo_update" {
+                            Self::store_lockfile_updates(db, analysis_id, &result.stderr)?;
+                        }
+
+                        // Store clippy-specific todos if this was a clippy run
+                        if tool == "cargo_clippy" {
+                            Self::parse_and_store_clippy_todos(db, &result.stdout)?;
+                        }
+
+                        // Undocumented items become todos in missing_docs mode
+                        if invocation.details.contains_key("missing_docs") {
+                            Self::store_missing_doc_todos(db, &result.stderr)?;
+                        }
+                        Ok(())
+                    })
+                });
+                if let Err(e) = stored {
+                    warn!("Failed to store details of analysis {}: {}", analysis_id, e);
+                }
+
+                Ok(Some(analysis_id))
+            }
+            Err(e) => Err(format!("Failed to store analysis: {}", e)),
+        }
+    }
+}
+
+impl ServerHandler for RustyToolsServer {
+    fn get_info(&self) -> ServerInfo {
+        let mut instructions =
+            "Rust development tools for formatting, linting, and analysis with persistence"
+                .to_string();
+        instructions.push_str(&format!(
+            ". Inputs are limited to {} bytes per code, baseline_code or files entry and {} bytes for all files together",
+            self.config.input_max_bytes, self.config.files_max_bytes
+        ));
+        let missing = self.requirements.known_missing();
+        if !missing.is_empty() {
+            instructions.push_str(&format!(
+                ". Unavailable in this environment: {} (see the doctor tool for install hints)",
+                missing.join(", ")
+            ));
+        }
+        ServerInfo {
+            instructions: Some(instructions),
+            capabilities: ServerCapabilities::builder()
+                .enable_tools()
+                .enable_prompts()
+                .enable_resources()
+                .build(),
+            ..Default::default()
+        }
+    }
+
+    #[allow(clippy::manual_async_fn)]
+    fn initialize(
+        &self,
+        _request: InitializeRequestParam,
+        _context: RequestContext<RoleServer>,
+    ) -> impl Future<Output = Result<InitializeResult, McpError>> + Send + '_ {
+        async move {
+            debug!("MCP client initialized");
+            Ok(self.get_info())
+        }
+    }
+
+    #[allow(clippy::manual_async_fn)]
+    fn list_tools(
+        &self,
+        _request: Option<PaginatedRequestParam>,
+        _context: RequestContext<RoleServer>,
+    ) -> impl Future<Output = Result<ListToolsResult, McpError>> + Send + '_ {
+        async move {
+            debug!("Listing tools");
+
+            let tools = self.tools.list();
+
+            Ok(ListToolsResult {
+                tools,
+                ..Default::default()
+            })
+        }
+    }
+
+    #[allow(clippy::manual_async_fn)]
+    fn list_resources(
+        &self,
+        _request: Option<PaginatedRequestParam>,
+        _context: RequestContext<RoleServer>,
+    ) -> impl Future<Output = Result<ListResourcesResult, McpError>> + Send + '_ {
+        async move {
+            Ok(ListResourcesResult {
+                resources: Vec::<Resource>::new(),
+                ..Default::default()
+            })
+        }
+    }
+
+    #[allow(clippy::manual_async_fn)]
+    fn list_resource_templates(
+        &self,
+        _request: Option<PaginatedRequestParam>,
+        _context: RequestContext<RoleServer>,
+    ) -> impl Future<Output = Result<ListResourceTemplatesResult, McpError>> + Send + '_ {
+        async move {
+            let explain = RawResourceTemplate {
+                uri_template: format!("{}{{error_code}}", EXPLAIN_SCHEME),
+                name: "rustc_explain".to_string(),
+                title: Some("rustc error code explanation".to_string()),
+                description: Some(
+                    "rustc --explain output for an error code, e.g. explain://E0308".to_string(),
+                ),
+                mime_type: Some("text/markdown".to_string()),
+            };
+            Ok(ListResourceTemplatesResult {
+                resource_templates: vec![explain.no_annotation()],
+                ..Default::default()
+            })
+        }
+    }
+
+    #[allow(clippy::manual_async_fn)]
+    fn read_resource(
+        &self,
+        request: ReadResourceRequestParam,
+        _context: RequestContext<RoleServer>,
+    ) -> impl Future<Output = Result<ReadResourceResult, McpError>> + Send + '_ {
+        async move {
+            debug!("Reading resource {}", request.uri);
+            self.read_explanation(&request.uri)
+        }
+    }
+
+    #[allow(clippy::manual_async_fn)]
+    fn list_prompts(
+        &self,
+        _request: Option<PaginatedRequestParam>,
+        _context: RequestContext<RoleServer>,
+    ) -> impl Future<Output = Result<ListPromptsResult, McpError>> + Send + '_ {
+        async move {
+            debug!("Listing prompts");
+            Ok(ListPromptsResult {
+                prompts: prompts::list(),
+                ..Default::default()
+            })
+        }
+    }
+
+    #[allow(clippy::manual_async_fn)]
+    fn get_prompt(
+        &self,
+        request: GetPromptRequestParam,
+        _context: RequestContext<RoleServer>,
+    ) -> impl Future<Output = Result<GetPromptResult, McpError>> + Send + '_ {
+        async move {
+            debug!("Rendering prompt: {}", request.name);
+            prompts::render(&request.name, request.arguments.as_ref())
+        }
+    }
+
+    #[allow(clippy::manual_async_fn)]
+    fn call_tool(
+        &self,
+        request: CallToolRequestParam,
+        context: RequestContext<RoleServer>,
+    ) -> impl Future<Output = Result<CallToolResult, McpError>> + Send + '_ {
+        let span = tracing::info_span!("call_tool", tool = %request.name);
+        let call = cancellation::scope(context.ct.clone(), self.dispatch(request));
+        progress::scope(Progress::from_context(&context), call).instrument(span)
+    }
+}
+
+/// Add `"coalesced": true` to the JSON of a result shared with an identical
+/// call
+fn mark_coalesced(result: &mut CallToolResult) {
+    for content in &mut result.content {
+        if let RawContent::Text(text) = &mut content.raw
+            && let Ok(Value::Object(mut response)) = serde_json::from_str::<Value>(&text.text)
+        {
+            response.insert("coalesced".to_string(), json!(true));
+            text.text = Value::Object(response).to_string();
+        }
+    }
+}
+
+/// Re-serialize the JSON text of a result indented, for `pretty_json`;
+/// plain text is left alone
+fn pretty_print(result: &mut CallToolResult) {
+    for content in &mut result.content {
+        if let RawContent::Text(text) = &mut content.raw
+            && let Ok(value) = serde_json::from_str::<Value>(&text.text)
+            && (value.is_object() || value.is_array())
+            && let Ok(pretty) = serde_json::to_string_pretty(&value)
+        {
+            text.text = pretty;
+        }
+    }
+}
+
+/// Why nothing was stored for a call that asked to `persist`
+const PERSISTENCE_DISABLED: &str = "persistence disabled";
+
+/// Why nothing was stored when the server runs with `--no-persist`
+const PERSISTENCE_OFF: &str = "persistence is turned off for this server (--no-persist)";
+
+/// The `persistence` object of a response: whether the call asked to
+/// `persist`, whether the analysis was stored and under which id, and why
+/// not. Failures are also logged, since the caller may ignore the field.
+fn persistence_report(requested: bool, stored: &Result<Option<i64>, String>) -> Value {
+    match stored {
+        // The operator's choice, not a failure
+        Err(e) if e == PERSISTENCE_OFF => debug!("Not storing analysis: {}", e),
+        Err(e) => warn!("Failed to store analysis: {}", e),
+        Ok(_) => {}
+    }
+    let analysis_id = stored.as_ref().ok().copied().flatten();
+    json!({
+        "requested": requested,
+        "stored": analysis_id.is_some(),
+        "analysis_id": analysis_id,
+        "error": stored.as_ref().err()
+    })
+}
+
+/// Build the response for a snippet tool run. The summary is always present;
+/// how much raw output accompanies it depends on `verbosity`.
+fn tool_response(
+    tool: &str,
+    code: &str,
+    result: &ExecResult,
+    verbosity: Verbosity,
+    invocation: &Invocation,
+) -> Value {
+    let mut response = json!({
+        "status": result.status,
+        "success": result.status == 0,
+        "duration_ms": result.duration_ms,
+        "summary": output::summarize(tool, code, result.status, &result.stdout, &result.stderr)
+    });
+    if result.cached {
+        response["cached"] = json!(true);
+    }
+    if result.timed_out {
+        response["timed_out"] = json!(true);
+        response["summary"] = json!(format!(
+            "timed out after {:.1}s",
+            result.duration_ms as f64 / 1000.0
+        ));
+    }
+    if let Some(resource) = result.resource_exceeded {
+        response["resource_exceeded"] = json!(resource);
+        response["summary"] = json!(format!("exceeded the {} limit", resource));
+    }
+    if !result.build_code.is_empty() {
+        response["build_code"] = json!(result.build_code);
+    }
+    let diagnostic_tool = DIAGNOSTIC_TOOLS.contains(&tool);
+    match verbosity {
+        Verbosity::Full => {
+            response["stdout"] = json!(result.stdout);
+            response["stderr"] = json!(result.stderr);
+        }
+        // JSON messages are replaced by `diagnostics`
+        Verbosity::Trimmed if diagnostic_tool => {
+            response["stderr"] = json!(output::trim_cargo_noise(&result.stderr));
+        }
+        Verbosity::Trimmed => {
+            response["stdout"] = json!(result.stdout);
+            response["stderr"] = json!(output::trim_cargo_noise(&result.stderr));
+        }
+        Verbosity::Summary => {}
+        Verbosity::Errors if diagnostic_tool => {}
+        Verbosity::Errors => {
+            let errors = output::error_blocks(&result.stderr);
+            if !errors.is_empty() {
+                response["stderr"] = json!(errors);
+            }
+        }
+    }
+    if diagnostic_tool && verbosity == Verbosity::Errors {
+        let mut diagnostics = diagnostics::from_json(&result.stdout);
+        diagnostics.retain(|diagnostic| diagnostic.severity() == Some("error"));
+        response["diagnostics"] = json!(diagnostics);
+    } else if diagnostic_tool && verbosity != Verbosity::Summary {
+        response["diagnostics"] = json!(diagnostics::from_json(&result.stdout));
+    }
+    if tool == "cargo_test"
+        && let Some(tests) = output::test_breakdown(&result.stdout, &result.stderr)
+    {
+        response["tests"] = json!(tests);
+        let test_results = output::test_outcomes(&result.stdout);
+        if !test_results.is_empty() {
+            response["test_results"] = json!(test_results);
+        }
+        let doc_test_failures = output::doc_test_failures(&result.stdout);
+        if !doc_test_failures.is_empty() {
+            response["doc_test_failures"] = json!(doc_test_failures);
+        }
+    }
+    if tool == "cargo_audit"
+        && let Some(vulnerabilities) = audit::vulnerabilities(&result.stdout)
+    {
+        response["vulnerabilities"] = json!(vulnerabilities);
+        response["warnings"] = json!(audit::warnings(&result.stdout).unwrap_or_default());
+    }
+    if let Some(artifact) = &result.artifact {
+        response["artifact"] = json!(artifact);
+    }
+    if let Some(docs_path) = &result.docs_path {
+        let crate_dir = match &result.crate_source {
+            Some(source) => source.name.replace('-', "_"),
+            None => "temp_project".to_string(),
+        };
+        response["docs"] = json!({
+            "path": docs_path.display().to_string(),
+            "index": docs_path.join(crate_dir).join("index.html").display().to_string()
+        });
+    }
+    if let Some(project_path) = &result.project_path {
+        response["project_path"] = json!(project_path.display().to_string());
+    }
+    if let Some(source) = &result.crate_source {
+        response["crate_source"] = json!(source);
+    }
+    cap_output(&mut response, invocation.max_output_bytes);
+    invocation.annotate(&mut response);
+    response
+}
+
+/// Truncate the `stdout` and `stderr` of a response or stored output to
+/// `max_bytes` each, flagging it `truncated` when either was cut
+fn cap_output(output: &mut Value, max_bytes: Option<usize>) {
+    let Some(max_bytes) = max_bytes else {
+        return;
+    };
+    for stream in ["stdout", "stderr"] {
+        if let Some(truncated) = output[stream]
+            .as_str()
+            .and_then(|text| output::truncate_output(text, max_bytes))
+        {
+            output[stream] = json!(truncated);
+            output["truncated"] = json!(true);
+        }
+    }
+}
+
+/// The snippet to run, validated, or an empty one for `crate_source` runs,
+/// `files` without `code` and pasted projects split into files
+fn get_snippet<'a>(
+    code: Option<&'a str>,
+    tool_name: &str,
+    invocation: &Invocation,
+) -> Result<&'a str, McpError> {
+    if invocation.crate_source.is_some()
+        || invocation.code_split
+        || (!invocation.files.is_empty() && code.is_none())
+    {
+        return Ok("");
+    }
+    let code = code.ok_or_else(|| {
+        McpError::invalid_params(format!("code parameter required for {}", tool_name), None)
+    })?;
+    validate_rust_code(code)?;
+    Ok(code)
+}
+
+/// The `line` and `column` arguments as a position, which takes both or neither
+fn position(
+    line: Option<NonZeroU32>,
+    column: Option<NonZeroU32>,
+) -> Result<Option<lsp::Position>, McpError> {
+    match (line, column) {
+        (Some(line), Some(column)) => Ok(Some(lsp::Position {
+            line: line.get(),
+            column: column.get(),
+        })),
+        (None, None) => Ok(None),
+        _ => Err(McpError::invalid_params(
+            "line and column must be given together",
+            None,
+        )),
+    }
+}
+
+/// Accept `YYYY-MM-DD` optionally followed by `[T ]HH:MM[:SS[.fff]]` and a
+/// `Z` or `±HH:MM` offset, i.e. the forms SQLite's `datetime()` understands
+fn is_iso8601_timestamp(ts: &str) -> bool {
+    fn digits(s: &str, n: usize) -> bool {
+        s.len() == n && s.bytes().all(|b| b.is_ascii_digit())
+    }
+
+    let (date, time) = match ts.find(['T', ' ']) {
+        Some(pos) => (&ts[..pos], Some(&ts[pos + 1..])),
+        None => (ts, None),
+    };
+    let date_parts: Vec<&str> = date.split('-').collect();
+    if date_parts.len() != 3
+        || !digits(date_parts[0], 4)
+        || !digits(date_parts[1], 2)
+        || !digits(date_parts[2], 2)
+    {
+        return false;
+    }
+
+    let Some(time) = time else {
+        return true;
+    };
+    let time = time.strip_suffix('Z').unwrap_or(time);
+    let time = match time.rfind(['+', '-']) {
+        Some(pos) => {
+            let offset: Vec<&str> = time[pos + 1..].split(':').collect();
+            if offset.len() != 2 || !digits(offset[0], 2) || !digits(offset[1], 2) {
+                return false;
+            }
+            &time[..pos]
+        }
+        None => time,
+    };
+    let time_parts: Vec<&str> = time.split(':').collect();
+    match time_parts.as_slice() {
+        [h, m] => digits(h, 2) && digits(m, 2),
+        [h, m, sec] => {
+            let (whole, frac) = sec.split_once('.').unwrap_or((sec, "0"));
+            digits(h, 2) && digits(m, 2) && digits(whole, 2) && digits(frac, frac.len().max(1))
+        }
+        _ => false,
+    }
+}
+
+fn validate_rust_code(code: &str) -> Result<(), McpError> {
+    if code.trim().is_empty() {
+        return Err(McpError::invalid_params("Code cannot be empty", None));
+    }
+
+    // Basic validation - check for potentially dangerous operations
+    let dangerous_patterns = ["std::process::Command", "std::fs::", "std::net::", "unsafe"];
+    for pattern in &dangerous_patterns {
+        if code.contains(pattern) {
+            return Err(McpError::invalid_params(
+                format!("Code contains potentially unsafe pattern: {}", pattern),
+                None,
+            ));
+        }
+    }
+
+    Ok(())
+}
+
+pub struct Database {
+    conn: Connection,
+    /// Whether the linked SQLite provides FTS5; searches fall back to LIKE otherwise
+    fts_enabled: bool,
+}
+
+impl Database {
+    pub fn new(mode: PersistenceMode) -> Result<Option<Self>> {
+        match mode {
+            PersistenceMode::Disabled => Ok(None),
+            PersistenceMode::Path(path) => {
+                let conn = Connection::open(&path)?;
+
+                // Create parent directory if it doesn't exist
+                if let Some(parent) = path.parent() {
+                    std::fs::create_dir_all(parent)?;
+                }
+
+                let mut db = Database {
+                    conn,
+                    fts_enabled: false,
+                };
+                db.init_schema()?;
+                db.fts_enabled = db.init_fts();
+                Ok(Some(db))
+            }
+        }
+    }
+
+    fn init_schema(&self) -> Result<()> {
+        // Create analyses table
+        self.conn.execute(
+            "CREATE TABLE IF NOT EXISTS analyses (
+                id INTEGER PRIMARY KEY AUTOINCREMENT,
+                timestamp DATETIME DEFAULT CURRENT_TIMESTAMP,
+                file_path TEXT,
+                tool TEXT NOT NULL,
+                full_output TEXT NOT NULL,
+                success BOOLEAN NOT NULL,
+                code TEXT,
+                code_hash TEXT,
+                session TEXT,
+                features TEXT,
+                batch_id TEXT,
+                duration_ms INTEGER
+            )",
+            [],
+        )?;
+
+        // Create errors table
+        self.conn.execute(
+            "CREATE TABLE IF NOT EXISTS errors (
+                id INTEGER PRIMARY KEY AUTOINCREMENT,
+                analysis_id INTEGER NOT NULL,
+                error_code TEXT,
+                severity TEXT,
+                message TEXT NOT NULL,
+                file TEXT,
+                line INTEGER,
+                suggestion TEXT,
+                FOREIGN KEY (analysis_id) REFERENCES analyses (id)
+            )",
+            [],
+        )?;
+
+        // Create todos table - fix column type issues
+        self.conn.execute(
+            "CREATE TABLE IF NOT EXISTS todos (
+                id INTEGER PRIMARY KEY AUTOINCREMENT,
+                created_at DATETIME DEFAULT CURRENT_TIMESTAMP,
+                source TEXT NOT NULL,
+                description TEXT NOT NULL,
+                file_path TEXT,
+                line_number INTEGER,
+                completed INTEGER DEFAULT 0
+            )",
+            [],
+        )?;
+
+        // Create fixes table
+        self.conn.execute(
+            "CREATE TABLE IF NOT EXISTS fixes (
+                id INTEGER PRIMARY KEY AUTOINCREMENT,
+                error_id INTEGER,
+                fix_applied TEXT NOT NULL,
+                timestamp DATETIME DEFAULT CURRENT_TIMESTAMP,
+                worked INTEGER,
+                FOREIGN KEY (error_id) REFERENCES errors (id)
+            )",
+            [],
+        )?;
+
+        // Add timestamp column to existing errors table if it doesn't exist
+        let _ = self.conn.execute(
+            "ALTER TABLE errors ADD COLUMN timestamp DATETIME DEFAULT CURRENT_TIMESTAMP",
+            [],
+        );
+
+        // Add severity column; warnings used to be stored under the pseudo
+        // error code WARNING
+        let _ = self
+            .conn
+            .execute("ALTER TABLE errors ADD COLUMN severity TEXT", []);
+        self.conn.execute(
+            "UPDATE errors SET severity = 'warning', error_code = NULL
+             WHERE error_code = 'WARNING'",
+            [],
+        )?;
+        self.conn.execute(
+            "UPDATE errors SET severity = 'error'
+             WHERE severity IS NULL
+               AND (error_code GLOB 'E[0-9]*' OR error_code = 'DOCTEST'
+                    OR error_code GLOB 'RUSTSEC-*')",
+            [],
+        )?;
+        self.conn.execute(
+            "UPDATE errors SET severity = 'help'
+             WHERE severity IS NULL AND error_code IS NULL AND message = 'Help'",
+            [],
+        )?;
+
+        // Add code column to existing analyses table if it doesn't exist
+        let _ = self
+            .conn
+            .execute("ALTER TABLE analyses ADD COLUMN code TEXT", []);
+        let _ = self
+            .conn
+            .execute("ALTER TABLE analyses ADD COLUMN code_hash TEXT", []);
+        self.conn.execute(
+            "CREATE INDEX IF NOT EXISTS idx_analyses_code_hash ON analyses (code_hash)",
+            [],
+        )?;
+        self.backfill_code_hashes()?;
+
+        // Submitted code, stored once per content hash
+        self.conn.execute(
+            "CREATE TABLE IF NOT EXISTS snippets (
+                hash TEXT PRIMARY KEY,
+                code TEXT NOT NULL,
+                size_bytes INTEGER NOT NULL,
+                first_seen DATETIME DEFAULT CURRENT_TIMESTAMP
+            )",
+            [],
+        )?;
+        // Move code stored inline on analyses into snippets
+        self.conn.execute(
+            "INSERT OR IGNORE INTO snippets (hash, code, size_bytes)
+             SELECT code_hash, code, length(CAST(code AS BLOB)) FROM analyses
+             WHERE code IS NOT NULL AND code_hash IS NOT NULL",
+            [],
+        )?;
+        self.conn.execute(
+            "UPDATE analyses SET code = NULL WHERE code IS NOT NULL AND code_hash IS NOT NULL",
+            [],
+        )?;
+
+        // Add session column to existing analyses table if it doesn't exist
+        let _ = self
+            .conn
+            .execute("ALTER TABLE analyses ADD COLUMN session TEXT", []);
+        self.conn.execute(
+            "CREATE INDEX IF NOT EXISTS idx_analyses_session ON analyses (session)",
+            [],
+        )?;
+
+        // Add features column (feature set of a cargo_feature_check run)
+        let _ = self
+            .conn
+            .execute("ALTER TABLE analyses ADD COLUMN features TEXT", []);
+
+        // Cached tool results, keyed by cache::cache_key; times are Unix millis
+        self.conn.execute(
+            "CREATE TABLE IF NOT EXISTS result_cache (
+                key TEXT PRIMARY KEY,
+                stdout TEXT NOT NULL,
+                stderr TEXT NOT NULL,
+                status INTEGER NOT NULL,
+                duration_ms INTEGER NOT NULL,
+                created_at INTEGER NOT NULL,
+                last_used INTEGER NOT NULL
+            )",
+            [],
+        )?;
+
+        // Add batch_id column (shared by the steps of one cargo_analyze or pipeline call)
+        let _ = self
+            .conn
+            .execute("ALTER TABLE analyses ADD COLUMN batch_id TEXT", []);
+        self.conn.execute(
+            "CREATE INDEX IF NOT EXISTS idx_analyses_batch_id ON analyses (batch_id)",
+            [],
+        )?;
+
+        // Add duration_ms column; earlier analyses keep theirs only in full_output
+        let _ = self
+            .conn
+            .execute("ALTER TABLE analyses ADD COLUMN duration_ms INTEGER", []);
+
+        // How full_output is stored: "gzip" for compressed JSON, NULL for
+        // plain JSON text as every analysis stored before compression
+        let _ = self
+            .conn
+            .execute("ALTER TABLE analyses ADD COLUMN output_encoding TEXT", []);
+
+        // `rustc --version` of the toolchain that ran the analysis
+        let _ = self
+            .conn
+            .execute("ALTER TABLE analyses ADD COLUMN rustc_version TEXT", []);
+
+        // `rustc --version --verbose` as JSON; earlier analyses have none
+        let _ = self
+            .conn
+            .execute("ALTER TABLE analyses ADD COLUMN toolchain TEXT", []);
+
+        // Clippy lints whose findings don't become todos; `suppressed` counts
+        // the findings skipped and open todos closed because of each
+        self.conn.execute(
+            "CREATE TABLE IF NOT EXISTS lint_ignores (
+                lint TEXT PRIMARY KEY,
+                created_at DATETIME DEFAULT CURRENT_TIMESTAMP,
+                expires_at DATETIME,
+                suppressed INTEGER NOT NULL DEFAULT 0
+            )",
+            [],
+        )?;
+        // The ignored lint that closed a todo, so unignoring can reopen it
+        let _ = self
+            .conn
+            .execute("ALTER TABLE todos ADD COLUMN suppressed_by TEXT", []);
+
+        // `rustc --explain` output, valid for the rustc version that gave it
+        self.conn.execute(
+            "CREATE TABLE IF NOT EXISTS explanations (
+                error_code TEXT PRIMARY KEY,
+                rustc_version TEXT NOT NULL,
+                explanation TEXT NOT NULL,
+                created_at DATETIME DEFAULT CURRENT_TIMESTAMP
+            )",
+            [],
+        )?;
+
+        Ok(())
+    }
+
+    /// Hash code stored before `code_hash` existed
+    fn backfill_code_hashes(&self) -> Result<()> {
+        let mut stmt = self.conn.prepare(
+            "SELECT id, code FROM analyses WHERE code IS NOT NULL AND code_hash IS NULL",
+        )?;
+        let rows = stmt
+            .query_map([], |row| {
+                Ok((row.get::<_, i64>(0)?, row.get::<_, String>(1)?))
+            })?
+            .collect::<rusqlite::Result<Vec<_>>>()?;
+        for (id, code) in rows {
+            self.conn.execute(
+                "UPDATE analyses SET code_hash = ?1 WHERE id = ?2",
+                rusqlite::params![code_hash(&code), id],
+            )?;
+        }
+        Ok(())
+    }
+
+    /// Create the full-text index over analysis output, backfilling it on
+    /// first creation. Returns false when FTS5 isn't compiled into SQLite.
+    fn init_fts(&self) -> bool {
+        let exists = self
+            .conn
+            .query_row(
+                "SELECT 1 FROM sqlite_master WHERE type = 'table' AND name = 'analyses_fts'",
+                [],
+                |_| Ok(()),
+            )
+            .is_ok();
+        if exists {
+            return true;
+        }
+
+        let created = self
+            .conn
+            .execute_batch(
+                "CREATE VIRTUAL TABLE analyses_fts USING fts5(body);
+                 CREATE TRIGGER IF NOT EXISTS analyses_fts_delete AFTER DELETE ON analyses
+                 BEGIN
+                    DELETE FROM analyses_fts WHERE rowid = old.id;
+                 END;",
+            )
+            .is_ok();
+        if !created {
+            warn!("FTS5 unavailable, search_output will use LIKE matching");
+            return false;
+        }
+
+        if let Err(e) = self.backfill_fts() {
+            warn!("Failed to index existing analyses: {}", e);
+        }
+        true
+    }
+
+    fn backfill_fts(&self) -> Result<()> {
+        let mut stmt = self
+            .conn
+            .prepare("SELECT id, full_output, output_encoding FROM analyses")?;
+        let rows = stmt.query_map([], |row| {
+            Ok((row.get::<_, i64>(0)?, stored_output(row, 1)?))
+        })?;
+        for row in rows {
+            let (id, full_output) = row?;
+            self.conn.execute(
+                "INSERT INTO analyses_fts (rowid, body) VALUES (?1, ?2)",
+                rusqlite::params![id, searchable_text(&full_output)],
+            )?;
+        }
+        Ok(())
+    }
+
+    /// Run `writes` in one transaction, committed only when they succeed, so
+    /// a retry after a failure doesn't repeat what went in before it
+    pub fn transaction<T>(&self, writes: impl FnOnce(&Database) -> Result<T>) -> Result<T> {
+        let tx = self.conn.unchecked_transaction()?;
+        let value = writes(self)?;
+        tx.commit()?;
+        Ok(value)
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    pub fn store_analysis(
+        &self,
+        tool: &str,
+        full_output: &Value,
+        success: bool,
+        duration_ms: Option<i64>,
+        file_path: Option<&str>,
+        code: Option<&str>,
+        session: Option<&str>,
+    ) -> Result<i64> {
+        use rusqlite::params;
+        let full_output_str = full_output.to_string();
+
+        let hash = code.map(code_hash);
+        if let (Some(code), Some(hash)) = (code, &hash) {
+            self.conn.execute(
+                "INSERT OR IGNORE INTO snippets (hash, code, size_bytes) VALUES (?1, ?2, ?3)",
+                params![hash, code, code.len()],
+            )?;
+        }
+        let (stored, encoding) = encode_output(&full_output_str);
+        self.conn.execute(
+            "INSERT INTO analyses (tool, full_output, output_encoding, success, duration_ms, file_path, code_hash, session)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8)",
+            params![tool, stored, encoding, success, duration_ms, file_path, hash, session],
+        )?;
+        let analysis_id = self.conn.last_insert_rowid();
+
+        if self.fts_enabled {
+            self.conn.execute(
+                "INSERT INTO analyses_fts (rowid, body) VALUES (?1, ?2)",
+                params![analysis_id, searchable_text(&full_output_str)],
+            )?;
+        }
+
+        Ok(analysis_id)
+    }
+
+    /// Record the `rustc --version` an analysis compiled with, and the whole
+    /// of `rustc --version --verbose` when known
+    pub fn set_analysis_toolchain(
+        &self,
+        analysis_id: i64,
+        version: &str,
+        toolchain: Option<&Value>,
+    ) -> Result<()> {
+        self.conn.execute(
+            "UPDATE analyses SET rustc_version = ?1, toolchain = ?2 WHERE id = ?3",
+            rusqlite::params![version, toolchain.map(Value::to_string), analysis_id],
+        )?;
+        Ok(())
+    }
+
+    /// Record the comma-joined feature set a `cargo_feature_check` analysis ran with
+    pub fn set_analysis_features(&self, analysis_id: i64, features: &str) -> Result<()> {
+        self.conn.execute(
+            "UPDATE analyses SET features = ?1 WHERE id = ?2",
+            rusqlite::params![features, analysis_id],
+        )?;
+        Ok(())
+    }
+
+    /// Link an analysis to the other steps of a `cargo_analyze` or `pipeline` call
+    pub fn set_analysis_batch(&self, analysis_id: i64, batch_id: &str) -> Result<()> {
+        self.conn.execute(
+            "UPDATE analyses SET batch_id = ?1 WHERE id = ?2",
+            rusqlite::params![batch_id, analysis_id],
+        )?;
+        Ok(())
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    pub fn store_error(
+        &self,
+        analysis_id: i64,
+        error_code: Option<&str>,
+        severity: Option<&str>,
+        message: &str,
+        file: Option<&str>,
+        line: Option<i32>,
+        suggestion: Option<&str>,
+    ) -> Result<()> {
+        use rusqlite::params;
+        self.conn.execute(
+            "INSERT INTO errors (analysis_id, error_code, severity, message, file, line, suggestion) VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7)",
+            params![
+                analysis_id,
+                error_code,
+                severity,
+                message,
+                file,
+                line,
+                suggestion
+            ]
+        )?;
+        Ok(())
+    }
+
+    /// Record an applied fix and whether the code compiled afterwards
+    pub fn store_fix(&self, error_id: Option<i64>, fix_applied: &str, worked: bool) -> Result<i64> {
+        use rusqlite::params;
+        self.conn.execute(
+            "INSERT INTO fixes (error_id, fix_applied, worked) VALUES (?1, ?2, ?3)",
+            params![error_id, fix_applied, worked],
+        )?;
+        Ok(self.conn.last_insert_rowid())
+    }
+
+    /// Store a todo unless an open todo with the same source and description
+    /// already exists. Returns `true` if a new row was inserted.
+    pub fn store_todo(
+        &self,
+        source: &str,
+        description: &str,
+        file_path: Option<&str>,
+        line_number: Option<i32>,
+    ) -> Result<bool> {
+        use rusqlite::params;
+        let inserted = self.conn.execute(
+            "INSERT INTO todos (source, description, file_path, line_number)
+             SELECT ?1, ?2, ?3, ?4
+             WHERE NOT EXISTS (
+                SELECT 1 FROM todos
+                WHERE source = ?1 AND description = ?2 AND completed = 0
+             )",
+            params![source, description, file_path, line_number],
+        )?;
+        Ok(inserted > 0)
+    }
+
+    pub fn get_error_history(&self, filter: &HistoryFilter) -> Result<Vec<ErrorRecord>> {
+        let (ts_expr, query) = self.history_query(filter);
+        let sql = format!(
+            "SELECT e.id, e.error_code, e.message, e.file, e.line, e.suggestion,
+                    {ts} as timestamp, a.tool, a.id, a.code_hash, a.session, a.features,
+                    a.batch_id, e.severity, a.rustc_version
+             FROM errors e
+             JOIN analyses a ON e.analysis_id = a.id{where_sql}
+             ORDER BY {ts} DESC, e.id DESC
+             LIMIT ? OFFSET ?",
+            ts = ts_expr,
+            where_sql = query.where_sql(),
+        );
+
+        let mut params = query.params;
+        params.push(SqlValue::Integer(filter.limit.unwrap_or(10) as i64));
+        params.push(SqlValue::Integer(filter.offset.unwrap_or(0) as i64));
+
+        let mut stmt = self.conn.prepare(&sql)?;
+        let error_iter =
+            stmt.query_map(rusqlite::params_from_iter(params), ErrorRecord::from_row)?;
+
+        let mut errors = Vec::new();
+        for error in error_iter {
+            errors.push(error?);
+        }
+        Ok(errors)
+    }
+
+    /// Count the errors matching `filter`, ignoring its limit and offset
+    pub fn count_error_history(&self, filter: &HistoryFilter) -> Result<usize> {
+        let (_, query) = self.history_query(filter);
+        let sql = format!(
+            "SELECT COUNT(*) FROM errors e
+             JOIN analyses a ON e.analysis_id = a.id{}",
+            query.where_sql()
+        );
+        let count: i64 =
+            self.conn
+                .query_row(&sql, rusqlite::params_from_iter(query.params), |row| {
+                    row.get(0)
+                })?;
+        Ok(count as usize)
+    }
+
+    /// Build the shared WHERE clause for history queries, returning the
+    /// timestamp expression to order by alongside it
+    fn history_query(&self, filter: &HistoryFilter) -> (&'static str, QueryBuilder) {
+        // Check if timestamp column exists in errors table
+        let has_timestamp = self
+            .conn
+            .prepare("SELECT timestamp FROM errors LIMIT 1")
+            .is_ok();
+        let ts_expr = if has_timestamp {
+            "COALESCE(e.timestamp, a.timestamp)"
+        } else {
+            "a.timestamp"
+        };
+
+        let mut query = QueryBuilder::default();
+        if let Some(code) = &filter.error_code {
+            query.push("e.error_code = ?", code.clone());
+        }
+        if let Some(severity) = &filter.severity {
+            query.push("e.severity = ?", severity.clone());
+        }
+        if let Some(tool) = &filter.tool {
+            query.push("a.tool = ?", tool.clone());
+        }
+        if let Some(success) = filter.success {
+            query.push("a.success = ?", success as i64);
+        }
+        if let Some(file) = &filter.file {
+            query.push("e.file LIKE '%' || ? || '%'", file.clone());
+        }
+        if let Some(session) = &filter.session {
+            query.push("a.session = ?", session.clone());
+        }
+        if let Some(features) = &filter.features {
+            query.push("a.features = ?", features.clone());
+        }
+        if let Some(batch_id) = &filter.batch_id {
+            query.push("a.batch_id = ?", batch_id.clone());
+        }
+        if let Some(since) = &filter.since {
+            query.push(
+                &format!("datetime({}) >= datetime(?)", ts_expr),
+                since.clone(),
+            );
+        }
+        if let Some(until) = &filter.until {
+            query.push(
+                &format!("datetime({}) <= datetime(?)", ts_expr),
+                until.clone(),
+            );
+        }
+        (ts_expr, query)
+    }
+
+    /// Todos, newest first; open ones only unless `show_completed`, and
+    /// only those from `source` (e.g. "clippy") when given
+    pub fn get_todos(&self, show_completed: bool, source: Option<&str>) -> Result<Vec<TodoRecord>> {
+        let mut sql = "SELECT id, source, description, file_path,
+                    CAST(line_number AS INTEGER) as line_number,
+                    completed, created_at, suppressed_by
+             FROM todos
+             WHERE (?1 IS NULL OR source = ?1)"
+            .to_string();
+        if !show_completed {
+            sql.push_str(" AND completed = 0");
+        }
+        sql.push_str(" ORDER BY created_at DESC");
+
+        let mut stmt = self.conn.prepare(&sql)?;
+        let todo_iter = stmt.query_map([source], |row| {
+            // Handle line_number more carefully to avoid type issues
+            let line_number: Option<i32> = match row.get::<_, Option<rusqlite::types::Value>>(4)? {
+                Some(rusqlite::types::Value::Integer(i)) => Some(i as i32),
+                Some(rusqlite::types::Value::Text(s)) => s.parse().ok(),
+                Some(rusqlite::types::Value::Null) | None => None,
+                _ => None,
+            };
+
+            Ok(TodoRecord {
+                id: row.get(0)?,
+                source: row.get(1)?,
+                description: row.get(2)?,
+                file_path: row.get::<_, Option<String>>(3)?,
+                line_number,
+                completed: row.get::<_, i32>(5)? != 0, // Convert INTEGER to bool
+                created_at: row.get(6)?,
+                suppressed_by: row.get(7)?,
+            })
+        })?;
+
+        let mut todos = Vec::new();
+        for todo in todo_iter {
+            todos.push(todo?);
+        }
+        Ok(todos)
+    }
+
+    /// Stop turning findings of the clippy `lint` into todos, until
+    /// `expires_at` when given, and close its open todos. Returns how many
+    /// todos were closed.
+    pub fn ignore_lint(&self, lint: &str, expires_at: Option<&str>) -> Result<usize> {
+        use rusqlite::params;
+        let tx = self.conn.unchecked_transaction()?;
+        tx.execute(
+            "INSERT INTO lint_ignores (lint, expires_at) VALUES (?1, ?2)
+             ON CONFLICT (lint) DO UPDATE SET expires_at = excluded.expires_at",
+            params![lint, expires_at],
+        )?;
+        // Clippy todos end in " (clippy::lint)"; compared as a suffix because
+        // LIKE would read the underscores in lint names as wildcards
+        let closed = tx.execute(
+            "UPDATE todos SET completed = 1, suppressed_by = ?1
+             WHERE completed = 0 AND source = 'clippy'
+               AND substr(description, -length(' (' || ?1 || ')')) = ' (' || ?1 || ')'",
+            params![lint],
+        )?;
+        tx.execute(
+            "UPDATE lint_ignores SET suppressed = suppressed + ?2 WHERE lint = ?1",
+            params![lint, closed],
+        )?;
+        tx.commit()?;
+        Ok(closed)
+    }
+
+    /// Lift an ignore and reopen the todos it closed. `None` when `lint`
+    /// wasn't ignored.
+    pub fn unignore_lint(&self, lint: &str) -> Result<Option<usize>> {
+        let tx = self.conn.unchecked_transaction()?;
+        if tx.execute("DELETE FROM lint_ignores WHERE lint = ?1", [lint])? == 0 {
+            return Ok(None);
+        }
+        let reopened = tx.execute(
+            "UPDATE todos SET completed = 0, suppressed_by = NULL WHERE suppressed_by = ?1",
+            [lint],
+        )?;
+        tx.commit()?;
+        Ok(Some(reopened))
+    }
+
+    /// Ignores that haven't expired, oldest first
+    pub fn get_lint_ignores(&self) -> Result<Vec<LintIgnore>> {
+        let mut stmt = self.conn.prepare(
+            "SELECT lint, created_at, expires_at, suppressed FROM lint_ignores
+             WHERE expires_at IS NULL OR datetime(expires_at) > datetime('now')
+             ORDER BY created_at, lint",
+        )?;
+        let ignores = stmt
+            .query_map([], |row| {
+                Ok(LintIgnore {
+                    lint: row.get(0)?,
+                    created_at: row.get(1)?,
+                    expires_at: row.get(2)?,
+                    suppressed: row.get::<_, i64>(3)? as usize,
+                })
+            })?
+            .collect::<rusqlite::Result<_>>()?;
+        Ok(ignores)
+    }
+
+    /// Count findings of an ignored lint that were not stored as todos
+    pub fn record_suppressed(&self, lint: &str, count: usize) -> Result<()> {
+        use rusqlite::params;
+        self.conn.execute(
+            "UPDATE lint_ignores SET suppressed = suppressed + ?2 WHERE lint = ?1",
+            params![lint, count],
+        )?;
+        Ok(())
+    }
+
+    /// Complete the open clippy and clippy_help todos whose description is
+    /// not among `current`, the `(source, description)` pairs a fresh clippy
+    /// run produced, and return them
+    pub fn reconcile_todos(&self, current: &[(&str, &str)]) -> Result<Vec<TodoRecord>> {
+        use rusqlite::params;
+        let tx = self.conn.unchecked_transaction()?;
+        let mut stale = Vec::new();
+        for source in ["clippy", "clippy_help"] {
+            stale.extend(
+                self.get_todos(false, Some(source))?
+                    .into_iter()
+                    .filter(|todo| {
+                        !current.contains(&(todo.source.as_str(), todo.description.as_str()))
+                    }),
+            );
+        }
+        for todo in &mut stale {
+            tx.execute(
+                "UPDATE todos SET completed = 1 WHERE id = ?1",
+                params![todo.id],
+            )?;
+            todo.completed = true;
+        }
+        tx.commit()?;
+        Ok(stale)
+    }
+
+    #[allow(dead_code)]
+    pub fn mark_todo_completed(&self, todo_id: i64) -> Result<()> {
+        use rusqlite::params;
+        self.conn.execute(
+            "UPDATE todos SET completed = 1 WHERE id = ?1",
+            params![todo_id],
+        )?;
+        Ok(())
+    }
+
+    /// Fetch the metadata row for a single analysis
+    pub fn get_analysis(&self, analysis_id: i64) -> Result<Option<AnalysisRecord>> {
+        use rusqlite::OptionalExtension;
+        let record = self
+            .conn
+            .query_row(
+                "SELECT id, timestamp, tool, success, file_path, session, rustc_version,
+                        toolchain
+                 FROM analyses WHERE id = ?1",
+                [analysis_id],
+                |row| {
+                    Ok(AnalysisRecord {
+                        id: row.get(0)?,
+                        timestamp: row.get(1)?,
+                        tool: row.get(2)?,
+                        success: row.get(3)?,
+                        file_path: row.get::<_, Option<String>>(4)?,
+                        session: row.get::<_, Option<String>>(5)?,
+                        rustc_version: row.get::<_, Option<String>>(6)?,
+                        toolchain: row
+                            .get::<_, Option<String>>(7)?
+                            .and_then(|json| serde_json::from_str(&json).ok()),
+                    })
+                },
+            )
+            .optional()?;
+        Ok(record)
+    }
+
+    /// The source code and stored output of an analysis, if it exists.
+    /// Code is `None` for analyses recorded before code was captured or
+    /// whose code wasn't stored.
+    pub fn get_analysis_input(&self, analysis_id: i64) -> Result<Option<(Option<String>, Value)>> {
+        use rusqlite::OptionalExtension;
+        let row = self
+            .conn
+            .query_row(
+                "SELECT s.code, a.full_output, a.output_encoding FROM analyses a
+                 LEFT JOIN snippets s ON s.hash = a.code_hash
+                 WHERE a.id = ?1",
+                [analysis_id],
+                |row| Ok((row.get::<_, Option<String>>(0)?, stored_output(row, 1)?)),
+            )
+            .optional()?;
+        Ok(row.map(|(code, full_output)| {
+            let output = serde_json::from_str(&full_output).unwrap_or(Value::String(full_output));
+            (code, output)
+        }))
+    }
+
+    /// Stored code for each of the given hashes that is known
+    pub fn get_code_by_hash<'a>(
+        &self,
+        hashes: impl IntoIterator<Item = &'a str>,
+    ) -> Result<std::collections::BTreeMap<String, String>> {
+        use rusqlite::OptionalExtension;
+        let mut stmt = self
+            .conn
+            .prepare("SELECT code FROM snippets WHERE hash = ?1")?;
+        let mut codes = std::collections::BTreeMap::new();
+        for hash in hashes {
+            if codes.contains_key(hash) {
+                continue;
+            }
+            if let Some(code) = stmt
+                .query_row([hash], |row| row.get::<_, String>(0))
+                .optional()?
+            {
+                codes.insert(hash.to_string(), code);
+            }
+        }
+        Ok(codes)
+    }
+
+    /// Ids of the most recent analyses, newest first, optionally for one tool
+    pub fn latest_analysis_ids(&self, tool: Option<&str>, count: usize) -> Result<Vec<i64>> {
+        use rusqlite::params;
+        let mut stmt = self.conn.prepare(
+            "SELECT id FROM analyses
+             WHERE ?1 IS NULL OR tool = ?1
+             ORDER BY timestamp DESC, id DESC
+             LIMIT ?2",
+        )?;
+        let ids = stmt
+            .query_map(params![tool, count as i64], |row| row.get(0))?
+            .collect::<rusqlite::Result<Vec<i64>>>()?;
+        Ok(ids)
+    }
+
+    /// All error rows recorded for one analysis, in insertion order
+    pub fn get_errors_for_analysis(&self, analysis_id: i64) -> Result<Vec<ErrorRecord>> {
+        let mut stmt = self.conn.prepare(
+            "SELECT e.id, e.error_code, e.message, e.file, e.line, e.suggestion,
+                    COALESCE(e.timestamp, a.timestamp) as timestamp, a.tool, a.id, a.code_hash, a.session, a.features,
+                    a.batch_id, e.severity, a.rustc_version
+             FROM errors e
+             JOIN analyses a ON e.analysis_id = a.id
+             WHERE e.analysis_id = ?1
+             ORDER BY e.id",
+        )?;
+        let error_iter = stmt.query_map([analysis_id], ErrorRecord::from_row)?;
+
+        let mut errors = Vec::new();
+        for error in error_iter {
+            errors.push(error?);
+        }
+        Ok(errors)
+    }
+
+    /// Fixes recorded against any of an analysis's errors, oldest first
+    pub fn get_fixes_for_analysis(&self, analysis_id: i64) -> Result<Vec<FixRecord>> {
+        let mut stmt = self.conn.prepare(
+            "SELECT f.id, f.error_id, f.fix_applied, f.timestamp, f.worked
+             FROM fixes f
+             JOIN errors e ON f.error_id = e.id
+             WHERE e.analysis_id = ?1
+             ORDER BY f.id",
+        )?;
+        let fixes = stmt
+            .query_map([analysis_id], |row| {
+                let applied: String = row.get(2)?;
+                Ok(FixRecord {
+                    id: row.get(0)?,
+                    error_id: row.get(1)?,
+                    fix_applied: serde_json::from_str(&applied).unwrap_or(Value::String(applied)),
+                    timestamp: row.get(3)?,
+                    worked: row.get::<_, Option<bool>>(4)?,
+                })
+            })?
+            .collect::<rusqlite::Result<Vec<_>>>()?;
+        Ok(fixes)
+    }
+
+    /// Everything stored for one analysis: its row, parsed output, code,
+    /// error rows and the fixes recorded against them
+    pub fn get_analysis_detail(&self, analysis_id: i64) -> Result<Option<AnalysisDetail>> {
+        let Some(analysis) = self.get_analysis(analysis_id)? else {
+            return Ok(None);
+        };
+        let (code, output) = self
+            .get_analysis_input(analysis_id)?
+            .unwrap_or((None, Value::Null));
+        Ok(Some(AnalysisDetail {
+            analysis,
+            output,
+            code,
+            errors: self.get_errors_for_analysis(analysis_id)?,
+            fixes: self.get_fixes_for_analysis(analysis_id)?,
+        }))
+    }
+
+    /// Search stored analysis output for `query`, treated as a literal phrase
+    pub fn search_output(&self, query: &str, limit: usize) -> Result<Vec<SearchHit>> {
+        use rusqlite::params;
+        let mut hits = Vec::new();
+
+        if self.fts_enabled {
+            let phrase = format!("\"{}\"", query.replace('"', "\"\""));
+            let mut stmt = self.conn.prepare(
+                "SELECT a.id, a.tool, a.timestamp, a.success,
+                        snippet(analyses_fts, 0, '[', ']', '…', 16)
+                 FROM analyses_fts f
+                 JOIN analyses a ON a.id = f.rowid
+                 WHERE analyses_fts MATCH ?1
+                 ORDER BY rank
+                 LIMIT ?2",
+            )?;
+            let rows = stmt.query_map(params![phrase, limit as i64], |row| {
+                Ok(SearchHit {
+                    analysis_id: row.get(0)?,
+                    tool: row.get(1)?,
+                    timestamp: row.get(2)?,
+                    success: row.get(3)?,
+                    snippet: row.get(4)?,
+                })
+            })?;
+            for hit in rows {
+                hits.push(hit?);
+            }
+            return Ok(hits);
+        }
+
+        // Compressed outputs can't be matched in SQL, so every output is
+        // decoded and matched the way LIKE would: ASCII case-insensitively
+        let needle = query.to_ascii_lowercase();
+        let mut stmt = self.conn.prepare(
+            "SELECT id, tool, timestamp, success, full_output, output_encoding
+             FROM analyses
+             ORDER BY timestamp DESC, id DESC",
+        )?;
+        let mut rows = stmt.query([])?;
+        while hits.len() < limit
+            && let Some(row) = rows.next()?
+        {
+            let full_output = stored_output(row, 4)?;
+            if !full_output.to_ascii_lowercase().contains(&needle) {
+                continue;
+            }
+            hits.push(SearchHit {
+                analysis_id: row.get(0)?,
+                tool: row.get(1)?,
+                timestamp: row.get(2)?,
+                success: row.get(3)?,
+                snippet: like_snippet(&searchable_text(&full_output), query),
+            });
+        }
+        Ok(hits)
+    }
+
+    /// Whether full-text search is backed by FTS5 ("fts5") or LIKE ("like")
+    pub fn search_mode(&self) -> &'static str {
+        if self.fts_enabled { "fts5" } else { "like" }
+    }
+
+    /// Get statistics about stored data
+    pub fn get_stats(&self) -> Result<DatabaseStats> {
+        let analyses_count: i64 =
+            self.conn
+                .query_row("SELECT COUNT(*) FROM analyses", [], |row| row.get(0))?;
+
+        let errors_count: i64 = self
+            .conn
+            .query_row("SELECT COUNT(*) FROM errors", [], |row| row.get(0))?;
+
+        let mut stmt = self
+            .conn
+            .prepare("SELECT COALESCE(severity, 'unknown'), COUNT(*) FROM errors GROUP BY 1")?;
+        let errors_by_severity = stmt
+            .query_map([], |row| {
+                Ok((row.get::<_, String>(0)?, row.get::<_, i64>(1)? as usize))
+            })?
+            .collect::<rusqlite::Result<_>>()?;
+
+        let todos_count: i64 = self.conn.query_row(
+            "SELECT COUNT(*) FROM todos WHERE completed = 0",
+            [],
+            |row| row.get(0),
+        )?;
+
+        let completed_todos_count: i64 = self.conn.query_row(
+            "SELECT COUNT(*) FROM todos WHERE completed = 1",
+            [],
+            |row| row.get(0),
+        )?;
+
+        Ok(DatabaseStats {
+            total_analyses: analyses_count as usize,
+            total_errors: errors_count as usize,
+            errors_by_severity,
+            active_todos: todos_count as usize,
+            completed_todos: completed_todos_count as usize,
+        })
+    }
+
+    /// Per-tool invocation counts, success rates and durations, optionally for
+    /// one tool and analyses since an ISO-8601 timestamp, sorted by tool
+    pub fn get_metrics(&self, tool: Option<&str>, since: Option<&str>) -> Result<Vec<ToolMetrics>> {
+        use rusqlite::params;
+        let mut stmt = self.conn.prepare(
+            "SELECT tool, success,
+                    COALESCE(duration_ms,
+                             CASE WHEN output_encoding IS NULL AND json_valid(full_output)
+                                  THEN json_extract(full_output, '$.duration_ms') END)
+             FROM analyses
+             WHERE (?1 IS NULL OR tool = ?1)
+               AND (?2 IS NULL OR datetime(timestamp) >= datetime(?2))",
+        )?;
+        let rows = stmt
+            .query_map(params![tool, since], |row| {
+                Ok((
+                    row.get::<_, String>(0)?,
+                    row.get::<_, bool>(1)?,
+                    row.get::<_, Option<i64>>(2)?,
+                ))
+            })?
+            .collect::<rusqlite::Result<Vec<_>>>()?;
+
+        let mut by_tool: std::collections::BTreeMap<String, (usize, usize, Vec<u64>)> =
+            std::collections::BTreeMap::new();
+        for (tool, success, duration_ms) in rows {
+            let (invocations, successes, durations) = by_tool.entry(tool).or_default();
+            *invocations += 1;
+            *successes += success as usize;
+            durations.extend(duration_ms.map(|ms| ms.max(0) as u64));
+        }
+        Ok(by_tool
+            .into_iter()
+            .map(|(tool, (invocations, successes, durations))| {
+                ToolMetrics::new(tool, successes, durations, invocations)
+            })
+            .collect())
+    }
+
+    /// Analyses, failures and errors per day or week over the last
+    /// `filter.days` days, oldest bucket first, with empty buckets included
+    pub fn get_error_trends(&self, filter: &TrendFilter) -> Result<ErrorTrends> {
+        use std::collections::{BTreeMap, BTreeSet};
+        let window_start: String = self.conn.query_row(
+            "SELECT date('now', ?1)",
+            [format!("-{} days", filter.days.saturating_sub(1))],
+            |row| row.get(0),
+        )?;
+
+        let bucket_of = |ts: &str| filter.bucket.sql(ts);
+        let mut stmt = self.conn.prepare(&format!(
+            "WITH RECURSIVE buckets(start) AS (
+                SELECT {first}
+                UNION ALL
+                SELECT date(start, '{step}') FROM buckets WHERE start < {last}
+             )
+             SELECT start FROM buckets",
+            first = bucket_of("?1"),
+            last = bucket_of("date('now')"),
+            step = filter.bucket.step(),
+        ))?;
+        let mut points: BTreeMap<String, TrendPoint> = stmt
+            .query_map([&window_start], |row| row.get::<_, String>(0))?
+            .map(|start| {
+                start.map(|start| {
+                    let point = TrendPoint {
+                        bucket_start: start.clone(),
+                        ..Default::default()
+                    };
+                    (start, point)
+                })
+            })
+            .collect::<rusqlite::Result<_>>()?;
+        // Week buckets cover whole weeks, so the window starts on a Monday
+        let window_start = points.keys().next().cloned().unwrap_or(window_start);
+
+        let mut analyses = QueryBuilder::default();
+        analyses.push("date(a.timestamp) >= ?", window_start.clone());
+        if let Some(tool) = &filter.tool {
+            analyses.push("a.tool = ?", tool.clone());
+        }
+        if let Some(session) = &filter.session {
+            analyses.push("a.session = ?", session.clone());
+        }
+        let mut stmt = self.conn.prepare(&format!(
+            "SELECT {bucket}, COUNT(*), COALESCE(SUM(a.success = 0), 0), a.rustc_version
+             FROM analyses a{where_sql}
+             GROUP BY 1, 4",
+            bucket = bucket_of("a.timestamp"),
+            where_sql = analyses.where_sql(),
+        ))?;
+        let rows = stmt.query_map(rusqlite::params_from_iter(analyses.params), |row| {
+            Ok((
+                row.get::<_, String>(0)?,
+                row.get::<_, i64>(1)?,
+                row.get::<_, i64>(2)?,
+                row.get::<_, Option<String>>(3)?,
+            ))
+        })?;
+        for row in rows {
+            let (start, count, failures, rustc_version) = row?;
+            if let Some(point) = points.get_mut(&start) {
+                point.counts.analyses += count as usize;
+                point.counts.failures += failures as usize;
+                if filter.by_rustc {
+                    let counts = point
+                        .by_rustc
+                        .entry(rustc_minor(rustc_version.as_deref()))
+                        .or_default();
+                    counts.analyses += count as usize;
+                    counts.failures += failures as usize;
+                }
+            }
+        }
+
+        // Errors are bucketed by the same timestamp get_error_history orders
+        // by, so rows from before errors had their own timestamp still land
+        // in their analysis's bucket
+        let history = HistoryFilter {
+            error_code: filter.error_code.clone(),
+            tool: filter.tool.clone(),
+            session: filter.session.clone(),
+            ..Default::default()
+        };
+        let (ts_expr, query) = self.history_query(&history);
+        let mut stmt = self.conn.prepare(&format!(
+            "SELECT {bucket}, COALESCE(e.severity, 'unknown'), e.error_code, COUNT(*),
+                    a.rustc_version
+             FROM errors e
+             JOIN analyses a ON e.analysis_id = a.id{where_sql}
+             GROUP BY 1, 2, 3, 5",
+            bucket = bucket_of(ts_expr),
+            where_sql = query.where_sql(),
+        ))?;
+        let rows = stmt
+            .query_map(rusqlite::params_from_iter(query.params), |row| {
+                Ok((
+                    row.get::<_, String>(0)?,
+                    row.get::<_, String>(1)?,
+                    row.get::<_, Option<String>>(2)?,
+                    row.get::<_, i64>(3)?,
+                    row.get::<_, Option<String>>(4)?,
+                ))
+            })?
+            .collect::<rusqlite::Result<Vec<_>>>()?;
+
+        // A code is new in the bucket it first appeared in, looking at all
+        // history rather than just the window
+        let mut first_seen: BTreeMap<&str, &str> = BTreeMap::new();
+        for (start, _, code, ..) in &rows {
+            if let Some(code) = code {
+                let first = first_seen.entry(code).or_insert(start);
+                if start.as_str() < *first {
+                    *first = start;
+                }
+            }
+        }
+        let mut codes_in: BTreeMap<&str, BTreeSet<&str>> = BTreeMap::new();
+        for (start, severity, code, count, rustc_version) in &rows {
+            let Some(point) = points.get_mut(start) else {
+                continue;
+            };
+            point.counts.errors += *count as usize;
+            if filter.by_rustc {
+                point
+                    .by_rustc
+                    .entry(rustc_minor(rustc_version.as_deref()))
+                    .or_default()
+                    .errors += *count as usize;
+            }
+            *point
+                .counts
+                .errors_by_severity
+                .entry(severity.clone())
+                .or_default() += *count as usize;
+            if let Some(code) = code {
+                codes_in.entry(start).or_default().insert(code);
+            }
+        }
+        for (start, codes) in codes_in {
+            let point = points.get_mut(start).expect("bucket exists");
+            for code in codes {
+                if first_seen[code] == start {
+                    point.counts.new_codes += 1;
+                    point.new_error_codes.push(code.to_string());
+                } else {
+                    point.counts.recurring_codes += 1;
+                }
+            }
+        }
+
+        let buckets: Vec<TrendPoint> = points.into_values().collect();
+        let mut totals = TrendCounts::default();
+        let mut by_rustc = BTreeMap::new();
+        for point in &buckets {
+            totals.analyses += point.counts.analyses;
+            totals.failures += point.counts.failures;
+            totals.errors += point.counts.errors;
+            for (severity, count) in &point.counts.errors_by_severity {
+                *totals
+                    .errors_by_severity
+                    .entry(severity.clone())
+                    .or_default() += count;
+            }
+            totals.new_codes += point.new_error_codes.len();
+            for (minor, counts) in &point.by_rustc {
+                let total: &mut RustcCounts = by_rustc.entry(minor.clone()).or_default();
+                total.analyses += counts.analyses;
+                total.failures += counts.failures;
+                total.errors += counts.errors;
+            }
+        }
+        let window_codes: BTreeSet<&str> = rows
+            .iter()
+            .filter(|(start, ..)| *start >= window_start)
+            .filter_map(|(_, _, code, ..)| code.as_deref())
+            .collect();
+        totals.recurring_codes = window_codes.len() - totals.new_codes;
+
+        Ok(ErrorTrends {
+            bucket: filter.bucket.name(),
+            days: filter.days,
+            window_start,
+            buckets,
+            totals,
+            by_rustc,
+        })
+    }
+
+    /// The rustc error code (E followed by digits) stored most often; ties go
+    /// to the one seen last
+    pub fn most_frequent_error_code(&self) -> Result<Option<String>> {
+        use rusqlite::OptionalExtension;
+
+        Ok(self
+            .conn
+            .query_row(
+                "SELECT error_code FROM errors
+                 WHERE error_code GLOB 'E[0-9]*'
+                 GROUP BY error_code
+                 ORDER BY COUNT(*) DESC, MAX(id) DESC
+                 LIMIT 1",
+                [],
+                |row| row.get(0),
+            )
+            .optional()?)
+    }
+
+    /// How often `error_code` was stored, its most frequent messages and the
+    /// fixes that compiled for it, `limit` of each. Errors stored before they
+    /// had a timestamp count from their analysis's.
+    pub fn get_error_code_stats(&self, error_code: &str, limit: usize) -> Result<ErrorCodeStats> {
+        use rusqlite::params;
+
+        let (occurrences, analyses, first_seen, last_seen, distinct_messages) =
+            self.conn.query_row(
+                "SELECT COUNT(*), COUNT(DISTINCT e.analysis_id),
+                        MIN(COALESCE(e.timestamp, a.timestamp)),
+                        MAX(COALESCE(e.timestamp, a.timestamp)),
+                        COUNT(DISTINCT e.message)
+                 FROM errors e
+                 LEFT JOIN analyses a ON a.id = e.analysis_id
+                 WHERE e.error_code = ?1",
+                [error_code],
+                |row| {
+                    Ok((
+                        row.get::<_, i64>(0)? as usize,
+                        row.get::<_, i64>(1)? as usize,
+                        row.get::<_, Option<String>>(2)?,
+                        row.get::<_, Option<String>>(3)?,
+                        row.get::<_, i64>(4)? as usize,
+                    ))
+                },
+            )?;
+
+        let mut stmt = self.conn.prepare(
+            "SELECT message, COUNT(*) FROM errors
+             WHERE error_code = ?1
+             GROUP BY message
+             ORDER BY COUNT(*) DESC, MAX(id) DESC
+             LIMIT ?2",
+        )?;
+        let messages = stmt
+            .query_map(params![error_code, limit as i64], |row| {
+                Ok(MessageCount {
+                    message: row.get(0)?,
+                    count: row.get::<_, i64>(1)? as usize,
+                })
+            })?
+            .collect::<rusqlite::Result<Vec<_>>>()?;
+
+        let mut stmt = self.conn.prepare(
+            "SELECT f.id, f.error_id, f.fix_applied, f.timestamp, f.worked
+             FROM fixes f
+             JOIN errors e ON f.error_id = e.id
+             WHERE e.error_code = ?1 AND f.worked = 1
+             ORDER BY f.id DESC
+             LIMIT ?2",
+        )?;
+        let fixes = stmt
+            .query_map(params![error_code, limit as i64], |row| {
+                let applied: String = row.get(2)?;
+                Ok(FixRecord {
+                    id: row.get(0)?,
+                    error_id: row.get(1)?,
+                    fix_applied: serde_json::from_str(&applied).unwrap_or(Value::String(applied)),
+                    timestamp: row.get(3)?,
+                    worked: row.get::<_, Option<bool>>(4)?,
+                })
+            })?
+            .collect::<rusqlite::Result<Vec<_>>>()?;
+
+        Ok(ErrorCodeStats {
+            error_code: error_code.to_string(),
+            occurrences,
+            analyses,
+            first_seen,
+            last_seen,
+            distinct_messages,
+            messages,
+            fixes,
+        })
+    }
+
+    /// Rebuild the database file to return pages freed by deletes to the OS.
+    /// VACUUM needs exclusive access and can't run inside a transaction;
+    /// callers hold the database mutex, so no other statement is in flight.
+    pub fn vacuum(&self) -> Result<VacuumStats> {
+        if !self.conn.is_autocommit() {
+            anyhow::bail!("cannot VACUUM while a transaction is open");
+        }
+        let size_before = self.file_size()?;
+        self.conn.execute_batch("VACUUM")?;
+        let size_after = self.file_size()?;
+        Ok(VacuumStats {
+            size_before,
+            size_after,
+            reclaimed: size_before.saturating_sub(size_after),
+        })
+    }
+
+    /// Copy the live database to `destination` with SQLite's online backup
+    /// API, returning the size of the copy in bytes. Refuses to overwrite an
+    /// existing file unless `overwrite` is set. The copy is written next to
+    /// `destination` and renamed over it, so a failed backup leaves any
+    /// previous one in place.
+    pub fn backup(&self, destination: &Path, overwrite: bool) -> Result<u64> {
+        // Compared with `..` and symlinks resolved, which could otherwise
+        // name the live file another way
+        let destination = resolved_path(destination)?;
+        if let Some(live) = self.conn.path().filter(|p| !p.is_empty())
+            && resolved_path(Path::new(live))? == destination
+        {
+            anyhow::bail!("destination is the live database");
+        }
+        if !overwrite && destination.exists() {
+            anyhow::bail!("{} already exists", destination.display());
+        }
+        let directory = destination.parent().unwrap_or(Path::new("."));
+        let copy = tempfile::Builder::new()
+            .prefix(".rusty-tools-backup")
+            .tempfile_in(directory)?
+            .into_temp_path();
+        self.conn
+            .backup(rusqlite::DatabaseName::Main, &copy, None)?;
+        let bytes = std::fs::metadata(&copy)?.len();
+        if overwrite {
+            copy.persist(&destination)?;
+        } else {
+            // Fails rather than replaces a file created in the meantime
+            copy.persist_noclobber(&destination)?;
+        }
+        Ok(bytes)
+    }
+
+    /// Size of the database file in bytes, from page counts when the
+    /// connection has no file on disk
+    fn file_size(&self) -> Result<u64> {
+        if let Some(path) = self.conn.path().filter(|p| !p.is_empty()) {
+            return Ok(std::fs::metadata(path)?.len());
+        }
+        let size: i64 = self.conn.query_row(
+            "SELECT page_count * page_size FROM pragma_page_count(), pragma_page_size()",
+            [],
+            |row| row.get(0),
+        )?;
+        Ok(size as u64)
+    }
+
+    /// A cached run younger than `ttl`, which counts as a use for eviction
+    pub fn get_cached_run(&self, key: &str, ttl: Duration) -> Result<Option<CachedRun>> {
+        use rusqlite::{OptionalExtension, params};
+
+        let now = unix_millis();
+        let run = self
+            .conn
+            .query_row(
+                "SELECT stdout, stderr, status, duration_ms FROM result_cache
+                 WHERE key = ?1 AND created_at >= ?2",
+                params![key, now - ttl.as_millis() as i64],
+                |row| {
+                    Ok(CachedRun {
+                        stdout: row.get(0)?,
+                        stderr: row.get(1)?,
+                        status: row.get(2)?,
+                        duration_ms: row.get::<_, i64>(3)? as u128,
+                    })
+                },
+            )
+            .optional()?;
+        if run.is_some() {
+            self.conn.execute(
+                "UPDATE result_cache SET last_used = ?1 WHERE key = ?2",
+                params![now, key],
+            )?;
+        }
+        Ok(run)
+    }
+
+    /// Cache a run, evicting the least recently used entries beyond
+    /// `cache::MAX_ENTRIES`
+    pub fn put_cached_run(&self, key: &str, run: &CachedRun) -> Result<()> {
+        use rusqlite::params;
+
+        let now = unix_millis();
+        self.conn.execute(
+            "INSERT OR REPLACE INTO result_cache
+                (key, stdout, stderr, status, duration_ms, created_at, last_used)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?6)",
+            params![
+                key,
+                run.stdout,
+                run.stderr,
+                run.status,
+                run.duration_ms as i64,
+                now
+            ],
+        )?;
+        self.conn.execute(
+            "DELETE FROM result_cache WHERE key NOT IN (
+                SELECT key FROM result_cache
+                ORDER BY last_used DESC, rowid DESC
+                LIMIT ?1
+             )",
+            params![cache::MAX_ENTRIES],
+        )?;
+        Ok(())
+    }
+
+    /// The stored explanation of `error_code`, when `rustc_version` gave it
+    pub fn get_explanation(&self, error_code: &str, rustc_version: &str) -> Result<Option<String>> {
+        use rusqlite::{OptionalExtension, params};
+
+        Ok(self
+            .conn
+            .query_row(
+                "SELECT explanation FROM explanations
+                 WHERE error_code = ?1 AND rustc_version = ?2",
+                params![error_code, rustc_version],
+                |row| row.get(0),
+            )
+            .optional()?)
+    }
+
+    /// Store an explanation, replacing one from another rustc version
+    pub fn put_explanation(
+        &self,
+        error_code: &str,
+        rustc_version: &str,
+        explanation: &str,
+    ) -> Result<()> {
+        self.conn.execute(
+            "INSERT OR REPLACE INTO explanations (error_code, rustc_version, explanation)
+             VALUES (?1, ?2, ?3)",
+            rusqlite::params![error_code, rustc_version, explanation],
+        )?;
+        Ok(())
+    }
+
+    /// Delete every cached result, returning how many there were
+    pub fn purge_result_cache(&self) -> Result<usize> {
+        Ok(self.conn.execute("DELETE FROM result_cache", [])?)
+    }
+
+    /// Delete every analysis, error, todo and fix, children before the rows
+    /// they reference, along with the snippets only analyses referred to
+    pub fn reset(&self) -> Result<ResetCounts> {
+        let tx = self.conn.unchecked_transaction()?;
+        let counts = ResetCounts {
+            fixes: tx.execute("DELETE FROM fixes", [])?,
+            errors: tx.execute("DELETE FROM errors", [])?,
+            todos: tx.execute("DELETE FROM todos", [])?,
+            analyses: tx.execute("DELETE FROM analyses", [])?,
+            snippets: tx.execute("DELETE FROM snippets", [])?,
+        };
+        tx.commit()?;
+        Ok(counts)
+    }
+
+    /// Clean up old data beyond a certain limit
+    pub fn cleanup_old_data(&self, keep_analyses: usize) -> Result<PruneCounts> {
+        use rusqlite::params;
+
+        // Delete old analyses and their associated errors
+        let errors = self.conn.execute(
+            "DELETE FROM errors WHERE analysis_id IN (
+                SELECT id FROM analyses
+                ORDER BY timestamp DESC
+                LIMIT -1 OFFSET ?1
+            )",
+            params![keep_analyses],
+        )?;
+
+        let analyses = self.conn.execute(
+            "DELETE FROM analyses
+             WHERE id NOT IN (
+                SELECT id FROM analyses
+                ORDER BY timestamp DESC
+                LIMIT ?1
+             )",
+            params![keep_analyses],
+        )?;
+
+        let snippets = self.conn.execute(
+            "DELETE FROM snippets WHERE hash NOT IN (
+                SELECT code_hash FROM analyses WHERE code_hash IS NOT NULL
+             )",
+            [],
+        )?;
+
+        Ok(PruneCounts {
+            analyses,
+            errors,
+            snippets,
+        })
+    }
+}
+
+#[derive(Debug, Clone, serde::Serialize)]
+pub struct AnalysisRecord {
+    pub id: i64,
+    pub timestamp: String,
+    pub tool: String,
+    pub success: bool,
+    pub file_path: Option<String>,
+    pub session: Option<String>,
+    /// `rustc --version` of the toolchain the analysis compiled with
+    pub rustc_version: Option<String>,
+    /// Its `rustc --version --verbose`: release, commit, host and LLVM
+    /// version; None for analyses stored before it was recorded
+    pub toolchain: Option<Value>,
+}
+
+/// A fix applied with `apply_suggestion`
+#[derive(Debug, Clone, serde::Serialize)]
+pub struct FixRecord {
+    pub id: i64,
+    pub error_id: Option<i64>,
+    /// The suggestion's edits as they were applied
+    pub fix_applied: Value,
+    pub timestamp: String,
+    /// Whether the patched code compiled
+    pub worked: Option<bool>,
+}
+
+/// An analysis with all of its stored detail, as returned by `get_analysis`
+#[derive(Debug, Clone, serde::Serialize)]
+pub struct AnalysisDetail {
+    pub analysis: AnalysisRecord,
+    /// The stored `full_output`, parsed
+    pub output: Value,
+    /// Absent when code storage was disabled or the snippet was too large
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub code: Option<String>,
+    pub errors: Vec<ErrorRecord>,
+    pub fixes: Vec<FixRecord>,
+}
+
+/// Diagnostics that appeared, disappeared, or persisted between two analyses
+#[derive(Debug, Default, serde::Serialize)]
+pub struct ErrorDiff {
+    pub added: Vec<ErrorRecord>,
+    pub removed: Vec<ErrorRecord>,
+    pub unchanged: Vec<ErrorRecord>,
+}
+
+impl ErrorDiff {
+    /// Compare error rows keyed by (error_code, message, file, line). Repeated
+    /// identical diagnostics are matched one-for-one, so a duplicate that
+    /// appears an extra time counts as added.
+    pub fn between(baseline: &[ErrorRecord], current: &[ErrorRecord]) -> Self {
+        use std::collections::HashMap;
+
+        fn key(e: &ErrorRecord) -> (Option<&str>, &str, Option<&str>, Option<i32>) {
+            (
+                e.error_code.as_deref(),
+                &e.message,
+                e.file.as_deref(),
+                e.line,
+            )
+        }
+
+        let mut remaining: HashMap<_, Vec<&ErrorRecord>> = HashMap::new();
+        for error in baseline {
+            remaining.entry(key(error)).or_default().push(error);
+        }
+
+        let mut diff = ErrorDiff::default();
+        for error in current {
+            match remaining.get_mut(&key(error)).and_then(|v| v.pop()) {
+                Some(_) => diff.unchanged.push(error.clone()),
+                None => diff.added.push(error.clone()),
+            }
+        }
+        // Preserve baseline order for removed entries
+        for error in baseline {
+            if let Some(left) = remaining.get_mut(&key(error))
+                && left.iter().any(|e| e.id == error.id)
+            {
+                left.retain(|e| e.id != error.id);
+                diff.removed.push(error.clone());
+            }
+        }
+        diff
+    }
+}
+
+/// Set comparison of the distinct error codes seen in two analyses
+#[derive(Debug, Default, serde::Serialize)]
+pub struct ErrorCodeChanges {
+    pub resolved: Vec<String>,
+    pub introduced: Vec<String>,
+    pub persisting: Vec<String>,
+}
+
+impl ErrorCodeChanges {
+    pub fn between(baseline: &[ErrorRecord], current: &[ErrorRecord]) -> Self {
+        use std::collections::BTreeSet;
+        let codes = |errors: &[ErrorRecord]| -> BTreeSet<String> {
+            errors.iter().filter_map(|e| e.error_code.clone()).collect()
+        };
+        let (before, after) = (codes(baseline), codes(current));
+        ErrorCodeChanges {
+            resolved: before.difference(&after).cloned().collect(),
+            introduced: after.difference(&before).cloned().collect(),
+            persisting: before.intersection(&after).cloned().collect(),
+        }
+    }
+}
+
+#[derive(Debug, serde::Serialize)]
+pub struct SearchHit {
+    pub analysis_id: i64,
+    pub tool: String,
+    pub timestamp: String,
+    pub success: bool,
+    pub snippet: String,
+}
+
+fn unix_millis() -> i64 {
+    std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map(|d| d.as_millis() as i64)
+        .unwrap_or_default()
+}
+
+/// Stable hex-encoded SHA-256 of a code snippet, used to recognize identical inputs
+pub fn code_hash(code: &str) -> String {
+    use sha2::{Digest, Sha256};
+    Sha256::digest(code.as_bytes())
+        .iter()
+        .map(|b| format!("{:02x}", b))
+        .collect()
+}
+
+/// `output_encoding` of analyses whose `full_output` is gzip-compressed JSON
+const GZIP_ENCODING: &str = "gzip";
+
+/// `full_output` as stored, and its `output_encoding`: gzip-compressed when
+/// that is smaller, as it is for all but the shortest outputs
+fn encode_output(json: &str) -> (SqlValue, Option<&'static str>) {
+    let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+    if encoder.write_all(json.as_bytes()).is_ok()
+        && let Ok(compressed) = encoder.finish()
+        && compressed.len() < json.len()
+    {
+        return (SqlValue::Blob(compressed), Some(GZIP_ENCODING));
+    }
+    (SqlValue::Text(json.to_string()), None)
+}
+
+/// The JSON text of the `full_output` at `index`, decompressed according to
+/// the `output_encoding` right after it
+fn stored_output(row: &rusqlite::Row, index: usize) -> rusqlite::Result<String> {
+    let encoding: Option<String> = row.get(index + 1)?;
+    match (row.get::<_, SqlValue>(index)?, encoding.as_deref()) {
+        (SqlValue::Text(json), None) => Ok(json),
+        (SqlValue::Blob(compressed), Some(GZIP_ENCODING)) => {
+            let mut json = String::new();
+            GzDecoder::new(compressed.as_slice())
+                .read_to_string(&mut json)
+                .map_err(|e| {
+                    rusqlite::Error::FromSqlConversionFailure(
+                        index,
+                        rusqlite::types::Type::Blob,
+                        Box::new(e),
+                    )
+                })?;
+            Ok(json)
+        }
+        (stored, encoding) => Err(rusqlite::Error::FromSqlConversionFailure(
+            index,
+            stored.data_type(),
+            format!("unknown output_encoding {:?}", encoding).into(),
+        )),
+    }
+}
+
+/// Text indexed for search: the stdout and stderr of a stored result, or the
+/// raw stored string when it isn't a tool result object
+fn searchable_text(full_output: &str) -> String {
+    match serde_json::from_str::<Value>(full_output) {
+        Ok(value) if value.get("stdout").is_some() || value.get("stderr").is_some() => {
+            let field = |name| value.get(name).and_then(|v| v.as_str()).unwrap_or("");
+            format!("{}\n{}", field("stdout"), field("stderr"))
+        }
+        _ => full_output.to_string(),
+    }
+}
+
+/// Approximate FTS5's snippet() for the LIKE fallback: the match in brackets
+/// with some surrounding context on one line
+fn like_snippet(text: &str, query: &str) -> String {
+    const CONTEXT: usize = 60;
+    let lower = text.to_lowercase();
+    let Some(start) = lower
+        .find(&query.to_lowercase())
+        .filter(|_| lower.len() == text.len())
+    else {
+        return text.chars().take(CONTEXT * 2).collect();
+    };
+    let end = start + query.len();
+
+    let mut from = start.saturating_sub(CONTEXT);
+    while !text.is_char_boundary(from) {
+        from -= 1;
+    }
+    let mut to = (end + CONTEXT).min(text.len());
+    while !text.is_char_boundary(to) {
+        to += 1;
+    }
+
+    let snippet = format!(
+        "{}{}[{}]{}{}",
+        if from > 0 { "…" } else { "" },
+        &text[from..start],
+        &text[start..end],
+        &text[end..to],
+        if to < text.len() { "…" } else { "" },
+    );
+    snippet.replace('\n', " ")
+}
+
+/// Optional filters for `Database::get_error_history`; all set fields are combined with AND
+#[derive(Debug, Default, Clone)]
+pub struct HistoryFilter {
+    pub error_code: Option<String>,
+    /// "error", "warning", "note" or "help"
+    pub severity: Option<String>,
+    pub tool: Option<String>,
+    pub success: Option<bool>,
+    /// Substring match against the error's file path
+    pub file: Option<String>,
+    /// Inclusive lower bound, ISO-8601 timestamp
+    pub since: Option<String>,
+    /// Inclusive upper bound, ISO-8601 timestamp
+    pub until: Option<String>,
+    /// Exact match against the analysis session label
+    pub session: Option<String>,
+    /// Exact match against a `cargo_feature_check` feature set, comma-joined
+    /// and sorted as produced by `normalize_feature_set`
+    pub features: Option<String>,
+    /// Exact match against the batch id of a `cargo_analyze` or `pipeline` call
+    pub batch_id: Option<String>,
+    pub limit: Option<usize>,
+    pub offset: Option<usize>,
+}
+
+/// Accumulates WHERE clauses with positional parameters so dynamic queries
+/// never splice user input into SQL
+#[derive(Default)]
+struct QueryBuilder {
+    clauses: Vec<String>,
+    params: Vec<SqlValue>,
+}
+
+impl QueryBuilder {
+    /// Add a clause containing a single `?` placeholder bound to `value`
+    fn push(&mut self, clause: &str, value: impl Into<SqlValue>) {
+        self.clauses.push(clause.to_string());
+        self.params.push(value.into());
+    }
+
+    fn where_sql(&self) -> String {
+        if self.clauses.is_empty() {
+            String::new()
+        } else {
+            format!("\n             WHERE {}", self.clauses.join(" AND "))
+        }
+    }
+}
+
+#[derive(Debug, Clone, serde::Serialize)]
+pub struct ErrorRecord {
+    pub id: i64,
+    pub error_code: Option<String>,
+    /// "error", "warning", "note" or "help"
+    pub severity: Option<String>,
+    pub message: String,
+    pub file: Option<String>,
+    pub line: Option<i32>,
+    pub suggestion: Option<String>,
+    pub timestamp: String,
+    pub tool: String,
+    pub analysis_id: i64,
+    /// Hash of the analyzed code, shared by analyses of identical snippets
+    pub code_hash: Option<String>,
+    pub session: Option<String>,
+    /// Comma-joined feature set for `cargo_feature_check` runs
+    pub features: Option<String>,
+    /// Shared by the steps of one `cargo_analyze` or `pipeline` call
+    pub batch_id: Option<String>,
+    /// `rustc --version` of the analysis, to tell a fix from a smarter compiler
+    pub rustc_version: Option<String>,
+}
+
+impl ErrorRecord {
+    /// Build from a row selecting `e.id, e.error_code, e.message, e.file,
+    /// e.line, e.suggestion, <timestamp>, a.tool, a.id, a.code_hash, a.session,
+    /// a.features, a.batch_id, e.severity, a.rustc_version`
+    fn from_row(row: &rusqlite::Row) -> rusqlite::Result<Self> {
+        Ok(ErrorRecord {
+            id: row.get(0)?,
+            error_code: row.get::<_, Option<String>>(1)?,
+            message: row.get(2)?,
+            file: row.get::<_, Option<String>>(3)?,
+            line: row.get::<_, Option<i32>>(4)?,
+            suggestion: row.get::<_, Option<String>>(5)?,
+            timestamp: row.get(6)?,
+            tool: row.get(7)?,
+            analysis_id: row.get(8)?,
+            code_hash: row.get::<_, Option<String>>(9)?,
+            session: row.get::<_, Option<String>>(10)?,
+            features: row.get::<_, Option<String>>(11)?,
+            batch_id: row.get::<_, Option<String>>(12)?,
+            severity: row.get::<_, Option<String>>(13)?,
+            rustc_version: row.get::<_, Option<String>>(14)?,
+        })
+    }
+}
+
+#[derive(Debug, serde::Serialize)]
+pub struct TodoRecord {
+    pub id: i64,
+    pub source: String,
+    pub description: String,
+    pub file_path: Option<String>,
+    pub line_number: Option<i32>,
+    pub completed: bool,
+    pub created_at: String,
+    /// The ignored lint that closed this todo
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub suppressed_by: Option<String>,
+}
+
+/// A clippy lint whose findings are kept out of the todo list
+#[derive(Debug, Clone, PartialEq, serde::Serialize)]
+pub struct LintIgnore {
+    /// Full lint name, e.g. `clippy::module_name_repetitions`
+    pub lint: String,
+    pub created_at: String,
+    /// When the snooze ends; `None` ignores the lint until it is unignored
+    pub expires_at: Option<String>,
+    /// Findings skipped plus open todos closed because of this ignore
+    pub suppressed: usize,
+}
+
+/// Rows `Database::cleanup_old_data` deleted, per table
+#[derive(Debug, PartialEq, serde::Serialize)]
+pub struct PruneCounts {
+    pub analyses: usize,
+    pub errors: usize,
+    pub snippets: usize,
+}
+
+/// Rows `Database::reset` deleted, per table
+#[derive(Debug, PartialEq, serde::Serialize)]
+pub struct ResetCounts {
+    pub analyses: usize,
+    pub errors: usize,
+    pub todos: usize,
+    pub fixes: usize,
+    pub snippets: usize,
+}
+
+#[derive(Debug, serde::Serialize)]
+pub struct VacuumStats {
+    pub size_before: u64,
+    pub size_after: u64,
+    pub reclaimed: u64,
+}
+
+#[derive(Debug, serde::Serialize)]
+pub struct DatabaseStats {
+    pub total_analyses: usize,
+    pub total_errors: usize,
+    /// Error rows per severity; rows without one count as "unknown"
+    pub errors_by_severity: std::collections::BTreeMap<String, usize>,
+    pub active_todos: usize,
+    pub completed_todos: usize,
+}
+
+/// Bucket size for `Database::get_error_trends`
+#[derive(
+    Debug,
+    Clone,
+    Copy,
+    Default,
+    PartialEq,
+    serde::Serialize,
+    serde::Deserialize,
+    schemars::JsonSchema,
+)]
+#[serde(rename_all = "lowercase")]
+#[schemars(inline)]
+pub enum TrendBucket {
+    /// Calendar days
+    #[default]
+    Day,
+    /// Monday to Sunday
+    Week,
+}
+
+impl TrendBucket {
+    fn name(self) -> &'static str {
+        match self {
+            TrendBucket::Day => "day",
+            TrendBucket::Week => "week",
+        }
+    }
+
+    /// SQL for the start date of the bucket holding the timestamp `ts`
+    fn sql(self, ts: &str) -> String {
+        match self {
+            TrendBucket::Day => format!("date({})", ts),
+            // 'weekday 0' moves forward to the next Sunday, or stays put on one
+            TrendBucket::Week => format!("date({}, 'weekday 0', '-6 days')", ts),
+        }
+    }
+
+    fn step(self) -> &'static str {
+        match self {
+            TrendBucket::Day => "+1 day",
+            TrendBucket::Week => "+7 days",
+        }
+    }
+}
+
+/// What `Database::get_error_trends` aggregates; set filters are combined with AND
+#[derive(Debug, Clone)]
+pub struct TrendFilter {
+    pub bucket: TrendBucket,
+    /// Days back from today, today included
+    pub days: u32,
+    /// Only count errors with this code; analyses and failures are unaffected
+    pub error_code: Option<String>,
+    pub tool: Option<String>,
+    /// Exact match against the analysis session label
+    pub session: Option<String>,
+    /// Also count per rustc minor version, e.g. "1.85"
+    pub by_rustc: bool,
+}
+
+/// Counts for one bucket, or the whole window
+#[derive(Debug, Default, PartialEq, serde::Serialize)]
+pub struct TrendCounts {
+    pub analyses: usize,
+    /// Analyses that did not succeed
+    pub failures: usize,
+    pub errors: usize,
+    /// Error rows per severity; rows without one count as "unknown"
+    pub errors_by_severity: std::collections::BTreeMap<String, usize>,
+    /// Distinct error codes seen for the first time
+    pub new_codes: usize,
+    /// Distinct error codes that had been seen before
+    pub recurring_codes: usize,
+}
+
+#[derive(Debug, Default, PartialEq, serde::Serialize)]
+pub struct TrendPoint {
+    /// First day of the bucket, YYYY-MM-DD
+    pub bucket_start: String,
+    pub counts: TrendCounts,
+    /// The codes counted in `counts.new_codes`, sorted
+    pub new_error_codes: Vec<String>,
+    /// With `by_rustc`, counts per rustc minor version; "unknown" for
+    /// analyses stored without one
+    #[serde(skip_serializing_if = "std::collections::BTreeMap::is_empty")]
+    pub by_rustc: std::collections::BTreeMap<String, RustcCounts>,
+}
+
+/// What one rustc minor version saw in a bucket or the whole window
+#[derive(Debug, Default, PartialEq, serde::Serialize)]
+pub struct RustcCounts {
+    pub analyses: usize,
+    pub failures: usize,
+    pub errors: usize,
+}
+
+#[derive(Debug, serde::Serialize)]
+pub struct ErrorTrends {
+    pub bucket: &'static str,
+    pub days: u32,
+    /// First day counted, YYYY-MM-DD
+    pub window_start: String,
+    pub buckets: Vec<TrendPoint>,
+    pub totals: TrendCounts,
+    #[serde(skip_serializing_if = "std::collections::BTreeMap::is_empty")]
+    pub by_rustc: std::collections::BTreeMap<String, RustcCounts>,
+}
+
+/// Trend key of a stored `rustc --version`
+fn rustc_minor(rustc_version: Option<&str>) -> String {
+    rustc_version
+        .and_then(Toolchain::minor_version)
+        .unwrap_or_else(|| "unknown".to_string())
+}
+
+/// How often one error code was hit, as `error_code_stats` reports it
+#[derive(Debug, serde::Serialize)]
+pub struct ErrorCodeStats {
+    pub error_code: String,
+    pub occurrences: usize,
+    /// Analyses with at least one occurrence
+    pub analyses: usize,
+    pub first_seen: Option<String>,
+    pub last_seen: Option<String>,
+    pub distinct_messages: usize,
+    /// The most frequent messages first
+    pub messages: Vec<MessageCount>,
+    /// Fixes applied to an occurrence that compiled, newest first
+    pub fixes: Vec<FixRecord>,
+}
+
+#[derive(Debug, PartialEq, serde::Serialize)]
+pub struct MessageCount {
+    pub message: String,
+    pub count: usize,
+}
+
+/// Invocation counts and timings of one tool across stored analyses
+#[derive(Debug, PartialEq, serde::Serialize)]
+pub struct ToolMetrics {
+    pub tool: String,
+    pub invocations: usize,
+    pub successes: usize,
+    /// Fraction of invocations that succeeded, 0.0 to 1.0
+    pub success_rate: f64,
+    /// Timing stats over the analyses that recorded a duration; `None` when
+    /// none did
+    pub avg_duration_ms: Option<f64>,
+    pub p50_duration_ms: Option<u64>,
+    pub p95_duration_ms: Option<u64>,
+    pub max_duration_ms: Option<u64>,
+}
+
+impl ToolMetrics {
+    fn new(tool: String, successes: usize, mut durations: Vec<u64>, invocations: usize) -> Self {
+        durations.sort_unstable();
+        // Nearest-rank percentile
+        let percentile = |p: usize| {
+            let rank = (p * durations.len()).div_ceil(100).max(1);
+            durations.get(rank - 1).copied()
+        };
+        ToolMetrics {
+            avg_duration_ms: (!durations.is_empty())
+                .then(|| durations.iter().sum::<u64>() as f64 / durations.len() as f64),
+            p50_duration_ms: percentile(50),
+            p95_duration_ms: percentile(95),
+            max_duration_ms: durations.last().copied(),
+            success_rate: successes as f64 / invocations.max(1) as f64,
+            tool,
+            invocations,
+            successes,
+        }
+    }
+}
+
+/// What a cargo run produced
+#[derive(Debug, serde::Serialize)]
+pub struct ExecResult {
+    pub stdout: String,
+    pub stderr: String,
+    /// cargo's exit code; -1 when it was killed, e.g. by a timeout
+    pub status: i32,
+    pub duration_ms: u128,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub artifact: Option<Artifact>,
+    /// Where `cargo_doc` saved the generated HTML, with `save_docs`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub docs_path: Option<PathBuf>,
+    /// The project the run used, when it is still on disk: left there with
+    /// `keep_project`, or the caller's own `ToolInvocation::working_dir`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub project_path: Option<PathBuf>,
+    /// The published crate analyzed, for `crate_source` runs
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub crate_source: Option<ResolvedCrate>,
+    /// Replayed from the result cache rather than run
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    pub cached: bool,
+    /// Killed after exceeding the tool's timeout; `status` is -1
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    pub timed_out: bool,
+    /// The resource limit ("memory" or "cpu") a process cargo started ran into
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub resource_exceeded: Option<&'static str>,
+    /// Dependencies that ran build scripts or proc-macros, built with
+    /// `allow_build_scripts`
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub build_code: Vec<BuildCode>,
+}
+
+/// Executable produced by a `cargo_build` run with `return_artifact`
+#[derive(Debug, Clone, serde::Serialize)]
+pub struct Artifact {
+    pub name: String,
+    pub size_bytes: u64,
+    pub target: String,
+    pub profile: String,
+    /// Base64-encoded binary, omitted when larger than the configured cap
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub base64: Option<String>,
+    pub artifact_too_large: bool,
+}
+
+impl Artifact {
+    /// Metadata kept in stored analyses (`artifact.size_bytes` tracks size
+    /// over time); the binary itself is never stored
+    fn stored(&self) -> Value {
+        json!({
+            "name": self.name,
+            "size_bytes": self.size_bytes,
+            "target": self.target,
+            "profile": self.profile
+        })
+    }
+}
+
+/// Replace `destination` with a copy of the generated `target/doc` tree
+fn save_docs(doc_dir: &Path, destination: &Path) -> Result<(), McpError> {
+    if destination.exists() {
+        std::fs::remove_dir_all(destination).map_err(|e| {
+            McpError::internal_error(format!("Failed to replace saved docs: {}", e), None)
+        })?;
+    }
+    copy_dir(doc_dir, destination)
+        .map_err(|e| McpError::internal_error(format!("Failed to save docs: {}", e), None))
+}
+
+/// `path` with `..` and symlinks resolved, through its parent directory
+/// when it doesn't exist yet
+fn resolved_path(path: &Path) -> std::io::Result<PathBuf> {
+    match path.canonicalize() {
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => {
+            let name = path.file_name().ok_or_else(|| {
+                std::io::Error::new(
+                    std::io::ErrorKind::InvalidInput,
+                    format!("{} doesn't name a file", path.display()),
+                )
+            })?;
+            let parent = path
+                .parent()
+                .filter(|parent| !parent.as_os_str().is_empty())
+                .unwrap_or(Path::new("."));
+            Ok(parent.canonicalize()?.join(name))
+        }
+        resolved => resolved,
+    }
+}
+
+/// Recursively copy the directory `from` to `to`
+fn copy_dir(from: &Path, to: &Path) -> std::io::Result<()> {
+    std::fs::create_dir_all(to)?;
+    for entry in std::fs::read_dir(from)? {
+        let entry = entry?;
+        let target = to.join(entry.file_name());
+        if entry.file_type()?.is_dir() {
+            copy_dir(&entry.path(), &target)?;
+        } else {
+            std::fs::copy(entry.path(), target)?;
+        }
+    }
+    Ok(())
+}
+
+/// Find the executable in cargo's `--message-format=json` output, removing
+/// the JSON messages from `stdout`
+fn find_executable(stdout: &mut String) -> Option<PathBuf> {
+    let mut executable = None;
+    let mut remaining = String::new();
+    for line in stdout.lines() {
+        match serde_json::from_str::<Value>(line) {
+            Ok(message) if line.starts_with('{') => {
+                if message["reason"] == "compiler-artifact"
+                    && let Some(path) = message["executable"].as_str()
+                {
+                    executable = Some(PathBuf::from(path));
+                }
+            }
+            _ => {
+                remaining.push_str(line);
+                remaining.push('\n');
+            }
+        }
+    }
+    *stdout = remaining;
+    executable
+}
+
+/// Read the executable a build produced, base64-encoding it when it fits
+/// under `max_bytes`. Returns `None` when nothing was built.
+fn collect_artifact(
+    stdout: &mut String,
+    target: String,
+    profile: &str,
+    max_bytes: u64,
+) -> Result<Option<Artifact>, McpError> {
+    let Some(path) = find_executable(stdout) else {
+        return Ok(None);
+    };
+    let read_err = |e: std::io::Error| {
+        McpError::internal_error(format!("Failed to read build artifact: {}", e), None)
+    };
+    let size_bytes = std::fs::metadata(&path).map_err(read_err)?.len();
+    let artifact_too_large = size_bytes > max_bytes;
+    let base64 = if artifact_too_large {
+        None
+    } else {
+        use base64::Engine;
+        let bytes = std::fs::read(&path).map_err(read_err)?;
+        Some(base64::engine::general_purpose::STANDARD.encode(bytes))
+    };
+
+    Ok(Some(Artifact {
+        name: path
+            .file_name()
+            .map(|name| name.to_string_lossy().into_owned())
+            .unwrap_or_default(),
+        size_bytes,
+        target,
+        profile: profile.to_string(),
+        base64,
+        artifact_too_large,
+    }))
+}
+
+/// Snippet tools that accept `rustflags` and `cfgs`
+const COMPILE_FLAG_TOOLS: &[&str] = &["cargo_check", "cargo_clippy", "cargo_build", "cargo_test"];
+
+/// Upper bound on the combinations one `cargo_feature_check` call runs
+const MAX_FEATURE_COMBINATIONS: usize = 16;
+
+/// Longest window `error_trends` reports, ten years
+const MAX_TREND_DAYS: u64 = 3650;
+
+fn is_feature_name(name: &str) -> bool {
+    !name.is_empty()
+        && name
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-')
+}
+
+/// Sorted, de-duplicated, comma-joined feature set as stored in `analyses.features`
+pub fn normalize_feature_set<'a>(features: impl IntoIterator<Item = &'a str>) -> String {
+    let set: std::collections::BTreeSet<&str> =
+        features.into_iter().filter(|f| !f.is_empty()).collect();
+    set.into_iter().collect::<Vec<_>>().join(",")
+}
+
+/// Validate a `[features]` table whose entries may only enable other
+/// declared features
+fn check_feature_table(
+    features: &std::collections::BTreeMap<String, Vec<String>>,
+) -> Result<(), McpError> {
+    let invalid = |msg: String| McpError::invalid_params(msg, None);
+    for (name, implied) in features {
+        if !is_feature_name(name) {
+            return Err(invalid(format!("invalid feature name: {:?}", name)));
+        }
+        if let Some(unknown) = implied.iter().find(|f| !features.contains_key(f.as_str())) {
+            return Err(invalid(format!(
+                "feature {} enables {:?}, which is not a declared feature",
+                name, unknown
+            )));
+        }
+    }
+    Ok(())
+}
+
+/// The feature sets to check: every subset for "powerset" (the default) or
+/// an explicit list, capped at `MAX_FEATURE_COMBINATIONS`
+fn feature_combinations(
+    features: &std::collections::BTreeMap<String, Vec<String>>,
+    combinations: &Value,
+) -> Result<Vec<Vec<String>>, McpError> {
+    let too_many = |count: usize| {
+        McpError::invalid_params(
+            format!(
+                "{} feature combinations requested, at most {} are allowed",
+                count, MAX_FEATURE_COMBINATIONS
+            ),
+            None,
+        )
+    };
+
+    match combinations {
+        Value::String(mode) => {
+            if mode != "powerset" {
+                return Err(McpError::invalid_params(
+                    format!(
+                        "combinations must be \"powerset\" or a list of feature sets, got: {}",
+                        mode
+                    ),
+                    None,
+                ));
+            }
+            let names: Vec<&String> = features.keys().collect();
+            let count = 1usize.checked_shl(names.len() as u32).unwrap_or(usize::MAX);
+            if count > MAX_FEATURE_COMBINATIONS {
+                return Err(too_many(count));
+            }
+            Ok((0..count)
+                .map(|mask| {
+                    names
+                        .iter()
+                        .enumerate()
+                        .filter(|(bit, _)| mask & (1 << bit) != 0)
+                        .map(|(_, name)| name.to_string())
+                        .collect()
+                })
+                .collect())
+        }
+        Value::Array(sets) => {
+            if sets.len() > MAX_FEATURE_COMBINATIONS {
+                return Err(too_many(sets.len()));
+            }
+            sets.iter()
+                .map(|set| {
+                    let set: Vec<String> = set
+                        .as_array()
+                        .and_then(|items| {
+                            items
+                                .iter()
+                                .map(|item| item.as_str().map(str::to_string))
+                                .collect::<Option<Vec<_>>>()
+                        })
+                        .ok_or_else(|| {
+                            McpError::invalid_params(
+                                "each feature set must be an array of strings",
+                                None,
+                            )
+                        })?;
+                    if let Some(unknown) = set.iter().find(|f| !features.contains_key(f.as_str())) {
+                        return Err(McpError::invalid_params(
+                            format!("unknown feature in combination: {}", unknown),
+                            None,
+                        ));
+                    }
+                    Ok(set)
+                })
+                .collect()
+        }
+        _ => Err(McpError::invalid_params(
+            "combinations must be \"powerset\" or a list of feature sets",
+            None,
+        )),
+    }
+}
+
+/// Append a `[features]` table to a scratch project's manifest. The pool
+/// restores the original manifest when the project is returned.
+fn write_feature_manifest(
+    project_path: &Path,
+    features: &std::collections::BTreeMap<String, Vec<String>>,
+) -> Result<(), McpError> {
+    let write_err = |e: std::io::Error| {
+        McpError::internal_error(format!("Failed to write features: {}", e), None)
+    };
+    let manifest_path = project_path.join("Cargo.toml");
+    let mut manifest = std::fs::read_to_string(&manifest_path).map_err(write_err)?;
+    manifest.push_str("\n[features]\n");
+    for (name, implied) in features {
+        manifest.push_str(&format!("{} = {}\n", name, json!(implied)));
+    }
+    std::fs::write(manifest_path, manifest).map_err(write_err)
+}
+
+/// Set the scratch project's edition. The pool restores the original manifest
+/// when the project is returned.
+fn write_edition(project_path: &Path, edition: &str) -> Result<(), McpError> {
+    let write_err = |e: std::io::Error| {
+        McpError::internal_error(format!("Failed to write edition: {}", e), None)
+    };
+    let manifest_path = project_path.join("Cargo.toml");
+    let manifest = std::fs::read_to_string(&manifest_path).map_err(write_err)?;
+    std::fs::write(manifest_path, msrv::with_edition(&manifest, edition)).map_err(write_err)
+}
+
+/// Add `name = "requirement"` entries to a scratch project's
+/// `[dependencies]` table. The pool restores the original manifest when the
+/// project is returned.
+fn write_dependencies(
+    project_path: &Path,
+    dependencies: &[(String, String)],
+) -> Result<(), McpError> {
+    let write_err = |e: std::io::Error| {
+        McpError::internal_error(format!("Failed to write dependencies: {}", e), None)
+    };
+    let manifest_path = project_path.join("Cargo.toml");
+    let table = format!("[dependencies]\n{}", pool::dependency_lines(dependencies)?);
+    let manifest = std::fs::read_to_string(&manifest_path).map_err(write_err)?;
+    let manifest = if manifest.contains("[dependencies]\n") {
+        manifest.replacen("[dependencies]\n", &table, 1)
+    } else {
+        format!("{}\n\n{}", manifest.trim_end(), table)
+    };
+    std::fs::write(manifest_path, manifest).map_err(write_err)
+}
+
+/// A semver requirement such as `1.0`, `^0.4.2` or `>=1.2, <2`
+fn is_version_requirement(requirement: &str) -> bool {
+    !requirement.trim().is_empty()
+        && requirement.chars().all(|c| {
+            c.is_ascii_alphanumeric()
+                || matches!(
+                    c,
+                    '.' | '*' | '^' | '~' | '<' | '>' | '=' | ',' | ' ' | '-' | '+'
+                )
+        })
+}
+
+/// `cargo tree --edges` kinds
+const TREE_EDGE_KINDS: &[&str] = &[
+    "all",
+    "normal",
+    "build",
+    "dev",
+    "features",
+    "no-normal",
+    "no-build",
+    "no-dev",
+    "no-proc-macro",
+];
+
+/// A cargo package spec: a crate name with an optional `@version`
+fn is_package_spec(spec: &str) -> bool {
+    let (name, version) = spec.split_once('@').unwrap_or((spec, ""));
+    is_feature_name(name)
+        && !name.starts_with('-')
+        && version
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || matches!(c, '.' | '-' | '+'))
+}
+
+/// The `lint` argument of the lint-ignore tools as a full clippy lint name
+fn full_lint_name(lint: &str) -> Result<String, McpError> {
+    clippy_lint_name(lint).ok_or_else(|| {
+        McpError::invalid_params(
+            format!(
+                "lint must be a clippy lint name such as module_name_repetitions, got: {}",
+                lint
+            ),
+            None,
+        )
+    })
+}
+
+/// A todo a clippy finding becomes
+struct ClippyTodo {
+    /// Full lint name, e.g. `clippy::needless_return`
+    lint: String,
+    /// "clippy" for the finding itself, "clippy_help" for its help lines
+    source: &'static str,
+    description: String,
+    file: Option<String>,
+    line: Option<i32>,
+}
+
+/// The todos in clippy's JSON output: each lint, then its help lines except
+/// lint docs links and `-D warnings` override hints
+fn clippy_todos(stdout: &str) -> Vec<ClippyTodo> {
+    let mut todos = Vec::new();
+    let lints = diagnostics::from_json(stdout)
+        .into_iter()
+        .filter(|d| d.code.as_deref().is_some_and(|c| c.starts_with("clippy::")));
+    for lint in lints {
+        let line = lint.line.map(|line| line as i32);
+        let code = lint.code.clone().unwrap_or_default();
+        todos.push(ClippyTodo {
+            lint: code.clone(),
+            source: "clippy",
+            description: format!("{} ({})", lint.message, code),
+            file: lint.file.clone(),
+            line,
+        });
+        for help in lint
+            .help
+            .iter()
+            .filter(|h| !h.starts_with("for further information") && !h.starts_with("to override"))
+        {
+            todos.push(ClippyTodo {
+                lint: code.clone(),
+                source: "clippy_help",
+                description: help.clone(),
+                file: lint.file.clone(),
+                line,
+            });
+        }
+    }
+    todos
+}
+
+/// A clippy lint as its diagnostics name it, `clippy::` prefix included
+fn clippy_lint_name(name: &str) -> Option<String> {
+    let name = name.trim();
+    let name = name.strip_prefix("clippy::").unwrap_or(name);
+    (!name.is_empty()
+        && name
+            .bytes()
+            .all(|b| b.is_ascii_lowercase() || b.is_ascii_digit() || b == b'_'))
+    .then(|| format!("clippy::{}", name))
+}
+
+/// Release builds for `cargo_bloat` take longer than debug builds
+const BLOAT_TIMEOUT: Option<Duration> = Some(Duration::from_secs(120));
+
+/// `semver_check` builds rustdoc JSON for both versions of the crate
+const SEMVER_TIMEOUT: Option<Duration> = Some(Duration::from_secs(180));
+
+/// What `semver_check` compares the snippet against
+enum SemverBaseline<'a> {
+    /// A prior version of the snippet
+    Code(&'a str),
+    /// A version of `crate_name` published on crates.io
+    Published {
+        crate_name: &'a str,
+        version: &'a str,
+    },
+}
+
+/// Snippet tools that accept `target` and `no_std`
+const TARGET_TOOLS: &[&str] = &["cargo_check", "cargo_build", "cargo_clippy"];
+
+/// Cargo feature guarding the panic handler added by `write_no_std_scaffold`
+const NO_STD_PANIC_FEATURE: &str = "panic-handler";
+
+/// Per-call additions to a snippet tool's cargo run. `details` are echoed in
+/// the response and stored with the analysis output.
+#[derive(Debug, Default, Clone)]
+pub struct Invocation {
+    /// Replaces the tool's cargo command from `cargo_tool_command`
+    pub command: Option<&'static [&'static str]>,
+    /// Cargo arguments inserted before any `--` in the tool's command
+    pub args: Vec<String>,
+    pub env: Vec<(String, String)>,
+    /// Build the snippet as a library instead of a binary
+    pub library: bool,
+    /// With `library`, add `#![no_std]` and a stub panic handler
+    pub no_std: bool,
+    /// Locate the built executable from cargo's JSON messages on stdout
+    pub collect_artifact: bool,
+    /// Copy `target/doc` out of the project after a successful run
+    pub save_docs: bool,
+    /// Run even when a cached result exists
+    pub no_cache: bool,
+    /// Leave the project on disk instead of returning it to the pool
+    pub keep_project: bool,
+    /// Crate names and version requirements added to `[dependencies]`
+    pub dependencies: Vec<(String, String)>,
+    /// Run against this published crate instead of the snippet
+    pub crate_source: Option<CrateSource>,
+    /// Edition written into the manifest, for toolchains that predate the
+    /// one the scaffold chose
+    pub edition: Option<&'static str>,
+    /// Project files written alongside the snippet, or on their own when
+    /// they include a root `Cargo.toml`
+    pub files: Vec<(PathBuf, String)>,
+    /// Resolve the dependencies of `files`, or a `crate_source` crate and
+    /// its dependencies, first and refuse to build ones that run code at
+    /// build time: build scripts and proc-macros
+    pub inspect_build_code: bool,
+    /// With `inspect_build_code`, build anyway and report them
+    pub allow_build_scripts: bool,
+    /// `code` was a whole project pasted as one string, split into `files`
+    pub code_split: bool,
+    /// Cap on stdout and stderr each, in responses and stored outputs
+    pub max_output_bytes: Option<usize>,
+    pub details: serde_json::Map<String, Value>,
+}
+
+impl Invocation {
+    /// Record the toolchain as `rustc_version`, its first line, and
+    /// `toolchain`, the whole of `rustc --version --verbose`
+    fn set_toolchain(&mut self, toolchain: Option<Toolchain>) {
+        if let Some(toolchain) = toolchain {
+            self.details
+                .insert("rustc_version".to_string(), json!(toolchain.version));
+            self.details
+                .insert("toolchain".to_string(), json!(toolchain));
+        }
+    }
+
+    fn set_target(&mut self, target: &str) {
+        self.args.push("--target".to_string());
+        self.args.push(target.to_string());
+        self.details.insert("target".to_string(), json!(target));
+    }
+
+    /// The tool's cargo command with `args` spliced in ahead of the arguments
+    /// cargo forwards to the underlying tool
+    fn cargo_args(&self, base: &[&str]) -> Vec<String> {
+        let split = base
+            .iter()
+            .position(|arg| *arg == "--")
+            .unwrap_or(base.len());
+        let mut args: Vec<String> = base[..split].iter().map(|a| a.to_string()).collect();
+        args.extend(self.args.iter().cloned());
+        if self.no_std {
+            args.push("--features".to_string());
+            args.push(NO_STD_PANIC_FEATURE.to_string());
+        }
+        args.extend(base[split..].iter().map(|a| a.to_string()));
+        args
+    }
+
+    fn set_compile_flags(&mut self, flags: &CompileFlags) {
+        if flags.is_empty() {
+            return;
+        }
+        self.env
+            .push(("RUSTFLAGS".to_string(), flags.rustflags_env()));
+        self.details
+            .insert("rustflags".to_string(), json!(flags.effective()));
+    }
+
+    /// Add `details` to a response or stored output object
+    fn annotate(&self, output: &mut Value) {
+        if let Some(object) = output.as_object_mut() {
+            object.extend(self.details.clone());
+        }
+    }
+}
+
+/// Prefix of the resources holding `rustc --explain` output
+const EXPLAIN_SCHEME: &str = "explain://";
+
+/// `rustc --explain` output, as `rustc_explain` and `explain://` resources
+/// return it
+struct Explanation {
+    text: String,
+    stderr: String,
+    success: bool,
+    /// Read from the explanations table rather than running rustc
+    cached: bool,
+}
+
+/// An error code rustc can explain, e.g. E0308
+fn is_rustc_error_code(code: &str) -> bool {
+    code.len() > 1 && code.starts_with('E') && code[1..].bytes().all(|b| b.is_ascii_digit())
+}
+
+/// The opening paragraph of an explanation, on one line
+fn explanation_summary(text: &str) -> String {
+    text.trim_start()
+        .split("\n\n")
+        .next()
+        .unwrap_or_default()
+        .split_whitespace()
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+/// rust-analyzer session used by `rust_analyzer` calls without a `session`
+const DEFAULT_LSP_SESSION: &str = "default";
+
+/// Tools whose cargo command reports diagnostics as JSON, returned as a
+/// `diagnostics` array
+const DIAGNOSTIC_TOOLS: &[&str] = &["cargo_check", "cargo_clippy", "rust_analyzer", "check_msrv"];
+
+/// Snippet tools that can analyze a published crate with `crate_source`
+const CRATE_SOURCE_TOOLS: &[&str] = &[
+    "cargo_clippy",
+    "cargo_audit",
+    "cargo_tree",
+    "cargo_deps_report",
+    "cargo_doc",
+];
+
+/// Of those, the ones that compile the crate, running its build scripts and
+/// proc-macros and its dependencies'
+const CRATE_SOURCE_BUILD_TOOLS: &[&str] = &["cargo_clippy", "cargo_doc"];
+
+/// Snippet tools that take `files`, and `package`/`workspace` to pick what
+/// cargo builds
+const PROJECT_FILE_TOOLS: &[&str] = &["cargo_check", "cargo_clippy", "cargo_test", "cargo_build"];
+
+/// Most files a `files` argument may hold
+const MAX_PROJECT_FILES: usize = 64;
+
+/// Lay `files` out as the project: validate their sources, generate
+/// manifests for workspace members without one, and inspect build code when
+/// there is a manifest
+fn add_project_files(
+    invocation: &mut Invocation,
+    files: Vec<(String, String)>,
+) -> Result<(), McpError> {
+    for (path, contents) in files {
+        if let Some(reason) = tool_invocation::project_file_error(Path::new(&path)) {
+            return Err(McpError::invalid_params(
+                reason,
+                Some(json!({"path": path})),
+            ));
+        }
+        if path.ends_with(".rs") && !contents.trim().is_empty() {
+            validate_rust_code(&contents)?;
+        }
+        invocation.files.push((PathBuf::from(path), contents));
+    }
+    if let Some(workspace) =
+        Workspace::detect(&invocation.files).map_err(|e| McpError::invalid_params(e, None))?
+    {
+        invocation
+            .details
+            .insert("workspace_members".to_string(), json!(workspace.members));
+        invocation.files.extend(workspace.generated);
+    }
+    // A manifest can pull in dependencies whose build code runs here
+    invocation.inspect_build_code = invocation
+        .files
+        .iter()
+        .any(|(path, _)| path.file_name() == Some("Cargo.toml".as_ref()));
+    Ok(())
+}
+
+/// Read `files`, `package` and `workspace` into `invocation`. A workspace
+/// manifest among the files gets manifests generated for members that came
+/// without one.
+fn set_project_files(invocation: &mut Invocation, options: &args::Options) -> Result<(), McpError> {
+    if let Some(files) = &options.files {
+        if files.len() > MAX_PROJECT_FILES {
+            return Err(McpError::invalid_params(
+                format!("files may hold at most {} files", MAX_PROJECT_FILES),
+                None,
+            ));
+        }
+        for (option, given) in [
+            ("crate_source", options.crate_source.is_some()),
+            ("no_std", options.no_std),
+        ] {
+            if given {
+                return Err(McpError::invalid_params(
+                    format!("{} can't be combined with files", option),
+                    None,
+                ));
+            }
+        }
+        let has_manifest = files.contains_key("Cargo.toml");
+        if has_manifest && options.code.is_some() {
+            return Err(McpError::invalid_params(
+                "code can't be combined with a root Cargo.toml in files; add it as a file instead",
+                None,
+            ));
+        }
+        let project = files
+            .iter()
+            .map(|(path, contents)| (path.clone(), contents.clone()))
+            .collect();
+        add_project_files(invocation, project)?;
+        let paths: Vec<&str> = files.keys().map(String::as_str).collect();
+        invocation.details.insert("files".to_string(), json!(paths));
+    } else if let Some(code) = options
+        .code
+        .as_deref()
+        .filter(|_| options.crate_source.is_none() && !options.no_std)
+    {
+        match paste::split(code) {
+            Paste::Single => {}
+            Paste::Ambiguous(reason) => {
+                invocation
+                    .details
+                    .insert("parsed_as_single_file".to_string(), json!(true));
+                invocation
+                    .details
+                    .insert("paste_note".to_string(), json!(reason));
+            }
+            Paste::Files(files) => {
+                let paths: Vec<&str> = files.iter().map(|(path, _)| path.as_str()).collect();
+                invocation
+                    .details
+                    .insert("reconstructed_files".to_string(), json!(paths));
+                add_project_files(invocation, files)?;
+                invocation.code_split = true;
+            }
+        }
+    }
+    if options.package.is_some() && options.workspace {
+        return Err(McpError::invalid_params(
+            "pass either package or workspace, not both",
+            None,
+        ));
+    }
+    if let Some(package) = &options.package {
+        if !is_package_spec(package) {
+            return Err(McpError::invalid_params(
+                "package must be a package spec such as app or app@0.1.0",
+                None,
+            ));
+        }
+        invocation.args.push("-p".to_string());
+        invocation.args.push(package.clone());
+        invocation
+            .details
+            .insert("package".to_string(), json!(package));
+    }
+    if options.workspace {
+        invocation.args.push("--workspace".to_string());
+        invocation
+            .details
+            .insert("workspace".to_string(), json!(true));
+    }
+    Ok(())
+}
+
+/// Add `dependencies` to `invocation`, for the scratch project's manifest
+fn set_dependencies(
+    invocation: &mut Invocation,
+    dependencies: Option<&std::collections::BTreeMap<String, String>>,
+) -> Result<(), McpError> {
+    let Some(dependencies) = dependencies else {
+        return Ok(());
+    };
+    for (name, requirement) in dependencies {
+        if !is_version_requirement(requirement) {
+            return Err(McpError::invalid_params(
+                format!(
+                    "dependency {} needs a version requirement such as \"1.0\" or \">=0.4, <0.6\"",
+                    name
+                ),
+                None,
+            ));
+        }
+        if !is_package_spec(name) || name.contains('@') {
+            return Err(McpError::invalid_params(
+                format!("invalid crate name: {:?}", name),
+                None,
+            ));
+        }
+        invocation
+            .dependencies
+            .push((name.clone(), requirement.clone()));
+    }
+    invocation
+        .details
+        .insert("dependencies".to_string(), json!(dependencies));
+    Ok(())
+}
+
+/// Shortest timeout a `crate_source` run gets, whatever the tool's own
+const CRATE_SOURCE_MIN_TIMEOUT: Duration = Duration::from_secs(180);
+
+/// Tools with an entry in `cargo_tool_command`
+const SNIPPET_TOOLS: &[&str] = &[
+    "cargo_fmt",
+    "cargo_clippy",
+    "cargo_check",
+    "cargo_fix",
+    "cargo_audit",
+    "cargo_deny",
+    "cargo_coverage",
+    "cargo_test",
+    "cargo_build",
+    "cargo_tree",
+    "cargo_update",
+    "cargo_deps_report",
+    "find_duplicate_deps",
+    "cargo_doc",
+    "rust_analyzer",
+    "rust_unused",
+    "check_msrv",
+];
+
+/// Forces the unused-code lints on for `rust_unused`; unlike `-W`, the
+/// snippet's own `#![allow(unused)]` can't silence them
+const UNUSED_RUSTFLAGS: &str = "--force-warn unused --force-warn dead_code";
+
+/// Diagnostic levels stored in the errors table's `severity` column
+const SEVERITIES: &[&str] = &["error", "warning", "note", "help"];
+
+/// Project files besides the snippet that change a run's output, read into
+/// the result cache key
+const CACHE_KEY_FILES: &[&str] = &["Cargo.toml", "deny.toml", "src/lib.rs"];
+
+/// The snippet tool behind each `cargo_analyze` check name
+fn analyze_check_tool(check: &str) -> Option<&'static str> {
+    match check {
+        "fmt" => Some("cargo_fmt"),
+        "clippy" => Some("cargo_clippy"),
+        "check" => Some("cargo_check"),
+        "test" => Some("cargo_test"),
+        "audit" => Some("cargo_audit"),
+        _ => None,
+    }
+}
+
+/// Per-step responses from `run_batch`, `None` for skipped steps
+struct BatchRun {
+    batch_id: Option<String>,
+    steps: Vec<Option<Value>>,
+    success: bool,
+}
+
+/// Id shared by the analyses of one `cargo_analyze` or `pipeline` call
+fn new_batch_id(code: &str) -> String {
+    let nanos = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map(|d| d.as_nanos())
+        .unwrap_or_default();
+    code_hash(&format!("{}:{}", nanos, code))[..16].to_string()
+}
+
+/// Whether a tool spawns cargo builds and so waits for a `RunLimiter` slot
+fn runs_cargo(tool: &str) -> bool {
+    cargo_tool_command(tool).is_some()
+        || matches!(
+            tool,
+            "cargo_analyze"
+                | "pipeline"
+                | "apply_suggestion"
+                | "rust_type_of"
+                | "code_intel"
+                | "cargo_bloat"
+                | "cargo_machete"
+                | "semver_check"
+                | "cargo_feature_check"
+                | "rerun_analysis"
+                | "reconcile_todos"
+        )
+}
+
+/// Cargo arguments and timeout for each tool that runs against a code snippet
+pub fn cargo_tool_command(tool: &str) -> Option<(&'static [&'static str], Option<Duration>)> {
+    let command: (&'static [&'static str], Option<Duration>) = match tool {
+        "cargo_fmt" => (&["fmt", "--", "--emit=stdout"], None),
+        // Diagnostics go to stdout as JSON for `diagnostics::from_json`
+        "cargo_clippy" => (
+            &["clippy", "--message-format=json", "--", "-D", "warnings"],
+            Some(Duration::from_secs(30)),
+        ),
+        "cargo_check" => (
+            &["check", "--message-format=json"],
+            Some(Duration::from_secs(30)),
+        ),
+        "cargo_fix" => (&["fix", "--allow-dirty"], Some(Duration::from_secs(60))),
+        // cargo audit requires cargo-audit to be installed
+        "cargo_audit" => (&["audit", "--json"], Some(Duration::from_secs(60))),
+        // The first run clones the advisory database
+        "cargo_deny" => (
+            &["deny", "--format", "json", "check"],
+            Some(Duration::from_secs(180)),
+        ),
+        // cargo llvm-cov requires cargo-llvm-cov and llvm-tools-preview
+        "cargo_coverage" => (&["llvm-cov", "--json"], Some(Duration::from_secs(180))),
+        "cargo_test" => (&["test"], Some(Duration::from_secs(60))),
+        "cargo_build" => (&["build"], Some(Duration::from_secs(60))),
+        "cargo_tree" => (&["tree"], Some(Duration::from_secs(30))),
+        // Reads the index but downloads no crates
+        "cargo_update" => (&["update"], Some(Duration::from_secs(60))),
+        "cargo_deps_report" => (
+            &["metadata", "--format-version", "1"],
+            Some(Duration::from_secs(60)),
+        ),
+        "find_duplicate_deps" => (&["tree", "--duplicates"], Some(Duration::from_secs(30))),
+        "cargo_doc" => (&["doc"], Some(Duration::from_secs(60))),
+        // rust-analyzer check
+        "rust_analyzer" => (
+            &["check", "--message-format=json"],
+            Some(Duration::from_secs(30)),
+        ),
+        // With UNUSED_RUSTFLAGS
+        "rust_unused" => (
+            &["check", "--message-format=json"],
+            Some(Duration::from_secs(30)),
+        ),
+        // Under RUSTUP_TOOLCHAIN; older compilers are slower
+        "check_msrv" => (
+            &["check", "--message-format=json"],
+            Some(Duration::from_secs(120)),
+        ),
+        _ => return None,
+    };
+    Some(command)
+}
+
+/// Run cargo with `args` on `code` as `src/main.rs` of a fresh scratch
+/// project. `ToolInvocation` covers the same with more options.
+pub async fn run_rust_tool(
+    code: &str,
+    args: &[&str],
+    timeout: Option<Duration>,
+) -> Result<ExecResult, McpError> {
+    let mut invocation = ToolInvocation::new(code).args(args.iter().copied());
+    if let Some(timeout) = timeout {
+        invocation = invocation.timeout(timeout);
+    }
+    invocation.run().await
+}
+
+/// Turn a scratch project into a library crate: `code` goes to
+/// `src/lib.rs` and the binary target is disabled. For `no_std`, the code
+/// gains `#![no_std]` if it lacks it and a minimal panic handler is compiled
+/// in behind the `panic-handler` feature unless the code brings its own. The
+/// pool restores the manifest and removes `src/lib.rs` when the project is
+/// returned.
+fn write_library_scaffold(project_path: &Path, code: &str, no_std: bool) -> Result<(), McpError> {
+    let write_err = |e: std::io::Error| {
+        McpError::internal_error(format!("Failed to write library scaffold: {}", e), None)
+    };
+
+    let mut lib = String::new();
+    if no_std && !code.contains("#![no_std]") {
+        lib.push_str("#![no_std]\n");
+    }
+    lib.push_str(code);
+    if no_std && !code.contains("#[panic_handler]") {
+        lib.push_str(&format!(
+            "\n\n#[cfg(feature = \"{feature}\")]\n#[panic_handler]\nfn panic(_info: &core::panic::PanicInfo) -> ! {{\n    loop {{}}\n}}\n",
+            feature = NO_STD_PANIC_FEATURE
+        ));
+    }
+    std::fs::write(project_path.join("src").join("lib.rs"), lib).map_err(write_err)?;
+
+    let manifest_path = project_path.join("Cargo.toml");
+    let manifest = std::fs::read_to_string(&manifest_path).map_err(write_err)?;
+    let manifest = manifest.replacen("[package]\n", "[package]\nautobins = false\n", 1);
+    let mut manifest = format!("{}\n[lib]\npath = \"src/lib.rs\"\n", manifest.trim_end());
+    if no_std {
+        manifest.push_str(&format!(
+            "\n[features]\n{} = []\n\n[profile.dev]\npanic = \"abort\"\n\n[profile.release]\npanic = \"abort\"\n",
+            NO_STD_PANIC_FEATURE
+        ));
+    }
+    std::fs::write(manifest_path, manifest).map_err(write_err)
+}
+
+/// Write `code` to `src/main.rs` of an initialized project and run cargo there
+pub async fn run_in_project(
+    project_path: &Path,
+    code: &str,
+    args: &[&str],
+    timeout: Option<Duration>,
+    env: &[(String, String)],
+    child_env: &ChildEnv,
+) -> Result<ExecResult, McpError> {
+    // Write the provided code to src/main.rs
+    let main_rs_path = project_path.join("src").join("main.rs");
+    std::fs::write(&main_rs_path, code)
+        .map_err(|e| McpError::internal_error(format!("Failed to write code: {}", e), None))?;
+    run_cargo(project_path, args, timeout, env, child_env).await
+}
+
+/// Run cargo in a project as it is on disk
+pub async fn run_cargo(
+    project_path: &Path,
+    args: &[&str],
+    timeout: Option<Duration>,
+    env: &[(String, String)],
+    child_env: &ChildEnv,
+) -> Result<ExecResult, McpError> {
+    let start = Instant::now();
+    let mut cmd = Command::new("cargo");
+    cmd.args(args)
+        .current_dir(project_path)
+        .env_clear()
+        .envs(child_env.vars())
+        .env("CARGO_TERM_COLOR", "never")
+        .envs(env.iter().map(|(key, value)| (key, value)));
+    // So `kill_cargo` reaches what cargo started
+    ProcessTree::prepare(&mut cmd);
+    child_env.limits().apply(&mut cmd);
+
+    let mut child = cmd
+        .stdout(std::process::Stdio::piped())
+        .stderr(std::process::Stdio::piped())
+        .spawn()
+        .map_err(|e| McpError::internal_error(format!("Failed to spawn cargo: {}", e), None))?;
+    let tree = ProcessTree::attach(&child);
+
+    let stdout_reader = child
+        .stdout
+        .take()
+        .ok_or_else(|| McpError::internal_error("Failed to capture stdout", None))?;
+    let stderr_reader = child
+        .stderr
+        .take()
+        .ok_or_else(|| McpError::internal_error("Failed to capture stderr", None))?;
+
+    // Stream both pipes so lines reach the client's progress token as cargo
+    // writes them
+    let progress = progress::current();
+    let out_handle = tokio::spawn(progress::read_lines(
+        stdout_reader,
+        progress.clone(),
+        progress::Pipe::Stdout,
+    ));
+    let err_handle = tokio::spawn(progress::read_lines(
+        stderr_reader,
+        progress,
+        progress::Pipe::Stderr,
+    ));
+
+    // A timeout is a tool result, not a server fault: kill cargo and report
+    // whatever it wrote with `timed_out` set
+    let wait = async {
+        match timeout {
+            Some(dur) => tokio::time::timeout(dur, child.wait()).await.ok(),
+            None => Some(child.wait().await),
+        }
+    };
+    let outcome = tokio::select! {
+        outcome = wait => Some(outcome),
+        _ = cancellation::cancelled() => None,
+    };
+    // A cancelled call has no one to report to: kill cargo and fail, letting
+    // the project be cleaned up as the call unwinds
+    let Some(outcome) = outcome else {
+        kill_cargo(&mut child, &tree).await;
+        out_handle.abort();
+        err_handle.abort();
+        return Err(McpError::internal_error("Tool call cancelled", None));
+    };
+    let status = match outcome {
+        Some(Ok(s)) => Some(s),
+        Some(Err(e)) => {
+            return Err(McpError::internal_error(
+                format!("Failed to wait for cargo: {}", e),
+                None,
+            ));
+        }
+        None => {
+            kill_cargo(&mut child, &tree).await;
+            None
+        }
+    };
+    let timed_out = status.is_none();
+
+    let duration_ms = start.elapsed().as_millis();
+
+    let stdout_bytes = read_output(out_handle, timed_out)
+        .await
+        .map_err(|e| McpError::internal_error(format!("Stdout task failed: {}", e), None))?;
+    let stderr_bytes = read_output(err_handle, timed_out)
+        .await
+        .map_err(|e| McpError::internal_error(format!("Stderr task failed: {}", e), None))?;
+
+    let stdout = String::from_utf8_lossy(&stdout_bytes).to_string();
+    let stderr = String::from_utf8_lossy(&stderr_bytes).to_string();
+    // Hitting a limit kills rustc or the snippet, not the server: report
+    // which one, like a timeout
+    let resource_exceeded = match status {
+        Some(s) if !s.success() => limits::exceeded(exit_signal(s), &stderr),
+        _ => None,
+    };
+    let status = status.and_then(|s| s.code()).unwrap_or(-1);
+
+    Ok(ExecResult {
+        stdout,
+        stderr,
+        status,
+        duration_ms,
+        artifact: None,
+        docs_path: None,
+        project_path: None,
+        crate_source: None,
+        cached: false,
+        timed_out,
+        resource_exceeded,
+        build_code: Vec::new(),
+    })
+}
+
+fn exit_signal(status: std::process::ExitStatus) -> Option<i32> {
+    #[cfg(unix)]
+    return std::os::unix::process::ExitStatusExt::signal(&status);
+    #[cfg(not(unix))]
+    None
+}
+
+/// Kill cargo along with the test binaries, build scripts and rustc
+/// processes it started, which would otherwise keep running
+async fn kill_cargo(child: &mut tokio::process::Child, tree: &ProcessTree) {
+    tree.kill(child);
+    let _ = child.kill().await;
+    let _ = child.wait().await;
+}
+
+/// How long to keep reading output after a timeout kills cargo
+const OUTPUT_GRACE: Duration = Duration::from_secs(1);
+
+/// Wait for a pipe reader. After a timeout, processes cargo spawned (such as
+/// a looping test binary) can hold the pipe open, so give up after
+/// `OUTPUT_GRACE` and return nothing rather than hang.
+async fn read_output(
+    handle: tokio::task::JoinHandle<Vec<u8>>,
+    timed_out: bool,
+) -> Result<Vec<u8>, tokio::task::JoinError> {
+    if !timed_out {
+        return handle.await;
+    }
+    let abort = handle.abort_handle();
+    match tokio::time::timeout(OUTPUT_GRACE, handle).await {
+        Ok(output) => output,
+        Err(_) => {
+            abort.abort();
+            Ok(Vec::new())
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn test_db() -> (tempfile::TempDir, Database) {
+        let dir = tempfile::tempdir().expect("tempdir");
+        let db = Database::new(PersistenceMode::Path(dir.path().join("test.db")))
+            .expect("open db")
+            .expect("db enabled");
+        (dir, db)
+    }
+
+    const CLIPPY_STDOUT: &str = r#"{"reason":"compiler-message","message":{"rendered":"warning: this `if` has identical blocks\n","children":[{"children":[],"code":null,"level":"help","message":"consider removing the `else` block","rendered":null,"spans":[]}],"level":"warning","message":"this `if` has identical blocks","spans":[{"byte_end":58,"byte_start":41,"column_end":32,"column_start":15,"file_name":"src/main.rs","is_primary":true,"line_end":3,"line_start":3}],"code":{"code":"clippy::if_same_then_else","explanation":null}}}"#;
+
+    #[test]
+    fn repeated_clippy_output_stores_single_todo() {
+        let (_dir, db) = test_db();
+
+        RustyToolsServer::parse_and_store_clippy_todos(&db, CLIPPY_STDOUT).unwrap();
+        RustyToolsServer::parse_and_store_clippy_todos(&db, CLIPPY_STDOUT).unwrap();
+
+        let todos = db.get_todos(false, None).unwrap();
+        let clippy: Vec<_> = todos.iter().filter(|t| t.source == "clippy").collect();
+        let help: Vec<_> = todos.iter().filter(|t| t.source == "clippy_help").collect();
+        assert_eq!(clippy.len(), 1);
+        assert_eq!(help.len(), 1);
+    }
+
+    #[test]
+    fn reconciling_completes_todos_clippy_no_reports() {
+        let (_dir, db) = test_db();
+        RustyToolsServer::parse_and_store_clippy_todos(&db, CLIPPY_STDOUT).unwrap();
+        db.store_todo(
+            "clippy",
+            "needless return (clippy::needless_return)",
+            None,
+            None,
+        )
+        .unwrap();
+        db.store_todo("unused", "unused variable: `x`", None, None)
+            .unwrap();
+
+        let findings = clippy_todos(CLIPPY_STDOUT);
+        let current: Vec<(&str, &str)> = findings
+            .iter()
+            .map(|todo| (todo.source, todo.description.as_str()))
+            .collect();
+        let completed = db.reconcile_todos(&current).unwrap();
+        assert_eq!(completed.len(), 1);
+        assert_eq!(
+            completed[0].description,
+            "needless return (clippy::needless_return)"
+        );
+        assert!(completed[0].completed);
+        let open_sources = |db: &Database| {
+            let mut sources: Vec<String> = db
+                .get_todos(false, None)
+                .unwrap()
+                .into_iter()
+                .map(|t| t.source)
+                .collect();
+            sources.sort();
+            sources
+        };
+        assert_eq!(open_sources(&db), ["clippy", "clippy_help", "unused"]);
+
+        // Clean output completes the rest of clippy's todos only
+        assert_eq!(db.reconcile_todos(&[]).unwrap().len(), 2);
+        assert_eq!(open_sources(&db), ["unused"]);
+    }
+
+    #[test]
+    fn ignored_lints_close_and_skip_todos() {
+        let (_dir, db) = test_db();
+        RustyToolsServer::parse_and_store_clippy_todos(&db, CLIPPY_STDOUT).unwrap();
+        let open_sources = |db: &Database| {
+            let mut sources: Vec<String> = db
+                .get_todos(false, None)
+                .unwrap()
+                .into_iter()
+                .map(|t| t.source)
+                .collect();
+            sources.sort();
+            sources
+        };
+
+        let lint = clippy_lint_name("if_same_then_else").unwrap();
+        assert_eq!(lint, "clippy::if_same_then_else");
+        assert_eq!(
+            clippy_lint_name("clippy::if_same_then_else"),
+            Some(lint.clone())
+        );
+        assert_eq!(clippy_lint_name("bad lint"), None);
+
+        assert_eq!(db.ignore_lint(&lint, None).unwrap(), 1);
+        assert_eq!(open_sources(&db), ["clippy_help"]);
+        let closed = db.get_todos(true, None).unwrap();
+        assert!(
+            closed
+                .iter()
+                .any(|t| t.suppressed_by.as_deref() == Some(lint.as_str()))
+        );
+
+        // Later runs skip the lint, help included, and count it
+        RustyToolsServer::parse_and_store_clippy_todos(&db, CLIPPY_STDOUT).unwrap();
+        assert_eq!(open_sources(&db), ["clippy_help"]);
+        let ignores = db.get_lint_ignores().unwrap();
+        assert_eq!((ignores.len(), ignores[0].suppressed), (1, 2));
+
+        assert_eq!(db.unignore_lint(&lint).unwrap(), Some(1));
+        assert_eq!(db.unignore_lint(&lint).unwrap(), None);
+        assert_eq!(open_sources(&db), ["clippy", "clippy_help"]);
+
+        // An expired snooze no longer applies
+        db.ignore_lint("clippy::needless_return", Some("2000-01-01"))
+            .unwrap();
+        assert!(db.get_lint_ignores().unwrap().is_empty());
+    }
+
+    fn persist_options(persist: bool) -> args::Options {
+        args::Options {
+            persist,
+            ..args::Options::default()
+        }
+    }
+
+    /// The invocation `tool` runs with for a call's `arguments`
+    fn invocation_for(
+        server: &RustyToolsServer,
+        tool: &str,
+        arguments: Value,
+    ) -> Result<Invocation, McpError> {
+        let options = args::parse::<args::Options>(arguments.as_object())?;
+        server.build_invocation(tool, &options)
+    }
+
+    fn clippy_result() -> ExecResult {
+        ExecResult {
+            stdout: CLIPPY_STDOUT.to_string(),
+            stderr: String::new(),
+            status: 0,
+            duration_ms: 5,
+            artifact: None,
+            docs_path: None,
+            project_path: None,
+            crate_source: None,
+            cached: false,
+            timed_out: false,
+            resource_exceeded: None,
+            build_code: Vec::new(),
+        }
+    }
+
+    #[tokio::test]
+    async fn errors_only_responses_drop_everything_but_errors() {
+        let dir = tempfile::tempdir().unwrap();
+        let config = ServerConfig {
+            pool_size: 0,
+            ..ServerConfig::default()
+        };
+        let server =
+            RustyToolsServer::with_config(PersistenceMode::Path(dir.path().join("t.db")), config);
+        let options =
+            |arguments: Value| args::parse::<args::Options>(arguments.as_object()).unwrap();
+        let errors_only = options(json!({"errors_only": true, "persist": true}));
+        let verbosity = errors_only.verbosity().unwrap();
+        assert_eq!(verbosity, Verbosity::Errors);
+        assert!(
+            options(json!({"errors_only": true, "verbosity": "full"}))
+                .verbosity()
+                .is_err()
+        );
+
+        // Clippy's warnings aren't errors
+        let invocation = Invocation::default();
+        let response = tool_response("cargo_clippy", "", &clippy_result(), verbosity, &invocation);
+        assert_eq!(response["diagnostics"], json!([]));
+        assert!(response.get("stdout").is_none() && response.get("stderr").is_none());
+
+        let result = ExecResult {
+            stdout: "build script output\n".to_string(),
+            stderr: "warning: unused variable: `y`\n --> src/main.rs:2:9\n\nerror[E0308]: mismatched types\n --> src/main.rs:3:18\n".to_string(),
+            status: 101,
+            ..clippy_result()
+        };
+        let response = tool_response("cargo_build", "", &result, verbosity, &invocation);
+        assert!(response.get("stdout").is_none());
+        assert_eq!(
+            response["stderr"],
+            "error[E0308]: mismatched types\n --> src/main.rs:3:18"
+        );
+
+        // The stored output is still whole
+        let mut response = json!({});
+        let id = server
+            .persist_analysis(
+                &mut response,
+                &errors_only,
+                "cargo_build",
+                "fn main() {}",
+                &result,
+                &invocation,
+            )
+            .unwrap();
+        let (_, stored) = server
+            .db
+            .as_ref()
+            .unwrap()
+            .lock()
+            .get_analysis_input(id)
+            .unwrap()
+            .unwrap();
+        assert_eq!(stored["stderr"], result.stderr);
+        assert_eq!(stored["stdout"], result.stdout);
+    }
+
+    #[tokio::test]
+    async fn long_output_is_capped_in_responses_and_storage() {
+        let dir = tempfile::tempdir().unwrap();
+        let config = ServerConfig {
+            pool_size: 0,
+            ..ServerConfig::default()
+        };
+        let server =
+            RustyToolsServer::with_config(PersistenceMode::Path(dir.path().join("t.db")), config);
+        let args = json!({"max_output_bytes": 100});
+        let invocation = invocation_for(&server, "cargo_build", args).unwrap();
+        let result = ExecResult {
+            stderr: "error[E0277]: the trait bound `T: Foo` is not satisfied\n".repeat(1000),
+            ..clippy_result()
+        };
+        let code = "fn main() {}";
+
+        let response = tool_response("cargo_build", code, &result, Verbosity::Full, &invocation);
+        assert_eq!(response["truncated"], true);
+        let stderr = response["stderr"].as_str().unwrap().to_string();
+        assert!(stderr.ends_with(&format!(
+            "...[truncated {} bytes]",
+            result.stderr.len() - 100
+        )));
+        assert!(stderr.len() < 200);
+
+        let mut response = json!({});
+        let id = server
+            .persist_analysis(
+                &mut response,
+                &persist_options(true),
+                "cargo_build",
+                code,
+                &result,
+                &invocation,
+            )
+            .unwrap();
+        let (_, stored) = server
+            .db
+            .as_ref()
+            .unwrap()
+            .lock()
+            .get_analysis_input(id)
+            .unwrap()
+            .unwrap();
+        assert_eq!(stored["truncated"], true);
+        assert_eq!(stored["stderr"], stderr);
+        assert_eq!(stored["max_output_bytes"], 100);
+
+        let invalid = json!({"max_output_bytes": 0});
+        assert!(invocation_for(&server, "cargo_build", invalid).is_err());
+    }
+
+    #[tokio::test]
+    async fn persistence_is_reported_with_a_retrievable_analysis_id() {
+        let dir = tempfile::tempdir().unwrap();
+        let config = ServerConfig {
+            pool_size: 0,
+            ..ServerConfig::default()
+        };
+        let server =
+            RustyToolsServer::with_config(PersistenceMode::Path(dir.path().join("t.db")), config);
+        let code = "fn main() {}";
+
+        let mut invocation = Invocation::default();
+        invocation.set_toolchain(Toolchain::parse(
+            "rustc 1.85.0 (4d91de4e4 2025-02-17)\nbinary: rustc\nhost: x86_64-unknown-linux-gnu\nrelease: 1.85.0\nLLVM version: 19.1.7\n",
+        ));
+        let mut response = json!({});
+        let id = server.persist_analysis(
+            &mut response,
+            &persist_options(true),
+            "cargo_clippy",
+            code,
+            &clippy_result(),
+            &invocation,
+        );
+        let persistence = &response["persistence"];
+        assert_eq!(persistence["requested"], true);
+        assert_eq!(persistence["stored"], true);
+        assert_eq!(persistence["analysis_id"], json!(id));
+        assert!(persistence["error"].is_null());
+
+        let history = server
+            .db
+            .as_ref()
+            .unwrap()
+            .lock()
+            .get_error_history(&HistoryFilter::default())
+            .unwrap();
+        assert!(!history.is_empty());
+        assert!(history.iter().all(|e| Some(e.analysis_id) == id));
+        assert!(
+            history
+                .iter()
+                .all(|e| e.rustc_version.as_deref() == Some("rustc 1.85.0 (4d91de4e4 2025-02-17)"))
+        );
+        let record = server
+            .db
+            .as_ref()
+            .unwrap()
+            .lock()
+            .get_analysis(id.unwrap())
+            .unwrap()
+            .unwrap();
+        assert_eq!(
+            record.rustc_version.as_deref(),
+            Some("rustc 1.85.0 (4d91de4e4 2025-02-17)")
+        );
+        let toolchain = record.toolchain.unwrap();
+        assert_eq!(toolchain["host"], "x86_64-unknown-linux-gnu");
+        assert_eq!(toolchain["llvm_version"], "19.1.7");
+
+        let mut response = json!({});
+        server.persist_analysis(
+            &mut response,
+            &persist_options(false),
+            "cargo_clippy",
+            code,
+            &clippy_result(),
+            &Invocation::default(),
+        );
+        assert_eq!(
+            response["persistence"],
+            json!({"requested": false, "stored": false, "analysis_id": null, "error": null})
+        );
+    }
+
+    #[tokio::test]
+    async fn explanations_are_resources_cached_per_rustc_version() {
+        let dir = tempfile::tempdir().unwrap();
+        let config = ServerConfig {
+            pool_size: 0,
+            ..ServerConfig::default()
+        };
+        let server =
+            RustyToolsServer::with_config(PersistenceMode::Path(dir.path().join("t.db")), config);
+
+        let read = server.read_explanation("explain://E0308").unwrap();
+        let ResourceContents::TextResourceContents { uri, text, .. } = &read.contents[0] else {
+            panic!("explanations are text");
+        };
+        assert_eq!(uri, "explain://E0308");
+        assert!(text.starts_with("Expected type did not match the received type."));
+        let cached = server.explain("E0308").unwrap();
+        assert!(cached.cached && cached.success);
+        assert_eq!(&cached.text, text);
+
+        // Another toolchain's explanation isn't served
+        let db = server.db.as_ref().unwrap().lock();
+        assert!(
+            db.get_explanation("E0308", "rustc 1.0.0")
+                .unwrap()
+                .is_none()
+        );
+        drop(db);
+
+        for uri in [
+            "explain://E9999",
+            "explain://",
+            "explain://../E0308",
+            "file:///E0308",
+        ] {
+            let error = server.read_explanation(uri).unwrap_err();
+            assert_eq!(
+                error.code,
+                rmcp::model::ErrorCode::RESOURCE_NOT_FOUND,
+                "{}",
+                uri
+            );
+        }
+        assert!(!server.explain("E9999").unwrap().cached);
+    }
+
+    #[tokio::test]
+    async fn startup_prunes_analyses_beyond_max_analyses() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("t.db");
+        {
+            let db = Database::new(PersistenceMode::Path(path.clone()))
+                .unwrap()
+                .unwrap();
+            for day in 1..=3 {
+                let id = db
+                    .store_analysis(
+                        "cargo_check",
+                        &json!({"status": 0}),
+                        true,
+                        None,
+                        None,
+                        Some(&format!("fn main() {{ let _ = {}; }}", day)),
+                        None,
+                    )
+                    .unwrap();
+                db.store_error(
+                    id,
+                    Some("E0308"),
+                    Some("error"),
+                    "mismatched types",
+                    None,
+                    None,
+                    None,
+                )
+                .unwrap();
+                db.conn
+                    .execute(
+                        "UPDATE analyses SET timestamp = ?1 WHERE id = ?2",
+                        rusqlite::params![format!("2025-01-0{} 00:00:00", day), id],
+                    )
+                    .unwrap();
+            }
+        }
+
+        let config = ServerConfig {
+            pool_size: 0,
+            max_analyses: Some(2),
+            ..ServerConfig::default()
+        };
+        let server = RustyToolsServer::with_config(PersistenceMode::Path(path.clone()), config);
+        let db = server.db.as_ref().unwrap().lock();
+        assert_eq!(db.latest_analysis_ids(None, 10).unwrap(), [3, 2]);
+        assert_eq!(
+            db.cleanup_old_data(1).unwrap(),
+            PruneCounts {
+                analyses: 1,
+                errors: 1,
+                snippets: 1
+            }
+        );
+        drop(db);
+
+        // Without a limit nothing is pruned
+        let server = RustyToolsServer::with_config(
+            PersistenceMode::Path(path),
+            ServerConfig {
+                pool_size: 0,
+                ..ServerConfig::default()
+            },
+        );
+        let db = server.db.as_ref().unwrap().lock();
+        assert_eq!(db.latest_analysis_ids(None, 10).unwrap(), [3]);
+    }
+
+    #[tokio::test]
+    async fn db_reset_needs_confirmation_and_counts_deleted_rows() {
+        let dir = tempfile::tempdir().unwrap();
+        let config = ServerConfig {
+            pool_size: 0,
+            ..ServerConfig::default()
+        };
+        let server =
+            RustyToolsServer::with_config(PersistenceMode::Path(dir.path().join("t.db")), config);
+        {
+            let db = server.db.as_ref().unwrap().lock();
+            let id = db
+                .store_analysis(
+                    "cargo_check",
+                    &json!({"stderr": "error[E0308]: mismatched types"}),
+                    false,
+                    None,
+                    None,
+                    Some("fn main() {}"),
+                    None,
+                )
+                .unwrap();
+            db.store_error(
+                id,
+                Some("E0308"),
+                Some("error"),
+                "mismatched types",
+                None,
+                None,
+                None,
+            )
+            .unwrap();
+            db.store_fix(Some(1), "{}", true).unwrap();
+            db.store_todo("clippy", "needless return", None, None)
+                .unwrap();
+        }
+        let reset = |arguments: Value| {
+            let request = CallToolRequestParam {
+                name: "db_reset".into(),
+                arguments: arguments.as_object().cloned(),
+            };
+            server
+                .tools
+                .get("db_reset")
+                .unwrap()
+                .execute(&server, request)
+        };
+
+        let error = reset(json!({"confirm": false})).await.unwrap_err();
+        assert_eq!(error.code, rmcp::model::ErrorCode::INVALID_PARAMS);
+        assert_eq!(
+            server
+                .db
+                .as_ref()
+                .unwrap()
+                .lock()
+                .get_stats()
+                .unwrap()
+                .total_analyses,
+            1
+        );
+
+        let result = reset(json!({"confirm": true, "vacuum": true}))
+            .await
+            .unwrap();
+        let response: Value =
+            serde_json::from_str(&result.content[0].as_text().unwrap().text).unwrap();
+        assert_eq!(
+            response["deleted"],
+            json!({"analyses": 1, "errors": 1, "todos": 1, "fixes": 1, "snippets": 1})
+        );
+        assert!(response["vacuum"]["size_after"].is_u64());
+        let db = server.db.as_ref().unwrap().lock();
+        let stats = db.get_stats().unwrap();
+        assert_eq!((stats.total_analyses, stats.total_errors), (0, 0));
+        assert_eq!(stats.active_todos + stats.completed_todos, 0);
+        assert!(db.search_output("mismatched", 10).unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn persisting_without_a_database_is_a_visible_failure() {
+        let config = ServerConfig {
+            pool_size: 0,
+            ..ServerConfig::default()
+        };
+        // A database under a regular file can't be opened
+        let dir = tempfile::tempdir().unwrap();
+        let blocker = dir.path().join("blocker");
+        std::fs::write(&blocker, "").unwrap();
+        let server =
+            RustyToolsServer::with_config(PersistenceMode::Path(blocker.join("t.db")), config);
+        assert!(server.db.is_none());
+        let mut response = json!({});
+        let id = server.persist_analysis(
+            &mut response,
+            &persist_options(true),
+            "cargo_clippy",
+            "fn main() {}",
+            &clippy_result(),
+            &Invocation::default(),
+        );
+        assert_eq!(id, None);
+        assert_eq!(
+            response["persistence"],
+            json!({
+                "requested": true,
+                "stored": false,
+                "analysis_id": null,
+                "error": "persistence disabled"
+            })
+        );
+    }
+
+    #[tokio::test]
+    async fn no_persist_overrides_each_calls_persist() {
+        let config = ServerConfig {
+            pool_size: 0,
+            ..ServerConfig::default()
+        };
+        let server = RustyToolsServer::with_config(PersistenceMode::Disabled, config);
+        for requested in [true, false] {
+            let mut response = json!({});
+            let id = server.persist_analysis(
+                &mut response,
+                &persist_options(requested),
+                "cargo_clippy",
+                "fn main() {}",
+                &clippy_result(),
+                &Invocation::default(),
+            );
+            assert_eq!(id, None);
+            assert_eq!(response["persistence"]["requested"], requested);
+            assert_eq!(response["persistence"]["stored"], false);
+            let error = requested.then_some(PERSISTENCE_OFF);
+            assert_eq!(response["persistence"]["error"], json!(error));
+        }
+    }
+
+    #[tokio::test]
+    async fn test_env_is_applied_and_denylisted_keys_rejected() {
+        let config = ServerConfig {
+            pool_size: 0,
+            ..ServerConfig::default()
+        };
+        let server = RustyToolsServer::with_config(PersistenceMode::Disabled, config);
+        let args = json!({"env": {"APP_MODE": "test"}});
+        let invocation = invocation_for(&server, "cargo_test", args).unwrap();
+        assert!(
+            invocation
+                .env
+                .contains(&("APP_MODE".to_string(), "test".to_string()))
+        );
+        assert_eq!(invocation.details["env_keys"], json!(["APP_MODE"]));
+
+        for env in [
+            json!({"LD_PRELOAD": "/tmp/evil.so"}),
+            json!({"CARGO_HOME": "/tmp"}),
+            json!({"APP_MODE": 1}),
+            json!(["APP_MODE"]),
+        ] {
+            let args = json!({ "env": env });
+            let error = invocation_for(&server, "cargo_test", args).unwrap_err();
+            assert_eq!(error.code, rmcp::model::ErrorCode::INVALID_PARAMS);
+        }
+    }
+
+    #[test]
+    fn completed_todo_does_not_block_new_insert() {
+        let (_dir, db) = test_db();
+
+        assert!(
+            db.store_todo("clippy", "needless return", None, None)
+                .unwrap()
+        );
+        assert!(
+            !db.store_todo("clippy", "needless return", None, None)
+                .unwrap()
+        );
+
+        let id = db.get_todos(false, None).unwrap()[0].id;
+        db.mark_todo_completed(id).unwrap();
+
+        assert!(
+            db.store_todo("clippy", "needless return", None, None)
+                .unwrap()
+        );
+        assert_eq!(db.get_todos(true, None).unwrap().len(), 2);
+    }
+
+    #[test]
+    fn todos_filter_by_source() {
+        let (_dir, db) = test_db();
+        db.store_todo("clippy", "needless return", None, None)
+            .unwrap();
+        db.store_todo("unused", "unused function `helper`", None, None)
+            .unwrap();
+        db.store_todo("clippy", "redundant clone", None, None)
+            .unwrap();
+        let id = db.get_todos(false, Some("clippy")).unwrap()[0].id;
+        db.mark_todo_completed(id).unwrap();
+
+        let clippy = db.get_todos(false, Some("clippy")).unwrap();
+        assert_eq!(clippy.len(), 1);
+        assert!(clippy.iter().all(|todo| todo.source == "clippy"));
+        assert_eq!(db.get_todos(true, Some("clippy")).unwrap().len(), 2);
+        assert_eq!(db.get_todos(false, Some("unused")).unwrap().len(), 1);
+        assert!(db.get_todos(true, Some("manual")).unwrap().is_empty());
+        assert_eq!(db.get_todos(false, None).unwrap().len(), 2);
+    }
+
+    /// Seed two analyses with errors at known timestamps:
+    /// cargo_check (failed, 2025-01-10) with E0308 in src/main.rs and E0425 in src/lib.rs,
+    /// cargo_clippy (succeeded, 2025-02-20) with a warning in src/main.rs.
+    /// Only the cargo_check run is tagged, with session "refactor".
+    fn seed_history(db: &Database) {
+        let check = db
+            .store_analysis(
+                "cargo_check",
+                &json!({}),
+                false,
+                None,
+                None,
+                None,
+                Some("refactor"),
+            )
+            .unwrap();
+        db.store_error(
+            check,
+            Some("E0308"),
+            Some("error"),
+            "mismatched types",
+            Some("src/main.rs"),
+            Some(3),
+            None,
+        )
+        .unwrap();
+        db.store_error(
+            check,
+            Some("E0425"),
+            Some("error"),
+            "unresolved name",
+            Some("src/lib.rs"),
+            Some(7),
+            None,
+        )
+        .unwrap();
+        let clippy = db
+            .store_analysis("cargo_clippy", &json!({}), true, None, None, None, None)
+            .unwrap();
+        db.store_error(
+            clippy,
+            None,
+            Some("warning"),
+            "needless return",
+            Some("src/main.rs"),
+            Some(9),
+            None,
+        )
+        .unwrap();
+
+        db.conn
+            .execute(
+                "UPDATE errors SET timestamp = '2025-01-10 12:00:00' WHERE analysis_id = ?1",
+                [check],
+            )
+            .unwrap();
+        db.conn
+            .execute(
+                "UPDATE errors SET timestamp = '2025-02-20 12:00:00' WHERE analysis_id = ?1",
+                [clippy],
+            )
+            .unwrap();
+    }
+
+    fn codes(db: &Database, filter: &HistoryFilter) -> Vec<String> {
+        let mut codes: Vec<String> = db
+            .get_error_history(filter)
+            .unwrap()
+            .into_iter()
+            .filter_map(|e| e.error_code.or(e.severity))
+            .collect();
+        codes.sort();
+        assert_eq!(codes.len(), db.count_error_history(filter).unwrap());
+        codes
+    }
+
+    #[test]
+    fn history_filters_by_error_code() {
+        let (_dir, db) = test_db();
+        seed_history(&db);
+        let filter = HistoryFilter {
+            error_code: Some("E0308".into()),
+            ..Default::default()
+        };
+        assert_eq!(codes(&db, &filter), ["E0308"]);
+    }
+
+    #[test]
+    fn history_filters_by_tool() {
+        let (_dir, db) = test_db();
+        seed_history(&db);
+        let filter = HistoryFilter {
+            tool: Some("cargo_clippy".into()),
+            ..Default::default()
+        };
+        assert_eq!(codes(&db, &filter), ["warning"]);
+    }
+
+    #[test]
+    fn history_filters_by_success() {
+        let (_dir, db) = test_db();
+        seed_history(&db);
+        let filter = HistoryFilter {
+            success: Some(false),
+            ..Default::default()
+        };
+        assert_eq!(codes(&db, &filter), ["E0308", "E0425"]);
+    }
+
+    #[test]
+    fn history_filters_by_file_substring() {
+        let (_dir, db) = test_db();
+        seed_history(&db);
+        let filter = HistoryFilter {
+            file: Some("main".into()),
+            ..Default::default()
+        };
+        assert_eq!(codes(&db, &filter), ["E0308", "warning"]);
+    }
+
+    #[test]
+    fn history_filters_by_session() {
+        let (_dir, db) = test_db();
+        seed_history(&db);
+        let filter = HistoryFilter {
+            session: Some("refactor".into()),
+            ..Default::default()
+        };
+        assert_eq!(codes(&db, &filter), ["E0308", "E0425"]);
+        let history = db.get_error_history(&filter).unwrap();
+        assert!(
+            history
+                .iter()
+                .all(|e| e.session.as_deref() == Some("refactor"))
+        );
+
+        let untagged = HistoryFilter {
+            session: Some("other".into()),
+            ..Default::default()
+        };
+        assert!(codes(&db, &untagged).is_empty());
+    }
+
+    #[test]
+    fn history_filters_by_time_range() {
+        let (_dir, db) = test_db();
+        seed_history(&db);
+        let since = HistoryFilter {
+            since: Some("2025-02-01T00:00:00Z".into()),
+            ..Default::default()
+        };
+        assert_eq!(codes(&db, &since), ["warning"]);
+
+        let until = HistoryFilter {
+            until: Some("2025-02-01".into()),
+            ..Default::default()
+        };
+        assert_eq!(codes(&db, &until), ["E0308", "E0425"]);
+    }
+
+    #[test]
+    fn history_paginates_with_total_matching() {
+        let (_dir, db) = test_db();
+        seed_history(&db);
+        let page = |offset| HistoryFilter {
+            limit: Some(2),
+            offset: Some(offset),
+            ..Default::default()
+        };
+        assert_eq!(db.get_error_history(&page(0)).unwrap().len(), 2);
+        assert_eq!(db.get_error_history(&page(2)).unwrap().len(), 1);
+        assert_eq!(db.count_error_history(&page(2)).unwrap(), 3);
+    }
+
+    #[test]
+    fn metrics_aggregate_per_tool() {
+        let (_dir, db) = test_db();
+        for (success, duration_ms) in [(true, 100), (true, 300), (false, 200)] {
+            db.store_analysis(
+                "cargo_check",
+                &json!({}),
+                success,
+                Some(duration_ms),
+                None,
+                None,
+                None,
+            )
+            .unwrap();
+        }
+        // Analyses from before the duration_ms column carry it in full_output
+        db.store_analysis(
+            "cargo_test",
+            &json!({"duration_ms": 1000}),
+            true,
+            None,
+            None,
+            None,
+            None,
+        )
+        .unwrap();
+        // Outputs without a duration still count as invocations
+        db.store_analysis("cargo_test", &json!({}), false, None, None, None, None)
+            .unwrap();
 
-                    let db = db_arc.lock().map_err(|e| {
-                        McpError::internal_error(format!("Database lock failed: {}", e), None)
-                    })?;
+        let metrics = db.get_metrics(None, None).unwrap();
+        assert_eq!(
+            metrics[0],
+            ToolMetrics {
+                tool: "cargo_check".into(),
+                invocations: 3,
+                successes: 2,
+                success_rate: 2.0 / 3.0,
+                avg_duration_ms: Some(200.0),
+                p50_duration_ms: Some(200),
+                p95_duration_ms: Some(300),
+                max_duration_ms: Some(300),
+            }
+        );
+        assert_eq!((metrics[1].invocations, metrics[1].success_rate), (2, 0.5));
+        assert_eq!(metrics[1].avg_duration_ms, Some(1000.0));
 
-                    let history = db.get_error_history(error_code, Some(limit)).map_err(|e| {
-                        McpError::internal_error(format!("Failed to query history: {}", e), None)
-                    })?;
+        let test_only = db.get_metrics(Some("cargo_test"), None).unwrap();
+        assert_eq!(test_only.len(), 1);
+        assert!(db.get_metrics(None, Some("2999-01-01")).unwrap().is_empty());
+    }
 
-                    let json_result = json!({
-                        "error_code": error_code,
-                        "limit": limit,
-                        "results": history
-                    });
+    #[test]
+    fn error_code_stats_group_messages_and_list_fixes_that_worked() {
+        let (_dir, db) = test_db();
+        assert_eq!(db.most_frequent_error_code().unwrap(), None);
+        let mismatch = "mismatched types";
+        for messages in [
+            &[
+                (Some("E0308"), mismatch),
+                (Some("E0425"), "cannot find value `x`"),
+            ][..],
+            &[
+                (Some("E0308"), mismatch),
+                (Some("E0308"), "arguments to this function are incorrect"),
+            ],
+            &[(
+                Some("clippy::needless_return"),
+                "unneeded `return` statement",
+            ); 4],
+        ] {
+            let id = db
+                .store_analysis("cargo_check", &json!({}), false, None, None, None, None)
+                .unwrap();
+            for (code, message) in messages {
+                db.store_error(id, *code, Some("error"), message, None, None, None)
+                    .unwrap();
+            }
+        }
+        let mismatch_ids: Vec<i64> = db
+            .conn
+            .prepare("SELECT id FROM errors WHERE message = ?1 ORDER BY id")
+            .unwrap()
+            .query_map([mismatch], |row| row.get(0))
+            .unwrap()
+            .collect::<rusqlite::Result<_>>()
+            .unwrap();
+        db.store_fix(Some(mismatch_ids[0]), "{\"replacement\": \"1\"}", false)
+            .unwrap();
+        let worked = db
+            .store_fix(Some(mismatch_ids[1]), "{\"replacement\": \"2\"}", true)
+            .unwrap();
 
-                    Ok(CallToolResult {
-                        content: vec![rmcp::model::Content::text(json_result.to_string())],
-                        structured_content: None,
-                        meta: None,
-                        is_error: Some(false),
-                    })
+        // Lints outnumber E0308 but aren't rustc error codes
+        assert_eq!(
+            db.most_frequent_error_code().unwrap().as_deref(),
+            Some("E0308")
+        );
+        let stats = db.get_error_code_stats("E0308", 5).unwrap();
+        assert_eq!((stats.occurrences, stats.analyses), (3, 2));
+        assert!(stats.first_seen.is_some() && stats.first_seen <= stats.last_seen);
+        assert_eq!(stats.distinct_messages, 2);
+        assert_eq!(
+            stats.messages[0],
+            MessageCount {
+                message: mismatch.to_string(),
+                count: 2
+            }
+        );
+        assert_eq!(stats.fixes.len(), 1);
+        assert_eq!(stats.fixes[0].id, worked);
+        assert_eq!(stats.fixes[0].fix_applied, json!({"replacement": "2"}));
+        assert_eq!(
+            db.get_error_code_stats("E0308", 1).unwrap().messages.len(),
+            1
+        );
+
+        // Codes without fixes, or never seen at all
+        let unfixed = db.get_error_code_stats("E0425", 5).unwrap();
+        assert_eq!(unfixed.occurrences, 1);
+        assert!(unfixed.fixes.is_empty());
+        let unseen = db.get_error_code_stats("E0599", 5).unwrap();
+        assert_eq!((unseen.occurrences, unseen.distinct_messages), (0, 0));
+        assert!(unseen.first_seen.is_none() && unseen.messages.is_empty());
+
+        assert_eq!(
+            explanation_summary(
+                "Expected type did not match\nthe received type.\n\nErroneous code examples:\n"
+            ),
+            "Expected type did not match the received type."
+        );
+        assert!(is_rustc_error_code("E0308") && !is_rustc_error_code("clippy::todo"));
+    }
+
+    #[test]
+    fn error_trends_bucket_analyses_and_new_codes() {
+        let (_dir, db) = test_db();
+        let analysis =
+            |tool: &str, success: bool, days_ago: u32, errors: &[(Option<&str>, &str)]| {
+                let id = db
+                    .store_analysis(tool, &json!({}), success, None, None, None, None)
+                    .unwrap();
+                db.conn
+                    .execute(
+                        "UPDATE analyses SET timestamp = datetime('now', ?1) WHERE id = ?2",
+                        rusqlite::params![format!("-{} days", days_ago), id],
+                    )
+                    .unwrap();
+                for (code, severity) in errors {
+                    db.store_error(id, *code, Some(severity), "msg", None, None, None)
+                        .unwrap();
                 }
-                "cargo_todos" => {
-                    eprintln!("🔧 Executing cargo_todos");
-                    let show_completed = request
-                        .arguments
-                        .as_ref()
-                        .and_then(|args| args.get("show_completed"))
-                        .and_then(|v| v.as_bool())
-                        .unwrap_or(false);
-
-                    let Some(ref db_arc) = self.db else {
-                        return Err(McpError::internal_error("Database not available", None));
-                    };
+                db.conn
+                .execute(
+                    "UPDATE errors SET timestamp = (SELECT timestamp FROM analyses WHERE id = ?1)
+                         WHERE analysis_id = ?1",
+                    [id],
+                )
+                .unwrap();
+                id
+            };
+        analysis("cargo_check", false, 40, &[(Some("E0308"), "error")]);
+        let recent = analysis(
+            "cargo_check",
+            false,
+            2,
+            &[
+                (Some("E0308"), "error"),
+                (Some("E0425"), "error"),
+                (None, "warning"),
+            ],
+        );
+        // Errors stored before they had a timestamp fall back to their analysis's
+        db.conn
+            .execute(
+                "UPDATE errors SET timestamp = NULL WHERE analysis_id = ?1",
+                [recent],
+            )
+            .unwrap();
+        analysis("cargo_clippy", true, 0, &[]);
 
-                    let db = db_arc.lock().map_err(|e| {
-                        McpError::internal_error(format!("Database lock failed: {}", e), None)
-                    })?;
+        let filter = TrendFilter {
+            bucket: TrendBucket::Day,
+            days: 7,
+            error_code: None,
+            tool: None,
+            session: None,
+            by_rustc: false,
+        };
+        let trends = db.get_error_trends(&filter).unwrap();
+        assert_eq!(trends.buckets.len(), 7);
+        assert_eq!(trends.window_start, trends.buckets[0].bucket_start);
+        let two_days_ago = &trends.buckets[4];
+        assert_eq!(
+            (two_days_ago.counts.analyses, two_days_ago.counts.failures),
+            (1, 1)
+        );
+        assert_eq!(two_days_ago.counts.errors_by_severity["warning"], 1);
+        assert_eq!(
+            (
+                two_days_ago.counts.new_codes,
+                two_days_ago.counts.recurring_codes
+            ),
+            (1, 1)
+        );
+        assert_eq!(two_days_ago.new_error_codes, ["E0425"]);
+        assert_eq!(trends.buckets[6].counts.analyses, 1);
+        assert_eq!(
+            trends.totals,
+            TrendCounts {
+                analyses: 2,
+                failures: 1,
+                errors: 3,
+                errors_by_severity: [("error".into(), 2), ("warning".into(), 1)].into(),
+                new_codes: 1,
+                recurring_codes: 1,
+            }
+        );
 
-                    let todos = db.get_todos(show_completed).map_err(|e| {
-                        McpError::internal_error(format!("Failed to query todos: {}", e), None)
-                    })?;
+        let weekly = db
+            .get_error_trends(&TrendFilter {
+                bucket: TrendBucket::Week,
+                days: 60,
+                tool: Some("cargo_check".into()),
+                ..filter.clone()
+            })
+            .unwrap();
+        assert_eq!(weekly.totals.analyses, 2);
+        assert_eq!(weekly.totals.new_codes, 2);
+        let mondays: i64 = db
+            .conn
+            .query_row(
+                "SELECT COUNT(*) FROM json_each(?1) WHERE strftime('%w', value) = '1'",
+                [json!(
+                    weekly
+                        .buckets
+                        .iter()
+                        .map(|b| &b.bucket_start)
+                        .collect::<Vec<_>>()
+                )
+                .to_string()],
+                |row| row.get(0),
+            )
+            .unwrap();
+        assert_eq!(mondays as usize, weekly.buckets.len());
 
-                    let json_result = json!({
-                        "show_completed": show_completed,
-                        "todos": todos
-                    });
+        let e0308 = db
+            .get_error_trends(&TrendFilter {
+                error_code: Some("E0308".into()),
+                ..filter.clone()
+            })
+            .unwrap();
+        assert_eq!((e0308.totals.errors, e0308.totals.analyses), (1, 2));
+        assert_eq!(e0308.totals.recurring_codes, 1);
+        assert!(e0308.by_rustc.is_empty());
 
-                    Ok(CallToolResult {
-                        content: vec![rmcp::model::Content::text(json_result.to_string())],
-                        structured_content: None,
-                        meta: None,
-                        is_error: Some(false),
-                    })
-                }
-                "db_stats" => {
-                    eprintln!("🔧 Executing db_stats");
-                    let Some(ref db_arc) = self.db else {
-                        return Err(McpError::internal_error("Database not available", None));
-                    };
+        db.set_analysis_toolchain(recent, "rustc 1.85.0 (4d91de4e4 2025-02-17)", None)
+            .unwrap();
+        let by_rustc = db
+            .get_error_trends(&TrendFilter {
+                by_rustc: true,
+                ..filter
+            })
+            .unwrap();
+        let counts = |analyses, failures, errors| RustcCounts {
+            analyses,
+            failures,
+            errors,
+        };
+        assert_eq!(
+            by_rustc.by_rustc,
+            [
+                ("1.85".to_string(), counts(1, 1, 3)),
+                ("unknown".to_string(), counts(1, 0, 0))
+            ]
+            .into()
+        );
+        assert_eq!(by_rustc.buckets[4].by_rustc["1.85"], counts(1, 1, 3));
+    }
 
-                    let db = db_arc.lock().map_err(|e| {
-                        McpError::internal_error(format!("Database lock failed: {}", e), None)
-                    })?;
+    #[test]
+    fn history_filters_by_severity() {
+        let (_dir, db) = test_db();
+        seed_history(&db);
+        let filter = HistoryFilter {
+            severity: Some("warning".into()),
+            ..Default::default()
+        };
+        assert_eq!(codes(&db, &filter), ["warning"]);
+        let filter = HistoryFilter {
+            severity: Some("error".into()),
+            ..Default::default()
+        };
+        assert_eq!(codes(&db, &filter), ["E0308", "E0425"]);
 
-                    let stats = db.get_stats().map_err(|e| {
-                        McpError::internal_error(format!("Failed to get stats: {}", e), None)
-                    })?;
+        let stats = db.get_stats().unwrap();
+        assert_eq!(stats.errors_by_severity["error"], 2);
+        assert_eq!(stats.errors_by_severity["warning"], 1);
+    }
 
-                    let json_result = json!(stats);
+    #[test]
+    fn legacy_warning_codes_become_severities() {
+        let (dir, db) = test_db();
+        let id = store_output(&db, "cargo_clippy", "");
+        db.store_error(id, None, None, "unused variable", None, None, None)
+            .unwrap();
+        db.store_error(
+            id,
+            Some("E0308"),
+            None,
+            "mismatched types",
+            None,
+            None,
+            None,
+        )
+        .unwrap();
+        // Rows written before the severity column existed
+        db.conn
+            .execute(
+                "UPDATE errors SET error_code = 'WARNING' WHERE error_code IS NULL",
+                [],
+            )
+            .unwrap();
+        drop(db);
 
-                    Ok(CallToolResult {
-                        content: vec![rmcp::model::Content::text(json_result.to_string())],
-                        structured_content: None,
-                        meta: None,
-                        is_error: Some(false),
-                    })
-                }
-                _ => Err(McpError::internal_error(
-                    format!("Unknown tool: {}", request.name),
-                    None,
-                )),
-            }
-        }
+        let db = Database::new(PersistenceMode::Path(dir.path().join("test.db")))
+            .unwrap()
+            .unwrap();
+        let migrated = db.get_errors_for_analysis(id).unwrap();
+        let rows: Vec<(Option<&str>, Option<&str>)> = migrated
+            .iter()
+            .map(|e| (e.error_code.as_deref(), e.severity.as_deref()))
+            .collect();
+        assert!(rows.contains(&(None, Some("warning"))));
+        assert!(rows.contains(&(Some("E0308"), Some("error"))));
     }
-}
 
-fn get_code_arg<'a>(
-    request: &'a CallToolRequestParam,
-    tool_name: &str,
-) -> Result<&'a str, McpError> {
-    request
-        .arguments
-        .as_ref()
-        .and_then(|args| args.get("code"))
-        .and_then(|v| v.as_str())
-        .ok_or_else(|| {
-            McpError::invalid_params(format!("code parameter required for {}", tool_name), None)
-        })
-}
+    #[test]
+    fn history_combines_filters() {
+        let (_dir, db) = test_db();
+        seed_history(&db);
+        let filter = HistoryFilter {
+            tool: Some("cargo_check".into()),
+            file: Some("main".into()),
+            since: Some("2025-01-01".into()),
+            until: Some("2025-01-31T23:59:59".into()),
+            ..Default::default()
+        };
+        assert_eq!(codes(&db, &filter), ["E0308"]);
 
-fn validate_rust_code(code: &str) -> Result<(), McpError> {
-    if code.trim().is_empty() {
-        return Err(McpError::invalid_params("Code cannot be empty", None));
+        let none = HistoryFilter {
+            success: Some(true),
+            error_code: Some("E0308".into()),
+            ..Default::default()
+        };
+        assert!(codes(&db, &none).is_empty());
     }
 
-    // Basic validation - check for potentially dangerous operations
-    let dangerous_patterns = ["std::process::Command", "std::fs::", "std::net::", "unsafe"];
-    for pattern in &dangerous_patterns {
-        if code.contains(pattern) {
-            return Err(McpError::invalid_params(
-                format!("Code contains potentially unsafe pattern: {}", pattern),
-                None,
-            ));
-        }
+    fn store_output(db: &Database, tool: &str, stderr: &str) -> i64 {
+        let output = json!({"status": 1, "success": false, "stdout": "", "stderr": stderr});
+        db.store_analysis(tool, &output, false, None, None, None, None)
+            .unwrap()
     }
 
-    Ok(())
-}
+    #[test]
+    fn search_output_finds_phrase_with_snippet() {
+        let (_dir, db) = test_db();
+        assert_eq!(db.search_mode(), "fts5");
+        let moved = store_output(
+            &db,
+            "cargo_check",
+            "error[E0382]: borrow of moved value: `s`\n --> src/main.rs:4:20",
+        );
+        store_output(&db, "cargo_check", "error[E0308]: mismatched types");
 
-pub struct Database {
-    conn: Connection,
-}
+        let hits = db.search_output("borrow of moved value", 10).unwrap();
+        assert_eq!(hits.len(), 1);
+        assert_eq!(hits[0].analysis_id, moved);
+        assert!(hits[0].snippet.contains("[borrow of moved value]"));
 
-impl Database {
-    pub fn new(mode: PersistenceMode) -> Result<Option<Self>> {
-        match mode {
-            PersistenceMode::Disabled => Ok(None),
-            PersistenceMode::Path(path) => {
-                let conn = Connection::open(&path)?;
+        // Punctuation that is FTS5 query syntax must be treated literally
+        assert!(db.search_output("E0382]: \"borrow", 10).is_ok());
+    }
 
-                // Create parent directory if it doesn't exist
-                if let Some(parent) = path.parent() {
-                    std::fs::create_dir_all(parent)?;
-                }
+    #[test]
+    fn search_output_indexes_existing_rows_and_tracks_deletes() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("test.db");
+        // Simulate a database created before the FTS index existed
+        let mut db = Database::new(PersistenceMode::Path(path.clone()))
+            .unwrap()
+            .unwrap();
+        db.conn
+            .execute_batch("DROP TRIGGER analyses_fts_delete; DROP TABLE analyses_fts;")
+            .unwrap();
+        db.fts_enabled = false;
+        store_output(&db, "cargo_clippy", "warning: unused variable: `x`");
+        drop(db);
 
-                let db = Database { conn };
-                db.init_schema()?;
-                Ok(Some(db))
-            }
-        }
+        let db = Database::new(PersistenceMode::Path(path)).unwrap().unwrap();
+        assert_eq!(db.search_output("unused variable", 10).unwrap().len(), 1);
+
+        db.cleanup_old_data(0).unwrap();
+        assert!(db.search_output("unused variable", 10).unwrap().is_empty());
     }
 
-    fn init_schema(&self) -> Result<()> {
-        // Create analyses table
-        self.conn.execute(
-            "CREATE TABLE IF NOT EXISTS analyses (
-                id INTEGER PRIMARY KEY AUTOINCREMENT,
-                timestamp DATETIME DEFAULT CURRENT_TIMESTAMP,
-                file_path TEXT,
-                tool TEXT NOT NULL,
-                full_output TEXT NOT NULL,
-                success BOOLEAN NOT NULL
-            )",
-            [],
-        )?;
+    #[test]
+    fn outputs_are_stored_compressed_and_old_rows_still_read() {
+        let (_dir, mut db) = test_db();
+        let stderr = "warning: unused variable: `x`\n --> src/main.rs:2:9\n".repeat(200);
+        let output = json!({"status": 0, "stdout": "", "stderr": stderr});
+        let id = db
+            .store_analysis(
+                "cargo_check",
+                &output,
+                true,
+                None,
+                None,
+                Some("fn main() {}"),
+                None,
+            )
+            .unwrap();
+        let (kind, encoding, size): (String, Option<String>, i64) = db
+            .conn
+            .query_row(
+                "SELECT typeof(full_output), output_encoding, length(full_output) FROM analyses WHERE id = ?1",
+                [id],
+                |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?)),
+            )
+            .unwrap();
+        assert_eq!((kind.as_str(), encoding.as_deref()), ("blob", Some("gzip")));
+        assert!((size as usize) < output.to_string().len() / 10, "{}", size);
+        let (_, stored) = db.get_analysis_input(id).unwrap().unwrap();
+        assert_eq!(stored, output);
 
-        // Create errors table
-        self.conn.execute(
-            "CREATE TABLE IF NOT EXISTS errors (
-                id INTEGER PRIMARY KEY AUTOINCREMENT,
-                analysis_id INTEGER NOT NULL,
-                error_code TEXT,
-                message TEXT NOT NULL,
-                file TEXT,
-                line INTEGER,
-                suggestion TEXT,
-                FOREIGN KEY (analysis_id) REFERENCES analyses (id)
-            )",
-            [],
-        )?;
+        // Too short to gain from compression
+        let short = json!({"status": 0});
+        let id = db
+            .store_analysis("cargo_fmt", &short, true, None, None, None, None)
+            .unwrap();
+        assert_eq!(db.get_analysis_input(id).unwrap().unwrap().1, short);
 
-        // Create todos table - fix column type issues
-        self.conn.execute(
-            "CREATE TABLE IF NOT EXISTS todos (
-                id INTEGER PRIMARY KEY AUTOINCREMENT,
-                created_at DATETIME DEFAULT CURRENT_TIMESTAMP,
-                source TEXT NOT NULL,
-                description TEXT NOT NULL,
-                file_path TEXT,
-                line_number INTEGER,
-                completed INTEGER DEFAULT 0
-            )",
-            [],
-        )?;
+        // Rows stored before compression are plain text without an encoding
+        db.conn
+            .execute(
+                "INSERT INTO analyses (tool, full_output, success) VALUES ('cargo_build', ?1, 0)",
+                [json!({"stderr": "error: linking with `cc` failed"}).to_string()],
+            )
+            .unwrap();
+        let old = db.conn.last_insert_rowid();
+        assert_eq!(
+            db.get_analysis_input(old).unwrap().unwrap().1["stderr"],
+            "error: linking with `cc` failed"
+        );
 
-        // Create fixes table
-        self.conn.execute(
-            "CREATE TABLE IF NOT EXISTS fixes (
-                id INTEGER PRIMARY KEY AUTOINCREMENT,
-                error_id INTEGER,
-                fix_applied TEXT NOT NULL,
-                timestamp DATETIME DEFAULT CURRENT_TIMESTAMP,
-                worked INTEGER,
-                FOREIGN KEY (error_id) REFERENCES errors (id)
-            )",
-            [],
-        )?;
+        db.fts_enabled = false;
+        let hits = db.search_output("UNUSED variable", 10).unwrap();
+        assert_eq!(hits.len(), 1);
+        assert!(hits[0].snippet.contains("[unused variable]"));
+        assert_eq!(
+            db.search_output("linking with", 10).unwrap()[0].analysis_id,
+            old
+        );
+    }
 
-        // Add timestamp column to existing errors table if it doesn't exist
-        let _ = self.conn.execute(
-            "ALTER TABLE errors ADD COLUMN timestamp DATETIME DEFAULT CURRENT_TIMESTAMP",
-            [],
+    #[test]
+    fn search_output_falls_back_to_like() {
+        let (_dir, mut db) = test_db();
+        db.fts_enabled = false;
+        store_output(
+            &db,
+            "cargo_check",
+            "error[E0382]: borrow of moved value: `s`",
         );
+        store_output(&db, "cargo_check", "100% done_ok");
 
-        Ok(())
+        let hits = db.search_output("Moved Value", 10).unwrap();
+        assert_eq!(db.search_mode(), "like");
+        assert_eq!(hits.len(), 1);
+        assert!(hits[0].snippet.contains("[moved value]"));
+
+        // LIKE wildcards in the query are matched literally
+        assert_eq!(db.search_output("0% d", 10).unwrap().len(), 1);
+        assert!(db.search_output("_%", 10).unwrap().is_empty());
     }
 
-    pub fn store_analysis(
-        &self,
-        tool: &str,
-        full_output: &Value,
-        success: bool,
-        file_path: Option<&str>,
-    ) -> Result<i64> {
-        use rusqlite::params;
-        let full_output_str = full_output.to_string();
+    fn messages(errors: &[ErrorRecord]) -> Vec<&str> {
+        errors.iter().map(|e| e.message.as_str()).collect()
+    }
 
-        self.conn.execute(
-            "INSERT INTO analyses (tool, full_output, success, file_path) VALUES (?1, ?2, ?3, ?4)",
-            params![tool, full_output_str, success, file_path],
-        )?;
+    #[test]
+    fn error_diff_reports_added_removed_and_unchanged() {
+        let (_dir, db) = test_db();
+        let baseline = store_output(&db, "cargo_check", "");
+        db.store_error(
+            baseline,
+            Some("E0308"),
+            Some("error"),
+            "mismatched types",
+            Some("src/main.rs"),
+            Some(3),
+            None,
+        )
+        .unwrap();
+        db.store_error(
+            baseline,
+            Some("E0425"),
+            Some("error"),
+            "unresolved name",
+            Some("src/main.rs"),
+            Some(5),
+            None,
+        )
+        .unwrap();
+        let current = store_output(&db, "cargo_check", "");
+        db.store_error(
+            current,
+            Some("E0308"),
+            Some("error"),
+            "mismatched types",
+            Some("src/main.rs"),
+            Some(3),
+            None,
+        )
+        .unwrap();
+        db.store_error(
+            current,
+            Some("E0599"),
+            Some("error"),
+            "no method named `foo`",
+            Some("src/main.rs"),
+            Some(8),
+            None,
+        )
+        .unwrap();
 
-        Ok(self.conn.last_insert_rowid())
+        let diff = ErrorDiff::between(
+            &db.get_errors_for_analysis(baseline).unwrap(),
+            &db.get_errors_for_analysis(current).unwrap(),
+        );
+        assert_eq!(messages(&diff.added), ["no method named `foo`"]);
+        assert_eq!(messages(&diff.removed), ["unresolved name"]);
+        assert_eq!(messages(&diff.unchanged), ["mismatched types"]);
     }
 
-    pub fn store_error(
-        &self,
-        analysis_id: i64,
-        error_code: Option<&str>,
-        message: &str,
-        file: Option<&str>,
-        line: Option<i32>,
-        suggestion: Option<&str>,
-    ) -> Result<()> {
-        use rusqlite::params;
-        self.conn.execute(
-            "INSERT INTO errors (analysis_id, error_code, message, file, line, suggestion) VALUES (?1, ?2, ?3, ?4, ?5, ?6)",
-            params![
-                analysis_id,
-                error_code,
-                message,
-                file,
-                line,
-                suggestion
-            ]
-        )?;
-        Ok(())
+    #[test]
+    fn error_diff_of_identical_analyses_is_unchanged() {
+        let (_dir, db) = test_db();
+        let mut ids = Vec::new();
+        for _ in 0..2 {
+            let id = store_output(&db, "cargo_clippy", "");
+            db.store_error(
+                id,
+                None,
+                Some("warning"),
+                "unused variable",
+                Some("src/main.rs"),
+                Some(2),
+                None,
+            )
+            .unwrap();
+            db.store_error(
+                id,
+                None,
+                Some("warning"),
+                "unused variable",
+                Some("src/main.rs"),
+                Some(2),
+                None,
+            )
+            .unwrap();
+            ids.push(id);
+        }
+
+        let diff = ErrorDiff::between(
+            &db.get_errors_for_analysis(ids[0]).unwrap(),
+            &db.get_errors_for_analysis(ids[1]).unwrap(),
+        );
+        assert!(diff.added.is_empty());
+        assert!(diff.removed.is_empty());
+        assert_eq!(diff.unchanged.len(), 2);
     }
 
-    pub fn store_todo(
-        &self,
-        source: &str,
-        description: &str,
-        file_path: Option<&str>,
-        line_number: Option<i32>,
-    ) -> Result<()> {
-        use rusqlite::params;
-        self.conn.execute(
-            "INSERT INTO todos (source, description, file_path, line_number) VALUES (?1, ?2, ?3, ?4)",
-            params![
-                source,
-                description,
-                file_path,
-                line_number
-            ]
-        )?;
-        Ok(())
+    #[test]
+    fn vacuum_reclaims_deleted_space() {
+        let (_dir, db) = test_db();
+        let stderr = "error: padding\n".repeat(500);
+        for _ in 0..50 {
+            store_output(&db, "cargo_check", &stderr);
+        }
+        db.cleanup_old_data(0).unwrap();
+
+        let stats = db.vacuum().unwrap();
+        assert!(stats.size_after < stats.size_before);
+        assert_eq!(stats.reclaimed, stats.size_before - stats.size_after);
     }
 
-    pub fn get_error_history(
-        &self,
-        error_code: Option<&str>,
-        limit: Option<usize>,
-    ) -> Result<Vec<ErrorRecord>> {
-        use rusqlite::params;
-        let limit = limit.unwrap_or(10) as i64;
+    #[test]
+    fn backup_copies_live_database() {
+        let (dir, db) = test_db();
+        seed_history(&db);
+        let destination = dir.path().join("backup.db");
 
-        let mut errors = Vec::new();
+        let bytes = db.backup(&destination, false).unwrap();
+        assert_eq!(bytes, std::fs::metadata(&destination).unwrap().len());
+        let copy = Database::new(PersistenceMode::Path(destination.clone()))
+            .unwrap()
+            .unwrap();
+        assert_eq!(copy.get_stats().unwrap().total_errors, 3);
 
-        // Check if timestamp column exists in errors table
-        let has_timestamp = self
-            .conn
-            .prepare("SELECT timestamp FROM errors LIMIT 1")
-            .is_ok();
+        assert!(db.backup(&destination, false).is_err());
+        assert!(db.backup(&destination, true).is_ok());
+        assert!(db.backup(&dir.path().join("test.db"), true).is_err());
 
-        if let Some(code) = error_code {
-            let sql = if has_timestamp {
-                "SELECT e.id, e.error_code, e.message, e.file, e.line, e.suggestion,
-                        COALESCE(e.timestamp, a.timestamp) as timestamp, a.tool
-                 FROM errors e
-                 JOIN analyses a ON e.analysis_id = a.id
-                 WHERE e.error_code = ?1
-                 ORDER BY COALESCE(e.timestamp, a.timestamp) DESC
-                 LIMIT ?2"
-            } else {
-                "SELECT e.id, e.error_code, e.message, e.file, e.line, e.suggestion,
-                        a.timestamp, a.tool
-                 FROM errors e
-                 JOIN analyses a ON e.analysis_id = a.id
-                 WHERE e.error_code = ?1
-                 ORDER BY a.timestamp DESC
-                 LIMIT ?2"
-            };
-            let mut stmt = self.conn.prepare(sql)?;
-            let error_iter = stmt.query_map(params![code, limit], |row| {
-                Ok(ErrorRecord {
-                    id: row.get(0)?,
-                    error_code: row.get::<_, Option<String>>(1)?,
-                    message: row.get(2)?,
-                    file: row.get::<_, Option<String>>(3)?,
-                    line: row.get::<_, Option<i32>>(4)?,
-                    suggestion: row.get::<_, Option<String>>(5)?,
-                    timestamp: row.get(6)?,
-                    tool: row.get(7)?,
-                })
-            })?;
+        // The live database named another way is refused, and left intact
+        std::fs::create_dir(dir.path().join("docs")).unwrap();
+        let dotted = dir.path().join("docs").join("..").join("test.db");
+        let error = db.backup(&dotted, true).unwrap_err();
+        assert_eq!(error.to_string(), "destination is the live database");
+        #[cfg(unix)]
+        {
+            let link = dir.path().join("link.db");
+            std::os::unix::fs::symlink(dir.path().join("test.db"), &link).unwrap();
+            assert!(db.backup(&link, true).is_err());
+        }
+        seed_history(&db);
+        assert_eq!(db.get_stats().unwrap().total_errors, 6);
+        let reopened = Database::new(PersistenceMode::Path(dir.path().join("test.db")))
+            .unwrap()
+            .unwrap();
+        assert_eq!(reopened.get_stats().unwrap().total_errors, 6);
 
-            for error in error_iter {
-                errors.push(error?);
-            }
-        } else {
-            let sql = if has_timestamp {
-                "SELECT e.id, e.error_code, e.message, e.file, e.line, e.suggestion,
-                        COALESCE(e.timestamp, a.timestamp) as timestamp, a.tool
-                 FROM errors e
-                 JOIN analyses a ON e.analysis_id = a.id
-                 ORDER BY COALESCE(e.timestamp, a.timestamp) DESC
-                 LIMIT ?1"
-            } else {
-                "SELECT e.id, e.error_code, e.message, e.file, e.line, e.suggestion,
-                        a.timestamp, a.tool
-                 FROM errors e
-                 JOIN analyses a ON e.analysis_id = a.id
-                 ORDER BY a.timestamp DESC
-                 LIMIT ?1"
-            };
-            let mut stmt = self.conn.prepare(sql)?;
-            let error_iter = stmt.query_map(params![limit], |row| {
-                Ok(ErrorRecord {
-                    id: row.get(0)?,
-                    error_code: row.get::<_, Option<String>>(1)?,
-                    message: row.get(2)?,
-                    file: row.get::<_, Option<String>>(3)?,
-                    line: row.get::<_, Option<i32>>(4)?,
-                    suggestion: row.get::<_, Option<String>>(5)?,
-                    timestamp: row.get(6)?,
-                    tool: row.get(7)?,
-                })
-            })?;
+        // Copies are renamed into place, leaving no temporary files behind
+        assert!(
+            db.backup(&dir.path().join("missing").join("backup.db"), true)
+                .is_err()
+        );
+        let leftovers = std::fs::read_dir(dir.path())
+            .unwrap()
+            .filter(|entry| {
+                entry
+                    .as_ref()
+                    .unwrap()
+                    .file_name()
+                    .to_string_lossy()
+                    .starts_with(".rusty-tools-backup")
+            })
+            .count();
+        assert_eq!(leftovers, 0);
+    }
+
+    #[test]
+    fn invocation_args_precede_tool_args() {
+        let mut invocation = Invocation::default();
+        invocation.set_target("wasm32-unknown-unknown");
+        assert_eq!(
+            invocation.cargo_args(&["clippy", "--", "-D", "warnings"]),
+            [
+                "clippy",
+                "--target",
+                "wasm32-unknown-unknown",
+                "--",
+                "-D",
+                "warnings"
+            ]
+        );
+        invocation.no_std = true;
+        assert_eq!(
+            invocation.cargo_args(&["check"]),
+            [
+                "check",
+                "--target",
+                "wasm32-unknown-unknown",
+                "--features",
+                "panic-handler"
+            ]
+        );
+    }
 
-            for error in error_iter {
-                errors.push(error?);
-            }
-        }
+    #[tokio::test]
+    async fn no_std_scaffold_builds_library() {
+        let project = ScratchProject::create(&ChildEnv::default()).unwrap();
+        let code = "pub fn add(a: u32, b: u32) -> u32 {\n    a + b\n}\n";
+        write_library_scaffold(project.path(), code, true).unwrap();
+        let lib = std::fs::read_to_string(project.path().join("src").join("lib.rs")).unwrap();
+        assert!(lib.starts_with("#![no_std]\n"));
+        assert!(lib.contains("#[panic_handler]"));
 
-        Ok(errors)
+        let invocation = Invocation {
+            library: true,
+            no_std: true,
+            ..Default::default()
+        };
+        let args = invocation.cargo_args(&["check"]);
+        let args: Vec<&str> = args.iter().map(String::as_str).collect();
+        let result = run_in_project(project.path(), code, &args, None, &[], &ChildEnv::default())
+            .await
+            .unwrap();
+        assert_eq!(result.status, 0, "{}", result.stderr);
     }
 
-    pub fn get_todos(&self, show_completed: bool) -> Result<Vec<TodoRecord>> {
-        let sql = if show_completed {
-            "SELECT id, source, description, file_path,
-                    CAST(line_number AS INTEGER) as line_number,
-                    completed, created_at
-             FROM todos
-             ORDER BY created_at DESC"
-        } else {
-            "SELECT id, source, description, file_path,
-                    CAST(line_number AS INTEGER) as line_number,
-                    completed, created_at
-             FROM todos
-             WHERE completed = 0
-             ORDER BY created_at DESC"
+    #[tokio::test]
+    async fn run_limiter_queues_beyond_max() {
+        let limiter = Arc::new(RunLimiter::new(1));
+        let first = limiter.acquire().await.unwrap();
+        let waiting = {
+            let limiter = Arc::clone(&limiter);
+            tokio::spawn(async move { limiter.acquire().await.map(drop).is_ok() })
         };
+        while limiter.queued.load(Ordering::Relaxed) == 0 {
+            tokio::task::yield_now().await;
+        }
+        assert_eq!(
+            limiter.stats(),
+            json!({"max": 1, "running": 1, "queued": 1})
+        );
 
-        let mut stmt = self.conn.prepare(sql)?;
-        let todo_iter = stmt.query_map([], |row| {
-            // Handle line_number more carefully to avoid type issues
-            let line_number: Option<i32> = match row.get::<_, Option<rusqlite::types::Value>>(4)? {
-                Some(rusqlite::types::Value::Integer(i)) => Some(i as i32),
-                Some(rusqlite::types::Value::Text(s)) => s.parse().ok(),
-                Some(rusqlite::types::Value::Null) | None => None,
-                _ => None,
-            };
+        drop(first);
+        assert!(waiting.await.unwrap());
+        assert_eq!(
+            limiter.stats(),
+            json!({"max": 1, "running": 0, "queued": 0})
+        );
+    }
 
-            Ok(TodoRecord {
-                id: row.get(0)?,
-                source: row.get(1)?,
-                description: row.get(2)?,
-                file_path: row.get::<_, Option<String>>(3)?,
-                line_number,
-                completed: row.get::<_, i32>(5)? != 0, // Convert INTEGER to bool
-                created_at: row.get(6)?,
-            })
-        })?;
+    #[tokio::test]
+    async fn cancellation_kills_cargo() {
+        let project = ScratchProject::create(&ChildEnv::default()).unwrap();
+        let token = tokio_util::sync::CancellationToken::new();
+        let canceller = token.clone();
+        tokio::spawn(async move {
+            tokio::time::sleep(Duration::from_millis(50)).await;
+            canceller.cancel();
+        });
+        let start = Instant::now();
+        let result = cancellation::scope(
+            token,
+            run_in_project(
+                project.path(),
+                "fn main() { loop {} }",
+                &["run"],
+                None,
+                &[],
+                &ChildEnv::default(),
+            ),
+        )
+        .await;
+        assert!(result.is_err());
+        assert!(start.elapsed() < Duration::from_secs(10));
+    }
 
-        let mut todos = Vec::new();
-        for todo in todo_iter {
-            todos.push(todo?);
-        }
-        Ok(todos)
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn cpu_limit_is_a_result_not_an_error() {
+        let env = ChildEnv::default();
+        let project = ScratchProject::create(&env).unwrap();
+        run_in_project(project.path(), "fn main() {}", &["build"], None, &[], &env)
+            .await
+            .unwrap();
+        let limited = env.with_limits(ResourceLimits {
+            memory_bytes: None,
+            cpu_secs: Some(1),
+        });
+        let result = run_in_project(
+            project.path(),
+            "fn main() { let mut n = 0u64; loop { n = std::hint::black_box(n + 1); } }",
+            &["run"],
+            Some(Duration::from_secs(60)),
+            &[],
+            &limited,
+        )
+        .await
+        .unwrap();
+        assert!(!result.timed_out);
+        assert_eq!(result.resource_exceeded, Some("cpu"));
+
+        let response = tool_response(
+            "cargo_test",
+            "",
+            &result,
+            Verbosity::Summary,
+            &Invocation::default(),
+        );
+        assert_eq!(response["resource_exceeded"], "cpu");
+        assert_eq!(response["success"], false);
     }
 
-    #[allow(dead_code)]
-    pub fn mark_todo_completed(&self, todo_id: i64) -> Result<()> {
-        use rusqlite::params;
-        self.conn.execute(
-            "UPDATE todos SET completed = 1 WHERE id = ?1",
-            params![todo_id],
-        )?;
-        Ok(())
+    #[tokio::test]
+    async fn timeout_is_a_result_not_an_error() {
+        let project = ScratchProject::create(&ChildEnv::default()).unwrap();
+        let result = run_in_project(
+            project.path(),
+            "fn main() {}",
+            &["build"],
+            Some(Duration::from_millis(1)),
+            &[],
+            &ChildEnv::default(),
+        )
+        .await
+        .unwrap();
+        assert!(result.timed_out);
+        assert_eq!(result.status, -1);
+
+        let response = tool_response(
+            "cargo_build",
+            "fn main() {}",
+            &result,
+            Verbosity::Summary,
+            &Invocation::default(),
+        );
+        assert_eq!(response["timed_out"], true);
+        assert_eq!(response["success"], false);
+        assert!(
+            response["summary"]
+                .as_str()
+                .unwrap()
+                .starts_with("timed out after")
+        );
     }
 
-    /// Get statistics about stored data
-    pub fn get_stats(&self) -> Result<DatabaseStats> {
-        let analyses_count: i64 =
-            self.conn
-                .query_row("SELECT COUNT(*) FROM analyses", [], |row| row.get(0))?;
+    #[test]
+    fn feature_powerset_is_bounded() {
+        let table = |value: Value| -> std::collections::BTreeMap<String, Vec<String>> {
+            serde_json::from_value(value).unwrap()
+        };
+        let features = table(json!({"std": [], "serde": ["std"]}));
+        check_feature_table(&features).unwrap();
+        let sets = feature_combinations(&features, &json!("powerset")).unwrap();
+        assert_eq!(sets.len(), 4);
+        assert!(sets.contains(&vec![]));
+        assert!(sets.contains(&vec!["serde".to_string(), "std".to_string()]));
 
-        let errors_count: i64 = self
-            .conn
-            .query_row("SELECT COUNT(*) FROM errors", [], |row| row.get(0))?;
+        let explicit = feature_combinations(&features, &json!([["std"]])).unwrap();
+        assert_eq!(explicit, [vec!["std".to_string()]]);
+        assert!(feature_combinations(&features, &json!([["nope"]])).is_err());
 
-        let todos_count: i64 = self.conn.query_row(
-            "SELECT COUNT(*) FROM todos WHERE completed = 0",
-            [],
-            |row| row.get(0),
-        )?;
+        let wide = (0..5).map(|i| (format!("f{}", i), Vec::new())).collect();
+        assert!(feature_combinations(&wide, &json!("powerset")).is_err());
 
-        let completed_todos_count: i64 = self.conn.query_row(
-            "SELECT COUNT(*) FROM todos WHERE completed = 1",
-            [],
-            |row| row.get(0),
-        )?;
+        assert!(check_feature_table(&table(json!({"a": ["dep:serde"]}))).is_err());
+        assert!(check_feature_table(&table(json!({"a b": []}))).is_err());
+    }
 
-        Ok(DatabaseStats {
-            total_analyses: analyses_count as usize,
-            total_errors: errors_count as usize,
-            active_todos: todos_count as usize,
-            completed_todos: completed_todos_count as usize,
-        })
+    #[test]
+    fn history_filters_by_feature_set() {
+        let (_dir, db) = test_db();
+        seed_history(&db);
+        let with_std = store_output(&db, "cargo_feature_check", "");
+        db.set_analysis_features(with_std, &normalize_feature_set(["std", "alloc"]))
+            .unwrap();
+        db.store_error(
+            with_std,
+            Some("E0433"),
+            Some("error"),
+            "unresolved",
+            None,
+            None,
+            None,
+        )
+        .unwrap();
+
+        let filter = HistoryFilter {
+            features: Some("alloc,std".into()),
+            ..Default::default()
+        };
+        assert_eq!(codes(&db, &filter), ["E0433"]);
+        assert_eq!(
+            db.get_error_history(&filter).unwrap()[0]
+                .features
+                .as_deref(),
+            Some("alloc,std")
+        );
     }
 
-    /// Clean up old data beyond a certain limit
-    #[allow(dead_code)]
-    pub fn cleanup_old_data(&self, keep_analyses: usize) -> Result<()> {
-        use rusqlite::params;
+    #[test]
+    fn result_cache_honors_ttl_and_purge() {
+        let (_dir, db) = test_db();
+        let run = CachedRun {
+            stdout: "out".into(),
+            stderr: "warning: unused variable".into(),
+            status: 0,
+            duration_ms: 1234,
+        };
+        db.put_cached_run("key", &run).unwrap();
+        let ttl = Duration::from_secs(60);
+        assert_eq!(db.get_cached_run("key", ttl).unwrap(), Some(run.clone()));
+        assert_eq!(db.get_cached_run("other", ttl).unwrap(), None);
+        db.conn
+            .execute(
+                "UPDATE result_cache SET created_at = created_at - 120000",
+                [],
+            )
+            .unwrap();
+        assert_eq!(db.get_cached_run("key", ttl).unwrap(), None);
 
-        // Delete old analyses and their associated errors
-        self.conn.execute(
-            "DELETE FROM errors WHERE analysis_id IN (
-                SELECT id FROM analyses
-                ORDER BY timestamp DESC
-                LIMIT -1 OFFSET ?1
-            )",
-            params![keep_analyses],
-        )?;
+        for i in 0..cache::MAX_ENTRIES + 1 {
+            db.put_cached_run(&i.to_string(), &run).unwrap();
+        }
+        assert_eq!(db.get_cached_run("0", ttl).unwrap(), None);
+        assert_eq!(db.purge_result_cache().unwrap(), cache::MAX_ENTRIES);
+    }
 
-        self.conn.execute(
-            "DELETE FROM analyses
-             WHERE id NOT IN (
-                SELECT id FROM analyses
-                ORDER BY timestamp DESC
-                LIMIT ?1
-             )",
-            params![keep_analyses],
-        )?;
+    #[test]
+    fn history_filters_by_batch() {
+        let (_dir, db) = test_db();
+        seed_history(&db);
+        let batch_id = new_batch_id("fn main() {}");
+        for (tool, code) in [("cargo_check", "E0308"), ("cargo_clippy", "W0001")] {
+            let id = store_output(&db, tool, "");
+            db.set_analysis_batch(id, &batch_id).unwrap();
+            db.store_error(id, Some(code), Some("error"), "batched", None, None, None)
+                .unwrap();
+        }
 
-        Ok(())
+        let filter = HistoryFilter {
+            batch_id: Some(batch_id.clone()),
+            ..Default::default()
+        };
+        let mut batched = codes(&db, &filter);
+        batched.sort();
+        assert_eq!(batched, ["E0308", "W0001"]);
+        assert!(
+            db.get_error_history(&filter)
+                .unwrap()
+                .iter()
+                .all(|e| e.batch_id.as_deref() == Some(batch_id.as_str()))
+        );
     }
-}
 
-#[derive(Debug, serde::Serialize)]
-pub struct ErrorRecord {
-    pub id: i64,
-    pub error_code: Option<String>,
-    pub message: String,
-    pub file: Option<String>,
-    pub line: Option<i32>,
-    pub suggestion: Option<String>,
-    pub timestamp: String,
-    pub tool: String,
-}
+    #[test]
+    fn artifact_is_read_from_cargo_messages() {
+        let dir = tempfile::tempdir().unwrap();
+        let binary = dir.path().join("temp_project");
+        std::fs::write(&binary, b"\x7fELF").unwrap();
+        let message = json!({
+            "reason": "compiler-artifact",
+            "executable": binary.to_str().unwrap()
+        });
+        let raw = format!("{}\nbuild script output\n", message);
 
-#[derive(Debug, serde::Serialize)]
-pub struct TodoRecord {
-    pub id: i64,
-    pub source: String,
-    pub description: String,
-    pub file_path: Option<String>,
-    pub line_number: Option<i32>,
-    pub completed: bool,
-    pub created_at: String,
-}
+        let mut stdout = raw.clone();
+        let artifact = collect_artifact(
+            &mut stdout,
+            "x86_64-unknown-linux-gnu".into(),
+            "release",
+            1024,
+        )
+        .unwrap()
+        .unwrap();
+        assert_eq!(stdout, "build script output\n");
+        assert_eq!(artifact.name, "temp_project");
+        assert_eq!(artifact.size_bytes, 4);
+        assert_eq!(artifact.base64.as_deref(), Some("f0VMRg=="));
+        assert!(!artifact.artifact_too_large);
 
-#[derive(Debug, serde::Serialize)]
-pub struct DatabaseStats {
-    pub total_analyses: usize,
-    pub total_errors: usize,
-    pub active_todos: usize,
-    pub completed_todos: usize,
-}
+        let mut stdout = raw;
+        let too_large = collect_artifact(&mut stdout, "x86_64-unknown-linux-gnu".into(), "dev", 2)
+            .unwrap()
+            .unwrap();
+        assert!(too_large.artifact_too_large);
+        assert!(too_large.base64.is_none());
 
-#[derive(Debug, serde::Serialize)]
-pub struct ExecResult {
-    pub stdout: String,
-    pub stderr: String,
-    pub status: i32,
-    pub duration_ms: u128,
-}
+        let mut empty = String::new();
+        assert!(
+            collect_artifact(&mut empty, String::new(), "dev", 2)
+                .unwrap()
+                .is_none()
+        );
+    }
 
-pub async fn run_rust_tool(
-    code: &str,
-    args: &[&str],
-    timeout: Option<Duration>,
-) -> Result<ExecResult, McpError> {
-    // Create a temporary directory for the Rust project
-    let temp_dir = tempfile::tempdir()
-        .map_err(|e| McpError::internal_error(format!("Failed to create temp dir: {}", e), None))?;
+    #[test]
+    fn saved_docs_replace_previous_copy() {
+        let dir = tempfile::tempdir().unwrap();
+        let generated = dir.path().join("doc");
+        std::fs::create_dir_all(generated.join("temp_project")).unwrap();
+        std::fs::write(generated.join("temp_project").join("index.html"), "<html>").unwrap();
+        let destination = dir.path().join("saved");
+        std::fs::create_dir_all(&destination).unwrap();
+        std::fs::write(destination.join("stale.html"), "").unwrap();
 
-    let project_path = temp_dir.path();
+        save_docs(&generated, &destination).unwrap();
+        assert!(destination.join("temp_project").join("index.html").exists());
+        assert!(!destination.join("stale.html").exists());
+    }
 
-    // Initialize a new Cargo project
-    let output = StdCommand::new("cargo")
-        .args(["init", "--name", "temp_project"])
-        .current_dir(project_path)
-        .output()
-        .map_err(|e| McpError::internal_error(format!("Failed to run cargo init: {}", e), None))?;
+    #[test]
+    fn error_code_changes_use_set_semantics() {
+        let (_dir, db) = test_db();
+        let baseline = store_output(&db, "cargo_check", "");
+        for code in ["E0308", "E0308", "E0425"] {
+            db.store_error(baseline, Some(code), Some("error"), "msg", None, None, None)
+                .unwrap();
+        }
+        let current = store_output(&db, "cargo_check", "");
+        for code in ["E0308", "E0599"] {
+            db.store_error(current, Some(code), Some("error"), "msg", None, None, None)
+                .unwrap();
+        }
+        db.store_error(current, None, Some("help"), "Help", None, None, None)
+            .unwrap();
 
-    if !output.status.success() {
-        let stderr = String::from_utf8_lossy(&output.stderr);
-        return Err(McpError::internal_error(
-            format!("Cargo init failed: {}", stderr),
+        let changes = ErrorCodeChanges::between(
+            &db.get_errors_for_analysis(baseline).unwrap(),
+            &db.get_errors_for_analysis(current).unwrap(),
+        );
+        assert_eq!(changes.resolved, ["E0425"]);
+        assert_eq!(changes.introduced, ["E0599"]);
+        assert_eq!(changes.persisting, ["E0308"]);
+    }
+
+    #[test]
+    fn latest_analyses_without_baseline() {
+        let (_dir, db) = test_db();
+        store_output(&db, "cargo_check", "");
+        let clippy = store_output(&db, "cargo_clippy", "");
+        db.store_error(
+            clippy,
             None,
-        ));
+            Some("warning"),
+            "needless return",
+            None,
+            None,
+            None,
+        )
+        .unwrap();
+
+        let ids = db.latest_analysis_ids(Some("cargo_clippy"), 2).unwrap();
+        assert_eq!(ids, [clippy]);
+
+        let diff = ErrorDiff::between(&[], &db.get_errors_for_analysis(clippy).unwrap());
+        assert_eq!(messages(&diff.added), ["needless return"]);
+        assert!(db.get_analysis(clippy + 100).unwrap().is_none());
     }
 
-    // Write the provided code to src/main.rs
-    let main_rs_path = project_path.join("src").join("main.rs");
-    std::fs::write(&main_rs_path, code)
-        .map_err(|e| McpError::internal_error(format!("Failed to write code: {}", e), None))?;
+    #[test]
+    fn analysis_detail_links_errors_and_fixes() {
+        let (_dir, db) = test_db();
+        let check = store_output(&db, "cargo_check", "error[E0308]: mismatched types");
+        let other = store_output(&db, "cargo_check", "");
+        db.store_error(
+            check,
+            Some("E0308"),
+            Some("error"),
+            "mismatched types",
+            Some("src/main.rs"),
+            Some(1),
+            None,
+        )
+        .unwrap();
+        db.store_error(other, None, Some("warning"), "unused", None, None, None)
+            .unwrap();
+        let error = db.get_errors_for_analysis(check).unwrap()[0].id;
+        let other_error = db.get_errors_for_analysis(other).unwrap()[0].id;
+        let edit = r#"{"line_start":1,"replacement":"1"}"#;
+        db.store_fix(Some(error), edit, true).unwrap();
+        db.store_fix(Some(other_error), edit, false).unwrap();
 
-    // Run the specified cargo command
-    let start = Instant::now();
-    let mut cmd = Command::new("cargo");
-    cmd.args(args)
-        .current_dir(project_path)
-        .env("CARGO_TERM_COLOR", "never");
+        let detail = db.get_analysis_detail(check).unwrap().unwrap();
+        assert_eq!(detail.analysis.tool, "cargo_check");
+        assert_eq!(detail.output["stderr"], "error[E0308]: mismatched types");
+        assert_eq!(detail.errors.len(), 1);
+        assert_eq!(detail.fixes.len(), 1);
+        assert_eq!(detail.fixes[0].error_id, Some(error));
+        assert_eq!(detail.fixes[0].fix_applied["replacement"], "1");
+        assert_eq!(detail.fixes[0].worked, Some(true));
 
-    let mut child = cmd
-        .stdout(std::process::Stdio::piped())
-        .stderr(std::process::Stdio::piped())
-        .spawn()
-        .map_err(|e| McpError::internal_error(format!("Failed to spawn cargo: {}", e), None))?;
+        assert!(db.get_analysis_detail(other + 100).unwrap().is_none());
+    }
 
-    let mut stdout_reader = child
-        .stdout
-        .take()
-        .ok_or_else(|| McpError::internal_error("Failed to capture stdout", None))?;
-    let mut stderr_reader = child
-        .stderr
-        .take()
-        .ok_or_else(|| McpError::internal_error("Failed to capture stderr", None))?;
+    #[test]
+    fn analysis_input_round_trips_code() {
+        let (_dir, db) = test_db();
+        let code = "fn main() { let x: i32 = \"a\"; }";
+        let output = json!({"status": 101, "stderr": "error[E0308]: mismatched types"});
+        let id = db
+            .store_analysis("cargo_check", &output, false, None, None, Some(code), None)
+            .unwrap();
+        let legacy = db
+            .store_analysis("cargo_check", &output, false, None, None, None, None)
+            .unwrap();
 
-    let out_handle = tokio::spawn(async move {
-        let mut buf = Vec::new();
-        let _ = stdout_reader.read_to_end(&mut buf).await;
-        buf
-    });
-    let err_handle = tokio::spawn(async move {
-        let mut buf = Vec::new();
-        let _ = stderr_reader.read_to_end(&mut buf).await;
-        buf
-    });
+        let (stored_code, stored_output) = db.get_analysis_input(id).unwrap().unwrap();
+        assert_eq!(stored_code.as_deref(), Some(code));
+        assert_eq!(stored_output, output);
+        assert_eq!(db.get_analysis_input(legacy).unwrap().unwrap().0, None);
+        assert!(db.get_analysis_input(legacy + 1).unwrap().is_none());
+    }
 
-    let status = if let Some(dur) = timeout {
-        match tokio::time::timeout(dur, child.wait()).await {
-            Ok(Ok(s)) => s,
-            Ok(Err(e)) => {
-                return Err(McpError::internal_error(
-                    format!("Failed to wait for cargo: {}", e),
+    #[test]
+    fn identical_code_shares_hash_and_history_links_code() {
+        let (_dir, db) = test_db();
+        let code = "fn main() { let x: i32 = \"a\"; }";
+        let mut ids = Vec::new();
+        for _ in 0..2 {
+            let id = db
+                .store_analysis(
+                    "cargo_check",
+                    &json!({}),
+                    false,
                     None,
-                ));
-            }
-            Err(_) => {
-                let _ = child.kill().await;
-                let _ = child.wait().await;
-                return Err(McpError::internal_error(
-                    "Command timed out".to_string(),
                     None,
-                ));
-            }
+                    Some(code),
+                    None,
+                )
+                .unwrap();
+            db.store_error(
+                id,
+                Some("E0308"),
+                Some("error"),
+                "mismatched types",
+                None,
+                None,
+                None,
+            )
+            .unwrap();
+            ids.push(id);
         }
-    } else {
-        child.wait().await.map_err(|e| {
-            McpError::internal_error(format!("Failed to wait for cargo: {}", e), None)
-        })?
-    };
 
-    let duration_ms = start.elapsed().as_millis();
+        let history = db.get_error_history(&HistoryFilter::default()).unwrap();
+        assert_eq!(history.len(), 2);
+        assert_eq!(history[0].code_hash, history[1].code_hash);
+        assert_eq!(
+            history[0].code_hash.as_deref(),
+            Some(code_hash(code).as_str())
+        );
+        assert!(ids.contains(&history[0].analysis_id));
 
-    let stdout_bytes = out_handle
-        .await
-        .map_err(|e| McpError::internal_error(format!("Stdout task failed: {}", e), None))?;
-    let stderr_bytes = err_handle
-        .await
-        .map_err(|e| McpError::internal_error(format!("Stderr task failed: {}", e), None))?;
+        let codes = db
+            .get_code_by_hash(history.iter().filter_map(|e| e.code_hash.as_deref()))
+            .unwrap();
+        assert_eq!(codes.len(), 1);
+        assert_eq!(codes[&code_hash(code)], code);
 
-    let stdout = String::from_utf8_lossy(&stdout_bytes).to_string();
-    let stderr = String::from_utf8_lossy(&stderr_bytes).to_string();
-    let status = status.code().unwrap_or(-1);
+        let snippets: i64 = db
+            .conn
+            .query_row("SELECT COUNT(*) FROM snippets", [], |row| row.get(0))
+            .unwrap();
+        assert_eq!(snippets, 1);
+        db.cleanup_old_data(0).unwrap();
+        assert!(
+            db.get_code_by_hash([code_hash(code).as_str()])
+                .unwrap()
+                .is_empty()
+        );
+    }
 
-    Ok(ExecResult {
-        stdout,
-        stderr,
-        status,
-        duration_ms,
-    })
+    #[test]
+    fn code_hash_is_backfilled_on_open() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("test.db");
+        let db = Database::new(PersistenceMode::Path(path.clone()))
+            .unwrap()
+            .unwrap();
+        let id = db
+            .store_analysis(
+                "cargo_check",
+                &json!({}),
+                true,
+                None,
+                None,
+                Some("fn main() {}"),
+                None,
+            )
+            .unwrap();
+        // Before snippets, code was stored inline without a hash
+        db.conn
+            .execute(
+                "UPDATE analyses SET code = 'fn main() {}', code_hash = NULL",
+                [],
+            )
+            .unwrap();
+        db.conn.execute("DELETE FROM snippets", []).unwrap();
+        drop(db);
+
+        let db = Database::new(PersistenceMode::Path(path)).unwrap().unwrap();
+        let hash: Option<String> = db
+            .conn
+            .query_row(
+                "SELECT code_hash FROM analyses WHERE id = ?1",
+                [id],
+                |row| row.get(0),
+            )
+            .unwrap();
+        assert_eq!(hash, Some(code_hash("fn main() {}")));
+        let (code, _) = db.get_analysis_input(id).unwrap().unwrap();
+        assert_eq!(code.as_deref(), Some("fn main() {}"));
+    }
+
+    #[test]
+    fn parsed_error_records_match_stored_errors() {
+        let (_dir, db) = test_db();
+        let stderr = "error[E0308]: mismatched types\n --> src/main.rs:1:25\nhelp: try this";
+        let id = store_output(&db, "cargo_check", stderr);
+        RustyToolsServer::parse_and_store_errors(&db, id, CLIPPY_STDOUT, stderr).unwrap();
+
+        let parsed = RustyToolsServer::parse_error_records("cargo_check", CLIPPY_STDOUT, stderr);
+        let diff = ErrorDiff::between(&db.get_errors_for_analysis(id).unwrap(), &parsed);
+        assert_eq!(diff.unchanged.len(), parsed.len());
+        assert!(!parsed.is_empty());
+        assert!(diff.added.is_empty() && diff.removed.is_empty());
+    }
+
+    #[test]
+    fn every_snippet_tool_has_a_cargo_command() {
+        for tool in [
+            "cargo_fmt",
+            "cargo_clippy",
+            "cargo_check",
+            "cargo_fix",
+            "cargo_audit",
+            "cargo_test",
+            "cargo_build",
+            "cargo_tree",
+            "cargo_doc",
+            "rust_analyzer",
+        ] {
+            assert!(cargo_tool_command(tool).is_some(), "{tool}");
+        }
+        assert!(cargo_tool_command("cargo_history").is_none());
+    }
+
+    #[test]
+    fn oversized_inputs_are_refused_with_their_size() {
+        let config = ServerConfig {
+            pool_size: 0,
+            input_max_bytes: 10,
+            files_max_bytes: 16,
+            ..ServerConfig::default()
+        };
+        let server = RustyToolsServer::with_config(PersistenceMode::Disabled, config);
+        let check = |arguments: Value| {
+            server.check_input_size(&args::parse::<args::Options>(arguments.as_object()).unwrap())
+        };
+
+        assert!(check(json!({"code": "fn main(){}"})).is_err());
+        assert!(check(json!({"code": "fn f(){}", "files": {"src/a.rs": "mod b;"}})).is_ok());
+        let error = check(json!({"baseline_code": "pub fn old() {}"})).unwrap_err();
+        assert!(
+            error
+                .message
+                .starts_with("baseline_code is 15 bytes, over the 10-byte limit"),
+            "{}",
+            error.message
+        );
+        let error =
+            check(json!({"files": {"src/a.rs": "pub mod b;", "src/big.rs": "pub fn big() {}"}}))
+                .unwrap_err();
+        assert_eq!(
+            error.data.unwrap(),
+            json!({"input": "files[src/big.rs]", "size_bytes": 15, "limit_bytes": 10})
+        );
+        let error = check(json!({"files": {"src/a.rs": "pub mod b;", "src/b.rs": "pub mod c;"}}))
+            .unwrap_err();
+        assert_eq!(error.data.unwrap()["input"], "files");
+
+        let instructions = server.get_info().instructions.unwrap();
+        assert!(
+            instructions.contains("limited to 10 bytes per code"),
+            "{}",
+            instructions
+        );
+    }
+
+    #[test]
+    fn pretty_printing_indents_json_results_only() {
+        let mut result = CallToolResult::success(vec![
+            rmcp::model::Content::text(json!({"status": 0, "summary": "ok"}).to_string()),
+            rmcp::model::Content::text("plain text"),
+        ]);
+        pretty_print(&mut result);
+        let text = |i: usize| result.content[i].as_text().unwrap().text.clone();
+        assert_eq!(text(0), "{\n  \"status\": 0,\n  \"summary\": \"ok\"\n}");
+        assert_eq!(text(1), "plain text");
+    }
+
+    #[test]
+    fn iso8601_timestamp_validation() {
+        for ok in [
+            "2025-01-31",
+            "2025-01-31T10:20",
+            "2025-01-31 10:20:30",
+            "2025-01-31T10:20:30.123Z",
+            "2025-01-31T10:20:30+02:00",
+        ] {
+            assert!(is_iso8601_timestamp(ok), "{ok}");
+        }
+        for bad in [
+            "yesterday",
+            "2025-1-31",
+            "2025-01-31T10",
+            "2025-01-31T10:20:30.",
+            "",
+        ] {
+            assert!(!is_iso8601_timestamp(bad), "{bad}");
+        }
+    }
 }
@@ -0,0 +1,113 @@
+//! Resource limits for spawned cargo commands. On Unix each process cargo
+//! starts (rustc, build scripts, the snippet's own binary) inherits rlimits on
+//! memory and CPU time, so a const-eval bomb or runaway allocation fails that
+//! process instead of taking the host down with it. Other platforms run
+//! without them but still recognize a process that ran out of memory.
+
+use tokio::process::Command;
+
+/// Per-process caps; `None` leaves a resource unlimited
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct ResourceLimits {
+    pub memory_bytes: Option<u64>,
+    pub cpu_secs: Option<u64>,
+}
+
+impl ResourceLimits {
+    /// Set the limits in the child between fork and exec
+    pub fn apply(&self, cmd: &mut Command) {
+        #[cfg(unix)]
+        if *self != ResourceLimits::default() {
+            let limits = *self;
+            // SAFETY: the closure only calls setrlimit(2), which is
+            // async-signal-safe, and allocates nothing
+            unsafe {
+                cmd.pre_exec(move || limits.set());
+            }
+        }
+        #[cfg(not(unix))]
+        let _ = cmd;
+    }
+
+    #[cfg(unix)]
+    fn set(&self) -> std::io::Result<()> {
+        if let Some(bytes) = self.memory_bytes {
+            set_rlimit(MEMORY_RESOURCE, bytes, bytes)?;
+        }
+        if let Some(secs) = self.cpu_secs {
+            // The soft limit sends SIGXCPU; the hard one a second later
+            // kills anything that ignores it
+            set_rlimit(libc::RLIMIT_CPU, secs, secs.saturating_add(1))?;
+        }
+        Ok(())
+    }
+}
+
+/// Linux counts only memory a process can write to against `RLIMIT_DATA`, so
+/// the address space rustc's threads reserve up front doesn't trip it; other
+/// Unixes only offer `RLIMIT_AS`
+#[cfg(target_os = "linux")]
+const MEMORY_RESOURCE: Resource = libc::RLIMIT_DATA;
+#[cfg(all(unix, not(target_os = "linux")))]
+const MEMORY_RESOURCE: Resource = libc::RLIMIT_AS;
+
+/// glibc declares the resource argument of setrlimit as an enum
+#[cfg(all(target_os = "linux", target_env = "gnu"))]
+type Resource = libc::__rlimit_resource_t;
+#[cfg(all(unix, not(all(target_os = "linux", target_env = "gnu"))))]
+type Resource = libc::c_int;
+
+#[cfg(unix)]
+fn set_rlimit(resource: Resource, soft: u64, hard: u64) -> std::io::Result<()> {
+    let limit = libc::rlimit {
+        rlim_cur: soft as libc::rlim_t,
+        rlim_max: hard as libc::rlim_t,
+    };
+    // SAFETY: `limit` is a valid rlimit for the duration of the call
+    if unsafe { libc::setrlimit(resource, &limit) } != 0 {
+        return Err(std::io::Error::last_os_error());
+    }
+    Ok(())
+}
+
+/// Which limit stopped a run, if any: "memory" or "cpu". `signal` is what
+/// killed cargo itself; `stderr` is where cargo reports a rustc or snippet
+/// process that died.
+pub fn exceeded(signal: Option<i32>, stderr: &str) -> Option<&'static str> {
+    #[cfg(unix)]
+    if signal == Some(libc::SIGXCPU) {
+        return Some("cpu");
+    }
+    #[cfg(not(unix))]
+    let _ = signal;
+    if stderr.contains("SIGXCPU") || stderr.contains("CPU time limit exceeded") {
+        return Some("cpu");
+    }
+    let out_of_memory = stderr.lines().any(|line| {
+        (line.contains("memory allocation of") && line.contains("failed"))
+            || line.contains("out of memory")
+            || line.contains("Cannot allocate memory")
+            // How cargo reports a Windows process that ran out
+            || line.contains("STATUS_NO_MEMORY")
+    });
+    out_of_memory.then_some("memory")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn limits_are_recognized_in_what_cargo_reports() {
+        let oom = "   Compiling play v0.1.0\nmemory allocation of 4294967296 bytes failed\nerror: could not compile `play` (bin \"play\")\n\nCaused by:\n  process didn't exit successfully: `rustc ...` (signal: 6, SIGABRT: process abort signal)\n";
+        assert_eq!(exceeded(None, oom), Some("memory"));
+        let cpu = "     Running `target/debug/play`\nerror: process didn't exit successfully: `target/debug/play` (signal: 24, SIGXCPU: CPU time limit exceeded)\n";
+        assert_eq!(exceeded(None, cpu), Some("cpu"));
+        let windows = "error: process didn't exit successfully: `target\\debug\\play.exe` (exit code: 0xc0000017, STATUS_NO_MEMORY)\n";
+        assert_eq!(exceeded(None, windows), Some("memory"));
+        let failed = "error[E0308]: mismatched types\nerror: could not compile `play`\n";
+        assert_eq!(exceeded(None, failed), None);
+        #[cfg(unix)]
+        assert_eq!(exceeded(Some(libc::SIGXCPU), ""), Some("cpu"));
+    }
+}
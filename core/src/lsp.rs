@@ -0,0 +1,1065 @@
+//! `rust_analyzer` actions answered by a real rust-analyzer speaking LSP over
+//! stdio. Each session label gets its own server and scratch project, kept
+//! running between calls so indexing is paid once, and shut down after it
+//! sits idle.
+
+use crate::child_env::ChildEnv;
+use crate::pool::ScratchProject;
+use crate::process_tree::ProcessTree;
+use rmcp::ErrorData as McpError;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use serde_json::{Value, json};
+use std::collections::{HashMap, HashSet};
+use std::path::Path;
+use std::process::Stdio;
+use std::sync::atomic::{AtomicI64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tokio::io::{AsyncBufRead, AsyncBufReadExt, AsyncReadExt, AsyncWriteExt, BufReader};
+use tokio::process::{Child, ChildStdin, Command};
+use tokio::sync::{oneshot, watch};
+
+/// How long one action may wait for rust-analyzer, including indexing a new
+/// session's project and standard library
+const ACTION_TIMEOUT: Duration = Duration::from_secs(120);
+/// Quiet period after the check finishes before diagnostics count as final
+const SETTLE: Duration = Duration::from_millis(300);
+/// Progress token prefix of rust-analyzer's check-on-save runs
+const FLYCHECK_TOKEN: &str = "rust-analyzer/flycheck";
+
+/// What a `rust_analyzer` call asks for
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "lowercase")]
+#[schemars(inline)]
+pub enum Action {
+    /// Diagnostics published for the code, including check-on-save results
+    #[default]
+    Diagnostics,
+    /// Type and docs of the item at a position
+    Hover,
+    /// Completions offered at a position
+    Completions,
+    /// Outline of the items the code defines
+    Symbols,
+    /// Where the item at a position is defined
+    Definition,
+}
+
+impl Action {
+    pub fn name(self) -> &'static str {
+        match self {
+            Action::Diagnostics => "diagnostics",
+            Action::Hover => "hover",
+            Action::Completions => "completions",
+            Action::Symbols => "symbols",
+            Action::Definition => "definition",
+        }
+    }
+
+    /// Whether the action reads a `line`/`column` position
+    pub fn needs_position(self) -> bool {
+        matches!(
+            self,
+            Action::Hover | Action::Completions | Action::Definition
+        )
+    }
+}
+
+/// A 1-based line and character column, the convention of our diagnostics
+#[derive(Debug, Clone, Copy, PartialEq, serde::Serialize)]
+pub struct Position {
+    pub line: u32,
+    pub column: u32,
+}
+
+/// One action against one version of the code
+pub struct Query<'a> {
+    pub action: Action,
+    pub code: &'a str,
+    pub position: Option<Position>,
+    /// Most completions returned
+    pub limit: usize,
+}
+
+/// What the reader task has learned from server notifications
+#[derive(Debug, Default)]
+struct ServerState {
+    /// From `experimental/serverStatus`: nothing left to load or index
+    quiescent: bool,
+    /// `$/progress` tokens begun and not yet ended
+    in_progress: HashSet<String>,
+    /// Check-on-save runs finished so far
+    checks_finished: u64,
+    /// Latest `publishDiagnostics` for the snippet
+    diagnostics: Vec<Value>,
+    /// stdout closed: the server exited or was killed
+    exited: bool,
+}
+
+impl ServerState {
+    fn ready(&self) -> bool {
+        self.quiescent && self.in_progress.is_empty()
+    }
+}
+
+type Pending = HashMap<i64, oneshot::Sender<Result<Value, String>>>;
+
+struct Shared {
+    state: Mutex<ServerState>,
+    /// Bumped after every state change
+    changed: watch::Sender<u64>,
+    /// Requests awaiting their response, by id
+    pending: Mutex<Pending>,
+}
+
+impl Shared {
+    fn state(&self) -> std::sync::MutexGuard<'_, ServerState> {
+        self.state
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+
+    fn pending(&self) -> std::sync::MutexGuard<'_, Pending> {
+        self.pending
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+
+    fn update(&self, f: impl FnOnce(&mut ServerState)) {
+        f(&mut self.state());
+        self.changed.send_modify(|n| *n += 1);
+    }
+}
+
+/// A running rust-analyzer and the task reading its stdout
+struct LspClient {
+    child: Child,
+    /// rust-analyzer and the cargo checks it runs
+    tree: ProcessTree,
+    stdin: Arc<tokio::sync::Mutex<ChildStdin>>,
+    shared: Arc<Shared>,
+    next_id: AtomicI64,
+}
+
+impl LspClient {
+    fn spawn(root: &Path, child_env: &ChildEnv) -> Result<Self, McpError> {
+        let mut command = Command::new("rust-analyzer");
+        command
+            .current_dir(root)
+            .env_clear()
+            .envs(child_env.vars())
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::null())
+            .kill_on_drop(true);
+        // So `Drop` also stops the cargo checks it runs
+        ProcessTree::prepare(&mut command);
+        let mut child = command.spawn().map_err(|e| {
+            McpError::internal_error(format!("Failed to start rust-analyzer: {}", e), None)
+        })?;
+        let tree = ProcessTree::attach(&child);
+        let stdin = Arc::new(tokio::sync::Mutex::new(
+            child.stdin.take().expect("stdin is piped"),
+        ));
+        let stdout = child.stdout.take().expect("stdout is piped");
+        let shared = Arc::new(Shared {
+            state: Mutex::default(),
+            changed: watch::Sender::new(0),
+            pending: Mutex::default(),
+        });
+
+        let reader_shared = Arc::clone(&shared);
+        let reader_stdin = Arc::clone(&stdin);
+        tokio::spawn(async move {
+            let mut reader = BufReader::new(stdout);
+            while let Ok(Some(message)) = read_message(&mut reader).await {
+                handle_message(&reader_shared, &reader_stdin, message).await;
+            }
+            // Fail requests still waiting instead of letting them hang
+            reader_shared.pending().clear();
+            reader_shared.update(|state| state.exited = true);
+        });
+
+        Ok(LspClient {
+            child,
+            tree,
+            stdin,
+            shared,
+            next_id: AtomicI64::new(1),
+        })
+    }
+
+    async fn send(&self, message: Value) -> Result<(), McpError> {
+        let mut stdin = self.stdin.lock().await;
+        stdin.write_all(&encode(&message)).await.map_err(|e| {
+            McpError::internal_error(format!("Failed to write to rust-analyzer: {}", e), None)
+        })
+    }
+
+    async fn notify(&self, method: &str, params: Value) -> Result<(), McpError> {
+        self.send(json!({"jsonrpc": "2.0", "method": method, "params": params}))
+            .await
+    }
+
+    async fn request(&self, method: &str, params: Value) -> Result<Value, McpError> {
+        let id = self.next_id.fetch_add(1, Ordering::Relaxed);
+        let (tx, rx) = oneshot::channel();
+        self.shared.pending().insert(id, tx);
+        self.send(json!({"jsonrpc": "2.0", "id": id, "method": method, "params": params}))
+            .await?;
+        match rx.await {
+            Ok(Ok(result)) => Ok(result),
+            Ok(Err(error)) => Err(McpError::internal_error(
+                format!("rust-analyzer {} failed: {}", method, error),
+                None,
+            )),
+            Err(_) => Err(McpError::internal_error("rust-analyzer exited", None)),
+        }
+    }
+
+    /// Wait until `done` holds for the server state
+    async fn wait_until(&self, done: impl Fn(&ServerState) -> bool) -> Result<(), McpError> {
+        let mut changed = self.shared.changed.subscribe();
+        loop {
+            {
+                let state = self.shared.state();
+                if done(&state) {
+                    return Ok(());
+                }
+                if state.exited {
+                    return Err(McpError::internal_error("rust-analyzer exited", None));
+                }
+            }
+            changed
+                .changed()
+                .await
+                .map_err(|_| McpError::internal_error("rust-analyzer exited", None))?;
+        }
+    }
+
+    /// Wait until no notification has arrived for `SETTLE`
+    async fn settle(&self) {
+        let mut changed = self.shared.changed.subscribe();
+        while let Ok(Ok(())) = tokio::time::timeout(SETTLE, changed.changed()).await {}
+    }
+
+    fn exited(&self) -> bool {
+        self.shared.state().exited
+    }
+
+    /// Ask the server to exit, as editors do on close
+    async fn stop(&self) {
+        let stop = async {
+            self.request("shutdown", Value::Null).await?;
+            self.notify("exit", Value::Null).await
+        };
+        let _ = tokio::time::timeout(Duration::from_secs(2), stop).await;
+    }
+}
+
+impl Drop for LspClient {
+    fn drop(&mut self) {
+        self.tree.kill(&self.child);
+    }
+}
+
+/// Route one message from the server: responses to their waiting request,
+/// notifications into the state, and server requests answered with defaults
+async fn handle_message(shared: &Shared, stdin: &tokio::sync::Mutex<ChildStdin>, message: Value) {
+    let method = message["method"].as_str();
+    match (method, message.get("id")) {
+        (None, Some(id)) => {
+            let Some(id) = id.as_i64() else { return };
+            let Some(tx) = shared.pending().remove(&id) else {
+                return;
+            };
+            let result = match message.get("error") {
+                Some(error) => Err(error["message"].as_str().unwrap_or("error").to_string()),
+                None => Ok(message["result"].clone()),
+            };
+            let _ = tx.send(result);
+        }
+        (Some(method), Some(id)) => {
+            // Configuration requests get one null (use defaults) per item;
+            // progress creation and refresh requests need no more than a reply
+            let result = match method {
+                "workspace/configuration" => {
+                    let items = message["params"]["items"].as_array().map_or(0, Vec::len);
+                    Value::Array(vec![Value::Null; items])
+                }
+                _ => Value::Null,
+            };
+            let reply = json!({"jsonrpc": "2.0", "id": id, "result": result});
+            let _ = stdin.lock().await.write_all(&encode(&reply)).await;
+        }
+        (Some("experimental/serverStatus"), None) => {
+            let quiescent = message["params"]["quiescent"] == true;
+            shared.update(|state| state.quiescent = quiescent);
+        }
+        (Some("$/progress"), None) => {
+            let params = &message["params"];
+            let token = match &params["token"] {
+                Value::String(token) => token.clone(),
+                token => token.to_string(),
+            };
+            match params["value"]["kind"].as_str() {
+                Some("begin") => shared.update(|state| {
+                    state.in_progress.insert(token);
+                }),
+                Some("end") => shared.update(|state| {
+                    state.in_progress.remove(&token);
+                    if token.starts_with(FLYCHECK_TOKEN) {
+                        state.checks_finished += 1;
+                    }
+                }),
+                _ => {}
+            }
+        }
+        (Some("textDocument/publishDiagnostics"), None) => {
+            let params = &message["params"];
+            if !params["uri"]
+                .as_str()
+                .is_some_and(|uri| uri.ends_with("/src/main.rs"))
+            {
+                return;
+            }
+            let diagnostics = params["diagnostics"]
+                .as_array()
+                .cloned()
+                .unwrap_or_default();
+            shared.update(|state| state.diagnostics = diagnostics);
+        }
+        _ => {}
+    }
+}
+
+/// A rust-analyzer with its project and the snippet open as `src/main.rs`
+struct Session {
+    // Declared first so the server is killed before its project is removed
+    client: LspClient,
+    project: ScratchProject,
+    uri: String,
+    /// Version of the open document, `None` until opened. Calls hold the lock
+    /// for their whole turn so edits and queries don't interleave.
+    version: tokio::sync::Mutex<Option<i64>>,
+    last_used: Mutex<Instant>,
+}
+
+impl Session {
+    async fn start(child_env: &ChildEnv) -> Result<Self, McpError> {
+        let project = ScratchProject::create(child_env)?;
+        let root = project.path().canonicalize().map_err(|e| {
+            McpError::internal_error(format!("Failed to resolve project path: {}", e), None)
+        })?;
+        let client = LspClient::spawn(&root, child_env)?;
+        client
+            .request(
+                "initialize",
+                json!({
+                    "processId": std::process::id(),
+                    "rootUri": file_uri(&root),
+                    "capabilities": {
+                        "window": {"workDoneProgress": true},
+                        "textDocument": {
+                            "hover": {"contentFormat": ["markdown", "plaintext"]},
+                            "completion": {},
+                            "documentSymbol": {"hierarchicalDocumentSymbolSupport": true},
+                            "definition": {"linkSupport": true},
+                            "publishDiagnostics": {"versionSupport": true}
+                        },
+                        "experimental": {"serverStatusNotification": true}
+                    },
+                    "initializationOptions": {"checkOnSave": true}
+                }),
+            )
+            .await?;
+        client.notify("initialized", json!({})).await?;
+        Ok(Session {
+            client,
+            uri: file_uri(&root.join("src").join("main.rs")),
+            project,
+            version: tokio::sync::Mutex::new(None),
+            last_used: Mutex::new(Instant::now()),
+        })
+    }
+
+    fn touch(&self) {
+        *self.last_used.lock().unwrap_or_else(|p| p.into_inner()) = Instant::now();
+    }
+
+    fn idle_for(&self) -> Duration {
+        self.last_used
+            .lock()
+            .unwrap_or_else(|p| p.into_inner())
+            .elapsed()
+    }
+
+    /// Replace the document with `code`, save it so check-on-save runs, and
+    /// answer the query once the server has caught up
+    async fn run(&self, query: &Query<'_>) -> Result<Value, McpError> {
+        let mut version = self.version.lock().await;
+        std::fs::write(self.project.path().join("src").join("main.rs"), query.code).map_err(
+            |e| McpError::internal_error(format!("Failed to write main.rs: {}", e), None),
+        )?;
+        let checks_before = self.client.shared.state().checks_finished;
+        let next = version.map_or(1, |v| v + 1);
+        match *version {
+            None => {
+                self.client
+                    .notify(
+                        "textDocument/didOpen",
+                        json!({"textDocument": {"uri": self.uri, "languageId": "rust", "version": next, "text": query.code}}),
+                    )
+                    .await?
+            }
+            Some(_) => {
+                self.client
+                    .notify(
+                        "textDocument/didChange",
+                        json!({"textDocument": {"uri": self.uri, "version": next}, "contentChanges": [{"text": query.code}]}),
+                    )
+                    .await?
+            }
+        }
+        *version = Some(next);
+        self.client
+            .notify(
+                "textDocument/didSave",
+                json!({"textDocument": {"uri": self.uri}}),
+            )
+            .await?;
+
+        let document = json!({"uri": self.uri});
+        match query.action {
+            Action::Diagnostics => {
+                self.client
+                    .wait_until(|state| state.ready() && state.checks_finished > checks_before)
+                    .await?;
+                self.client.settle().await;
+                let diagnostics = self.client.shared.state().diagnostics.clone();
+                let diagnostics: Vec<Value> = diagnostics
+                    .iter()
+                    .map(|d| diagnostic(query.code, d))
+                    .collect();
+                Ok(json!({"diagnostics": diagnostics}))
+            }
+            Action::Hover => {
+                self.client.wait_until(ServerState::ready).await?;
+                let position = lsp_position(query.code, query.position)?;
+                let hover = self
+                    .client
+                    .request(
+                        "textDocument/hover",
+                        json!({"textDocument": document, "position": position}),
+                    )
+                    .await?;
+                Ok(json!({"hover": hover_result(query.code, &hover)}))
+            }
+            Action::Completions => {
+                self.client.wait_until(ServerState::ready).await?;
+                let position = lsp_position(query.code, query.position)?;
+                let list = self
+                    .client
+                    .request(
+                        "textDocument/completion",
+                        json!({"textDocument": document, "position": position, "context": {"triggerKind": 1}}),
+                    )
+                    .await?;
+                let mut items = match &list {
+                    Value::Array(items) => items.clone(),
+                    list => list["items"].as_array().cloned().unwrap_or_default(),
+                };
+                // The server leaves matching what was typed to the client
+                let typed = typed_prefix(query.code, query.position).to_lowercase();
+                items.retain(|item| {
+                    item["filterText"]
+                        .as_str()
+                        .or(item["label"].as_str())
+                        .is_some_and(|text| text.to_lowercase().starts_with(&typed))
+                });
+                // Best matches first, as an editor would list them
+                items.sort_by_cached_key(|item| {
+                    item["sortText"]
+                        .as_str()
+                        .or(item["label"].as_str())
+                        .unwrap_or_default()
+                        .to_string()
+                });
+                let completions: Vec<Value> =
+                    items.iter().take(query.limit).map(completion).collect();
+                Ok(json!({
+                    "completions": completions,
+                    "total": items.len(),
+                    "is_incomplete": list["isIncomplete"] == true || items.len() > query.limit
+                }))
+            }
+            Action::Symbols => {
+                self.client.wait_until(ServerState::ready).await?;
+                let symbols = self
+                    .client
+                    .request(
+                        "textDocument/documentSymbol",
+                        json!({"textDocument": document}),
+                    )
+                    .await?;
+                let symbols: Vec<Value> = symbols
+                    .as_array()
+                    .into_iter()
+                    .flatten()
+                    .map(|s| symbol(query.code, s))
+                    .collect();
+                Ok(json!({"symbols": symbols}))
+            }
+            Action::Definition => {
+                self.client.wait_until(ServerState::ready).await?;
+                let position = lsp_position(query.code, query.position)?;
+                let locations = self
+                    .client
+                    .request(
+                        "textDocument/definition",
+                        json!({"textDocument": document, "position": position}),
+                    )
+                    .await?;
+                let locations = match locations {
+                    Value::Array(locations) => locations,
+                    Value::Null => Vec::new(),
+                    location => vec![location],
+                };
+                let definitions: Vec<Value> = locations
+                    .iter()
+                    .map(|l| definition(query.code, &self.uri, l))
+                    .collect();
+                Ok(json!({"definitions": definitions}))
+            }
+        }
+    }
+}
+
+/// rust-analyzer servers by session label
+#[derive(Clone)]
+pub struct LspSessions {
+    sessions: Arc<Mutex<HashMap<String, Arc<Session>>>>,
+    /// Held while a session starts, so concurrent first calls share one server
+    starting: Arc<tokio::sync::Mutex<()>>,
+    child_env: ChildEnv,
+    idle: Duration,
+}
+
+impl LspSessions {
+    pub fn new(child_env: ChildEnv, idle: Duration) -> Self {
+        LspSessions {
+            sessions: Arc::default(),
+            starting: Arc::default(),
+            child_env,
+            idle,
+        }
+    }
+
+    fn sessions(&self) -> std::sync::MutexGuard<'_, HashMap<String, Arc<Session>>> {
+        self.sessions
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+
+    /// Number of running servers
+    pub fn running(&self) -> usize {
+        self.sessions().len()
+    }
+
+    /// Kill every server and remove its project
+    pub fn shutdown(&self) {
+        self.sessions().clear();
+    }
+
+    /// Answer `query` with the server of session `key`, starting one if needed
+    pub async fn run(&self, key: &str, query: Query<'_>) -> Result<Value, McpError> {
+        if query.action.needs_position() && query.position.is_none() {
+            return Err(McpError::invalid_params(
+                format!("{} needs line and column", query.action.name()),
+                None,
+            ));
+        }
+        let session = self.session(key).await?;
+        session.touch();
+        let result = tokio::select! {
+            result = tokio::time::timeout(ACTION_TIMEOUT, session.run(&query)) => {
+                result.unwrap_or_else(|_| {
+                    Err(McpError::internal_error(
+                        format!("rust-analyzer did not answer within {}s", ACTION_TIMEOUT.as_secs()),
+                        None,
+                    ))
+                })
+            }
+            _ = crate::cancellation::cancelled() => {
+                Err(McpError::internal_error("Tool call cancelled", None))
+            }
+        };
+        session.touch();
+        if session.client.exited() {
+            self.remove(key, &session);
+        }
+        result
+    }
+
+    async fn session(&self, key: &str) -> Result<Arc<Session>, McpError> {
+        let _starting = self.starting.lock().await;
+        if let Some(session) = self.sessions().get(key)
+            && !session.client.exited()
+        {
+            return Ok(Arc::clone(session));
+        }
+        tracing::info!("Starting rust-analyzer for session {}", key);
+        let session = Arc::new(Session::start(&self.child_env).await?);
+        self.sessions()
+            .insert(key.to_string(), Arc::clone(&session));
+        self.stop_when_idle(key.to_string(), &session);
+        Ok(session)
+    }
+
+    fn remove(&self, key: &str, session: &Arc<Session>) {
+        let mut sessions = self.sessions();
+        if sessions.get(key).is_some_and(|s| Arc::ptr_eq(s, session)) {
+            sessions.remove(key);
+        }
+    }
+
+    /// Shut the session's server down once no call has used it for `idle`
+    fn stop_when_idle(&self, key: String, session: &Arc<Session>) {
+        let sessions = self.clone();
+        let weak = Arc::downgrade(session);
+        tokio::spawn(async move {
+            loop {
+                let Some(session) = weak.upgrade() else {
+                    return;
+                };
+                let remaining = sessions.idle.saturating_sub(session.idle_for());
+                if remaining.is_zero() {
+                    tracing::info!("Stopping idle rust-analyzer for session {}", key);
+                    sessions.remove(&key, &session);
+                    session.client.stop().await;
+                    return;
+                }
+                drop(session);
+                tokio::time::sleep(remaining).await;
+            }
+        });
+    }
+}
+
+/// Frame a message with the LSP `Content-Length` header
+fn encode(message: &Value) -> Vec<u8> {
+    let body = message.to_string();
+    format!("Content-Length: {}\r\n\r\n{}", body.len(), body).into_bytes()
+}
+
+/// Read one framed message; `None` at end of stream
+async fn read_message<R: AsyncBufRead + Unpin>(reader: &mut R) -> std::io::Result<Option<Value>> {
+    let invalid = |message: String| std::io::Error::new(std::io::ErrorKind::InvalidData, message);
+    let mut length = None;
+    loop {
+        let mut line = String::new();
+        if reader.read_line(&mut line).await? == 0 {
+            return Ok(None);
+        }
+        let line = line.trim_end();
+        if line.is_empty() {
+            break;
+        }
+        if let Some((name, value)) = line.split_once(':')
+            && name.eq_ignore_ascii_case("content-length")
+        {
+            length = value.trim().parse::<usize>().ok();
+        }
+    }
+    let length = length.ok_or_else(|| invalid("missing Content-Length".to_string()))?;
+    let mut body = vec![0; length];
+    reader.read_exact(&mut body).await?;
+    serde_json::from_slice(&body)
+        .map(Some)
+        .map_err(|e| invalid(e.to_string()))
+}
+
+fn file_uri(path: &Path) -> String {
+    format!("file://{}", path.display())
+}
+
+/// Our 1-based character position as an LSP position, which counts UTF-16
+/// code units from 0
+fn lsp_position(code: &str, position: Option<Position>) -> Result<Value, McpError> {
+    let position =
+        position.ok_or_else(|| McpError::invalid_params("line and column are required", None))?;
+    let out_of_bounds = || {
+        McpError::invalid_params(
+            format!(
+                "line {} column {} is outside the code",
+                position.line, position.column
+            ),
+            None,
+        )
+    };
+    let line = (position.line as usize)
+        .checked_sub(1)
+        .ok_or_else(out_of_bounds)?;
+    let text = code.split('\n').nth(line).ok_or_else(out_of_bounds)?;
+    let column = (position.column as usize)
+        .checked_sub(1)
+        .filter(|&column| column <= text.chars().count())
+        .ok_or_else(out_of_bounds)?;
+    let character: usize = text.chars().take(column).map(char::len_utf16).sum();
+    Ok(json!({"line": line, "character": character}))
+}
+
+/// An LSP position back in 1-based lines and character columns
+fn position(code: &str, position: &Value) -> Option<Position> {
+    let line = position["line"].as_u64()? as usize;
+    let units = position["character"].as_u64()? as usize;
+    let text = code.split('\n').nth(line).unwrap_or_default();
+    let mut seen = 0;
+    let column = text
+        .chars()
+        .take_while(|c| {
+            seen += c.len_utf16();
+            seen <= units
+        })
+        .count();
+    Some(Position {
+        line: line as u32 + 1,
+        column: column as u32 + 1,
+    })
+}
+
+/// The identifier characters just before a position, e.g. `le` in `x.le|`
+fn typed_prefix(code: &str, position: Option<Position>) -> String {
+    let Some(position) = position else {
+        return String::new();
+    };
+    let text = code
+        .split('\n')
+        .nth(position.line as usize - 1)
+        .unwrap_or_default();
+    let before: Vec<char> = text.chars().take(position.column as usize - 1).collect();
+    let start = before
+        .iter()
+        .rposition(|c| !(c.is_alphanumeric() || *c == '_'))
+        .map_or(0, |i| i + 1);
+    before[start..].iter().collect()
+}
+
+fn range(code: &str, range: &Value) -> Value {
+    json!({
+        "start": position(code, &range["start"]),
+        "end": position(code, &range["end"])
+    })
+}
+
+fn diagnostic(code: &str, diagnostic: &Value) -> Value {
+    let severity = match diagnostic["severity"].as_u64() {
+        Some(1) => "error",
+        Some(2) => "warning",
+        Some(3) => "information",
+        Some(4) => "hint",
+        _ => "error",
+    };
+    let code_value = match &diagnostic["code"] {
+        Value::Number(n) => Some(n.to_string()),
+        Value::String(s) => Some(s.clone()),
+        _ => None,
+    };
+    json!({
+        "severity": severity,
+        "code": code_value,
+        "source": diagnostic["source"],
+        "message": diagnostic["message"],
+        "range": range(code, &diagnostic["range"])
+    })
+}
+
+/// Hover contents as one markdown or plain-text string; rust-analyzer sends
+/// `MarkupContent`, but older `MarkedString` forms are accepted too
+fn hover_result(code: &str, hover: &Value) -> Value {
+    if hover.is_null() {
+        return Value::Null;
+    }
+    fn marked(value: &Value) -> (String, &'static str) {
+        match value {
+            Value::String(text) => (text.clone(), "markdown"),
+            Value::Object(object) if object.contains_key("kind") => (
+                object["value"].as_str().unwrap_or_default().to_string(),
+                if object["kind"] == "plaintext" {
+                    "plaintext"
+                } else {
+                    "markdown"
+                },
+            ),
+            Value::Object(object) => (
+                format!(
+                    "```{}\n{}\n```",
+                    object["language"].as_str().unwrap_or_default(),
+                    object["value"].as_str().unwrap_or_default()
+                ),
+                "markdown",
+            ),
+            _ => (String::new(), "markdown"),
+        }
+    }
+    let (contents, kind) = match &hover["contents"] {
+        Value::Array(parts) => (
+            parts
+                .iter()
+                .map(|part| marked(part).0)
+                .collect::<Vec<_>>()
+                .join("\n\n"),
+            "markdown",
+        ),
+        contents => marked(contents),
+    };
+    let mut result = json!({"contents": contents.trim(), "kind": kind});
+    if hover.get("range").is_some_and(|r| !r.is_null()) {
+        result["range"] = range(code, &hover["range"]);
+    }
+    result
+}
+
+const COMPLETION_KINDS: &[&str] = &[
+    "text",
+    "method",
+    "function",
+    "constructor",
+    "field",
+    "variable",
+    "class",
+    "interface",
+    "module",
+    "property",
+    "unit",
+    "value",
+    "enum",
+    "keyword",
+    "snippet",
+    "color",
+    "file",
+    "reference",
+    "folder",
+    "enum_member",
+    "constant",
+    "struct",
+    "event",
+    "operator",
+    "type_parameter",
+];
+
+const SYMBOL_KINDS: &[&str] = &[
+    "file",
+    "module",
+    "namespace",
+    "package",
+    "class",
+    "method",
+    "property",
+    "field",
+    "constructor",
+    "enum",
+    "interface",
+    "function",
+    "variable",
+    "constant",
+    "string",
+    "number",
+    "boolean",
+    "array",
+    "object",
+    "key",
+    "null",
+    "enum_member",
+    "struct",
+    "event",
+    "operator",
+    "type_parameter",
+];
+
+/// LSP kinds are 1-based indexes into the spec's list
+fn kind_name(kinds: &[&'static str], kind: &Value) -> Option<&'static str> {
+    let index = kind.as_u64()?.checked_sub(1)?;
+    kinds.get(index as usize).copied()
+}
+
+fn completion(item: &Value) -> Value {
+    json!({
+        "label": item["label"],
+        "kind": kind_name(COMPLETION_KINDS, &item["kind"]),
+        "detail": item["detail"]
+    })
+}
+
+/// A `Location` or `LocationLink`. Targets in the snippet are marked
+/// `in_code`; others, such as the standard library, keep their path and have
+/// their positions read from that file.
+fn definition(code: &str, snippet_uri: &str, location: &Value) -> Value {
+    let (uri, target) = match location.get("targetUri") {
+        Some(uri) => (uri, &location["targetSelectionRange"]),
+        None => (&location["uri"], &location["range"]),
+    };
+    let uri = uri.as_str().unwrap_or_default();
+    if uri == snippet_uri {
+        return json!({"in_code": true, "range": range(code, target)});
+    }
+    let path = uri.strip_prefix("file://").unwrap_or(uri);
+    let text = std::fs::read_to_string(path).unwrap_or_default();
+    let mut result = json!({"in_code": false, "file": path, "range": range(&text, target)});
+    let line = target["start"]["line"].as_u64().unwrap_or(0) as usize;
+    if let Some(line) = text.split('\n').nth(line) {
+        result["line_text"] = json!(line.trim());
+    }
+    result
+}
+
+/// A `DocumentSymbol` with its children, or a flat `SymbolInformation`
+fn symbol(code: &str, symbol: &Value) -> Value {
+    let mut result = json!({
+        "name": symbol["name"],
+        "kind": kind_name(SYMBOL_KINDS, &symbol["kind"]),
+    });
+    if let Some(detail) = symbol["detail"].as_str() {
+        result["detail"] = json!(detail);
+    }
+    match symbol.get("range") {
+        Some(full) => {
+            result["range"] = range(code, full);
+            result["selection_range"] = range(code, &symbol["selectionRange"]);
+        }
+        None => result["range"] = range(code, &symbol["location"]["range"]),
+    }
+    let children: Vec<Value> = symbol["children"]
+        .as_array()
+        .into_iter()
+        .flatten()
+        .map(|child| self::symbol(code, child))
+        .collect();
+    if !children.is_empty() {
+        result["children"] = json!(children);
+    }
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn messages_round_trip_through_framing() {
+        let first = json!({"jsonrpc": "2.0", "id": 1, "result": {"text": "é"}});
+        let second = json!({"jsonrpc": "2.0", "method": "exit"});
+        let mut bytes = encode(&first);
+        bytes.extend(encode(&second));
+        let mut reader = BufReader::new(bytes.as_slice());
+        assert_eq!(read_message(&mut reader).await.unwrap(), Some(first));
+        assert_eq!(read_message(&mut reader).await.unwrap(), Some(second));
+        assert_eq!(read_message(&mut reader).await.unwrap(), None);
+    }
+
+    #[test]
+    fn positions_convert_between_characters_and_utf16() {
+        let code = "fn main() {\n    let s = \"🦀\"; s.len();\n}\n";
+        // Column 17 is the `s` after the crab, which takes two UTF-16 units
+        let lsp = lsp_position(
+            code,
+            Some(Position {
+                line: 2,
+                column: 17,
+            }),
+        )
+        .unwrap();
+        assert_eq!(lsp, json!({"line": 1, "character": 17}));
+        assert_eq!(
+            position(code, &lsp),
+            Some(Position {
+                line: 2,
+                column: 17
+            })
+        );
+
+        assert!(lsp_position(code, Some(Position { line: 9, column: 1 })).is_err());
+        assert!(
+            lsp_position(
+                code,
+                Some(Position {
+                    line: 1,
+                    column: 40
+                })
+            )
+            .is_err()
+        );
+        assert!(lsp_position(code, Some(Position { line: 0, column: 1 })).is_err());
+
+        let typed = |column| typed_prefix("    x.le", Some(Position { line: 1, column }));
+        assert_eq!(
+            (typed(9), typed(8), typed(7)),
+            ("le".into(), "l".into(), String::new())
+        );
+    }
+
+    #[test]
+    fn server_results_are_normalized() {
+        let code = "fn main() {\n    let x = vec![1u8];\n}\n";
+        let hover = json!({
+            "contents": {"kind": "markdown", "value": "\n```rust\nlet x: Vec<u8>\n```\n"},
+            "range": {"start": {"line": 1, "character": 8}, "end": {"line": 1, "character": 9}}
+        });
+        assert_eq!(
+            hover_result(code, &hover),
+            json!({
+                "contents": "```rust\nlet x: Vec<u8>\n```",
+                "kind": "markdown",
+                "range": {"start": {"line": 2, "column": 9}, "end": {"line": 2, "column": 10}}
+            })
+        );
+        assert_eq!(hover_result(code, &Value::Null), Value::Null);
+
+        let outline = json!({
+            "name": "main", "kind": 12, "detail": "fn()",
+            "range": {"start": {"line": 0, "character": 0}, "end": {"line": 2, "character": 1}},
+            "selectionRange": {"start": {"line": 0, "character": 3}, "end": {"line": 0, "character": 7}},
+            "children": [{
+                "name": "x", "kind": 13,
+                "range": {"start": {"line": 1, "character": 8}, "end": {"line": 1, "character": 9}},
+                "selectionRange": {"start": {"line": 1, "character": 8}, "end": {"line": 1, "character": 9}}
+            }]
+        });
+        let outline = symbol(code, &outline);
+        assert_eq!(outline["kind"], "function");
+        assert_eq!(
+            outline["selection_range"]["start"],
+            json!({"line": 1, "column": 4})
+        );
+        assert_eq!(outline["children"][0]["kind"], "variable");
+
+        let item = json!({"label": "len", "kind": 2, "detail": "fn(&self) -> usize"});
+        assert_eq!(completion(&item)["kind"], "method");
+
+        let local = json!({
+            "uri": "file:///tmp/play/src/main.rs",
+            "range": {"start": {"line": 1, "character": 8}, "end": {"line": 1, "character": 9}}
+        });
+        assert_eq!(
+            definition(code, "file:///tmp/play/src/main.rs", &local),
+            json!({
+                "in_code": true,
+                "range": {"start": {"line": 2, "column": 9}, "end": {"line": 2, "column": 10}}
+            })
+        );
+        let dir = tempfile::tempdir().unwrap();
+        let vec_rs = dir.path().join("vec.rs");
+        std::fs::write(&vec_rs, "// Vec\npub struct Vec<T> {\n").unwrap();
+        let link = json!({
+            "targetUri": file_uri(&vec_rs),
+            "targetRange": {"start": {"line": 0, "character": 0}, "end": {"line": 1, "character": 20}},
+            "targetSelectionRange": {"start": {"line": 1, "character": 11}, "end": {"line": 1, "character": 14}}
+        });
+        let external = definition(code, "file:///tmp/play/src/main.rs", &link);
+        assert_eq!(external["in_code"], false);
+        assert_eq!(external["file"], vec_rs.display().to_string());
+        assert_eq!(external["range"]["start"], json!({"line": 2, "column": 12}));
+        assert_eq!(external["line_text"], "pub struct Vec<T> {");
+    }
+}
@@ -0,0 +1,113 @@
+//! Checking a snippet against an older Rust release: which toolchain to ask
+//! rustup for, and which edition that release can build.
+
+/// A release such as `1.70` or `1.70.0`, as rustup names its toolchain
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct RustVersion {
+    pub major: u32,
+    pub minor: u32,
+    pub patch: Option<u32>,
+}
+
+impl RustVersion {
+    pub fn parse(version: &str) -> Result<Self, String> {
+        let invalid = || {
+            format!(
+                "rust_version must be a release like 1.70 or 1.70.0, got: {}",
+                version
+            )
+        };
+        let mut parts = version.trim().split('.');
+        let mut number = |required: bool| -> Result<Option<u32>, String> {
+            match parts.next() {
+                Some(part) if !part.is_empty() && part.bytes().all(|b| b.is_ascii_digit()) => {
+                    part.parse().map(Some).map_err(|_| invalid())
+                }
+                None if !required => Ok(None),
+                _ => Err(invalid()),
+            }
+        };
+        let major = number(true)?.ok_or_else(invalid)?;
+        let minor = number(true)?.ok_or_else(invalid)?;
+        let patch = number(false)?;
+        if parts.next().is_some() || major != 1 {
+            return Err(invalid());
+        }
+        Ok(RustVersion {
+            major,
+            minor,
+            patch,
+        })
+    }
+
+    /// The rustup toolchain name. rustup only knows full releases, so `1.70`
+    /// means 1.70.0.
+    pub fn toolchain(&self) -> String {
+        format!("{}.{}.{}", self.major, self.minor, self.patch.unwrap_or(0))
+    }
+
+    /// The newest edition this release supports, so the scratch project's
+    /// manifest is one its cargo can read
+    pub fn edition(&self) -> &'static str {
+        match self.minor {
+            85.. => "2024",
+            56.. => "2021",
+            31.. => "2018",
+            _ => "2015",
+        }
+    }
+}
+
+/// `manifest` with its `edition` line set to `edition`
+pub fn with_edition(manifest: &str, edition: &str) -> String {
+    let line = format!("edition = \"{}\"", edition);
+    let mut replaced = false;
+    let mut lines: Vec<String> = manifest
+        .lines()
+        .map(|l| {
+            if !replaced && l.trim_start().starts_with("edition") && l.contains('=') {
+                replaced = true;
+                line.clone()
+            } else {
+                l.to_string()
+            }
+        })
+        .collect();
+    if !replaced && let Some(package) = lines.iter().position(|l| l.trim() == "[package]") {
+        lines.insert(package + 1, line);
+    }
+    let mut manifest = lines.join("\n");
+    manifest.push('\n');
+    manifest
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn versions_pick_a_toolchain_and_edition() {
+        let msrv = RustVersion::parse("1.70").unwrap();
+        assert_eq!(msrv.toolchain(), "1.70.0");
+        assert_eq!(msrv.edition(), "2021");
+        assert_eq!(RustVersion::parse("1.85.1").unwrap().edition(), "2024");
+        assert_eq!(
+            RustVersion::parse(" 1.40.0 ").unwrap().toolchain(),
+            "1.40.0"
+        );
+        assert_eq!(RustVersion::parse("1.31").unwrap().edition(), "2018");
+        for bad in ["stable", "1", "1.70.0.1", "2.0", "1.x", "1.70-nightly", ""] {
+            assert!(RustVersion::parse(bad).is_err(), "{}", bad);
+        }
+
+        let manifest = "[package]\nname = \"temp_project\"\nversion = \"0.1.0\"\nedition = \"2024\"\n\n[dependencies]\n";
+        assert_eq!(
+            with_edition(manifest, "2021"),
+            manifest.replace("2024", "2021")
+        );
+        assert_eq!(
+            with_edition("[package]\nname = \"a\"\n", "2018"),
+            "[package]\nedition = \"2018\"\nname = \"a\"\n"
+        );
+    }
+}
@@ -0,0 +1,619 @@
+//! A nested outline of a snippet built from syn's syntax tree: signatures,
+//! fields, variants, docs, attributes and line ranges of every item. Code
+//! that doesn't parse is split into top-level chunks so the items that do
+//! parse are still outlined.
+
+use crate::syntax::{self, SyntaxError};
+use proc_macro2::Span;
+use syn::spanned::Spanned;
+
+/// One item, or a variant, in the outline
+#[derive(Debug, Clone, Default, PartialEq, serde::Serialize)]
+pub struct Node {
+    /// "fn", "struct", "enum", "variant", "impl", "trait", "mod", ...
+    pub kind: &'static str,
+    /// For impls `Trait for Type`, or just the type
+    pub name: String,
+    /// e.g. "pub" or "pub(crate)"; absent for private items
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub visibility: Option<String>,
+    /// const, async, unsafe, extern, auto, default, mut
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub modifiers: Vec<&'static str>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub generics: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub signature: Option<String>,
+    /// The implementing type of an impl
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub self_type: Option<String>,
+    /// Attributes other than doc comments, e.g. `#[derive(Debug)]`
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub attributes: Vec<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub docs: Option<String>,
+    /// 1-based and inclusive, covering doc comments and attributes
+    pub start_line: usize,
+    pub end_line: usize,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub fields: Vec<Field>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub variants: Vec<Node>,
+    /// Items of a module, impl or trait
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub items: Vec<Node>,
+}
+
+/// A struct, union or variant field; tuple fields are named by position
+#[derive(Debug, Clone, PartialEq, serde::Serialize)]
+pub struct Field {
+    pub name: String,
+    #[serde(rename = "type")]
+    pub ty: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub visibility: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub docs: Option<String>,
+    pub line: usize,
+}
+
+/// The outline, complete when `errors` is empty and partial otherwise
+#[derive(Debug, Clone, Default, PartialEq, serde::Serialize)]
+pub struct Outline {
+    pub items: Vec<Node>,
+    pub errors: Vec<SyntaxError>,
+}
+
+/// Outline `code`, falling back to one chunk per top-level item when the
+/// whole file doesn't parse
+pub fn outline(code: &str) -> Outline {
+    let mut outline = Outline::default();
+    match syn::parse_file(code) {
+        Ok(file) => {
+            outline.items = Source {
+                code,
+                first_line: 0,
+            }
+            .items(&file.items)
+        }
+        Err(_) => {
+            for (first_line, chunk) in chunks(code) {
+                let source = Source {
+                    code: &chunk,
+                    first_line,
+                };
+                match syn::parse_file(&chunk) {
+                    Ok(file) => outline.items.extend(source.items(&file.items)),
+                    Err(error) => outline.errors.extend(error.into_iter().map(|error| {
+                        let mut error = SyntaxError::from(error);
+                        error.line += first_line;
+                        error
+                    })),
+                }
+            }
+        }
+    }
+    syntax::release_spans();
+    outline
+}
+
+/// Items named `query` anywhere in the outline. A query matches an item's
+/// name, its kind and name (`impl Point`, `fn main`), or a path through
+/// its parents (`Point::new`, `shapes::Circle`).
+pub fn find<'a>(items: &'a [Node], query: &str) -> Vec<&'a Node> {
+    fn walk<'a>(items: &'a [Node], parent: Option<&str>, query: &str, found: &mut Vec<&'a Node>) {
+        for item in items {
+            let path = parent.map(|parent| format!("{}::{}", parent, item.name));
+            if item.name == query
+                || format!("{} {}", item.kind, item.name) == query
+                || path.as_deref() == Some(query)
+            {
+                found.push(item);
+            }
+            // Methods are found as `Type::method`, without the type's generics
+            let scope = match &item.self_type {
+                Some(self_type) => self_type.split('<').next().unwrap_or_default().trim(),
+                None => &item.name,
+            };
+            let scope = match parent {
+                Some(parent) if item.kind == "mod" => format!("{}::{}", parent, scope),
+                _ => scope.to_string(),
+            };
+            walk(&item.items, Some(&scope), query, found);
+        }
+    }
+    let mut found = Vec::new();
+    walk(items, None, query, &mut found);
+    found
+}
+
+/// The source lines an item spans
+pub fn source_of(code: &str, item: &Node) -> String {
+    code.lines()
+        .skip(item.start_line.saturating_sub(1))
+        .take(item.end_line + 1 - item.start_line)
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+/// Top-level chunks of code that may not parse as a whole, each with the
+/// number of lines before it. A chunk starts at an unindented line that isn't
+/// a closing delimiter, together with the attributes and comments above it.
+fn chunks(code: &str) -> Vec<(usize, String)> {
+    let lines: Vec<&str> = code.lines().collect();
+    let mut starts = vec![0];
+    let mut pending = None;
+    for (index, line) in lines.iter().enumerate() {
+        let Some(first) = line.chars().next() else {
+            continue;
+        };
+        if first.is_whitespace() || matches!(first, '}' | ')' | ']') {
+            continue;
+        }
+        let start = *pending.get_or_insert(index);
+        if line.starts_with("#[") || line.starts_with("//") {
+            continue;
+        }
+        pending = None;
+        if start > 0 && starts.last() != Some(&start) {
+            starts.push(start);
+        }
+    }
+    starts.push(lines.len());
+    starts
+        .windows(2)
+        .map(|range| (range[0], lines[range[0]..range[1]].join("\n")))
+        .collect()
+}
+
+/// The text a syntax tree was parsed from, to read spans against
+struct Source<'a> {
+    code: &'a str,
+    /// Lines of the snippet before this text
+    first_line: usize,
+}
+
+impl Source<'_> {
+    /// Source text under a span with whitespace runs collapsed
+    fn text(&self, span: Span) -> String {
+        self.code
+            .get(span.byte_range())
+            .unwrap_or_default()
+            .split_whitespace()
+            .collect::<Vec<_>>()
+            .join(" ")
+    }
+
+    fn line(&self, span: Span) -> usize {
+        span.start().line + self.first_line
+    }
+
+    fn node(&self, kind: &'static str, name: String, item: &impl Spanned) -> Node {
+        let span = item.span();
+        Node {
+            kind,
+            name,
+            start_line: self.line(span),
+            end_line: span.end().line + self.first_line,
+            ..Default::default()
+        }
+    }
+
+    fn visibility(&self, vis: &syn::Visibility) -> Option<String> {
+        match vis {
+            syn::Visibility::Inherited => None,
+            vis => Some(self.text(vis.span())),
+        }
+    }
+
+    fn generics(&self, generics: &syn::Generics) -> Option<String> {
+        (!generics.params.is_empty()).then(|| self.text(generics.span()))
+    }
+
+    /// Attributes and doc comments of an item
+    fn attributes(&self, node: &mut Node, attrs: &[syn::Attribute]) {
+        let (docs, attributes) = self.split_attributes(attrs);
+        node.docs = docs;
+        node.attributes = attributes;
+    }
+
+    fn split_attributes(&self, attrs: &[syn::Attribute]) -> (Option<String>, Vec<String>) {
+        let mut docs = Vec::new();
+        let mut attributes = Vec::new();
+        for attr in attrs {
+            if attr.path().is_ident("doc") {
+                if let syn::Meta::NameValue(meta) = &attr.meta
+                    && let syn::Expr::Lit(syn::ExprLit {
+                        lit: syn::Lit::Str(doc),
+                        ..
+                    }) = &meta.value
+                {
+                    let doc = doc.value();
+                    docs.push(doc.strip_prefix(' ').unwrap_or(&doc).to_string());
+                }
+            } else {
+                attributes.push(self.text(attr.span()));
+            }
+        }
+        ((!docs.is_empty()).then(|| docs.join("\n")), attributes)
+    }
+
+    fn fields(&self, fields: &syn::Fields) -> Vec<Field> {
+        fields
+            .iter()
+            .enumerate()
+            .map(|(index, field)| Field {
+                name: field
+                    .ident
+                    .as_ref()
+                    .map_or_else(|| index.to_string(), ToString::to_string),
+                ty: self.text(field.ty.span()),
+                visibility: self.visibility(&field.vis),
+                docs: self.split_attributes(&field.attrs).0,
+                line: self.line(field.span()),
+            })
+            .collect()
+    }
+
+    fn function(
+        &self,
+        item: &impl Spanned,
+        attrs: &[syn::Attribute],
+        vis: Option<&syn::Visibility>,
+        sig: &syn::Signature,
+    ) -> Node {
+        let mut node = self.node("fn", sig.ident.to_string(), item);
+        self.attributes(&mut node, attrs);
+        node.visibility = vis.and_then(|vis| self.visibility(vis));
+        node.generics = self.generics(&sig.generics);
+        node.signature = Some(self.text(sig.span()));
+        node.modifiers = [
+            sig.constness.map(|_| "const"),
+            sig.asyncness.map(|_| "async"),
+            sig.unsafety.map(|_| "unsafe"),
+            sig.abi.as_ref().map(|_| "extern"),
+        ]
+        .into_iter()
+        .flatten()
+        .collect();
+        node
+    }
+
+    fn items(&self, items: &[syn::Item]) -> Vec<Node> {
+        items.iter().filter_map(|item| self.item(item)).collect()
+    }
+
+    fn item(&self, item: &syn::Item) -> Option<Node> {
+        let node = match item {
+            syn::Item::Fn(item) => self.function(item, &item.attrs, Some(&item.vis), &item.sig),
+            syn::Item::Struct(item) => {
+                let mut node = self.node("struct", item.ident.to_string(), item);
+                self.attributes(&mut node, &item.attrs);
+                node.visibility = self.visibility(&item.vis);
+                node.generics = self.generics(&item.generics);
+                node.fields = self.fields(&item.fields);
+                node
+            }
+            syn::Item::Union(item) => {
+                let mut node = self.node("union", item.ident.to_string(), item);
+                self.attributes(&mut node, &item.attrs);
+                node.visibility = self.visibility(&item.vis);
+                node.generics = self.generics(&item.generics);
+                node.fields = self.fields(&syn::Fields::Named(item.fields.clone()));
+                node
+            }
+            syn::Item::Enum(item) => {
+                let mut node = self.node("enum", item.ident.to_string(), item);
+                self.attributes(&mut node, &item.attrs);
+                node.visibility = self.visibility(&item.vis);
+                node.generics = self.generics(&item.generics);
+                node.variants = item
+                    .variants
+                    .iter()
+                    .map(|variant| {
+                        let mut node = self.node("variant", variant.ident.to_string(), variant);
+                        self.attributes(&mut node, &variant.attrs);
+                        node.fields = self.fields(&variant.fields);
+                        node
+                    })
+                    .collect();
+                node
+            }
+            syn::Item::Trait(item) => {
+                let mut node = self.node("trait", item.ident.to_string(), item);
+                self.attributes(&mut node, &item.attrs);
+                node.visibility = self.visibility(&item.vis);
+                node.generics = self.generics(&item.generics);
+                node.modifiers = [
+                    item.unsafety.map(|_| "unsafe"),
+                    item.auto_token.map(|_| "auto"),
+                ]
+                .into_iter()
+                .flatten()
+                .collect();
+                node.items = item
+                    .items
+                    .iter()
+                    .filter_map(|item| self.trait_item(item))
+                    .collect();
+                node
+            }
+            syn::Item::Impl(item) => {
+                let self_type = self.text(item.self_ty.span());
+                let name = match &item.trait_ {
+                    Some((negative, path, _)) => format!(
+                        "{}{} for {}",
+                        if negative.is_some() { "!" } else { "" },
+                        self.text(path.span()),
+                        self_type
+                    ),
+                    None => self_type.clone(),
+                };
+                let mut node = self.node("impl", name, item);
+                self.attributes(&mut node, &item.attrs);
+                node.generics = self.generics(&item.generics);
+                node.self_type = Some(self_type);
+                node.modifiers = [
+                    item.defaultness.map(|_| "default"),
+                    item.unsafety.map(|_| "unsafe"),
+                ]
+                .into_iter()
+                .flatten()
+                .collect();
+                node.items = item
+                    .items
+                    .iter()
+                    .filter_map(|item| self.impl_item(item))
+                    .collect();
+                node
+            }
+            syn::Item::Mod(item) => {
+                let mut node = self.node("mod", item.ident.to_string(), item);
+                self.attributes(&mut node, &item.attrs);
+                node.visibility = self.visibility(&item.vis);
+                if let Some((_, items)) = &item.content {
+                    node.items = self.items(items);
+                }
+                node
+            }
+            syn::Item::Const(item) => {
+                let mut node = self.node("const", item.ident.to_string(), item);
+                self.attributes(&mut node, &item.attrs);
+                node.visibility = self.visibility(&item.vis);
+                node.signature = Some(format!(
+                    "const {}: {}",
+                    item.ident,
+                    self.text(item.ty.span())
+                ));
+                node
+            }
+            syn::Item::Static(item) => {
+                let mut node = self.node("static", item.ident.to_string(), item);
+                self.attributes(&mut node, &item.attrs);
+                node.visibility = self.visibility(&item.vis);
+                let mutability = match item.mutability {
+                    syn::StaticMutability::Mut(_) => {
+                        node.modifiers.push("mut");
+                        "mut "
+                    }
+                    _ => "",
+                };
+                node.signature = Some(format!(
+                    "static {}{}: {}",
+                    mutability,
+                    item.ident,
+                    self.text(item.ty.span())
+                ));
+                node
+            }
+            syn::Item::Type(item) => {
+                let mut node = self.node("type", item.ident.to_string(), item);
+                self.attributes(&mut node, &item.attrs);
+                node.visibility = self.visibility(&item.vis);
+                node.generics = self.generics(&item.generics);
+                node.signature = Some(format!(
+                    "type {}{} = {}",
+                    item.ident,
+                    node.generics.as_deref().unwrap_or_default(),
+                    self.text(item.ty.span())
+                ));
+                node
+            }
+            syn::Item::Use(item) => {
+                let mut node = self.node("use", self.text(item.tree.span()), item);
+                self.attributes(&mut node, &item.attrs);
+                node.visibility = self.visibility(&item.vis);
+                node
+            }
+            syn::Item::Macro(item) => {
+                let name = match &item.ident {
+                    Some(ident) => ident.to_string(),
+                    None => format!("{}!", self.text(item.mac.path.span())),
+                };
+                let mut node = self.node("macro", name, item);
+                self.attributes(&mut node, &item.attrs);
+                node
+            }
+            syn::Item::ExternCrate(item) => {
+                let mut node = self.node("extern crate", item.ident.to_string(), item);
+                self.attributes(&mut node, &item.attrs);
+                node.visibility = self.visibility(&item.vis);
+                node
+            }
+            _ => return None,
+        };
+        Some(node)
+    }
+
+    fn impl_item(&self, item: &syn::ImplItem) -> Option<Node> {
+        let node = match item {
+            syn::ImplItem::Fn(item) => {
+                let mut node = self.function(item, &item.attrs, Some(&item.vis), &item.sig);
+                if item.defaultness.is_some() {
+                    node.modifiers.insert(0, "default");
+                }
+                node
+            }
+            syn::ImplItem::Const(item) => {
+                let mut node = self.node("const", item.ident.to_string(), item);
+                self.attributes(&mut node, &item.attrs);
+                node.visibility = self.visibility(&item.vis);
+                node.signature = Some(format!(
+                    "const {}: {}",
+                    item.ident,
+                    self.text(item.ty.span())
+                ));
+                node
+            }
+            syn::ImplItem::Type(item) => {
+                let mut node = self.node("type", item.ident.to_string(), item);
+                self.attributes(&mut node, &item.attrs);
+                node.visibility = self.visibility(&item.vis);
+                node.signature = Some(format!(
+                    "type {} = {}",
+                    item.ident,
+                    self.text(item.ty.span())
+                ));
+                node
+            }
+            _ => return None,
+        };
+        Some(node)
+    }
+
+    fn trait_item(&self, item: &syn::TraitItem) -> Option<Node> {
+        let node = match item {
+            syn::TraitItem::Fn(item) => self.function(item, &item.attrs, None, &item.sig),
+            syn::TraitItem::Const(item) => {
+                let mut node = self.node("const", item.ident.to_string(), item);
+                self.attributes(&mut node, &item.attrs);
+                node.signature = Some(format!(
+                    "const {}: {}",
+                    item.ident,
+                    self.text(item.ty.span())
+                ));
+                node
+            }
+            syn::TraitItem::Type(item) => {
+                let mut node = self.node("type", item.ident.to_string(), item);
+                self.attributes(&mut node, &item.attrs);
+                node.generics = self.generics(&item.generics);
+                node
+            }
+            _ => return None,
+        };
+        Some(node)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const CODE: &str = r#"use std::fmt;
+
+/// A point
+/// on a plane
+#[derive(Debug, Clone)]
+pub struct Point<T> {
+    /// Horizontal
+    pub x: T,
+    y: T,
+}
+
+enum Shape {
+    Circle(f64),
+    Square { side: f64 },
+}
+
+impl<T: fmt::Display> fmt::Display for Point<T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.x)
+    }
+}
+
+impl Point<i32> {
+    pub const fn origin() -> Self {
+        Point { x: 0, y: 0 }
+    }
+}
+
+pub(crate) mod shapes {
+    pub async unsafe fn draw() {}
+}
+"#;
+
+    #[test]
+    fn outlines_items_with_details() {
+        let outline = outline(CODE);
+        assert!(outline.errors.is_empty());
+        let kinds: Vec<_> = outline.items.iter().map(|item| item.kind).collect();
+        assert_eq!(kinds, ["use", "struct", "enum", "impl", "impl", "mod"]);
+
+        let point = &outline.items[1];
+        assert_eq!(point.name, "Point");
+        assert_eq!(point.visibility.as_deref(), Some("pub"));
+        assert_eq!(point.generics.as_deref(), Some("<T>"));
+        assert_eq!(point.docs.as_deref(), Some("A point\non a plane"));
+        assert_eq!(point.attributes, ["#[derive(Debug, Clone)]"]);
+        assert_eq!((point.start_line, point.end_line), (3, 10));
+        assert_eq!(point.fields[0].docs.as_deref(), Some("Horizontal"));
+        assert_eq!(
+            point
+                .fields
+                .iter()
+                .map(|f| (f.name.as_str(), f.ty.as_str(), f.visibility.is_some()))
+                .collect::<Vec<_>>(),
+            [("x", "T", true), ("y", "T", false)]
+        );
+
+        let shape = &outline.items[2];
+        assert_eq!(shape.variants[0].fields[0].name, "0");
+        assert_eq!(shape.variants[1].fields[0].name, "side");
+
+        let display = &outline.items[3];
+        assert_eq!(display.name, "fmt::Display for Point<T>");
+        assert_eq!(display.self_type.as_deref(), Some("Point<T>"));
+        assert_eq!(
+            display.items[0].signature.as_deref(),
+            Some("fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result")
+        );
+
+        let origin = &outline.items[4].items[0];
+        assert_eq!(origin.modifiers, ["const"]);
+        let draw = &outline.items[5].items[0];
+        assert_eq!(draw.modifiers, ["async", "unsafe"]);
+        assert_eq!(outline.items[5].visibility.as_deref(), Some("pub(crate)"));
+    }
+
+    #[test]
+    fn finds_items_by_name_kind_or_path() {
+        let outline = outline(CODE);
+        assert_eq!(find(&outline.items, "Point").len(), 1);
+        let inherent = find(&outline.items, "impl Point<i32>");
+        assert_eq!(inherent.len(), 1);
+        assert_eq!(
+            source_of(CODE, inherent[0]),
+            "impl Point<i32> {\n    pub const fn origin() -> Self {\n        Point { x: 0, y: 0 }\n    }\n}"
+        );
+        assert_eq!(find(&outline.items, "Point::origin").len(), 1);
+        assert_eq!(find(&outline.items, "shapes::draw").len(), 1);
+        assert!(find(&outline.items, "missing").is_empty());
+    }
+
+    #[test]
+    fn broken_code_keeps_the_items_that_parse() {
+        let code =
+            "struct Ok1;\n\nfn broken() {\n    let x = ;\n}\n\n/// Still here\nfn fine() {}\n";
+        let outline = outline(code);
+        let names: Vec<_> = outline
+            .items
+            .iter()
+            .map(|item| item.name.as_str())
+            .collect();
+        assert_eq!(names, ["Ok1", "fine"]);
+        assert_eq!(outline.items[1].start_line, 7);
+        assert_eq!(outline.errors.len(), 1);
+        assert_eq!(outline.errors[0].line, 4);
+    }
+}
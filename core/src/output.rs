@@ -0,0 +1,1338 @@
+//! Post-processing of cargo output for tool responses: stripping progress
+//! noise and producing a short human-readable summary.
+
+use schemars::JsonSchema;
+use serde::Deserialize;
+use serde_json::Value;
+
+/// Cargo status verbs that only report progress, printed right-aligned
+/// (e.g. `   Compiling foo v0.1.0`) and never part of a diagnostic
+const STATUS_VERBS: &[&str] = &[
+    "Adding",
+    "Blocking",
+    "Checking",
+    "Compiling",
+    "Documenting",
+    "Downloaded",
+    "Downloading",
+    "Finished",
+    "Fixed",
+    "Fixing",
+    "Fresh",
+    "Generated",
+    "Locking",
+    "Packaging",
+    "Running",
+    "Updating",
+];
+
+/// How much of the raw output a response carries
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, JsonSchema)]
+#[serde(rename_all = "lowercase")]
+#[schemars(inline)]
+pub enum Verbosity {
+    /// Raw stdout and stderr
+    Full,
+    /// Stderr without cargo progress lines; diagnostics intact
+    Trimmed,
+    /// Only the summary, no stdout or stderr
+    Summary,
+    /// Only what went wrong: error-level diagnostics, or the error messages
+    /// on stderr for tools without JSON diagnostics
+    Errors,
+}
+
+fn is_status_line(line: &str) -> bool {
+    if !line.starts_with(' ') {
+        return false;
+    }
+    let trimmed = line.trim_start();
+    STATUS_VERBS.iter().any(|verb| {
+        trimmed
+            .strip_prefix(verb)
+            .is_some_and(|rest| rest.starts_with(' ') || rest.is_empty())
+    })
+}
+
+/// The first `max_bytes` of `text`, cut back to a character boundary and
+/// marked with how much was dropped; None when it already fits
+pub fn truncate_output(text: &str, max_bytes: usize) -> Option<String> {
+    if text.len() <= max_bytes {
+        return None;
+    }
+    let mut end = max_bytes;
+    while !text.is_char_boundary(end) {
+        end -= 1;
+    }
+    Some(format!(
+        "{}...[truncated {} bytes]",
+        &text[..end],
+        text.len() - end
+    ))
+}
+
+/// The error messages in rendered compiler output, each with its code frame
+/// and notes; warnings and progress lines are dropped
+pub fn error_blocks(stderr: &str) -> String {
+    let mut blocks = String::new();
+    let mut in_error = false;
+    for line in stderr.lines() {
+        if line.trim().is_empty() {
+            if in_error {
+                blocks.push('\n');
+            }
+            in_error = false;
+            continue;
+        }
+        // A message starts at column 0; its code frame and notes are indented
+        if !line.starts_with(char::is_whitespace) {
+            in_error = line.starts_with("error:") || line.starts_with("error[");
+        }
+        if in_error {
+            blocks.push_str(line);
+            blocks.push('\n');
+        }
+    }
+    blocks.trim_end().to_string()
+}
+
+/// Remove cargo progress lines such as `Compiling`/`Finished`/`Downloaded`
+pub fn trim_cargo_noise(stderr: &str) -> String {
+    let mut trimmed = String::with_capacity(stderr.len());
+    for line in stderr.lines().filter(|line| !is_status_line(line)) {
+        trimmed.push_str(line);
+        trimmed.push('\n');
+    }
+    trimmed
+}
+
+/// Errors and warnings found in rendered or JSON compiler output
+#[derive(Debug, Default)]
+struct DiagnosticCounts {
+    errors: usize,
+    warnings: usize,
+    messages: Vec<String>,
+}
+
+impl DiagnosticCounts {
+    fn from_output(stdout: &str, stderr: &str) -> Self {
+        let mut counts = DiagnosticCounts::default();
+        for line in stderr.lines() {
+            if let Some(message) = line.strip_prefix("error") {
+                let message =
+                    message
+                        .split_once(": ")
+                        .map(|(code, msg)| match code.strip_prefix('[') {
+                            Some(code) => format!("{} ({})", msg, code.trim_end_matches(']')),
+                            None => msg.to_string(),
+                        });
+                let Some(message) = message else { continue };
+                // Cargo's closing "could not compile" line and rustc's "aborting
+                // due to" line restate errors already counted
+                if message.starts_with("could not compile")
+                    || message.starts_with("aborting due to")
+                {
+                    continue;
+                }
+                counts.errors += 1;
+                counts.messages.push(message);
+            } else if let Some(message) = line.strip_prefix("warning: ") {
+                if (message.starts_with('`') && message.contains(" generated "))
+                    || message.starts_with("build failed")
+                {
+                    continue;
+                }
+                counts.warnings += 1;
+                counts.messages.push(message.to_string());
+            }
+        }
+
+        // `--message-format=json` puts diagnostics on stdout instead
+        for diagnostic in crate::diagnostics::from_json(stdout) {
+            match diagnostic.level.as_str() {
+                "error" => counts.errors += 1,
+                "warning" => counts.warnings += 1,
+                _ => continue,
+            }
+            match diagnostic.code {
+                Some(code) => counts
+                    .messages
+                    .push(format!("{} ({})", diagnostic.message, code)),
+                None => counts.messages.push(diagnostic.message),
+            }
+        }
+        counts
+    }
+
+    fn is_empty(&self) -> bool {
+        self.errors == 0 && self.warnings == 0
+    }
+
+    fn describe(&self) -> String {
+        const SHOWN: usize = 3;
+        let mut text = format!(
+            "{} error{}, {} warning{}",
+            self.errors,
+            if self.errors == 1 { "" } else { "s" },
+            self.warnings,
+            if self.warnings == 1 { "" } else { "s" },
+        );
+        if !self.messages.is_empty() {
+            let shown: Vec<&str> = self
+                .messages
+                .iter()
+                .take(SHOWN)
+                .map(String::as_str)
+                .collect();
+            text.push_str(": ");
+            text.push_str(&shown.join("; "));
+            if self.messages.len() > SHOWN {
+                text.push_str(&format!(" (+{} more)", self.messages.len() - SHOWN));
+            }
+        }
+        text
+    }
+}
+
+/// Number of errors and warnings in rendered or JSON compiler output
+pub fn count_diagnostics(stdout: &str, stderr: &str) -> (usize, usize) {
+    let counts = DiagnosticCounts::from_output(stdout, stderr);
+    (counts.errors, counts.warnings)
+}
+
+/// Totals across every `test result:` line libtest prints
+/// Counts from one or more libtest `test result:` lines
+#[derive(Debug, Default, Clone, Copy, PartialEq, serde::Serialize)]
+pub struct TestCounts {
+    pub passed: u64,
+    pub failed: u64,
+    pub ignored: u64,
+}
+
+impl TestCounts {
+    fn from_result_line(line: &str) -> Option<Self> {
+        let rest = line.strip_prefix("test result: ")?;
+        let mut counts = TestCounts::default();
+        for part in rest.split(['.', ';']) {
+            let mut words = part.split_whitespace();
+            let (Some(n), Some(label)) = (words.next(), words.next()) else {
+                continue;
+            };
+            let Ok(n) = n.parse::<u64>() else { continue };
+            match label {
+                "passed" => counts.passed += n,
+                "failed" => counts.failed += n,
+                "ignored" => counts.ignored += n,
+                _ => {}
+            }
+        }
+        Some(counts)
+    }
+
+    fn add(&mut self, other: TestCounts) {
+        self.passed += other.passed;
+        self.failed += other.failed;
+        self.ignored += other.ignored;
+    }
+}
+
+/// `cargo test` results split by the kind of test target that produced
+/// them; a category is `None` when no target of that kind ran
+#[derive(Debug, Default, PartialEq, serde::Serialize)]
+pub struct TestBreakdown {
+    #[serde(flatten)]
+    pub total: TestCounts,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub unit: Option<TestCounts>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub integration: Option<TestCounts>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub doc: Option<TestCounts>,
+    /// Names of failed tests; doc tests as `path - item (line N)`
+    pub failed_tests: Vec<String>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum TestKind {
+    Unit,
+    Integration,
+    Doc,
+}
+
+/// Split `cargo test` results by category. Cargo announces each test binary
+/// on stderr (`Running unittests ...`, `Running tests/...`, `Doc-tests ...`)
+/// while the binaries print their `test result:` lines to stdout in the
+/// same order, so the two are matched up by position. If they don't line
+/// up, doc tests are told apart by their `path - item (line N)` names and
+/// everything else counts as unit tests.
+pub fn test_breakdown(stdout: &str, stderr: &str) -> Option<TestBreakdown> {
+    let headers: Vec<TestKind> = stderr
+        .lines()
+        .filter_map(|line| {
+            let line = line.trim_start();
+            if line.starts_with("Doc-tests ") {
+                Some(TestKind::Doc)
+            } else if line.starts_with("Running unittests ") {
+                Some(TestKind::Unit)
+            } else if line.starts_with("Running ") {
+                Some(TestKind::Integration)
+            } else {
+                None
+            }
+        })
+        .collect();
+
+    let mut blocks: Vec<(TestCounts, bool)> = Vec::new();
+    let mut failed_tests = Vec::new();
+    let mut has_doc_names = false;
+    for line in stdout.lines() {
+        if let Some(counts) = TestCounts::from_result_line(line) {
+            blocks.push((counts, has_doc_names));
+            has_doc_names = false;
+            continue;
+        }
+        let Some(name) = line.strip_prefix("test ") else {
+            continue;
+        };
+        if parse_doc_test_name(name).is_some() {
+            has_doc_names = true;
+        }
+        if let Some(name) = name.strip_suffix(" ... FAILED") {
+            failed_tests.push(doc_test_base_name(name).to_string());
+        }
+    }
+    if blocks.is_empty() {
+        return json_test_breakdown(stdout);
+    }
+
+    let aligned = headers.len() == blocks.len();
+    let mut breakdown = TestBreakdown {
+        failed_tests,
+        ..TestBreakdown::default()
+    };
+    for (i, (counts, has_doc_names)) in blocks.into_iter().enumerate() {
+        let kind = if aligned {
+            headers[i]
+        } else if has_doc_names {
+            TestKind::Doc
+        } else {
+            TestKind::Unit
+        };
+        let category = match kind {
+            TestKind::Unit => &mut breakdown.unit,
+            TestKind::Integration => &mut breakdown.integration,
+            TestKind::Doc => &mut breakdown.doc,
+        };
+        category.get_or_insert_with(TestCounts::default).add(counts);
+        breakdown.total.add(counts);
+    }
+    Some(breakdown)
+}
+
+/// Breakdown from libtest JSON events, as `cargo nextest run
+/// --message-format libtest-json` prints them. Nextest names tests
+/// `binary-id$path`, where unit test binaries are `crate` or `crate::bin/name`.
+fn json_test_breakdown(stdout: &str) -> Option<TestBreakdown> {
+    let outcomes = json_test_outcomes(stdout);
+    if outcomes.is_empty() {
+        return None;
+    }
+    let mut breakdown = TestBreakdown::default();
+    for outcome in outcomes {
+        let mut counts = TestCounts::default();
+        match outcome.status {
+            "passed" => counts.passed = 1,
+            "failed" => counts.failed = 1,
+            _ => counts.ignored = 1,
+        }
+        let binary = outcome
+            .name
+            .split_once('$')
+            .map_or("", |(binary, _)| binary);
+        let category = match binary.split_once("::") {
+            Some((_, target)) if !target.starts_with("bin/") => &mut breakdown.integration,
+            _ => &mut breakdown.unit,
+        };
+        category.get_or_insert_with(TestCounts::default).add(counts);
+        breakdown.total.add(counts);
+        if outcome.status == "failed" {
+            breakdown.failed_tests.push(outcome.name);
+        }
+    }
+    Some(breakdown)
+}
+
+/// Result of a single test
+#[derive(Debug, Clone, PartialEq, serde::Serialize)]
+pub struct TestOutcome {
+    pub name: String,
+    /// "passed", "failed" or "ignored"
+    pub status: &'static str,
+    /// Only reported by runners that time each test
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub duration_ms: Option<f64>,
+}
+
+/// Per-test results, from libtest JSON events when present and otherwise
+/// from libtest's `test name ... ok` lines
+pub fn test_outcomes(stdout: &str) -> Vec<TestOutcome> {
+    let outcomes = json_test_outcomes(stdout);
+    if !outcomes.is_empty() {
+        return outcomes;
+    }
+    stdout
+        .lines()
+        .filter_map(|line| {
+            let (name, result) = line.strip_prefix("test ")?.rsplit_once(" ... ")?;
+            let status = match result {
+                "ok" => "passed",
+                "FAILED" => "failed",
+                _ if result.starts_with("ignored") => "ignored",
+                _ => return None,
+            };
+            Some(TestOutcome {
+                name: doc_test_base_name(name).to_string(),
+                status,
+                duration_ms: None,
+            })
+        })
+        .collect()
+}
+
+fn json_test_outcomes(stdout: &str) -> Vec<TestOutcome> {
+    stdout
+        .lines()
+        .filter(|line| line.starts_with('{'))
+        .filter_map(|line| serde_json::from_str::<Value>(line).ok())
+        .filter(|event| event["type"] == "test")
+        .filter_map(|event| {
+            let status = match event["event"].as_str()? {
+                "ok" => "passed",
+                "failed" => "failed",
+                "ignored" => "ignored",
+                _ => return None,
+            };
+            Some(TestOutcome {
+                name: event["name"].as_str()?.to_string(),
+                status,
+                duration_ms: event["exec_time"].as_f64().map(|secs| secs * 1000.0),
+            })
+        })
+        .collect()
+}
+
+/// One-line description of a tool run for LLM and human consumers
+pub fn summarize(tool: &str, code: &str, status: i32, stdout: &str, stderr: &str) -> String {
+    let diagnostics = DiagnosticCounts::from_output(stdout, stderr);
+
+    match tool {
+        "cargo_test" => {
+            if let Some(tests) = test_breakdown(stdout, stderr) {
+                let TestCounts {
+                    passed,
+                    failed,
+                    ignored,
+                } = tests.total;
+                return format!("{} passed, {} failed, {} ignored", passed, failed, ignored);
+            }
+        }
+        "cargo_deny" => {
+            if let Some(report) = crate::deny::DenyReport::from_json_output(stderr) {
+                let names = |passed: bool| {
+                    report
+                        .categories
+                        .iter()
+                        .filter(|(_, result)| result.passed == passed)
+                        .map(|(category, _)| *category)
+                        .collect::<Vec<_>>()
+                };
+                let (passed, failed) = (names(true), names(false));
+                return if failed.is_empty() {
+                    format!("passed: {}", passed.join(", "))
+                } else {
+                    format!(
+                        "failed: {}; passed: {}",
+                        failed.join(", "),
+                        passed.join(", ")
+                    )
+                };
+            }
+        }
+        "cargo_audit" => {
+            if let Some(vulnerabilities) = crate::audit::vulnerabilities(stdout) {
+                return format!("{} vulnerabilities found", vulnerabilities.len());
+            }
+        }
+        "find_duplicate_deps" if status == 0 => {
+            return format!(
+                "{} crates with multiple versions",
+                duplicate_deps(stdout).len()
+            );
+        }
+        "cargo_fmt" if status == 0 => {
+            // rustfmt --emit=stdout prints "<path>:\n\n<formatted source>"
+            let formatted = stdout.split_once("\n\n").map_or(stdout, |(_, body)| body);
+            return if formatted.trim_end() == code.trim_end() {
+                "unchanged: code is already formatted".to_string()
+            } else {
+                "changed: formatting applied".to_string()
+            };
+        }
+        _ => {}
+    }
+
+    if !diagnostics.is_empty() {
+        diagnostics.describe()
+    } else if status == 0 {
+        "succeeded".to_string()
+    } else {
+        format!("failed with exit status {}", status)
+    }
+}
+
+/// File and line of a location such as `src/main.rs:10:5`, read from the
+/// right so Windows paths keep their drive letter (`C:\\proj\\src\\main.rs:10:5`).
+/// The line is `None` when the location doesn't end in numbers.
+pub fn file_location(location: &str) -> Option<(String, Option<i32>)> {
+    let location = location.trim();
+    let mut file = location;
+    let mut numbers = Vec::new();
+    while numbers.len() < 2
+        && let Some((head, tail)) = file.rsplit_once(':')
+        && let Ok(number) = tail.parse::<i32>()
+    {
+        numbers.push(number);
+        file = head;
+    }
+    if let Some(line) = numbers.last() {
+        return Some((file.to_string(), Some(*line)));
+    }
+    // No line: whatever precedes the first colon past a drive letter
+    let drive = match location.as_bytes() {
+        [letter, b':', b'\\' | b'/', ..] if letter.is_ascii_alphabetic() => 2,
+        _ => 0,
+    };
+    let (file, _) = location[drive..].split_once(':')?;
+    Some((location[..drive + file.len()].to_string(), None))
+}
+
+/// Strip the mode rustdoc appends to a doc test's name, e.g.
+/// `src/lib.rs - f (line 3) - compile fail`
+fn doc_test_base_name(name: &str) -> &str {
+    match name.find(" (line ") {
+        Some(start) if parse_doc_test_name(name).is_some() => name[start..]
+            .find(')')
+            .map_or(name, |end| &name[..start + end + 1]),
+        _ => name,
+    }
+}
+
+/// Split a doc test name `src/lib.rs - path::item (line 5)` into file,
+/// item and the line of the example's opening fence
+fn parse_doc_test_name(name: &str) -> Option<(&str, &str, i32)> {
+    let (file, rest) = name.split_once(" - ")?;
+    let (item, rest) = rest.split_once(" (line ")?;
+    let (line, _) = rest.split_once(')')?;
+    Some((file, item, line.parse().ok()?))
+}
+
+/// A doc test example that failed, with the reason rustdoc gave
+#[derive(Debug, PartialEq, serde::Serialize)]
+pub struct DocTestFailure {
+    pub item: String,
+    pub file: String,
+    /// Line of the example's opening fence, or of the compile error rustc
+    /// reported inside it
+    pub line: i32,
+    pub reason: String,
+}
+
+/// Failed doc tests from the `---- name stdout ----` sections of
+/// `cargo test` output. Rustdoc reports lines in the source file, which for
+/// snippets built as a library is the snippet itself.
+pub fn doc_test_failures(stdout: &str) -> Vec<DocTestFailure> {
+    let mut failures: Vec<DocTestFailure> = Vec::new();
+    let mut current: Option<usize> = None;
+    let mut after_panic = false;
+    let mut located = false;
+    for line in stdout.lines() {
+        if let Some(name) = line
+            .strip_prefix("---- ")
+            .and_then(|rest| rest.strip_suffix(" stdout ----"))
+        {
+            current = parse_doc_test_name(name).map(|(file, item, fence)| {
+                failures.push(DocTestFailure {
+                    item: item.to_string(),
+                    file: file.to_string(),
+                    line: fence,
+                    reason: String::new(),
+                });
+                failures.len() - 1
+            });
+            after_panic = false;
+            located = false;
+            continue;
+        }
+        // The list of failed names that closes the report
+        if line == "failures:" {
+            current = None;
+        }
+        let Some(failure) = current.map(|i| &mut failures[i]) else {
+            continue;
+        };
+        let trimmed = line.trim();
+        if trimmed.is_empty() {
+            continue;
+        }
+        if after_panic {
+            failure.reason = trimmed.to_string();
+            after_panic = false;
+        } else if trimmed.contains("panicked at ") {
+            after_panic = true;
+        } else if trimmed.starts_with("error") && failure.reason.is_empty() {
+            failure.reason = trimmed.to_string();
+        } else if let Some(location) = trimmed.strip_prefix("--> ")
+            && let Some((_, Some(error_line))) = file_location(location)
+            && failure.reason.starts_with("error")
+            && !located
+        {
+            failure.line = error_line;
+            located = true;
+        } else if trimmed.starts_with("Test compiled successfully") {
+            failure.reason = trimmed.to_string();
+        }
+    }
+    for failure in &mut failures {
+        if failure.reason.is_empty() {
+            failure.reason = "doc test failed".to_string();
+        }
+    }
+    failures
+}
+
+/// A crate that appears in the dependency graph in more than one version
+#[derive(Debug, PartialEq, serde::Serialize)]
+pub struct DuplicateDep {
+    #[serde(rename = "crate")]
+    pub krate: String,
+    pub versions: Vec<String>,
+}
+
+/// Group the root lines of `cargo tree --duplicates` (`name vX.Y.Z ...`)
+/// by crate name; the indented lines beneath each are its dependents
+pub fn duplicate_deps(tree: &str) -> Vec<DuplicateDep> {
+    let mut duplicates: Vec<DuplicateDep> = Vec::new();
+    for line in tree.lines() {
+        if line.starts_with(char::is_whitespace) || line.starts_with(['├', '└', '│']) {
+            continue;
+        }
+        let mut words = line.split_whitespace();
+        let (Some(name), Some(version)) = (words.next(), words.next()) else {
+            continue;
+        };
+        let Some(version) = version.strip_prefix('v') else {
+            continue;
+        };
+        match duplicates.iter_mut().find(|d| d.krate == name) {
+            Some(duplicate) => {
+                if !duplicate.versions.iter().any(|v| v == version) {
+                    duplicate.versions.push(version.to_string());
+                }
+            }
+            None => duplicates.push(DuplicateDep {
+                krate: name.to_string(),
+                versions: vec![version.to_string()],
+            }),
+        }
+    }
+    duplicates
+}
+
+/// A package in the dependency graph and the packages it depends on
+#[derive(Debug, PartialEq, serde::Serialize)]
+pub struct TreeNode {
+    pub name: String,
+    pub version: String,
+    /// Path, git or registry source shown for packages not from crates.io
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub source: Option<String>,
+    /// Marked `(*)`: shown earlier in the tree, so its dependencies are omitted
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    pub deduplicated: bool,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub dependencies: Vec<TreeNode>,
+}
+
+/// Nest the lines of `cargo tree --prefix depth` (`<depth><name> v<version>
+/// (<source>) (*)`) into one tree per root package
+pub fn dependency_tree(tree: &str) -> Vec<TreeNode> {
+    fn attach(roots: &mut Vec<TreeNode>, stack: &mut [TreeNode], node: TreeNode) {
+        match stack.last_mut() {
+            Some(parent) => parent.dependencies.push(node),
+            None => roots.push(node),
+        }
+    }
+
+    let mut roots = Vec::new();
+    let mut stack: Vec<TreeNode> = Vec::new();
+    let mut depths: Vec<usize> = Vec::new();
+    for line in tree.lines() {
+        let digits = line.len() - line.trim_start_matches(|c: char| c.is_ascii_digit()).len();
+        let Ok(depth) = line[..digits].parse::<usize>() else {
+            continue;
+        };
+        let mut words = line[digits..].split_whitespace();
+        let (Some(name), Some(version)) = (words.next(), words.next()) else {
+            continue;
+        };
+        let Some(version) = version.strip_prefix('v') else {
+            continue;
+        };
+        let mut node = TreeNode {
+            name: name.to_string(),
+            version: version.to_string(),
+            source: None,
+            deduplicated: false,
+            dependencies: Vec::new(),
+        };
+        let rest: Vec<&str> = words.collect();
+        for group in rest.join(" ").split(')') {
+            let Some(group) = group.trim().strip_prefix('(') else {
+                continue;
+            };
+            match group {
+                "*" => node.deduplicated = true,
+                "proc-macro" => {}
+                source => node.source = Some(source.to_string()),
+            }
+        }
+
+        while depths.last().is_some_and(|&d| d >= depth) {
+            depths.pop();
+            let done = stack.pop().expect("stack and depths have equal length");
+            attach(&mut roots, &mut stack, done);
+        }
+        stack.push(node);
+        depths.push(depth);
+    }
+    while let Some(done) = stack.pop() {
+        attach(&mut roots, &mut stack, done);
+    }
+    roots
+}
+
+/// A lockfile change `cargo update` reported
+#[derive(Debug, PartialEq, serde::Serialize)]
+pub struct LockfileUpdate {
+    pub name: String,
+    /// None for packages the update adds
+    pub from: Option<String>,
+    /// None for packages the update removes
+    pub to: Option<String>,
+    /// Whether `from` and `to` are semver-compatible; None unless both are
+    /// known
+    pub compatible: Option<bool>,
+}
+
+/// The "Updating name v1.2.3 -> v1.2.4" lines of `cargo update`, plus the
+/// packages it adds, removes and downgrades
+pub fn lockfile_updates(stderr: &str) -> Vec<LockfileUpdate> {
+    let mut updates = Vec::new();
+    for line in stderr.lines() {
+        let words: Vec<&str> = line.split_whitespace().collect();
+        let [verb, name, from, ..] = words[..] else {
+            continue;
+        };
+        let Some(from) = from.strip_prefix('v') else {
+            continue;
+        };
+        let update = match verb {
+            "Updating" | "Downgrading" => {
+                // Git sources follow the version in parentheses
+                let Some(to) = words
+                    .iter()
+                    .skip_while(|word| **word != "->")
+                    .nth(1)
+                    .and_then(|to| to.strip_prefix('v'))
+                else {
+                    continue;
+                };
+                LockfileUpdate {
+                    name: name.to_string(),
+                    from: Some(from.to_string()),
+                    to: Some(to.to_string()),
+                    compatible: Some(semver_compatible(from, to)),
+                }
+            }
+            "Adding" => LockfileUpdate {
+                name: name.to_string(),
+                from: None,
+                to: Some(from.to_string()),
+                compatible: None,
+            },
+            "Removing" => LockfileUpdate {
+                name: name.to_string(),
+                from: Some(from.to_string()),
+                to: None,
+                compatible: None,
+            },
+            _ => continue,
+        };
+        updates.push(update);
+    }
+    updates
+}
+
+/// Cargo's caret rule: the leftmost non-zero components match, e.g.
+/// 1.2.3 and 1.9.0, or 0.4.1 and 0.4.7, but not 0.4.1 and 0.5.0
+fn semver_compatible(from: &str, to: &str) -> bool {
+    let components = |version: &str| -> Vec<String> {
+        let release = version.split(['-', '+']).next().unwrap_or(version);
+        release.split('.').map(str::to_string).collect()
+    };
+    let (from, to) = (components(from), components(to));
+    for (old, new) in from.iter().zip(&to) {
+        if old != new {
+            return false;
+        }
+        if old != "0" {
+            return true;
+        }
+    }
+    from == to
+}
+
+/// A public item reported by the `missing_docs` lint
+#[derive(Debug, PartialEq, serde::Serialize)]
+pub struct MissingDoc {
+    /// Item kind as rustc names it, e.g. "function" or "struct field"
+    pub kind: String,
+    pub file: String,
+    pub line: i32,
+    /// Source text of the item's span
+    pub item: String,
+}
+
+/// Items rustdoc flagged with "missing documentation for ..." in rendered output
+pub fn missing_docs(stderr: &str) -> Vec<MissingDoc> {
+    let mut items = Vec::new();
+    let mut lines = stderr.lines().peekable();
+    while let Some(line) = lines.next() {
+        let Some(kind) = ["error: ", "warning: "]
+            .iter()
+            .find_map(|level| line.strip_prefix(level))
+            .and_then(|msg| msg.strip_prefix("missing documentation for "))
+        else {
+            continue;
+        };
+        let kind = ["a ", "an ", "the "]
+            .iter()
+            .find_map(|article| kind.strip_prefix(article))
+            .unwrap_or(kind);
+
+        // " --> src/lib.rs:2:1", then "  |", "2 | <source>", "  | ^^^^"
+        let Some((file, line_no, column)) = lines
+            .next()
+            .and_then(|l| l.trim_start().strip_prefix("--> "))
+            .and_then(|loc| {
+                let mut parts = loc.rsplitn(3, ':');
+                let column: usize = parts.next()?.parse().ok()?;
+                let line: i32 = parts.next()?.parse().ok()?;
+                Some((parts.next()?.to_string(), line, column))
+            })
+        else {
+            continue;
+        };
+
+        let mut source = None;
+        let mut item = String::new();
+        while let Some(next) = lines.peek() {
+            let Some((gutter, text)) = next.split_once('|') else {
+                break;
+            };
+            if gutter.trim() == line_no.to_string() {
+                source = Some(text.strip_prefix(' ').unwrap_or(text).to_string());
+            } else if let Some(source) = &source
+                && gutter.trim().is_empty()
+                && text.contains('^')
+            {
+                let carets = text.trim_start_matches(' ').trim_end();
+                let width = carets.chars().take_while(|c| *c == '^').count();
+                item = source
+                    .chars()
+                    .skip(column.saturating_sub(1))
+                    .take(width)
+                    .collect();
+            }
+            lines.next();
+        }
+
+        items.push(MissingDoc {
+            kind: kind.to_string(),
+            file,
+            line: line_no,
+            item,
+        });
+    }
+    items
+}
+
+/// Unified line diff between two texts, empty when they are identical
+pub fn unified_diff(old: &str, new: &str, old_label: &str, new_label: &str) -> String {
+    if old == new {
+        return String::new();
+    }
+    similar::TextDiff::from_lines(old, new)
+        .unified_diff()
+        .context_radius(3)
+        .header(old_label, new_label)
+        .to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    const CHECK_STDERR: &str = "    Checking temp_project v0.1.0 (/tmp/.tmpabc)
+warning: unused variable: `x`
+ --> src/main.rs:2:9
+  |
+2 |     let x = 1;
+  |         ^ help: if this is intentional, prefix it with an underscore: `_x`
+  |
+error[E0308]: mismatched types
+ --> src/main.rs:3:18
+warning: `temp_project` (bin \"temp_project\") generated 1 warning
+error: could not compile `temp_project` (bin \"temp_project\") due to 1 previous error; 1 warning emitted
+";
+
+    #[test]
+    fn trimmed_output_keeps_diagnostics() {
+        let trimmed = trim_cargo_noise(CHECK_STDERR);
+        assert!(!trimmed.contains("Checking temp_project"));
+        assert!(trimmed.contains("error[E0308]: mismatched types"));
+        assert!(trimmed.contains("  |         ^ help: if this is intentional"));
+        assert_eq!(
+            trim_cargo_noise("   Compiling a v0.1.0\n    Finished `dev` profile\n"),
+            ""
+        );
+    }
+
+    #[test]
+    fn test_results_are_split_by_category() {
+        let stderr = "    Finished `test` profile [unoptimized + debuginfo] target(s) in 0.35s
+     Running unittests src/lib.rs (target/debug/deps/tt-2ffcd961b67f0851)
+     Running tests/x.rs (target/debug/deps/x-145754b27645a0ed)
+   Doc-tests tt
+";
+        let stdout = "
+running 2 tests
+test a ... ok
+test b ... ignored
+
+test result: ok. 1 passed; 0 failed; 1 ignored; 0 measured; 0 filtered out; finished in 0.00s
+
+running 1 test
+test i ... ok
+
+test result: ok. 1 passed; 0 failed; 0 ignored; 0 measured; 0 filtered out; finished in 0.00s
+
+running 1 test
+test src/lib.rs - f (line 1) ... FAILED
+
+test result: FAILED. 0 passed; 1 failed; 0 ignored; 0 measured; 0 filtered out; finished in 0.00s
+";
+        let counts = |passed, failed, ignored| TestCounts {
+            passed,
+            failed,
+            ignored,
+        };
+        let breakdown = test_breakdown(stdout, stderr).unwrap();
+        assert_eq!(breakdown.total, counts(2, 1, 1));
+        assert_eq!(breakdown.unit, Some(counts(1, 0, 1)));
+        assert_eq!(breakdown.integration, Some(counts(1, 0, 0)));
+        assert_eq!(breakdown.doc, Some(counts(0, 1, 0)));
+
+        // Without cargo's headers, doc tests are recognised by name
+        let breakdown = test_breakdown(stdout, "").unwrap();
+        assert_eq!(breakdown.unit, Some(counts(2, 0, 1)));
+        assert_eq!(breakdown.integration, None);
+        assert_eq!(breakdown.doc, Some(counts(0, 1, 0)));
+        assert!(test_breakdown("", stderr).is_none());
+    }
+
+    #[test]
+    fn nextest_json_events_give_per_test_durations() {
+        let stdout = r#"{"type":"suite","event":"started","test_count":3}
+{"type":"test","event":"started","name":"temp_project::bin/temp_project$tests::fast"}
+{"type":"test","name":"temp_project::bin/temp_project$tests::fast","event":"ok","exec_time":0.002}
+{"type":"test","name":"temp_project::bin/temp_project$tests::slow","event":"failed","exec_time":1.5,"stdout":"panicked"}
+{"type":"test","name":"temp_project::it$works","event":"ignored"}
+{"type":"suite","event":"failed","passed":1,"failed":1,"ignored":1,"measured":0,"filtered_out":0,"exec_time":1.6}
+"#;
+        let outcomes = test_outcomes(stdout);
+        assert_eq!(outcomes.len(), 3);
+        assert_eq!(outcomes[1].status, "failed");
+        assert_eq!(outcomes[1].duration_ms, Some(1500.0));
+        assert_eq!(outcomes[2].duration_ms, None);
+
+        let breakdown = test_breakdown(stdout, "").unwrap();
+        assert_eq!(
+            breakdown.unit,
+            Some(TestCounts {
+                passed: 1,
+                failed: 1,
+                ignored: 0
+            })
+        );
+        assert_eq!(breakdown.integration.unwrap().ignored, 1);
+        assert_eq!(
+            breakdown.failed_tests,
+            ["temp_project::bin/temp_project$tests::slow"]
+        );
+        assert_eq!(
+            summarize("cargo_test", "", 100, stdout, ""),
+            "1 passed, 1 failed, 1 ignored"
+        );
+
+        let libtest = test_outcomes("test a ... ok\ntest b ... ignored, slow\ntest result: ok.");
+        assert_eq!(libtest[1].status, "ignored");
+        assert_eq!(libtest[0].duration_ms, None);
+    }
+
+    #[test]
+    fn duplicate_versions_are_grouped_by_crate() {
+        let tree = "\
+bitflags v1.3.2
+└── nix v0.26.4
+    └── temp_project v0.1.0 (/tmp/.tmpabc)
+
+bitflags v2.4.0
+└── temp_project v0.1.0 (/tmp/.tmpabc)
+
+syn v1.0.109
+└── serde_derive v1.0.100 (proc-macro)
+[build-dependencies]
+└── temp_project v0.1.0 (/tmp/.tmpabc)
+
+syn v2.0.48
+└── thiserror-impl v1.0.56 (proc-macro)
+";
+        assert_eq!(
+            duplicate_deps(tree),
+            [
+                DuplicateDep {
+                    krate: "bitflags".into(),
+                    versions: vec!["1.3.2".into(), "2.4.0".into()]
+                },
+                DuplicateDep {
+                    krate: "syn".into(),
+                    versions: vec!["1.0.109".into(), "2.0.48".into()]
+                },
+            ]
+        );
+        assert!(duplicate_deps("").is_empty());
+    }
+
+    #[test]
+    fn lockfile_updates_are_read_with_their_compatibility() {
+        let stderr = "    Updating crates.io index
+     Locking 4 packages to latest compatible versions
+    Updating serde v1.0.100 -> v1.0.210
+    Updating rand v0.7.3 -> v0.8.5
+ Downgrading log v0.4.22 -> v0.4.20
+    Updating tool v0.1.0 (https://github.com/a/tool#1234abcd) -> #5678ef01
+      Adding itoa v1.0.11
+    Removing winapi v0.3.9
+warning: not updating lockfile due to dry run
+";
+        let update =
+            |name: &str, from: Option<&str>, to: Option<&str>, compatible| LockfileUpdate {
+                name: name.into(),
+                from: from.map(Into::into),
+                to: to.map(Into::into),
+                compatible,
+            };
+        assert_eq!(
+            lockfile_updates(stderr),
+            [
+                update("serde", Some("1.0.100"), Some("1.0.210"), Some(true)),
+                update("rand", Some("0.7.3"), Some("0.8.5"), Some(false)),
+                update("log", Some("0.4.22"), Some("0.4.20"), Some(true)),
+                update("itoa", None, Some("1.0.11"), None),
+                update("winapi", Some("0.3.9"), None, None),
+            ]
+        );
+        assert!(semver_compatible("0.0.3", "0.0.3"));
+        assert!(!semver_compatible("0.0.3", "0.0.4"));
+        assert!(!semver_compatible("1.9.0", "2.0.0-rc.1"));
+    }
+
+    #[test]
+    fn depth_prefixed_tree_is_nested() {
+        let tree = "\
+0temp_project v0.1.0 (/tmp/.tmpabc)
+1serde v1.0.200
+2serde_derive v1.0.200 (proc-macro)
+3proc-macro2 v1.0.81
+1serde_json v1.0.116
+2serde v1.0.200 (*)
+1local v0.2.0 (/tmp/local)
+";
+        let roots = dependency_tree(tree);
+        assert_eq!(roots.len(), 1);
+        let root = &roots[0];
+        assert_eq!(root.source.as_deref(), Some("/tmp/.tmpabc"));
+        let names: Vec<&str> = root.dependencies.iter().map(|d| d.name.as_str()).collect();
+        assert_eq!(names, ["serde", "serde_json", "local"]);
+
+        let derive = &root.dependencies[0].dependencies[0];
+        assert_eq!(derive.name, "serde_derive");
+        assert_eq!(derive.source, None);
+        assert_eq!(derive.dependencies[0].name, "proc-macro2");
+
+        let repeated = &root.dependencies[1].dependencies[0];
+        assert!(repeated.deduplicated);
+        assert_eq!(repeated.version, "1.0.200");
+        assert_eq!(root.dependencies[2].source.as_deref(), Some("/tmp/local"));
+        assert!(dependency_tree("").is_empty());
+    }
+
+    #[test]
+    fn doc_test_failures_point_at_source_lines() {
+        let stdout = "
+running 5 tests
+test src/lib.rs - f (line 13) - compile fail ... ok
+test src/lib.rs - f (line 17) - compile fail ... FAILED
+test src/lib.rs - f (line 21) ... FAILED
+test src/lib.rs - f (line 5) ... FAILED
+test src/lib.rs - f (line 9) - compile ... ok
+
+failures:
+
+---- src/lib.rs - f (line 17) stdout ----
+Test compiled successfully, but it's marked `compile_fail`.
+---- src/lib.rs - f (line 21) stdout ----
+error[E0308]: mismatched types
+  --> src/lib.rs:22:13
+   |
+22 | let y: u8 = \"bad\";
+   |        --   ^^^^^ expected `u8`, found `&str`
+
+error: aborting due to 1 previous error
+
+Couldn't compile the test.
+---- src/lib.rs - f (line 5) stdout ----
+Test executable failed (exit status: 101).
+
+stderr:
+
+thread 'main' (27738) panicked at src/lib.rs:5:1:
+assertion `left == right` failed
+  left: 1
+ right: 2
+
+
+failures:
+    src/lib.rs - f (line 17)
+    src/lib.rs - f (line 21)
+    src/lib.rs - f (line 5)
+
+test result: FAILED. 2 passed; 3 failed; 0 ignored; 0 measured; 0 filtered out; finished in 0.30s
+";
+        let failures = doc_test_failures(stdout);
+        let located: Vec<(i32, &str)> = failures
+            .iter()
+            .map(|f| (f.line, f.reason.as_str()))
+            .collect();
+        assert_eq!(
+            located,
+            [
+                (
+                    17,
+                    "Test compiled successfully, but it's marked `compile_fail`."
+                ),
+                (22, "error[E0308]: mismatched types"),
+                (5, "assertion `left == right` failed"),
+            ]
+        );
+        assert!(
+            failures
+                .iter()
+                .all(|f| f.item == "f" && f.file == "src/lib.rs")
+        );
+
+        // no_run and passing compile_fail examples aren't failures
+        let breakdown = test_breakdown(stdout, "   Doc-tests temp_project").unwrap();
+        assert_eq!(
+            breakdown.failed_tests,
+            [
+                "src/lib.rs - f (line 17)",
+                "src/lib.rs - f (line 21)",
+                "src/lib.rs - f (line 5)"
+            ]
+        );
+        assert_eq!(breakdown.doc.unwrap().passed, 2);
+    }
+
+    #[test]
+    fn check_summary_counts_errors_and_warnings() {
+        let summary = summarize("cargo_check", "", 101, "", CHECK_STDERR);
+        assert_eq!(
+            summary,
+            "1 error, 1 warning: unused variable: `x`; mismatched types (E0308)"
+        );
+        assert_eq!(summarize("cargo_check", "", 0, "", ""), "succeeded");
+    }
+
+    #[test]
+    fn json_diagnostics_are_counted() {
+        let stdout = r#"{"reason":"compiler-message","message":{"level":"error","message":"mismatched types","code":{"code":"E0308"}}}
+{"reason":"build-finished","success":false}"#;
+        assert_eq!(
+            summarize("rust_analyzer", "", 101, stdout, ""),
+            "1 error, 0 warnings: mismatched types (E0308)"
+        );
+    }
+
+    #[test]
+    fn test_summary_adds_up_result_lines() {
+        let stdout = "running 2 tests\ntest result: ok. 2 passed; 0 failed; 1 ignored; 0 measured; 0 filtered out\n\ntest result: FAILED. 1 passed; 1 failed; 0 ignored; 0 measured; 0 filtered out\n";
+        assert_eq!(
+            summarize("cargo_test", "", 101, stdout, ""),
+            "3 passed, 1 failed, 1 ignored"
+        );
+    }
+
+    #[test]
+    fn error_blocks_keep_errors_and_drop_warnings() {
+        let stderr = "   Compiling temp_project v0.1.0 (/tmp/.tmpabc)
+warning: unused variable: `y`
+ --> src/main.rs:2:9
+  |
+2 |     let y = 1;
+  |         ^ help: prefix it with an underscore: `_y`
+
+error[E0308]: mismatched types
+ --> src/main.rs:3:18
+  |
+3 |     let x: u8 = \"a\";
+  |            --   ^^^ expected `u8`, found `&str`
+
+error: could not compile `temp_project` (bin \"temp_project\") due to 1 previous error; 1 warning emitted
+";
+        let errors = error_blocks(stderr);
+        assert!(errors.starts_with("error[E0308]: mismatched types\n --> src/main.rs:3:18"));
+        assert!(errors.ends_with("due to 1 previous error; 1 warning emitted"));
+        assert!(!errors.contains("unused variable") && !errors.contains("Compiling"));
+        assert_eq!(error_blocks("warning: unused import\n"), "");
+    }
+
+    #[test]
+    fn long_output_is_truncated_at_a_char_boundary() {
+        assert_eq!(truncate_output("short", 5), None);
+        assert_eq!(
+            truncate_output("error: a\nerror: b\n", 9).unwrap(),
+            "error: a\n...[truncated 9 bytes]"
+        );
+        // "é" is two bytes, so the cut moves back before it
+        assert_eq!(truncate_output("aé", 2).unwrap(), "a...[truncated 2 bytes]");
+    }
+
+    #[test]
+    fn fmt_summary_reports_changes() {
+        let code = "fn main() {\n    let x = 1;\n}\n";
+        let stdout = format!("/tmp/x/src/main.rs:\n\n{}", code);
+        assert!(summarize("cargo_fmt", code, 0, &stdout, "").starts_with("unchanged"));
+        assert!(
+            summarize("cargo_fmt", "fn main(){let x=1;}", 0, &stdout, "").starts_with("changed")
+        );
+    }
+
+    #[test]
+    fn missing_docs_lists_items() {
+        let stderr = " Documenting temp_project v0.1.0 (/tmp/.tmpabc)
+error: missing documentation for a function
+ --> src/lib.rs:2:1
+  |
+2 | pub fn add(a: u32) -> u32 { a }
+  | ^^^^^^^^^^^^^^^^^^^^^^^^^
+  |
+  = note: requested on the command line with `-D missing-docs`
+
+warning: missing documentation for a struct field
+ --> src/lib.rs:3:16
+  |
+3 | pub struct S { pub x: u8 }
+  |                ^^^^^^^^^
+
+error: could not document `temp_project`
+";
+        assert_eq!(
+            missing_docs(stderr),
+            [
+                MissingDoc {
+                    kind: "function".into(),
+                    file: "src/lib.rs".into(),
+                    line: 2,
+                    item: "pub fn add(a: u32) -> u32".into(),
+                },
+                MissingDoc {
+                    kind: "struct field".into(),
+                    file: "src/lib.rs".into(),
+                    line: 3,
+                    item: "pub x: u8".into(),
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn unified_diff_marks_changed_lines() {
+        let diff = unified_diff("a\nb\nc\n", "a\nB\nc\n", "baseline", "current");
+        assert!(diff.starts_with("--- baseline\n+++ current\n"));
+        assert!(diff.contains("-b\n+B\n"));
+        assert_eq!(unified_diff("same\n", "same\n", "a", "b"), "");
+    }
+
+    #[test]
+    fn file_locations_keep_windows_drive_letters() {
+        let located = |file: &str, line| Some((file.to_string(), line));
+        assert_eq!(
+            file_location("src/main.rs:10:5"),
+            located("src/main.rs", Some(10))
+        );
+        assert_eq!(
+            file_location("src/main.rs:10"),
+            located("src/main.rs", Some(10))
+        );
+        assert_eq!(
+            file_location(r"src\main.rs:10:5"),
+            located(r"src\main.rs", Some(10))
+        );
+        assert_eq!(
+            file_location(r"C:\Users\ana\proj\src\lib.rs:3:1"),
+            located(r"C:\Users\ana\proj\src\lib.rs", Some(3))
+        );
+        assert_eq!(
+            file_location(r"C:\proj\src\lib.rs:here"),
+            located(r"C:\proj\src\lib.rs", None)
+        );
+        assert_eq!(
+            file_location("src/main.rs:here"),
+            located("src/main.rs", None)
+        );
+        assert_eq!(file_location("src/main.rs"), None);
+    }
+
+    #[test]
+    fn verbosity_parsing() {
+        let parse = |value: Value| serde_json::from_value::<Verbosity>(value);
+        assert_eq!(parse(json!("full")).unwrap(), Verbosity::Full);
+        assert_eq!(parse(json!("trimmed")).unwrap(), Verbosity::Trimmed);
+        assert_eq!(parse(json!("summary")).unwrap(), Verbosity::Summary);
+        assert_eq!(parse(json!("errors")).unwrap(), Verbosity::Errors);
+        assert!(parse(json!("loud")).is_err());
+    }
+}
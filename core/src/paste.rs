@@ -0,0 +1,335 @@
+//! Whole projects pasted as one `code` string: a `Cargo.toml` and several
+//! source files run together, marked by Markdown code fences or file-name
+//! comments such as `// src/lib.rs` or `# Cargo.toml`. The paste is split
+//! back into project files; input that doesn't split cleanly stays a single
+//! file.
+
+/// How a `code` string reads
+#[derive(Debug, Clone, PartialEq)]
+pub enum Paste {
+    /// No file markers: one file, as usual
+    Single,
+    /// File markers the input doesn't split cleanly along, and why
+    Ambiguous(String),
+    /// Files by relative path, in the order they were pasted
+    Files(Vec<(String, String)>),
+}
+
+/// Split a pasted project into its files
+pub fn split(code: &str) -> Paste {
+    let result = if code.lines().any(|line| fence(line).is_some()) {
+        split_fenced(code)
+    } else {
+        split_marked(code)
+    };
+    match result {
+        Ok(files) if files.is_empty() => Paste::Single,
+        Ok(files) => match check_paths(&files) {
+            Some(reason) => Paste::Ambiguous(reason),
+            None => Paste::Files(files),
+        },
+        Err(reason) => Paste::Ambiguous(reason),
+    }
+}
+
+/// Markdown: each fenced block is a file, named by its info string, by a
+/// file-name line just before it, or by a file-name comment on its first
+/// line. Prose and shell blocks in between are dropped.
+fn split_fenced(code: &str) -> Result<Vec<(String, String)>, String> {
+    let mut files = Vec::new();
+    let mut unnamed = Vec::new();
+    let mut pending: Option<String> = None;
+    let mut lines = code.lines();
+    while let Some(line) = lines.next() {
+        let Some((ticks, info)) = fence(line) else {
+            if let Some(path) = file_marker(line) {
+                pending = Some(path);
+            }
+            continue;
+        };
+        let mut body = Vec::new();
+        let mut closed = false;
+        for line in lines.by_ref() {
+            if fence(line).is_some_and(|(n, rest)| n >= ticks && rest.is_empty()) {
+                closed = true;
+                break;
+            }
+            body.push(line);
+        }
+        if !closed {
+            return Err("a code fence is never closed".to_string());
+        }
+        let (language, info_path) = fence_info(info);
+        let mut path = info_path.or_else(|| pending.take());
+        if path.is_none()
+            && let Some(first) = body.iter().position(|line| !line.trim().is_empty())
+            && let Some(marked) = file_marker(body[first])
+        {
+            path = Some(marked);
+            body.drain(..=first);
+        }
+        pending = None;
+        let contents = file_contents(&body);
+        match path {
+            Some(path) => files.push((path, contents)),
+            None if language == "toml" || is_manifest(&contents) => {
+                files.push(("Cargo.toml".to_string(), contents))
+            }
+            None if matches!(language, "" | "rust" | "rs") => unnamed.push(contents),
+            // Shell commands, program output and the like
+            None => {}
+        }
+    }
+    match unnamed.len() {
+        0 => {}
+        1 => {
+            // A lone unnamed block is the crate root
+            let contents = unnamed.remove(0);
+            let root = if contents.contains("fn main") {
+                "src/main.rs"
+            } else {
+                "src/lib.rs"
+            };
+            files.push((root.to_string(), contents));
+        }
+        n => return Err(format!("{} code blocks have no file name", n)),
+    }
+    Ok(files)
+}
+
+/// Plain text: a file-name comment starts each file. A manifest may come
+/// first without one.
+fn split_marked(code: &str) -> Result<Vec<(String, String)>, String> {
+    let mut files: Vec<(String, Vec<&str>)> = Vec::new();
+    let mut preamble = Vec::new();
+    for line in code.lines() {
+        if let Some(path) = file_marker(line) {
+            files.push((path, Vec::new()));
+        } else if let Some((_, body)) = files.last_mut() {
+            body.push(line);
+        } else {
+            preamble.push(line);
+        }
+    }
+    if files.is_empty() {
+        return Ok(Vec::new());
+    }
+    let mut files: Vec<(String, String)> = files
+        .into_iter()
+        .map(|(path, body)| (path, file_contents(&body)))
+        .collect();
+    let preamble = file_contents(&preamble);
+    if is_manifest(&preamble) {
+        files.insert(0, ("Cargo.toml".to_string(), preamble));
+    } else if !preamble.trim().is_empty() {
+        return Err("code comes before the first file name".to_string());
+    }
+    if let [(path, _)] = files.as_slice()
+        && path == "src/main.rs"
+    {
+        // Already where a single snippet goes
+        return Ok(Vec::new());
+    }
+    Ok(files)
+}
+
+/// Why `files` can't be laid out as a project, if they can't
+fn check_paths(files: &[(String, String)]) -> Option<String> {
+    for (i, (path, _)) in files.iter().enumerate() {
+        if files[..i].iter().any(|(seen, _)| seen == path) {
+            return Some(format!("{} appears more than once", path));
+        }
+    }
+    None
+}
+
+/// Backtick count and info string of a fence line
+fn fence(line: &str) -> Option<(usize, &str)> {
+    let line = line.trim();
+    let ticks = line.chars().take_while(|c| *c == '`').count();
+    (ticks >= 3).then(|| (ticks, line[ticks..].trim()))
+}
+
+/// Language and file name of a fence's info string, e.g. `rust`,
+/// `toml:Cargo.toml`, `rust title="src/lib.rs"` or `rust src/lib.rs`
+fn fence_info(info: &str) -> (&str, Option<String>) {
+    let mut words = info.split_whitespace();
+    let Some(first) = words.next() else {
+        return ("", None);
+    };
+    let (language, named) = match first.split_once(':') {
+        Some((language, path)) => (language, Some(path)),
+        None => (first, None),
+    };
+    let language = language.split(',').next().unwrap_or_default();
+    let path = named.into_iter().chain(words).find_map(|word| {
+        let word = word
+            .split_once('=')
+            .map_or(word, |(_, value)| value)
+            .trim_matches(['"', '\'']);
+        project_path(word)
+    });
+    (language, path)
+}
+
+/// The file a line names when it is nothing but a file name, perhaps as a
+/// comment or heading: `// src/lib.rs`, `// file: src/lib.rs`,
+/// `# Cargo.toml`, `**src/main.rs**`, `` `build.rs`: ``
+fn file_marker(line: &str) -> Option<String> {
+    let line = line.trim();
+    if line.starts_with("//!") || line.starts_with("#[") || line.starts_with("#!") {
+        return None;
+    }
+    let text = line.trim_start_matches(['/', '#']);
+    let text = text.trim_matches(|c: char| c.is_whitespace() || "=-*`".contains(c));
+    let text = text.strip_suffix(':').unwrap_or(text);
+    let lower = text.to_ascii_lowercase();
+    let text = ["filename:", "file:", "path:"]
+        .iter()
+        .find_map(|label| lower.starts_with(label).then(|| &text[label.len()..]))
+        .unwrap_or(text);
+    project_path(text.trim_matches(|c: char| c.is_whitespace() || "*`".contains(c)))
+}
+
+/// A relative path to a Rust source file or a manifest, with bare source
+/// file names placed under `src/` (bar `build.rs`)
+fn project_path(text: &str) -> Option<String> {
+    let text = text.strip_prefix("./").unwrap_or(text);
+    if text.is_empty()
+        || !text
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || "_-./".contains(c))
+        || text
+            .split('/')
+            .any(|part| part.is_empty() || part == "." || part == "..")
+    {
+        return None;
+    }
+    let name = text.rsplit('/').next().unwrap_or(text);
+    if name != "Cargo.toml" && !(name.len() > 3 && name.ends_with(".rs")) {
+        return None;
+    }
+    if text == name && name.ends_with(".rs") && name != "build.rs" {
+        return Some(format!("src/{}", name));
+    }
+    Some(text.to_string())
+}
+
+/// A TOML manifest: a `[package]` or `[workspace]` table
+fn is_manifest(contents: &str) -> bool {
+    contents
+        .lines()
+        .any(|line| matches!(line.trim(), "[package]" | "[workspace]"))
+}
+
+/// Lines without the blank ones around them, newline-terminated
+fn file_contents(lines: &[&str]) -> String {
+    let start = lines
+        .iter()
+        .position(|line| !line.trim().is_empty())
+        .unwrap_or(lines.len());
+    let end = lines
+        .iter()
+        .rposition(|line| !line.trim().is_empty())
+        .map_or(start, |end| end + 1);
+    let mut contents = lines[start..end].join("\n");
+    if !contents.is_empty() {
+        contents.push('\n');
+    }
+    contents
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn paths(paste: &Paste) -> Vec<&str> {
+        match paste {
+            Paste::Files(files) => files.iter().map(|(path, _)| path.as_str()).collect(),
+            other => panic!("expected files, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn markdown_pastes_are_split_into_files() {
+        // An answer with prose, a named manifest, fences named three ways and
+        // a shell block
+        let paste = "Here's the project:\n\n**Cargo.toml**\n```toml\n[package]\nname = \"demo\"\nversion = \"0.1.0\"\nedition = \"2021\"\n```\n\n```rust title=\"src/lib.rs\"\npub mod util;\n```\n\n```rust\n// src/util.rs\npub fn one() -> u8 { 1 }\n```\n\n`src/main.rs`:\n```rust\nfn main() { println!(\"{}\", demo::util::one()); }\n```\n\nRun it with:\n```sh\ncargo run\n```\n";
+        let pasted = split(paste);
+        assert_eq!(
+            paths(&pasted),
+            ["Cargo.toml", "src/lib.rs", "src/util.rs", "src/main.rs"]
+        );
+        let Paste::Files(files) = pasted else {
+            unreachable!()
+        };
+        assert!(files[0].1.starts_with("[package]\nname = \"demo\""));
+        assert_eq!(files[2].1, "pub fn one() -> u8 { 1 }\n");
+
+        // Unnamed blocks: a manifest by its contents, and a lone crate root
+        let paste = "```\n[package]\nname = \"demo\"\n```\n```rust\npub fn f() {}\n```\n";
+        assert_eq!(paths(&split(paste)), ["Cargo.toml", "src/lib.rs"]);
+        assert_eq!(paths(&split("```rs\nfn main() {}\n```")), ["src/main.rs"]);
+        assert_eq!(
+            paths(&split(
+                "```toml:Cargo.toml\n[package]\n```\n```rust:main.rs\nfn main() {}\n```"
+            )),
+            ["Cargo.toml", "src/main.rs"]
+        );
+    }
+
+    #[test]
+    fn comment_marked_pastes_are_split_into_files() {
+        let paste = "# Cargo.toml\n[package]\nname = \"demo\"\n\n// file: src/lib.rs\n//! Crate docs\npub fn f() {}\n\n// ===== main.rs =====\nfn main() {\n    // not/a/marker.rs here\n    demo::f();\n}\n";
+        let pasted = split(paste);
+        assert_eq!(paths(&pasted), ["Cargo.toml", "src/lib.rs", "src/main.rs"]);
+        let Paste::Files(files) = pasted else {
+            unreachable!()
+        };
+        assert_eq!(files[1].1, "//! Crate docs\npub fn f() {}\n");
+
+        // An unmarked manifest first, and workspace members
+        let paste = "[workspace]\nmembers = [\"crates/*\"]\n// crates/a/src/lib.rs\npub fn a() {}\n// build.rs\nfn main() {}\n";
+        assert_eq!(
+            paths(&split(paste)),
+            ["Cargo.toml", "crates/a/src/lib.rs", "build.rs"]
+        );
+    }
+
+    #[test]
+    fn plain_and_ambiguous_pastes_stay_single_files() {
+        for single in [
+            "fn main() {\n    println!(\"hi\");\n}\n",
+            "#![allow(dead_code)]\n#[derive(Debug)]\nstruct S;\nfn main() {}\n",
+            // Already the snippet's own file
+            "// main.rs\nfn main() {}\n",
+            // Not file names
+            "// see src/lib.rs for details\n// ../escape.rs\n// notes.txt\nfn main() {}\n",
+        ] {
+            assert_eq!(split(single), Paste::Single, "{}", single);
+        }
+
+        for (ambiguous, reason) in [
+            (
+                "use std::fmt;\n// src/lib.rs\npub fn f() {}\n",
+                "code comes before the first file name",
+            ),
+            ("```rust\nfn main() {}\n", "a code fence is never closed"),
+            (
+                "```rust\nfn a() {}\n```\n```rust\nfn b() {}\n```\n",
+                "2 code blocks have no file name",
+            ),
+            (
+                "// src/lib.rs\npub fn a() {}\n// lib.rs\npub fn b() {}\n",
+                "src/lib.rs appears more than once",
+            ),
+        ] {
+            assert_eq!(
+                split(ambiguous),
+                Paste::Ambiguous(reason.to_string()),
+                "{}",
+                ambiguous
+            );
+        }
+    }
+}
@@ -0,0 +1,574 @@
+//! Pool of pre-initialized scratch Cargo projects so tool calls skip the
+//! scaffold and tempdir setup cost.
+
+use crate::child_env::ChildEnv;
+use crate::msrv::RustVersion;
+use rmcp::ErrorData as McpError;
+use std::path::{Path, PathBuf};
+use std::process::Command as StdCommand;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::{Arc, Mutex, OnceLock};
+use tempfile::TempDir;
+use tokio::sync::Notify;
+
+/// Top-level entries of a scaffold or that cargo commands may leave behind.
+/// Anything else means the call touched the project in unexpected ways.
+const EXPECTED_ENTRIES: &[&str] = &[
+    "Cargo.toml",
+    "Cargo.lock",
+    "src",
+    "target",
+    // cargo_deny's policy
+    "deny.toml",
+    ".git",
+    ".gitignore",
+];
+
+/// The edition of generated manifests: the newest the installed cargo can
+/// read, as `cargo init` would choose. Falls back to 2021 when cargo's
+/// version can't be read.
+fn default_edition(child_env: &ChildEnv) -> &'static str {
+    static EDITION: OnceLock<&'static str> = OnceLock::new();
+    EDITION.get_or_init(|| {
+        let output = StdCommand::new("cargo")
+            .arg("--version")
+            .env_clear()
+            .envs(child_env.vars())
+            .output();
+        // "cargo 1.85.0 (d73d2caf9 2024-12-31)" or "cargo 1.86.0-nightly (...)"
+        output
+            .ok()
+            .and_then(|output| String::from_utf8(output.stdout).ok())
+            .and_then(|version| {
+                let release = version.split_whitespace().nth(1)?.split('-').next()?;
+                RustVersion::parse(release).ok()
+            })
+            .map_or("2021", |version| version.edition())
+    })
+}
+
+/// The manifest of a scaffolded project
+fn manifest(
+    name: &str,
+    edition: &str,
+    dependencies: &[(String, String)],
+) -> Result<String, McpError> {
+    Ok(format!(
+        "[package]\nname = \"{}\"\nversion = \"0.1.0\"\nedition = \"{}\"\n\n[dependencies]\n{}",
+        name,
+        edition,
+        dependency_lines(dependencies)?
+    ))
+}
+
+/// `name = "requirement"` lines for a `[dependencies]` table. Names are keys
+/// written as they are, so one that isn't a package name is refused rather
+/// than let it add to the manifest.
+pub(crate) fn dependency_lines(dependencies: &[(String, String)]) -> Result<String, McpError> {
+    let mut lines = String::new();
+    for (name, requirement) in dependencies {
+        if !is_package_name(name) {
+            return Err(McpError::invalid_params(
+                format!("invalid dependency name: {:?}", name),
+                None,
+            ));
+        }
+        lines.push_str(&format!("{} = {}\n", name, serde_json::json!(requirement)));
+    }
+    Ok(lines)
+}
+
+/// A name cargo accepts for a package: ASCII letters, digits, `-` and `_`,
+/// not starting with a digit
+fn is_package_name(name: &str) -> bool {
+    name.starts_with(|c: char| c.is_ascii_alphabetic() || c == '_')
+        && name
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_')
+}
+
+/// A freshly initialized `temp_project` binary crate in its own temp directory
+pub struct ScratchProject {
+    dir: TempDir,
+    manifest: String,
+}
+
+impl ScratchProject {
+    pub fn create(child_env: &ChildEnv) -> Result<Self, McpError> {
+        Self::init(child_env, "temp_project", false)
+    }
+
+    /// A project named `name`, a library crate with `lib`. The scaffold is
+    /// written directly rather than by `cargo init`, which refuses
+    /// directories that already hold files and creates a git repository.
+    pub fn init(child_env: &ChildEnv, name: &str, lib: bool) -> Result<Self, McpError> {
+        Self::scaffold(child_env, name, lib, None, &[])
+    }
+
+    /// Like `init`, with `edition` (the installed cargo's newest by default)
+    /// and `dependencies` written straight into the manifest
+    pub fn scaffold(
+        child_env: &ChildEnv,
+        name: &str,
+        lib: bool,
+        edition: Option<&str>,
+        dependencies: &[(String, String)],
+    ) -> Result<Self, McpError> {
+        if !is_package_name(name) {
+            return Err(McpError::invalid_params(
+                format!("invalid project name: {:?}", name),
+                None,
+            ));
+        }
+        if let Some(edition) = edition
+            && (edition.is_empty() || !edition.chars().all(|c| c.is_ascii_digit()))
+        {
+            return Err(McpError::invalid_params(
+                format!("invalid edition: {:?}", edition),
+                None,
+            ));
+        }
+        let edition = edition.unwrap_or_else(|| default_edition(child_env));
+        let manifest = manifest(name, edition, dependencies)?;
+        // Create a temporary directory for the Rust project
+        let dir = tempfile::tempdir().map_err(|e| {
+            McpError::internal_error(format!("Failed to create temp dir: {}", e), None)
+        })?;
+
+        let (root, source) = if lib {
+            ("lib.rs", "")
+        } else {
+            (
+                "main.rs",
+                "fn main() {\n    println!(\"Hello, world!\");\n}\n",
+            )
+        };
+        std::fs::create_dir(dir.path().join("src"))
+            .and_then(|()| std::fs::write(dir.path().join("Cargo.toml"), &manifest))
+            .and_then(|()| std::fs::write(dir.path().join("src").join(root), source))
+            .map_err(|e| {
+                McpError::internal_error(format!("Failed to write project scaffold: {}", e), None)
+            })?;
+
+        Ok(ScratchProject { dir, manifest })
+    }
+
+    /// An empty directory, for projects that bring their own manifest
+    pub fn empty() -> Result<Self, McpError> {
+        let dir = tempfile::tempdir().map_err(|e| {
+            McpError::internal_error(format!("Failed to create temp dir: {}", e), None)
+        })?;
+        Ok(ScratchProject {
+            dir,
+            manifest: String::new(),
+        })
+    }
+
+    pub fn path(&self) -> &Path {
+        self.dir.path()
+    }
+
+    /// Stop managing the directory so it outlives the project, returning
+    /// its path
+    pub fn keep(self) -> PathBuf {
+        self.dir.keep()
+    }
+
+    /// Restore the project to its freshly initialized state. Returns false if
+    /// the call left files we don't know how to clean up, in which case the
+    /// project should be discarded instead of reused.
+    fn reset(&self) -> bool {
+        let path = self.path();
+        let names = |dir: &Path| -> Option<Vec<String>> {
+            std::fs::read_dir(dir)
+                .ok()?
+                .map(|entry| {
+                    entry
+                        .ok()
+                        .map(|e| e.file_name().to_string_lossy().into_owned())
+                })
+                .collect()
+        };
+
+        let Some(top_level) = names(path) else {
+            return false;
+        };
+        if top_level
+            .iter()
+            .any(|name| !EXPECTED_ENTRIES.contains(&name.as_str()))
+        {
+            return false;
+        }
+        match names(&path.join("src")) {
+            Some(src) if src.iter().all(|name| name == "main.rs" || name == "lib.rs") => {}
+            _ => return false,
+        }
+
+        // no_std runs turn the project into a library
+        let _ = std::fs::remove_file(path.join("src").join("lib.rs"));
+        let _ = std::fs::remove_file(path.join("Cargo.lock"));
+        let _ = std::fs::remove_file(path.join("deny.toml"));
+        if path.join("target").exists() && std::fs::remove_dir_all(path.join("target")).is_err() {
+            return false;
+        }
+        std::fs::write(path.join("Cargo.toml"), &self.manifest).is_ok()
+    }
+}
+
+#[derive(Debug, Clone, serde::Serialize)]
+pub struct PoolStats {
+    pub size: usize,
+    pub idle: usize,
+    pub hits: u64,
+    pub misses: u64,
+    pub discarded: u64,
+}
+
+struct PoolInner {
+    size: usize,
+    /// Environment the scaffold's cargo version is read with
+    child_env: ChildEnv,
+    idle: Mutex<Vec<ScratchProject>>,
+    hits: AtomicU64,
+    misses: AtomicU64,
+    discarded: AtomicU64,
+    refill: Arc<Notify>,
+    refill_started: AtomicBool,
+    closed: AtomicBool,
+}
+
+impl Drop for PoolInner {
+    fn drop(&mut self) {
+        // Wake the refill task so it notices the pool is gone and exits
+        self.refill.notify_one();
+    }
+}
+
+/// Keeps up to `size` scratch projects ready. A background task tops the pool
+/// up after each checkout; a checkout that finds the pool empty creates a
+/// project inline and counts as a miss.
+#[derive(Clone)]
+pub struct ProjectPool {
+    inner: Arc<PoolInner>,
+}
+
+impl ProjectPool {
+    pub fn new(size: usize, child_env: ChildEnv) -> Self {
+        let pool = ProjectPool {
+            inner: Arc::new(PoolInner {
+                size,
+                child_env,
+                idle: Mutex::new(Vec::new()),
+                hits: AtomicU64::new(0),
+                misses: AtomicU64::new(0),
+                discarded: AtomicU64::new(0),
+                refill: Arc::new(Notify::new()),
+                refill_started: AtomicBool::new(false),
+                closed: AtomicBool::new(false),
+            }),
+        };
+        pool.ensure_refill();
+        pool
+    }
+
+    /// Take a project for exclusive use by one call. It is reset and returned
+    /// to the pool when the `PooledProject` is dropped.
+    pub fn checkout(&self) -> Result<PooledProject, McpError> {
+        self.ensure_refill();
+        let pooled = self.idle().pop();
+        let project = match pooled {
+            Some(project) => {
+                self.inner.hits.fetch_add(1, Ordering::Relaxed);
+                project
+            }
+            None => {
+                self.inner.misses.fetch_add(1, Ordering::Relaxed);
+                ScratchProject::create(&self.inner.child_env)?
+            }
+        };
+        self.inner.refill.notify_one();
+
+        Ok(PooledProject {
+            project: Some(project),
+            pool: self.clone(),
+        })
+    }
+
+    pub fn stats(&self) -> PoolStats {
+        PoolStats {
+            size: self.inner.size,
+            idle: self.idle().len(),
+            hits: self.inner.hits.load(Ordering::Relaxed),
+            misses: self.inner.misses.load(Ordering::Relaxed),
+            discarded: self.inner.discarded.load(Ordering::Relaxed),
+        }
+    }
+
+    /// Delete all idle projects and stop refilling. Checked-out projects are
+    /// deleted instead of returned once their calls finish.
+    pub fn shutdown(&self) {
+        self.inner.closed.store(true, Ordering::SeqCst);
+        self.idle().clear();
+        self.inner.refill.notify_one();
+    }
+
+    fn idle(&self) -> std::sync::MutexGuard<'_, Vec<ScratchProject>> {
+        // A panic while holding the lock can't leave the Vec inconsistent
+        self.inner
+            .idle
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+
+    /// Start the background refill task once a tokio runtime is available
+    fn ensure_refill(&self) {
+        if self.inner.size == 0 || self.inner.refill_started.swap(true, Ordering::SeqCst) {
+            return;
+        }
+        let Ok(handle) = tokio::runtime::Handle::try_current() else {
+            self.inner.refill_started.store(false, Ordering::SeqCst);
+            return;
+        };
+
+        let weak = Arc::downgrade(&self.inner);
+        let refill = Arc::clone(&self.inner.refill);
+        let child_env = self.inner.child_env.clone();
+        handle.spawn(async move {
+            loop {
+                let needed = match weak.upgrade() {
+                    Some(inner) if !inner.closed.load(Ordering::SeqCst) => {
+                        let idle = inner.idle.lock().map(|idle| idle.len()).unwrap_or(0);
+                        inner.size.saturating_sub(idle)
+                    }
+                    _ => break,
+                };
+
+                if needed == 0 {
+                    refill.notified().await;
+                    continue;
+                }
+
+                let env = child_env.clone();
+                match tokio::task::spawn_blocking(move || ScratchProject::create(&env)).await {
+                    Ok(Ok(project)) => {
+                        let Some(inner) = weak.upgrade() else { break };
+                        let pool = ProjectPool { inner };
+                        let mut idle = pool.idle();
+                        if !pool.inner.closed.load(Ordering::SeqCst) && idle.len() < pool.inner.size
+                        {
+                            idle.push(project);
+                        }
+                    }
+                    Ok(Err(e)) => {
+                        tracing::warn!("Failed to prepare pooled project: {}", e.message);
+                        // Don't spin on a broken toolchain; wait for the next checkout
+                        refill.notified().await;
+                    }
+                    Err(_) => break,
+                }
+            }
+        });
+    }
+
+    fn checkin(&self, project: ScratchProject) {
+        if self.inner.closed.load(Ordering::SeqCst) || !project.reset() {
+            // Counted once its directory is gone
+            drop(project);
+            self.inner.discarded.fetch_add(1, Ordering::Relaxed);
+            return;
+        }
+        let mut idle = self.idle();
+        if idle.len() < self.inner.size {
+            idle.push(project);
+        }
+    }
+}
+
+/// A project checked out of a `ProjectPool` for the duration of one call
+pub struct PooledProject {
+    project: Option<ScratchProject>,
+    pool: ProjectPool,
+}
+
+impl PooledProject {
+    pub fn path(&self) -> &Path {
+        self.project
+            .as_ref()
+            .map(ScratchProject::path)
+            .expect("project is present until drop")
+    }
+
+    /// Keep the project on disk for the caller to inspect instead of
+    /// returning it to the pool, returning its path
+    pub fn keep(mut self) -> PathBuf {
+        self.project
+            .take()
+            .map(ScratchProject::keep)
+            .expect("project is present until drop")
+    }
+}
+
+impl Drop for PooledProject {
+    fn drop(&mut self) {
+        let Some(project) = self.project.take() else {
+            return;
+        };
+        let pool = self.pool.clone();
+        // Resetting removes target/, which can be slow; keep it off the runtime
+        match tokio::runtime::Handle::try_current() {
+            Ok(handle) => {
+                handle.spawn_blocking(move || pool.checkin(project));
+            }
+            Err(_) => pool.checkin(project),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashSet;
+    use std::time::Duration;
+
+    async fn wait_for_idle(pool: &ProjectPool, idle: usize) {
+        for _ in 0..300 {
+            if pool.stats().idle >= idle {
+                return;
+            }
+            tokio::time::sleep(Duration::from_millis(50)).await;
+        }
+        panic!("pool never reached {} idle projects", idle);
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+    async fn concurrent_checkouts_get_distinct_projects() {
+        let pool = ProjectPool::new(2, ChildEnv::default());
+        wait_for_idle(&pool, 2).await;
+
+        let handles: Vec<_> = (0..4)
+            .map(|_| {
+                let pool = pool.clone();
+                tokio::task::spawn_blocking(move || pool.checkout().unwrap())
+            })
+            .collect();
+        let mut projects = Vec::new();
+        for handle in handles {
+            projects.push(handle.await.unwrap());
+        }
+
+        let paths: HashSet<_> = projects.iter().map(|p| p.path().to_path_buf()).collect();
+        assert_eq!(paths.len(), 4);
+        let stats = pool.stats();
+        assert_eq!(stats.hits + stats.misses, 4);
+        assert!(stats.hits >= 1);
+    }
+
+    #[test]
+    fn reset_restores_fresh_project() {
+        let project = ScratchProject::create(&ChildEnv::default()).unwrap();
+        let path = project.path();
+        std::fs::write(path.join("src").join("main.rs"), "fn main() {}").unwrap();
+        std::fs::create_dir_all(path.join("target").join("debug")).unwrap();
+        std::fs::write(path.join("Cargo.lock"), "").unwrap();
+        std::fs::write(path.join("Cargo.toml"), "[package]\nname = \"changed\"\n").unwrap();
+
+        assert!(project.reset());
+        assert!(!path.join("target").exists());
+        assert!(!path.join("Cargo.lock").exists());
+        let manifest = std::fs::read_to_string(path.join("Cargo.toml")).unwrap();
+        assert!(manifest.contains("temp_project"));
+
+        std::fs::write(path.join("src").join("lib.rs"), "#![no_std]").unwrap();
+        assert!(project.reset());
+        assert!(!path.join("src").join("lib.rs").exists());
+
+        std::fs::write(path.join("src").join("build.rs"), "").unwrap();
+        assert!(!project.reset());
+    }
+
+    #[test]
+    fn scaffold_is_written_without_cargo_init() {
+        let env = ChildEnv::default();
+        let project = ScratchProject::init(&env, "my-tool", false).unwrap();
+        let path = project.path();
+        let manifest = std::fs::read_to_string(path.join("Cargo.toml")).unwrap();
+        assert!(manifest.contains("name = \"my-tool\""));
+        assert!(manifest.contains(&format!("edition = \"{}\"", default_edition(&env))));
+        assert!(path.join("src").join("main.rs").exists());
+        assert!(!path.join(".git").exists());
+
+        let lib = ScratchProject::init(&env, "helper", true).unwrap();
+        assert!(lib.path().join("src").join("lib.rs").exists());
+        assert!(!lib.path().join("src").join("main.rs").exists());
+
+        let dependencies = [("serde".to_string(), "1.0".to_string())];
+        let scaffolded =
+            ScratchProject::scaffold(&env, "app", false, Some("2021"), &dependencies).unwrap();
+        let manifest = std::fs::read_to_string(scaffolded.path().join("Cargo.toml")).unwrap();
+        assert!(manifest.contains("edition = \"2021\""));
+        assert!(manifest.ends_with("[dependencies]\nserde = \"1.0\"\n"));
+
+        for name in ["", "1st", "has space", "a/b"] {
+            assert!(
+                ScratchProject::init(&env, name, false).is_err(),
+                "{:?}",
+                name
+            );
+        }
+        let injected = [(
+            "serde = \"1\"\n[build-dependencies]\nevil".to_string(),
+            "1.0".to_string(),
+        )];
+        assert!(ScratchProject::scaffold(&env, "app", false, None, &injected).is_err());
+    }
+
+    #[tokio::test]
+    async fn project_with_unexpected_files_is_discarded() {
+        let pool = ProjectPool::new(1, ChildEnv::default());
+        wait_for_idle(&pool, 1).await;
+
+        let project = pool.checkout().unwrap();
+        let path = project.path().to_path_buf();
+        std::fs::write(path.join("build.rs"), "fn main() {}").unwrap();
+        drop(project);
+
+        for _ in 0..100 {
+            if pool.stats().discarded == 1 {
+                break;
+            }
+            tokio::time::sleep(Duration::from_millis(20)).await;
+        }
+        assert_eq!(pool.stats().discarded, 1);
+        assert!(!path.exists());
+    }
+
+    #[tokio::test]
+    async fn kept_project_outlives_pool() {
+        let pool = ProjectPool::new(0, ChildEnv::default());
+        let path = pool.checkout().unwrap().keep();
+        pool.shutdown();
+        assert!(path.join("Cargo.toml").exists());
+        assert_eq!(pool.stats().discarded, 0);
+        std::fs::remove_dir_all(path).unwrap();
+    }
+
+    #[tokio::test]
+    async fn shutdown_removes_idle_projects() {
+        let pool = ProjectPool::new(2, ChildEnv::default());
+        wait_for_idle(&pool, 2).await;
+        let checked_out = pool.checkout().unwrap();
+        let path = checked_out.path().to_path_buf();
+
+        pool.shutdown();
+        assert_eq!(pool.stats().idle, 0);
+
+        drop(checked_out);
+        for _ in 0..100 {
+            if !path.exists() {
+                break;
+            }
+            tokio::time::sleep(Duration::from_millis(20)).await;
+        }
+        assert!(!path.exists());
+    }
+}
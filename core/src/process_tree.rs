@@ -0,0 +1,122 @@
+//! Killing a spawned cargo or rust-analyzer together with the compilers,
+//! build scripts and test binaries it started, which would otherwise keep
+//! running after a timeout or cancellation. On Unix the child leads its own
+//! process group and the group is killed. On Windows the child is put in a
+//! job object, which every process it starts afterwards joins too; the job
+//! is terminated to kill them, and kills whatever is left when it's closed.
+
+use tokio::process::{Child, Command};
+
+/// What it takes to kill a child's whole process tree
+#[derive(Debug, Default)]
+pub struct ProcessTree {
+    /// `None` when Windows refused the job; only the child itself can be
+    /// killed then
+    #[cfg(windows)]
+    job: Option<job::Job>,
+}
+
+impl ProcessTree {
+    /// Make the child `cmd` spawns the root of a tree that can be killed as
+    /// one: on Unix, the leader of a new process group
+    pub fn prepare(cmd: &mut Command) {
+        #[cfg(unix)]
+        cmd.process_group(0);
+        #[cfg(not(unix))]
+        let _ = cmd;
+    }
+
+    /// Track the tree under `child`, spawned from a prepared command. On
+    /// Windows, processes the child starts before it joins the job aren't
+    /// in it; cargo starts nothing that early.
+    pub fn attach(child: &Child) -> Self {
+        #[cfg(not(windows))]
+        let _ = child;
+        ProcessTree {
+            #[cfg(windows)]
+            job: child.raw_handle().and_then(job::Job::containing),
+        }
+    }
+
+    /// Kill every process in the tree. The child still has to be killed and
+    /// reaped through tokio, which also covers a refused job.
+    pub fn kill(&self, child: &Child) {
+        #[cfg(unix)]
+        if let Some(pid) = child.id() {
+            // SAFETY: kill(2) on our child's process group has no
+            // memory-safety preconditions
+            unsafe {
+                libc::kill(-(pid as libc::pid_t), libc::SIGKILL);
+            }
+        }
+        #[cfg(not(unix))]
+        let _ = child;
+        #[cfg(windows)]
+        if let Some(job) = &self.job {
+            job.terminate();
+        }
+    }
+}
+
+#[cfg(windows)]
+mod job {
+    use std::os::windows::io::RawHandle;
+    use windows_sys::Win32::Foundation::{CloseHandle, HANDLE};
+    use windows_sys::Win32::System::JobObjects::{
+        AssignProcessToJobObject, CreateJobObjectW, JOB_OBJECT_LIMIT_KILL_ON_JOB_CLOSE,
+        JOBOBJECT_EXTENDED_LIMIT_INFORMATION, JobObjectExtendedLimitInformation,
+        SetInformationJobObject, TerminateJobObject,
+    };
+
+    /// An anonymous job object that kills its processes when closed
+    #[derive(Debug)]
+    pub struct Job(HANDLE);
+
+    // SAFETY: job handles may be used and closed from any thread
+    unsafe impl Send for Job {}
+    unsafe impl Sync for Job {}
+
+    impl Job {
+        /// A new job holding `process`, or `None` if Windows refuses one
+        pub fn containing(process: RawHandle) -> Option<Job> {
+            // SAFETY: null attributes and name create an unnamed job with
+            // default security
+            let handle = unsafe { CreateJobObjectW(std::ptr::null(), std::ptr::null()) };
+            if handle.is_null() {
+                return None;
+            }
+            let job = Job(handle);
+            // SAFETY: the struct is plain integers, for which zero is valid
+            let mut limits: JOBOBJECT_EXTENDED_LIMIT_INFORMATION = unsafe { std::mem::zeroed() };
+            limits.BasicLimitInformation.LimitFlags = JOB_OBJECT_LIMIT_KILL_ON_JOB_CLOSE;
+            // SAFETY: `limits` outlives the call and the length is its size;
+            // `process` is the child's handle, which tokio keeps open
+            let joined = unsafe {
+                SetInformationJobObject(
+                    job.0,
+                    JobObjectExtendedLimitInformation,
+                    (&raw const limits).cast(),
+                    std::mem::size_of_val(&limits) as u32,
+                ) != 0
+                    && AssignProcessToJobObject(job.0, process) != 0
+            };
+            joined.then_some(job)
+        }
+
+        pub fn terminate(&self) {
+            // SAFETY: the handle stays open until the job is dropped
+            unsafe {
+                TerminateJobObject(self.0, 1);
+            }
+        }
+    }
+
+    impl Drop for Job {
+        fn drop(&mut self) {
+            // SAFETY: the handle is open and only closed here
+            unsafe {
+                CloseHandle(self.0);
+            }
+        }
+    }
+}
@@ -0,0 +1,195 @@
+//! MCP progress notifications for cargo runs. When a `tools/call` request
+//! carries a progress token, each line cargo writes is forwarded to the client
+//! as it arrives so long builds and test runs show live output. A call that
+//! streams diagnostics gets each compiler message as its structured
+//! diagnostic instead of cargo's raw JSON.
+
+use crate::diagnostics;
+use rmcp::model::{ProgressNotificationParam, ProgressToken};
+use rmcp::service::{Peer, RequestContext, RoleServer};
+use std::future::Future;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicU64, Ordering};
+use tokio::io::{AsyncBufReadExt, AsyncRead, BufReader};
+
+tokio::task_local! {
+    /// Progress sink for the tool call running on this task
+    static PROGRESS: Option<Progress>;
+}
+
+/// Where to report a call's progress
+#[derive(Clone)]
+pub struct Progress {
+    peer: Peer<RoleServer>,
+    token: ProgressToken,
+    /// Lines reported so far, shared by the stdout and stderr readers
+    lines: Arc<AtomicU64>,
+    /// Report stdout's compiler messages as structured diagnostics
+    diagnostics: bool,
+}
+
+/// Which of cargo's pipes a line came from
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Pipe {
+    Stdout,
+    Stderr,
+}
+
+impl Progress {
+    /// A sink for the request's progress token, if the client sent one
+    pub fn from_context(context: &RequestContext<RoleServer>) -> Option<Self> {
+        let token = context.meta.get_progress_token()?;
+        Some(Progress {
+            peer: context.peer.clone(),
+            token,
+            lines: Arc::default(),
+            diagnostics: false,
+        })
+    }
+
+    /// The same sink, reporting `{"diagnostic": ...}` for each compiler
+    /// message on stdout and skipping cargo's other JSON messages
+    pub fn streaming_diagnostics(self) -> Self {
+        Progress {
+            diagnostics: true,
+            ..self
+        }
+    }
+
+    pub(crate) async fn report(&self, line: &str) {
+        let progress = self.lines.fetch_add(1, Ordering::Relaxed) + 1;
+        let param = ProgressNotificationParam {
+            progress_token: self.token.clone(),
+            progress: progress as f64,
+            total: None,
+            message: Some(line.to_string()),
+        };
+        if let Err(e) = self.peer.notify_progress(param).await {
+            tracing::warn!("Failed to send progress: {}", e);
+        }
+    }
+}
+
+/// Run `future` with `progress` as the sink `current` returns
+pub fn scope<F: Future>(progress: Option<Progress>, future: F) -> impl Future<Output = F::Output> {
+    PROGRESS.scope(progress, future)
+}
+
+/// The sink of the tool call running on this task. Spawned tasks don't
+/// inherit it, so read it before spawning.
+pub fn current() -> Option<Progress> {
+    PROGRESS.try_with(Clone::clone).ok().flatten()
+}
+
+/// Read a pipe to the end, reporting each line as it arrives. The bytes are
+/// returned unchanged, including a final line without a newline.
+pub async fn read_lines(
+    reader: impl AsyncRead + Unpin,
+    progress: Option<Progress>,
+    pipe: Pipe,
+) -> Vec<u8> {
+    for_each_line(reader, |line| {
+        let progress = progress.clone();
+        async move {
+            if let Some(progress) = progress
+                && let Some(message) = line_message(&line, pipe, progress.diagnostics)
+            {
+                progress.report(&message).await;
+            }
+        }
+    })
+    .await
+}
+
+/// Read a pipe to the end, awaiting `on_line` for each line before reading
+/// the next, so lines are handled in order and none are skipped however
+/// fast the pipe fills
+async fn for_each_line<F, Fut>(reader: impl AsyncRead + Unpin, mut on_line: F) -> Vec<u8>
+where
+    F: FnMut(String) -> Fut,
+    Fut: Future<Output = ()>,
+{
+    let mut reader = BufReader::new(reader);
+    let mut buf = Vec::new();
+    loop {
+        let start = buf.len();
+        match reader.read_until(b'\n', &mut buf).await {
+            Ok(0) | Err(_) => break,
+            Ok(_) => {
+                let line = String::from_utf8_lossy(&buf[start..]);
+                on_line(line.trim_end().to_string()).await;
+            }
+        }
+    }
+    buf
+}
+
+/// What to report for a line, if anything
+fn line_message(line: &str, pipe: Pipe, diagnostics: bool) -> Option<String> {
+    if !diagnostics || pipe == Pipe::Stderr {
+        return Some(line.to_string());
+    }
+    diagnostics::from_line(line)
+        .map(|diagnostic| serde_json::json!({ "diagnostic": diagnostic }).to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn lines_are_read_unchanged() {
+        let output: &[u8] = b"   Compiling temp_project\n\xffwarning\nno newline";
+        assert_eq!(read_lines(output, None, Pipe::Stderr).await, output);
+        assert!(current().is_none());
+        assert!(scope(None, async { current().is_none() }).await);
+    }
+
+    #[tokio::test]
+    async fn streamed_diagnostics_keep_order_under_a_fast_producer() {
+        const MESSAGES: usize = 5000;
+        let (mut writer, reader) = tokio::io::duplex(256);
+        let producer = tokio::spawn(async move {
+            use tokio::io::AsyncWriteExt;
+            for i in 0..MESSAGES {
+                let line = format!(
+                    r#"{{"reason":"compiler-message","message":{{"rendered":"error: e{i}\n","children":[],"level":"error","message":"e{i}","spans":[],"code":null}}}}"#
+                );
+                writer.write_all(line.as_bytes()).await.unwrap();
+                writer.write_all(b"\n").await.unwrap();
+                writer
+                    .write_all(b"{\"reason\":\"compiler-artifact\"}\n")
+                    .await
+                    .unwrap();
+            }
+        });
+        let reported = std::sync::Mutex::new(Vec::new());
+        let output = for_each_line(reader, |line| {
+            if let Some(message) = line_message(&line, Pipe::Stdout, true) {
+                reported.lock().unwrap().push(message);
+            }
+            async {}
+        })
+        .await;
+        producer.await.unwrap();
+
+        let reported = reported.into_inner().unwrap();
+        assert_eq!(reported.len(), MESSAGES);
+        for (i, message) in reported.iter().enumerate() {
+            let message: serde_json::Value = serde_json::from_str(message).unwrap();
+            assert_eq!(message["diagnostic"]["message"], format!("e{}", i));
+        }
+        // The aggregate sees the same messages
+        let stdout = String::from_utf8(output).unwrap();
+        assert_eq!(diagnostics::from_json(&stdout).len(), MESSAGES);
+
+        assert_eq!(
+            line_message("   Compiling temp_project", Pipe::Stderr, true).as_deref(),
+            Some("   Compiling temp_project")
+        );
+        assert_eq!(
+            line_message("{\"reason\":\"build-finished\"}", Pipe::Stdout, false).as_deref(),
+            Some("{\"reason\":\"build-finished\"}")
+        );
+    }
+}
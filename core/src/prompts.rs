@@ -0,0 +1,191 @@
+//! Canned debugging workflows served through MCP `prompts/list` and
+//! `prompts/get`. Each prompt renders to a single user message that walks the
+//! model through the server's tools with the caller's code filled in.
+
+use rmcp::ErrorData as McpError;
+use rmcp::model::{
+    GetPromptResult, JsonObject, Prompt, PromptArgument, PromptMessage, PromptMessageRole,
+};
+
+struct PromptSpec {
+    name: &'static str,
+    description: &'static str,
+    /// Argument name, description and whether it is required
+    arguments: &'static [(&'static str, &'static str, bool)],
+}
+
+const CODE_ARGUMENT: (&str, &str, bool) = ("code", "Rust code to work on", true);
+
+const PROMPTS: &[PromptSpec] = &[
+    PromptSpec {
+        name: "diagnose_error",
+        description: "Reproduce a compiler error with cargo_check and explain it with rustc_explain",
+        arguments: &[
+            CODE_ARGUMENT,
+            (
+                "error_output",
+                "Compiler output the user saw (optional)",
+                false,
+            ),
+        ],
+    },
+    PromptSpec {
+        name: "review_clippy",
+        description: "Run cargo_clippy and summarize the lints by category",
+        arguments: &[CODE_ARGUMENT],
+    },
+    PromptSpec {
+        name: "fix_and_verify",
+        description: "Apply cargo_fix suggestions, then confirm the result with cargo_test",
+        arguments: &[CODE_ARGUMENT],
+    },
+];
+
+/// Every prompt with its declared arguments
+pub fn list() -> Vec<Prompt> {
+    PROMPTS
+        .iter()
+        .map(|spec| {
+            let arguments = spec
+                .arguments
+                .iter()
+                .map(|(name, description, required)| PromptArgument {
+                    name: name.to_string(),
+                    title: None,
+                    description: Some(description.to_string()),
+                    required: Some(*required),
+                })
+                .collect();
+            Prompt::new(spec.name, Some(spec.description), Some(arguments))
+        })
+        .collect()
+}
+
+/// Render a prompt with its arguments interpolated
+pub fn render(name: &str, arguments: Option<&JsonObject>) -> Result<GetPromptResult, McpError> {
+    let spec = PROMPTS
+        .iter()
+        .find(|spec| spec.name == name)
+        .ok_or_else(|| McpError::invalid_params(format!("unknown prompt: {}", name), None))?;
+    let argument = |key: &str| -> Result<Option<&str>, McpError> {
+        match arguments.and_then(|args| args.get(key)) {
+            None => Ok(None),
+            Some(value) => value.as_str().map(Some).ok_or_else(|| {
+                McpError::invalid_params(format!("prompt argument {} must be a string", key), None)
+            }),
+        }
+    };
+    for (key, _, required) in spec.arguments {
+        if *required && argument(key)?.is_none_or(|value| value.trim().is_empty()) {
+            return Err(McpError::invalid_params(
+                format!("prompt {} requires the {} argument", name, key),
+                None,
+            ));
+        }
+    }
+    let code = argument("code")?.unwrap_or_default();
+
+    let text = match name {
+        "diagnose_error" => {
+            let reported = match argument("error_output")? {
+                Some(output) if !output.trim().is_empty() => format!(
+                    "\n\nThe user reported this compiler output:\n\n```text\n{}\n```",
+                    output.trim_end()
+                ),
+                _ => String::new(),
+            };
+            format!(
+                "Diagnose the compiler error in this Rust code.\n\n```rust\n{}\n```{}\n\n\
+                 1. Run the cargo_check tool on the code to reproduce the error.\n\
+                 2. For each error code in the output (e.g. E0308), run rustc_explain.\n\
+                 3. Explain the cause in plain terms, point at the offending lines and \
+                 propose a corrected version of the code.",
+                code.trim_end(),
+                reported
+            )
+        }
+        "review_clippy" => format!(
+            "Review this Rust code with clippy.\n\n```rust\n{}\n```\n\n\
+             1. Run the cargo_clippy tool on the code.\n\
+             2. Group the reported lints by category (correctness, suspicious, style, \
+             complexity, perf, pedantic) and count each group.\n\
+             3. For every lint, quote the line it points at and describe the idiomatic fix, \
+             starting with correctness and suspicious lints.",
+            code.trim_end()
+        ),
+        "fix_and_verify" => format!(
+            "Fix the warnings in this Rust code and verify the result.\n\n```rust\n{}\n```\n\n\
+             1. Run the cargo_fix tool on the code and apply the suggested changes.\n\
+             2. Run cargo_test on the fixed code.\n\
+             3. Report the changes cargo_fix made and whether the tests pass; if they fail, \
+             explain which fix broke them.",
+            code.trim_end()
+        ),
+        _ => unreachable!("every prompt in PROMPTS is rendered"),
+    };
+
+    Ok(GetPromptResult {
+        description: Some(spec.description.to_string()),
+        messages: vec![PromptMessage::new_text(PromptMessageRole::User, text)],
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rmcp::model::PromptMessageContent;
+    use serde_json::json;
+
+    fn rendered_text(name: &str, arguments: serde_json::Value) -> String {
+        let arguments = arguments.as_object().cloned();
+        let result = render(name, arguments.as_ref()).unwrap();
+        assert_eq!(result.messages.len(), 1);
+        assert_eq!(result.messages[0].role, PromptMessageRole::User);
+        match &result.messages[0].content {
+            PromptMessageContent::Text { text } => text.clone(),
+            other => panic!("unexpected content: {:?}", other),
+        }
+    }
+
+    #[test]
+    fn prompts_declare_their_arguments() {
+        let prompts = list();
+        let names: Vec<&str> = prompts.iter().map(|p| p.name.as_str()).collect();
+        assert_eq!(names, ["diagnose_error", "review_clippy", "fix_and_verify"]);
+        let arguments = prompts[0].arguments.as_ref().unwrap();
+        assert_eq!(arguments[0].name, "code");
+        assert_eq!(arguments[0].required, Some(true));
+        assert_eq!(arguments[1].name, "error_output");
+        assert_eq!(arguments[1].required, Some(false));
+    }
+
+    #[test]
+    fn prompts_interpolate_arguments() {
+        let code = "fn main() { let x: i32 = \"a\"; }";
+        let text = rendered_text(
+            "diagnose_error",
+            json!({"code": code, "error_output": "error[E0308]: mismatched types"}),
+        );
+        assert!(text.contains(&format!("```rust\n{}\n```", code)));
+        assert!(text.contains("error[E0308]: mismatched types"));
+        assert!(text.contains("cargo_check"));
+        assert!(text.contains("rustc_explain"));
+        assert!(!rendered_text("diagnose_error", json!({"code": code})).contains("reported"));
+
+        let text = rendered_text("review_clippy", json!({"code": code}));
+        assert!(text.contains(code));
+        assert!(text.contains("cargo_clippy"));
+        assert!(text.contains("category"));
+
+        let text = rendered_text("fix_and_verify", json!({"code": code}));
+        assert!(text.contains(code));
+        assert!(text.find("cargo_fix").unwrap() < text.find("cargo_test").unwrap());
+    }
+
+    #[test]
+    fn missing_or_unknown_prompts_are_rejected() {
+        assert!(render("review_clippy", None).is_err());
+        assert!(render("review_clippy", json!({"code": 1}).as_object()).is_err());
+        assert!(render("nope", json!({"code": "fn main() {}"}).as_object()).is_err());
+    }
+}
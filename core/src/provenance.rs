@@ -0,0 +1,347 @@
+//! Where each dependency comes from, for `cargo_deps_report`, read from the
+//! `cargo metadata --format-version 1` document.
+
+use serde_json::Value;
+use std::collections::BTreeMap;
+
+/// The registry crates.io packages resolve from, git or sparse protocol
+const CRATES_IO_SOURCES: &[&str] = &[
+    "registry+https://github.com/rust-lang/crates.io-index",
+    "sparse+https://index.crates.io/",
+];
+
+/// One package in the resolve graph
+#[derive(Debug, Clone, PartialEq, serde::Serialize)]
+pub struct PackageSource {
+    pub name: String,
+    pub version: String,
+    /// "crates.io", "registry" (any other), "git" or "path"
+    pub source: &'static str,
+    /// Index URL of other registries, repository URL of git sources, and
+    /// directory of path sources
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub url: Option<String>,
+    /// Commit a git source is locked to
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub rev: Option<String>,
+    /// What the manifest asked a git source for, e.g. "branch=main",
+    /// "tag=v1.0" or "rev=4f1c2e0"; None means the default branch
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub reference: Option<String>,
+    /// SPDX expression from the manifest; None when it only names a
+    /// license file or declares nothing
+    pub license: Option<String>,
+    pub repository: Option<String>,
+    /// Runs at compile time inside the compiler
+    pub proc_macro: bool,
+    /// Has a build.rs, which runs arbitrary code at build time
+    pub build_script: bool,
+    /// A git source following a branch, so its commit changes whenever the
+    /// lockfile is regenerated
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    pub branch_pinned: bool,
+}
+
+/// A dependency that runs its own code while the project builds
+#[derive(Debug, Clone, PartialEq, serde::Serialize)]
+pub struct BuildCode {
+    pub name: String,
+    pub version: String,
+    pub source: &'static str,
+    pub build_script: bool,
+    pub proc_macro: bool,
+}
+
+#[derive(Debug, Clone, PartialEq, serde::Serialize)]
+pub struct SourceTotals {
+    pub packages: usize,
+    /// Package count per `source` kind
+    pub by_source: BTreeMap<&'static str, usize>,
+    pub build_scripts: usize,
+    pub proc_macros: usize,
+    pub unlicensed: usize,
+    /// `name version` of git sources following a branch
+    pub branch_pinned: Vec<String>,
+}
+
+#[derive(Debug, Clone, PartialEq, serde::Serialize)]
+pub struct DepsReport {
+    /// Sorted by name, then version
+    pub packages: Vec<PackageSource>,
+    pub totals: SourceTotals,
+}
+
+impl DepsReport {
+    /// Classify every package in the resolve graph except the workspace's
+    /// own members
+    pub fn from_metadata(stdout: &str) -> Option<Self> {
+        let metadata: Value = serde_json::from_str(stdout).ok()?;
+        let members: Vec<&str> = metadata
+            .get("workspace_members")?
+            .as_array()?
+            .iter()
+            .filter_map(|id| id.as_str())
+            .collect();
+        let resolved: Vec<&str> = match metadata["resolve"]["nodes"].as_array() {
+            Some(nodes) => nodes.iter().filter_map(|n| n["id"].as_str()).collect(),
+            // --no-deps leaves out the resolve graph
+            None => Vec::new(),
+        };
+        let mut packages: Vec<PackageSource> = metadata
+            .get("packages")?
+            .as_array()?
+            .iter()
+            .filter(|package| {
+                package["id"]
+                    .as_str()
+                    .is_some_and(|id| resolved.contains(&id) && !members.contains(&id))
+            })
+            .filter_map(package_source)
+            .collect();
+        packages.sort_by(|a, b| (&a.name, &a.version).cmp(&(&b.name, &b.version)));
+
+        let mut by_source = BTreeMap::new();
+        for package in &packages {
+            *by_source.entry(package.source).or_insert(0) += 1;
+        }
+        let totals = SourceTotals {
+            packages: packages.len(),
+            by_source,
+            build_scripts: packages.iter().filter(|p| p.build_script).count(),
+            proc_macros: packages.iter().filter(|p| p.proc_macro).count(),
+            unlicensed: packages.iter().filter(|p| p.license.is_none()).count(),
+            branch_pinned: packages
+                .iter()
+                .filter(|p| p.branch_pinned)
+                .map(|p| format!("{} {}", p.name, p.version))
+                .collect(),
+        };
+        Some(DepsReport { packages, totals })
+    }
+
+    /// Packages with a build script or that are proc-macros
+    pub fn build_code(&self) -> Vec<BuildCode> {
+        self.packages.iter().filter_map(build_code).collect()
+    }
+}
+
+/// Workspace members with a build script or that are proc-macros: for a
+/// `crate_source` run the member is the published crate, whose own build
+/// code runs as much as its dependencies'
+pub fn member_build_code(stdout: &str) -> Vec<BuildCode> {
+    let Ok(metadata) = serde_json::from_str::<Value>(stdout) else {
+        return Vec::new();
+    };
+    let members: Vec<&str> = metadata["workspace_members"]
+        .as_array()
+        .into_iter()
+        .flatten()
+        .filter_map(|id| id.as_str())
+        .collect();
+    metadata["packages"]
+        .as_array()
+        .into_iter()
+        .flatten()
+        .filter(|package| {
+            package["id"]
+                .as_str()
+                .is_some_and(|id| members.contains(&id))
+        })
+        .filter_map(package_source)
+        .filter_map(|package| build_code(&package))
+        .collect()
+}
+
+fn build_code(package: &PackageSource) -> Option<BuildCode> {
+    (package.build_script || package.proc_macro).then(|| BuildCode {
+        name: package.name.clone(),
+        version: package.version.clone(),
+        source: package.source,
+        build_script: package.build_script,
+        proc_macro: package.proc_macro,
+    })
+}
+
+fn package_source(package: &Value) -> Option<PackageSource> {
+    let text = |key: &str| package[key].as_str().map(str::to_string);
+    let has_target_kind = |kind: &str| {
+        package["targets"].as_array().is_some_and(|targets| {
+            targets.iter().any(|target| {
+                target["kind"]
+                    .as_array()
+                    .is_some_and(|kinds| kinds.iter().any(|k| k == kind))
+            })
+        })
+    };
+    let mut source = PackageSource {
+        name: text("name")?,
+        version: text("version")?,
+        source: "path",
+        url: None,
+        rev: None,
+        reference: None,
+        license: text("license"),
+        repository: text("repository"),
+        proc_macro: has_target_kind("proc-macro"),
+        build_script: has_target_kind("custom-build"),
+        branch_pinned: false,
+    };
+    match package["source"].as_str() {
+        None => {
+            source.url = package["manifest_path"]
+                .as_str()
+                .and_then(|manifest| manifest.strip_suffix("Cargo.toml"))
+                .map(|dir| dir.trim_end_matches('/').to_string());
+        }
+        Some(registry) if CRATES_IO_SOURCES.contains(&registry) => source.source = "crates.io",
+        Some(git) if git.starts_with("git+") => {
+            // git+<url>[?branch=|tag=|rev=<name>]#<commit>
+            let (location, rev) = git["git+".len()..]
+                .split_once('#')
+                .unwrap_or((&git["git+".len()..], ""));
+            let (url, reference) = match location.split_once('?') {
+                Some((url, query)) => (url, Some(query.to_string())),
+                None => (location, None),
+            };
+            source.source = "git";
+            source.url = Some(url.to_string());
+            source.rev = (!rev.is_empty()).then(|| rev.to_string());
+            source.branch_pinned = reference
+                .as_deref()
+                .is_none_or(|query| query.starts_with("branch="));
+            source.reference = reference;
+        }
+        Some(registry) => {
+            source.source = "registry";
+            source.url = Some(
+                registry
+                    .split_once('+')
+                    .map_or(registry, |(_, url)| url)
+                    .to_string(),
+            );
+        }
+    }
+    Some(source)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn member_build_code_covers_the_workspaces_own_packages() {
+        let metadata = json!({
+            "workspace_members": ["published", "plain"],
+            "packages": [
+                {"id": "published", "name": "published", "version": "1.2.3", "source": null,
+                 "targets": [{"kind": ["lib"]}, {"kind": ["custom-build"]}]},
+                {"id": "plain", "name": "plain", "version": "0.1.0", "source": null,
+                 "targets": [{"kind": ["lib"]}]},
+                {"id": "derive", "name": "derive", "version": "1.0.0",
+                 "source": "sparse+https://index.crates.io/",
+                 "targets": [{"kind": ["proc-macro"]}]},
+            ]
+        });
+        let build_code = member_build_code(&metadata.to_string());
+        assert_eq!(
+            build_code,
+            [BuildCode {
+                name: "published".to_string(),
+                version: "1.2.3".to_string(),
+                source: "path",
+                build_script: true,
+                proc_macro: false,
+            }]
+        );
+        assert!(member_build_code("not json").is_empty());
+    }
+
+    #[test]
+    fn packages_are_classified_by_source_and_risk() {
+        let lib = json!([{"kind": ["lib"]}]);
+        let metadata = json!({
+            "workspace_members": ["path+file:///tmp/app#0.1.0"],
+            "resolve": {"nodes": [
+                {"id": "path+file:///tmp/app#0.1.0"},
+                {"id": "serde_derive"},
+                {"id": "libc"},
+                {"id": "tool"},
+                {"id": "pinned"},
+                {"id": "helper"},
+                {"id": "internal"},
+            ]},
+            "packages": [
+                {"id": "path+file:///tmp/app#0.1.0", "name": "app", "version": "0.1.0",
+                 "source": null, "license": null, "repository": null,
+                 "manifest_path": "/tmp/app/Cargo.toml", "targets": lib},
+                {"id": "serde_derive", "name": "serde_derive", "version": "1.0.210",
+                 "source": "registry+https://github.com/rust-lang/crates.io-index",
+                 "license": "MIT OR Apache-2.0", "repository": "https://github.com/serde-rs/serde",
+                 "targets": [{"kind": ["proc-macro"]}]},
+                {"id": "libc", "name": "libc", "version": "0.2.158",
+                 "source": "sparse+https://index.crates.io/", "license": "MIT OR Apache-2.0",
+                 "repository": "https://github.com/rust-lang/libc",
+                 "targets": [{"kind": ["lib"]}, {"kind": ["custom-build"]}]},
+                {"id": "tool", "name": "tool", "version": "0.3.0",
+                 "source": "git+https://github.com/a/tool?branch=main#1234abcd",
+                 "license": "MIT", "repository": null, "targets": lib},
+                {"id": "pinned", "name": "pinned", "version": "1.0.0",
+                 "source": "git+https://github.com/a/pinned?rev=5678ef01#5678ef01aaaa",
+                 "license": "MIT", "repository": null, "targets": lib},
+                {"id": "helper", "name": "helper", "version": "0.1.0", "source": null,
+                 "license": null, "repository": null,
+                 "manifest_path": "/tmp/helper/Cargo.toml", "targets": lib},
+                {"id": "internal", "name": "internal", "version": "2.0.0",
+                 "source": "sparse+https://crates.example.com/index/",
+                 "license": "Apache-2.0", "repository": null, "targets": lib},
+                // Not in the resolve graph, e.g. an unused optional dependency
+                {"id": "unused", "name": "unused", "version": "1.0.0",
+                 "source": "sparse+https://index.crates.io/", "license": "MIT",
+                 "repository": null, "targets": lib},
+            ]
+        });
+        let report = DepsReport::from_metadata(&metadata.to_string()).unwrap();
+        let names: Vec<&str> = report.packages.iter().map(|p| p.name.as_str()).collect();
+        assert_eq!(
+            names,
+            [
+                "helper",
+                "internal",
+                "libc",
+                "pinned",
+                "serde_derive",
+                "tool"
+            ]
+        );
+
+        let find = |name: &str| report.packages.iter().find(|p| p.name == name).unwrap();
+        let serde_derive = find("serde_derive");
+        assert_eq!(serde_derive.source, "crates.io");
+        assert!(serde_derive.proc_macro && !serde_derive.build_script);
+        assert!(find("libc").build_script);
+        let tool = find("tool");
+        assert_eq!(tool.source, "git");
+        assert_eq!(tool.url.as_deref(), Some("https://github.com/a/tool"));
+        assert_eq!(tool.rev.as_deref(), Some("1234abcd"));
+        assert_eq!(tool.reference.as_deref(), Some("branch=main"));
+        assert!(tool.branch_pinned);
+        assert!(!find("pinned").branch_pinned);
+        assert_eq!(find("helper").url.as_deref(), Some("/tmp/helper"));
+        assert_eq!(
+            find("internal").url.as_deref(),
+            Some("https://crates.example.com/index/")
+        );
+
+        let totals = &report.totals;
+        assert_eq!(totals.packages, 6);
+        assert_eq!(
+            totals.by_source,
+            BTreeMap::from([("crates.io", 2), ("git", 2), ("path", 1), ("registry", 1)])
+        );
+        assert_eq!((totals.build_scripts, totals.proc_macros), (1, 1));
+        assert_eq!(totals.unlicensed, 1);
+        assert_eq!(totals.branch_pinned, ["tool 0.3.0"]);
+        assert!(DepsReport::from_metadata("not json").is_none());
+    }
+}
@@ -0,0 +1,577 @@
+//! External binaries each tool depends on, probed lazily and cached so a
+//! missing toolchain component produces one consistent error instead of a
+//! confusing spawn failure.
+
+use rmcp::ErrorData as McpError;
+use serde_json::json;
+use std::collections::HashMap;
+use std::path::PathBuf;
+use std::process::Command as StdCommand;
+use std::sync::Mutex;
+use std::time::SystemTime;
+
+/// A binary or cargo subcommand, with the command that prints its version
+#[derive(Debug)]
+pub struct Requirement {
+    pub name: &'static str,
+    probe: &'static [&'static str],
+    pub install_hint: &'static str,
+    /// Optional plugins aren't needed by any core tool
+    pub optional: bool,
+}
+
+pub const CARGO: Requirement = Requirement {
+    name: "cargo",
+    probe: &["cargo", "--version"],
+    install_hint: "install Rust via https://rustup.rs",
+    optional: false,
+};
+pub const RUSTC: Requirement = Requirement {
+    name: "rustc",
+    probe: &["rustc", "--version"],
+    install_hint: "install Rust via https://rustup.rs",
+    optional: false,
+};
+pub const RUSTFMT: Requirement = Requirement {
+    name: "rustfmt",
+    probe: &["rustfmt", "--version"],
+    install_hint: "rustup component add rustfmt",
+    optional: false,
+};
+pub const CLIPPY: Requirement = Requirement {
+    name: "clippy",
+    probe: &["cargo", "clippy", "--version"],
+    install_hint: "rustup component add clippy",
+    optional: false,
+};
+pub const RUST_ANALYZER: Requirement = Requirement {
+    name: "rust-analyzer",
+    probe: &["rust-analyzer", "--version"],
+    install_hint: "rustup component add rust-analyzer",
+    optional: true,
+};
+pub const CARGO_AUDIT: Requirement = Requirement {
+    name: "cargo-audit",
+    probe: &["cargo", "audit", "--version"],
+    install_hint: "cargo install cargo-audit",
+    optional: true,
+};
+pub const CARGO_DENY: Requirement = Requirement {
+    name: "cargo-deny",
+    probe: &["cargo", "deny", "--version"],
+    install_hint: "cargo install cargo-deny",
+    optional: true,
+};
+pub const CARGO_EXPAND: Requirement = Requirement {
+    name: "cargo-expand",
+    probe: &["cargo", "expand", "--version"],
+    install_hint: "cargo install cargo-expand",
+    optional: true,
+};
+pub const CARGO_UDEPS: Requirement = Requirement {
+    name: "cargo-udeps",
+    probe: &["cargo", "udeps", "--version"],
+    install_hint: "cargo install cargo-udeps",
+    optional: true,
+};
+pub const CARGO_MSRV: Requirement = Requirement {
+    name: "cargo-msrv",
+    probe: &["cargo", "msrv", "--version"],
+    install_hint: "cargo install cargo-msrv",
+    optional: true,
+};
+pub const CARGO_LLVM_COV: Requirement = Requirement {
+    name: "cargo-llvm-cov",
+    probe: &["cargo", "llvm-cov", "--version"],
+    install_hint: "cargo install cargo-llvm-cov && rustup component add llvm-tools-preview",
+    optional: true,
+};
+pub const CARGO_NEXTEST: Requirement = Requirement {
+    name: "cargo-nextest",
+    probe: &["cargo", "nextest", "--version"],
+    install_hint: "cargo install cargo-nextest --locked",
+    optional: true,
+};
+pub const CARGO_MACHETE: Requirement = Requirement {
+    name: "cargo-machete",
+    probe: &["cargo", "machete", "--version"],
+    install_hint: "cargo install cargo-machete",
+    optional: true,
+};
+pub const CARGO_BLOAT: Requirement = Requirement {
+    name: "cargo-bloat",
+    probe: &["cargo", "bloat", "--version"],
+    install_hint: "cargo install cargo-bloat",
+    optional: true,
+};
+pub const CARGO_SEMVER_CHECKS: Requirement = Requirement {
+    name: "cargo-semver-checks",
+    probe: &["cargo", "semver-checks", "--version"],
+    install_hint: "cargo install cargo-semver-checks --locked",
+    optional: true,
+};
+/// Installs and selects the older toolchains `check_msrv` builds with
+pub const RUSTUP: Requirement = Requirement {
+    name: "rustup",
+    probe: &["rustup", "--version"],
+    install_hint: "install rustup via https://rustup.rs",
+    optional: true,
+};
+/// Symbol sizes for `cargo_bloat` when cargo-bloat isn't installed
+pub const NM: Requirement = Requirement {
+    name: "nm",
+    probe: &["nm", "--version"],
+    install_hint: "install binutils",
+    optional: true,
+};
+
+/// Everything `doctor` reports on
+pub const ALL: &[&Requirement] = &[
+    &CARGO,
+    &RUSTC,
+    &RUSTFMT,
+    &CLIPPY,
+    &RUST_ANALYZER,
+    &CARGO_AUDIT,
+    &CARGO_DENY,
+    &CARGO_EXPAND,
+    &CARGO_UDEPS,
+    &CARGO_MSRV,
+    &CARGO_LLVM_COV,
+    &CARGO_NEXTEST,
+    &CARGO_MACHETE,
+    &CARGO_BLOAT,
+    &CARGO_SEMVER_CHECKS,
+    &RUSTUP,
+    &NM,
+];
+
+/// Binaries a tool needs before it can run. Tools that only touch the
+/// database need nothing.
+pub fn requirements_for(tool: &str) -> &'static [&'static Requirement] {
+    match tool {
+        "cargo_fmt" => &[&CARGO, &RUSTFMT],
+        "cargo_clippy" => &[&CARGO, &CLIPPY],
+        "cargo_audit" => &[&CARGO, &CARGO_AUDIT],
+        "cargo_deny" => &[&CARGO, &CARGO_DENY],
+        "cargo_coverage" => &[&CARGO, &CARGO_LLVM_COV],
+        "cargo_check"
+        | "cargo_feature_check"
+        | "cargo_fix"
+        | "cargo_test"
+        | "cargo_build"
+        | "cargo_tree"
+        | "cargo_update"
+        | "cargo_deps_report"
+        | "find_duplicate_deps"
+        | "cargo_doc"
+        | "cargo_bloat"
+        | "cargo_machete"
+        | "rust_analyzer"
+        | "rust_unused" => &[&CARGO],
+        "check_msrv" => &[&CARGO, &RUSTUP],
+        "code_intel" => &[&CARGO, &RUST_ANALYZER],
+        "semver_check" => &[&CARGO, &CARGO_SEMVER_CHECKS],
+        _ => &[],
+    }
+}
+
+/// Run a requirement's version command, returning the first line of output
+fn probe(requirement: &Requirement) -> Option<String> {
+    let (program, args) = requirement.probe.split_first()?;
+    let output = StdCommand::new(program).args(args).output().ok()?;
+    if !output.status.success() {
+        return None;
+    }
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    Some(stdout.lines().next().unwrap_or_default().trim().to_string())
+}
+
+/// Install a toolchain with rustup's minimal profile, returning rustup's
+/// error output when that fails. Blocks for as long as the download takes.
+pub fn install_toolchain(toolchain: &str) -> Result<(), String> {
+    let output = StdCommand::new("rustup")
+        .args(["toolchain", "install", toolchain, "--profile", "minimal"])
+        .output()
+        .map_err(|e| format!("Failed to run rustup: {}", e))?;
+    if output.status.success() {
+        return Ok(());
+    }
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    // rustup appends a backtrace when RUST_BACKTRACE is set
+    let message = stderr.split("Stack backtrace:").next().unwrap_or_default();
+    Err(message.trim().to_string())
+}
+
+/// Compilation targets with an installed standard library. Without rustup
+/// only the host target is known.
+fn probe_targets() -> Vec<String> {
+    let installed = StdCommand::new("rustup")
+        .args(["target", "list", "--installed"])
+        .output()
+        .ok()
+        .filter(|output| output.status.success());
+    if let Some(output) = installed {
+        return String::from_utf8_lossy(&output.stdout)
+            .lines()
+            .map(|line| line.trim().to_string())
+            .filter(|line| !line.is_empty())
+            .collect();
+    }
+    probe_host().into_iter().collect()
+}
+
+/// The host target triple from `rustc -vV`
+fn probe_host() -> Option<String> {
+    let output = StdCommand::new("rustc").arg("-vV").output().ok()?;
+    String::from_utf8_lossy(&output.stdout)
+        .lines()
+        .find_map(|line| line.strip_prefix("host: ").map(str::to_string))
+}
+
+fn is_target_name(target: &str) -> bool {
+    !target.is_empty()
+        && target
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || matches!(c, '_' | '-' | '.'))
+}
+
+/// `rustc --version --verbose` of a toolchain
+#[derive(Debug, Clone, PartialEq, serde::Serialize)]
+pub struct Toolchain {
+    /// The first line, e.g. "rustc 1.85.0 (4d91de4e4 2025-02-17)"
+    pub version: String,
+    pub release: Option<String>,
+    pub commit_hash: Option<String>,
+    pub commit_date: Option<String>,
+    pub host: Option<String>,
+    pub llvm_version: Option<String>,
+}
+
+impl Toolchain {
+    pub fn parse(verbose: &str) -> Option<Self> {
+        let mut lines = verbose.lines();
+        let version = lines.next()?.trim();
+        if !version.starts_with("rustc ") {
+            return None;
+        }
+        let mut toolchain = Toolchain {
+            version: version.to_string(),
+            release: None,
+            commit_hash: None,
+            commit_date: None,
+            host: None,
+            llvm_version: None,
+        };
+        for line in lines {
+            let Some((key, value)) = line.split_once(": ") else {
+                continue;
+            };
+            let value = Some(value.trim().to_string());
+            match key.trim() {
+                "release" => toolchain.release = value,
+                "commit-hash" => toolchain.commit_hash = value,
+                "commit-date" => toolchain.commit_date = value,
+                "host" => toolchain.host = value,
+                "LLVM version" => toolchain.llvm_version = value,
+                _ => {}
+            }
+        }
+        Some(toolchain)
+    }
+
+    /// "1.85" from the release, e.g. for grouping analyses
+    pub fn minor_version(rustc_version: &str) -> Option<String> {
+        // "rustc 1.86.0-nightly (...)" or a bare release
+        let release = rustc_version
+            .trim_start_matches("rustc ")
+            .split([' ', '-'])
+            .next()?;
+        let mut parts = release.split('.');
+        let (major, minor) = (parts.next()?, parts.next()?);
+        (major.chars().all(|c| c.is_ascii_digit()) && minor.chars().all(|c| c.is_ascii_digit()))
+            .then(|| format!("{}.{}", major, minor))
+    }
+}
+
+/// Cached probe results keyed by requirement name; `None` means missing
+#[derive(Debug, Default)]
+pub struct ToolRequirements {
+    versions: Mutex<HashMap<&'static str, Option<String>>>,
+    targets: Mutex<Option<Vec<String>>>,
+    host: std::sync::OnceLock<Option<String>>,
+    /// `rustc --version --verbose` by rustup toolchain name, for installed
+    /// toolchains
+    toolchains: Mutex<HashMap<String, Toolchain>>,
+    /// The default toolchain, with the rustup state it was probed under
+    toolchain: Mutex<Option<(Option<SystemTime>, Toolchain)>>,
+}
+
+impl ToolRequirements {
+    /// Detected version of a requirement, probing it on first use
+    pub fn version(&self, requirement: &'static Requirement) -> Option<String> {
+        if let Some(cached) = self.cache().get(requirement.name) {
+            return cached.clone();
+        }
+        let version = probe(requirement);
+        self.cache().insert(requirement.name, version.clone());
+        version
+    }
+
+    /// Fail with a structured error listing every missing binary the tool needs
+    pub fn check(&self, tool: &str) -> Result<(), McpError> {
+        let missing: Vec<&Requirement> = requirements_for(tool)
+            .iter()
+            .copied()
+            .filter(|r| self.version(r).is_none())
+            .collect();
+        if missing.is_empty() {
+            return Ok(());
+        }
+        Err(missing_error(tool, &missing))
+    }
+
+    /// Installed compilation targets, listed once and cached
+    pub fn installed_targets(&self) -> Vec<String> {
+        let mut targets = self
+            .targets
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner());
+        targets.get_or_insert_with(probe_targets).clone()
+    }
+
+    /// Target triple builds produce when no `--target` is given
+    pub fn host_target(&self) -> Option<String> {
+        self.host.get_or_init(probe_host).clone()
+    }
+
+    /// Fail with a structured error unless `target` is installed
+    pub fn check_target(&self, target: &str) -> Result<(), McpError> {
+        if !is_target_name(target) {
+            return Err(McpError::invalid_params(
+                format!("target must be a target triple, got: {}", target),
+                None,
+            ));
+        }
+        let installed = self.installed_targets();
+        if installed.iter().any(|t| t == target) {
+            return Ok(());
+        }
+        Err(McpError::invalid_params(
+            format!("target {} is not installed", target),
+            Some(json!({
+                "missing_target": target,
+                "installed_targets": installed,
+                "install_hint": format!("rustup target add {}", target)
+            })),
+        ))
+    }
+
+    /// The toolchain cargo runs by default. Cached until rustup's settings
+    /// or its record of installed toolchains change, as they do on `rustup
+    /// default` and `rustup update`.
+    pub fn toolchain(&self) -> Option<Toolchain> {
+        let stamp = rustup_stamp();
+        let mut cached = self
+            .toolchain
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner());
+        if let Some((probed_at, toolchain)) = cached.as_ref()
+            && *probed_at == stamp
+        {
+            return Some(toolchain.clone());
+        }
+        let toolchain = probe_toolchain(None)?;
+        *cached = Some((stamp, toolchain.clone()));
+        Some(toolchain)
+    }
+
+    /// A rustup toolchain by name, or `None` when it isn't installed. Only
+    /// installed toolchains are cached, so one installed later is found.
+    pub fn installed_toolchain(&self, name: &str) -> Option<Toolchain> {
+        let mut toolchains = self
+            .toolchains
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner());
+        if let Some(toolchain) = toolchains.get(name) {
+            return Some(toolchain.clone());
+        }
+        let toolchain = probe_toolchain(Some(name))?;
+        toolchains.insert(name.to_string(), toolchain.clone());
+        Some(toolchain)
+    }
+
+    /// Versions probed so far, by requirement name, without probing more
+    pub fn cached(&self) -> std::collections::BTreeMap<&'static str, Option<String>> {
+        self.cache()
+            .iter()
+            .map(|(name, version)| (*name, version.clone()))
+            .collect()
+    }
+
+    /// Drop cached results so the next lookup probes again
+    pub fn refresh(&self) {
+        self.cache().clear();
+        *self
+            .toolchain
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner()) = None;
+        *self
+            .targets
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner()) = None;
+    }
+
+    /// Requirements already probed and found missing, without probing more
+    pub fn known_missing(&self) -> Vec<&'static str> {
+        let mut missing: Vec<&'static str> = self
+            .cache()
+            .iter()
+            .filter(|(_, version)| version.is_none())
+            .map(|(name, _)| *name)
+            .collect();
+        missing.sort();
+        missing
+    }
+
+    fn cache(&self) -> std::sync::MutexGuard<'_, HashMap<&'static str, Option<String>>> {
+        self.versions
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+}
+
+/// `rustc --version --verbose`, of the rustup toolchain `name` when given
+fn probe_toolchain(name: Option<&str>) -> Option<Toolchain> {
+    let mut command = StdCommand::new("rustc");
+    command.args(["--version", "--verbose"]);
+    if let Some(name) = name {
+        command
+            .env("RUSTUP_TOOLCHAIN", name)
+            // Don't let rustup install it as a side effect of asking
+            .env("RUSTUP_AUTO_INSTALL", "0");
+    }
+    let output = command.output().ok().filter(|o| o.status.success())?;
+    Toolchain::parse(&String::from_utf8_lossy(&output.stdout))
+}
+
+/// Newest modification time of rustup's settings and of its per-toolchain
+/// update hashes; None without rustup
+fn rustup_stamp() -> Option<SystemTime> {
+    let home = std::env::var_os("RUSTUP_HOME")
+        .map(PathBuf::from)
+        .or_else(|| std::env::var_os("HOME").map(|home| PathBuf::from(home).join(".rustup")))?;
+    let modified = |path: PathBuf| std::fs::metadata(path).and_then(|m| m.modified()).ok();
+    let hashes = std::fs::read_dir(home.join("update-hashes"))
+        .into_iter()
+        .flatten()
+        .filter_map(|entry| modified(entry.ok()?.path()));
+    modified(home.join("settings.toml"))
+        .into_iter()
+        .chain(hashes)
+        .max()
+}
+
+fn missing_error(tool: &str, missing: &[&Requirement]) -> McpError {
+    let names: Vec<&str> = missing.iter().map(|r| r.name).collect();
+    let hints: Vec<&str> = missing.iter().map(|r| r.install_hint).collect();
+    McpError::internal_error(
+        format!(
+            "{} requires {} which is not installed",
+            tool,
+            names.join(", ")
+        ),
+        Some(json!({
+            "missing": names,
+            "install_hint": hints.join("; ")
+        })),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const MISSING: Requirement = Requirement {
+        name: "not-a-real-tool",
+        probe: &["rusty-tools-definitely-not-installed", "--version"],
+        install_hint: "cargo install not-a-real-tool",
+        optional: true,
+    };
+
+    #[test]
+    fn every_cargo_tool_needs_cargo() {
+        for tool in crate::SNIPPET_TOOLS {
+            assert!(crate::cargo_tool_command(tool).is_some());
+            assert_eq!(requirements_for(tool)[0].name, "cargo", "{}", tool);
+        }
+        assert!(requirements_for("db_stats").is_empty());
+    }
+
+    #[test]
+    fn verbose_version_is_parsed() {
+        let toolchain = Toolchain::parse(
+            "rustc 1.86.0-nightly (a1b2c3d4e 2025-01-20)\nbinary: rustc\ncommit-hash: a1b2c3d4e5f6\ncommit-date: 2025-01-20\nhost: aarch64-apple-darwin\nrelease: 1.86.0-nightly\nLLVM version: 19.1.6\n",
+        )
+        .unwrap();
+        assert_eq!(toolchain.release.as_deref(), Some("1.86.0-nightly"));
+        assert_eq!(toolchain.commit_hash.as_deref(), Some("a1b2c3d4e5f6"));
+        assert_eq!(toolchain.host.as_deref(), Some("aarch64-apple-darwin"));
+        assert_eq!(toolchain.llvm_version.as_deref(), Some("19.1.6"));
+        assert_eq!(
+            Toolchain::minor_version(&toolchain.version).as_deref(),
+            Some("1.86")
+        );
+        assert_eq!(
+            Toolchain::minor_version("rustc 1.85.0 (4d91de4e4 2025-02-17)").as_deref(),
+            Some("1.85")
+        );
+        assert_eq!(Toolchain::minor_version("rustc unknown"), None);
+        assert!(Toolchain::parse("cargo 1.85.0").is_none());
+
+        let requirements = ToolRequirements::default();
+        let current = requirements.toolchain().unwrap();
+        assert!(current.version.starts_with("rustc "));
+        assert_eq!(requirements.toolchain(), Some(current));
+    }
+
+    #[test]
+    fn missing_binary_is_cached_and_reported() {
+        let requirements = ToolRequirements::default();
+        assert_eq!(requirements.version(&MISSING), None);
+        assert_eq!(requirements.known_missing(), ["not-a-real-tool"]);
+        assert!(
+            requirements
+                .version(&CARGO)
+                .is_some_and(|v| v.starts_with("cargo "))
+        );
+
+        requirements.refresh();
+        assert!(requirements.known_missing().is_empty());
+    }
+
+    #[test]
+    fn only_installed_targets_are_accepted() {
+        let requirements = ToolRequirements::default();
+        let installed = requirements.installed_targets();
+        assert!(!installed.is_empty());
+        assert!(requirements.check_target(&installed[0]).is_ok());
+
+        let error = requirements
+            .check_target("thumbv6m-not-a-real-target")
+            .unwrap_err();
+        assert_eq!(
+            error.data.unwrap()["install_hint"],
+            "rustup target add thumbv6m-not-a-real-target"
+        );
+        assert!(requirements.check_target("../custom.json").is_err());
+    }
+
+    #[test]
+    fn missing_error_lists_binaries_and_hints() {
+        let error = missing_error("cargo_audit", &[&MISSING]);
+        let data = error.data.unwrap();
+        assert_eq!(data["missing"], json!(["not-a-real-tool"]));
+        assert_eq!(data["install_hint"], "cargo install not-a-real-tool");
+    }
+}
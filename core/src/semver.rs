@@ -0,0 +1,250 @@
+//! API compatibility results for `semver_check`, read from the report
+//! `cargo semver-checks check-release` writes to stderr.
+
+/// One lint that fired: a breaking change, or a warning-level one
+#[derive(Debug, Clone, PartialEq, serde::Serialize)]
+pub struct SemverChange {
+    /// cargo-semver-checks' lint id, e.g. `function_missing`
+    pub lint: String,
+    /// "failure" for breaking changes, "warning" for lints that only warn
+    pub level: String,
+    /// One-line description, e.g. "pub fn removed or renamed"
+    pub summary: String,
+    pub description: String,
+    /// The Cargo reference section on the rule, when the lint links one
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub reference: Option<String>,
+    /// Each item the lint fired on, e.g. "function temp_project::foo,
+    /// previously in file src/lib.rs:1"
+    pub locations: Vec<String>,
+}
+
+/// Lint counts from the "Checked" line
+#[derive(Debug, Clone, Copy, PartialEq, serde::Serialize)]
+pub struct CheckCounts {
+    pub total: u64,
+    pub passed: u64,
+    pub failed: u64,
+    pub warned: u64,
+    pub skipped: u64,
+}
+
+#[derive(Debug, Clone, PartialEq, serde::Serialize)]
+pub struct SemverReport {
+    /// "major" or "minor" when the changes need that version bump; `None`
+    /// when the release is compatible
+    pub required_bump: Option<String>,
+    pub checks: Option<CheckCounts>,
+    pub changes: Vec<SemverChange>,
+}
+
+impl SemverReport {
+    /// Read the report from cargo-semver-checks' stderr. `None` when no
+    /// checks ran, e.g. either version failed to build.
+    pub fn from_output(stderr: &str) -> Option<Self> {
+        let mut checks = None;
+        let mut required_bump = None;
+        let mut summarized = false;
+        let mut changes: Vec<SemverChange> = Vec::new();
+        let mut section = Section::None;
+
+        for line in stderr.lines() {
+            let trimmed = line.trim();
+            if let Some(header) = trimmed
+                .strip_prefix("--- ")
+                .and_then(|rest| rest.strip_suffix(" ---"))
+                && let Some((level, rest)) = header.split_once(' ')
+                && matches!(level, "failure" | "warning")
+            {
+                let (lint, summary) = rest.split_once(": ").unwrap_or((rest, ""));
+                changes.push(SemverChange {
+                    lint: lint.to_string(),
+                    level: level.to_string(),
+                    summary: summary.to_string(),
+                    description: String::new(),
+                    reference: None,
+                    locations: Vec::new(),
+                });
+                section = Section::None;
+                continue;
+            }
+            if let Some(rest) = trimmed.strip_prefix("Checked [") {
+                checks = rest
+                    .split_once(']')
+                    .and_then(|(_, counts)| parse_counts(counts));
+                section = Section::None;
+                continue;
+            }
+            if let Some(summary) = trimmed.strip_prefix("Summary ") {
+                summarized = true;
+                required_bump = ["major", "minor"]
+                    .into_iter()
+                    .find(|bump| summary.contains(&format!("requires new {} version", bump)))
+                    .map(str::to_string);
+                section = Section::None;
+                continue;
+            }
+            let Some(change) = changes.last_mut() else {
+                continue;
+            };
+            match trimmed {
+                "Description:" => section = Section::Description,
+                "Failed in:" => section = Section::Locations,
+                "" => {
+                    if section == Section::Locations {
+                        section = Section::None;
+                    }
+                }
+                _ if trimmed.starts_with("ref: ") => {
+                    change.reference = Some(trimmed["ref: ".len()..].to_string());
+                }
+                _ if trimmed.starts_with("impl: ") => {}
+                _ => match section {
+                    Section::Description => {
+                        if !change.description.is_empty() {
+                            change.description.push(' ');
+                        }
+                        change.description.push_str(trimmed);
+                    }
+                    Section::Locations => change.locations.push(trimmed.to_string()),
+                    Section::None => {}
+                },
+            }
+        }
+
+        (checks.is_some() || summarized).then_some(SemverReport {
+            required_bump,
+            checks,
+            changes,
+        })
+    }
+
+    /// Whether no lint found a breaking change
+    pub fn compatible(&self) -> bool {
+        !self.changes.iter().any(|change| change.level == "failure")
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Section {
+    None,
+    Description,
+    Locations,
+}
+
+/// A published version as cargo-semver-checks takes it: `1.2.3`, optionally
+/// with a pre-release such as `1.2.3-beta.1`
+pub fn is_exact_version(version: &str) -> bool {
+    let (release, pre) = version.split_once('-').unwrap_or((version, "a"));
+    let parts: Vec<&str> = release.split('.').collect();
+    parts.len() == 3
+        && parts
+            .iter()
+            .all(|part| !part.is_empty() && part.bytes().all(|b| b.is_ascii_digit()))
+        && !pre.is_empty()
+        && pre
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || matches!(c, '.' | '-'))
+}
+
+/// "196 checks: 195 pass, 1 fail, 0 warn, 0 skip"
+fn parse_counts(text: &str) -> Option<CheckCounts> {
+    let (total, rest) = text.trim().split_once(" checks:")?;
+    let count = |label: &str| {
+        rest.split(',')
+            .filter_map(|part| part.trim().split_once(' '))
+            .find(|(_, name)| *name == label)
+            .and_then(|(n, _)| n.parse().ok())
+            .unwrap_or(0)
+    };
+    Some(CheckCounts {
+        total: total.trim().parse().ok()?,
+        passed: count("pass"),
+        failed: count("fail"),
+        warned: count("warn"),
+        skipped: count("skip"),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const REPORT: &str = "     Parsing temp_project v0.1.0 (current)
+      Parsed [   0.6s] (current)
+     Parsing temp_project v0.1.0 (baseline)
+      Parsed [   0.5s] (baseline)
+    Checking temp_project v0.1.0 -> v0.1.0 (no change; assume patch)
+     Checked [   0.012s] 196 checks: 194 pass, 1 fail, 1 warn, 0 skip
+
+--- failure function_missing: pub fn removed or renamed ---
+
+Description:
+A publicly-visible function cannot be imported by its prior path. A `pub use` may have been removed,
+or the function itself may have been renamed or removed entirely.
+        ref: https://doc.rust-lang.org/cargo/reference/semver.html#item-remove
+       impl: https://github.com/obi1kenobi/cargo-semver-checks/tree/v0.36.0/src/lints/function_missing.ron
+
+Failed in:
+  function temp_project::greet, previously in file src/lib.rs:1
+
+--- warning function_must_use_added: function #[must_use] added ---
+
+Description:
+A function is now #[must_use].
+       impl: https://github.com/obi1kenobi/cargo-semver-checks/tree/v0.36.0/src/lints/function_must_use_added.ron
+
+Failed in:
+  function temp_project::add in src/lib.rs:4
+
+     Summary semver requires new major version: 1 major and 0 minor checks failed
+     Warning produced 1 major and 0 minor level warnings
+    Finished [   1.2s] temp_project
+";
+
+    #[test]
+    fn report_lists_each_lint_with_its_locations() {
+        let report = SemverReport::from_output(REPORT).unwrap();
+        assert_eq!(report.required_bump.as_deref(), Some("major"));
+        assert_eq!(
+            report.checks,
+            Some(CheckCounts {
+                total: 196,
+                passed: 194,
+                failed: 1,
+                warned: 1,
+                skipped: 0,
+            })
+        );
+        assert_eq!(report.changes.len(), 2);
+        let missing = &report.changes[0];
+        assert_eq!(
+            (missing.lint.as_str(), missing.level.as_str()),
+            ("function_missing", "failure")
+        );
+        assert_eq!(missing.summary, "pub fn removed or renamed");
+        assert!(missing.description.ends_with("removed entirely."));
+        assert_eq!(
+            missing.reference.as_deref(),
+            Some("https://doc.rust-lang.org/cargo/reference/semver.html#item-remove")
+        );
+        assert_eq!(
+            missing.locations,
+            ["function temp_project::greet, previously in file src/lib.rs:1"]
+        );
+        assert_eq!(report.changes[1].level, "warning");
+        assert!(!report.compatible());
+
+        let compatible = SemverReport::from_output(
+            "     Checked [   0.010s] 196 checks: 196 pass, 0 skip\n     Summary no semver update required\n",
+        )
+        .unwrap();
+        assert!(compatible.compatible() && compatible.required_bump.is_none());
+        assert!(SemverReport::from_output("error: failed to build rustdoc for crate").is_none());
+
+        assert!(is_exact_version("1.0.200") && is_exact_version("0.3.0-beta.1"));
+        assert!(
+            !is_exact_version("1.0") && !is_exact_version("^1.0.0") && !is_exact_version("1.0.0-")
+        );
+    }
+}
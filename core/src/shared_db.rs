@@ -0,0 +1,215 @@
+//! The server's database connection, shared between tool calls. A call that
+//! panics while holding the lock doesn't take persistence down with it: the
+//! next caller recovers the lock. A connection SQLite reports as corrupt or
+//! unreadable is reopened from the persistence mode and the operation retried
+//! once.
+
+use crate::{Database, PersistenceMode};
+use rusqlite::ErrorCode;
+use std::sync::{Mutex, MutexGuard, PoisonError};
+use tracing::warn;
+
+pub struct SharedDatabase {
+    db: Mutex<Database>,
+    mode: PersistenceMode,
+    health: Mutex<Health>,
+}
+
+#[derive(Debug)]
+struct Health {
+    healthy: bool,
+    last_error: Option<String>,
+}
+
+impl SharedDatabase {
+    pub fn new(db: Database, mode: PersistenceMode) -> Self {
+        SharedDatabase {
+            db: Mutex::new(db),
+            mode,
+            health: Mutex::new(Health {
+                healthy: true,
+                last_error: None,
+            }),
+        }
+    }
+
+    /// The connection, recovered when a call panicked while holding it. The
+    /// connection itself is intact: SQLite rolls back a transaction the
+    /// panicking call left open.
+    pub fn lock(&self) -> MutexGuard<'_, Database> {
+        self.db.lock().unwrap_or_else(|poisoned| {
+            warn!("Database lock was poisoned by a panicking call; recovering");
+            // The connection is still usable, so only the error is noted
+            self.health().last_error =
+                Some("lock poisoned by a panicking call; recovered".to_string());
+            self.db.clear_poison();
+            poisoned.into_inner()
+        })
+    }
+
+    /// Run `operation` on the connection. When it fails because the
+    /// connection is unusable, reopen it and retry once; several writes that
+    /// mustn't be repeated go in one `Database::transaction`.
+    pub fn run<T>(&self, operation: impl Fn(&Database) -> anyhow::Result<T>) -> anyhow::Result<T> {
+        let mut db = self.lock();
+        let error = match operation(&db) {
+            Ok(value) => return Ok(value),
+            Err(error) if is_fatal(&error) => error,
+            Err(error) => return Err(error),
+        };
+        warn!("Database error, reopening the connection: {}", error);
+        self.record_error(error.to_string());
+        match Database::new(self.mode.clone()) {
+            Ok(Some(reopened)) => *db = reopened,
+            Ok(None) => return Err(error),
+            Err(e) => {
+                self.record_error(format!("reopening failed: {}", e));
+                return Err(error);
+            }
+        }
+        let retried = operation(&db);
+        if retried.is_ok() {
+            self.health().healthy = true;
+        }
+        retried
+    }
+
+    /// False from a connection error until an operation succeeds on the
+    /// reopened connection
+    pub fn healthy(&self) -> bool {
+        self.health().healthy
+    }
+
+    /// The most recent lock or connection error, kept after recovery
+    pub fn last_error(&self) -> Option<String> {
+        self.health().last_error.clone()
+    }
+
+    fn record_error(&self, error: String) {
+        let mut health = self.health();
+        health.healthy = false;
+        health.last_error = Some(error);
+    }
+
+    fn health(&self) -> MutexGuard<'_, Health> {
+        self.health.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+}
+
+/// The outcome of a write the caller can do without: a failed statement is
+/// logged and skipped, but a broken connection is returned for
+/// [`SharedDatabase::run`] to reopen and retry. `action` reads like "store
+/// error".
+pub(crate) fn tolerate<T>(result: anyhow::Result<T>, action: &str) -> anyhow::Result<Option<T>> {
+    match result {
+        Ok(value) => Ok(Some(value)),
+        Err(error) if is_fatal(&error) => Err(error),
+        Err(error) => {
+            warn!("Failed to {}: {}", action, error);
+            Ok(None)
+        }
+    }
+}
+
+/// Errors that mean the connection, not the statement, is broken
+fn is_fatal(error: &anyhow::Error) -> bool {
+    matches!(
+        error.downcast_ref::<rusqlite::Error>(),
+        Some(rusqlite::Error::SqliteFailure(failure, _))
+            if matches!(
+                failure.code,
+                ErrorCode::DatabaseCorrupt
+                    | ErrorCode::NotADatabase
+                    | ErrorCode::CannotOpen
+                    | ErrorCode::SystemIoFailure
+            )
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Arc;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    #[test]
+    fn poisoned_lock_and_broken_connection_are_recovered() {
+        let dir = tempfile::tempdir().unwrap();
+        let mode = PersistenceMode::Path(dir.path().join("t.db"));
+        let db = Database::new(mode.clone()).unwrap().unwrap();
+        let shared = Arc::new(SharedDatabase::new(db, mode));
+        assert!(shared.healthy() && shared.last_error().is_none());
+
+        let poisoner = Arc::clone(&shared);
+        std::thread::spawn(move || {
+            let _db = poisoner.lock();
+            panic!("handler panicked while holding the database");
+        })
+        .join()
+        .unwrap_err();
+        assert!(shared.db.is_poisoned());
+        assert!(
+            shared
+                .lock()
+                .store_todo("clippy", "needless return", None, None)
+                .unwrap()
+        );
+        assert!(!shared.db.is_poisoned());
+        assert!(shared.healthy());
+        assert!(shared.last_error().unwrap().contains("poisoned"));
+
+        let attempts = AtomicUsize::new(0);
+        let todos = shared
+            .run(|db| {
+                if attempts.fetch_add(1, Ordering::Relaxed) == 0 {
+                    let corrupt = rusqlite::ffi::Error::new(rusqlite::ffi::SQLITE_CORRUPT);
+                    return Err(rusqlite::Error::SqliteFailure(corrupt, None).into());
+                }
+                db.get_todos(false, None)
+            })
+            .unwrap();
+        assert_eq!(attempts.load(Ordering::Relaxed), 2);
+        assert_eq!(todos.len(), 1);
+        assert!(shared.healthy());
+        assert!(shared.last_error().unwrap().contains("malformed"));
+
+        // Statement errors aren't retried
+        let attempts = AtomicUsize::new(0);
+        let failed: anyhow::Result<()> = shared.run(|_| {
+            attempts.fetch_add(1, Ordering::Relaxed);
+            Err(rusqlite::Error::QueryReturnedNoRows.into())
+        });
+        assert!(failed.is_err());
+        assert_eq!(attempts.load(Ordering::Relaxed), 1);
+
+        // A retried transaction doesn't repeat the writes before the failure
+        let analysis_id = shared
+            .run(|db| {
+                db.store_analysis(
+                    "cargo_check",
+                    &serde_json::json!({}),
+                    false,
+                    None,
+                    None,
+                    None,
+                    None,
+                )
+            })
+            .unwrap();
+        let attempts = AtomicUsize::new(0);
+        shared
+            .run(|db| {
+                db.transaction(|db| {
+                    db.store_error(analysis_id, None, Some("error"), "E", None, None, None)?;
+                    if attempts.fetch_add(1, Ordering::Relaxed) == 0 {
+                        let corrupt = rusqlite::ffi::Error::new(rusqlite::ffi::SQLITE_CORRUPT);
+                        return Err(rusqlite::Error::SqliteFailure(corrupt, None).into());
+                    }
+                    Ok(())
+                })
+            })
+            .unwrap();
+        let errors = shared.run(|db| db.get_errors_for_analysis(analysis_id));
+        assert_eq!(errors.unwrap().len(), 1);
+    }
+}
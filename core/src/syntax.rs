@@ -0,0 +1,148 @@
+//! Syntax-only checking with syn, in-process: nothing is compiled or run, so
+//! it answers "does this parse" in milliseconds.
+
+use quote::ToTokens;
+use syn::spanned::Spanned;
+
+/// A parse error at a 1-based line and character column
+#[derive(Debug, Clone, PartialEq, serde::Serialize)]
+pub struct SyntaxError {
+    pub message: String,
+    pub line: usize,
+    pub column: usize,
+}
+
+/// A top-level item of the parsed file
+#[derive(Debug, Clone, PartialEq, serde::Serialize)]
+pub struct OutlineItem {
+    /// "fn", "struct", "enum", "impl", "trait", "mod", ...
+    pub kind: &'static str,
+    /// The item's name; for impls the type, with the trait as `Trait for Type`
+    pub name: String,
+    pub line: usize,
+}
+
+/// The outline of `code`, or every error syn reports for it
+pub fn parse(code: &str) -> Result<Vec<OutlineItem>, Vec<SyntaxError>> {
+    let parsed = match syn::parse_file(code) {
+        Ok(file) => Ok(file.items.iter().filter_map(outline_item).collect()),
+        Err(error) => Err(error.into_iter().map(SyntaxError::from).collect()),
+    };
+    release_spans();
+    parsed
+}
+
+/// proc-macro2 keeps the source of every parse in a thread-local map so spans
+/// can report locations; drop it once they have been read, or a long-running
+/// server grows with each call
+pub(crate) fn release_spans() {
+    proc_macro2::extra::invalidate_current_thread_spans();
+}
+
+impl From<syn::Error> for SyntaxError {
+    fn from(error: syn::Error) -> Self {
+        let start = error.span().start();
+        SyntaxError {
+            message: error.to_string(),
+            line: start.line,
+            // proc-macro2 columns are 0-based
+            column: start.column + 1,
+        }
+    }
+}
+
+fn outline_item(item: &syn::Item) -> Option<OutlineItem> {
+    let named = |kind, ident: &syn::Ident| OutlineItem {
+        kind,
+        name: ident.to_string(),
+        line: ident.span().start().line,
+    };
+    let item = match item {
+        syn::Item::Fn(item) => named("fn", &item.sig.ident),
+        syn::Item::Struct(item) => named("struct", &item.ident),
+        syn::Item::Enum(item) => named("enum", &item.ident),
+        syn::Item::Union(item) => named("union", &item.ident),
+        syn::Item::Trait(item) => named("trait", &item.ident),
+        syn::Item::TraitAlias(item) => named("trait", &item.ident),
+        syn::Item::Type(item) => named("type", &item.ident),
+        syn::Item::Const(item) => named("const", &item.ident),
+        syn::Item::Static(item) => named("static", &item.ident),
+        syn::Item::Mod(item) => named("mod", &item.ident),
+        syn::Item::Macro(item) => named("macro", item.ident.as_ref()?),
+        syn::Item::Impl(item) => {
+            let self_ty = tokens(&item.self_ty);
+            OutlineItem {
+                kind: "impl",
+                name: match &item.trait_ {
+                    Some((negative, path, _)) => format!(
+                        "{}{} for {}",
+                        if negative.is_some() { "!" } else { "" },
+                        tokens(path),
+                        self_ty
+                    ),
+                    None => self_ty,
+                },
+                line: item.impl_token.span().start().line,
+            }
+        }
+        syn::Item::Use(item) => OutlineItem {
+            kind: "use",
+            name: tokens(&item.tree),
+            line: item.use_token.span().start().line,
+        },
+        _ => return None,
+    };
+    Some(item)
+}
+
+/// Tokens printed the way they would be written, not as `Vec < T >`
+fn tokens(node: &impl ToTokens) -> String {
+    let printed = node.to_token_stream().to_string();
+    [
+        (" :: ", "::"),
+        (":: ", "::"),
+        (" < ", "<"),
+        ("< ", "<"),
+        (" >", ">"),
+        (" ,", ","),
+        ("& ", "&"),
+    ]
+    .iter()
+    .fold(printed, |text, (from, to)| text.replace(from, to))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn outlines_top_level_items() {
+        let code = "use std::fmt;\n\nstruct Point { x: i32 }\n\nimpl fmt::Display for Point {\n    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result { write!(f, \"{}\", self.x) }\n}\n\nimpl Vec<Point> {}\n\nfn main() {}\n";
+        let items = parse(code).unwrap();
+        let outline: Vec<_> = items
+            .iter()
+            .map(|item| (item.kind, item.name.as_str(), item.line))
+            .collect();
+        assert_eq!(
+            outline,
+            [
+                ("use", "std::fmt", 1),
+                ("struct", "Point", 3),
+                ("impl", "fmt::Display for Point", 5),
+                ("impl", "Vec<Point>", 9),
+                ("fn", "main", 11),
+            ]
+        );
+    }
+
+    #[test]
+    fn errors_carry_their_location() {
+        let errors = parse("fn main() {\n    let x = ;\n}\n").unwrap_err();
+        assert_eq!(errors.len(), 1);
+        assert_eq!((errors[0].line, errors[0].column), (2, 13));
+        assert_eq!(errors[0].message, "expected an expression");
+
+        let errors = parse("fn main() {\n    let s = \"open;\n}\n").unwrap_err();
+        assert_eq!(errors[0].line, 2);
+    }
+}
@@ -0,0 +1,370 @@
+//! Running cargo on a snippet outside the MCP layer, for embedders that need
+//! more control than `run_rust_tool` gives: the project's name, crate type and
+//! edition, extra files, dependencies, environment, target directory, or an existing
+//! project to run in.
+//!
+//! ```no_run
+//! # async fn example() -> Result<(), rmcp::ErrorData> {
+//! use rusty_tools_core::{CrateType, ToolInvocation};
+//! use std::time::Duration;
+//!
+//! let result = ToolInvocation::new("pub fn add(a: u8, b: u8) -> u8 { a + b }")
+//!     .project_name("adder")
+//!     .crate_type(CrateType::Lib)
+//!     .arg("check")
+//!     .timeout(Duration::from_secs(30))
+//!     .run()
+//!     .await?;
+//! assert_eq!(result.status, 0);
+//! # Ok(())
+//! # }
+//! ```
+
+use crate::{ChildEnv, ExecResult, ScratchProject, run_cargo, write_dependencies, write_edition};
+use rmcp::ErrorData as McpError;
+use std::path::{Component, Path, PathBuf};
+use std::time::Duration;
+
+/// Which target the snippet is written as
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub enum CrateType {
+    /// `src/main.rs`
+    #[default]
+    Bin,
+    /// `src/lib.rs`
+    Lib,
+}
+
+/// One cargo run, configured with the builder methods and started with `run`
+#[derive(Debug, Clone)]
+pub struct ToolInvocation {
+    code: Option<String>,
+    files: Vec<(PathBuf, String)>,
+    args: Vec<String>,
+    timeout: Option<Duration>,
+    env: Vec<(String, String)>,
+    project_name: String,
+    crate_type: CrateType,
+    edition: Option<String>,
+    dependencies: Vec<(String, String)>,
+    target_dir: Option<PathBuf>,
+    working_dir: Option<PathBuf>,
+    keep_project: bool,
+    child_env: ChildEnv,
+}
+
+impl Default for ToolInvocation {
+    fn default() -> Self {
+        ToolInvocation {
+            code: None,
+            files: Vec::new(),
+            args: Vec::new(),
+            timeout: None,
+            env: Vec::new(),
+            project_name: "temp_project".to_string(),
+            crate_type: CrateType::Bin,
+            edition: None,
+            dependencies: Vec::new(),
+            target_dir: None,
+            working_dir: None,
+            keep_project: false,
+            child_env: ChildEnv::default(),
+        }
+    }
+}
+
+impl ToolInvocation {
+    /// A run of `code` as the crate's root file
+    pub fn new(code: impl Into<String>) -> Self {
+        ToolInvocation {
+            code: Some(code.into()),
+            ..Default::default()
+        }
+    }
+
+    /// A run that only writes the files added with `file`
+    pub fn from_files() -> Self {
+        ToolInvocation::default()
+    }
+
+    /// Write `contents` to `path`, relative to the project root, e.g.
+    /// `src/util.rs` or `tests/it.rs`
+    pub fn file(mut self, path: impl Into<PathBuf>, contents: impl Into<String>) -> Self {
+        self.files.push((path.into(), contents.into()));
+        self
+    }
+
+    /// Append one cargo argument, e.g. `check`
+    pub fn arg(mut self, arg: impl Into<String>) -> Self {
+        self.args.push(arg.into());
+        self
+    }
+
+    /// Append cargo arguments in order, e.g. `["test", "--test", "it"]`
+    pub fn args<I, S>(mut self, args: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        self.args.extend(args.into_iter().map(Into::into));
+        self
+    }
+
+    /// Kill cargo and report `timed_out` after this long
+    pub fn timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = Some(timeout);
+        self
+    }
+
+    /// Set a variable for cargo and everything it starts, on top of the
+    /// allowlisted base environment `ChildEnv` passes through
+    pub fn env(mut self, key: impl Into<String>, value: impl Into<String>) -> Self {
+        self.env.push((key.into(), value.into()));
+        self
+    }
+
+    /// Package name in the generated manifest; `temp_project` by default
+    pub fn project_name(mut self, name: impl Into<String>) -> Self {
+        self.project_name = name.into();
+        self
+    }
+
+    pub fn crate_type(mut self, crate_type: CrateType) -> Self {
+        self.crate_type = crate_type;
+        self
+    }
+
+    /// Edition in the manifest, e.g. `2021`; the installed cargo's newest
+    /// by default
+    pub fn edition(mut self, edition: impl Into<String>) -> Self {
+        self.edition = Some(edition.into());
+        self
+    }
+
+    /// Add `name = "requirement"` to `[dependencies]`. `run` fails with an
+    /// invalid-params error when `name` isn't a package name.
+    pub fn dependency(mut self, name: impl Into<String>, requirement: impl Into<String>) -> Self {
+        self.dependencies.push((name.into(), requirement.into()));
+        self
+    }
+
+    /// Build into this directory (`CARGO_TARGET_DIR`), e.g. one shared
+    /// between runs so dependencies compile once
+    pub fn target_dir(mut self, dir: impl Into<PathBuf>) -> Self {
+        self.target_dir = Some(dir.into());
+        self
+    }
+
+    /// Run in this existing cargo project instead of a fresh scratch one. The
+    /// code and files are written into it and it is left in place.
+    pub fn working_dir(mut self, dir: impl Into<PathBuf>) -> Self {
+        self.working_dir = Some(dir.into());
+        self
+    }
+
+    /// Leave the scratch project on disk and return its path
+    pub fn keep_project(mut self, keep: bool) -> Self {
+        self.keep_project = keep;
+        self
+    }
+
+    /// The environment and resource limits cargo runs with
+    pub fn child_env(mut self, child_env: ChildEnv) -> Self {
+        self.child_env = child_env;
+        self
+    }
+
+    /// Create or reuse the project, write the sources and run cargo.
+    /// `ExecResult::project_path` is set when the project is still on disk
+    /// afterwards: with `working_dir` or `keep_project`.
+    pub async fn run(self) -> Result<ExecResult, McpError> {
+        let scratch = match &self.working_dir {
+            Some(_) => None,
+            None => Some(ScratchProject::scaffold(
+                &self.child_env,
+                &self.project_name,
+                self.crate_type == CrateType::Lib,
+                self.edition.as_deref(),
+                &self.dependencies,
+            )?),
+        };
+        let project_path = match (&self.working_dir, &scratch) {
+            (Some(dir), _) => dir.clone(),
+            (None, Some(project)) => project.path().to_path_buf(),
+            (None, None) => unreachable!("a scratch project is created without working_dir"),
+        };
+        self.write_sources(&project_path)?;
+
+        let mut env = self.env.clone();
+        if let Some(dir) = &self.target_dir {
+            env.push((
+                "CARGO_TARGET_DIR".to_string(),
+                dir.to_string_lossy().into_owned(),
+            ));
+        }
+        let args: Vec<&str> = self.args.iter().map(String::as_str).collect();
+        let mut result =
+            run_cargo(&project_path, &args, self.timeout, &env, &self.child_env).await?;
+        result.project_path = match scratch {
+            None => Some(project_path),
+            Some(project) if self.keep_project => Some(project.keep()),
+            Some(_) => None,
+        };
+        Ok(result)
+    }
+
+    fn write_sources(&self, project_path: &Path) -> Result<(), McpError> {
+        let write = |path: &Path, contents: &str| {
+            let path = project_path.join(path);
+            if let Some(parent) = path.parent() {
+                std::fs::create_dir_all(parent)?;
+            }
+            std::fs::write(path, contents)
+        };
+        let write_err = |path: &Path, e: std::io::Error| {
+            McpError::internal_error(format!("Failed to write {}: {}", path.display(), e), None)
+        };
+        if let Some(code) = &self.code {
+            let root = match self.crate_type {
+                CrateType::Bin => Path::new("src/main.rs"),
+                CrateType::Lib => Path::new("src/lib.rs"),
+            };
+            write(root, code).map_err(|e| write_err(root, e))?;
+        }
+        for (path, contents) in &self.files {
+            if let Some(reason) = project_file_error(path) {
+                return Err(McpError::invalid_params(reason, None));
+            }
+            write(path, contents).map_err(|e| write_err(path, e))?;
+        }
+        // Scratch projects are scaffolded with both already in the manifest
+        if self.working_dir.is_some() {
+            if !self.dependencies.is_empty() {
+                write_dependencies(project_path, &self.dependencies)?;
+            }
+            if let Some(edition) = &self.edition {
+                write_edition(project_path, edition)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Directories and files cargo or rustup read configuration from. A project
+/// bringing its own could point cargo at another rustc, linker, flags,
+/// environment or registry, or rustup at another toolchain.
+const CONFIG_NAMES: &[&str] = &[".cargo", "rust-toolchain", "rust-toolchain.toml"];
+
+/// Why `path` can't be written into a project, if it can't: it must stay
+/// inside the project and mustn't be cargo or rustup configuration
+pub(crate) fn project_file_error(path: &Path) -> Option<String> {
+    if !path
+        .components()
+        .all(|component| matches!(component, Component::Normal(_)))
+    {
+        return Some(format!(
+            "file paths must be relative to the project, got: {}",
+            path.display()
+        ));
+    }
+    // At any depth, and in any case for case-insensitive file systems
+    let config = path.components().find_map(|component| {
+        let name = component.as_os_str().to_str()?;
+        CONFIG_NAMES
+            .iter()
+            .find(|config| name.eq_ignore_ascii_case(config))
+    })?;
+    Some(format!(
+        "{} can't be among the files: cargo and rustup configuration isn't accepted, got: {}",
+        config,
+        path.display()
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn builder_runs_a_library_with_extra_files_and_env() {
+        let target = tempfile::tempdir().unwrap();
+        let result = ToolInvocation::new("mod util;\npub use util::double;\n")
+            .project_name("doubler")
+            .crate_type(CrateType::Lib)
+            .file("src/util.rs", "pub fn double(n: u32) -> u32 { n * 2 }\n")
+            .file(
+                "tests/it.rs",
+                "#[test]\nfn doubles() { assert_eq!(doubler::double(2), 4); assert_eq!(env!(\"RUN_MODE\"), \"ci\"); }\n",
+            )
+            .env("RUN_MODE", "ci")
+            .target_dir(target.path())
+            .args(["test", "--test", "it"])
+            .keep_project(true)
+            .run()
+            .await
+            .unwrap();
+        assert_eq!(result.status, 0, "{}", result.stderr);
+        assert!(result.stderr.contains("doubler"));
+        assert!(target.path().join("debug").is_dir());
+        let project = result.project_path.unwrap();
+        assert!(project.join("src").join("util.rs").is_file());
+        std::fs::remove_dir_all(project).unwrap();
+
+        let escaped = ToolInvocation::from_files()
+            .file("../outside.rs", "")
+            .arg("check")
+            .run()
+            .await
+            .unwrap_err();
+        assert!(escaped.message.contains("relative to the project"));
+
+        let injected = ToolInvocation::new("fn main() {}")
+            .dependency("serde = \"1\"\n[patch.crates-io]\nserde", "1.0")
+            .arg("check")
+            .run()
+            .await
+            .unwrap_err();
+        assert!(injected.message.contains("invalid dependency name"));
+    }
+
+    #[tokio::test]
+    async fn builder_scaffolds_the_requested_edition() {
+        // `async` is only a keyword from 2018 on
+        let code = "fn main() {\n    let async = 1;\n    let _ = async;\n}\n";
+        let result = ToolInvocation::new(code)
+            .edition("2015")
+            .arg("check")
+            .keep_project(true)
+            .run()
+            .await
+            .unwrap();
+        assert_eq!(result.status, 0, "{}", result.stderr);
+        let project = result.project_path.unwrap();
+        let manifest = std::fs::read_to_string(project.join("Cargo.toml")).unwrap();
+        assert!(manifest.contains("edition = \"2015\""));
+        std::fs::remove_dir_all(project).unwrap();
+
+        let invalid = ToolInvocation::new(code)
+            .edition("2015\"\n[lib]")
+            .arg("check")
+            .run()
+            .await
+            .unwrap_err();
+        assert!(invalid.message.contains("invalid edition"));
+    }
+
+    #[tokio::test]
+    async fn builder_reports_timeouts_and_runs_in_a_working_dir() {
+        let project = ScratchProject::create(&ChildEnv::default()).unwrap();
+        let result = ToolInvocation::new("fn main() { loop {} }")
+            .working_dir(project.path())
+            .arg("run")
+            .timeout(Duration::from_secs(5))
+            .run()
+            .await
+            .unwrap();
+        assert!(result.timed_out);
+        assert_eq!(result.status, -1);
+        assert_eq!(result.project_path.as_deref(), Some(project.path()));
+    }
+}
@@ -0,0 +1,3808 @@
+//! The tools the server offers. Each is registered once, with its `args` type
+//! and the handler `call_tool` dispatches to. The schema `list_tools` reports
+//! is generated from the type and every call is deserialized into it, defaults
+//! included, before its handler gets it, so the two can't drift apart.
+//! Embedders can add their own with `RustyToolsServer::register_tool`.
+
+use super::*;
+use args::ToolArgs;
+use rmcp::model::JsonObject;
+use schemars::JsonSchema;
+use serde::de::DeserializeOwned;
+use std::pin::Pin;
+
+/// What a tool call returns
+pub type ToolOutput = Result<CallToolResult, McpError>;
+
+/// A running tool call
+pub type ToolFuture<'a> = Pin<Box<dyn Future<Output = ToolOutput> + Send + 'a>>;
+
+/// One tool: how it is described to clients and how it runs
+pub trait ToolHandler: Send + Sync {
+    fn name(&self) -> &str;
+
+    fn description(&self) -> &str;
+
+    /// JSON schema of the tool's arguments
+    fn schema(&self) -> Arc<JsonObject>;
+
+    /// Run a call with the server's pools, database and configuration
+    fn execute<'a>(
+        &'a self,
+        server: &'a RustyToolsServer,
+        request: CallToolRequestParam,
+    ) -> ToolFuture<'a>;
+
+    /// The tool as `list_tools` reports it
+    fn tool(&self) -> Tool {
+        Tool::new(
+            self.name().to_string(),
+            self.description().to_string(),
+            self.schema(),
+        )
+    }
+}
+
+/// Registered tools by name, in registration order
+#[derive(Clone, Default)]
+pub struct ToolRegistry {
+    handlers: Vec<Arc<dyn ToolHandler>>,
+}
+
+impl ToolRegistry {
+    /// Add a tool, replacing any registered under the same name
+    pub fn register(&mut self, handler: impl ToolHandler + 'static) {
+        let handler: Arc<dyn ToolHandler> = Arc::new(handler);
+        match self
+            .handlers
+            .iter_mut()
+            .find(|existing| existing.name() == handler.name())
+        {
+            Some(existing) => *existing = handler,
+            None => self.handlers.push(handler),
+        }
+    }
+
+    pub fn get(&self, name: &str) -> Option<&dyn ToolHandler> {
+        self.handlers
+            .iter()
+            .find(|handler| handler.name() == name)
+            .map(|handler| handler.as_ref())
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &dyn ToolHandler> {
+        self.handlers.iter().map(|handler| handler.as_ref())
+    }
+
+    pub fn list(&self) -> Vec<Tool> {
+        self.iter().map(ToolHandler::tool).collect()
+    }
+}
+
+/// A server method running a tool with its call's arguments deserialized, and
+/// the `Options` among them
+type Run<A> = for<'a> fn(&'a RustyToolsServer, args::Options, A) -> ToolFuture<'a>;
+
+/// A `Run` behind the deserialization of its arguments
+type Dispatch =
+    Box<dyn for<'a> Fn(&'a RustyToolsServer, CallToolRequestParam) -> ToolFuture<'a> + Send + Sync>;
+
+/// A built-in tool: its schema, and the server method that runs it once a
+/// call's arguments have been deserialized into its `args` type
+struct BuiltinTool {
+    tool: Tool,
+    run: Dispatch,
+}
+
+impl ToolHandler for BuiltinTool {
+    fn name(&self) -> &str {
+        &self.tool.name
+    }
+
+    fn description(&self) -> &str {
+        self.tool.description.as_deref().unwrap_or_default()
+    }
+
+    fn schema(&self) -> Arc<JsonObject> {
+        Arc::clone(&self.tool.input_schema)
+    }
+
+    fn execute<'a>(
+        &'a self,
+        server: &'a RustyToolsServer,
+        request: CallToolRequestParam,
+    ) -> ToolFuture<'a> {
+        (self.run)(server, request)
+    }
+
+    fn tool(&self) -> Tool {
+        self.tool.clone()
+    }
+}
+
+/// A tool taking arguments of type `A`, which its schema is generated from
+fn handler<A: ToolArgs + DeserializeOwned + JsonSchema + Send + 'static>(
+    name: &'static str,
+    description: &'static str,
+    run: Run<A>,
+) -> BuiltinTool {
+    BuiltinTool {
+        tool: Tool::new(name, description, args::schema::<A>()),
+        run: Box::new(
+            move |server, request| match args::parse::<A>(request.arguments.as_ref()) {
+                Ok(args) => {
+                    let options = args.options();
+                    Box::pin(
+                        server.run_builtin(request, options, move |options| {
+                            run(server, options, args)
+                        }),
+                    )
+                }
+                Err(e) => Box::pin(std::future::ready(Err(e))),
+            },
+        ),
+    }
+}
+
+/// The built-in tools, in the order `list_tools` reports them
+pub(crate) fn builtin() -> ToolRegistry {
+    let mut registry = ToolRegistry::default();
+    for handler in [
+        handler::<args::CargoFmtArgs>(
+            "cargo_fmt",
+            "Format Rust code using rustfmt",
+            |server, options, args| Box::pin(server.handle_cargo_fmt(options, args)),
+        ),
+        handler::<args::CargoClippyArgs>(
+            "cargo_clippy",
+            "Analyze code with clippy for improvements",
+            |server, options, args| Box::pin(server.handle_cargo_clippy(options, args)),
+        ),
+        handler::<args::CargoCheckArgs>(
+            "cargo_check",
+            "Type-check Rust code without building",
+            |server, options, args| Box::pin(server.handle_cargo_check(options, args)),
+        ),
+        handler::<args::RustcExplainArgs>(
+            "rustc_explain",
+            "Explain a Rust compiler error code",
+            |server, options, args| Box::pin(server.handle_rustc_explain(options, args)),
+        ),
+        handler::<args::CargoFixArgs>(
+            "cargo_fix",
+            "Automatically fix compiler warnings",
+            |server, options, args| Box::pin(server.handle_cargo_fix(options, args)),
+        ),
+        handler::<args::ApplySuggestionArgs>(
+            "apply_suggestion",
+            "Apply one compiler suggestion to code and check that the result compiles",
+            |server, options, args| Box::pin(server.handle_apply_suggestion(options, args)),
+        ),
+        handler::<args::CargoAuditArgs>(
+            "cargo_audit",
+            "Scan for security vulnerabilities in dependencies",
+            |server, options, args| Box::pin(server.handle_cargo_audit(options, args)),
+        ),
+        handler::<args::CargoDenyArgs>(
+            "cargo_deny",
+            "Check dependencies against advisory, license, ban and source policies with cargo deny",
+            |server, options, args| Box::pin(server.handle_cargo_deny(options, args)),
+        ),
+        handler::<args::CargoCoverageArgs>(
+            "cargo_coverage",
+            "Run tests under cargo llvm-cov and report line, region and function coverage",
+            |server, options, args| Box::pin(server.handle_cargo_coverage(options, args)),
+        ),
+        handler::<args::CargoTestArgs>(
+            "cargo_test",
+            "Run tests on Rust code",
+            |server, options, args| Box::pin(server.handle_cargo_test(options, args)),
+        ),
+        handler::<args::CargoBuildArgs>(
+            "cargo_build",
+            "Build Rust code (produces artifacts)",
+            |server, options, args| Box::pin(server.handle_cargo_build(options, args)),
+        ),
+        handler::<args::CargoSearchArgs>(
+            "cargo_search",
+            "Search crates.io for packages",
+            |server, options, args| Box::pin(server.handle_cargo_search(options, args)),
+        ),
+        handler::<args::CargoTreeArgs>(
+            "cargo_tree",
+            "Show dependency tree for Rust code",
+            |server, options, args| Box::pin(server.handle_cargo_tree(options, args)),
+        ),
+        handler::<args::CargoUpdateArgs>(
+            "cargo_update",
+            "Report what cargo update would change in Cargo.lock: each package's old and new version and whether the bump is semver-compatible",
+            |server, options, args| Box::pin(server.handle_cargo_update(options, args)),
+        ),
+        handler::<args::CargoDepsReportArgs>(
+            "cargo_deps_report",
+            "Report where every dependency comes from (crates.io, registry, git, path) with its license and repository, flagging proc-macro and build-script crates and git dependencies that follow a branch",
+            |server, options, args| Box::pin(server.handle_cargo_deps_report(options, args)),
+        ),
+        handler::<args::FindDuplicateDepsArgs>(
+            "find_duplicate_deps",
+            "List crates present in more than one version in the dependency graph (cargo tree --duplicates)",
+            |server, options, args| Box::pin(server.handle_find_duplicate_deps(options, args)),
+        ),
+        handler::<args::CargoAnalyzeArgs>(
+            "cargo_analyze",
+            "Run several checks (fmt, clippy, check, test, audit) on one snippet in a single project and return per-check results",
+            |server, options, args| Box::pin(server.handle_cargo_analyze(options, args)),
+        ),
+        handler::<args::PipelineArgs>(
+            "pipeline",
+            "Run snippet tools in order (e.g. cargo_fmt, cargo_check, cargo_clippy, cargo_test) in one project sharing its build, returning per-step results",
+            |server, options, args| Box::pin(server.handle_pipeline(options, args)),
+        ),
+        handler::<args::CargoMacheteArgs>(
+            "cargo_machete",
+            "List dependencies declared in Cargo.toml but never referenced in the code",
+            |server, options, args| Box::pin(server.handle_cargo_machete(options, args)),
+        ),
+        handler::<args::CargoDocArgs>(
+            "cargo_doc",
+            "Generate documentation for Rust code",
+            |server, options, args| Box::pin(server.handle_cargo_doc(options, args)),
+        ),
+        handler::<args::CargoBloatArgs>(
+            "cargo_bloat",
+            "Build Rust code in release mode and report binary size with the largest functions and crates",
+            |server, options, args| Box::pin(server.handle_cargo_bloat(options, args)),
+        ),
+        handler::<args::CargoFeatureCheckArgs>(
+            "cargo_feature_check",
+            "Run cargo check --no-default-features for each combination of declared features and return a pass/fail matrix",
+            |server, options, args| Box::pin(server.handle_cargo_feature_check(options, args)),
+        ),
+        handler::<args::RustAnalyzerArgs>(
+            "rust_analyzer",
+            "Query rust-analyzer about Rust code: diagnostics, hover, completions, go-to-definition or a symbol outline",
+            |server, options, args| Box::pin(server.handle_rust_analyzer(options, args)),
+        ),
+        handler::<args::RustParseArgs>(
+            "rust_parse",
+            "Check that Rust code parses, in-process with syn and without running cargo, returning syntax errors or an outline of the top-level items",
+            |server, options, args| Box::pin(server.handle_rust_parse(options, args)),
+        ),
+        handler::<args::RustUnusedArgs>(
+            "rust_unused",
+            "Report unused variables, imports, mut bindings and dead code, compiling with the unused and dead_code lints forced on and grouping the findings by lint",
+            |server, options, args| Box::pin(server.handle_rust_unused(options, args)),
+        ),
+        handler::<args::CheckMsrvArgs>(
+            "check_msrv",
+            "Check that code compiles on an older Rust release, installing that toolchain with rustup when needed, and report the first error",
+            |server, options, args| Box::pin(server.handle_check_msrv(options, args)),
+        ),
+        handler::<args::SemverCheckArgs>(
+            "semver_check",
+            "Check a library for breaking API changes against a prior version of its code or a published release with cargo-semver-checks, listing each breaking change",
+            |server, options, args| Box::pin(server.handle_semver_check(options, args)),
+        ),
+        handler::<args::RustOutlineArgs>(
+            "rust_outline",
+            "Nested outline of Rust code parsed with syn: modules, functions with signatures, structs and enums with fields and variants, impls, visibility, modifiers, generics, attributes, doc comments and line ranges. Code that doesn't fully parse yields a partial outline",
+            |server, options, args| Box::pin(server.handle_rust_outline(options, args)),
+        ),
+        handler::<args::CodeMetricsArgs>(
+            "code_metrics",
+            "Lines of code, counts of functions, structs, enums, traits, impls and modules, and a rough cyclomatic complexity per function, computed in-process with syn",
+            |server, options, args| Box::pin(server.handle_code_metrics(options, args)),
+        ),
+        handler::<args::RustTypeOfArgs>(
+            "rust_type_of",
+            "Infer the type of an expression, or of the identifier at a line/column, via rust-analyzer hover or a deliberate `let _: () = expr;` type error",
+            |server, options, args| Box::pin(server.handle_rust_type_of(options, args)),
+        ),
+        handler::<args::CodeIntelArgs>(
+            "code_intel",
+            "Hover info and the definition location of the symbol at a line/column, from rust-analyzer",
+            |server, options, args| Box::pin(server.handle_code_intel(options, args)),
+        ),
+        handler::<args::CargoHistoryArgs>(
+            "cargo_history",
+            "Query past errors from stored analyses with optional filters",
+            |server, options, args| Box::pin(server.handle_cargo_history(options, args)),
+        ),
+        handler::<args::CargoTodosArgs>(
+            "cargo_todos",
+            "Show current todo list from warnings and clippy suggestions",
+            |server, options, args| Box::pin(server.handle_cargo_todos(options, args)),
+        ),
+        handler::<args::TodoIgnoreLintArgs>(
+            "todo_ignore_lint",
+            "Stop turning a clippy lint's findings into todos, optionally until a date, and close its open todos",
+            |server, options, args| Box::pin(server.handle_todo_ignore_lint(options, args)),
+        ),
+        handler::<args::TodoUnignoreLintArgs>(
+            "todo_unignore_lint",
+            "Turn a clippy lint's findings back into todos and reopen the todos ignoring it closed",
+            |server, options, args| Box::pin(server.handle_todo_unignore_lint(options, args)),
+        ),
+        handler::<args::ReconcileTodosArgs>(
+            "reconcile_todos",
+            "Complete open clippy todos whose warning no longer appears in fresh clippy output",
+            |server, options, args| Box::pin(server.handle_reconcile_todos(options, args)),
+        ),
+        handler::<args::DbStatsArgs>(
+            "db_stats",
+            "Show database statistics and stored data counts",
+            |server, options, args| Box::pin(server.handle_db_stats(options, args)),
+        ),
+        handler::<args::MetricsArgs>(
+            "metrics",
+            "Per-tool invocation counts, success rate and average/percentile duration from stored analyses",
+            |server, options, args| Box::pin(server.handle_metrics(options, args)),
+        ),
+        handler::<args::ErrorTrendsArgs>(
+            "error_trends",
+            "Analyses, failures and errors per day or week from stored analyses, with new vs recurring error codes",
+            |server, options, args| Box::pin(server.handle_error_trends(options, args)),
+        ),
+        handler::<args::ErrorCodeStatsArgs>(
+            "error_code_stats",
+            "How often an error code (by default the most frequent one) was hit: occurrences, first and last seen, its distinct messages, the rustc explanation and fixes that worked",
+            |server, options, args| Box::pin(server.handle_error_code_stats(options, args)),
+        ),
+        handler::<args::DbVacuumArgs>(
+            "db_vacuum",
+            "Compact the database file with VACUUM and report the size before and after",
+            |server, options, args| Box::pin(server.handle_db_vacuum(options, args)),
+        ),
+        handler::<args::DbCleanupArgs>(
+            "db_cleanup",
+            "Delete all but the most recent analyses, purge cached tool results and optionally prune the dedicated cargo home",
+            |server, options, args| Box::pin(server.handle_db_cleanup(options, args)),
+        ),
+        handler::<args::DbBackupArgs>(
+            "db_backup",
+            "Snapshot the live database to a file using SQLite's online backup API",
+            |server, options, args| Box::pin(server.handle_db_backup(options, args)),
+        ),
+        handler::<args::DbResetArgs>(
+            "db_reset",
+            "Delete every stored analysis, error, todo and fix, optionally compacting the file afterwards; requires confirm: true",
+            |server, options, args| Box::pin(server.handle_db_reset(options, args)),
+        ),
+        handler::<args::PingArgs>(
+            "ping",
+            "Check the server is responsive: returns ok, uptime and whether persistence is available, without running cargo",
+            |server, options, args| Box::pin(server.handle_ping(options, args)),
+        ),
+        handler::<args::ServerStatusArgs>(
+            "server_status",
+            "Report server version, uptime, persistence, timeouts, in-flight calls, pool statistics and detected toolchain versions",
+            |server, options, args| Box::pin(server.handle_server_status(options, args)),
+        ),
+        handler::<args::DoctorArgs>(
+            "doctor",
+            "Report installed versions of cargo, rustc, rustfmt, clippy, rust-analyzer and optional cargo plugins",
+            |server, options, args| Box::pin(server.handle_doctor(options, args)),
+        ),
+        handler::<args::RerunAnalysisArgs>(
+            "rerun_analysis",
+            "Re-run a stored analysis on its original code and compare with the stored result",
+            |server, options, args| Box::pin(server.handle_rerun_analysis(options, args)),
+        ),
+        handler::<args::CompareAnalysesArgs>(
+            "compare_analyses",
+            "Diff the stored diagnostics of two analyses to see which errors were fixed or introduced",
+            |server, options, args| Box::pin(server.handle_compare_analyses(options, args)),
+        ),
+        handler::<args::DiffAnalysesArgs>(
+            "diff_analyses",
+            "Diff two stored analyses: code, output text, error codes resolved/introduced, and timing",
+            |server, options, args| Box::pin(server.handle_diff_analyses(options, args)),
+        ),
+        handler::<args::GetAnalysisArgs>(
+            "get_analysis",
+            "Fetch one stored analysis by id: its metadata, full output, error rows and the fixes recorded against them",
+            |server, options, args| Box::pin(server.handle_get_analysis(options, args)),
+        ),
+        handler::<args::SearchOutputArgs>(
+            "search_output",
+            "Full-text search across stored analysis output",
+            |server, options, args| Box::pin(server.handle_search_output(options, args)),
+        ),
+    ] {
+        registry.register(handler);
+    }
+    registry
+}
+
+impl RustyToolsServer {
+    async fn handle_cargo_fmt(
+        &self,
+        options: args::Options,
+        args: args::CargoFmtArgs,
+    ) -> ToolOutput {
+        debug!("Executing cargo_fmt");
+        let code = args.code.as_str();
+        validate_rust_code(code)?;
+        let verbosity = options.verbosity()?;
+        let mode = args.mode;
+        let invocation = self.build_invocation("cargo_fmt", &options)?;
+        let mut result = self
+            .run_cargo_tool(&mode.wrap(code), "cargo_fmt", &invocation)
+            .await?;
+        let mut warning = None;
+        if mode != fragment::FragmentMode::File {
+            // rustfmt --emit=stdout prints "<path>:\n\n<formatted source>"
+            let unwrapped = (result.status == 0)
+                .then(|| result.stdout.split_once("\n\n"))
+                .flatten()
+                .and_then(|(path, formatted)| {
+                    let fragment = mode.unwrap(code, formatted)?;
+                    Some(format!("{}\n\n{}", path, fragment))
+                });
+            match unwrapped {
+                Some(stdout) => result.stdout = stdout,
+                None => {
+                    warning = Some(format!(
+                        "the code could not be formatted as an {} fragment, so it was formatted as a file",
+                        mode.name()
+                    ));
+                    result = self.run_cargo_tool(code, "cargo_fmt", &invocation).await?;
+                }
+            }
+        }
+        let mut json_result = tool_response("cargo_fmt", code, &result, verbosity, &invocation);
+        if mode != fragment::FragmentMode::File {
+            json_result["mode"] = json!(mode.name());
+        }
+        if let Some(warning) = warning {
+            json_result["warning"] = json!(warning);
+        }
+        self.persist_analysis(
+            &mut json_result,
+            &options,
+            "cargo_fmt",
+            code,
+            &result,
+            &invocation,
+        );
+        Ok(CallToolResult {
+            content: vec![rmcp::model::Content::text(json_result.to_string())],
+            structured_content: None,
+            meta: None,
+            is_error: Some(result.status != 0),
+        })
+    }
+
+    async fn handle_cargo_clippy(
+        &self,
+        options: args::Options,
+        args: args::CargoClippyArgs,
+    ) -> ToolOutput {
+        debug!("Executing cargo_clippy");
+        let verbosity = options.verbosity()?;
+        let invocation = self.build_invocation("cargo_clippy", &options)?;
+        let code = get_snippet(args.code.as_deref(), "cargo_clippy", &invocation)?;
+        let result = self
+            .run_cargo_tool(code, "cargo_clippy", &invocation)
+            .await?;
+        let mut json_result = tool_response("cargo_clippy", code, &result, verbosity, &invocation);
+        self.persist_analysis(
+            &mut json_result,
+            &options,
+            "cargo_clippy",
+            code,
+            &result,
+            &invocation,
+        );
+        Ok(CallToolResult {
+            content: vec![rmcp::model::Content::text(json_result.to_string())],
+            structured_content: None,
+            meta: None,
+            is_error: Some(result.status != 0),
+        })
+    }
+
+    async fn handle_cargo_check(
+        &self,
+        options: args::Options,
+        args: args::CargoCheckArgs,
+    ) -> ToolOutput {
+        debug!("Executing cargo_check");
+        let verbosity = options.verbosity()?;
+        let invocation = self.build_invocation("cargo_check", &options)?;
+        let code = get_snippet(args.code.as_deref(), "cargo_check", &invocation)?;
+        let stream = args.stream;
+        // Streaming swaps the raw JSON lines sent as progress for
+        // one structured diagnostic per compiler message
+        let progress = progress::current().map(|progress| {
+            if stream {
+                progress.streaming_diagnostics()
+            } else {
+                progress
+            }
+        });
+        let result = progress::scope(
+            progress,
+            self.run_cargo_tool(code, "cargo_check", &invocation),
+        )
+        .await?;
+        let mut json_result = tool_response("cargo_check", code, &result, verbosity, &invocation);
+        self.persist_analysis(
+            &mut json_result,
+            &options,
+            "cargo_check",
+            code,
+            &result,
+            &invocation,
+        );
+        Ok(CallToolResult {
+            content: vec![rmcp::model::Content::text(json_result.to_string())],
+            structured_content: None,
+            meta: None,
+            is_error: Some(result.status != 0),
+        })
+    }
+
+    async fn handle_rustc_explain(
+        &self,
+        _options: args::Options,
+        args: args::RustcExplainArgs,
+    ) -> ToolOutput {
+        debug!("Executing rustc_explain");
+        let error_code = args.error_code.as_str();
+        let explanation = self.explain(error_code)?;
+        let mut json_result = json!({
+            "error_code": error_code,
+            "explanation": explanation.text,
+            "stderr": explanation.stderr,
+            "success": explanation.success
+        });
+        if explanation.cached {
+            json_result["cached"] = json!(true);
+        }
+
+        Ok(CallToolResult {
+            content: vec![rmcp::model::Content::text(json_result.to_string())],
+            structured_content: None,
+            meta: None,
+            is_error: Some(!explanation.success),
+        })
+    }
+
+    async fn handle_cargo_fix(
+        &self,
+        options: args::Options,
+        args: args::CargoFixArgs,
+    ) -> ToolOutput {
+        debug!("Executing cargo_fix");
+        let code = args.code.as_str();
+        validate_rust_code(code)?;
+        let verbosity = options.verbosity()?;
+        let invocation = self.build_invocation("cargo_fix", &options)?;
+        let result = self.run_cargo_tool(code, "cargo_fix", &invocation).await?;
+        let mut json_result = tool_response("cargo_fix", code, &result, verbosity, &invocation);
+        self.persist_analysis(
+            &mut json_result,
+            &options,
+            "cargo_fix",
+            code,
+            &result,
+            &invocation,
+        );
+        Ok(CallToolResult {
+            content: vec![rmcp::model::Content::text(json_result.to_string())],
+            structured_content: None,
+            meta: None,
+            is_error: Some(result.status != 0),
+        })
+    }
+
+    async fn handle_apply_suggestion(
+        &self,
+        options: args::Options,
+        args: args::ApplySuggestionArgs,
+    ) -> ToolOutput {
+        debug!("Executing apply_suggestion");
+        let code = args.code.as_str();
+        let suggestion = &args.suggestion;
+        let edits: Vec<diagnostics::Edit> = match suggestion {
+            Value::Array(_) => serde_json::from_value(suggestion.clone()),
+            _ => serde_json::from_value(suggestion.clone()).map(|edit| vec![edit]),
+        }
+        .map_err(|e| McpError::invalid_params(format!("invalid suggestion: {}", e), None))?;
+        if edits.is_empty() {
+            return Err(McpError::invalid_params("suggestion has no edits", None));
+        }
+        let error_id = args.error_id;
+        let patched = diagnostics::apply_edits(code, &edits)
+            .map_err(|e| McpError::invalid_params(e, None))?;
+        validate_rust_code(&patched)?;
+
+        let invocation = self.build_invocation("cargo_check", &options)?;
+        let result = self
+            .run_cargo_tool(&patched, "cargo_check", &invocation)
+            .await?;
+        let compiles = result.status == 0;
+        let mut json_result = json!({
+            "compiles": compiles,
+            "rustc_version": invocation.details.get("rustc_version"),
+            "patched_code": patched,
+            "diff": output::unified_diff(code, &patched, "original", "patched"),
+            "diagnostics": diagnostics::from_json(&result.stdout),
+            "duration_ms": result.duration_ms
+        });
+        let persist = args.persist;
+        let stored = match &self.db {
+            _ if !persist => Ok(None),
+            _ if matches!(self.mode, PersistenceMode::Disabled) => Err(PERSISTENCE_OFF.to_string()),
+            Some(db) => db
+                .run(|db| db.store_fix(error_id, &suggestion.to_string(), compiles))
+                .map(Some)
+                .map_err(|e| e.to_string()),
+            None => Err(PERSISTENCE_DISABLED.to_string()),
+        };
+        if let Ok(Some(fix_id)) = stored {
+            json_result["fix_id"] = json!(fix_id);
+        }
+        // What is stored is a fix, not an analysis
+        let mut persistence = persistence_report(persist, &stored);
+        persistence["fix_id"] = persistence["analysis_id"].take();
+        persistence["analysis_id"] = Value::Null;
+        json_result["persistence"] = persistence;
+        Ok(CallToolResult {
+            content: vec![rmcp::model::Content::text(json_result.to_string())],
+            structured_content: None,
+            meta: None,
+            is_error: Some(!compiles),
+        })
+    }
+
+    async fn handle_cargo_audit(
+        &self,
+        options: args::Options,
+        args: args::CargoAuditArgs,
+    ) -> ToolOutput {
+        debug!("Executing cargo_audit");
+        let verbosity = options.verbosity()?;
+        let invocation = self.build_invocation("cargo_audit", &options)?;
+        let code = get_snippet(args.code.as_deref(), "cargo_audit", &invocation)?;
+        let result = self
+            .run_cargo_tool(code, "cargo_audit", &invocation)
+            .await?;
+        let mut json_result = tool_response("cargo_audit", code, &result, verbosity, &invocation);
+        self.persist_analysis(
+            &mut json_result,
+            &options,
+            "cargo_audit",
+            code,
+            &result,
+            &invocation,
+        );
+        Ok(CallToolResult {
+            content: vec![rmcp::model::Content::text(json_result.to_string())],
+            structured_content: None,
+            meta: None,
+            is_error: Some(result.status != 0),
+        })
+    }
+
+    async fn handle_cargo_deny(
+        &self,
+        options: args::Options,
+        args: args::CargoDenyArgs,
+    ) -> ToolOutput {
+        debug!("Executing cargo_deny");
+        let code = args.code.as_str();
+        validate_rust_code(code)?;
+        let config = args.config.as_deref();
+        let mut invocation = self.build_invocation("cargo_deny", &options)?;
+        let (result, report) = self.run_deny(code, config, &invocation).await?;
+        invocation
+            .details
+            .insert("custom_config".to_string(), json!(config.is_some()));
+        if let Some(report) = &report {
+            let passed: serde_json::Map<String, Value> = report
+                .categories
+                .iter()
+                .map(|(category, result)| (category.to_string(), json!(result.passed)))
+                .collect();
+            invocation
+                .details
+                .insert("deny".to_string(), Value::Object(passed));
+        }
+        let mut json_result =
+            tool_response("cargo_deny", code, &result, Verbosity::Summary, &invocation);
+        if let Some(report) = &report {
+            json_result["checks"] = json!(report);
+        } else {
+            json_result["stderr"] = json!(output::trim_cargo_noise(&result.stderr));
+        }
+        self.persist_analysis(
+            &mut json_result,
+            &options,
+            "cargo_deny",
+            code,
+            &result,
+            &invocation,
+        );
+        Ok(CallToolResult {
+            content: vec![rmcp::model::Content::text(json_result.to_string())],
+            structured_content: None,
+            meta: None,
+            is_error: Some(!report.as_ref().is_some_and(DenyReport::passed)),
+        })
+    }
+
+    async fn handle_cargo_coverage(
+        &self,
+        options: args::Options,
+        args: args::CargoCoverageArgs,
+    ) -> ToolOutput {
+        debug!("Executing cargo_coverage");
+        let code = args.code.as_str();
+        validate_rust_code(code)?;
+        let verbosity = options.verbosity()?;
+        let mut invocation = self.build_invocation("cargo_coverage", &options)?;
+        let result = self
+            .run_cargo_tool(code, "cargo_coverage", &invocation)
+            .await?;
+        let report = CoverageReport::from_llvm_cov_output(
+            &result.stdout,
+            invocation.details.contains_key("functions"),
+        );
+        if let Some(report) = &report {
+            invocation.details.insert(
+                "coverage".to_string(),
+                json!({
+                    "lines_percent": report.lines.percent,
+                    "regions_percent": report.regions.percent,
+                    "functions_percent": report.functions.percent,
+                    "branches_percent": report.branches.map(|b| b.percent)
+                }),
+            );
+        }
+        let mut json_result =
+            tool_response("cargo_coverage", code, &result, verbosity, &invocation);
+        if let Some(report) = &report
+            && let Some(response) = json_result.as_object_mut()
+        {
+            // The raw export is replaced by the parsed report
+            response.remove("stdout");
+            response.insert("lines".to_string(), json!(report.lines));
+            response.insert("regions".to_string(), json!(report.regions));
+            response.insert("functions".to_string(), json!(report.functions));
+            if let Some(branches) = &report.branches {
+                response.insert("branches".to_string(), json!(branches));
+            }
+            response.insert("files".to_string(), json!(report.files));
+            if let Some(threshold) = invocation.details.get("fail_under") {
+                response.insert(
+                    "below_threshold".to_string(),
+                    json!(threshold.as_f64().is_some_and(|t| report.lines.percent < t)),
+                );
+            }
+            if let Some(per_function) = &report.per_function {
+                response.insert("per_function".to_string(), json!(per_function));
+            }
+        }
+        self.persist_analysis(
+            &mut json_result,
+            &options,
+            "cargo_coverage",
+            code,
+            &result,
+            &invocation,
+        );
+        Ok(CallToolResult {
+            content: vec![rmcp::model::Content::text(json_result.to_string())],
+            structured_content: None,
+            meta: None,
+            is_error: Some(result.status != 0 || report.is_none()),
+        })
+    }
+
+    async fn handle_cargo_test(
+        &self,
+        options: args::Options,
+        args: args::CargoTestArgs,
+    ) -> ToolOutput {
+        debug!("Executing cargo_test");
+        let verbosity = options.verbosity()?;
+        let invocation = self.build_invocation("cargo_test", &options)?;
+        let code = get_snippet(args.code.as_deref(), "cargo_test", &invocation)?;
+        let result = self.run_cargo_tool(code, "cargo_test", &invocation).await?;
+        let mut json_result = tool_response("cargo_test", code, &result, verbosity, &invocation);
+        self.persist_analysis(
+            &mut json_result,
+            &options,
+            "cargo_test",
+            code,
+            &result,
+            &invocation,
+        );
+        Ok(CallToolResult {
+            content: vec![rmcp::model::Content::text(json_result.to_string())],
+            structured_content: None,
+            meta: None,
+            is_error: Some(result.status != 0),
+        })
+    }
+
+    async fn handle_cargo_build(
+        &self,
+        options: args::Options,
+        args: args::CargoBuildArgs,
+    ) -> ToolOutput {
+        debug!("Executing cargo_build");
+        let verbosity = options.verbosity()?;
+        let invocation = self.build_invocation("cargo_build", &options)?;
+        let code = get_snippet(args.code.as_deref(), "cargo_build", &invocation)?;
+        let result = self
+            .run_cargo_tool(code, "cargo_build", &invocation)
+            .await?;
+        let mut json_result = tool_response("cargo_build", code, &result, verbosity, &invocation);
+        self.persist_analysis(
+            &mut json_result,
+            &options,
+            "cargo_build",
+            code,
+            &result,
+            &invocation,
+        );
+        Ok(CallToolResult {
+            content: vec![rmcp::model::Content::text(json_result.to_string())],
+            structured_content: None,
+            meta: None,
+            is_error: Some(result.status != 0),
+        })
+    }
+
+    async fn handle_cargo_search(
+        &self,
+        _options: args::Options,
+        args: args::CargoSearchArgs,
+    ) -> ToolOutput {
+        debug!("Executing cargo_search");
+        let query = args.query.as_str();
+        let output = StdCommand::new("cargo")
+            .args(["search", query])
+            .env_clear()
+            .envs(self.child_env.vars())
+            .output()
+            .map_err(|e| {
+                McpError::internal_error(format!("Failed to run cargo search: {}", e), None)
+            })?;
+
+        let results = String::from_utf8_lossy(&output.stdout).to_string();
+        let stderr = String::from_utf8_lossy(&output.stderr).to_string();
+
+        let json_result = json!({
+            "query": query,
+            "results": results,
+            "stderr": stderr,
+            "success": output.status.success()
+        });
+
+        Ok(CallToolResult {
+            content: vec![rmcp::model::Content::text(json_result.to_string())],
+            structured_content: None,
+            meta: None,
+            is_error: Some(!output.status.success()),
+        })
+    }
+
+    async fn handle_cargo_tree(
+        &self,
+        options: args::Options,
+        args: args::CargoTreeArgs,
+    ) -> ToolOutput {
+        debug!("Executing cargo_tree");
+        let verbosity = options.verbosity()?;
+        let invocation = self.build_invocation("cargo_tree", &options)?;
+        let code = get_snippet(args.code.as_deref(), "cargo_tree", &invocation)?;
+        let result = self.run_cargo_tool(code, "cargo_tree", &invocation).await?;
+        let mut json_result = tool_response("cargo_tree", code, &result, verbosity, &invocation);
+        if invocation
+            .details
+            .get("format")
+            .is_some_and(|f| f == "json")
+            && result.status == 0
+        {
+            json_result["tree"] = json!(output::dependency_tree(&result.stdout));
+        }
+        self.persist_analysis(
+            &mut json_result,
+            &options,
+            "cargo_tree",
+            code,
+            &result,
+            &invocation,
+        );
+        Ok(CallToolResult {
+            content: vec![rmcp::model::Content::text(json_result.to_string())],
+            structured_content: None,
+            meta: None,
+            is_error: Some(result.status != 0),
+        })
+    }
+
+    async fn handle_cargo_update(
+        &self,
+        options: args::Options,
+        args: args::CargoUpdateArgs,
+    ) -> ToolOutput {
+        debug!("Executing cargo_update");
+        let verbosity = options.verbosity()?;
+        let invocation = self.build_invocation("cargo_update", &options)?;
+        let code = match args.code {
+            Some(_) => get_snippet(args.code.as_deref(), "cargo_update", &invocation)?,
+            // Only the dependencies matter
+            None => "fn main() {}",
+        };
+        let result = self
+            .run_cargo_tool(code, "cargo_update", &invocation)
+            .await?;
+        let mut json_result = tool_response("cargo_update", code, &result, verbosity, &invocation);
+        let updates = output::lockfile_updates(&result.stderr);
+        if result.status == 0 {
+            let incompatible = updates
+                .iter()
+                .filter(|update| update.compatible == Some(false))
+                .count();
+            json_result["summary"] = json!(match (updates.len(), incompatible) {
+                (0, _) => "Cargo.lock is up to date".to_string(),
+                (n, 0) => format!("{} package(s) change", n),
+                (n, m) => format!("{} package(s) change, {} semver-incompatible", n, m),
+            });
+        }
+        json_result["updates"] = json!(updates);
+        self.persist_analysis(
+            &mut json_result,
+            &options,
+            "cargo_update",
+            code,
+            &result,
+            &invocation,
+        );
+        Ok(CallToolResult {
+            content: vec![rmcp::model::Content::text(json_result.to_string())],
+            structured_content: None,
+            meta: None,
+            is_error: Some(result.status != 0),
+        })
+    }
+
+    async fn handle_cargo_deps_report(
+        &self,
+        options: args::Options,
+        args: args::CargoDepsReportArgs,
+    ) -> ToolOutput {
+        debug!("Executing cargo_deps_report");
+        let invocation = self.build_invocation("cargo_deps_report", &options)?;
+        let code = get_snippet(args.code.as_deref(), "cargo_deps_report", &invocation)?;
+        let result = self
+            .run_cargo_tool(code, "cargo_deps_report", &invocation)
+            .await?;
+        let mut json_result = tool_response(
+            "cargo_deps_report",
+            code,
+            &result,
+            Verbosity::Summary,
+            &invocation,
+        );
+        match DepsReport::from_metadata(&result.stdout) {
+            Some(report) if result.status == 0 => {
+                let totals = &report.totals;
+                let sources: Vec<String> = totals
+                    .by_source
+                    .iter()
+                    .map(|(source, count)| format!("{} {}", count, source))
+                    .collect();
+                let mut summary = format!(
+                    "{} packages ({}); {} with build scripts, {} proc-macros",
+                    totals.packages,
+                    sources.join(", "),
+                    totals.build_scripts,
+                    totals.proc_macros
+                );
+                if !totals.branch_pinned.is_empty() {
+                    summary.push_str(&format!(
+                        "; {} git dependencies follow a branch",
+                        totals.branch_pinned.len()
+                    ));
+                }
+                json_result["summary"] = json!(summary);
+                json_result["packages"] = json!(report.packages);
+                json_result["totals"] = json!(report.totals);
+            }
+            _ => json_result["stderr"] = json!(output::trim_cargo_noise(&result.stderr)),
+        }
+        self.persist_analysis(
+            &mut json_result,
+            &options,
+            "cargo_deps_report",
+            code,
+            &result,
+            &invocation,
+        );
+        Ok(CallToolResult {
+            content: vec![rmcp::model::Content::text(json_result.to_string())],
+            structured_content: None,
+            meta: None,
+            is_error: Some(result.status != 0),
+        })
+    }
+
+    async fn handle_find_duplicate_deps(
+        &self,
+        options: args::Options,
+        args: args::FindDuplicateDepsArgs,
+    ) -> ToolOutput {
+        debug!("Executing find_duplicate_deps");
+        let code = args.code.as_str();
+        validate_rust_code(code)?;
+        let invocation = Invocation::default();
+        let result = self
+            .run_cargo_tool(code, "find_duplicate_deps", &invocation)
+            .await?;
+        let mut json_result = tool_response(
+            "find_duplicate_deps",
+            code,
+            &result,
+            Verbosity::Summary,
+            &invocation,
+        );
+        if result.status == 0 {
+            json_result["duplicates"] = json!(output::duplicate_deps(&result.stdout));
+        } else {
+            json_result["stderr"] = json!(output::trim_cargo_noise(&result.stderr));
+        }
+        self.persist_analysis(
+            &mut json_result,
+            &options,
+            "find_duplicate_deps",
+            code,
+            &result,
+            &invocation,
+        );
+        Ok(CallToolResult {
+            content: vec![rmcp::model::Content::text(json_result.to_string())],
+            structured_content: None,
+            meta: None,
+            is_error: Some(result.status != 0),
+        })
+    }
+
+    async fn handle_cargo_analyze(
+        &self,
+        options: args::Options,
+        args: args::CargoAnalyzeArgs,
+    ) -> ToolOutput {
+        debug!("Executing cargo_analyze");
+        let code = args.code.as_str();
+        validate_rust_code(code)?;
+        let mut checks: Vec<(String, &'static str)> = Vec::new();
+        for check in args.checks {
+            let tool = analyze_check_tool(&check).ok_or_else(|| {
+                McpError::invalid_params(
+                    format!(
+                        "unknown check {:?}; expected fmt, clippy, check, test or audit",
+                        check
+                    ),
+                    None,
+                )
+            })?;
+            if !checks.iter().any(|(c, _)| *c == check) {
+                checks.push((check, tool));
+            }
+        }
+        if checks.is_empty() {
+            return Err(McpError::invalid_params(
+                "checks must list at least one check",
+                None,
+            ));
+        }
+        let invocation = Invocation {
+            no_cache: args.no_cache,
+            max_output_bytes: args.max_output_bytes.map(|n| n.get() as usize),
+            ..Default::default()
+        };
+        let tools: Vec<&'static str> = checks.iter().map(|(_, tool)| *tool).collect();
+        let batch = self
+            .run_batch(&options, code, &tools, args.fail_fast, &invocation)
+            .await?;
+
+        let mut results = serde_json::Map::new();
+        let mut skipped = Vec::new();
+        for ((check, _), step) in checks.into_iter().zip(batch.steps) {
+            match step {
+                Some(json_result) => {
+                    results.insert(check, json_result);
+                }
+                None => skipped.push(check),
+            }
+        }
+        let (success, batch_id) = (batch.success, batch.batch_id);
+        let json_result = json!({
+            "success": success,
+            "batch_id": batch_id,
+            "checks": results,
+            "skipped": skipped
+        });
+
+        Ok(CallToolResult {
+            content: vec![rmcp::model::Content::text(json_result.to_string())],
+            structured_content: None,
+            meta: None,
+            is_error: Some(!success),
+        })
+    }
+
+    async fn handle_pipeline(
+        &self,
+        options: args::Options,
+        args: args::PipelineArgs,
+    ) -> ToolOutput {
+        debug!("Executing pipeline");
+        let code = args.code.as_str();
+        validate_rust_code(code)?;
+        let mut tools: Vec<&'static str> = Vec::new();
+        for name in &args.tools {
+            let tool = SNIPPET_TOOLS
+                .iter()
+                .find(|tool| **tool == name)
+                .ok_or_else(|| {
+                    McpError::invalid_params(
+                        format!(
+                            "unknown pipeline tool {:?}; expected one of {}",
+                            name,
+                            SNIPPET_TOOLS.join(", ")
+                        ),
+                        None,
+                    )
+                })?;
+            tools.push(tool);
+        }
+        if tools.is_empty() {
+            return Err(McpError::invalid_params(
+                "tools must list at least one tool",
+                None,
+            ));
+        }
+        let invocation = Invocation {
+            no_cache: args.no_cache,
+            max_output_bytes: args.max_output_bytes.map(|n| n.get() as usize),
+            ..Default::default()
+        };
+        let batch = self
+            .run_batch(&options, code, &tools, !args.continue_on_error, &invocation)
+            .await?;
+
+        let steps: Vec<Value> = tools
+            .iter()
+            .zip(batch.steps)
+            .map(|(tool, step)| match step {
+                Some(mut step) => {
+                    step["tool"] = json!(tool);
+                    step
+                }
+                None => json!({"tool": tool, "skipped": true}),
+            })
+            .collect();
+        let json_result = json!({
+            "success": batch.success,
+            "batch_id": batch.batch_id,
+            "steps": steps
+        });
+
+        Ok(CallToolResult {
+            content: vec![rmcp::model::Content::text(json_result.to_string())],
+            structured_content: None,
+            meta: None,
+            is_error: Some(!batch.success),
+        })
+    }
+
+    async fn handle_cargo_machete(
+        &self,
+        options: args::Options,
+        args: args::CargoMacheteArgs,
+    ) -> ToolOutput {
+        debug!("Executing cargo_machete");
+        let code = args.code.as_str();
+        validate_rust_code(code)?;
+        self.requirements.check("cargo_machete")?;
+        let project = self.pool.checkout()?;
+        let manifest = std::fs::read_to_string(project.path().join("Cargo.toml")).map_err(|e| {
+            McpError::internal_error(format!("Failed to read Cargo.toml: {}", e), None)
+        })?;
+
+        let mut invocation = Invocation::default();
+        let (result, unused, method) = if self
+            .requirements
+            .version(&requirements::CARGO_MACHETE)
+            .is_some()
+        {
+            let result = run_in_project(
+                project.path(),
+                code,
+                &["machete"],
+                Some(Duration::from_secs(30)),
+                &[],
+                &self.child_env,
+            )
+            .await?;
+            let unused = deps::machete_unused(&result.stdout);
+            (result, unused, "cargo-machete")
+        } else {
+            let unused = deps::unused_dependencies(&manifest, code);
+            let result = ExecResult {
+                stdout: unused.join("\n"),
+                stderr: String::new(),
+                status: 0,
+                duration_ms: 0,
+                artifact: None,
+                docs_path: None,
+                project_path: None,
+                crate_source: None,
+                cached: false,
+                timed_out: false,
+                resource_exceeded: None,
+                build_code: Vec::new(),
+            };
+            (result, unused, "scan")
+        };
+        invocation
+            .details
+            .insert("method".to_string(), json!(method));
+        // cargo machete exits 1 when it finds unused dependencies
+        let success = !result.timed_out && (result.status == 0 || !unused.is_empty());
+
+        let mut json_result = json!({
+            "success": success,
+            "declared": deps::declared_dependencies(&manifest),
+            "unused": unused,
+            "duration_ms": result.duration_ms
+        });
+        invocation.annotate(&mut json_result);
+        if result.timed_out {
+            json_result["timed_out"] = json!(true);
+        }
+        if !success {
+            json_result["stderr"] = json!(output::trim_cargo_noise(&result.stderr));
+        }
+        self.persist_analysis(
+            &mut json_result,
+            &options,
+            "cargo_machete",
+            code,
+            &result,
+            &invocation,
+        );
+        Ok(CallToolResult {
+            content: vec![rmcp::model::Content::text(json_result.to_string())],
+            structured_content: None,
+            meta: None,
+            is_error: Some(!success),
+        })
+    }
+
+    async fn handle_cargo_doc(
+        &self,
+        options: args::Options,
+        args: args::CargoDocArgs,
+    ) -> ToolOutput {
+        debug!("Executing cargo_doc");
+        let verbosity = options.verbosity()?;
+        let invocation = self.build_invocation("cargo_doc", &options)?;
+        let code = get_snippet(args.code.as_deref(), "cargo_doc", &invocation)?;
+        let result = self.run_cargo_tool(code, "cargo_doc", &invocation).await?;
+        let mut json_result = tool_response("cargo_doc", code, &result, verbosity, &invocation);
+        if invocation.details.contains_key("missing_docs") {
+            json_result["undocumented"] = json!(output::missing_docs(&result.stderr));
+        }
+        self.persist_analysis(
+            &mut json_result,
+            &options,
+            "cargo_doc",
+            code,
+            &result,
+            &invocation,
+        );
+        Ok(CallToolResult {
+            content: vec![rmcp::model::Content::text(json_result.to_string())],
+            structured_content: None,
+            meta: None,
+            is_error: Some(result.status != 0),
+        })
+    }
+
+    async fn handle_cargo_bloat(
+        &self,
+        options: args::Options,
+        args: args::CargoBloatArgs,
+    ) -> ToolOutput {
+        debug!("Executing cargo_bloat");
+        let code = args.code.as_str();
+        validate_rust_code(code)?;
+        let mut invocation = self.build_invocation("cargo_bloat", &options)?;
+        let (result, report) = self
+            .run_bloat(code, &invocation, args.top.get() as usize)
+            .await?;
+        if let Some(report) = &report {
+            invocation.details.insert(
+                "bloat".to_string(),
+                json!({
+                    "method": report.method,
+                    "total_size_bytes": report.total_size_bytes,
+                    "text_size_bytes": report.text_size_bytes
+                }),
+            );
+        }
+        let mut json_result = tool_response(
+            "cargo_bloat",
+            code,
+            &result,
+            Verbosity::Summary,
+            &invocation,
+        );
+        if let Some(report) = &report {
+            json_result["functions"] = json!(report.functions);
+            json_result["crates"] = json!(report.crates);
+        } else {
+            json_result["stderr"] = json!(output::trim_cargo_noise(&result.stderr));
+        }
+        self.persist_analysis(
+            &mut json_result,
+            &options,
+            "cargo_bloat",
+            code,
+            &result,
+            &invocation,
+        );
+        Ok(CallToolResult {
+            content: vec![rmcp::model::Content::text(json_result.to_string())],
+            structured_content: None,
+            meta: None,
+            is_error: Some(report.is_none()),
+        })
+    }
+
+    async fn handle_cargo_feature_check(
+        &self,
+        _options: args::Options,
+        args: args::CargoFeatureCheckArgs,
+    ) -> ToolOutput {
+        debug!("Executing cargo_feature_check");
+        let code = args.code.as_str();
+        validate_rust_code(code)?;
+        let features = args.features;
+        check_feature_table(&features)?;
+        let combinations = feature_combinations(&features, &args.combinations)?;
+        let persist = args.persist;
+        let session = args.session;
+
+        self.requirements.check("cargo_feature_check")?;
+        let (_, timeout) = cargo_tool_command("cargo_check").expect("cargo_check has a command");
+        // One project for every combination so they share target/
+        let project = self.pool.checkout()?;
+        write_feature_manifest(project.path(), &features)?;
+
+        let mut matrix = Vec::with_capacity(combinations.len());
+        let mut failed = 0;
+        for set in &combinations {
+            let joined = normalize_feature_set(set.iter().map(String::as_str));
+            let mut cargo_args = vec!["check", "--no-default-features"];
+            if !set.is_empty() {
+                cargo_args.extend(["--features", joined.as_str()]);
+            }
+            let result = run_in_project(
+                project.path(),
+                code,
+                &cargo_args,
+                timeout,
+                &[],
+                &self.child_env,
+            )
+            .await?;
+            let (error_count, warning_count) =
+                output::count_diagnostics(&result.stdout, &result.stderr);
+            if result.status != 0 {
+                failed += 1;
+            }
+
+            let mut invocation = Invocation::default();
+            invocation.set_toolchain(self.requirements.toolchain());
+            invocation
+                .details
+                .insert("features".to_string(), json!(set));
+            let stored = self.store_analysis_with_errors(
+                "cargo_feature_check",
+                code,
+                &result,
+                &invocation,
+                persist,
+                session.as_deref(),
+            );
+            let persistence = persistence_report(persist, &stored);
+            let analysis_id = stored.ok().flatten();
+            if let (Some(id), Some(db_arc)) = (analysis_id, &self.db)
+                && let Err(e) = db_arc.run(|db| db.set_analysis_features(id, &joined))
+            {
+                warn!("Failed to record feature set: {}", e);
+            }
+
+            matrix.push(json!({
+                "features": set,
+                "success": result.status == 0,
+                "timed_out": result.timed_out,
+                "error_count": error_count,
+                "warning_count": warning_count,
+                "duration_ms": result.duration_ms,
+                "summary": output::summarize(
+                    "cargo_check",
+                    code,
+                    result.status,
+                    &result.stdout,
+                    &result.stderr
+                ),
+                "analysis_id": analysis_id,
+                "persistence": persistence
+            }));
+        }
+
+        let json_result = json!({
+            "combinations": combinations.len(),
+            "rustc_version": self.rustc_version(),
+            "failed": failed,
+            "matrix": matrix
+        });
+
+        Ok(CallToolResult {
+            content: vec![rmcp::model::Content::text(json_result.to_string())],
+            structured_content: None,
+            meta: None,
+            is_error: Some(failed > 0),
+        })
+    }
+
+    async fn handle_rust_analyzer(
+        &self,
+        options: args::Options,
+        args: args::RustAnalyzerArgs,
+    ) -> ToolOutput {
+        debug!("Executing rust_analyzer");
+        let code = args.code.as_str();
+        validate_rust_code(code)?;
+        let action = args.action;
+        if self
+            .requirements
+            .version(&requirements::RUST_ANALYZER)
+            .is_some()
+        {
+            return self.analyze_with_lsp(&options, &args).await;
+        }
+
+        // Without rust-analyzer, report what cargo check finds
+        let verbosity = options.verbosity()?;
+        let invocation = self.build_invocation("rust_analyzer", &options)?;
+        let result = self
+            .run_cargo_tool(code, "rust_analyzer", &invocation)
+            .await?;
+        let mut json_result = tool_response("rust_analyzer", code, &result, verbosity, &invocation);
+        json_result["fallback"] = json!(true);
+        if action != lsp::Action::Diagnostics {
+            json_result["warning"] = json!(format!(
+                "{} needs rust-analyzer ({}); returned cargo check diagnostics instead",
+                action.name(),
+                requirements::RUST_ANALYZER.install_hint
+            ));
+        }
+        self.persist_analysis(
+            &mut json_result,
+            &options,
+            "rust_analyzer",
+            code,
+            &result,
+            &invocation,
+        );
+        Ok(CallToolResult {
+            content: vec![rmcp::model::Content::text(json_result.to_string())],
+            structured_content: None,
+            meta: None,
+            is_error: Some(result.status != 0),
+        })
+    }
+
+    async fn handle_rust_parse(
+        &self,
+        options: args::Options,
+        args: args::RustParseArgs,
+    ) -> ToolOutput {
+        debug!("Executing rust_parse");
+        let code = args.code.as_str();
+        // Nothing is compiled or run, so validate_rust_code's
+        // dangerous-pattern check doesn't apply
+        let started = Instant::now();
+        let parsed = syntax::parse(code);
+        let duration_ms = started.elapsed().as_millis();
+        let (items, errors) = match parsed {
+            Ok(items) => (items, Vec::new()),
+            Err(errors) => (Vec::new(), errors),
+        };
+        let success = errors.is_empty();
+        let mut json_result = json!({
+            "success": success,
+            "errors": errors,
+            "items": items,
+            "duration_ms": duration_ms
+        });
+
+        let result = ExecResult {
+            stdout: json_result.to_string(),
+            stderr: String::new(),
+            status: if success { 0 } else { 1 },
+            duration_ms,
+            artifact: None,
+            docs_path: None,
+            project_path: None,
+            crate_source: None,
+            cached: false,
+            timed_out: false,
+            resource_exceeded: None,
+            build_code: Vec::new(),
+        };
+        if let Some(analysis_id) = self.persist_analysis(
+            &mut json_result,
+            &options,
+            "rust_parse",
+            code,
+            &result,
+            &Invocation::default(),
+        ) && let Some(db) = &self.db
+            && let Err(e) = db.run(|db| {
+                db.transaction(|db| {
+                    for error in &errors {
+                        let stored = db.store_error(
+                            analysis_id,
+                            None,
+                            Some("error"),
+                            &error.message,
+                            Some("src/main.rs"),
+                            Some(error.line as i32),
+                            None,
+                        );
+                        tolerate(stored, "store error")?;
+                    }
+                    Ok(())
+                })
+            })
+        {
+            warn!("Failed to store errors of analysis {}: {}", analysis_id, e);
+        }
+
+        Ok(CallToolResult {
+            content: vec![rmcp::model::Content::text(json_result.to_string())],
+            structured_content: None,
+            meta: None,
+            is_error: Some(!success),
+        })
+    }
+
+    async fn handle_rust_unused(
+        &self,
+        options: args::Options,
+        args: args::RustUnusedArgs,
+    ) -> ToolOutput {
+        debug!("Executing rust_unused");
+        let code = args.code.as_str();
+        validate_rust_code(code)?;
+        let invocation = self.build_invocation("rust_unused", &options)?;
+        let result = self
+            .run_cargo_tool(code, "rust_unused", &invocation)
+            .await?;
+        let diagnostics = diagnostics::from_json(&result.stdout);
+        let findings = unused::findings(code, &diagnostics);
+        let mut json_result = tool_response(
+            "rust_unused",
+            code,
+            &result,
+            Verbosity::Summary,
+            &invocation,
+        );
+        json_result["count"] = json!(findings.len());
+        // Unused-code lints only run once the code compiles
+        if result.status != 0 {
+            let errors: Vec<_> = diagnostics.iter().filter(|d| d.level == "error").collect();
+            json_result["diagnostics"] = json!(errors);
+        }
+        if args.store_as_todos {
+            match &self.db {
+                Some(db) => match db
+                    .run(|db| db.transaction(|db| Self::store_unused_todos(db, &findings)))
+                {
+                    Ok(stored) => json_result["todos_stored"] = json!(stored),
+                    Err(e) => warn!("Failed to store unused todos: {}", e),
+                },
+                None => warn!("Database not initialized, unused todos not stored"),
+            }
+        }
+        json_result["unused"] = json!(unused::group(findings));
+        self.persist_analysis(
+            &mut json_result,
+            &options,
+            "rust_unused",
+            code,
+            &result,
+            &invocation,
+        );
+        Ok(CallToolResult {
+            content: vec![rmcp::model::Content::text(json_result.to_string())],
+            structured_content: None,
+            meta: None,
+            is_error: Some(result.status != 0),
+        })
+    }
+
+    async fn handle_check_msrv(
+        &self,
+        options: args::Options,
+        args: args::CheckMsrvArgs,
+    ) -> ToolOutput {
+        debug!("Executing check_msrv");
+        let code = args.code.as_str();
+        validate_rust_code(code)?;
+        let verbosity = options.verbosity()?;
+        let version = msrv::RustVersion::parse(&args.rust_version)
+            .map_err(|e| McpError::invalid_params(e, None))?;
+        self.requirements.check("check_msrv")?;
+        let installed = self
+            .ensure_toolchain(&version.toolchain(), args.install)
+            .await?;
+        // After installing, so it carries the toolchain's rustc version
+        let invocation = self.build_invocation("check_msrv", &options)?;
+        let result = self.run_cargo_tool(code, "check_msrv", &invocation).await?;
+        let mut json_result = tool_response("check_msrv", code, &result, verbosity, &invocation);
+        json_result["compiles"] = json!(result.status == 0);
+        json_result["first_error"] = json!(
+            diagnostics::from_json(&result.stdout)
+                .into_iter()
+                .find(|d| d.level == "error")
+        );
+        if installed {
+            json_result["installed_toolchain"] = json!(true);
+        }
+        self.persist_analysis(
+            &mut json_result,
+            &options,
+            "check_msrv",
+            code,
+            &result,
+            &invocation,
+        );
+        Ok(CallToolResult {
+            content: vec![rmcp::model::Content::text(json_result.to_string())],
+            structured_content: None,
+            meta: None,
+            is_error: Some(result.status != 0),
+        })
+    }
+
+    async fn handle_semver_check(
+        &self,
+        options: args::Options,
+        args: args::SemverCheckArgs,
+    ) -> ToolOutput {
+        debug!("Executing semver_check");
+        let code = args.code.as_str();
+        validate_rust_code(code)?;
+        let baseline = match (
+            args.baseline_code.as_deref(),
+            args.baseline_version.as_deref(),
+            args.crate_name.as_deref(),
+        ) {
+            (Some(baseline_code), None, _) => {
+                validate_rust_code(baseline_code)?;
+                SemverBaseline::Code(baseline_code)
+            }
+            (None, Some(version), Some(crate_name)) => {
+                if !semver::is_exact_version(version) {
+                    return Err(McpError::invalid_params(
+                        format!(
+                            "baseline_version must be a published version such as 1.2.0, got: {}",
+                            version
+                        ),
+                        None,
+                    ));
+                }
+                if !is_package_spec(crate_name) || crate_name.contains('@') {
+                    return Err(McpError::invalid_params(
+                        format!("invalid crate name: {:?}", crate_name),
+                        None,
+                    ));
+                }
+                SemverBaseline::Published {
+                    crate_name,
+                    version,
+                }
+            }
+            (None, Some(_), None) => {
+                return Err(McpError::invalid_params(
+                    "crate_name is required with baseline_version",
+                    None,
+                ));
+            }
+            _ => {
+                return Err(McpError::invalid_params(
+                    "pass either baseline_code or baseline_version",
+                    None,
+                ));
+            }
+        };
+        let mut invocation = self.build_invocation("semver_check", &options)?;
+        let (result, report) = self.run_semver_checks(code, &baseline, &invocation).await?;
+        let baseline_details = match &baseline {
+            SemverBaseline::Code(baseline_code) => {
+                json!({"code_hash": code_hash(baseline_code)})
+            }
+            SemverBaseline::Published {
+                crate_name,
+                version,
+            } => json!({"crate_name": crate_name, "version": version}),
+        };
+        invocation
+            .details
+            .insert("baseline".to_string(), baseline_details);
+        let mut json_result = tool_response(
+            "semver_check",
+            code,
+            &result,
+            Verbosity::Summary,
+            &invocation,
+        );
+        let compatible = report.as_ref().map(SemverReport::compatible);
+        json_result["compatible"] = json!(compatible);
+        if let Some(report) = &report {
+            let breaking: Vec<&SemverChange> = report
+                .changes
+                .iter()
+                .filter(|change| change.level == "failure")
+                .collect();
+            json_result["required_bump"] = json!(report.required_bump);
+            json_result["checks"] = json!(report.checks);
+            json_result["breaking_changes"] = json!(breaking);
+            json_result["warnings"] = json!(
+                report
+                    .changes
+                    .iter()
+                    .filter(|change| change.level != "failure")
+                    .collect::<Vec<_>>()
+            );
+            json_result["summary"] = json!(match (breaking.len(), &report.required_bump) {
+                (0, _) => "no breaking changes".to_string(),
+                (n, Some(bump)) =>
+                    format!("{} breaking change(s); requires a new {} version", n, bump),
+                (n, None) => format!("{} breaking change(s)", n),
+            });
+        } else {
+            json_result["stderr"] = json!(output::trim_cargo_noise(&result.stderr));
+        }
+        self.persist_analysis(
+            &mut json_result,
+            &options,
+            "semver_check",
+            code,
+            &result,
+            &invocation,
+        );
+        Ok(CallToolResult {
+            content: vec![rmcp::model::Content::text(json_result.to_string())],
+            structured_content: None,
+            meta: None,
+            is_error: Some(compatible != Some(true)),
+        })
+    }
+
+    async fn handle_rust_outline(
+        &self,
+        _options: args::Options,
+        args: args::RustOutlineArgs,
+    ) -> ToolOutput {
+        debug!("Executing rust_outline");
+        let code = args.code.as_str();
+        // Parsed in-process like rust_parse; nothing is run
+        let started = Instant::now();
+        let outline = outline::outline(code);
+        let success = outline.errors.is_empty();
+        // A partial outline is still an answer
+        let failed = !success && outline.items.is_empty();
+        let json_result = match args.item.as_deref() {
+            Some(query) => {
+                let found = outline::find(&outline.items, query);
+                if found.is_empty() {
+                    let names: Vec<_> = outline
+                        .items
+                        .iter()
+                        .map(|item| item.name.as_str())
+                        .collect();
+                    return Err(McpError::invalid_params(
+                        format!(
+                            "no item named {}; top-level items: {}",
+                            query,
+                            names.join(", ")
+                        ),
+                        None,
+                    ));
+                }
+                let matches: Vec<Value> = found
+                    .into_iter()
+                    .map(|item| {
+                        let mut found = json!(item);
+                        found["source"] = json!(outline::source_of(code, item));
+                        found
+                    })
+                    .collect();
+                json!({
+                    "success": success,
+                    "item": query,
+                    "matches": matches,
+                    "errors": outline.errors,
+                    "duration_ms": started.elapsed().as_millis()
+                })
+            }
+            None => json!({
+                "success": success,
+                "partial": !success,
+                "items": outline.items,
+                "errors": outline.errors,
+                "duration_ms": started.elapsed().as_millis()
+            }),
+        };
+        Ok(CallToolResult {
+            content: vec![rmcp::model::Content::text(json_result.to_string())],
+            structured_content: None,
+            meta: None,
+            is_error: Some(failed),
+        })
+    }
+
+    async fn handle_code_metrics(
+        &self,
+        _options: args::Options,
+        args: args::CodeMetricsArgs,
+    ) -> ToolOutput {
+        debug!("Executing code_metrics");
+        let code = args.code.as_str();
+        // Parsed in-process like rust_parse; nothing is run
+        let started = Instant::now();
+        let measured = code_metrics::measure(code);
+        let success = measured.is_ok();
+        let json_result = match measured {
+            Ok(metrics) => {
+                let mut json_result = json!(metrics);
+                json_result["success"] = json!(true);
+                json_result["duration_ms"] = json!(started.elapsed().as_millis());
+                json_result
+            }
+            Err(errors) => json!({
+                "success": false,
+                "errors": errors,
+                "duration_ms": started.elapsed().as_millis()
+            }),
+        };
+        Ok(CallToolResult {
+            content: vec![rmcp::model::Content::text(json_result.to_string())],
+            structured_content: None,
+            meta: None,
+            is_error: Some(!success),
+        })
+    }
+
+    async fn handle_rust_type_of(
+        &self,
+        options: args::Options,
+        args: args::RustTypeOfArgs,
+    ) -> ToolOutput {
+        debug!("Executing rust_type_of");
+        validate_rust_code(&args.code)?;
+        self.type_of(&options, &args).await
+    }
+
+    async fn handle_code_intel(
+        &self,
+        _options: args::Options,
+        args: args::CodeIntelArgs,
+    ) -> ToolOutput {
+        debug!("Executing code_intel");
+        let code = args.code.as_str();
+        validate_rust_code(code)?;
+        let position = lsp::Position {
+            line: args.line.get(),
+            column: args.column.get(),
+        };
+        self.requirements.check("code_intel")?;
+
+        let started = Instant::now();
+        let session = args.session.as_deref().unwrap_or(DEFAULT_LSP_SESSION);
+        let query = |action| lsp::Query {
+            action,
+            code,
+            position: Some(position),
+            limit: 0,
+        };
+        let hover = self.lsp.run(session, query(lsp::Action::Hover)).await?;
+        let definitions = self
+            .lsp
+            .run(session, query(lsp::Action::Definition))
+            .await?;
+
+        let mut hover = hover["hover"].clone();
+        if let Some(inferred) = hover["contents"].as_str().and_then(type_of::from_hover) {
+            hover["type"] = json!(inferred.ty);
+        }
+        // The first target is the definition; rust-analyzer lists more only
+        // for items defined in several places, e.g. by a macro
+        let definition = definitions["definitions"].get(0).map(|target| {
+            let file = match target["in_code"].as_bool() {
+                Some(true) => json!("src/main.rs"),
+                _ => target["file"].clone(),
+            };
+            json!({
+                "file": file,
+                "line": target["range"]["start"]["line"],
+                "col": target["range"]["start"]["column"],
+                "in_code": target["in_code"]
+            })
+        });
+        let json_result = json!({
+            "success": true,
+            "line": position.line,
+            "column": position.column,
+            "hover": hover,
+            "definition": definition,
+            "duration_ms": started.elapsed().as_millis()
+        });
+
+        Ok(CallToolResult {
+            content: vec![rmcp::model::Content::text(json_result.to_string())],
+            structured_content: None,
+            meta: None,
+            is_error: Some(false),
+        })
+    }
+
+    async fn handle_cargo_history(
+        &self,
+        _options: args::Options,
+        args: args::CargoHistoryArgs,
+    ) -> ToolOutput {
+        debug!("Executing cargo_history");
+        let filter = HistoryFilter {
+            error_code: args.error_code,
+            severity: args.severity,
+            tool: args.tool,
+            success: args.success,
+            file: args.file,
+            since: args.since,
+            until: args.until,
+            session: args.session,
+            features: args
+                .features
+                .map(|set| normalize_feature_set(set.split(',').map(str::trim))),
+            batch_id: args.batch_id,
+            limit: Some(args.limit as usize),
+            offset: Some(args.offset as usize),
+        };
+
+        if let Some(severity) = &filter.severity
+            && !SEVERITIES.contains(&severity.as_str())
+        {
+            return Err(McpError::invalid_params(
+                format!(
+                    "severity must be one of {}, got: {}",
+                    SEVERITIES.join(", "),
+                    severity
+                ),
+                None,
+            ));
+        }
+        for (name, value) in [("since", &filter.since), ("until", &filter.until)] {
+            if let Some(ts) = value
+                && !is_iso8601_timestamp(ts)
+            {
+                return Err(McpError::invalid_params(
+                    format!("{} must be an ISO-8601 timestamp, got: {}", name, ts),
+                    None,
+                ));
+            }
+        }
+
+        let Some(ref db_arc) = self.db else {
+            return Err(McpError::internal_error("Database not available", None));
+        };
+
+        let history = db_arc
+            .run(|db| db.get_error_history(&filter))
+            .map_err(|e| {
+                McpError::internal_error(format!("Failed to query history: {}", e), None)
+            })?;
+        let total_matching = db_arc
+            .run(|db| db.count_error_history(&filter))
+            .map_err(|e| {
+                McpError::internal_error(format!("Failed to count history: {}", e), None)
+            })?;
+        let mut json_result = json!({
+            "error_code": filter.error_code,
+            "severity": filter.severity,
+            "tool": filter.tool,
+            "success": filter.success,
+            "file": filter.file,
+            "since": filter.since,
+            "until": filter.until,
+            "session": filter.session,
+            "features": filter.features,
+            "batch_id": filter.batch_id,
+            "limit": filter.limit,
+            "offset": filter.offset,
+            "total_matching": total_matching,
+            "results": history
+        });
+        if args.include_code {
+            let code = db_arc
+                .run(|db| {
+                    db.get_code_by_hash(history.iter().filter_map(|e| e.code_hash.as_deref()))
+                })
+                .map_err(|e| {
+                    McpError::internal_error(format!("Failed to load analyzed code: {}", e), None)
+                })?;
+            json_result["code"] = json!(code);
+        }
+
+        Ok(CallToolResult {
+            content: vec![rmcp::model::Content::text(json_result.to_string())],
+            structured_content: None,
+            meta: None,
+            is_error: Some(false),
+        })
+    }
+
+    async fn handle_cargo_todos(
+        &self,
+        _options: args::Options,
+        args: args::CargoTodosArgs,
+    ) -> ToolOutput {
+        debug!("Executing cargo_todos");
+        let show_completed = args.show_completed;
+        let source = args.source.as_deref();
+
+        let Some(ref db_arc) = self.db else {
+            return Err(McpError::internal_error("Database not available", None));
+        };
+
+        let todos = db_arc
+            .run(|db| db.get_todos(show_completed, source))
+            .map_err(|e| McpError::internal_error(format!("Failed to query todos: {}", e), None))?;
+        let ignores = db_arc.run(|db| db.get_lint_ignores()).map_err(|e| {
+            McpError::internal_error(format!("Failed to query lint ignores: {}", e), None)
+        })?;
+
+        let mut json_result = json!({
+            "show_completed": show_completed,
+            "source": source,
+            "suppressed": ignores.iter().map(|ignore| ignore.suppressed).sum::<usize>()
+        });
+        if args.group_by_source {
+            let mut groups: std::collections::BTreeMap<String, Vec<TodoRecord>> =
+                std::collections::BTreeMap::new();
+            for todo in todos {
+                groups.entry(todo.source.clone()).or_default().push(todo);
+            }
+            json_result["todos_by_source"] = json!(groups);
+        } else {
+            json_result["todos"] = json!(todos);
+        }
+        if args.list_ignores {
+            json_result["ignores"] = json!(ignores);
+        }
+
+        Ok(CallToolResult {
+            content: vec![rmcp::model::Content::text(json_result.to_string())],
+            structured_content: None,
+            meta: None,
+            is_error: Some(false),
+        })
+    }
+
+    async fn handle_todo_ignore_lint(
+        &self,
+        _options: args::Options,
+        args: args::TodoIgnoreLintArgs,
+    ) -> ToolOutput {
+        debug!("Executing todo_ignore_lint");
+        let lint = full_lint_name(&args.lint)?;
+        let until = args.until.as_deref();
+        if let Some(ts) = until
+            && !is_iso8601_timestamp(ts)
+        {
+            return Err(McpError::invalid_params(
+                format!("until must be an ISO-8601 date or timestamp, got: {}", ts),
+                None,
+            ));
+        }
+
+        let Some(ref db_arc) = self.db else {
+            return Err(McpError::internal_error("Database not available", None));
+        };
+
+        let closed = db_arc
+            .run(|db| db.ignore_lint(&lint, until))
+            .map_err(|e| McpError::internal_error(format!("Failed to ignore lint: {}", e), None))?;
+
+        let json_result = json!({
+            "lint": lint,
+            "expires_at": until,
+            "closed_todos": closed
+        });
+
+        Ok(CallToolResult {
+            content: vec![rmcp::model::Content::text(json_result.to_string())],
+            structured_content: None,
+            meta: None,
+            is_error: Some(false),
+        })
+    }
+
+    async fn handle_todo_unignore_lint(
+        &self,
+        _options: args::Options,
+        args: args::TodoUnignoreLintArgs,
+    ) -> ToolOutput {
+        debug!("Executing todo_unignore_lint");
+        let lint = full_lint_name(&args.lint)?;
+
+        let Some(ref db_arc) = self.db else {
+            return Err(McpError::internal_error("Database not available", None));
+        };
+
+        let reopened = db_arc.run(|db| db.unignore_lint(&lint)).map_err(|e| {
+            McpError::internal_error(format!("Failed to unignore lint: {}", e), None)
+        })?;
+
+        let json_result = json!({
+            "lint": lint,
+            "was_ignored": reopened.is_some(),
+            "reopened_todos": reopened.unwrap_or(0)
+        });
+
+        Ok(CallToolResult {
+            content: vec![rmcp::model::Content::text(json_result.to_string())],
+            structured_content: None,
+            meta: None,
+            is_error: Some(false),
+        })
+    }
+
+    async fn handle_reconcile_todos(
+        &self,
+        options: args::Options,
+        args: args::ReconcileTodosArgs,
+    ) -> ToolOutput {
+        debug!("Executing reconcile_todos");
+        let Some(ref db_arc) = self.db else {
+            return Err(McpError::internal_error("Database not available", None));
+        };
+
+        let stdout = match (args.code, args.clippy_output) {
+            (None, Some(output)) => output,
+            (Some(code), None) => {
+                validate_rust_code(&code)?;
+                let invocation = self.build_invocation("cargo_clippy", &options)?;
+                self.run_cargo_tool(&code, "cargo_clippy", &invocation)
+                    .await?
+                    .stdout
+            }
+            _ => {
+                return Err(McpError::invalid_params(
+                    "pass either code or clippy_output",
+                    None,
+                ));
+            }
+        };
+        // Code that doesn't compile gets no lints at all, which
+        // would complete every todo
+        let compile_errors: Vec<String> = diagnostics::from_json(&stdout)
+            .into_iter()
+            .filter(|d| d.is_compile_error())
+            .map(|d| d.message)
+            .collect();
+        if !compile_errors.is_empty() {
+            let json_result = json!({
+                "completed": 0,
+                "compile_errors": compile_errors,
+                "summary": "clippy found compile errors; no todos were completed"
+            });
+            return Ok(CallToolResult {
+                content: vec![rmcp::model::Content::text(json_result.to_string())],
+                structured_content: None,
+                meta: None,
+                is_error: Some(true),
+            });
+        }
+        let findings = clippy_todos(&stdout);
+        let current: Vec<(&str, &str)> = findings
+            .iter()
+            .map(|todo| (todo.source, todo.description.as_str()))
+            .collect();
+
+        let completed = db_arc.run(|db| db.reconcile_todos(&current)).map_err(|e| {
+            McpError::internal_error(format!("Failed to reconcile todos: {}", e), None)
+        })?;
+
+        let json_result = json!({
+            "findings": findings.len(),
+            "completed": completed.len(),
+            "completed_todos": completed
+        });
+
+        Ok(CallToolResult {
+            content: vec![rmcp::model::Content::text(json_result.to_string())],
+            structured_content: None,
+            meta: None,
+            is_error: Some(false),
+        })
+    }
+
+    async fn handle_db_stats(
+        &self,
+        _options: args::Options,
+        _args: args::DbStatsArgs,
+    ) -> ToolOutput {
+        debug!("Executing db_stats");
+        let Some(ref db_arc) = self.db else {
+            return Err(McpError::internal_error("Database not available", None));
+        };
+
+        let stats = db_arc
+            .run(|db| db.get_stats())
+            .map_err(|e| McpError::internal_error(format!("Failed to get stats: {}", e), None))?;
+
+        let mut json_result = json!(stats);
+        json_result["project_pool"] = json!(self.pool.stats());
+
+        Ok(CallToolResult {
+            content: vec![rmcp::model::Content::text(json_result.to_string())],
+            structured_content: None,
+            meta: None,
+            is_error: Some(false),
+        })
+    }
+
+    async fn handle_metrics(&self, _options: args::Options, args: args::MetricsArgs) -> ToolOutput {
+        debug!("Executing metrics");
+        let (tool, since) = (args.tool.as_deref(), args.since.as_deref());
+        if let Some(ts) = since
+            && !is_iso8601_timestamp(ts)
+        {
+            return Err(McpError::invalid_params(
+                format!("since must be an ISO-8601 timestamp, got: {}", ts),
+                None,
+            ));
+        }
+
+        let Some(ref db_arc) = self.db else {
+            return Err(McpError::internal_error("Database not available", None));
+        };
+
+        let tools = db_arc
+            .run(|db| db.get_metrics(tool, since))
+            .map_err(|e| McpError::internal_error(format!("Failed to get metrics: {}", e), None))?;
+
+        Ok(CallToolResult {
+            content: vec![rmcp::model::Content::text(
+                json!({"tools": tools}).to_string(),
+            )],
+            structured_content: None,
+            meta: None,
+            is_error: Some(false),
+        })
+    }
+
+    async fn handle_error_trends(
+        &self,
+        _options: args::Options,
+        args: args::ErrorTrendsArgs,
+    ) -> ToolOutput {
+        debug!("Executing error_trends");
+        let days = match args.days {
+            days @ 1..=MAX_TREND_DAYS => days as u32,
+            days => {
+                return Err(McpError::invalid_params(
+                    format!(
+                        "days must be a whole number from 1 to {}, got: {}",
+                        MAX_TREND_DAYS, days
+                    ),
+                    None,
+                ));
+            }
+        };
+        let group_by = args.group_by;
+        if let Some(group_by) = &group_by
+            && group_by != "rustc_minor"
+        {
+            return Err(McpError::invalid_params(
+                format!("group_by must be rustc_minor, got: {}", group_by),
+                None,
+            ));
+        }
+        let filter = TrendFilter {
+            bucket: args.bucket,
+            days,
+            error_code: args.error_code,
+            tool: args.tool,
+            session: args.tag,
+            by_rustc: group_by.is_some(),
+        };
+
+        let Some(ref db_arc) = self.db else {
+            return Err(McpError::internal_error("Database not available", None));
+        };
+
+        let trends = db_arc.run(|db| db.get_error_trends(&filter)).map_err(|e| {
+            McpError::internal_error(format!("Failed to get error trends: {}", e), None)
+        })?;
+
+        let mut json_result = json!(trends);
+        json_result["error_code"] = json!(filter.error_code);
+        json_result["tool"] = json!(filter.tool);
+        json_result["tag"] = json!(filter.session);
+        json_result["group_by"] = json!(group_by);
+
+        Ok(CallToolResult {
+            content: vec![rmcp::model::Content::text(json_result.to_string())],
+            structured_content: None,
+            meta: None,
+            is_error: Some(false),
+        })
+    }
+
+    async fn handle_error_code_stats(
+        &self,
+        _options: args::Options,
+        args: args::ErrorCodeStatsArgs,
+    ) -> ToolOutput {
+        debug!("Executing error_code_stats");
+        let requested = args
+            .error_code
+            .as_deref()
+            .map(str::trim)
+            .filter(|code| !code.is_empty());
+        let limit = args.limit.get() as usize;
+
+        let Some(ref db_arc) = self.db else {
+            return Err(McpError::internal_error("Database not available", None));
+        };
+
+        let error_code = match requested {
+            Some(code) => Some(code.to_string()),
+            None => db_arc
+                .run(|db| db.most_frequent_error_code())
+                .map_err(|e| {
+                    McpError::internal_error(format!("Failed to find error codes: {}", e), None)
+                })?,
+        };
+        let stats = error_code
+            .map(|code| db_arc.run(|db| db.get_error_code_stats(&code, limit)))
+            .transpose()
+            .map_err(|e| {
+                McpError::internal_error(format!("Failed to get error code stats: {}", e), None)
+            })?;
+        let Some(stats) = stats else {
+            let json_result = json!({
+                "error_code": null,
+                "occurrences": 0,
+                "summary": "no rustc error codes stored yet"
+            });
+            return Ok(CallToolResult {
+                content: vec![rmcp::model::Content::text(json_result.to_string())],
+                structured_content: None,
+                meta: None,
+                is_error: Some(false),
+            });
+        };
+
+        // Only rustc's own codes have an explanation; lints and unknown codes don't
+        let explanation = is_rustc_error_code(&stats.error_code)
+            .then(|| self.explain(&stats.error_code))
+            .transpose()?
+            .filter(|explanation| explanation.success)
+            .map(|explanation| explanation_summary(&explanation.text));
+        let summary = match (&stats.first_seen, stats.occurrences) {
+            (Some(first_seen), occurrences @ 1..) => format!(
+                "{} seen {} time{} in {} analys{} since {}; {} fix{} that worked",
+                stats.error_code,
+                occurrences,
+                if occurrences == 1 { "" } else { "s" },
+                stats.analyses,
+                if stats.analyses == 1 { "is" } else { "es" },
+                first_seen,
+                stats.fixes.len(),
+                if stats.fixes.len() == 1 { "" } else { "es" }
+            ),
+            _ => format!("{} has no stored occurrences", stats.error_code),
+        };
+        let mut json_result = json!(stats);
+        json_result["explanation"] = json!(explanation);
+        json_result["summary"] = json!(summary);
+
+        Ok(CallToolResult {
+            content: vec![rmcp::model::Content::text(json_result.to_string())],
+            structured_content: None,
+            meta: None,
+            is_error: Some(false),
+        })
+    }
+
+    async fn handle_db_vacuum(
+        &self,
+        _options: args::Options,
+        _args: args::DbVacuumArgs,
+    ) -> ToolOutput {
+        debug!("Executing db_vacuum");
+        let Some(ref db_arc) = self.db else {
+            return Err(McpError::internal_error("Database not available", None));
+        };
+
+        let stats = db_arc.run(|db| db.vacuum()).map_err(|e| {
+            McpError::internal_error(format!("Failed to vacuum database: {}", e), None)
+        })?;
+
+        Ok(CallToolResult {
+            content: vec![rmcp::model::Content::text(json!(stats).to_string())],
+            structured_content: None,
+            meta: None,
+            is_error: Some(false),
+        })
+    }
+
+    async fn handle_db_cleanup(
+        &self,
+        _options: args::Options,
+        args: args::DbCleanupArgs,
+    ) -> ToolOutput {
+        debug!("Executing db_cleanup");
+        let keep_analyses = args.keep_analyses.map(|keep| keep as usize);
+        let (purge_cache, prune_cargo_home) = (args.purge_cache, args.prune_cargo_home);
+
+        // Without a database the cache lives in memory
+        let mut pruned = None;
+        let cache_entries_purged = match self.db {
+            Some(ref db_arc) => {
+                if let Some(keep) = keep_analyses {
+                    pruned = Some(db_arc.run(|db| db.cleanup_old_data(keep)).map_err(|e| {
+                        McpError::internal_error(
+                            format!("Failed to clean up analyses: {}", e),
+                            None,
+                        )
+                    })?);
+                }
+                if purge_cache {
+                    db_arc.run(|db| db.purge_result_cache()).map_err(|e| {
+                        McpError::internal_error(
+                            format!("Failed to purge result cache: {}", e),
+                            None,
+                        )
+                    })?
+                } else {
+                    0
+                }
+            }
+            None if keep_analyses.is_some() => {
+                return Err(McpError::internal_error("Database not available", None));
+            }
+            None if purge_cache => self.cache.clear(),
+            None => 0,
+        };
+        let cargo_home_bytes_freed = if prune_cargo_home {
+            self.child_env.prune().map_err(|e| {
+                McpError::internal_error(format!("Failed to prune cargo home: {}", e), None)
+            })?
+        } else {
+            0
+        };
+
+        let json_result = json!({
+            "keep_analyses": keep_analyses,
+            "pruned": pruned,
+            "cache_entries_purged": cache_entries_purged,
+            "cargo_home_bytes_freed": cargo_home_bytes_freed
+        });
+        Ok(CallToolResult {
+            content: vec![rmcp::model::Content::text(json_result.to_string())],
+            structured_content: None,
+            meta: None,
+            is_error: Some(false),
+        })
+    }
+
+    async fn handle_db_reset(
+        &self,
+        _options: args::Options,
+        args: args::DbResetArgs,
+    ) -> ToolOutput {
+        debug!("Executing db_reset");
+        if !args.confirm {
+            return Err(McpError::invalid_params(
+                "db_reset deletes all stored data; pass confirm: true to go ahead",
+                None,
+            ));
+        }
+
+        let Some(ref db_arc) = self.db else {
+            return Err(McpError::internal_error("Database not available", None));
+        };
+
+        let deleted = db_arc.run(|db| db.reset()).map_err(|e| {
+            McpError::internal_error(format!("Failed to reset database: {}", e), None)
+        })?;
+        let vacuumed = if args.vacuum {
+            Some(db_arc.run(|db| db.vacuum()).map_err(|e| {
+                McpError::internal_error(format!("Failed to vacuum database: {}", e), None)
+            })?)
+        } else {
+            None
+        };
+        info!(
+            "Database reset: {} analyses, {} errors, {} todos and {} fixes deleted",
+            deleted.analyses, deleted.errors, deleted.todos, deleted.fixes
+        );
+
+        let json_result = json!({
+            "deleted": deleted,
+            "vacuum": vacuumed
+        });
+
+        Ok(CallToolResult {
+            content: vec![rmcp::model::Content::text(json_result.to_string())],
+            structured_content: None,
+            meta: None,
+            is_error: Some(false),
+        })
+    }
+
+    async fn handle_db_backup(
+        &self,
+        _options: args::Options,
+        args: args::DbBackupArgs,
+    ) -> ToolOutput {
+        debug!("Executing db_backup");
+        let destination = PathBuf::from(args.destination);
+        if !destination.is_absolute() {
+            return Err(McpError::invalid_params(
+                format!(
+                    "destination must be an absolute path, got: {}",
+                    destination.display()
+                ),
+                None,
+            ));
+        }
+
+        let Some(ref db_arc) = self.db else {
+            return Err(McpError::internal_error("Database not available", None));
+        };
+
+        let bytes = db_arc
+            .run(|db| db.backup(&destination, args.overwrite))
+            .map_err(|e| {
+                McpError::internal_error(format!("Failed to back up database: {}", e), None)
+            })?;
+
+        let json_result = json!({
+            "destination": destination.display().to_string(),
+            "bytes": bytes
+        });
+
+        Ok(CallToolResult {
+            content: vec![rmcp::model::Content::text(json_result.to_string())],
+            structured_content: None,
+            meta: None,
+            is_error: Some(false),
+        })
+    }
+
+    async fn handle_ping(&self, _options: args::Options, _args: args::PingArgs) -> ToolOutput {
+        debug!("Executing ping");
+        let json_result = json!({
+            "ok": true,
+            "uptime_secs": self.started.elapsed().as_secs(),
+            "db_available": self.db.is_some()
+        });
+
+        Ok(CallToolResult {
+            content: vec![rmcp::model::Content::text(json_result.to_string())],
+            structured_content: None,
+            meta: None,
+            is_error: Some(false),
+        })
+    }
+
+    async fn handle_server_status(
+        &self,
+        _options: args::Options,
+        _args: args::ServerStatusArgs,
+    ) -> ToolOutput {
+        debug!("Executing server_status");
+        let persistence = match (&self.mode, &self.db) {
+            (PersistenceMode::Path(path), Some(db_arc)) => {
+                let size_bytes = db_arc.run(|db| db.file_size()).ok();
+                json!({
+                    "mode": "path",
+                    "path": path.display().to_string(),
+                    "available": true,
+                    "size_bytes": size_bytes,
+                    "db_healthy": db_arc.healthy(),
+                    "db_last_error": db_arc.last_error()
+                })
+            }
+            (PersistenceMode::Path(path), None) => json!({
+                "mode": "path",
+                "path": path.display().to_string(),
+                "available": false
+            }),
+            (PersistenceMode::Disabled, _) => json!({
+                "mode": "disabled",
+                "available": false
+            }),
+        };
+        let timeouts: serde_json::Map<String, Value> = SNIPPET_TOOLS
+            .iter()
+            .filter_map(|tool| {
+                let (_, timeout) = cargo_tool_command(tool)?;
+                Some((tool.to_string(), json!(timeout.map(|t| t.as_secs()))))
+            })
+            .collect();
+        let toolchain: serde_json::Map<String, Value> = self
+            .requirements
+            .cached()
+            .into_iter()
+            .map(|(name, version)| (name.to_string(), json!(version)))
+            .collect();
+
+        let json_result = json!({
+            "version": env!("CARGO_PKG_VERSION"),
+            "uptime_secs": self.started.elapsed().as_secs(),
+            "persistence": persistence,
+            "timeouts_secs": timeouts,
+            "concurrency_limit": self.runs.stats(),
+            "tool_calls": {
+                // Includes this call
+                "in_flight": self.activity.in_flight.load(Ordering::Relaxed),
+                "completed": self.activity.completed.load(Ordering::Relaxed)
+            },
+            "project_pool": self.pool.stats(),
+            "lsp_sessions": self.lsp.running(),
+            "config": {
+                "artifact_max_bytes": self.config.artifact_max_bytes,
+                "doc_dir": self.config.doc_dir.display().to_string(),
+                "offline": self.config.offline,
+                "crate_max_bytes": self.config.crate_max_bytes
+            },
+            "cargo_home": {
+                "path": self.child_env.cargo_home().map(|p| p.display().to_string()),
+                "shared": self.child_env.cargo_home().is_none(),
+                "size_bytes": self.child_env.size_bytes()
+            },
+            "toolchain": toolchain
+        });
+
+        Ok(CallToolResult {
+            content: vec![rmcp::model::Content::text(json_result.to_string())],
+            structured_content: None,
+            meta: None,
+            is_error: Some(false),
+        })
+    }
+
+    async fn handle_doctor(&self, _options: args::Options, _args: args::DoctorArgs) -> ToolOutput {
+        debug!("Executing doctor");
+        let requirements = Arc::clone(&self.requirements);
+        let json_result = tokio::task::spawn_blocking(move || {
+            requirements.refresh();
+            let tools: Vec<Value> = requirements::ALL
+                .iter()
+                .map(|requirement| {
+                    let version = requirements.version(requirement);
+                    json!({
+                        "name": requirement.name,
+                        "available": version.is_some(),
+                        "version": version,
+                        "optional": requirement.optional,
+                        "install_hint": requirement.install_hint
+                    })
+                })
+                .collect();
+            json!({
+                "tools": tools,
+                "missing": requirements.known_missing()
+            })
+        })
+        .await
+        .map_err(|e| McpError::internal_error(format!("Failed to probe tools: {}", e), None))?;
+
+        Ok(CallToolResult {
+            content: vec![rmcp::model::Content::text(json_result.to_string())],
+            structured_content: None,
+            meta: None,
+            is_error: Some(false),
+        })
+    }
+
+    async fn handle_rerun_analysis(
+        &self,
+        _options: args::Options,
+        args: args::RerunAnalysisArgs,
+    ) -> ToolOutput {
+        debug!("Executing rerun_analysis");
+        let analysis_id = args.analysis_id;
+
+        let Some(ref db_arc) = self.db else {
+            return Err(McpError::internal_error("Database not available", None));
+        };
+
+        let (previous, code, previous_output, previous_errors) = {
+            let db_err = |e: anyhow::Error| {
+                McpError::internal_error(format!("Failed to load analysis: {}", e), None)
+            };
+            let not_found =
+                || McpError::invalid_params(format!("Analysis {} not found", analysis_id), None);
+            let (previous, input, errors) = db_arc
+                .run(|db| {
+                    Ok((
+                        db.get_analysis(analysis_id)?,
+                        db.get_analysis_input(analysis_id)?,
+                        db.get_errors_for_analysis(analysis_id)?,
+                    ))
+                })
+                .map_err(db_err)?;
+            let previous = previous.ok_or_else(not_found)?;
+            let (code, output) = input.ok_or_else(not_found)?;
+            (previous, code, output, errors)
+        };
+
+        if cargo_tool_command(&previous.tool).is_none() {
+            return Err(McpError::invalid_params(
+                format!("{} results can't be re-run", previous.tool),
+                None,
+            ));
+        }
+        let invocation = self.stored_invocation(&previous.tool, &previous_output)?;
+        let code = match code {
+            Some(code) => {
+                validate_rust_code(&code)?;
+                code
+            }
+            None if invocation.crate_source.is_some() => String::new(),
+            None => {
+                let reason = match previous_output.get("code_omitted").and_then(|v| v.as_str()) {
+                    Some("disabled") => "code storage was disabled",
+                    Some("too_large") => "the code was over the size cap",
+                    _ => "recorded before code capture",
+                };
+                return Err(McpError::invalid_params(
+                    format!("Analysis {} has no stored code ({})", analysis_id, reason),
+                    None,
+                ));
+            }
+        };
+        let result = self
+            .run_cargo_tool(&code, &previous.tool, &invocation)
+            .await?;
+        let persist = args.persist;
+        let stored = self.store_analysis_with_errors(
+            &previous.tool,
+            &code,
+            &result,
+            &invocation,
+            persist,
+            args.session.as_deref(),
+        );
+        let persistence = persistence_report(persist, &stored);
+        let rerun_id = stored.ok().flatten();
+
+        let current_errors =
+            Self::parse_error_records(&previous.tool, &result.stdout, &result.stderr);
+        let diff = ErrorDiff::between(&previous_errors, &current_errors);
+        let previous_duration = previous_output.get("duration_ms").and_then(|v| v.as_i64());
+
+        let json_result = json!({
+            "analysis_id": analysis_id,
+            "rerun_analysis_id": rerun_id,
+            "persistence": persistence,
+            "tool": previous.tool,
+            "result": {
+                "status": result.status,
+                "success": result.status == 0,
+                "stdout": result.stdout,
+                "stderr": result.stderr,
+                "duration_ms": result.duration_ms
+            },
+            "previous": previous_output,
+            "comparison": {
+                "success_before": previous.success,
+                "success_after": result.status == 0,
+                "status_before": previous_output.get("status"),
+                "status_after": result.status,
+                "duration_delta_ms": previous_duration
+                    .map(|before| result.duration_ms as i64 - before),
+                "counts": {
+                    "added": diff.added.len(),
+                    "removed": diff.removed.len(),
+                    "unchanged": diff.unchanged.len()
+                },
+                "added": diff.added,
+                "removed": diff.removed,
+                "identical": diff.added.is_empty()
+                    && diff.removed.is_empty()
+                    && previous.success == (result.status == 0)
+            }
+        });
+
+        Ok(CallToolResult {
+            content: vec![rmcp::model::Content::text(json_result.to_string())],
+            structured_content: None,
+            meta: None,
+            is_error: Some(result.status != 0),
+        })
+    }
+
+    async fn handle_compare_analyses(
+        &self,
+        _options: args::Options,
+        args: args::CompareAnalysesArgs,
+    ) -> ToolOutput {
+        debug!("Executing compare_analyses");
+        let tool = args.tool.as_deref();
+
+        let Some(ref db_arc) = self.db else {
+            return Err(McpError::internal_error("Database not available", None));
+        };
+
+        let db_err = |e: anyhow::Error| {
+            McpError::internal_error(format!("Failed to compare analyses: {}", e), None)
+        };
+
+        let (baseline_id, current_id) = if args.latest {
+            let ids = db_arc
+                .run(|db| db.latest_analysis_ids(tool, 2))
+                .map_err(db_err)?;
+            let Some(&current) = ids.first() else {
+                return Err(McpError::invalid_params(
+                    "No stored analyses to compare",
+                    None,
+                ));
+            };
+            (ids.get(1).copied(), current)
+        } else {
+            match (args.baseline_id, args.current_id) {
+                (Some(baseline), Some(current)) => (Some(baseline), current),
+                _ => {
+                    return Err(McpError::invalid_params(
+                        "baseline_id and current_id are required unless latest is true",
+                        None,
+                    ));
+                }
+            }
+        };
+
+        let load = |id: i64| -> Result<_, McpError> {
+            let (record, errors) = db_arc
+                .run(|db| Ok((db.get_analysis(id)?, db.get_errors_for_analysis(id)?)))
+                .map_err(db_err)?;
+            let record = record.ok_or_else(|| {
+                McpError::invalid_params(format!("Analysis {} not found", id), None)
+            })?;
+            Ok((record, errors))
+        };
+
+        let (current, current_errors) = load(current_id)?;
+        let (baseline, diff) = match baseline_id {
+            Some(id) => {
+                let (baseline, baseline_errors) = load(id)?;
+                (
+                    Some(baseline),
+                    ErrorDiff::between(&baseline_errors, &current_errors),
+                )
+            }
+            // Without a baseline every current diagnostic is new, but
+            // nothing can have regressed
+            None => (None, ErrorDiff::between(&[], &current_errors)),
+        };
+        let regression = baseline.is_some() && !diff.added.is_empty();
+        // Diagnostics can come and go with the compiler rather than the code
+        let toolchain_changed = baseline.as_ref().and_then(|baseline| {
+            Some(baseline.rustc_version.as_ref()? != current.rustc_version.as_ref()?)
+        });
+
+        let json_result = json!({
+            "baseline": baseline,
+            "current": current,
+            "counts": {
+                "added": diff.added.len(),
+                "removed": diff.removed.len(),
+                "unchanged": diff.unchanged.len()
+            },
+            "added": diff.added,
+            "removed": diff.removed,
+            "unchanged": diff.unchanged,
+            "regression": regression,
+            "toolchain_changed": toolchain_changed
+        });
+
+        Ok(CallToolResult {
+            content: vec![rmcp::model::Content::text(json_result.to_string())],
+            structured_content: None,
+            meta: None,
+            is_error: Some(false),
+        })
+    }
+
+    async fn handle_diff_analyses(
+        &self,
+        _options: args::Options,
+        args: args::DiffAnalysesArgs,
+    ) -> ToolOutput {
+        debug!("Executing diff_analyses");
+        let (baseline_id, current_id) = (args.baseline_id, args.current_id);
+
+        let Some(ref db_arc) = self.db else {
+            return Err(McpError::internal_error("Database not available", None));
+        };
+
+        let db_err = |e: anyhow::Error| {
+            McpError::internal_error(format!("Failed to diff analyses: {}", e), None)
+        };
+        let load = |id: i64| -> Result<_, McpError> {
+            let not_found = || McpError::invalid_params(format!("Analysis {} not found", id), None);
+            let (record, input, errors) = db_arc
+                .run(|db| {
+                    Ok((
+                        db.get_analysis(id)?,
+                        db.get_analysis_input(id)?,
+                        db.get_errors_for_analysis(id)?,
+                    ))
+                })
+                .map_err(db_err)?;
+            let record = record.ok_or_else(not_found)?;
+            let (code, output) = input.ok_or_else(not_found)?;
+            Ok((record, code, output, errors))
+        };
+
+        let (baseline, baseline_code, baseline_output, baseline_errors) = load(baseline_id)?;
+        let (current, current_code, current_output, current_errors) = load(current_id)?;
+
+        let text = |output: &Value, field: &str| {
+            output::trim_cargo_noise(
+                output
+                    .get(field)
+                    .and_then(|v| v.as_str())
+                    .unwrap_or_default(),
+            )
+        };
+        let diff_field = |field: &str| {
+            output::unified_diff(
+                &text(&baseline_output, field),
+                &text(&current_output, field),
+                &format!("analysis {}", baseline_id),
+                &format!("analysis {}", current_id),
+            )
+        };
+        let code_diff = match (&baseline_code, &current_code) {
+            (Some(before), Some(after)) => Some(output::unified_diff(
+                before,
+                after,
+                &format!("analysis {}", baseline_id),
+                &format!("analysis {}", current_id),
+            )),
+            _ => None,
+        };
+        let duration = |output: &Value| output.get("duration_ms").and_then(|v| v.as_i64());
+        let (before_ms, after_ms) = (duration(&baseline_output), duration(&current_output));
+
+        let json_result = json!({
+            "baseline": baseline,
+            "current": current,
+            "error_codes": ErrorCodeChanges::between(&baseline_errors, &current_errors),
+            "errors": {
+                "baseline": baseline_errors.len(),
+                "current": current_errors.len()
+            },
+            "timing": {
+                "baseline_ms": before_ms,
+                "current_ms": after_ms,
+                "delta_ms": before_ms.zip(after_ms).map(|(before, after)| after - before)
+            },
+            "code_diff": code_diff,
+            "stdout_diff": diff_field("stdout"),
+            "stderr_diff": diff_field("stderr")
+        });
+
+        Ok(CallToolResult {
+            content: vec![rmcp::model::Content::text(json_result.to_string())],
+            structured_content: None,
+            meta: None,
+            is_error: Some(false),
+        })
+    }
+
+    async fn handle_get_analysis(
+        &self,
+        _options: args::Options,
+        args: args::GetAnalysisArgs,
+    ) -> ToolOutput {
+        debug!("Executing get_analysis");
+        let analysis_id = args.analysis_id;
+
+        let Some(ref db_arc) = self.db else {
+            return Err(McpError::internal_error("Database not available", None));
+        };
+        let detail = db_arc
+            .run(|db| db.get_analysis_detail(analysis_id))
+            .map_err(|e| {
+                McpError::internal_error(format!("Failed to load analysis: {}", e), None)
+            })?;
+
+        let json_result = match detail {
+            Some(mut detail) => {
+                if !args.include_code {
+                    detail.code = None;
+                }
+                let mut json_result = json!(detail);
+                json_result["found"] = json!(true);
+                json_result
+            }
+            None => json!({
+                "found": false,
+                "analysis_id": analysis_id,
+                "error": format!("Analysis {} not found", analysis_id)
+            }),
+        };
+
+        Ok(CallToolResult {
+            content: vec![rmcp::model::Content::text(json_result.to_string())],
+            structured_content: None,
+            meta: None,
+            is_error: Some(json_result["found"] != true),
+        })
+    }
+
+    async fn handle_search_output(
+        &self,
+        _options: args::Options,
+        args: args::SearchOutputArgs,
+    ) -> ToolOutput {
+        debug!("Executing search_output");
+        let query = args.query.as_str();
+        if query.trim().is_empty() {
+            return Err(McpError::invalid_params("query must not be empty", None));
+        }
+        let limit = args.limit as usize;
+
+        let Some(ref db_arc) = self.db else {
+            return Err(McpError::internal_error("Database not available", None));
+        };
+
+        let (results, mode) = db_arc
+            .run(|db| Ok((db.search_output(query, limit)?, db.search_mode())))
+            .map_err(|e| {
+                McpError::internal_error(format!("Failed to search output: {}", e), None)
+            })?;
+
+        let json_result = json!({
+            "query": query,
+            "mode": mode,
+            "limit": limit,
+            "results": results
+        });
+
+        Ok(CallToolResult {
+            content: vec![rmcp::model::Content::text(json_result.to_string())],
+            structured_content: None,
+            meta: None,
+            is_error: Some(false),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A stand-in argument of the type a schema property declares
+    fn placeholder(property: &Value) -> Value {
+        match property["type"].as_str() {
+            Some("integer") | Some("number") => json!(1),
+            Some("boolean") => json!(true),
+            Some("array") => json!([]),
+            Some("object") => json!({}),
+            _ => json!("x"),
+        }
+    }
+
+    #[tokio::test]
+    async fn required_arguments_are_enforced() {
+        let dir = tempfile::tempdir().unwrap();
+        let config = ServerConfig {
+            pool_size: 0,
+            ..ServerConfig::default()
+        };
+        let server =
+            RustyToolsServer::with_config(PersistenceMode::Path(dir.path().join("t.db")), config);
+        let tools = server.tools.list();
+        assert_eq!(tools.len(), server.tools.iter().count());
+
+        for handler in server.tools.iter() {
+            let schema = Value::Object((*handler.schema()).clone());
+            let required: Vec<&str> = schema["required"]
+                .as_array()
+                .into_iter()
+                .flatten()
+                .filter_map(Value::as_str)
+                .collect();
+            for missing in &required {
+                let arguments: serde_json::Map<String, Value> = required
+                    .iter()
+                    .filter(|name| *name != missing)
+                    .map(|name| (name.to_string(), placeholder(&schema["properties"][*name])))
+                    .collect();
+                let request = CallToolRequestParam {
+                    name: handler.name().to_string().into(),
+                    arguments: Some(arguments),
+                };
+                let error = handler.execute(&server, request).await.expect_err(&format!(
+                    "{} ran without {}",
+                    handler.name(),
+                    missing
+                ));
+                assert_eq!(
+                    error.code,
+                    rmcp::model::ErrorCode::INVALID_PARAMS,
+                    "{} without {}: {}",
+                    handler.name(),
+                    missing,
+                    error.message
+                );
+            }
+        }
+    }
+
+    /// The invalid-params message of a call that must not run
+    async fn rejected(
+        server: &RustyToolsServer,
+        name: &str,
+        arguments: serde_json::Map<String, Value>,
+    ) -> String {
+        let request = CallToolRequestParam {
+            name: name.to_string().into(),
+            arguments: Some(arguments),
+        };
+        let error = server
+            .tools
+            .get(name)
+            .unwrap()
+            .execute(server, request)
+            .await
+            .expect_err("malformed arguments ran");
+        assert_eq!(error.code, rmcp::model::ErrorCode::INVALID_PARAMS);
+        error.message.into_owned()
+    }
+
+    #[tokio::test]
+    async fn malformed_arguments_are_rejected_before_running() {
+        let server = RustyToolsServer::with_config(
+            PersistenceMode::Disabled,
+            ServerConfig {
+                pool_size: 0,
+                ..ServerConfig::default()
+            },
+        );
+        for handler in server.tools.iter() {
+            let name = handler.name();
+            let schema = Value::Object((*handler.schema()).clone());
+            let valid: serde_json::Map<String, Value> = schema["required"]
+                .as_array()
+                .into_iter()
+                .flatten()
+                .filter_map(Value::as_str)
+                .map(|field| (field.to_string(), placeholder(&schema["properties"][field])))
+                .collect();
+
+            let mut typo = valid.clone();
+            typo.insert("presist".to_string(), json!(true));
+            let message = rejected(&server, name, typo).await;
+            assert!(
+                message.contains("unknown field `presist`"),
+                "{}: {}",
+                name,
+                message
+            );
+
+            for (field, property) in schema["properties"].as_object().into_iter().flatten() {
+                let wrong = match property["type"].as_str() {
+                    Some("string") => json!(7),
+                    Some("boolean") => json!("yes"),
+                    Some("integer") | Some("number") => json!("7"),
+                    Some("array") | Some("object") => json!(true),
+                    _ => continue,
+                };
+                let mut arguments = valid.clone();
+                arguments.insert(field.clone(), wrong);
+                let message = rejected(&server, name, arguments).await;
+                assert!(
+                    message.starts_with(&format!("{}: invalid type", field)),
+                    "{}.{}: {}",
+                    name,
+                    field,
+                    message
+                );
+            }
+        }
+
+        let message = rejected(
+            &server,
+            "cargo_doc",
+            json!({"crate_source": {"name": "serde", "verison": "1"}})
+                .as_object()
+                .unwrap()
+                .clone(),
+        )
+        .await;
+        assert!(message.starts_with("crate_source: unknown field `verison`"));
+    }
+
+    #[tokio::test]
+    async fn tools_run_with_their_schema_defaults() {
+        let dir = tempfile::tempdir().unwrap();
+        let server = RustyToolsServer::with_config(
+            PersistenceMode::Path(dir.path().join("t.db")),
+            ServerConfig {
+                pool_size: 0,
+                ..ServerConfig::default()
+            },
+        );
+        let schema = server.tools.get("cargo_history").unwrap().schema();
+        let result = server
+            .dispatch(CallToolRequestParam {
+                name: "cargo_history".into(),
+                arguments: None,
+            })
+            .await
+            .unwrap();
+        let response: Value =
+            serde_json::from_str(&result.content[0].as_text().unwrap().text).unwrap();
+        for field in ["limit", "offset"] {
+            assert_eq!(
+                response[field], schema["properties"][field]["default"],
+                "{}",
+                field
+            );
+        }
+
+        let message = rejected(
+            &server,
+            "cargo_bloat",
+            json!({"code": "fn main() {}", "top": 0})
+                .as_object()
+                .unwrap()
+                .clone(),
+        )
+        .await;
+        assert!(message.starts_with("top: invalid value"), "{}", message);
+    }
+
+    #[test]
+    fn stored_outputs_rebuild_the_invocation_they_ran_with() {
+        let server = RustyToolsServer::with_config(
+            PersistenceMode::Disabled,
+            ServerConfig {
+                pool_size: 0,
+                ..ServerConfig::default()
+            },
+        );
+        let arguments = json!({
+            "code": "fn main() {}",
+            "profile": "release",
+            "cfgs": ["test"],
+            "max_output_bytes": 100,
+            "verbosity": "full"
+        });
+        let args: args::CargoBuildArgs = args::parse(arguments.as_object()).unwrap();
+        let invocation = server
+            .build_invocation("cargo_build", &args.options())
+            .unwrap();
+        let mut output = json!({"status": 0, "stdout": ""});
+        invocation.annotate(&mut output);
+
+        let rebuilt = server.stored_invocation("cargo_build", &output).unwrap();
+        assert_eq!(rebuilt.args, invocation.args);
+        assert_eq!(rebuilt.env, invocation.env);
+        assert_eq!(rebuilt.max_output_bytes, Some(100));
+    }
+
+    #[tokio::test]
+    async fn workspace_members_are_checked_and_diagnostics_name_their_package() {
+        let dir = tempfile::tempdir().unwrap();
+        let config = ServerConfig {
+            pool_size: 0,
+            ..ServerConfig::default()
+        };
+        let server =
+            RustyToolsServer::with_config(PersistenceMode::Path(dir.path().join("t.db")), config);
+        let files = json!({
+            "Cargo.toml": "[workspace]\nmembers = [\"app\", \"util\"]\nresolver = \"2\"\n",
+            "app/src/main.rs": "fn main() {\n    let n: u32 = \"one\";\n    println!(\"{}\", n);\n}\n",
+            "util/Cargo.toml": "[package]\nname = \"util_crate\"\nversion = \"0.2.0\"\nedition = \"2021\"\n",
+            "util/src/lib.rs": "pub fn double(n: u32) -> u32 {\n    n * 2\n}\n",
+        });
+        let check = |arguments: Value| {
+            let request = CallToolRequestParam {
+                name: "cargo_check".into(),
+                arguments: arguments.as_object().cloned(),
+            };
+            server.dispatch(request)
+        };
+
+        let result = check(json!({"files": files, "workspace": true, "persist": true}))
+            .await
+            .unwrap();
+        assert_eq!(result.is_error, Some(true));
+        let response: Value =
+            serde_json::from_str(&result.content[0].as_text().unwrap().text).unwrap();
+        assert_eq!(response["workspace_members"], json!(["app", "util"]));
+        let error = response["diagnostics"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .find(|d| d["level"] == "error")
+            .unwrap();
+        assert_eq!(error["package"], "app");
+        assert_eq!(error["file"], "app/src/main.rs");
+        let history = server
+            .db
+            .as_ref()
+            .unwrap()
+            .lock()
+            .get_error_history(&HistoryFilter::default())
+            .unwrap();
+        assert!(
+            history
+                .iter()
+                .any(|e| e.file.as_deref() == Some("app/src/main.rs"))
+        );
+
+        let result = check(json!({"files": files, "package": "util_crate"}))
+            .await
+            .unwrap();
+        assert_eq!(result.is_error, Some(false));
+
+        let both = json!({"files": files, "package": "app", "workspace": true});
+        let error = check(both).await.unwrap_err();
+        assert!(error.message.contains("not both"));
+        let error = check(json!({"files": files, "package": "--all"}))
+            .await
+            .unwrap_err();
+        assert!(error.message.starts_with("package must be"));
+    }
+
+    #[tokio::test]
+    async fn dependencies_with_build_scripts_are_refused_unless_allowed() {
+        let dir = tempfile::tempdir().unwrap();
+        let config = ServerConfig {
+            pool_size: 0,
+            ..ServerConfig::default()
+        };
+        let server =
+            RustyToolsServer::with_config(PersistenceMode::Path(dir.path().join("t.db")), config);
+        let files = json!({
+            "Cargo.toml": "[package]\nname = \"app\"\nversion = \"0.1.0\"\nedition = \"2021\"\n\n[dependencies]\nhelper = { path = \"helper\" }\n",
+            "src/main.rs": "fn main() {\n    println!(\"{}\", helper::ANSWER);\n}\n",
+            "helper/Cargo.toml": "[package]\nname = \"helper\"\nversion = \"0.3.0\"\nedition = \"2021\"\n",
+            "helper/build.rs": "fn main() {\n    println!(\"cargo:rustc-env=HELPER_BUILT=yes\");\n}\n",
+            // Only compiles once the build script has run
+            "helper/src/lib.rs": "pub const ANSWER: &str = env!(\"HELPER_BUILT\");\n",
+        });
+        let check = |arguments: Value| {
+            let request = CallToolRequestParam {
+                name: "cargo_check".into(),
+                arguments: arguments.as_object().cloned(),
+            };
+            server.dispatch(request)
+        };
+
+        let error = check(json!({"files": files})).await.unwrap_err();
+        assert!(error.message.contains("helper 0.3.0"), "{}", error.message);
+        let data = error.data.unwrap();
+        assert_eq!(data["allow_with"], "allow_build_scripts");
+        assert_eq!(
+            data["build_code"],
+            json!([{"name": "helper", "version": "0.3.0", "source": "path", "build_script": true, "proc_macro": false}])
+        );
+
+        let result = check(json!({"files": files, "allow_build_scripts": true, "persist": true}))
+            .await
+            .unwrap();
+        assert_eq!(result.is_error, Some(false));
+        let response: Value =
+            serde_json::from_str(&result.content[0].as_text().unwrap().text).unwrap();
+        assert_eq!(response["build_code"][0]["name"], "helper");
+        let id = response["persistence"]["analysis_id"].as_i64().unwrap();
+        let (_, stored) = server
+            .db
+            .as_ref()
+            .unwrap()
+            .lock()
+            .get_analysis_input(id)
+            .unwrap()
+            .unwrap();
+        assert_eq!(stored["allow_build_scripts"], true);
+        assert_eq!(stored["build_code"][0]["name"], "helper");
+
+        // Without a manifest of their own, files only add to the snippet
+        let result = check(json!({
+            "code": "mod util;\nfn main() {\n    util::hi();\n}\n",
+            "files": {"src/util.rs": "pub fn hi() {}\n"},
+        }))
+        .await
+        .unwrap();
+        assert_eq!(result.is_error, Some(false));
+    }
+
+    #[tokio::test]
+    async fn crate_sources_with_build_code_are_refused_unless_allowed() {
+        let config = ServerConfig {
+            pool_size: 0,
+            ..ServerConfig::default()
+        };
+        let server = RustyToolsServer::with_config(PersistenceMode::Disabled, config);
+        // Stands in for a downloaded crate, as fetching one needs crates.io
+        let published = || {
+            let dir = tempfile::tempdir().unwrap();
+            std::fs::create_dir(dir.path().join("src")).unwrap();
+            std::fs::write(
+                dir.path().join("Cargo.toml"),
+                "[package]\nname = \"published\"\nversion = \"1.2.3\"\nedition = \"2021\"\n",
+            )
+            .unwrap();
+            std::fs::write(dir.path().join("build.rs"), "fn main() {}\n").unwrap();
+            std::fs::write(dir.path().join("src/lib.rs"), "//! Docs\n").unwrap();
+            FetchedCrate {
+                dir,
+                resolved: ResolvedCrate {
+                    name: "published".to_string(),
+                    version: "1.2.3".to_string(),
+                },
+            }
+        };
+        let (args, timeout) = cargo_tool_command("cargo_doc").unwrap();
+        let source = json!({"name": "published", "version": "1.2.3"});
+        let invocation = |arguments: Value| {
+            let options = args::parse(arguments.as_object()).unwrap();
+            server.build_invocation("cargo_doc", &options).unwrap()
+        };
+
+        let refused = invocation(json!({"crate_source": source}));
+        let error = server
+            .run_fetched_crate(published(), args, timeout, &refused)
+            .await
+            .unwrap_err();
+        assert!(
+            error.message.contains("published 1.2.3"),
+            "{}",
+            error.message
+        );
+        assert_eq!(
+            error.data.unwrap()["build_code"],
+            json!([{"name": "published", "version": "1.2.3", "source": "path", "build_script": true, "proc_macro": false}])
+        );
+
+        let allowed = invocation(json!({"crate_source": source, "allow_build_scripts": true}));
+        let result = server
+            .run_fetched_crate(published(), args, timeout, &allowed)
+            .await
+            .unwrap();
+        assert_eq!(result.status, 0, "{}", result.stderr);
+        assert_eq!(result.build_code[0].name, "published");
+        assert_eq!(allowed.details["allow_build_scripts"], true);
+
+        // Reading the dependency graph builds nothing
+        let tree = server
+            .build_invocation(
+                "cargo_tree",
+                &args::parse(json!({"crate_source": source}).as_object()).unwrap(),
+            )
+            .unwrap();
+        assert!(!tree.inspect_build_code);
+    }
+
+    #[tokio::test]
+    async fn cargo_and_rustup_configuration_is_refused_in_files() {
+        let server = RustyToolsServer::with_config(
+            PersistenceMode::Disabled,
+            ServerConfig {
+                pool_size: 0,
+                ..ServerConfig::default()
+            },
+        );
+        for (path, config) in [
+            (".cargo/config.toml", ".cargo"),
+            ("crates/a/.cargo/config", ".cargo"),
+            (".Cargo/config.toml", ".cargo"),
+            ("rust-toolchain.toml", "rust-toolchain.toml"),
+            ("rust-toolchain", "rust-toolchain"),
+        ] {
+            // A wrapper standing in for rustc would run on every check
+            let files = json!({
+                path: "[build]\nrustc-wrapper = \"./rustc\"\n",
+                "rustc": "#!/bin/sh\ntouch /tmp/pwned\n",
+                "src/main.rs": "fn main() {}\n"
+            });
+            let message = rejected(
+                &server,
+                "cargo_check",
+                json!({"files": files}).as_object().unwrap().clone(),
+            )
+            .await;
+            assert!(
+                message.starts_with(&format!("{} can't be among the files", config)),
+                "{}: {}",
+                path,
+                message
+            );
+        }
+        // The same holds for embedders
+        let error = ToolInvocation::from_files()
+            .file(
+                ".cargo/config.toml",
+                "[build]\nrustflags = [\"-Cdebuginfo=0\"]\n",
+            )
+            .arg("check")
+            .run()
+            .await
+            .unwrap_err();
+        assert_eq!(error.code, rmcp::model::ErrorCode::INVALID_PARAMS);
+    }
+
+    #[tokio::test]
+    async fn pasted_projects_are_split_into_files() {
+        let config = ServerConfig {
+            pool_size: 0,
+            ..ServerConfig::default()
+        };
+        let server = RustyToolsServer::with_config(PersistenceMode::Disabled, config);
+        let check = |code: &str| {
+            let request = CallToolRequestParam {
+                name: "cargo_check".into(),
+                arguments: json!({"code": code}).as_object().cloned(),
+            };
+            server.dispatch(request)
+        };
+
+        let paste = "**Cargo.toml**\n```toml\n[package]\nname = \"pasted\"\nversion = \"0.1.0\"\nedition = \"2021\"\n```\n\n```rust\n// src/lib.rs\npub fn answer() -> u8 {\n    42\n}\n```\n\n```rust\n// src/main.rs\nfn main() {\n    println!(\"{}\", pasted::answer());\n}\n```\n";
+        let result = check(paste).await.unwrap();
+        let response: Value =
+            serde_json::from_str(&result.content[0].as_text().unwrap().text).unwrap();
+        assert_eq!(result.is_error, Some(false), "{}", response);
+        assert_eq!(
+            response["reconstructed_files"],
+            json!(["Cargo.toml", "src/lib.rs", "src/main.rs"])
+        );
+
+        // Two unnamed blocks could go either way
+        let result = check("```rust\nfn helper() {}\n```\n```rust\nfn main() {}\n```\n")
+            .await
+            .unwrap();
+        assert_eq!(result.is_error, Some(true));
+        let response: Value =
+            serde_json::from_str(&result.content[0].as_text().unwrap().text).unwrap();
+        assert_eq!(response["parsed_as_single_file"], true);
+        assert_eq!(response["paste_note"], "2 code blocks have no file name");
+        assert!(response.get("reconstructed_files").is_none());
+    }
+
+    #[tokio::test]
+    async fn read_only_tools_reopen_a_broken_connection() {
+        let dir = tempfile::tempdir().unwrap();
+        let config = ServerConfig {
+            pool_size: 0,
+            ..ServerConfig::default()
+        };
+        let server =
+            RustyToolsServer::with_config(PersistenceMode::Path(dir.path().join("t.db")), config);
+        let db_arc = server.db.as_ref().unwrap();
+        assert!(
+            db_arc
+                .lock()
+                .store_todo("clippy", "needless return", None, None)
+                .unwrap()
+        );
+        // Swap in a connection whose file SQLite can't read
+        let garbage = dir.path().join("garbage.db");
+        std::fs::write(&garbage, vec![0x5a; 8192]).unwrap();
+        *db_arc.lock() = Database {
+            conn: Connection::open(&garbage).unwrap(),
+            fts_enabled: false,
+        };
+
+        let result = server
+            .dispatch(CallToolRequestParam {
+                name: "cargo_todos".into(),
+                arguments: None,
+            })
+            .await
+            .unwrap();
+        let response: Value =
+            serde_json::from_str(&result.content[0].as_text().unwrap().text).unwrap();
+        assert_eq!(response["todos"][0]["description"], "needless return");
+        assert!(db_arc.healthy());
+        let last_error = db_arc.last_error().unwrap();
+        assert!(last_error.contains("not a database"), "{}", last_error);
+    }
+
+    #[tokio::test]
+    async fn identical_concurrent_requests_share_one_run() {
+        // One slot: a second call needing its own would queue behind the first
+        let config = ServerConfig {
+            pool_size: 0,
+            max_concurrency: 1,
+            ..ServerConfig::default()
+        };
+        let server = RustyToolsServer::with_config(PersistenceMode::Disabled, config);
+        // The test fails to show its output, naming the process that ran it,
+        // and lingers so the second call arrives while the first runs
+        let code = "fn main() {}\n#[test]\nfn names_its_process() {\n    std::thread::sleep(std::time::Duration::from_secs(2));\n    panic!(\"ran in process {}\", std::process::id());\n}\n";
+        let test = || {
+            server.dispatch(CallToolRequestParam {
+                name: "cargo_test".into(),
+                arguments: json!({"code": code, "verbosity": "full"})
+                    .as_object()
+                    .cloned(),
+            })
+        };
+        let response = |result: ToolOutput| -> Value {
+            serde_json::from_str(&result.unwrap().content[0].as_text().unwrap().text).unwrap()
+        };
+        let midway = async {
+            tokio::time::sleep(Duration::from_secs(1)).await;
+            server.runs.stats()
+        };
+
+        let (first, second, runs) = tokio::join!(test(), test(), midway);
+        let (first, second) = (response(first), response(second));
+        assert!(
+            first["stdout"]
+                .as_str()
+                .unwrap()
+                .contains("ran in process "),
+            "{}",
+            first
+        );
+        assert_eq!(first["stdout"], second["stdout"]);
+        assert!(first.get("coalesced").is_none());
+        assert_eq!(second["coalesced"], true);
+        // The waiting call held no slot and checked out no project
+        assert_eq!(runs["running"], 1);
+        assert_eq!(runs["queued"], 0);
+        assert_eq!(server.pool.stats().misses, 1);
+
+        // Once finished, the same call is answered from the cache
+        let third = response(test().await);
+        assert_eq!(third["cached"], true);
+        assert!(third.get("coalesced").is_none());
+        assert_eq!(third["stdout"], first["stdout"]);
+    }
+
+    #[tokio::test]
+    async fn registered_tools_replace_built_in_ones() {
+        struct Echo;
+        impl ToolHandler for Echo {
+            fn name(&self) -> &str {
+                "ping"
+            }
+            fn description(&self) -> &str {
+                "Echo the arguments back"
+            }
+            fn schema(&self) -> Arc<JsonObject> {
+                Arc::new(rmcp::object!({"type": "object", "properties": {}}))
+            }
+            fn execute<'a>(
+                &'a self,
+                _server: &'a RustyToolsServer,
+                request: CallToolRequestParam,
+            ) -> ToolFuture<'a> {
+                Box::pin(async move {
+                    Ok(CallToolResult::success(vec![rmcp::model::Content::text(
+                        json!(request.arguments).to_string(),
+                    )]))
+                })
+            }
+        }
+
+        let config = ServerConfig {
+            pool_size: 0,
+            ..ServerConfig::default()
+        };
+        let mut server = RustyToolsServer::with_config(PersistenceMode::Disabled, config);
+        let count = server.tools.iter().count();
+        server.register_tool(Echo);
+        assert_eq!(server.tools.iter().count(), count);
+        let ping = server.tools.get("ping").unwrap();
+        assert_eq!(ping.description(), "Echo the arguments back");
+        let result = ping
+            .execute(
+                &server,
+                CallToolRequestParam {
+                    name: "ping".into(),
+                    arguments: Some(json!({"a": 1}).as_object().unwrap().clone()),
+                },
+            )
+            .await
+            .unwrap();
+        assert_eq!(result.is_error, Some(false));
+    }
+
+    #[tokio::test]
+    async fn fragments_are_formatted_alone() {
+        let dir = tempfile::tempdir().unwrap();
+        let config = ServerConfig {
+            pool_size: 0,
+            ..ServerConfig::default()
+        };
+        let server =
+            RustyToolsServer::with_config(PersistenceMode::Path(dir.path().join("t.db")), config);
+        let format = |code: &str, mode: &str| {
+            let request = CallToolRequestParam {
+                name: "cargo_fmt".into(),
+                arguments: json!({"code": code, "mode": mode, "no_cache": true})
+                    .as_object()
+                    .cloned(),
+            };
+            async {
+                let result = server.dispatch(request).await.unwrap();
+                let response: Value =
+                    serde_json::from_str(&result.content[0].as_text().unwrap().text).unwrap();
+                let stdout = response["stdout"].as_str().unwrap();
+                let formatted = stdout.split_once("\n\n").map_or("", |(_, body)| body);
+                let formatted = formatted.to_string();
+                (response, formatted)
+            }
+        };
+
+        let (response, formatted) =
+            format("vec![1,2].iter().map(|x|x*2).sum::<i32>()", "expression").await;
+        assert_eq!(formatted, "vec![1, 2].iter().map(|x| x * 2).sum::<i32>()");
+        assert_eq!(response["mode"], "expression");
+        assert!(response.get("warning").is_none());
+
+        // Statements keep their indentation and the comments around them
+        let (_, formatted) = format(
+            "        // leading\n        let x=1;\n        let y = x+2 ;  // trailing\n        // after\n",
+            "expression",
+        )
+        .await;
+        assert_eq!(
+            formatted,
+            "        // leading\n        let x = 1;\n        let y = x + 2; // trailing\n        // after\n"
+        );
+
+        let (_, formatted) =
+            format("/// Doc\nimpl Foo{fn a(&self)->u8{1}}\n// end\n", "item").await;
+        assert_eq!(
+            formatted,
+            "/// Doc\nimpl Foo {\n    fn a(&self) -> u8 {\n        1\n    }\n}\n// end\n"
+        );
+
+        // Closing the wrapper early falls back to formatting a file
+        let (response, formatted) = format("}\nfn other(){\nlet a=1;", "expression").await;
+        assert!(response["warning"].as_str().unwrap().contains("as a file"));
+        assert_eq!(response["success"], false);
+        assert_eq!(formatted, "");
+    }
+}
@@ -0,0 +1,214 @@
+//! Inferring the type of an expression, either from rust-analyzer's hover or
+//! by assigning the expression to `()` and reading the E0308 mismatch rustc
+//! reports.
+
+use crate::diagnostics::Diagnostic;
+
+/// An inferred type and whether it is only a description of one
+#[derive(Debug, Clone, PartialEq)]
+pub struct Inferred {
+    pub ty: String,
+    /// Closures, opaque `impl Trait` types, fn items and unsuffixed literals
+    /// have no name that could be written back into code
+    pub approximate: bool,
+}
+
+impl Inferred {
+    fn new(ty: &str, kind: &str) -> Self {
+        let approximate = matches!(kind, "closure" | "opaque type" | "fn item")
+            || ty.contains('{')
+            || ty.contains("impl ");
+        Inferred {
+            ty: ty.to_string(),
+            approximate,
+        }
+    }
+}
+
+/// Code with `let _: () = (expression);` added, and the 1-based line it is on.
+/// With a `line` the probe goes right after it so the expression sees that
+/// scope's bindings; otherwise it goes in a function of its own.
+pub fn probe(code: &str, expression: &str, line: Option<u32>) -> Result<(String, u32), String> {
+    let statement = format!(
+        "#[allow(unused_parens)] let _: () = ({});",
+        expression.trim().trim_end_matches(';')
+    );
+    let lines: Vec<&str> = code.lines().collect();
+    match line {
+        Some(line) => {
+            let after = line as usize;
+            if after == 0 || after > lines.len() {
+                return Err(format!("line {} is outside the code", line));
+            }
+            let mut probed = lines[..after].join("\n");
+            probed.push('\n');
+            probed.push_str(&statement);
+            probed.push('\n');
+            probed.push_str(&lines[after..].join("\n"));
+            probed.push('\n');
+            Ok((probed, line + 1))
+        }
+        None => {
+            let mut probed = code.trim_end().to_string();
+            probed.push_str("\n\n#[allow(dead_code)]\nfn __rusty_tools_type_of() {\n");
+            let probe_line = probed.lines().count() as u32 + 1;
+            probed.push_str(&statement);
+            probed.push_str("\n}\n");
+            Ok((probed, probe_line))
+        }
+    }
+}
+
+/// The identifier under a 1-based line and character column, to probe when
+/// rust-analyzer can't say what is there
+pub fn identifier_at(code: &str, line: u32, column: u32) -> Option<String> {
+    let text = code.lines().nth((line as usize).checked_sub(1)?)?;
+    let chars: Vec<char> = text.chars().collect();
+    let at = (column as usize).checked_sub(1)?;
+    let is_ident = |c: &char| c.is_alphanumeric() || *c == '_';
+    if !chars.get(at).is_some_and(is_ident) {
+        return None;
+    }
+    let start = chars[..at]
+        .iter()
+        .rposition(|c| !is_ident(c))
+        .map_or(0, |i| i + 1);
+    let end = chars[at..]
+        .iter()
+        .position(|c| !is_ident(c))
+        .map_or(chars.len(), |i| at + i);
+    let identifier: String = chars[start..end].iter().collect();
+    (!identifier.starts_with(|c: char| c.is_ascii_digit())).then_some(identifier)
+}
+
+/// The "found" side of an E0308 against `()`. The note carries the full type;
+/// the label is all rustc gives for primitives.
+pub fn from_mismatch(diagnostic: &Diagnostic) -> Option<Inferred> {
+    if diagnostic.code.as_deref() != Some("E0308") {
+        return None;
+    }
+    let rendered = &diagnostic.rendered;
+    let mut lines = rendered.lines().map(str::trim);
+    if lines.any(|line| line.ends_with("expected unit type `()`"))
+        && let Some(found) = lines.next().and_then(|line| line.strip_prefix("found "))
+        && let Some((kind, ty)) = found.split_once(" `")
+    {
+        return Some(Inferred::new(ty.trim_end_matches('`'), kind));
+    }
+    let label = rendered
+        .lines()
+        .find_map(|line| line.split_once("expected `()`, found "))?
+        .1
+        .trim();
+    match label {
+        "integer" => Some(Inferred::new("{integer}", label)),
+        "floating-point number" => Some(Inferred::new("{float}", label)),
+        _ => {
+            let ty = label.strip_prefix('`')?.strip_suffix('`')?;
+            Some(Inferred::new(ty, ""))
+        }
+    }
+}
+
+/// The type in a hover over a binding, field or parameter, which rust-analyzer
+/// shows as a one-line `let name: Type` or `name: Type` code block
+pub fn from_hover(contents: &str) -> Option<Inferred> {
+    let signature = contents.split("\n---").next().unwrap_or_default();
+    signature
+        .split("```")
+        .skip(1)
+        .step_by(2)
+        .filter_map(|block| {
+            let block = block.strip_prefix("rust").unwrap_or(block).trim();
+            (!block.contains('\n')).then_some(block)
+        })
+        .find_map(|line| {
+            let binding = line.strip_prefix("pub ").unwrap_or(line);
+            let binding = binding.strip_prefix("let ").unwrap_or(binding);
+            let binding = binding.strip_prefix("mut ").unwrap_or(binding);
+            let (name, ty) = binding.split_once(": ")?;
+            name.chars()
+                .all(|c| c.is_alphanumeric() || c == '_')
+                .then(|| Inferred::new(ty.trim(), ""))
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn mismatch(rendered: &str) -> Diagnostic {
+        Diagnostic {
+            level: "error".to_string(),
+            code: Some("E0308".to_string()),
+            message: "mismatched types".to_string(),
+            file: Some("src/main.rs".to_string()),
+            line: Some(3),
+            column: Some(17),
+            rendered: rendered.to_string(),
+            spans: Vec::new(),
+            help: Vec::new(),
+            suggestions: Vec::new(),
+            package: None,
+        }
+    }
+
+    #[test]
+    fn probes_go_after_the_line_or_in_their_own_function() {
+        let code = "fn main() {\n    let v = vec![1];\n}\n";
+        let (probed, line) = probe(code, "v.len()", Some(2)).unwrap();
+        assert_eq!(line, 3);
+        assert_eq!(
+            probed.lines().nth(2),
+            Some("#[allow(unused_parens)] let _: () = (v.len());")
+        );
+        assert_eq!(probed.lines().nth(3), Some("}"));
+
+        let (probed, line) = probe(code, "1u8;", None).unwrap();
+        assert_eq!(
+            probed.lines().nth(line as usize - 1),
+            Some("#[allow(unused_parens)] let _: () = (1u8);")
+        );
+        assert!(probe(code, "v", Some(4)).is_err());
+
+        assert_eq!(identifier_at(code, 2, 9).as_deref(), Some("v"));
+        assert_eq!(identifier_at(code, 2, 4), None);
+    }
+
+    #[test]
+    fn mismatches_name_the_found_type() {
+        let closure = mismatch(
+            "error[E0308]: mismatched types\n --> src/main.rs:4:17\n  |\n4 |     let _: () = || 1;\n  |            --   ^^^^ expected `()`, found closure\n  = note: expected unit type `()`\n               found closure `{closure@src/main.rs:4:17: 4:19}`\n",
+        );
+        assert_eq!(
+            from_mismatch(&closure),
+            Some(Inferred {
+                ty: "{closure@src/main.rs:4:17: 4:19}".to_string(),
+                approximate: true
+            })
+        );
+        let primitive = mismatch(
+            "error[E0308]: mismatched types\n  |            --   ^^^ expected `()`, found `&str`\n",
+        );
+        assert_eq!(
+            from_mismatch(&primitive),
+            Some(Inferred {
+                ty: "&str".to_string(),
+                approximate: false
+            })
+        );
+        let literal =
+            mismatch("error[E0308]: mismatched types\n  |   ^^^ expected `()`, found integer\n");
+        assert!(from_mismatch(&literal).unwrap().approximate);
+    }
+
+    #[test]
+    fn hovers_over_bindings_give_their_type() {
+        let hover = "```rust\nlet v: Vec<i32>\n```\n\n---\n\nneeds Drop";
+        assert_eq!(from_hover(hover).unwrap().ty, "Vec<i32>");
+        let closure = "```rust\nlet f: impl Fn(i32) -> i32\n```\n\n---\n\nsize = 0";
+        assert!(from_hover(closure).unwrap().approximate);
+        let method = "```rust\nalloc::vec::Vec\n```\n\n```rust\nimpl<T, A> Vec<T, A>\npub const fn len(&self) -> usize\nwhere\n    A: Allocator,\n```";
+        assert_eq!(from_hover(method), None);
+    }
+}
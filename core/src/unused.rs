@@ -0,0 +1,153 @@
+//! Unused-code findings: the `unused_*` and `dead_code` lints picked out of a
+//! snippet's diagnostics, with the names of the items they are about.
+
+use crate::diagnostics::Diagnostic;
+use std::collections::BTreeMap;
+
+/// One unused variable, import, item, `mut` or value
+#[derive(Debug, Clone, PartialEq, serde::Serialize)]
+pub struct Finding {
+    /// The lint, e.g. `unused_variables` or `dead_code`
+    pub kind: String,
+    /// Names of the items, usually one; "fields `a` and `b` are never read"
+    /// names two
+    pub names: Vec<String>,
+    pub message: String,
+    pub file: Option<String>,
+    pub line: Option<u32>,
+    pub column: Option<u32>,
+}
+
+pub fn is_unused_lint(code: &str) -> bool {
+    code == "dead_code" || code.starts_with("unused_")
+}
+
+/// The unused-code diagnostics among `diagnostics`; `code` is the snippet,
+/// read for the binding an `unused_mut` points at
+pub fn findings(code: &str, diagnostics: &[Diagnostic]) -> Vec<Finding> {
+    diagnostics
+        .iter()
+        .filter_map(|diagnostic| {
+            let kind = diagnostic.code.as_deref().filter(|c| is_unused_lint(c))?;
+            let mut names = quoted(&diagnostic.message);
+            // "variable does not need to be mutable" doesn't say which one
+            if names.is_empty()
+                && kind == "unused_mut"
+                && let (Some(line), Some(column)) = (diagnostic.line, diagnostic.column)
+            {
+                names.extend(mut_binding(code, line, column));
+            }
+            Some(Finding {
+                kind: kind.to_string(),
+                names,
+                message: diagnostic.message.clone(),
+                file: diagnostic.file.clone(),
+                line: diagnostic.line,
+                column: diagnostic.column,
+            })
+        })
+        .collect()
+}
+
+/// Findings keyed by lint
+pub fn group(findings: Vec<Finding>) -> BTreeMap<String, Vec<Finding>> {
+    let mut groups: BTreeMap<String, Vec<Finding>> = BTreeMap::new();
+    for finding in findings {
+        groups
+            .entry(finding.kind.clone())
+            .or_default()
+            .push(finding);
+    }
+    groups
+}
+
+/// The backticked parts of a message: "unused imports: `A` and `B`"
+fn quoted(message: &str) -> Vec<String> {
+    message
+        .split('`')
+        .skip(1)
+        .step_by(2)
+        .map(str::to_string)
+        .collect()
+}
+
+/// The binding named in `mut x` at a 1-based line and character column
+fn mut_binding(code: &str, line: u32, column: u32) -> Option<String> {
+    let text = code.lines().nth((line as usize).checked_sub(1)?)?;
+    let rest: String = text
+        .chars()
+        .skip((column as usize).checked_sub(1)?)
+        .collect();
+    let name: String = rest
+        .strip_prefix("mut")?
+        .trim_start()
+        .chars()
+        .take_while(|c| c.is_alphanumeric() || *c == '_')
+        .collect();
+    (!name.is_empty()).then_some(name)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn warning(code: &str, message: &str, line: u32, column: u32) -> Diagnostic {
+        Diagnostic {
+            level: "warning".to_string(),
+            code: Some(code.to_string()),
+            message: message.to_string(),
+            file: Some("src/main.rs".to_string()),
+            line: Some(line),
+            column: Some(column),
+            rendered: String::new(),
+            spans: Vec::new(),
+            help: Vec::new(),
+            suggestions: Vec::new(),
+            package: None,
+        }
+    }
+
+    #[test]
+    fn unused_lints_are_grouped_with_their_names() {
+        let code = "use std::collections::{HashMap, HashSet};\n\nstruct S { a: u8, b: u8 }\n\nfn helper() {}\n\nfn main() {\n    let mut count = 1;\n    let x = count;\n}\n";
+        let diagnostics = [
+            warning(
+                "unused_imports",
+                "unused imports: `HashMap` and `HashSet`",
+                1,
+                24,
+            ),
+            warning("unused_variables", "unused variable: `x`", 9, 9),
+            warning("unused_mut", "variable does not need to be mutable", 8, 9),
+            warning("dead_code", "function `helper` is never used", 5, 4),
+            warning("dead_code", "fields `a` and `b` are never read", 3, 12),
+            warning(
+                "clippy::needless_return",
+                "unneeded `return` statement",
+                9,
+                5,
+            ),
+        ];
+        let findings = findings(code, &diagnostics);
+        assert_eq!(findings.len(), 5);
+        assert_eq!(findings[0].names, ["HashMap", "HashSet"]);
+        assert_eq!(findings[2].names, ["count"]);
+
+        let groups = group(findings);
+        assert_eq!(
+            groups.keys().collect::<Vec<_>>(),
+            [
+                "dead_code",
+                "unused_imports",
+                "unused_mut",
+                "unused_variables"
+            ]
+        );
+        let dead: Vec<_> = groups["dead_code"]
+            .iter()
+            .map(|f| f.names.join(","))
+            .collect();
+        assert_eq!(dead, ["helper", "a,b"]);
+        assert_eq!(groups["unused_variables"][0].line, Some(9));
+    }
+}
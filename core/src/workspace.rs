@@ -0,0 +1,160 @@
+//! Projects submitted as `files` rather than a single snippet. A root
+//! `Cargo.toml` with a `[workspace]` table makes them a workspace: its
+//! members are read from the manifest, and members submitted without a
+//! manifest of their own get a minimal one.
+
+use std::path::{Component, Path, PathBuf};
+
+/// A workspace's members, with `dir/*` globs expanded against the submitted
+/// paths
+#[derive(Debug, Clone, PartialEq)]
+pub struct Workspace {
+    pub members: Vec<String>,
+    /// Manifests written for members that came without one
+    pub generated: Vec<(PathBuf, String)>,
+}
+
+impl Workspace {
+    /// The workspace `files` describe; `None` when there is no root manifest
+    /// or it has no `[workspace]` table
+    pub fn detect(files: &[(PathBuf, String)]) -> Result<Option<Self>, String> {
+        let Some((_, manifest)) = files
+            .iter()
+            .find(|(path, _)| path == Path::new("Cargo.toml"))
+        else {
+            return Ok(None);
+        };
+        let Some(patterns) = members(manifest) else {
+            return Ok(None);
+        };
+        let mut members: Vec<String> = Vec::new();
+        let mut add = |member: String| {
+            if !members.contains(&member) {
+                members.push(member);
+            }
+        };
+        for pattern in patterns {
+            if let Some(parent) = pattern.strip_suffix("/*") {
+                // Every directory directly under `parent` that holds a file
+                for (path, _) in files {
+                    let Ok(rest) = path.strip_prefix(parent) else {
+                        continue;
+                    };
+                    let mut components = rest.components();
+                    if let (Some(Component::Normal(dir)), Some(_)) =
+                        (components.next(), components.next())
+                    {
+                        add(format!("{}/{}", parent, dir.to_string_lossy()));
+                    }
+                }
+            } else if pattern.contains(['*', '?', '[']) {
+                return Err(format!(
+                    "workspace member pattern {} is not supported; list the members or use dir/*",
+                    pattern
+                ));
+            } else {
+                add(pattern);
+            }
+        }
+        let generated = members
+            .iter()
+            .map(|member| Path::new(member).join("Cargo.toml"))
+            .filter(|manifest| !files.iter().any(|(path, _)| path == manifest))
+            .map(|manifest| {
+                let dir = manifest.parent().unwrap_or(Path::new(""));
+                let name = dir
+                    .file_name()
+                    .map(|name| name.to_string_lossy().into_owned())
+                    .unwrap_or_default();
+                let contents = format!(
+                    "[package]\nname = \"{}\"\nversion = \"0.1.0\"\nedition = \"2021\"\n",
+                    name
+                );
+                (manifest, contents)
+            })
+            .collect();
+        Ok(Some(Workspace { members, generated }))
+    }
+}
+
+/// The `members` array of a manifest's `[workspace]` table, or `None`
+/// without one
+fn members(manifest: &str) -> Option<Vec<String>> {
+    let mut in_workspace = false;
+    let mut found = false;
+    let mut array: Option<String> = None;
+    for line in manifest.lines() {
+        let line = line.split('#').next().unwrap_or_default().trim();
+        if let Some(array) = &mut array {
+            array.push_str(line);
+            if line.contains(']') {
+                break;
+            }
+            continue;
+        }
+        if line.starts_with('[') {
+            in_workspace = line == "[workspace]";
+            found |= in_workspace;
+        } else if in_workspace
+            && let Some(value) = line.strip_prefix("members")
+            && let Some(value) = value.trim_start().strip_prefix('=')
+        {
+            array = Some(value.to_string());
+            if value.contains(']') {
+                break;
+            }
+        }
+    }
+    if !found {
+        return None;
+    }
+    let array = array.unwrap_or_default().replace('\'', "\"");
+    Some(
+        array
+            .split('"')
+            .skip(1)
+            .step_by(2)
+            .map(|member| member.trim_end_matches('/').to_string())
+            .collect(),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn workspace_members_are_read_and_missing_manifests_generated() {
+        let files = |entries: &[(&str, &str)]| -> Vec<(PathBuf, String)> {
+            entries
+                .iter()
+                .map(|(path, contents)| (PathBuf::from(path), contents.to_string()))
+                .collect()
+        };
+        let root = "[workspace]\nmembers = [\n    \"app\", # the binary\n    'crates/*',\n]\nresolver = \"2\"\n\n[workspace.dependencies]\nmembers = \"not these\"\n";
+        let workspace = Workspace::detect(&files(&[
+            ("Cargo.toml", root),
+            ("app/Cargo.toml", "[package]\nname = \"app\"\n"),
+            ("app/src/main.rs", "fn main() {}"),
+            ("crates/util/src/lib.rs", ""),
+            ("crates/util/src/fmt.rs", ""),
+            ("crates/README.md", ""),
+        ]))
+        .unwrap()
+        .unwrap();
+        assert_eq!(workspace.members, ["app", "crates/util"]);
+        assert_eq!(
+            workspace.generated,
+            [(
+                PathBuf::from("crates/util/Cargo.toml"),
+                "[package]\nname = \"util\"\nversion = \"0.1.0\"\nedition = \"2021\"\n".to_string()
+            )]
+        );
+
+        let package = files(&[("Cargo.toml", "[package]\nname = \"app\"\n")]);
+        assert_eq!(Workspace::detect(&package), Ok(None));
+        assert_eq!(Workspace::detect(&files(&[("src/lib.rs", "")])), Ok(None));
+        let globbed = files(&[("Cargo.toml", "[workspace]\nmembers = [\"crates/a*\"]\n")]);
+        assert!(Workspace::detect(&globbed).is_err());
+    }
+}
@@ -1,38 +1,120 @@
 use anyhow::Result;
 use rmcp::{ServiceExt, transport::stdio};
-use rusty_tools_core::{PersistenceMode, RustyToolsServer};
-use std::path::PathBuf;
+use rusty_tools_core::{PersistenceMode, RustyToolsServer, ServerConfig};
+use std::io::IsTerminal;
+use std::path::{Path, PathBuf};
+use tracing_appender::non_blocking::WorkerGuard;
+use tracing_appender::rolling::{RollingFileAppender, Rotation};
+use tracing_subscriber::layer::SubscriberExt;
+use tracing_subscriber::util::SubscriberInitExt;
+use tracing_subscriber::{EnvFilter, fmt};
+
+/// Rotated log files kept next to the current one
+const LOG_FILES_KEPT: usize = 7;
 
 #[tokio::main]
 async fn main() -> Result<()> {
-    // Log server start to stderr (won't interfere with MCP protocol)
-    eprintln!("🚀 Rusty Tools MCP Server starting...");
+    let log_file = log_file()?;
+    // Held until exit so buffered file logs are flushed
+    let _log_guard = init_logging(log_file.as_deref())?;
+    tracing::info!("Rusty Tools MCP Server starting");
+    if let Some(path) = &log_file {
+        tracing::info!("Logging to {} (rotated daily)", path.display());
+    }
 
-    let mode = if let Ok(path) = std::env::var("RUSTY_TOOLS_DB_PATH") {
+    // --no-persist wins over a database path and over each call's `persist`
+    let mode = if no_persist() {
+        PersistenceMode::Disabled
+    } else if let Ok(path) = std::env::var("RUSTY_TOOLS_DB_PATH") {
         PersistenceMode::Path(PathBuf::from(path))
     } else {
-        // Default to ~/.rusty-tools/rusty-tools.db or XDG_DATA_HOME
-        let default_path = std::env::var("HOME")
-            .map(|h| PathBuf::from(h).join(".rusty-tools").join("rusty-tools.db"))
-            .or_else(|_| {
-                std::env::var("XDG_DATA_HOME")
-                    .map(|x| PathBuf::from(x).join("rusty-tools").join("rusty-tools.db"))
-            })
-            .unwrap_or_else(|_| {
-                eprintln!("⚠️  No HOME or XDG_DATA_HOME set, using current directory for DB");
+        // Default to ~/.rusty-tools/rusty-tools.db, XDG_DATA_HOME without a
+        // home, or %LOCALAPPDATA%\rusty-tools on Windows
+        let default_path = rusty_tools_core::data_dir()
+            .map(|dir| dir.join("rusty-tools.db"))
+            .unwrap_or_else(|| {
+                tracing::warn!("No home or data directory found, using current directory for DB");
                 PathBuf::from("rusty-tools.db")
             });
         PersistenceMode::Path(default_path)
     };
 
-    let handler = RustyToolsServer::new(mode);
+    let handler = RustyToolsServer::with_config(mode, ServerConfig::from_env());
     let service = handler
+        .clone()
         .serve(stdio())
         .await
         .map_err(|e| anyhow::anyhow!("failed to start server: {}", e))?;
 
     service.waiting().await?;
+    handler.shutdown();
 
-    eprintln!("🛑 Rusty Tools MCP Server shutting down");
+    tracing::info!("Rusty Tools MCP Server shutting down");
     Ok(())
 }
+
+/// Where to tee logs: `--log-file <path>`, or `RUSTY_TOOLS_LOG_FILE`
+fn log_file() -> Result<Option<PathBuf>> {
+    let mut args = std::env::args().skip(1);
+    while let Some(arg) = args.next() {
+        if arg == "--log-file" {
+            let path = args
+                .next()
+                .ok_or_else(|| anyhow::anyhow!("--log-file needs a path"))?;
+            return Ok(Some(PathBuf::from(path)));
+        }
+        if let Some(path) = arg.strip_prefix("--log-file=") {
+            return Ok(Some(PathBuf::from(path)));
+        }
+    }
+    Ok(std::env::var_os("RUSTY_TOOLS_LOG_FILE")
+        .filter(|path| !path.is_empty())
+        .map(PathBuf::from))
+}
+
+/// Whether nothing may be written to a database: `--no-persist`, or
+/// `RUSTY_TOOLS_NO_PERSIST`
+fn no_persist() -> bool {
+    std::env::args().skip(1).any(|arg| arg == "--no-persist")
+        || std::env::var("RUSTY_TOOLS_NO_PERSIST").is_ok_and(|v| v == "1" || v == "true")
+}
+
+/// Logs go to stderr, and also to a daily-rotated file when one is given, but
+/// never to stdout, which carries the MCP protocol. RUST_LOG picks the level,
+/// e.g. RUST_LOG=warn for errors only.
+fn init_logging(log_file: Option<&Path>) -> Result<Option<WorkerGuard>> {
+    let filter = EnvFilter::try_from_default_env().unwrap_or_else(|_| EnvFilter::new("info"));
+    let stderr = fmt::layer()
+        .with_writer(std::io::stderr)
+        .with_ansi(std::io::stderr().is_terminal());
+    let (file, guard) = match log_file {
+        Some(path) => {
+            let name = path
+                .file_name()
+                .ok_or_else(|| anyhow::anyhow!("log file {} has no file name", path.display()))?;
+            let dir = path
+                .parent()
+                .filter(|dir| !dir.as_os_str().is_empty())
+                .unwrap_or(Path::new("."));
+            std::fs::create_dir_all(dir)?;
+            // Files are named <name>.<date>; the oldest beyond LOG_FILES_KEPT are removed
+            let appender = RollingFileAppender::builder()
+                .rotation(Rotation::DAILY)
+                .filename_prefix(name.to_string_lossy())
+                .max_log_files(LOG_FILES_KEPT)
+                .build(dir)?;
+            let (writer, guard) = tracing_appender::non_blocking(appender);
+            (
+                Some(fmt::layer().with_writer(writer).with_ansi(false)),
+                Some(guard),
+            )
+        }
+        None => (None, None),
+    };
+    tracing_subscriber::registry()
+        .with(filter)
+        .with(stderr)
+        .with(file)
+        .init();
+    Ok(guard)
+}